@@ -7,6 +7,7 @@ import (
 	"errors"
 	"fmt"
 	"sync"
+	"unsafe"
 
 	"github.com/ebitengine/purego"
 )
@@ -31,6 +32,7 @@ var (
 	dbClose           func(uintptr) uintptr
 	connPrepare       func(uintptr, string) uintptr
 	connGetError      func(uintptr) uintptr
+	connExecBatch     func(uintptr, string, uintptr) int32
 	freeBlobFunc      func(uintptr)
 	freeStringFunc    func(uintptr)
 	rowsGetColumns    func(uintptr) int32
@@ -57,6 +59,7 @@ func ensureLibLoaded() error {
 		purego.RegisterLibFunc(&dbClose, limboLib, FfiDbClose)
 		purego.RegisterLibFunc(&connPrepare, limboLib, FfiDbPrepare)
 		purego.RegisterLibFunc(&connGetError, limboLib, FfiDbGetError)
+		purego.RegisterLibFunc(&connExecBatch, limboLib, FfiDbExecBatch)
 		purego.RegisterLibFunc(&freeBlobFunc, limboLib, FfiFreeBlob)
 		purego.RegisterLibFunc(&freeStringFunc, limboLib, FfiFreeCString)
 		purego.RegisterLibFunc(&rowsGetColumns, limboLib, FfiRowsGetColumns)
@@ -137,6 +140,36 @@ func (c *limboConn) Prepare(query string) (driver.Stmt, error) {
 	return newStmt(stmtPtr, query), nil
 }
 
+// ExecContext executes queries without arguments directly on the connection,
+// which allows multiple semicolon separated statements in a single call.
+// Queries with arguments are left to database/sql to prepare as usual.
+func (c *limboConn) ExecContext(ctx context.Context, query string, args []driver.NamedValue) (driver.Result, error) {
+	if len(args) > 0 {
+		return nil, driver.ErrSkip
+	}
+	if c.ctx == 0 {
+		return nil, errors.New("connection closed")
+	}
+	select {
+	case <-ctx.Done():
+		return nil, ctx.Err()
+	default:
+	}
+	c.Lock()
+	defer c.Unlock()
+	var changes int64
+	rc := connExecBatch(c.ctx, query, uintptr(unsafe.Pointer(&changes)))
+	switch ResultCode(rc) {
+	case Ok, Done:
+		return driver.RowsAffected(changes), nil
+	default:
+		if err := c.getError(); err != nil {
+			return nil, err
+		}
+		return nil, fmt.Errorf("error executing query: %s", ResultCode(rc))
+	}
+}
+
 // limboTx implements driver.Tx
 type limboTx struct {
 	conn *limboConn
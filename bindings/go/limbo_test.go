@@ -357,6 +357,32 @@ func TestTransaction(t *testing.T) {
 	t.Log("Transaction test passed")
 }
 
+func TestExecMultipleStatements(t *testing.T) {
+	db, err := sql.Open("sqlite3", ":memory:")
+	if err != nil {
+		t.Fatalf("Error opening database: %v", err)
+	}
+	defer db.Close()
+
+	_, err = db.Exec("CREATE TABLE batch (x INTEGER); INSERT INTO batch VALUES (1); INSERT INTO batch VALUES (2);")
+	if err != nil {
+		t.Fatalf("Error executing batch: %v", err)
+	}
+
+	_, err = db.Exec("INSERT INTO batch VALUES (3); INSERT INTO missing VALUES (4); INSERT INTO batch VALUES (5);")
+	if err == nil {
+		t.Fatal("Expected error from batch referencing a missing table")
+	}
+
+	var count int
+	if err := db.QueryRow("SELECT count(*) FROM batch").Scan(&count); err != nil {
+		t.Fatalf("Error counting rows: %v", err)
+	}
+	if count != 3 {
+		t.Fatalf("Expected 3 rows, got %d", count)
+	}
+}
+
 func TestVectorOperations(t *testing.T) {
 	db, err := sql.Open("sqlite3", ":memory:")
 	if err != nil {
@@ -7,6 +7,7 @@ use std::{
     sync::Arc,
 };
 use turso_core::{Connection, LimboError};
+use types::ResultCode;
 
 /// # Safety
 /// Safe to be called from Go with null terminated DSN string.
@@ -77,6 +78,42 @@ pub extern "C" fn db_get_error(ctx: *mut c_void) -> *const c_char {
     conn.get_error()
 }
 
+/// Execute every statement in a semicolon separated SQL string, stopping at
+/// the first error. The number of rows changed by the last statement is
+/// written to `changes`.
+/// # Safety
+/// `query` must be a valid null terminated string and `changes`, if not null,
+/// must point to a valid i64.
+#[no_mangle]
+pub unsafe extern "C" fn db_exec_batch(
+    ctx: *mut c_void,
+    query: *const c_char,
+    changes: *mut i64,
+) -> ResultCode {
+    if ctx.is_null() || query.is_null() {
+        return ResultCode::Error;
+    }
+    let query_str = unsafe { std::ffi::CStr::from_ptr(query) }.to_str().unwrap();
+    let db = LimboConn::from_ptr(ctx);
+    match db.conn.execute_batch(query_str) {
+        Ok(()) => {
+            if !changes.is_null() {
+                unsafe { *changes = db.conn.changes() };
+            }
+            ResultCode::Ok
+        }
+        Err(err) => {
+            let code = match &err {
+                LimboError::Busy => ResultCode::Busy,
+                LimboError::ReadOnly => ResultCode::ReadOnly,
+                _ => ResultCode::Error,
+            };
+            db.err = Some(err);
+            code
+        }
+    }
+}
+
 /// Close the database connection
 /// # Safety
 /// safely frees the connection's memory
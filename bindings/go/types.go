@@ -73,6 +73,7 @@ const (
 	FfiDbClose            = "db_close"
 	FfiDbPrepare          = "db_prepare"
 	FfiDbGetError         = "db_get_error"
+	FfiDbExecBatch        = "db_exec_batch"
 	FfiStmtExec           = "stmt_execute"
 	FfiStmtQuery          = "stmt_query"
 	FfiStmtParameterCount = "stmt_parameter_count"
@@ -108,4 +108,5 @@ pub const SQLITE_NULL: i32 = 5;
 
 pub const TURSO_FAILED_TO_PARSE_BYTE_ARRAY: i32 = 1100;
 pub const TURSO_FAILED_TO_PREPARE_STATEMENT: i32 = 1200;
+pub const TURSO_FAILED_TO_EXECUTE_BATCH: i32 = 1300;
 pub const TURSO_ETC: i32 = 9999;
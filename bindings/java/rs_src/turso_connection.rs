@@ -1,5 +1,5 @@
 use crate::errors::{
-    Result, TursoError, TURSO_ETC, TURSO_FAILED_TO_PARSE_BYTE_ARRAY,
+    Result, TursoError, TURSO_ETC, TURSO_FAILED_TO_EXECUTE_BATCH, TURSO_FAILED_TO_PARSE_BYTE_ARRAY,
     TURSO_FAILED_TO_PREPARE_STATEMENT,
 };
 use crate::turso_statement::TursoStatement;
@@ -89,3 +89,61 @@ pub extern "system" fn Java_tech_turso_core_TursoConnection_prepareUtf8<'local>(
         }
     }
 }
+
+#[no_mangle]
+pub extern "system" fn Java_tech_turso_core_TursoConnection_executeBatchUtf8<'local>(
+    mut env: JNIEnv<'local>,
+    obj: JObject<'local>,
+    connection_ptr: jlong,
+    sql_bytes: JByteArray<'local>,
+) {
+    let connection = match to_turso_connection(connection_ptr) {
+        Ok(conn) => conn,
+        Err(e) => {
+            set_err_msg_and_throw_exception(&mut env, obj, TURSO_ETC, e.to_string());
+            return;
+        }
+    };
+
+    let sql = match utf8_byte_arr_to_str(&env, sql_bytes) {
+        Ok(sql) => sql,
+        Err(e) => {
+            set_err_msg_and_throw_exception(
+                &mut env,
+                obj,
+                TURSO_FAILED_TO_PARSE_BYTE_ARRAY,
+                e.to_string(),
+            );
+            return;
+        }
+    };
+
+    if let Err(e) = connection.conn.execute_batch(sql) {
+        set_err_msg_and_throw_exception(
+            &mut env,
+            obj,
+            TURSO_FAILED_TO_EXECUTE_BATCH,
+            e.to_string(),
+        );
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_tech_turso_core_TursoConnection_setBusyTimeout<'local>(
+    mut env: JNIEnv<'local>,
+    obj: JObject<'local>,
+    connection_ptr: jlong,
+    timeout_millis: jlong,
+) {
+    let connection = match to_turso_connection(connection_ptr) {
+        Ok(conn) => conn,
+        Err(e) => {
+            set_err_msg_and_throw_exception(&mut env, obj, TURSO_ETC, e.to_string());
+            return;
+        }
+    };
+
+    connection
+        .conn
+        .set_busy_timeout(timeout_millis.max(0) as u64);
+}
@@ -39,6 +39,7 @@ public enum TursoErrorCode {
   UNKNOWN_ERROR(-1, "Unknown error"),
   TURSO_FAILED_TO_PARSE_BYTE_ARRAY(1100, "Failed to parse ut8 byte array"),
   TURSO_FAILED_TO_PREPARE_STATEMENT(1200, "Failed to prepare statement"),
+  TURSO_FAILED_TO_EXECUTE_BATCH(1300, "Failed to execute batch"),
   TURSO_ETC(9999, "Unclassified error");
 
   public final int code;
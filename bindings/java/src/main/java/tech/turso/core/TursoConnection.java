@@ -84,6 +84,37 @@ public final class TursoConnection {
 
   private native long prepareUtf8(long connectionPtr, byte[] sqlUtf8) throws SQLException;
 
+  /**
+   * Executes one or more semicolon separated SQL statements, discarding any rows they return.
+   * Execution stops at the first statement that fails.
+   *
+   * @param sql SQL statements.
+   * @throws SQLException if a database access error occurs.
+   */
+  public void executeBatch(String sql) throws SQLException {
+    logger.trace("DriverManager [{}] [SQLite EXEC] {}", Thread.currentThread().getName(), sql);
+    byte[] sqlBytes = stringToUtf8ByteArray(sql);
+    if (sqlBytes == null) {
+      throw new SQLException("Failed to convert " + sql + " into bytes");
+    }
+    executeBatchUtf8(connectionPtr, sqlBytes);
+  }
+
+  private native void executeBatchUtf8(long connectionPtr, byte[] sqlUtf8) throws SQLException;
+
+  /**
+   * Sets how long a write waits for a lock held by another connection before failing.
+   *
+   * @param busyTimeout timeout in milliseconds, 0 to fail immediately.
+   * @throws SQLException if a database access error occurs.
+   */
+  public void setBusyTimeout(int busyTimeout) throws SQLException {
+    setBusyTimeout(connectionPtr, busyTimeout);
+  }
+
+  private native void setBusyTimeout(long connectionPtr, long busyTimeoutMillis)
+      throws SQLException;
+
   // TODO: check whether this is still valid for turso
   /**
    * Checks whether the type, concurrency, and holdability settings for a {@link ResultSet} are
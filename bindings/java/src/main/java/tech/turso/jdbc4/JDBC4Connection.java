@@ -14,6 +14,7 @@ public final class JDBC4Connection implements Connection {
   private final TursoConnection connection;
 
   private Map<String, Class<?>> typeMap = new HashMap<>();
+  private int busyTimeout = 0;
 
   public JDBC4Connection(String url, String filePath) throws SQLException {
     this.connection = new TursoConnection(url, filePath);
@@ -355,14 +356,14 @@ public final class JDBC4Connection implements Connection {
     return false;
   }
 
-  public void setBusyTimeout(int busyTimeout) {
-    // TODO: add support for busy timeout
+  public void setBusyTimeout(int busyTimeout) throws SQLException {
+    connection.setBusyTimeout(busyTimeout);
+    this.busyTimeout = busyTimeout;
   }
 
   /** @return busy timeout in milliseconds. */
   public int getBusyTimeout() {
-    // TODO: add support for busyTimeout
-    return 0;
+    return busyTimeout;
   }
 
   public String getUrl() {
@@ -364,7 +364,6 @@ public class JDBC4Statement implements Statement {
   private <T> T withConnectionTimeout(SQLCallable<T> callable) throws SQLException {
     final int originalBusyTimeoutMillis = connection.getBusyTimeout();
     if (queryTimeoutSeconds > 0) {
-      // TODO: set busy timeout
       connection.setBusyTimeout(1000 * queryTimeoutSeconds);
     }
 
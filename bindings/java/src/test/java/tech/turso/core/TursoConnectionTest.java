@@ -0,0 +1,49 @@
+package tech.turso.core;
+
+import static org.assertj.core.api.Assertions.assertThatThrownBy;
+import static org.junit.jupiter.api.Assertions.assertEquals;
+
+import java.sql.SQLException;
+import org.junit.jupiter.api.BeforeEach;
+import org.junit.jupiter.api.Test;
+import tech.turso.TestUtils;
+
+class TursoConnectionTest {
+
+  private TursoConnection connection;
+
+  @BeforeEach
+  void setUp() throws Exception {
+    String filePath = TestUtils.createTempFile();
+    String url = "jdbc:turso:" + filePath;
+    connection = new TursoConnection(url, filePath);
+  }
+
+  @Test
+  void executeBatch_runs_every_statement() throws Exception {
+    connection.executeBatch(
+        "CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1); INSERT INTO t VALUES (2);");
+
+    assertEquals(2L, countRows());
+  }
+
+  @Test
+  void executeBatch_stops_at_first_failing_statement() throws Exception {
+    connection.executeBatch("CREATE TABLE t (x INTEGER);");
+
+    assertThatThrownBy(
+            () ->
+                connection.executeBatch(
+                    "INSERT INTO t VALUES (1); INSERT INTO missing VALUES (2); INSERT INTO t VALUES (3);"))
+        .isInstanceOf(SQLException.class);
+    assertEquals(1L, countRows());
+  }
+
+  private long countRows() throws Exception {
+    TursoStatement stmt = connection.prepare("SELECT count(*) FROM t;");
+    stmt.execute();
+    long count = (long) stmt.getResultSet().get(1);
+    stmt.close();
+    return count;
+  }
+}
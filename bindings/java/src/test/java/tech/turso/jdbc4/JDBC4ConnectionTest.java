@@ -66,6 +66,13 @@ class JDBC4ConnectionTest {
     connection.prepare("CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT)");
   }
 
+  @Test
+  void busy_timeout_defaults_to_zero_and_can_be_set() throws Exception {
+    assertEquals(0, connection.getBusyTimeout());
+    connection.setBusyTimeout(1500);
+    assertEquals(1500, connection.getBusyTimeout());
+  }
+
   @Test
   void calling_close_multiple_times_throws_no_exception() throws Exception {
     assertFalse(connection.isClosed());
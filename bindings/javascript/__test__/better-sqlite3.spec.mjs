@@ -348,6 +348,14 @@ test("Test Statement.source", async t => {
   t.is(stmt.source, sql);
 });
 
+test("Test Statement.readonly", async t => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (id int)");
+  t.is(db.prepare("SELECT * FROM t").readonly, true);
+  t.is(db.prepare("INSERT INTO t VALUES (1)").readonly, false);
+  t.is(db.prepare("CREATE TABLE u (id int)").readonly, false);
+});
+
 const connect = async (path) => {
   const db = new Database(path);
   return [db];
@@ -48,6 +48,58 @@ test("Statement.run() returns correct result object", async (t) => {
   t.deepEqual(rows, [{ name: "Alice", age: 42 }]);
 });
 
+test("Statement.runMany() inserts every row", async (t) => {
+  const [db] = await connect(":memory:");
+  db.prepare("CREATE TABLE users (name TEXT, age INTEGER)").run();
+  const changes = db
+    .prepare("INSERT INTO users (name, age) VALUES (?, ?)")
+    .runMany([["Alice", 42], ["Bob", 24], ["Carol", 33]]);
+  t.is(changes, 3);
+  let rows = db.prepare("SELECT name FROM users").raw().all();
+  t.deepEqual(rows, [["Alice"], ["Bob"], ["Carol"]]);
+});
+
+test("Statement.runMany() reports the failing row", async (t) => {
+  const [db] = await connect(":memory:");
+  db.prepare("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)").run();
+  const stmt = db.prepare("INSERT INTO users (id, name) VALUES (?, ?)");
+  t.throws(() => stmt.runMany([[1, "Alice"], [2, "Bob"], [1, "Carol"]]), {
+    message: /^row 2:/,
+  });
+  let rows = db.prepare("SELECT name FROM users").raw().all();
+  t.deepEqual(rows, [["Alice"], ["Bob"]]);
+});
+
+test("Read-only database rejects writes", async (t) => {
+  const name = "readonly.db";
+  fs.rmSync(name, { force: true });
+  const writer = new Database(name);
+  writer.prepare("CREATE TABLE users (name TEXT)").run();
+  writer.prepare("INSERT INTO users (name) VALUES ('Alice')").run();
+  writer.close();
+
+  const db = new Database(name, { readonly: true });
+  t.is(db.readonly, true);
+  t.deepEqual(db.prepare("SELECT name FROM users").raw().all(), [["Alice"]]);
+  t.throws(() => db.prepare("INSERT INTO users (name) VALUES ('Bob')"), {
+    message: /read-only/,
+  });
+  t.throws(() => db.prepare("DROP TABLE users"), { message: /read-only/ });
+  db.close();
+  fs.rmSync(name, { force: true });
+});
+
+test("Statements fail cleanly after Database.close()", async (t) => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE users (name TEXT)");
+  db.exec("INSERT INTO users (name) VALUES ('Alice')");
+  const stmt = db.prepare("SELECT name FROM users");
+  db.close();
+  t.throws(() => stmt.all(), { message: /database is closed/ });
+  // Closing twice is a no-op.
+  db.close();
+});
+
 test("Statment.iterate() should correctly return an iterable object", async (t) => {
   const [db] = await connect(":memory:");
   db.prepare(
@@ -71,6 +123,77 @@ test("Statment.iterate() should correctly return an iterable object", async (t)
   }
 });
 
+test("Statement.iterateChunks() streams rows without blocking the event loop", async (t) => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (x INTEGER)");
+  const total = 200000;
+  db.prepare("INSERT INTO t VALUES (?)").runMany(
+    Array.from({ length: total }, (_, i) => [i]),
+  );
+
+  let maxBlocked = 0;
+  let last = performance.now();
+  const timer = setInterval(() => {
+    const now = performance.now();
+    maxBlocked = Math.max(maxBlocked, now - last);
+    last = now;
+  }, 0);
+
+  let expected = 0;
+  let inOrder = true;
+  let maxChunk = 0;
+  const stmt = db.prepare("SELECT x FROM t").pluck();
+  for await (const chunk of stmt.iterateChunks({ rowsPerChunk: 1000 })) {
+    maxChunk = Math.max(maxChunk, chunk.length);
+    for (const x of chunk) {
+      inOrder &&= x === expected++;
+    }
+  }
+  clearInterval(timer);
+
+  t.is(expected, total);
+  t.true(inOrder);
+  t.is(maxChunk, 1000);
+  t.true(maxBlocked < 10, `event loop was blocked for ${maxBlocked}ms`);
+});
+
+test("Statement.iterateChunks() binds parameters and handles an exact multiple of the chunk size", async (t) => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (x INTEGER)");
+  db.prepare("INSERT INTO t VALUES (?)").runMany([[1], [2], [3], [4], [5]]);
+
+  const chunks = [];
+  const stmt = db.prepare("SELECT x FROM t WHERE x > ?").raw();
+  for await (const chunk of stmt.iterateChunks({ rowsPerChunk: 2 }, 1)) {
+    chunks.push(chunk);
+  }
+  t.deepEqual(chunks, [[[2], [3]], [[4], [5]]]);
+  t.throws(() => stmt.iterateChunks({ rowsPerChunk: 0 }), { instanceOf: RangeError });
+});
+
+test("Statement.iterateChunks() can be cancelled", async (t) => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (x INTEGER)");
+  db.prepare("INSERT INTO t VALUES (?)").runMany([[1], [2], [3], [4], [5]]);
+
+  const stmt = db.prepare("SELECT x FROM t").pluck();
+  const iterator = stmt.iterateChunks({ rowsPerChunk: 2 });
+  t.deepEqual(await iterator.next(), { done: false, value: [1, 2] });
+  t.deepEqual(await iterator.return(), { done: true, value: undefined });
+  t.deepEqual(await iterator.next(), { done: true, value: undefined });
+
+  // The cancelled statement released its transaction, so writes go through and the
+  // statement can be run again from the start.
+  db.exec("INSERT INTO t VALUES (6)");
+  const rows = [];
+  for await (const chunk of stmt.iterateChunks({ rowsPerChunk: 4 })) {
+    rows.push(...chunk);
+    break;
+  }
+  t.deepEqual(rows, [1, 2, 3, 4]);
+  t.deepEqual(stmt.all(), [1, 2, 3, 4, 5, 6]);
+});
+
 test("Empty prepared statement should throw", async (t) => {
   const [db] = await connect(":memory:");
   t.throws(
@@ -209,6 +332,16 @@ test("Test exec(): Should correctly load multiple statements from file", async (
   }
 });
 
+test("Test exec(): Should stop at the first failing statement", async (t) => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);");
+  t.throws(() => {
+    db.exec("INSERT INTO t VALUES (2); INSERT INTO missing VALUES (3); INSERT INTO t VALUES (4);");
+  });
+  const rows = db.prepare("SELECT x FROM t").raw().all();
+  t.deepEqual(rows, [[1], [2]]);
+});
+
 test("pragma query", async (t) => {
   const [db] = await connect(":memory:");
   let page_size = db.pragma("page_size");
@@ -243,6 +376,14 @@ test("Test Statement.source", async t => {
   t.is(stmt.source, sql);
 });
 
+test("Test Statement.readonly", async t => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (id int)");
+  t.is(db.prepare("SELECT * FROM t").readonly, true);
+  t.is(db.prepare("INSERT INTO t VALUES (1)").readonly, false);
+  t.is(db.prepare("CREATE TABLE u (id int)").readonly, false);
+});
+
 test("simple pragma table_list", async (t) => {
   const [db] = await connect(":memory:");
   let param = "sqlite_schema";
@@ -251,6 +392,16 @@ test("simple pragma table_list", async (t) => {
   t.deepEqual(actual, expectedValue);
 });
 
+test("EXPLAIN QUERY PLAN returns the plan as rows", async (t) => {
+  const [db] = await connect(":memory:");
+  db.exec("CREATE TABLE t (id INTEGER PRIMARY KEY, x)");
+  const rows = db.prepare("EXPLAIN QUERY PLAN SELECT x FROM t ORDER BY x").all();
+  t.deepEqual(rows, [
+    { id: 1, parent: 0, notused: 0, detail: "SCAN t" },
+    { id: 2, parent: 0, notused: 0, detail: "USE TEMP B-TREE FOR ORDER BY" },
+  ]);
+});
+
 const connect = async (path) => {
   const db = new Database(path);
   return [db];
@@ -34,6 +34,10 @@ export declare class Statement {
   get(): unknown
   all(): NapiResult
   run(args: Array<unknown>): void
+  runMany(rows: Array<Array<unknown>>): number
+  startChunks(args?: Array<unknown> | undefined | null): void
+  nextChunk(rowsPerChunk: number): unknown
+  cancel(): void
   static iterate(): void
   static pluck(): void
   static expand(): void
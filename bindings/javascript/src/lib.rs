@@ -9,7 +9,7 @@ use std::sync::Arc;
 use napi::iterator::Generator;
 use napi::{bindgen_prelude::ObjectFinalize, Env, JsUnknown};
 use napi_derive::napi;
-use turso_core::{LimboError, StepResult};
+use turso_core::LimboError;
 
 #[derive(Default)]
 #[napi(object)]
@@ -45,9 +45,11 @@ pub struct Database {
 }
 
 impl ObjectFinalize for Database {
-    // TODO: check if something more is required
-    fn finalize(self, _env: Env) -> napi::Result<()> {
-        self.conn.close().map_err(into_napi_error)?;
+    fn finalize(mut self, _env: Env) -> napi::Result<()> {
+        // Statements hold clones of the database; only the last one closes it.
+        if Arc::strong_count(&self.conn) == 1 {
+            self.close()?;
+        }
         Ok(())
     }
 }
@@ -63,17 +65,18 @@ impl Database {
             Arc::new(turso_core::PlatformIO::new().map_err(into_napi_error)?)
         };
         let opts = options.unwrap_or_default();
-        let flag = if opts.readonly {
-            turso_core::OpenFlags::ReadOnly
+        let db = if opts.readonly {
+            turso_core::Database::open_readonly(io.clone(), &path).map_err(into_napi_error)?
         } else {
-            turso_core::OpenFlags::Create
+            let file = io
+                .open_file(&path, turso_core::OpenFlags::Create, false)
+                .map_err(into_napi_error)?;
+            let db_file = Arc::new(DatabaseFile::new(file));
+            turso_core::Database::open(io.clone(), &path, db_file, false, false)
+                .map_err(into_napi_error)?
         };
-        let file = io.open_file(&path, flag, false).map_err(into_napi_error)?;
-
-        let db_file = Arc::new(DatabaseFile::new(file));
-        let db = turso_core::Database::open(io.clone(), &path, db_file, false, false)
-            .map_err(into_napi_error)?;
         let conn = db.connect().map_err(into_napi_error)?;
+        conn.set_busy_timeout(opts.timeout as u64);
 
         Ok(Self {
             readonly: opts.readonly,
@@ -170,53 +173,27 @@ impl Database {
     pub fn load_extension(&self, path: String) -> napi::Result<()> {
         let ext_path = turso_core::resolve_ext_path(path.as_str()).map_err(into_napi_error)?;
         self.conn
-            .load_extension(ext_path)
+            .load_extension(ext_path, None)
             .map_err(into_napi_error)?;
         Ok(())
     }
 
     #[napi]
     pub fn exec(&self, sql: String) -> napi::Result<()> {
-        let query_runner = self.conn.query_runner(sql.as_bytes());
-
-        // Since exec doesn't return any values, we can just iterate over the results
-        for output in query_runner {
-            match output {
-                Ok(Some(mut stmt)) => loop {
-                    match stmt.step() {
-                        Ok(StepResult::Row) => continue,
-                        Ok(StepResult::IO) => self.io.run_once().map_err(into_napi_error)?,
-                        Ok(StepResult::Done) => break,
-                        Ok(StepResult::Interrupt | StepResult::Busy) => {
-                            return Err(napi::Error::new(
-                                napi::Status::GenericFailure,
-                                "Statement execution interrupted or busy".to_string(),
-                            ));
-                        }
-                        Err(err) => {
-                            return Err(napi::Error::new(
-                                napi::Status::GenericFailure,
-                                format!("Error executing SQL: {}", err),
-                            ));
-                        }
-                    }
-                },
-                Ok(None) => continue,
-                Err(err) => {
-                    return Err(napi::Error::new(
-                        napi::Status::GenericFailure,
-                        format!("Error executing SQL: {}", err),
-                    ));
-                }
-            }
-        }
-        Ok(())
+        self.conn.execute_batch(&sql).map_err(into_napi_error)
     }
 
     #[napi]
     pub fn close(&mut self) -> napi::Result<()> {
         if self.open {
             self.conn.close().map_err(into_napi_error)?;
+            // Statements that are still around fail with "database is closed" from now on.
+            self._db
+                .close(turso_core::CloseOptions {
+                    force: true,
+                    ..Default::default()
+                })
+                .map_err(into_napi_error)?;
             self.open = false;
         }
         Ok(())
@@ -237,8 +214,6 @@ pub struct Statement {
     // #[napi(able = false)]
     // pub reader: bool,
     // #[napi(writable = false)]
-    // pub readonly: bool,
-    // #[napi(writable = false)]
     // pub busy: bool,
     #[napi(writable = false)]
     pub source: String,
@@ -329,6 +304,41 @@ impl Statement {
         self.internal_all(env, stmt)
     }
 
+    /// Executes the statement once for each array of bind parameters in `rows`,
+    /// returning the number of rows changed by the whole batch.
+    #[napi]
+    pub fn run_many(&self, rows: Vec<Vec<JsUnknown>>) -> napi::Result<i64> {
+        let rows = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(from_js_value).collect())
+            .collect::<napi::Result<Vec<Vec<_>>>>()?;
+        let mut rows = rows.into_iter();
+        let mut stmt = self.inner.borrow_mut();
+        loop {
+            let step = stmt.execute_batch(&mut rows).map_err(|e| {
+                napi::Error::new(
+                    napi::Status::GenericFailure,
+                    format!("row {}: {e}", stmt.batch_rows_done()),
+                )
+            })?;
+            match step {
+                turso_core::StepResult::Done => break,
+                turso_core::StepResult::IO => {
+                    self.database.io.run_once().map_err(into_napi_error)?;
+                }
+                turso_core::StepResult::Row
+                | turso_core::StepResult::Interrupt
+                | turso_core::StepResult::Busy => {
+                    return Err(napi::Error::new(
+                        napi::Status::GenericFailure,
+                        format!("{:?}", step),
+                    ))
+                }
+            }
+        }
+        Ok(self.database.conn.changes())
+    }
+
     #[napi]
     pub fn iterate(
         &self,
@@ -344,6 +354,51 @@ impl Statement {
         })
     }
 
+    /// Resets the statement and binds `args` so that the result can be fetched in chunks
+    /// with `next_chunk()`.
+    #[napi]
+    pub fn start_chunks(&self, args: Option<Vec<JsUnknown>>) -> napi::Result<()> {
+        self.check_and_bind(args)?;
+        Ok(())
+    }
+
+    /// Steps the statement until it has produced `rows_per_chunk` rows or is done, and
+    /// returns those rows. A chunk shorter than `rows_per_chunk` means the statement is done.
+    /// The statement keeps its position between calls.
+    #[napi]
+    pub fn next_chunk(&self, env: Env, rows_per_chunk: u32) -> napi::Result<JsUnknown> {
+        let mut stmt = self.inner.borrow_mut();
+        let mut chunk = env.create_empty_array()?;
+        let mut index = 0;
+        while index < rows_per_chunk {
+            let step = stmt.step().map_err(into_napi_error)?;
+            match step {
+                turso_core::StepResult::Row => {
+                    let row = row_to_js(&env, &stmt, &self.presentation_mode)?;
+                    chunk.set_element(index, row)?;
+                    index += 1;
+                }
+                turso_core::StepResult::Done => break,
+                turso_core::StepResult::IO => {
+                    self.database.io.run_once().map_err(into_napi_error)?;
+                }
+                turso_core::StepResult::Interrupt | turso_core::StepResult::Busy => {
+                    return Err(napi::Error::new(
+                        napi::Status::GenericFailure,
+                        format!("{:?}", step),
+                    ))
+                }
+            }
+        }
+        Ok(chunk.into_unknown())
+    }
+
+    /// Abandons a statement that is being fetched in chunks, releasing the locks it holds.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<()> {
+        self.inner.borrow_mut().cancel().map_err(into_napi_error)
+    }
+
     #[napi]
     pub fn all(&self, env: Env, args: Option<Vec<JsUnknown>>) -> napi::Result<JsUnknown> {
         let stmt = self.check_and_bind(args)?;
@@ -415,6 +470,11 @@ impl Statement {
         Ok(results.into_unknown())
     }
 
+    #[napi(getter)]
+    pub fn readonly(&self) -> bool {
+        self.inner.borrow().is_readonly()
+    }
+
     #[napi]
     pub fn pluck(&mut self, pluck: Option<bool>) {
         if let Some(false) = pluck {
@@ -537,6 +597,38 @@ impl Generator for IteratorStatement {
     }
 }
 
+fn row_to_js(
+    env: &Env,
+    stmt: &turso_core::Statement,
+    presentation_mode: &PresentationMode,
+) -> napi::Result<JsUnknown> {
+    let row = stmt.row().unwrap();
+    match presentation_mode {
+        PresentationMode::Raw => {
+            let mut raw_array = env.create_array(row.len() as u32)?;
+            for (idx, value) in row.get_values().enumerate() {
+                raw_array.set(idx as u32, to_js_value(env, value)?)?;
+            }
+            Ok(raw_array.coerce_to_object()?.into_unknown())
+        }
+        PresentationMode::Pluck => {
+            let value = row.get_values().next().ok_or(napi::Error::new(
+                napi::Status::GenericFailure,
+                "Pluck mode requires at least one column in the result",
+            ))?;
+            to_js_value(env, value)
+        }
+        PresentationMode::None => {
+            let mut obj = env.create_object()?;
+            for (idx, value) in row.get_values().enumerate() {
+                let key = stmt.get_column_name(idx);
+                obj.set_named_property(&key, to_js_value(env, value)?)?;
+            }
+            Ok(obj.into_unknown())
+        }
+    }
+}
+
 fn to_js_value(env: &napi::Env, value: &turso_core::Value) -> napi::Result<JsUnknown> {
     match value {
         turso_core::Value::Null => Ok(env.get_null()?.into_unknown()),
@@ -12,9 +12,9 @@ class Database {
    * @constructor
    * @param {string} path - Path to the database file.
    * @param {Object} opts - Options for database behavior.
-   * @param {boolean} [opts.readonly=false] - Open the database in read-only mode.
+   * @param {boolean} [opts.readonly=false] - Open an existing database in read-only mode. Statements that would write are rejected when prepared.
    * @param {boolean} [opts.fileMustExist=false] - If true, throws if database file does not exist.
-   * @param {number} [opts.timeout=0] - Timeout duration in milliseconds for database operations. Defaults to 0 (no timeout).
+   * @param {number} [opts.timeout=0] - Milliseconds to wait for a lock held by another connection before failing with a busy error. Defaults to 0 (fail immediately).
    */
   constructor(path, opts = {}) {
     opts.readonly = opts.readonly === undefined ? false : opts.readonly;
@@ -200,6 +200,10 @@ class Statement {
     throw new Error("not implemented");
   }
 
+  get readonly() {
+    return this.stmt.readonly;
+  }
+
   get source() {
     return this.stmt.source;
   }
@@ -215,6 +219,16 @@ class Statement {
     return this.stmt.run(bindParameters.flat());
   }
 
+  /**
+   * Executes the SQL statement once for each array of bind parameters.
+   *
+   * @param rows - An array of bind parameter arrays, one per execution.
+   * @returns The number of rows changed by the whole batch.
+   */
+  runMany(rows) {
+    return this.stmt.runMany(rows);
+  }
+
   /**
    * Executes the SQL statement and returns the first row.
    *
@@ -233,6 +247,72 @@ class Statement {
     return this.stmt.iterate(bindParameters.flat());
   }
 
+  /**
+   * Executes the SQL statement and returns an async iterator over arrays of the resulting rows.
+   *
+   * Every chunk is fetched in its own event loop turn, so streaming a large result doesn't
+   * block the event loop. The statement only advances when the next chunk is requested, and
+   * ending the iteration early (e.g. `break` in a `for await` loop) cancels the statement and
+   * releases its locks. The statement must not be used for anything else until the iteration ends.
+   *
+   * @param {Object} [opts] - Options for the iteration.
+   * @param {number} [opts.rowsPerChunk=1000] - Maximum number of rows in each chunk.
+   * @param bindParameters - The bind parameters for executing the statement.
+   */
+  iterateChunks(opts = {}, ...bindParameters) {
+    const rowsPerChunk = opts.rowsPerChunk === undefined ? 1000 : opts.rowsPerChunk;
+    if (!Number.isInteger(rowsPerChunk) || rowsPerChunk <= 0) {
+      throw new RangeError("rowsPerChunk must be a positive integer");
+    }
+
+    const stmt = this.stmt;
+    stmt.startChunks(bindParameters.flat());
+
+    let done = false;
+    // Serializes next() calls that are issued without awaiting the previous one.
+    let pending = Promise.resolve();
+    const finish = () => {
+      if (!done) {
+        done = true;
+        stmt.cancel();
+      }
+    };
+    const fetch = async () => {
+      if (done) return { done: true, value: undefined };
+      await new Promise((resolve) => setImmediate(resolve));
+      if (done) return { done: true, value: undefined };
+      let chunk;
+      try {
+        chunk = stmt.nextChunk(rowsPerChunk);
+      } catch (err) {
+        finish();
+        throw err;
+      }
+      if (chunk.length < rowsPerChunk) done = true;
+      if (chunk.length === 0) return { done: true, value: undefined };
+      return { done: false, value: chunk };
+    };
+
+    return {
+      next() {
+        const result = pending.then(fetch);
+        pending = result.catch(() => {});
+        return result;
+      },
+      async return(value) {
+        finish();
+        return { done: true, value };
+      },
+      async throw(err) {
+        finish();
+        throw err;
+      },
+      [Symbol.asyncIterator]() {
+        return this;
+      },
+    };
+  }
+
   /**
    * Executes the SQL statement and returns an array of the resulting rows.
    *
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use errors::*;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyList, PyTuple};
 use std::cell::RefCell;
@@ -52,7 +53,7 @@ pub struct Cursor {
     ///
     /// This attribute is `None` for operations that do not return rows or if no `.execute*()` method has been invoked.
     #[pyo3(get)]
-    description: Option<Description>,
+    description: Option<Vec<Description>>,
 
     /// Read-only attribute that provides the number of modified rows for `INSERT`, `UPDATE`, `DELETE`,
     /// and `REPLACE` statements; it is `-1` for other statements, including CTE queries.
@@ -73,8 +74,15 @@ impl Cursor {
         let stmt_is_ddl = stmt_is_ddl(sql);
         let stmt_is_tx = stmt_is_tx(sql);
 
-        let statement = self.conn.conn.prepare(sql).map_err(|e| {
-            PyErr::new::<ProgrammingError, _>(format!("Failed to prepare statement: {:?}", e))
+        if stmt_is_dml {
+            self.conn.begin_implicit_transaction()?;
+        }
+
+        let statement = self.conn.conn.prepare(sql).map_err(|e| match e {
+            turso_core::LimboError::ReadOnly => {
+                PyErr::new::<OperationalError, _>(format!("Failed to prepare statement: {:?}", e))
+            }
+            _ => PyErr::new::<ProgrammingError, _>(format!("Failed to prepare statement: {:?}", e)),
         })?;
 
         let stmt = Rc::new(RefCell::new(statement));
@@ -96,18 +104,28 @@ impl Cursor {
         // For DDL and DML statements,
         // we need to execute the statement immediately
         if stmt_is_ddl || stmt_is_dml || stmt_is_tx {
-            while let turso_core::StepResult::IO = stmt
-                .borrow_mut()
-                .step()
-                .map_err(|e| PyErr::new::<OperationalError, _>(format!("Step error: {:?}", e)))?
-            {
-                self.conn
-                    .io
-                    .run_once()
-                    .map_err(|e| PyErr::new::<OperationalError, _>(format!("IO error: {:?}", e)))?;
+            loop {
+                let result = stmt.borrow_mut().step().map_err(|e| {
+                    PyErr::new::<OperationalError, _>(format!("Step error: {:?}", e))
+                })?;
+                match result {
+                    turso_core::StepResult::IO => {
+                        self.conn.io.run_once().map_err(|e| {
+                            PyErr::new::<OperationalError, _>(format!("IO error: {:?}", e))
+                        })?;
+                    }
+                    // A write has already waited for the write lock for `timeout` seconds.
+                    turso_core::StepResult::Busy => {
+                        return Err(
+                            PyErr::new::<OperationalError, _>("Busy error".to_string()).into()
+                        );
+                    }
+                    _ => break,
+                }
             }
         }
 
+        self.description = description(&stmt.borrow());
         self.smt = Some(stmt);
 
         Ok(Cursor {
@@ -154,6 +172,15 @@ impl Cursor {
         }
     }
 
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Returns the next row, raising `StopIteration` once there are no more.
+    fn __next__(&mut self, py: Python) -> Result<Option<PyObject>> {
+        self.fetchone(py)
+    }
+
     pub fn fetchall(&mut self, py: Python) -> Result<Vec<PyObject>> {
         let mut results = Vec::new();
         if let Some(smt) = &self.smt {
@@ -190,17 +217,76 @@ impl Cursor {
         }
     }
 
-    pub fn close(&self) -> PyResult<()> {
-        self.conn.close()?;
+    /// Whether the last statement prepared by `.execute*()` leaves the database unmodified.
+    #[getter]
+    pub fn is_readonly(&self) -> PyResult<bool> {
+        match &self.smt {
+            Some(smt) => Ok(smt.borrow().is_readonly()),
+            None => Err(PyErr::new::<ProgrammingError, _>(
+                "No statement prepared for execution",
+            )),
+        }
+    }
+
+    /// Finalizes the statement of the cursor. The connection stays open.
+    pub fn close(&mut self) -> PyResult<()> {
+        self.smt = None;
 
         Ok(())
     }
 
     #[pyo3(signature = (sql, parameters=None))]
-    pub fn executemany(&self, sql: &str, parameters: Option<Py<PyList>>) -> PyResult<()> {
-        Err(PyErr::new::<NotSupportedError, _>(
-            "executemany() is not supported in this version",
-        ))
+    pub fn executemany(&mut self, sql: &str, parameters: Option<Py<PyList>>) -> Result<()> {
+        if stmt_is_dml(sql) {
+            self.conn.begin_implicit_transaction()?;
+        }
+        let mut statement = self.conn.conn.prepare(sql).map_err(|e| {
+            PyErr::new::<ProgrammingError, _>(format!("Failed to prepare statement: {:?}", e))
+        })?;
+
+        let rows = Python::with_gil(|py| {
+            let mut rows = Vec::new();
+            if let Some(parameters) = parameters {
+                for params in parameters.into_bound(py).iter() {
+                    let row = params
+                        .try_iter()?
+                        .map(|elem| py_to_owned_value(&elem?))
+                        .collect::<Result<Vec<_>>>()?;
+                    rows.push(row);
+                }
+            }
+            Ok::<_, anyhow::Error>(rows)
+        })?;
+
+        let mut rows = rows.into_iter();
+        loop {
+            let result = statement.execute_batch(&mut rows).map_err(|e| {
+                let msg = format!("Row {}: {:?}", statement.batch_rows_done(), e);
+                match e {
+                    turso_core::LimboError::Constraint(_) => PyErr::new::<IntegrityError, _>(msg),
+                    _ => PyErr::new::<OperationalError, _>(msg),
+                }
+            })?;
+            match result {
+                turso_core::StepResult::IO => {
+                    self.conn.io.run_once().map_err(|e| {
+                        PyErr::new::<OperationalError, _>(format!("IO error: {:?}", e))
+                    })?;
+                }
+                turso_core::StepResult::Done => break,
+                turso_core::StepResult::Interrupt => {
+                    return Err(PyErr::new::<OperationalError, _>("Interrupted".to_string()).into());
+                }
+                turso_core::StepResult::Busy => {
+                    return Err(PyErr::new::<OperationalError, _>("Busy error".to_string()).into());
+                }
+                turso_core::StepResult::Row => unreachable!("execute_batch discards rows"),
+            }
+        }
+
+        self.rowcount = self.conn.conn.changes();
+        self.smt = None;
+        Ok(())
     }
 
     #[pyo3(signature = (size=None))]
@@ -234,6 +320,50 @@ fn stmt_is_tx(sql: &str) -> bool {
 pub struct Connection {
     conn: Arc<turso_core::Connection>,
     io: Arc<dyn turso_core::IO>,
+    /// Kind of transaction begun before DML statements, `None` to run every statement in its
+    /// own transaction. Shared with the cursors of the connection.
+    isolation_level: Rc<RefCell<Option<String>>>,
+}
+
+impl Connection {
+    /// Issues `BEGIN <isolation_level>` if an isolation level is set and no transaction is open.
+    fn begin_implicit_transaction(&self) -> PyResult<()> {
+        let Some(level) = self.isolation_level.borrow().clone() else {
+            return Ok(());
+        };
+        if !self.conn.get_auto_commit() {
+            return Ok(());
+        }
+        self.conn
+            .execute_batch(format!("BEGIN {level}"))
+            .map_err(|e| {
+                PyErr::new::<OperationalError, _>(format!("Failed to begin transaction: {:?}", e))
+            })
+    }
+}
+
+/// Builds the `.description` of a statement from its result column metadata. The `type_code`
+/// is the declared type of the column, or empty if the column is computed.
+fn description(stmt: &turso_core::Statement) -> Option<Vec<Description>> {
+    if stmt.column_count() == 0 {
+        return None;
+    }
+    Some(
+        (0..stmt.column_count())
+            .map(|idx| Description {
+                name: stmt.column_name(idx).to_string(),
+                type_code: stmt
+                    .column_declared_type(idx)
+                    .unwrap_or_default()
+                    .to_string(),
+                display_size: None,
+                internal_size: None,
+                precision: None,
+                scale: None,
+                null_ok: None,
+            })
+            .collect(),
+    )
 }
 
 #[pymethods]
@@ -248,6 +378,16 @@ impl Connection {
         })
     }
 
+    /// Executes a script of SQL statements separated by semicolons, stopping at the
+    /// first statement that fails.
+    pub fn executescript(&self, sql: &str) -> PyResult<()> {
+        self.conn.execute_batch(sql).map_err(|e| {
+            PyErr::new::<OperationalError, _>(format!("Failed to execute script: {:?}", e))
+        })
+    }
+
+    /// Closes the connection. Cursors that are still around fail with "database is closed"
+    /// afterwards. Other connections to the same database are not affected.
     pub fn close(&self) -> PyResult<()> {
         self.conn.close().map_err(|e| {
             PyErr::new::<OperationalError, _>(format!("Failed to close connection: {:?}", e))
@@ -258,13 +398,10 @@ impl Connection {
 
     pub fn commit(&self) -> PyResult<()> {
         if !self.conn.get_auto_commit() {
+            // With an isolation level, the next DML statement begins the next transaction.
             self.conn.execute("COMMIT").map_err(|e| {
                 PyErr::new::<OperationalError, _>(format!("Failed to commit: {:?}", e))
             })?;
-
-            self.conn.execute("BEGIN").map_err(|e| {
-                PyErr::new::<OperationalError, _>(format!("Failed to commit: {:?}", e))
-            })?;
         }
         Ok(())
     }
@@ -272,16 +409,76 @@ impl Connection {
     pub fn rollback(&self) -> PyResult<()> {
         if !self.conn.get_auto_commit() {
             self.conn.execute("ROLLBACK").map_err(|e| {
-                PyErr::new::<OperationalError, _>(format!("Failed to commit: {:?}", e))
+                PyErr::new::<OperationalError, _>(format!("Failed to rollback: {:?}", e))
             })?;
+        }
+        Ok(())
+    }
 
-            self.conn.execute("BEGIN").map_err(|e| {
-                PyErr::new::<OperationalError, _>(format!("Failed to commit: {:?}", e))
-            })?;
+    /// Number of seconds a write waits for another connection to release the write lock
+    /// before failing with `OperationalError`, as passed to `connect()`. Reads don't wait.
+    #[getter]
+    pub fn timeout(&self) -> f64 {
+        self.conn.busy_timeout() as f64 / 1000.0
+    }
+
+    /// Kind of transaction implicitly begun before `INSERT`, `UPDATE` and `DELETE` statements
+    /// when none is open, like `sqlite3.Connection.isolation_level`: `""` or `"DEFERRED"`,
+    /// `"IMMEDIATE"` or `"EXCLUSIVE"`. Defaults to `None`, which runs every statement in its
+    /// own transaction unless one is begun explicitly with `BEGIN`; `commit()` and `rollback()`
+    /// end it and leave the connection in autocommit mode.
+    #[getter]
+    pub fn isolation_level(&self) -> Option<String> {
+        self.isolation_level.borrow().clone()
+    }
+
+    /// Setting `None` commits the open transaction, if any.
+    #[setter]
+    pub fn set_isolation_level(&self, level: Option<String>) -> PyResult<()> {
+        match &level {
+            Some(level) => {
+                if !["", "DEFERRED", "IMMEDIATE", "EXCLUSIVE"]
+                    .contains(&level.to_uppercase().as_str())
+                {
+                    return Err(PyValueError::new_err(
+                        "isolation_level string must be '', 'DEFERRED', 'IMMEDIATE', or 'EXCLUSIVE'",
+                    ));
+                }
+            }
+            None => {
+                if !self.conn.get_auto_commit() {
+                    self.conn.execute("COMMIT").map_err(|e| {
+                        PyErr::new::<OperationalError, _>(format!("Failed to commit: {:?}", e))
+                    })?;
+                }
+            }
         }
+        *self.isolation_level.borrow_mut() = level;
         Ok(())
     }
 
+    /// Returns the connection to the state of a freshly opened one: rolls back an open
+    /// transaction and resets session pragmas and registered functions.
+    pub fn reset_session(&self) -> PyResult<()> {
+        self.conn.reset_session().map_err(|e| {
+            PyErr::new::<OperationalError, _>(format!("Failed to reset session: {:?}", e))
+        })
+    }
+
+    /// Registers `callback`, called with the SQL text of every statement once it has run to
+    /// completion, like `sqlite3.Connection.set_trace_callback`. Exceptions raised by the
+    /// callback are ignored. Passing `None` removes the callback.
+    pub fn set_trace_callback(&self, callback: Option<PyObject>) {
+        match callback {
+            Some(callback) => self.conn.set_trace_hook(Box::new(move |sql, _| {
+                Python::with_gil(|py| {
+                    let _ = callback.call1(py, (sql,));
+                })
+            })),
+            None => self.conn.clear_trace_hook(),
+        }
+    }
+
     fn __enter__(&self) -> PyResult<Self> {
         Ok(self.clone())
     }
@@ -298,17 +495,34 @@ impl Connection {
 
 impl Drop for Connection {
     fn drop(&mut self) {
-        self.conn
-            .close()
-            .expect("Failed to drop (close) connection");
+        // Cursors hold clones of the connection; only the last one closes it.
+        if Arc::strong_count(&self.conn) == 1 {
+            self.close().expect("Failed to drop (close) connection");
+        }
     }
 }
 
 #[allow(clippy::arc_with_non_send_sync)]
 #[pyfunction]
-pub fn connect(path: &str) -> Result<Connection> {
-    match turso_core::Connection::from_uri(path, false, false) {
-        Ok((io, conn)) => Ok(Connection { conn, io }),
+#[pyo3(signature = (path, read_only=false, timeout=5.0, key=None))]
+pub fn connect(path: &str, read_only: bool, timeout: f64, key: Option<&str>) -> Result<Connection> {
+    let result = match key {
+        Some(key) => register_encrypted_vfs().and_then(|()| {
+            turso_core::Connection::from_uri(&encrypted_uri(path, key, read_only), false, false)
+        }),
+        None if read_only => open_readonly(path),
+        None => turso_core::Connection::from_uri(path, false, false),
+    };
+    match result {
+        Ok((io, conn)) => {
+            // Like sqlite3, `timeout` is the number of seconds to wait for a lock.
+            conn.set_busy_timeout((timeout.max(0.0) * 1000.0) as u64);
+            Ok(Connection {
+                conn,
+                io,
+                isolation_level: Rc::new(RefCell::new(None)),
+            })
+        }
         Err(e) => Err(PyErr::new::<ProgrammingError, _>(format!(
             "Failed to create connection: {:?}",
             e
@@ -317,6 +531,59 @@ pub fn connect(path: &str) -> Result<Connection> {
     }
 }
 
+#[allow(clippy::arc_with_non_send_sync)]
+fn open_readonly(
+    path: &str,
+) -> turso_core::Result<(Arc<dyn turso_core::IO>, Arc<turso_core::Connection>)> {
+    let io: Arc<dyn turso_core::IO> = Arc::new(turso_core::PlatformIO::new()?);
+    let db = turso_core::Database::open_readonly(io.clone(), path)?;
+    let conn = db.connect()?;
+    Ok((io, conn))
+}
+
+/// Registers the encrypted VFS, which is built into the module, the first time a database is
+/// opened with a key.
+fn register_encrypted_vfs() -> turso_core::Result<()> {
+    static REGISTER: std::sync::Once = std::sync::Once::new();
+    let mut result = Ok(());
+    REGISTER.call_once(|| {
+        result = unsafe {
+            turso_core::register_static_vfs(limbo_encryption::register_static_EncryptedFS())
+        };
+    });
+    result
+}
+
+/// Returns the URI opening the database at `path`, which may already be a URI, encrypted with
+/// the passphrase `key`.
+fn encrypted_uri(path: &str, key: &str, read_only: bool) -> String {
+    let mut uri = if path.starts_with("file:") {
+        path.to_string()
+    } else {
+        format!("file:{}", encode_uri_component(path, "/"))
+    };
+    uri.push(if uri.contains('?') { '&' } else { '?' });
+    uri.push_str("key=");
+    uri.push_str(&encode_uri_component(key, ""));
+    if read_only {
+        uri.push_str("&mode=ro");
+    }
+    uri
+}
+
+/// Percent-encodes the bytes of `s` that may have a meaning in a URI, except those in `keep`.
+fn encode_uri_component(s: &str, keep: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) || keep.as_bytes().contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
 fn row_to_py(py: Python, row: &turso_core::Row) -> Result<PyObject> {
     let mut py_values = Vec::new();
     for value in row.get_values() {
@@ -1,5 +1,6 @@
 import os
 import sqlite3
+import time
 
 import pytest
 import turso
@@ -107,6 +108,20 @@ def test_fetchone_select_all_users(provider):
     assert bob == (2, "bob")
 
 
+@pytest.mark.parametrize("provider", ["sqlite3", "turso"])
+def test_cursor_iteration(provider):
+    conn = connect(provider, "tests/database.db")
+    cursor = conn.cursor()
+
+    users = [row for row in cursor.execute("SELECT * FROM users ORDER BY id")]
+    assert users == [(1, "alice"), (2, "bob")]
+
+    cursor.execute("SELECT id FROM users ORDER BY id")
+    assert next(cursor) == (1,)
+    assert list(cursor) == [(2,)]
+    conn.close()
+
+
 @pytest.mark.parametrize("provider", ["sqlite3", "turso"])
 def test_fetchone_select_max_user_id(provider):
     conn = connect(provider, "tests/database.db")
@@ -158,6 +173,250 @@ def test_commit(provider):
     assert record
 
 
+@pytest.mark.parametrize("provider", ["sqlite3", "turso"])
+def test_executemany(provider):
+    conn = connect(provider, "tests/database.db")
+    cur = conn.cursor()
+    cur.executemany("INSERT INTO users VALUES (?, ?)", [(3, "carol"), (4, "dave"), (5, "erin")])
+    conn.commit()
+    assert cur.rowcount == 3
+
+    cur.execute("SELECT COUNT(*) FROM users")
+    assert cur.fetchone() == (5,)
+    conn.close()
+
+
+def test_executemany_reports_failing_row():
+    conn = connect("turso", "tests/database.db")
+    cur = conn.cursor()
+    with pytest.raises(turso.IntegrityError, match="^Row 1:"):
+        cur.executemany("INSERT INTO users VALUES (?, ?)", [(3, "carol"), (1, "alice"), (4, "dave")])
+
+    cur.execute("SELECT COUNT(*) FROM users")
+    assert cur.fetchone() == (3,)
+    conn.close()
+
+
+def test_description_reports_declared_types():
+    conn = connect("turso", "tests/database.db")
+    cur = conn.cursor()
+    cur.execute("SELECT id, username AS name, length(username) FROM users")
+    assert [d.type_code for d in cur.description] == ["INT", "TEXT", ""]
+    assert [d.name for d in cur.description][:2] == ["id", "name"]
+    conn.close()
+
+
+def test_cursor_is_readonly():
+    conn = connect("turso", "tests/database.db")
+    cur = conn.cursor()
+    cur.execute("SELECT * FROM users")
+    assert cur.is_readonly
+    cur.execute("INSERT INTO users VALUES (3, 'carol')")
+    assert not cur.is_readonly
+    conn.close()
+
+
+def test_read_only_rejects_writes():
+    conn = turso.connect("tests/database.db", read_only=True)
+    cur = conn.cursor()
+    cur.execute("SELECT COUNT(*) FROM users")
+    assert cur.fetchone() == (2,)
+
+    with pytest.raises(turso.OperationalError, match="ReadOnly"):
+        cur.execute("INSERT INTO users VALUES (3, 'carol')")
+    with pytest.raises(turso.OperationalError, match="ReadOnly"):
+        cur.execute("CREATE TABLE t (x)")
+    conn.close()
+
+
+def test_connect_uri():
+    conn = turso.connect("file:tests/database.db?mode=ro&cache=shared")
+    cur = conn.cursor()
+    cur.execute("SELECT username FROM users ORDER BY id")
+    assert cur.fetchall() == [("alice",), ("bob",)]
+
+    with pytest.raises(turso.OperationalError, match="ReadOnly"):
+        cur.execute("INSERT INTO users VALUES (3, 'carol')")
+    conn.close()
+
+
+def test_connect_with_key():
+    db_path = "tests/encrypted.db"
+    for path in (db_path, db_path + "-wal"):
+        if os.path.exists(path):
+            os.remove(path)
+
+    conn = turso.connect(db_path, key="secret")
+    cur = conn.cursor()
+    cur.execute("CREATE TABLE t (x TEXT)")
+    cur.execute("INSERT INTO t VALUES ('hidden value')")
+    conn.commit()
+    conn.close()
+
+    for path in (db_path, db_path + "-wal"):
+        with open(path, "rb") as f:
+            assert b"hidden value" not in f.read()
+
+    conn = turso.connect(db_path, key="secret")
+    cur = conn.cursor()
+    cur.execute("SELECT x FROM t")
+    assert cur.fetchall() == [("hidden value",)]
+    conn.close()
+
+    with pytest.raises(turso.ProgrammingError):
+        turso.connect(db_path, key="wrong")
+
+
+def test_reset_session_rolls_back_leaked_transaction():
+    conn = connect("turso", "tests/database.db")
+    cur = conn.cursor()
+    cur.execute("PRAGMA cache_size = 100").fetchall()
+    cur.execute("BEGIN")
+    cur.execute("INSERT INTO users VALUES (3, 'carol')")
+
+    conn.reset_session()
+
+    cur = conn.cursor()
+    cur.execute("SELECT COUNT(*) FROM users")
+    assert cur.fetchone() == (2,)
+    cur.execute("PRAGMA cache_size")
+    assert cur.fetchone() == (-2000,)
+    conn.close()
+
+
+def test_isolation_level():
+    conn = connect("turso", "tests/database.db")
+    other = connect("turso", "tests/database.db")
+    assert conn.isolation_level is None
+    with pytest.raises(ValueError):
+        conn.isolation_level = "SERIALIZABLE"
+
+    conn.isolation_level = "IMMEDIATE"
+    assert conn.isolation_level == "IMMEDIATE"
+    cur = conn.cursor()
+    cur.execute("INSERT INTO users VALUES (3, 'carol')")
+    other_cur = other.cursor()
+    other_cur.execute("SELECT COUNT(*) FROM users")
+    assert other_cur.fetchone() == (2,)
+    conn.rollback()
+    cur.execute("SELECT COUNT(*) FROM users")
+    assert cur.fetchone() == (2,)
+
+    cur.execute("INSERT INTO users VALUES (3, 'carol')")
+    conn.commit()
+    other_cur.execute("SELECT COUNT(*) FROM users")
+    assert other_cur.fetchone() == (3,)
+
+    # Without an isolation level, every statement commits on its own.
+    conn.isolation_level = None
+    cur.execute("INSERT INTO users VALUES (4, 'dave')")
+    other_cur.execute("SELECT COUNT(*) FROM users")
+    assert other_cur.fetchone() == (4,)
+
+    # An explicit transaction ends with commit(), after which statements commit on their own.
+    cur.execute("BEGIN")
+    cur.execute("INSERT INTO users VALUES (5, 'erin')")
+    conn.commit()
+    cur.execute("INSERT INTO users VALUES (6, 'frank')")
+    other_cur.execute("SELECT COUNT(*) FROM users")
+    assert other_cur.fetchone() == (6,)
+    other.close()
+    conn.close()
+
+
+def test_timeout_retries_busy_writes():
+    conn = connect("turso", "tests/database.db")
+    assert conn.timeout == 5.0
+    other = turso.connect("tests/database.db", timeout=0.2)
+    assert other.timeout == 0.2
+
+    cur = conn.cursor()
+    cur.execute("BEGIN")
+    cur.execute("INSERT INTO users VALUES (3, 'carol')")
+
+    other_cur = other.cursor()
+    start = time.monotonic()
+    with pytest.raises(turso.OperationalError):
+        other_cur.execute("INSERT INTO users VALUES (4, 'dave')")
+    assert time.monotonic() - start >= 0.2
+
+    # Reads don't conflict with the writer.
+    other_cur.execute("SELECT COUNT(*) FROM users")
+    assert other_cur.fetchone() == (2,)
+
+    conn.commit()
+    other_cur.execute("INSERT INTO users VALUES (4, 'dave')")
+    other_cur.execute("SELECT COUNT(*) FROM users")
+    assert other_cur.fetchone() == (4,)
+    other.close()
+    conn.close()
+
+
+@pytest.mark.parametrize("provider", ["sqlite3", "turso"])
+def test_executescript(provider):
+    conn = connect(provider, "tests/database.db")
+    conn.executescript("""
+        CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT);
+        -- semicolons in comments; and strings are not separators
+        INSERT INTO posts VALUES (1, 'a;b');
+        INSERT INTO posts VALUES (2, 'c');
+    """)
+    cur = conn.cursor()
+    cur.execute("SELECT title FROM posts ORDER BY id")
+    assert cur.fetchall() == [("a;b",), ("c",)]
+    conn.close()
+
+
+def test_executescript_stops_at_failing_statement():
+    conn = connect("turso", "tests/database.db")
+    with pytest.raises(turso.OperationalError):
+        conn.executescript("INSERT INTO users VALUES (3, 'carol'); INSERT INTO missing VALUES (1);")
+    cur = conn.cursor()
+    cur.execute("SELECT COUNT(*) FROM users")
+    assert cur.fetchone() == (3,)
+    conn.close()
+
+
+def test_cursor_after_close_fails_cleanly():
+    conn = connect("turso", "tests/database.db")
+    cursor = conn.cursor()
+    cursor.execute("SELECT id FROM users ORDER BY id")
+    assert cursor.fetchone() == (1,)
+
+    conn.close()
+    with pytest.raises(turso.OperationalError, match="DatabaseClosed"):
+        cursor.fetchone()
+    # Closing twice is a no-op.
+    conn.close()
+
+
+def test_explain_query_plan():
+    conn = connect("turso", ":memory:")
+    cur = conn.cursor()
+    cur.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, x)")
+    cur.execute("EXPLAIN QUERY PLAN SELECT x FROM t WHERE id = 1")
+    assert [d.name for d in cur.description] == ["id", "parent", "notused", "detail"]
+    assert cur.fetchall() == [(1, 0, 0, "SEARCH t USING INTEGER PRIMARY KEY (rowid=?)")]
+    conn.close()
+
+
+def test_set_trace_callback():
+    conn = connect("turso", ":memory:")
+    statements = []
+    conn.set_trace_callback(statements.append)
+    cur = conn.cursor()
+    cur.execute("CREATE TABLE t (x)")
+    cur.execute("INSERT INTO t VALUES (1)")
+    cur.execute("SELECT x FROM t")
+    assert cur.fetchall() == [(1,)]
+    assert statements == ["CREATE TABLE t (x)", "INSERT INTO t VALUES (1)", "SELECT x FROM t"]
+
+    conn.set_trace_callback(None)
+    cur.execute("INSERT INTO t VALUES (2)")
+    assert len(statements) == 3
+    conn.close()
+
+
 @pytest.mark.parametrize("provider", ["sqlite3", "turso"])
 def test_with_statement(provider):
     with connect(provider, "tests/database.db") as conn:
@@ -31,18 +31,50 @@
 //! println!("Row: {:?}", value);
 //! # }
 //! ```
+//!
+//! [`Rows`] is also a [`Stream`](futures_core::Stream) of rows:
+//!
+//! ```rust,no_run
+//! # async fn run() {
+//! # use turso::Builder;
+//! # let db = Builder::new_local(":memory:").build().await.unwrap();
+//! # let conn = db.connect().unwrap();
+//! use futures_util::TryStreamExt;
+//!
+//! let mut rows = conn.query("SELECT * FROM users", ()).await.unwrap();
+//! while let Some(row) = rows.try_next().await.unwrap() {
+//!     println!("Row: {:?}", row.get_value(0).unwrap());
+//! }
+//! # }
+//! ```
+//!
+//! ## Tokio
+//!
+//! The database engine does its I/O synchronously, so by default statements run on the
+//! thread that polls their future. With the `tokio` feature, every [`Connection`] gets a
+//! thread of its own instead, which runs its statements one after the other, so that they
+//! don't stall the runtime's worker threads, as web frameworks like Axum expect. The
+//! connection and its statements are only ever used from that thread, and the futures just
+//! wait for their results, so they can be polled from any runtime.
 
 pub mod params;
 pub mod value;
+mod worker;
 
 pub use value::Value;
 
 pub use params::params_from_iter;
 
 use crate::params::*;
+use crate::worker::{Local, Worker};
+use std::cell::RefCell;
 use std::fmt::Debug;
+#[cfg(feature = "tokio")]
+use std::future::Future;
 use std::num::NonZero;
-use std::sync::{Arc, Mutex};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -127,31 +159,25 @@ impl Debug for Database {
 impl Database {
     /// Connect to the database.
     pub fn connect(&self) -> Result<Connection> {
-        let conn = self.inner.connect()?;
-        #[allow(clippy::arc_with_non_send_sync)]
+        let worker = Worker::spawn()?;
+        let db = self.inner.clone();
+        let job_worker = worker.clone();
+        let conn = worker.run_blocking(move || Ok(Local::new(&job_worker, db.connect()?)))?;
         let connection = Connection {
-            inner: Arc::new(Mutex::new(conn)),
+            inner: Arc::new(conn),
+            worker,
         };
         Ok(connection)
     }
 }
 
 /// A database connection.
+#[derive(Clone)]
 pub struct Connection {
-    inner: Arc<Mutex<Arc<turso_core::Connection>>>,
+    inner: Arc<Local<Arc<turso_core::Connection>>>,
+    worker: Worker,
 }
 
-impl Clone for Connection {
-    fn clone(&self) -> Self {
-        Self {
-            inner: Arc::clone(&self.inner),
-        }
-    }
-}
-
-unsafe impl Send for Connection {}
-unsafe impl Sync for Connection {}
-
 impl Connection {
     /// Query the database with SQL.
     pub async fn query(&self, sql: &str, params: impl IntoParams) -> Result<Rows> {
@@ -165,20 +191,42 @@ impl Connection {
         stmt.execute(params).await
     }
 
+    /// Execute a batch of SQL statements separated by semicolons, such as a
+    /// migration script. Stops at the first statement that fails.
+    pub async fn execute_batch(&self, sql: &str) -> Result<()> {
+        let conn = self.inner.clone();
+        let sql = sql.to_string();
+        self.worker
+            .run(move || {
+                conn.get().execute_batch(&sql)?;
+                Ok(())
+            })
+            .await
+    }
+
     /// Prepare a SQL statement for later execution.
     pub async fn prepare(&self, sql: &str) -> Result<Statement> {
-        let conn = self
-            .inner
-            .lock()
-            .map_err(|e| Error::MutexError(e.to_string()))?;
-
-        let stmt = conn.prepare(sql)?;
-
-        #[allow(clippy::arc_with_non_send_sync)]
-        let statement = Statement {
-            inner: Arc::new(Mutex::new(stmt)),
-        };
-        Ok(statement)
+        let conn = self.inner.clone();
+        let sql = sql.to_string();
+        let worker = self.worker.clone();
+        self.worker
+            .run(move || {
+                let stmt = conn.get().prepare(&sql)?;
+                let columns = (0..stmt.num_columns())
+                    .map(|i| Column {
+                        name: stmt.get_column_name(i).into_owned(),
+                        decl_type: stmt.column_declared_type(i).map(str::to_string),
+                    })
+                    .collect();
+                let readonly = stmt.is_readonly();
+                Ok(Statement {
+                    inner: Arc::new(Local::new(&worker, RefCell::new(stmt))),
+                    worker,
+                    columns: Arc::new(columns),
+                    readonly,
+                })
+            })
+            .await
     }
 
     /// Query a pragma.
@@ -186,17 +234,17 @@ impl Connection {
     where
         F: FnMut(&Row) -> turso_core::Result<()>,
     {
-        let conn = self
-            .inner
-            .lock()
-            .map_err(|e| Error::MutexError(e.to_string()))?;
-
-        let rows: Vec<Row> = conn
-            .pragma_query(pragma_name)
-            .map_err(|e| Error::SqlExecutionFailure(e.to_string()))?
-            .iter()
-            .map(|row| row.iter().collect::<Row>())
-            .collect();
+        let conn = self.inner.clone();
+        let pragma_name = pragma_name.to_string();
+        let rows: Vec<Row> = self.worker.run_blocking(move || {
+            Ok(conn
+                .get()
+                .pragma_query(&pragma_name)
+                .map_err(|e| Error::SqlExecutionFailure(e.to_string()))?
+                .iter()
+                .map(|row| row.iter().collect::<Row>())
+                .collect())
+        })?;
 
         rows.iter().try_for_each(|row| {
             f(row).map_err(|e| {
@@ -214,119 +262,107 @@ impl Debug for Connection {
 }
 
 /// A prepared statement.
+#[derive(Clone)]
 pub struct Statement {
-    inner: Arc<Mutex<turso_core::Statement>>,
-}
-
-impl Clone for Statement {
-    fn clone(&self) -> Self {
-        Self {
-            inner: Arc::clone(&self.inner),
-        }
-    }
+    inner: Arc<Local<RefCell<turso_core::Statement>>>,
+    worker: Worker,
+    /// Read when the statement is prepared, so that asking for them doesn't need the worker.
+    columns: Arc<Vec<Column>>,
+    readonly: bool,
 }
 
-unsafe impl Send for Statement {}
-unsafe impl Sync for Statement {}
-
 impl Statement {
     /// Query the database with this prepared statement.
     pub async fn query(&mut self, params: impl IntoParams) -> Result<Rows> {
         let params = params.into_params()?;
-        match params {
-            params::Params::None => (),
-            params::Params::Positional(values) => {
-                for (i, value) in values.into_iter().enumerate() {
-                    let mut stmt = self.inner.lock().unwrap();
-                    stmt.bind_at(NonZero::new(i + 1).unwrap(), value.into());
-                }
-            }
-            params::Params::Named(values) => {
-                for (name, value) in values.into_iter() {
-                    let mut stmt = self.inner.lock().unwrap();
-                    let i = stmt.parameters().index(name).unwrap();
-                    stmt.bind_at(i, value.into());
-                }
-            }
-        }
-        #[allow(clippy::arc_with_non_send_sync)]
+        let stmt = self.inner.clone();
+        self.worker
+            .run(move || {
+                bind(&mut stmt.get().borrow_mut(), params);
+                Ok(())
+            })
+            .await?;
         let rows = Rows {
             inner: Arc::clone(&self.inner),
+            worker: self.worker.clone(),
+            #[cfg(feature = "tokio")]
+            pending: None,
         };
         Ok(rows)
     }
 
     /// Execute this prepared statement.
     pub async fn execute(&mut self, params: impl IntoParams) -> Result<u64> {
-        {
-            // Reset the statement before executing
-            self.inner.lock().unwrap().reset();
-        }
         let params = params.into_params()?;
-        match params {
-            params::Params::None => (),
-            params::Params::Positional(values) => {
-                for (i, value) in values.into_iter().enumerate() {
-                    let mut stmt = self.inner.lock().unwrap();
-                    stmt.bind_at(NonZero::new(i + 1).unwrap(), value.into());
-                }
-            }
-            params::Params::Named(values) => {
-                for (name, value) in values.into_iter() {
-                    let mut stmt = self.inner.lock().unwrap();
-                    let i = stmt.parameters().index(name).unwrap();
-                    stmt.bind_at(i, value.into());
-                }
-            }
-        }
-        loop {
-            let mut stmt = self.inner.lock().unwrap();
-            match stmt.step() {
-                Ok(turso_core::StepResult::Row) => {
-                    // unexpected row during execution, error out.
-                    return Ok(2);
-                }
-                Ok(turso_core::StepResult::Done) => {
-                    return Ok(0);
-                }
-                Ok(turso_core::StepResult::IO) => {
-                    let _ = stmt.run_once();
-                    //return Ok(1);
-                }
-                Ok(turso_core::StepResult::Busy) => {
-                    return Ok(4);
-                }
-                Ok(turso_core::StepResult::Interrupt) => {
-                    return Ok(3);
-                }
-                Err(err) => {
-                    return Err(err.into());
-                }
-            }
-        }
+        let stmt = self.inner.clone();
+        self.worker
+            .run(move || {
+                let mut stmt = stmt.get().borrow_mut();
+                // Reset the statement before executing
+                stmt.reset();
+                bind(&mut stmt, params);
+                execute(&mut stmt)
+            })
+            .await
     }
 
     /// Returns columns of the result of this prepared statement.
     pub fn columns(&self) -> Vec<Column> {
-        let stmt = self.inner.lock().unwrap();
-
-        let n = stmt.num_columns();
+        self.columns.to_vec()
+    }
 
-        let mut cols = Vec::with_capacity(n);
+    /// Returns true if executing this prepared statement does not modify the database.
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+}
 
-        for i in 0..n {
-            let name = stmt.get_column_name(i).into_owned();
-            cols.push(Column {
-                name,
-                decl_type: None, // TODO
-            });
+fn bind(stmt: &mut turso_core::Statement, params: params::Params) {
+    match params {
+        params::Params::None => (),
+        params::Params::Positional(values) => {
+            for (i, value) in values.into_iter().enumerate() {
+                stmt.bind_at(NonZero::new(i + 1).unwrap(), value.into());
+            }
+        }
+        params::Params::Named(values) => {
+            for (name, value) in values.into_iter() {
+                let i = stmt.parameters().index(name).unwrap();
+                stmt.bind_at(i, value.into());
+            }
         }
+    }
+}
 
-        cols
+fn execute(stmt: &mut turso_core::Statement) -> Result<u64> {
+    loop {
+        match stmt.step() {
+            Ok(turso_core::StepResult::Row) => {
+                // unexpected row during execution, error out.
+                return Ok(2);
+            }
+            Ok(turso_core::StepResult::Done) => {
+                return Ok(0);
+            }
+            Ok(turso_core::StepResult::IO) => {
+                let _ = stmt.run_once();
+                //return Ok(1);
+            }
+            Ok(turso_core::StepResult::Busy) => {
+                return Ok(4);
+            }
+            Ok(turso_core::StepResult::Interrupt) => {
+                return Ok(3);
+            }
+            Err(err) => {
+                return Err(err.into());
+            }
+        }
     }
 }
 
 /// Column information.
+#[derive(Clone)]
 pub struct Column {
     name: String,
     decl_type: Option<String>,
@@ -359,50 +395,88 @@ pub struct Transaction {}
 
 /// Results of a prepared statement query.
 pub struct Rows {
-    inner: Arc<Mutex<turso_core::Statement>>,
+    inner: Arc<Local<RefCell<turso_core::Statement>>>,
+    worker: Worker,
+    /// The step started by [`Stream::poll_next`](futures_core::Stream::poll_next) that has
+    /// not produced its row yet.
+    #[cfg(feature = "tokio")]
+    pending: Option<tokio::sync::oneshot::Receiver<Result<Option<Row>>>>,
 }
 
 impl Clone for Rows {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
+            worker: self.worker.clone(),
+            #[cfg(feature = "tokio")]
+            pending: None,
         }
     }
 }
 
-unsafe impl Send for Rows {}
-unsafe impl Sync for Rows {}
-
 impl Rows {
     /// Fetch the next row of this result set.
     pub async fn next(&mut self) -> Result<Option<Row>> {
-        loop {
-            let mut stmt = self
-                .inner
-                .lock()
-                .map_err(|e| Error::MutexError(e.to_string()))?;
-            match stmt.step() {
-                Ok(turso_core::StepResult::Row) => {
-                    let row = stmt.row().unwrap();
-                    return Ok(Some(Row {
-                        values: row.get_values().map(|v| v.to_owned()).collect(),
-                    }));
-                }
-                Ok(turso_core::StepResult::Done) => return Ok(None),
-                Ok(turso_core::StepResult::IO) => {
-                    if let Err(e) = stmt.run_once() {
-                        return Err(e.into());
-                    }
-                    continue;
+        let stmt = self.inner.clone();
+        self.worker
+            .run(move || next_row(&mut stmt.get().borrow_mut()))
+            .await
+    }
+}
+
+fn next_row(stmt: &mut turso_core::Statement) -> Result<Option<Row>> {
+    loop {
+        match stmt.step() {
+            Ok(turso_core::StepResult::Row) => {
+                let row = stmt.row().unwrap();
+                return Ok(Some(Row {
+                    values: row.get_values().map(|v| v.to_owned()).collect(),
+                }));
+            }
+            Ok(turso_core::StepResult::Done) => return Ok(None),
+            Ok(turso_core::StepResult::IO) => {
+                if let Err(e) = stmt.run_once() {
+                    return Err(e.into());
                 }
-                Ok(turso_core::StepResult::Busy) => return Ok(None),
-                Ok(turso_core::StepResult::Interrupt) => return Ok(None),
-                _ => return Ok(None),
+                continue;
             }
+            Ok(turso_core::StepResult::Busy) => return Ok(None),
+            Ok(turso_core::StepResult::Interrupt) => return Ok(None),
+            _ => return Ok(None),
         }
     }
 }
 
+impl futures_core::Stream for Rows {
+    type Item = Result<Row>;
+
+    #[cfg(feature = "tokio")]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.pending.is_none() {
+            let stmt = self.inner.clone();
+            let step = self
+                .worker
+                .submit(move || next_row(&mut stmt.get().borrow_mut()));
+            self.pending = Some(step);
+        }
+        let step = self.pending.as_mut().expect("a step was just started");
+        let result = match Pin::new(step).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.pending = None;
+        Poll::Ready(match result {
+            Ok(row) => row.transpose(),
+            Err(_) => Some(Err(worker::thread_exited())),
+        })
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(next_row(&mut self.inner.get().borrow_mut()).transpose())
+    }
+}
+
 /// Query result row.
 #[derive(Debug)]
 pub struct Row {
@@ -490,6 +564,28 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_statement_is_readonly() -> Result<()> {
+        let db = Builder::new_local(":memory:").build().await?;
+        let conn = db.connect()?;
+        conn.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT);", ())
+            .await?;
+
+        assert!(conn.prepare("SELECT * FROM t;").await?.is_readonly());
+        assert!(conn.prepare("SELECT 1;").await?.is_readonly());
+        assert!(!conn
+            .prepare("INSERT INTO t (name) VALUES ('Alice');")
+            .await?
+            .is_readonly());
+        assert!(!conn.prepare("DELETE FROM t;").await?.is_readonly());
+        assert!(!conn
+            .prepare("CREATE TABLE u (id INTEGER);")
+            .await?
+            .is_readonly());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_database_persistence_many_frames() -> Result<()> {
         let temp_file = NamedTempFile::new().unwrap();
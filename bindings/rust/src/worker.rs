@@ -0,0 +1,187 @@
+//! Where the statements of a connection run.
+//!
+//! The connection and statement objects of the database engine are not thread-safe, so they
+//! are wrapped in [`Local`] and only touched from jobs run by the [`Worker`] of their
+//! connection.
+//!
+//! With the `tokio` feature, the worker is a thread of its own per connection, which runs the
+//! jobs sent to it over a channel one after the other. The engine objects are created, used and
+//! dropped on that thread only, and the futures of the API just wait for the result of their
+//! job, so that statements never block the worker threads of the runtime. Without the feature,
+//! jobs run right away on the calling thread.
+
+use crate::Result;
+use std::mem::ManuallyDrop;
+
+#[cfg(feature = "tokio")]
+use crate::Error;
+#[cfg(feature = "tokio")]
+use std::sync::mpsc;
+#[cfg(feature = "tokio")]
+use std::thread::{self, ThreadId};
+
+#[cfg(feature = "tokio")]
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs the jobs of a connection.
+#[derive(Clone)]
+pub(crate) struct Worker {
+    #[cfg(feature = "tokio")]
+    jobs: mpsc::Sender<Job>,
+    #[cfg(feature = "tokio")]
+    thread: ThreadId,
+}
+
+#[cfg(feature = "tokio")]
+impl Worker {
+    /// Start the thread of a new connection. It exits once every handle to the connection and
+    /// its statements is dropped.
+    pub(crate) fn spawn() -> Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let handle = thread::Builder::new()
+            .name("turso-connection".to_string())
+            .spawn(move || {
+                while let Ok(job) = receiver.recv() {
+                    job();
+                }
+            })
+            .map_err(|e| Error::SqlExecutionFailure(e.to_string()))?;
+        Ok(Self {
+            jobs,
+            thread: handle.thread().id(),
+        })
+    }
+
+    fn is_current(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+
+    /// Start `f` on the worker, and return the receiver of its result.
+    pub(crate) fn submit<T, F>(&self, f: F) -> tokio::sync::oneshot::Receiver<Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        // If the thread is gone, the job is dropped along with the sender, which the receiver
+        // reports as an error.
+        let _ = self.jobs.send(Box::new(move || {
+            let _ = sender.send(f());
+        }));
+        receiver
+    }
+
+    /// Run `f` on the worker.
+    pub(crate) async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        self.submit(f).await.map_err(|_| thread_exited())?
+    }
+
+    /// Run `f` on the worker and block until it's done, for the synchronous parts of the API.
+    pub(crate) fn run_blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        // Not a Tokio channel: blocking on one of those panics inside a runtime.
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let _ = self.jobs.send(Box::new(move || {
+            let _ = sender.send(f());
+        }));
+        receiver.recv().map_err(|_| thread_exited())?
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) fn thread_exited() -> Error {
+    Error::SqlExecutionFailure("the thread of the connection has exited".to_string())
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Worker {
+    pub(crate) fn spawn() -> Result<Self> {
+        Ok(Self {})
+    }
+
+    pub(crate) async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        f()
+    }
+
+    pub(crate) fn run_blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        f()
+    }
+}
+
+/// An engine object that belongs to the worker of its connection.
+///
+/// With the `tokio` feature, [`Local::get`] panics when it's called off the worker thread, and
+/// dropping the last handle elsewhere sends the object back to the worker to be dropped there,
+/// which is what makes it sound to send the handle between threads.
+pub(crate) struct Local<T: 'static> {
+    value: ManuallyDrop<T>,
+    #[cfg(feature = "tokio")]
+    worker: Worker,
+}
+
+unsafe impl<T: 'static> Send for Local<T> {}
+unsafe impl<T: 'static> Sync for Local<T> {}
+
+impl<T: 'static> Local<T> {
+    /// Wrap an object created by a job of `worker`.
+    #[allow(unused_variables)]
+    pub(crate) fn new(worker: &Worker, value: T) -> Self {
+        #[cfg(feature = "tokio")]
+        assert!(worker.is_current(), "engine object created off its worker");
+        Self {
+            value: ManuallyDrop::new(value),
+            #[cfg(feature = "tokio")]
+            worker: worker.clone(),
+        }
+    }
+
+    pub(crate) fn get(&self) -> &T {
+        #[cfg(feature = "tokio")]
+        assert!(
+            self.worker.is_current(),
+            "engine object used off its worker"
+        );
+        &self.value
+    }
+}
+
+/// Moves an engine object into the job that drops it.
+#[cfg(feature = "tokio")]
+struct SendToWorker<T>(T);
+
+#[cfg(feature = "tokio")]
+unsafe impl<T> Send for SendToWorker<T> {}
+
+impl<T: 'static> Drop for Local<T> {
+    fn drop(&mut self) {
+        // SAFETY: the value is not used again.
+        let value = unsafe { ManuallyDrop::take(&mut self.value) };
+        #[cfg(feature = "tokio")]
+        if !self.worker.is_current() {
+            let value = SendToWorker(value);
+            // Dropping the wrapper as a whole, so that the closure captures it and not the value.
+            if let Err(mpsc::SendError(job)) = self.worker.jobs.send(Box::new(move || drop(value)))
+            {
+                // The thread is gone, so the object can't be dropped where it belongs. Leak it.
+                std::mem::forget(job);
+            }
+            return;
+        }
+        drop(value);
+    }
+}
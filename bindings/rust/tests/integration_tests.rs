@@ -55,3 +55,111 @@ async fn test_rows_next() {
     );
     assert!(res.next().await.unwrap().is_none());
 }
+
+#[tokio::test]
+async fn test_execute_batch() {
+    let builder = Builder::new_local(":memory:");
+    let db = builder.build().await.unwrap();
+    let conn = db.connect().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+         -- a comment; with a semicolon
+         INSERT INTO users VALUES (1, 'a;b');
+         INSERT INTO users VALUES (2, 'c');",
+    )
+    .await
+    .unwrap();
+    let mut res = conn
+        .query("SELECT name FROM users ORDER BY id", ())
+        .await
+        .unwrap();
+    assert_eq!(
+        res.next().await.unwrap().unwrap().get_value(0).unwrap(),
+        Value::Text("a;b".to_string())
+    );
+    assert_eq!(
+        res.next().await.unwrap().unwrap().get_value(0).unwrap(),
+        Value::Text("c".to_string())
+    );
+    assert!(res.next().await.unwrap().is_none());
+
+    let err = conn
+        .execute_batch("INSERT INTO users VALUES (3, 'd'); INSERT INTO missing VALUES (1);")
+        .await;
+    assert!(err.is_err());
+}
+
+#[tokio::test]
+async fn test_rows_stream() {
+    use futures_util::TryStreamExt;
+
+    let db = Builder::new_local(":memory:").build().await.unwrap();
+    let conn = db.connect().unwrap();
+    conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1), (2), (3);")
+        .await
+        .unwrap();
+    let rows: Vec<_> = conn
+        .query("SELECT x FROM t ORDER BY x", ())
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    let values: Vec<_> = rows.iter().map(|row| row.get_value(0).unwrap()).collect();
+    assert_eq!(values, vec![1.into(), 2.into(), 3.into()]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_connection_in_spawned_task() {
+    use futures_util::TryStreamExt;
+
+    let db = Builder::new_local(":memory:").build().await.unwrap();
+    let conn = db.connect().unwrap();
+    let count = tokio::spawn(async move {
+        conn.execute("CREATE TABLE t (x INTEGER)", ())
+            .await
+            .unwrap();
+        conn.execute("INSERT INTO t VALUES (1), (2)", ())
+            .await
+            .unwrap();
+        let mut rows = conn.query("SELECT count(*) FROM t", ()).await.unwrap();
+        rows.try_next()
+            .await
+            .unwrap()
+            .unwrap()
+            .get_value(0)
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert_eq!(count, 2.into());
+}
+
+#[tokio::test]
+async fn test_statement_used_and_dropped_on_another_thread() {
+    let db = Builder::new_local(":memory:").build().await.unwrap();
+    let conn = db.connect().unwrap();
+    conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1), (2);")
+        .await
+        .unwrap();
+    let mut stmt = conn.prepare("SELECT x FROM t ORDER BY x").await.unwrap();
+    let value = std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            let mut rows = stmt.query(()).await.unwrap();
+            let row = rows.next().await.unwrap().unwrap();
+            row.get_value(0).unwrap()
+        })
+    })
+    .join()
+    .unwrap();
+    assert_eq!(value, 1.into());
+    // The statement was dropped on the other thread, and the connection keeps working.
+    let mut rows = conn.query("SELECT count(*) FROM t", ()).await.unwrap();
+    assert_eq!(
+        rows.next().await.unwrap().unwrap().get_value(0).unwrap(),
+        2.into()
+    );
+}
@@ -65,6 +65,35 @@ test.serial("Statement.raw().iterate()", async (t) => {
     t.throws(() => emptyStmt.next(), { instanceOf: TypeError });
 });
 
+test.serial("Statement.iterateChunks()", async (t) => {
+    if (t.context.provider !== "limbo-wasm") {
+        t.pass("iterateChunks() is not part of the better-sqlite3 API");
+        return;
+    }
+    const db = t.context.db;
+
+    const chunks = [];
+    for await (const chunk of db.prepare("SELECT id FROM users").iterateChunks({ rowsPerChunk: 1 })) {
+        chunks.push(chunk);
+    }
+    t.deepEqual(chunks, [[[1]], [[2]]]);
+
+    const all = [];
+    for await (const chunk of db.prepare("SELECT id FROM users").iterateChunks()) {
+        all.push(chunk);
+    }
+    t.deepEqual(all, [[[1], [2]]]);
+
+    t.throws(() => db.prepare("SELECT id FROM users").iterateChunks({ rowsPerChunk: 0 }), {
+        instanceOf: RangeError,
+    });
+
+    const iter = db.prepare("SELECT id FROM users").iterateChunks({ rowsPerChunk: 1 });
+    t.deepEqual(await iter.next(), { done: false, value: [[1]] });
+    t.deepEqual(await iter.return(), { done: true, value: undefined });
+    t.deepEqual(await iter.next(), { done: true, value: undefined });
+});
+
 const connect = async (path_opt) => {
     // delete hello.db if it exists
     if (existsSync("hello.db")) {
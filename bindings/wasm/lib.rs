@@ -29,7 +29,7 @@ impl Database {
 
     #[wasm_bindgen]
     pub fn exec(&self, _sql: &str) {
-        self.conn.execute(_sql).unwrap();
+        self.conn.execute_batch(_sql).unwrap();
     }
 
     #[wasm_bindgen]
@@ -74,6 +74,55 @@ impl RowIterator {
     }
 }
 
+/// Fetches the rows of a statement in chunks; see [Statement::iterate_chunks].
+#[wasm_bindgen]
+pub struct ChunkIterator {
+    inner: RefCell<turso_core::Statement>,
+    rows_per_chunk: u32,
+}
+
+#[wasm_bindgen]
+impl ChunkIterator {
+    /// Steps the statement until it has produced `rows_per_chunk` rows or is done. A chunk
+    /// shorter than `rows_per_chunk` means the statement is done. Throws if the statement
+    /// fails, or can't go on because the database is locked.
+    #[wasm_bindgen]
+    pub fn next_chunk(&self) -> Result<js_sys::Array, JsValue> {
+        let chunk = js_sys::Array::new();
+        let mut stmt = self.inner.borrow_mut();
+        while chunk.length() < self.rows_per_chunk {
+            match stmt.step() {
+                Ok(turso_core::StepResult::Row) => {
+                    let row = stmt.row().unwrap();
+                    let row_array = js_sys::Array::new();
+                    for value in row.get_values() {
+                        let value = to_js_value(value);
+                        row_array.push(&value);
+                    }
+                    chunk.push(&row_array);
+                }
+                Ok(turso_core::StepResult::IO) => {}
+                Ok(turso_core::StepResult::Done) => break,
+                Ok(step @ turso_core::StepResult::Interrupt)
+                | Ok(step @ turso_core::StepResult::Busy) => {
+                    return Err(js_sys::Error::new(&format!("{:?}", step)).into());
+                }
+                Err(e) => return Err(js_sys::Error::new(&e.to_string()).into()),
+            }
+        }
+        Ok(chunk)
+    }
+
+    /// Abandons the statement, releasing the locks it holds.
+    #[wasm_bindgen]
+    pub fn cancel(&self) -> Result<(), JsValue> {
+        self.inner
+            .borrow_mut()
+            .cancel()
+            .map_err(|e| js_sys::Error::new(&e.to_string()).into())
+    }
+}
+
 #[wasm_bindgen]
 pub struct Statement {
     inner: RefCell<turso_core::Statement>,
@@ -166,6 +215,103 @@ impl Statement {
 
         JsValue::from(iterator_obj)
     }
+
+    /// Returns an async iterator over arrays of at most `options.rowsPerChunk` (default 1000)
+    /// rows. Every chunk is fetched in its own macrotask, so streaming a large result doesn't
+    /// block the event loop; the statement only advances when the next chunk is requested, and
+    /// `return()`/`throw()` or a failing chunk cancel it.
+    #[wasm_bindgen(js_name = iterateChunks)]
+    pub fn iterate_chunks(self, options: Option<Object>) -> Result<JsValue, JsValue> {
+        let rows_per_chunk = match options {
+            Some(options) => js_sys::Reflect::get(&options, &JsValue::from_str("rowsPerChunk"))?,
+            None => JsValue::UNDEFINED,
+        };
+        let rows_per_chunk = if rows_per_chunk.is_undefined() {
+            1000
+        } else {
+            match rows_per_chunk.as_f64() {
+                Some(n) if n.fract() == 0.0 && (1.0..=u32::MAX as f64).contains(&n) => n as u32,
+                _ => {
+                    return Err(
+                        js_sys::RangeError::new("rowsPerChunk must be a positive integer").into(),
+                    )
+                }
+            }
+        };
+        let iterator = ChunkIterator {
+            inner: self.inner,
+            rows_per_chunk,
+        };
+        let iterator_obj = Object::new();
+
+        js_sys::Reflect::set(
+            &iterator_obj,
+            &JsValue::from_str("iterator"),
+            &JsValue::from(iterator),
+        )
+        .unwrap();
+        js_sys::Reflect::set(
+            &iterator_obj,
+            &JsValue::from_str("rowsPerChunk"),
+            &JsValue::from(rows_per_chunk),
+        )
+        .unwrap();
+        js_sys::Reflect::set(&iterator_obj, &JsValue::from_str("done"), &JsValue::FALSE).unwrap();
+        js_sys::Reflect::set(
+            &iterator_obj,
+            &JsValue::from_str("pending"),
+            &js_sys::Promise::resolve(&JsValue::UNDEFINED),
+        )
+        .unwrap();
+
+        // next() calls are chained so that a consumer that doesn't await them still gets
+        // the chunks in order.
+        let next_fn = js_sys::Function::new_with_args(
+            "",
+            "const fetch = async () => {
+                if (this.done) return { value: undefined, done: true };
+                const defer = typeof setImmediate === 'function' ? setImmediate : setTimeout;
+                await new Promise((resolve) => defer(resolve));
+                if (this.done) return { value: undefined, done: true };
+                let chunk;
+                try {
+                    chunk = this.iterator.next_chunk();
+                } catch (err) {
+                    this.done = true;
+                    this.iterator.cancel();
+                    throw err;
+                }
+                if (chunk.length < this.rowsPerChunk) this.done = true;
+                if (chunk.length === 0) return { value: undefined, done: true };
+                return { value: chunk, done: false };
+             };
+             const result = this.pending.then(fetch);
+             this.pending = result.catch(() => {});
+             return result;",
+        );
+        let finish = "if (!this.done) { this.done = true; this.iterator.cancel(); }";
+        let return_fn = js_sys::Function::new_with_args(
+            "value",
+            &format!("{finish} return Promise.resolve({{ value, done: true }});"),
+        );
+        let throw_fn = js_sys::Function::new_with_args(
+            "err",
+            &format!("{finish} return Promise.reject(err);"),
+        );
+        let symbol_async_iterator = js_sys::Function::new_no_args("return this;");
+
+        js_sys::Reflect::set(&iterator_obj, &JsValue::from_str("next"), &next_fn).unwrap();
+        js_sys::Reflect::set(&iterator_obj, &JsValue::from_str("return"), &return_fn).unwrap();
+        js_sys::Reflect::set(&iterator_obj, &JsValue::from_str("throw"), &throw_fn).unwrap();
+        js_sys::Reflect::set(
+            &iterator_obj,
+            &js_sys::Symbol::async_iterator(),
+            &symbol_async_iterator,
+        )
+        .unwrap();
+
+        Ok(JsValue::from(iterator_obj))
+    }
 }
 
 fn to_js_value(value: &turso_core::Value) -> JsValue {
@@ -255,6 +401,12 @@ impl turso_core::File for File {
     fn size(&self) -> Result<u64> {
         Ok(self.vfs.size(self.fd))
     }
+
+    fn truncate(&self, _len: u64) -> Result<()> {
+        Err(turso_core::LimboError::InternalError(
+            "truncate is not supported".to_string(),
+        ))
+    }
 }
 
 pub struct PlatformIO {
@@ -309,6 +461,11 @@ impl turso_core::IO for PlatformIO {
     fn get_memory_io(&self) -> Arc<turso_core::MemoryIO> {
         Arc::new(turso_core::MemoryIO::new())
     }
+
+    fn sleep(&self, _duration: std::time::Duration) {
+        // The browser event loop can't be blocked, so a throttled checkpoint polls the clock
+        // until it may continue instead.
+    }
 }
 
 #[wasm_bindgen]
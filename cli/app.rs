@@ -1,12 +1,12 @@
 use crate::{
     commands::{
-        args::{EchoMode, TimerMode},
+        args::{BailMode, EchoMode, HighlightMode, TimerMode},
         import::ImportFile,
         Command, CommandParser,
     },
     config::Config,
     helper::LimboHelper,
-    input::{get_io, get_writer, DbLocation, OutputMode, Settings},
+    input::{get_io, get_writer, split_sql_script, DbLocation, OutputMode, Settings},
     opcodes_dictionary::OPCODE_DESCRIPTIONS,
     HISTORY_FILE,
 };
@@ -26,7 +26,9 @@ use std::{
 };
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use turso_core::{Connection, Database, LimboError, OpenFlags, Statement, StepResult, Value};
+use turso_core::{
+    unquote_ident, Connection, Database, LimboError, OpenFlags, Statement, StepResult, Value,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "Turso")]
@@ -79,9 +81,57 @@ pub struct Limbo {
     config: Option<Config>,
 }
 
-struct QueryStatistics {
-    io_time_elapsed_samples: Vec<Duration>,
-    execute_time_elapsed_samples: Vec<Duration>,
+/// Wall-clock and CPU time at the start of a command, reported by `.timer on`.
+struct RunTime {
+    real: Instant,
+    user: Duration,
+    sys: Duration,
+}
+
+impl RunTime {
+    fn start() -> Self {
+        let (user, sys) = cpu_times();
+        Self {
+            real: Instant::now(),
+            user,
+            sys,
+        }
+    }
+}
+
+impl std::fmt::Display for RunTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (user, sys) = cpu_times();
+        write!(
+            f,
+            "Run Time: real {:.3} user {:.6} sys {:.6}",
+            self.real.elapsed().as_secs_f64(),
+            user.saturating_sub(self.user).as_secs_f64(),
+            sys.saturating_sub(self.sys).as_secs_f64(),
+        )
+    }
+}
+
+/// Returns the user and system CPU time consumed by this process so far.
+#[cfg(unix)]
+fn cpu_times() -> (Duration, Duration) {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the provided struct.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return (Duration::ZERO, Duration::ZERO);
+    }
+    // SAFETY: getrusage succeeded, so the struct is initialized.
+    let usage = unsafe { usage.assume_init() };
+    let to_duration = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    (to_duration(usage.ru_utime), to_duration(usage.ru_stime))
+}
+
+/// CPU time is not available on this platform, so only wall-clock time is reported.
+#[cfg(not(unix))]
+fn cpu_times() -> (Duration, Duration) {
+    (Duration::ZERO, Duration::ZERO)
 }
 
 macro_rules! query_internal {
@@ -222,10 +272,14 @@ impl Limbo {
     }
 
     #[cfg(not(target_family = "wasm"))]
-    fn handle_load_extension(&mut self, path: &str) -> Result<(), String> {
+    fn handle_load_extension(
+        &mut self,
+        path: &str,
+        entry_point: Option<&str>,
+    ) -> Result<(), String> {
         let ext_path = turso_core::resolve_ext_path(path).map_err(|e| e.to_string())?;
         self.conn
-            .load_extension(ext_path)
+            .load_extension(ext_path, entry_point)
             .map_err(|e| e.to_string())
     }
 
@@ -322,7 +376,6 @@ impl Limbo {
             Err(x) => Err(x),
         }?;
 
-        self.conn.close()?;
         self.writeln("COMMIT;")?;
         Ok(())
     }
@@ -357,7 +410,7 @@ impl Limbo {
     }
 
     fn open_db(&mut self, path: &str, vfs_name: Option<&str>) -> anyhow::Result<()> {
-        self.conn.close()?;
+        self.close_conn()?;
         let (io, db) = if let Some(vfs_name) = vfs_name {
             self.conn.open_new(path, vfs_name)?
         } else {
@@ -425,17 +478,36 @@ impl Limbo {
         self.input_buff.push(' ');
     }
 
-    fn run_query(&mut self, input: &str) {
+    /// Prints the bytecode of `sql`, returning false if it could not be compiled.
+    fn explain_query(&mut self, sql: &str) -> bool {
+        match self.conn.query(sql) {
+            Ok(Some(stmt)) if stmt.is_explain_query_plan() => {
+                return self.print_query_result(sql, Ok(Some(stmt))).is_ok();
+            }
+            Ok(Some(stmt)) => {
+                if let Err(e) = stmt.explain_to(&mut self.writer) {
+                    let _ = self.writeln(e.to_string());
+                    return false;
+                }
+            }
+            Err(e) => {
+                let _ = self.writeln(e.to_string());
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Runs every statement in `input`, returning false if any of them failed.
+    fn run_query(&mut self, input: &str) -> bool {
         let echo = self.opts.echo;
         if echo {
             let _ = self.writeln(input);
         }
 
-        let start = Instant::now();
-        let mut stats = QueryStatistics {
-            io_time_elapsed_samples: vec![],
-            execute_time_elapsed_samples: vec![],
-        };
+        let run_time = RunTime::start();
+        let mut succeeded = true;
         // TODO this is a quickfix. Some ideas to do case insensitive comparisons is to use
         // Uncased or Unicase.
         let explain_str = "explain";
@@ -445,70 +517,46 @@ impl Limbo {
             .map(|s| s.eq_ignore_ascii_case(explain_str))
             .unwrap_or(false)
         {
-            match self.conn.query(input) {
-                Ok(Some(stmt)) => {
-                    let _ = self.writeln(stmt.explain().as_bytes());
-                }
-                Err(e) => {
-                    let _ = self.writeln(e.to_string());
-                }
-                _ => {}
-            }
+            succeeded = self.explain_query(input);
         } else {
             let conn = self.conn.clone();
             let runner = conn.query_runner(input.as_bytes());
+            // Like the sqlite3 shell, a failing statement doesn't keep the ones after it on
+            // the same line from running.
             for output in runner {
-                if self
-                    .print_query_result(input, output, Some(&mut stats))
-                    .is_err()
-                {
-                    break;
+                if self.print_query_result(input, output).is_err() {
+                    succeeded = false;
                 }
             }
         }
-        self.print_query_performance_stats(start, stats);
+        if self.opts.timer {
+            let _ = self.writeln(run_time.to_string());
+        }
         self.reset_input();
+        succeeded
     }
 
-    fn print_query_performance_stats(&mut self, start: Instant, stats: QueryStatistics) {
-        let elapsed_as_str = |duration: Duration| {
-            if duration.as_secs() >= 1 {
-                format!("{} s", duration.as_secs_f64())
-            } else if duration.as_millis() >= 1 {
-                format!("{} ms", duration.as_millis() as f64)
-            } else if duration.as_micros() >= 1 {
-                format!("{} us", duration.as_micros() as f64)
-            } else {
-                format!("{} ns", duration.as_nanos())
+    /// Executes the dot commands and SQL statements of a script file, stopping at the
+    /// first failing statement when `.bail` is on.
+    fn read_file(&mut self, path: &str) -> anyhow::Result<()> {
+        let script = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot open \"{}\": {}", path, e))?;
+        let mut statements = 0;
+        for input in split_sql_script(&script) {
+            if let Some(command) = input.strip_prefix('.') {
+                self.handle_dot_command(command);
+                continue;
             }
-        };
-        let sample_stats_as_str = |name: &str, samples: Vec<Duration>| {
-            if samples.is_empty() {
-                return format!("{}: No samples available", name);
+            statements += 1;
+            if !self.run_query(input) && self.opts.bail {
+                anyhow::bail!(
+                    "{}: statement {} failed, stopping (use .bail off to continue past errors)",
+                    path,
+                    statements
+                );
             }
-            let avg_time_spent = samples.iter().sum::<Duration>() / samples.len() as u32;
-            let total_time = samples.iter().fold(Duration::ZERO, |acc, x| acc + *x);
-            format!(
-                "{}: avg={}, total={}",
-                name,
-                elapsed_as_str(avg_time_spent),
-                elapsed_as_str(total_time),
-            )
-        };
-        if self.opts.timer {
-            let _ = self.writeln("Command stats:\n----------------------------");
-            let _ = self.writeln(format!(
-                "total: {} (this includes parsing/coloring of cli app)\n",
-                elapsed_as_str(start.elapsed())
-            ));
-
-            let _ = self.writeln("query execution stats:\n----------------------------");
-            let _ = self.writeln(sample_stats_as_str(
-                "Execution",
-                stats.execute_time_elapsed_samples,
-            ));
-            let _ = self.writeln(sample_stats_as_str("I/O", stats.io_time_elapsed_samples));
         }
+        Ok(())
     }
 
     fn reset_line(&mut self, _line: &str) -> rustyline::Result<()> {
@@ -541,9 +589,8 @@ impl Limbo {
                         let conn = self.conn.clone();
                         let runner = conn.query_runner(after_comment.as_bytes());
                         for output in runner {
-                            if let Err(e) = self.print_query_result(after_comment, output, None) {
-                                let _ = self.writeln(e.to_string());
-                            }
+                            // Errors are reported by print_query_result itself.
+                            let _ = self.print_query_result(after_comment, output);
                         }
                         self.reset_input();
                         return self.handle_input_line(after_comment);
@@ -650,7 +697,9 @@ impl Limbo {
                 }
                 Command::LoadExtension(args) => {
                     #[cfg(not(target_family = "wasm"))]
-                    if let Err(e) = self.handle_load_extension(&args.path) {
+                    if let Err(e) =
+                        self.handle_load_extension(&args.path, args.entry_point.as_deref())
+                    {
                         let _ = self.writeln(&e);
                     }
                 }
@@ -676,6 +725,23 @@ impl Limbo {
                         TimerMode::Off => false,
                     };
                 }
+                Command::Highlight(args) => {
+                    let enable = matches!(args.mode, HighlightMode::On);
+                    if let Some(helper) = self.rl.as_mut().and_then(|rl| rl.helper_mut()) {
+                        helper.set_highlight(enable);
+                    }
+                }
+                Command::Explain(args) => {
+                    self.explain_query(&args.sql.join(" "));
+                }
+                Command::Read(args) => {
+                    if let Err(e) = self.read_file(&args.path) {
+                        let _ = self.writeln(format!("Error: {}", e));
+                    }
+                }
+                Command::Bail(args) => {
+                    self.opts.bail = matches!(args.mode, BailMode::On);
+                }
             },
         }
     }
@@ -684,9 +750,11 @@ impl Limbo {
         &mut self,
         sql: &str,
         mut output: Result<Option<Statement>, LimboError>,
-        mut statistics: Option<&mut QueryStatistics>,
     ) -> anyhow::Result<()> {
         match output {
+            Ok(Some(ref mut rows)) if rows.is_explain_query_plan() => {
+                return self.print_query_plan(rows);
+            }
             Ok(Some(ref mut rows)) => match self.opts.output_mode {
                 OutputMode::List => loop {
                     if self.interrupt_count.load(Ordering::SeqCst) > 0 {
@@ -694,13 +762,8 @@ impl Limbo {
                         return Ok(());
                     }
 
-                    let start = Instant::now();
-
                     match rows.step() {
                         Ok(StepResult::Row) => {
-                            if let Some(ref mut stats) = statistics {
-                                stats.execute_time_elapsed_samples.push(start.elapsed());
-                            }
                             let row = rows.row().unwrap();
                             for (i, value) in row.get_values().enumerate() {
                                 if i > 0 {
@@ -715,32 +778,19 @@ impl Limbo {
                             let _ = self.writeln("");
                         }
                         Ok(StepResult::IO) => {
-                            let start = Instant::now();
                             self.io.run_once()?;
-                            if let Some(ref mut stats) = statistics {
-                                stats.io_time_elapsed_samples.push(start.elapsed());
-                            }
                         }
                         Ok(StepResult::Interrupt) => break,
                         Ok(StepResult::Done) => {
-                            if let Some(ref mut stats) = statistics {
-                                stats.execute_time_elapsed_samples.push(start.elapsed());
-                            }
                             break;
                         }
                         Ok(StepResult::Busy) => {
-                            if let Some(ref mut stats) = statistics {
-                                stats.execute_time_elapsed_samples.push(start.elapsed());
-                            }
                             let _ = self.writeln("database is busy");
                             break;
                         }
                         Err(err) => {
-                            if let Some(ref mut stats) = statistics {
-                                stats.execute_time_elapsed_samples.push(start.elapsed());
-                            }
                             let _ = self.writeln(err.to_string());
-                            break;
+                            anyhow::bail!("query execution failed");
                         }
                     }
                 },
@@ -767,12 +817,8 @@ impl Limbo {
                         table.set_header(header);
                     }
                     loop {
-                        let start = Instant::now();
                         match rows.step() {
                             Ok(StepResult::Row) => {
-                                if let Some(ref mut stats) = statistics {
-                                    stats.execute_time_elapsed_samples.push(start.elapsed());
-                                }
                                 let record = rows.row().unwrap();
                                 let mut row = Row::new();
                                 row.max_height(1);
@@ -805,39 +851,23 @@ impl Limbo {
                                 table.add_row(row);
                             }
                             Ok(StepResult::IO) => {
-                                let start = Instant::now();
                                 self.io.run_once()?;
-                                if let Some(ref mut stats) = statistics {
-                                    stats.io_time_elapsed_samples.push(start.elapsed());
-                                }
                             }
                             Ok(StepResult::Interrupt) => {
-                                if let Some(ref mut stats) = statistics {
-                                    stats.execute_time_elapsed_samples.push(start.elapsed());
-                                }
                                 break;
                             }
                             Ok(StepResult::Done) => {
-                                if let Some(ref mut stats) = statistics {
-                                    stats.execute_time_elapsed_samples.push(start.elapsed());
-                                }
                                 break;
                             }
                             Ok(StepResult::Busy) => {
-                                if let Some(ref mut stats) = statistics {
-                                    stats.execute_time_elapsed_samples.push(start.elapsed());
-                                }
                                 let _ = self.writeln("database is busy");
                                 break;
                             }
                             Err(err) => {
-                                if let Some(ref mut stats) = statistics {
-                                    stats.execute_time_elapsed_samples.push(start.elapsed());
-                                }
                                 let report =
                                     miette::Error::from(err).with_source_code(sql.to_owned());
                                 let _ = self.write_fmt(format_args!("{:?}", report));
-                                break;
+                                anyhow::bail!("query execution failed");
                             }
                         }
                     }
@@ -857,6 +887,48 @@ impl Limbo {
         Ok(())
     }
 
+    /// Prints the rows of an EXPLAIN QUERY PLAN as a tree, like the SQLite shell.
+    fn print_query_plan(&mut self, rows: &mut Statement) -> anyhow::Result<()> {
+        let mut nodes = Vec::new();
+        loop {
+            match rows.step()? {
+                StepResult::Row => {
+                    let row = rows.row().unwrap();
+                    nodes.push((
+                        row.get::<i64>(0)?,
+                        row.get::<i64>(1)?,
+                        row.get::<String>(3)?,
+                    ));
+                }
+                StepResult::IO => self.io.run_once()?,
+                StepResult::Done | StepResult::Interrupt => break,
+                StepResult::Busy => {
+                    let _ = self.writeln("database is busy");
+                    return Ok(());
+                }
+            }
+        }
+        let _ = self.writeln("QUERY PLAN");
+        self.print_query_plan_children(&nodes, 0, "")
+    }
+
+    fn print_query_plan_children(
+        &mut self,
+        nodes: &[(i64, i64, String)],
+        parent: i64,
+        prefix: &str,
+    ) -> anyhow::Result<()> {
+        let children: Vec<_> = nodes.iter().filter(|(_, p, _)| *p == parent).collect();
+        for (i, (id, _, detail)) in children.iter().enumerate() {
+            let is_last = i == children.len() - 1;
+            let branch = if is_last { "`--" } else { "|--" };
+            self.writeln(format!("{}{}{}", prefix, branch, detail))?;
+            let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "|  " });
+            self.print_query_plan_children(nodes, *id, &child_prefix)?;
+        }
+        Ok(())
+    }
+
     pub fn init_tracing(&mut self) -> Result<WorkerGuard, std::io::Error> {
         let ((non_blocking, guard), should_emit_ansi) =
             if let Some(file) = &self.opts.tracing_output {
@@ -892,8 +964,8 @@ impl Limbo {
     fn display_schema(&mut self, table: Option<&str>) -> anyhow::Result<()> {
         let sql = match table {
         Some(table_name) => format!(
-            "SELECT sql FROM sqlite_schema WHERE type IN ('table', 'index') AND tbl_name = '{}' AND name NOT LIKE 'sqlite_%'",
-            table_name
+            "SELECT sql FROM sqlite_schema WHERE type IN ('table', 'index') AND tbl_name = '{}' COLLATE NOCASE AND name NOT LIKE 'sqlite_%'",
+            unquote_ident(table_name).replace('\'', "''")
         ),
         None => String::from(
             "SELECT sql FROM sqlite_schema WHERE type IN ('table', 'index') AND name NOT LIKE 'sqlite_%'"
@@ -950,8 +1022,8 @@ impl Limbo {
     fn display_indexes(&mut self, maybe_table: Option<String>) -> anyhow::Result<()> {
         let sql = match maybe_table {
             Some(ref tbl_name) => format!(
-                "SELECT name FROM sqlite_schema WHERE type='index' AND tbl_name = '{}' ORDER BY 1",
-                tbl_name
+                "SELECT name FROM sqlite_schema WHERE type='index' AND tbl_name = '{}' COLLATE NOCASE ORDER BY 1",
+                unquote_ident(tbl_name).replace('\'', "''")
             ),
             None => String::from("SELECT name FROM sqlite_schema WHERE type='index' ORDER BY 1"),
         };
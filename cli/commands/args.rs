@@ -111,6 +111,8 @@ pub struct LoadExtensionArgs {
     /// Path to extension file
     #[arg(add = ArgValueCompleter::new(PathCompleter::file()))]
     pub path: String,
+    /// Name of the function that registers the extension, `register_extension` by default
+    pub entry_point: Option<String>,
 }
 
 #[derive(Debug, ValueEnum, Clone)]
@@ -124,3 +126,41 @@ pub struct TimerArgs {
     #[arg(value_enum)]
     pub mode: TimerMode,
 }
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum HighlightMode {
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct HighlightArgs {
+    #[arg(value_enum)]
+    pub mode: HighlightMode,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ReadArgs {
+    /// Path to the SQL script
+    #[arg(add = ArgValueCompleter::new(PathCompleter::file()))]
+    pub path: String,
+}
+
+#[derive(Debug, ValueEnum, Clone)]
+pub enum BailMode {
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BailArgs {
+    #[arg(value_enum)]
+    pub mode: BailMode,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ExplainArgs {
+    /// SQL statement to explain
+    #[arg(required = true, num_args = 1.., trailing_var_arg = true, allow_hyphen_values = true)]
+    pub sql: Vec<String>,
+}
@@ -2,8 +2,9 @@ pub mod args;
 pub mod import;
 
 use args::{
-    CwdArgs, EchoArgs, ExitArgs, IndexesArgs, LoadExtensionArgs, NullValueArgs, OpcodesArgs,
-    OpenArgs, OutputModeArgs, SchemaArgs, SetOutputArgs, TablesArgs, TimerArgs,
+    BailArgs, CwdArgs, EchoArgs, ExitArgs, ExplainArgs, HighlightArgs, IndexesArgs,
+    LoadExtensionArgs, NullValueArgs, OpcodesArgs, OpenArgs, OutputModeArgs, ReadArgs, SchemaArgs,
+    SetOutputArgs, TablesArgs, TimerArgs,
 };
 use clap::Parser;
 use import::ImportArgs;
@@ -75,8 +76,21 @@ pub enum Command {
     /// Show names of indexes
     #[command(name = "indexes", display_name = ".indexes")]
     ListIndexes(IndexesArgs),
+    /// Turn the display of run times for each query on or off
     #[command(name = "timer", display_name = ".timer")]
     Timer(TimerArgs),
+    /// Toggle syntax highlighting of the input line
+    #[command(name = "highlight", display_name = ".highlight")]
+    Highlight(HighlightArgs),
+    /// Show the bytecode of an SQL statement
+    #[command(name = "explain", display_name = ".explain")]
+    Explain(ExplainArgs),
+    /// Execute SQL statements and commands from FILE
+    #[command(name = "read", display_name = ".read")]
+    Read(ReadArgs),
+    /// Stop running a script after the first error
+    #[command(name = "bail", display_name = ".bail")]
+    Bail(BailArgs),
 }
 
 const _HELP_TEMPLATE: &str = "{before-help}{name}
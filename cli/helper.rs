@@ -35,6 +35,7 @@ pub struct LimboHelper {
     syntax_set: SyntaxSet,
     theme_set: ThemeSet,
     syntax_config: HighlightConfig,
+    no_color: bool,
     #[rustyline(Hinter)]
     hinter: HistoryHinter,
 }
@@ -58,20 +59,34 @@ impl LimboHelper {
                 tracing::error!("{err}");
             }
         }
+        // https://no-color.org: any non-empty value disables colored output
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let mut syntax_config = syntax_config.unwrap_or_default();
+        if no_color {
+            syntax_config.enable = false;
+        }
         LimboHelper {
             completer: SqlCompleter::new(conn, io),
             syntax_set: ps,
             theme_set: ts,
-            syntax_config: syntax_config.unwrap_or_default(),
+            syntax_config,
+            no_color,
             hinter: HistoryHinter::new(),
         }
     }
+
+    /// Enables or disables syntax highlighting. Has no effect when `NO_COLOR` is set.
+    pub fn set_highlight(&mut self, enable: bool) {
+        self.syntax_config.enable = enable && !self.no_color;
+    }
 }
 
 impl Highlighter for LimboHelper {
     fn highlight<'l>(&self, line: &'l str, pos: usize) -> std::borrow::Cow<'l, str> {
         let _ = pos;
-        if self.syntax_config.enable {
+        if self.no_color {
+            std::borrow::Cow::Borrowed(line)
+        } else if self.syntax_config.enable {
             // TODO use lifetimes to store highlight lines
             let syntax = self
                 .syntax_set
@@ -110,6 +125,9 @@ impl Highlighter for LimboHelper {
         default: bool,
     ) -> std::borrow::Cow<'b, str> {
         let _ = default;
+        if self.no_color {
+            return std::borrow::Cow::Borrowed(prompt);
+        }
         // Dark emerald green for prompt
         let style = Style::new().bold().fg(self.syntax_config.prompt.0);
         let styled_str = style.paint(prompt);
@@ -117,6 +135,9 @@ impl Highlighter for LimboHelper {
     }
 
     fn highlight_hint<'h>(&self, hint: &'h str) -> std::borrow::Cow<'h, str> {
+        if self.no_color {
+            return std::borrow::Cow::Borrowed(hint);
+        }
         let style = Style::new().bold().fg(self.syntax_config.hint.0); // Brighter dark grey for hints
         let styled_str = style.paint(hint);
         std::borrow::Cow::Owned(styled_str.to_string())
@@ -128,6 +149,9 @@ impl Highlighter for LimboHelper {
         completion: rustyline::CompletionType,
     ) -> std::borrow::Cow<'c, str> {
         let _ = completion;
+        if self.no_color {
+            return std::borrow::Cow::Borrowed(candidate);
+        }
         let style = Style::new().fg(self.syntax_config.candidate.0);
         let styled_str = style.paint(candidate);
         std::borrow::Cow::Owned(styled_str.to_string())
@@ -83,6 +83,7 @@ pub struct Settings {
     pub io: Io,
     pub tracing_output: Option<String>,
     pub timer: bool,
+    pub bail: bool,
 }
 
 impl From<Opts> for Settings {
@@ -107,6 +108,7 @@ impl From<Opts> for Settings {
             },
             tracing_output: opts.tracing_output,
             timer: false,
+            bail: true,
         }
     }
 }
@@ -115,7 +117,7 @@ impl std::fmt::Display for Settings {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Settings:\nOutput mode: {}\nDB: {}\nOutput: {}\nNull value: {}\nCWD: {}\nEcho: {}",
+            "Settings:\nOutput mode: {}\nDB: {}\nOutput: {}\nNull value: {}\nCWD: {}\nEcho: {}\nBail: {}",
             self.output_mode,
             self.db_file,
             match self.is_stdout {
@@ -127,6 +129,10 @@ impl std::fmt::Display for Settings {
             match self.echo {
                 true => "on",
                 false => "off",
+            },
+            match self.bail {
+                true => "on",
+                false => "off",
             }
         )
     }
@@ -172,6 +178,58 @@ pub fn get_io(db_location: DbLocation, io_choice: &str) -> anyhow::Result<Arc<dy
     })
 }
 
+/// Splits a script into dot commands and complete SQL statements, in order.
+///
+/// A statement ends at a `;` outside of string literals, quoted identifiers and
+/// comments; a dot command at the start of a statement takes the rest of its line.
+/// Text after the last `;` is returned as a final statement.
+pub fn split_sql_script(script: &str) -> Vec<&str> {
+    let bytes = script.as_bytes();
+    let skip_until = |from: usize, end: &[u8]| {
+        bytes[from..]
+            .windows(end.len())
+            .position(|w| w == end)
+            .map_or(bytes.len(), |pos| from + pos + end.len())
+    };
+    let mut statements = Vec::new();
+    let mut i = 0;
+    loop {
+        // Skip whitespace and comments between statements.
+        while i < bytes.len() {
+            match bytes[i] {
+                b if b.is_ascii_whitespace() => i += 1,
+                b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_until(i, b"\n"),
+                b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_until(i + 2, b"*/"),
+                _ => break,
+            }
+        }
+        if i >= bytes.len() {
+            break;
+        }
+        let start = i;
+        if bytes[i] == b'.' {
+            i = skip_until(i, b"\n");
+            statements.push(script[start..i].trim_end());
+            continue;
+        }
+        while i < bytes.len() {
+            match bytes[i] {
+                quote @ (b'\'' | b'"' | b'`') => i = skip_until(i + 1, &[quote]),
+                b'[' => i = skip_until(i + 1, b"]"),
+                b'-' if bytes.get(i + 1) == Some(&b'-') => i = skip_until(i, b"\n"),
+                b'/' if bytes.get(i + 1) == Some(&b'*') => i = skip_until(i + 2, b"*/"),
+                b';' => {
+                    i += 1;
+                    break;
+                }
+                _ => i += 1,
+            }
+        }
+        statements.push(script[start..i].trim_end());
+    }
+    statements
+}
+
 pub const BEFORE_HELP_MSG: &str = r#"
 
 Limbo SQL Shell Help
@@ -221,6 +279,44 @@ pub const AFTER_HELP_MSG: &str = r#"Usage Examples:
 14. To show names of indexes:
    .indexes ?TABLE?
 
+15. To turn syntax highlighting of the input line off:
+   .highlight off
+
+16. To show the bytecode of a query without running it:
+   .explain SELECT * FROM users;
+
+17. To show the wall-clock and CPU time taken by each query:
+   .timer on
+
+18. To run the SQL statements in 'migration.sql', continuing past errors:
+   .bail off
+   .read migration.sql
+
 Note:
 - All SQL commands must end with a semicolon (;).
 - Special commands start with a dot (.) and are not required to end with a semicolon."#;
+
+#[cfg(test)]
+mod tests {
+    use super::split_sql_script;
+
+    #[test]
+    fn test_split_sql_script() {
+        let script = "-- create the table\nCREATE TABLE t (x);\n.tables\nINSERT INTO t VALUES ('a;b'), (\"c;d\");\n/* ; */ SELECT [x;] FROM t -- trailing ;\n;\nSELECT 1";
+        assert_eq!(
+            split_sql_script(script),
+            vec![
+                "CREATE TABLE t (x);",
+                ".tables",
+                "INSERT INTO t VALUES ('a;b'), (\"c;d\");",
+                "SELECT [x;] FROM t -- trailing ;\n;",
+                "SELECT 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_sql_script_only_comments() {
+        assert!(split_sql_script("  -- nothing here\n/* or here */\n").is_empty());
+    }
+}
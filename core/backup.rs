@@ -0,0 +1,300 @@
+//! Online backup of a database into another one, page by page, mirroring SQLite's
+//! `sqlite3_backup_*` API.
+//!
+//! Every [BackupHandle::step] copies a batch of pages from a read snapshot of the source into
+//! a write transaction on the destination, so both databases stay usable between steps. Pages
+//! are read through the source pager, which looks them up in the WAL first (see
+//! [crate::storage::wal::Wal::find_frame]), so changes that were not checkpointed yet are
+//! copied too. Page 1 goes last: until the final step commits, the destination still carries
+//! its old header and schema cookie. If the source changes between two steps, the backup starts
+//! over from the beginning. Like [crate::Statement::step], a step returns
+//! [BackupStatus::IO] instead of blocking when it has to wait for a page to be read or
+//! written.
+
+use std::sync::Arc;
+
+use crate::io::IO;
+use crate::result::LimboResult;
+use crate::schema::Schema;
+use crate::storage::header_accessor;
+use crate::storage::pager::{allocate_page, Pager};
+use crate::storage::sqlite3_ondisk::{DATABASE_HEADER_PAGE_ID, DATABASE_HEADER_SIZE};
+use crate::types::CursorResult;
+use crate::util::parse_schema_rows;
+use crate::{Connection, LimboError, PagerCacheflushStatus, Result};
+
+/// Progress of a backup after a call to [BackupHandle::step].
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupStatus {
+    /// More pages remain to be copied; holds how many.
+    OK(usize),
+    /// Every page was copied and the destination now holds a copy of the source.
+    Done,
+    /// The step is waiting for IO. Call [BackupHandle::run_once] and then [BackupHandle::step]
+    /// again to carry on with it.
+    IO,
+}
+
+/// Where a step stands, so that it can be resumed after returning [BackupStatus::IO].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepState {
+    /// No transaction is open.
+    Start,
+    /// The source read transaction is open, the destination write transaction is not yet.
+    SourceOpen { n_pages: usize },
+    /// The destination read transaction is open too.
+    DestReadOpen { n_pages: usize },
+    /// Both transactions are open, `n_pages` pages remain to be copied in this step.
+    Copying { n_pages: usize },
+    /// The destination transaction is being committed.
+    Committing { finished: bool },
+}
+
+/// An online backup in progress, created by [crate::Database::backup_to].
+pub struct BackupHandle {
+    source: Arc<Connection>,
+    dest: Arc<Connection>,
+    /// WAL frame count and database size of the source when the backup (re)started.
+    snapshot: Option<(u64, u32)>,
+    /// Next page to copy. Pages are copied from 2 up to the end, then page 1.
+    next_page: usize,
+    state: StepState,
+    /// The IO the step waits for when it returned [BackupStatus::IO].
+    pending_io: Option<Arc<dyn IO>>,
+    done: bool,
+}
+
+impl BackupHandle {
+    pub(crate) fn new(source: Arc<Connection>, dest: Arc<Connection>) -> Self {
+        Self {
+            source,
+            dest,
+            snapshot: None,
+            next_page: DATABASE_HEADER_PAGE_ID + 1,
+            state: StepState::Start,
+            pending_io: None,
+            done: false,
+        }
+    }
+
+    /// Copies up to `n_pages` pages from the source into the destination and commits them.
+    ///
+    /// Returns [BackupStatus::IO] when it has to wait for IO, like [crate::Statement::step].
+    /// Fails with [LimboError::Busy] if the source cannot be read or the destination cannot
+    /// be written right now; the step can simply be retried later.
+    pub fn step(&mut self, n_pages: usize) -> Result<BackupStatus> {
+        if self.done {
+            return Ok(BackupStatus::Done);
+        }
+        self.pending_io = None;
+        let result = self.step_inner(n_pages);
+        match &result {
+            Ok(BackupStatus::IO) => {}
+            Ok(_) => self.state = StepState::Start,
+            Err(_) => self.abort(),
+        }
+        result
+    }
+
+    /// Runs the IO the last step is waiting for.
+    pub fn run_once(&self) -> Result<()> {
+        match &self.pending_io {
+            Some(io) => io.run_once(),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of pages the source had when the backup last (re)started, or 0 before the first step.
+    pub fn page_count(&self) -> usize {
+        self.snapshot
+            .map_or(0, |(_, page_count)| page_count as usize)
+    }
+
+    fn step_inner(&mut self, n_pages: usize) -> Result<BackupStatus> {
+        let source = self.source.pager.clone();
+        let dest = self.dest.pager.clone();
+        loop {
+            match self.state {
+                StepState::Start => {
+                    match source.begin_read_tx()? {
+                        CursorResult::IO => return Ok(self.wait_for(&source)),
+                        CursorResult::Ok(LimboResult::Busy) => return Err(LimboError::Busy),
+                        CursorResult::Ok(_) => {}
+                    }
+                    self.state = StepState::SourceOpen { n_pages: 0 };
+                    let remaining = self.check_snapshot(&source)?;
+                    if n_pages == 0 {
+                        source.end_read_tx()?;
+                        return Ok(BackupStatus::OK(remaining));
+                    }
+                    self.state = StepState::SourceOpen {
+                        n_pages: n_pages.min(remaining),
+                    };
+                }
+                StepState::SourceOpen { n_pages } => {
+                    match dest.begin_read_tx()? {
+                        CursorResult::IO => return Ok(self.wait_for(&dest)),
+                        CursorResult::Ok(LimboResult::Busy) => return Err(LimboError::Busy),
+                        CursorResult::Ok(_) => {}
+                    }
+                    self.state = StepState::DestReadOpen { n_pages };
+                }
+                StepState::DestReadOpen { n_pages } => {
+                    match dest.begin_write_tx(false)? {
+                        CursorResult::IO => return Ok(self.wait_for(&dest)),
+                        CursorResult::Ok(LimboResult::Busy) => return Err(LimboError::Busy),
+                        CursorResult::Ok(_) => {}
+                    }
+                    self.state = StepState::Copying { n_pages };
+                }
+                StepState::Copying { n_pages } => {
+                    let finished = match self.copy_pages(&source, &dest, n_pages)? {
+                        CursorResult::IO => return Ok(self.wait_for(&source)),
+                        CursorResult::Ok(finished) => finished,
+                    };
+                    self.state = StepState::Committing { finished };
+                }
+                StepState::Committing { finished } => {
+                    match dest.end_tx(
+                        false,
+                        false,
+                        &self.dest,
+                        self.dest.wal_checkpoint_disabled.get(),
+                    )? {
+                        PagerCacheflushStatus::IO => return Ok(self.wait_for(&dest)),
+                        PagerCacheflushStatus::Done(_) => {}
+                    }
+                    self.state = StepState::Start;
+                    source.end_read_tx()?;
+                    if finished {
+                        self.done = true;
+                        reload_schema(&self.dest)?;
+                        return Ok(BackupStatus::Done);
+                    }
+                    let page_count = self.page_count();
+                    return Ok(BackupStatus::OK(page_count + 2 - self.next_page));
+                }
+            }
+        }
+    }
+
+    fn wait_for(&mut self, pager: &Pager) -> BackupStatus {
+        self.pending_io = Some(pager.io.clone());
+        BackupStatus::IO
+    }
+
+    /// Ends the transactions the failed step left open.
+    fn abort(&mut self) {
+        let state = std::mem::replace(&mut self.state, StepState::Start);
+        let dest = &self.dest.pager;
+        match state {
+            StepState::Start => return,
+            StepState::SourceOpen { .. } => {}
+            StepState::DestReadOpen { .. } => {
+                let _ = dest.end_read_tx();
+            }
+            StepState::Copying { .. } | StepState::Committing { .. } => {
+                let _ = dest.rollback(false, &self.dest);
+                let _ = dest.end_tx(true, false, &self.dest, false);
+            }
+        }
+        let _ = self.source.pager.end_read_tx();
+    }
+
+    /// Starts the backup over if this is the first step or the source was written to since
+    /// the previous one. Returns the number of pages left to copy.
+    fn check_snapshot(&mut self, source: &Pager) -> Result<usize> {
+        let snapshot = (
+            source.wal_frame_count()?,
+            header_accessor::get_database_size(source)?,
+        );
+        if self.snapshot != Some(snapshot) {
+            let source_page_size = header_accessor::get_page_size(source)?;
+            let dest_page_size = self.dest.pager.buffer_pool.get_page_size();
+            if source_page_size as usize != dest_page_size {
+                return Err(LimboError::InternalError(format!(
+                    "backup requires matching page sizes, source has {} and destination has {}",
+                    source_page_size, dest_page_size
+                )));
+            }
+            self.snapshot = Some(snapshot);
+            self.next_page = DATABASE_HEADER_PAGE_ID + 1;
+        }
+        Ok(snapshot.1 as usize + 2 - self.next_page)
+    }
+
+    /// Copies `n_pages` pages into the open write transaction of `dest`. Returns true if
+    /// page 1 was copied, which completes the backup.
+    fn copy_pages(
+        &mut self,
+        source: &Pager,
+        dest: &Pager,
+        n_pages: usize,
+    ) -> Result<CursorResult<bool>> {
+        let page_count = self.page_count();
+        for left in (1..=n_pages).rev() {
+            if self.next_page <= page_count {
+                if let CursorResult::IO = copy_page(source, dest, self.next_page)? {
+                    self.state = StepState::Copying { n_pages: left };
+                    return Ok(CursorResult::IO);
+                }
+                self.next_page += 1;
+                continue;
+            }
+            // Like SQLite, bump the schema cookie of the destination instead of taking the
+            // source's, so that its other connections notice that the schema changed.
+            let schema_cookie = header_accessor::get_schema_cookie(dest)?;
+            if let CursorResult::IO = copy_page(source, dest, DATABASE_HEADER_PAGE_ID)? {
+                self.state = StepState::Copying { n_pages: left };
+                return Ok(CursorResult::IO);
+            }
+            header_accessor::set_schema_cookie(dest, schema_cookie + 1)?;
+            self.next_page += 1;
+            return Ok(CursorResult::Ok(true));
+        }
+        Ok(CursorResult::Ok(false))
+    }
+}
+
+/// Overwrites page `page_idx` of `dest` with the contents of the same page in `source`, once
+/// that page is read.
+fn copy_page(source: &Pager, dest: &Pager, page_idx: usize) -> Result<CursorResult<()>> {
+    let page = source.read_page(page_idx)?;
+    if !page.is_loaded() || page.is_locked() {
+        return Ok(CursorResult::IO);
+    }
+    let offset = if page_idx == DATABASE_HEADER_PAGE_ID {
+        DATABASE_HEADER_SIZE
+    } else {
+        0
+    };
+    let copy = allocate_page(page_idx, &dest.buffer_pool, offset);
+    copy.get_contents()
+        .as_ptr()
+        .copy_from_slice(page.get_contents().as_ptr());
+    copy.set_dirty();
+    dest.add_dirty(page_idx);
+    dest.update_dirty_loaded_page_in_cache(page_idx, copy)?;
+    Ok(CursorResult::Ok(()))
+}
+
+/// Replaces the schema of `conn` and of its database with the one stored in the database.
+fn reload_schema(conn: &Arc<Connection>) -> Result<()> {
+    let mut schema = Schema::new(conn.schema.borrow().indexes_enabled());
+    schema.schema_version = header_accessor::get_schema_cookie(&conn.pager)?;
+    let rows = conn.query("SELECT * FROM sqlite_schema")?;
+    {
+        let syms = conn.syms.borrow();
+        if let Err(LimboError::ExtensionError(e)) =
+            parse_schema_rows(rows, &mut schema, conn.pager.io.clone(), &syms, None)
+        {
+            // this means that a vtab exists and we no longer have the module loaded. we print
+            // a warning to the user to load the module
+            eprintln!("Warning: {}", e);
+        }
+    }
+    conn.schema.replace(schema.clone());
+    *conn._db.schema.write() = schema;
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use pprof::criterion::{Output, PProfProfiler};
+use std::num::NonZeroUsize;
 use std::sync::Arc;
-use turso_core::{Database, PlatformIO, IO};
+use turso_core::{Database, PlatformIO, Value, IO};
 
 fn rusqlite_open() -> rusqlite::Connection {
     let sqlite_conn = rusqlite::Connection::open("../testing/testing.db").unwrap();
@@ -58,6 +59,38 @@ fn bench_prepare_query(criterion: &mut Criterion) {
     }
 }
 
+fn bench_prepare_distinct_literals(criterion: &mut Criterion) {
+    const QUERIES: i64 = 100_000;
+    let mut group = criterion.benchmark_group("Prepare 100k point queries with distinct literals");
+    group.sample_size(10);
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    let io = Arc::new(PlatformIO::new().unwrap());
+    let db = Database::open_file(io.clone(), "../testing/testing.db", false, false).unwrap();
+    let limbo_conn = db.connect().unwrap();
+
+    for shape_cache in [false, true] {
+        group.bench_with_input(
+            BenchmarkId::new("limbo_query_shape_cache", shape_cache),
+            &shape_cache,
+            |b, &shape_cache| {
+                limbo_conn.set_query_shape_cache(shape_cache);
+                b.iter(|| {
+                    for i in 0..QUERIES {
+                        black_box(
+                            limbo_conn
+                                .prepare(format!("SELECT * FROM users WHERE id = {i}"))
+                                .unwrap(),
+                        );
+                    }
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
 fn bench_execute_select_rows(criterion: &mut Criterion) {
     // https://github.com/tursodatabase/turso/issues/174
     // The rusqlite benchmark crashes on Mac M1 when using the flamegraph features
@@ -233,9 +266,165 @@ fn bench_execute_select_count(criterion: &mut Criterion) {
     group.finish();
 }
 
+fn bench_insert_page_checksums(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("Execute `INSERT INTO t VALUES (...)`");
+
+    for checksums in [false, true] {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checksums.db");
+        #[allow(clippy::arc_with_non_send_sync)]
+        let io = Arc::new(PlatformIO::new().unwrap());
+        let db = Database::open_file(io.clone(), path.to_str().unwrap(), false, false).unwrap();
+        let limbo_conn = db.connect().unwrap();
+        if checksums {
+            // Must be enabled before the first write so that the database reserves checksum space.
+            limbo_conn
+                .execute("PRAGMA checksum_verification = ON")
+                .unwrap();
+        }
+        limbo_conn
+            .execute("CREATE TABLE t (x INTEGER, y TEXT)")
+            .unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::new("limbo_insert_page_checksums", checksums),
+            &checksums,
+            |b, _| {
+                b.iter(|| {
+                    limbo_conn
+                        .execute("INSERT INTO t VALUES (1, 'the quick brown fox')")
+                        .unwrap();
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_insert_batch(criterion: &mut Criterion) {
+    const ROWS: i64 = 1_000_000;
+    let mut group = criterion.benchmark_group("Insert 1M rows");
+    group.sample_size(10);
+
+    let setup = || {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch.db");
+        #[allow(clippy::arc_with_non_send_sync)]
+        let io = Arc::new(PlatformIO::new().unwrap());
+        let db = Database::open_file(io.clone(), path.to_str().unwrap(), false, false).unwrap();
+        let conn = db.connect().unwrap();
+        conn.execute("CREATE TABLE t (x INTEGER, y TEXT)").unwrap();
+        let stmt = conn.prepare("INSERT INTO t VALUES (?, ?)").unwrap();
+        (dir, io, conn, stmt)
+    };
+    let row = |i: i64| vec![Value::Integer(i), Value::build_text("the quick brown fox")];
+
+    group.bench_function("limbo_insert_looped_step", |b| {
+        b.iter_with_setup(setup, |(_dir, io, _conn, mut stmt)| {
+            for i in 0..ROWS {
+                stmt.reset();
+                for (idx, value) in row(i).into_iter().enumerate() {
+                    stmt.bind_at(NonZeroUsize::new(idx + 1).unwrap(), value);
+                }
+                loop {
+                    match stmt.step().unwrap() {
+                        turso_core::StepResult::IO => {
+                            let _ = io.run_once();
+                        }
+                        turso_core::StepResult::Done => break,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        });
+    });
+
+    group.bench_function("limbo_insert_execute_batch", |b| {
+        b.iter_with_setup(setup, |(_dir, io, _conn, mut stmt)| {
+            let mut rows = (0..ROWS).map(row);
+            loop {
+                match stmt.execute_batch(&mut rows).unwrap() {
+                    turso_core::StepResult::IO => {
+                        let _ = io.run_once();
+                    }
+                    turso_core::StepResult::Done => break,
+                    _ => unreachable!(),
+                }
+            }
+        });
+    });
+
+    group.finish();
+}
+
+fn bench_scan_after_churn(criterion: &mut Criterion) {
+    const ROWS: i64 = 20_000;
+    let mut group =
+        criterion.benchmark_group("Full scan after deleting and re-inserting half the rows");
+
+    // The same workload with pages reused in freelist order, as allocation used to, and with
+    // pages picked close to the btree being grown.
+    for allocation in ["no_churn", "freelist_order", "near"] {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("churn.db");
+        #[allow(clippy::arc_with_non_send_sync)]
+        let io = Arc::new(PlatformIO::new().unwrap());
+        let db = Database::open_file(io.clone(), path.to_str().unwrap(), false, false).unwrap();
+        let limbo_conn = db.connect().unwrap();
+        limbo_conn.set_freelist_locality(allocation == "near");
+        limbo_conn
+            .execute("CREATE TABLE t (x INTEGER, y TEXT)")
+            .unwrap();
+        let insert = |range: std::ops::Range<i64>| {
+            limbo_conn.execute("BEGIN").unwrap();
+            for i in range {
+                limbo_conn
+                    .execute(format!("INSERT INTO t VALUES ({i}, '{}')", "y".repeat(100)))
+                    .unwrap();
+            }
+            limbo_conn.execute("COMMIT").unwrap();
+        };
+        insert(0..ROWS);
+        if allocation != "no_churn" {
+            // Pages freed by the delete are reused by the re-insert, so the scan below
+            // measures how well the freelist keeps the table's pages together.
+            limbo_conn
+                .execute(format!("DELETE FROM t WHERE x < {}", ROWS / 2))
+                .unwrap();
+            insert(ROWS..ROWS + ROWS / 2);
+        }
+
+        group.bench_with_input(
+            BenchmarkId::new("limbo_scan_after_churn", allocation),
+            &allocation,
+            |b, _| {
+                let mut stmt = limbo_conn.prepare("SELECT sum(length(y)) FROM t").unwrap();
+                b.iter(|| {
+                    loop {
+                        match stmt.step().unwrap() {
+                            turso_core::StepResult::Row => {
+                                black_box(stmt.row());
+                            }
+                            turso_core::StepResult::IO => {
+                                let _ = io.run_once();
+                            }
+                            turso_core::StepResult::Done => break,
+                            _ => unreachable!(),
+                        }
+                    }
+                    stmt.reset();
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
-    targets = bench_prepare_query, bench_execute_select_1, bench_execute_select_rows, bench_execute_select_count
+    targets = bench_prepare_query, bench_prepare_distinct_literals, bench_execute_select_1, bench_execute_select_rows, bench_execute_select_count, bench_insert_page_checksums, bench_insert_batch, bench_scan_after_churn
 }
 criterion_main!(benches);
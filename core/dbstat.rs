@@ -0,0 +1,352 @@
+use crate::schema::Schema;
+use crate::storage::btree::{payload_overflow_threshold_max, payload_overflow_threshold_min};
+use crate::storage::header_accessor;
+use crate::storage::pager::{PageRef, Pager, DB_STATE_INITIALIZED};
+use crate::storage::sqlite3_ondisk::{BTreeCell, PageType, DATABASE_HEADER_SIZE};
+use crate::types::CursorResult;
+use crate::{Connection, LimboError, Value};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// The name of the DBSTAT virtual table, which can be used without being created first.
+pub(crate) const DBSTAT_NAME: &str = "dbstat";
+
+const DBSTAT_SCHEMA: &str = "CREATE TABLE x(
+    name TEXT,
+    path TEXT,
+    pageno INTEGER,
+    pagetype TEXT,
+    ncell INTEGER,
+    payload INTEGER,
+    unused INTEGER,
+    mx_payload INTEGER,
+    pgoffset INTEGER,
+    pgsize INTEGER
+)";
+
+/// The DBSTAT virtual table returns one row per page of every b-tree in the database,
+/// see https://www.sqlite.org/dbstat.html.
+#[derive(Debug, Clone)]
+pub(crate) struct DbStatVirtualTable;
+
+impl DbStatVirtualTable {
+    pub(crate) fn create() -> (Self, String) {
+        (DbStatVirtualTable, DBSTAT_SCHEMA.to_string())
+    }
+
+    pub(crate) fn open(&self, conn: Arc<Connection>) -> crate::Result<DbStatVirtualTableCursor> {
+        Ok(DbStatVirtualTableCursor {
+            conn,
+            rows: Vec::new(),
+            pos: 0,
+            page_size: 0,
+            walk: None,
+        })
+    }
+}
+
+/// A row of the DBSTAT virtual table, describing a single page.
+struct DbStatRow {
+    /// The name of the table or index the page belongs to.
+    name: String,
+    /// The path from the root page of the b-tree to the page, like in SQLite: the root page
+    /// is "/", its children are "/000/", "/001/", ... and the overflow pages of the cells of a
+    /// page are "<path>000+000000", "<path>000+000001", ... (all numbers are hexadecimal).
+    path: String,
+    pageno: usize,
+    pagetype: &'static str,
+    ncell: usize,
+    payload: usize,
+    unused: usize,
+    mx_payload: usize,
+}
+
+pub struct DbStatVirtualTableCursor {
+    conn: Arc<Connection>,
+    rows: Vec<DbStatRow>,
+    pos: usize,
+    page_size: usize,
+    /// The pages left to visit while [Self::filter] walks the b-trees, the next one last. Set
+    /// while the walk waits for a page to be read.
+    walk: Option<Vec<WalkStep>>,
+}
+
+/// A page the walk of the b-trees still has to visit.
+enum WalkStep {
+    /// Page `page_idx` of the b-tree `name`, whose path is `path`.
+    BTreePage {
+        name: Rc<str>,
+        page_idx: usize,
+        path: String,
+    },
+    /// Overflow page `overflow_idx` of the `overflow_count` pages of a cell whose path is
+    /// `cell_path`. The cell spills `overflow_payload` bytes over them.
+    OverflowPage {
+        name: Rc<str>,
+        page_idx: usize,
+        cell_path: String,
+        overflow_idx: usize,
+        overflow_count: usize,
+        overflow_payload: usize,
+    },
+}
+
+impl WalkStep {
+    fn page_idx(&self) -> usize {
+        match self {
+            WalkStep::BTreePage { page_idx, .. } | WalkStep::OverflowPage { page_idx, .. } => {
+                *page_idx
+            }
+        }
+    }
+}
+
+impl DbStatVirtualTableCursor {
+    pub(crate) fn rowid(&self) -> i64 {
+        self.pos as i64
+    }
+
+    pub(crate) fn next(&mut self) -> crate::Result<bool> {
+        self.pos += 1;
+        Ok(self.pos < self.rows.len())
+    }
+
+    pub(crate) fn column(&self, idx: usize) -> crate::Result<Value> {
+        let row = self
+            .rows
+            .get(self.pos)
+            .ok_or_else(|| LimboError::InternalError("No row available".into()))?;
+        let value = match idx {
+            0 => Value::from_text(&row.name),
+            1 => Value::from_text(&row.path),
+            2 => Value::Integer(row.pageno as i64),
+            3 => Value::from_text(row.pagetype),
+            4 => Value::Integer(row.ncell as i64),
+            5 => Value::Integer(row.payload as i64),
+            6 => Value::Integer(row.unused as i64),
+            7 => Value::Integer(row.mx_payload as i64),
+            8 => Value::Integer(((row.pageno - 1) * self.page_size) as i64),
+            9 => Value::Integer(self.page_size as i64),
+            _ => Value::Null,
+        };
+        Ok(value)
+    }
+
+    /// Walks every b-tree of the database. Returns IO while a page is being read, in which case
+    /// the walk picks up where it left off on the next call.
+    pub(crate) fn filter(&mut self, _args: Vec<Value>) -> crate::Result<CursorResult<bool>> {
+        let pager = self.conn.pager.clone();
+        let mut walk = match self.walk.take() {
+            Some(walk) => walk,
+            None => {
+                self.pos = 0;
+                self.rows.clear();
+                start_walk(&pager, &self.conn.schema.borrow())
+            }
+        };
+        while let Some(step) = walk.last() {
+            let page = pager.read_page(step.page_idx())?;
+            if !page.is_loaded() || page.is_locked() {
+                self.walk = Some(walk);
+                return Ok(CursorResult::IO);
+            }
+            let step = walk.pop().unwrap();
+            visit_page(&pager, step, &page, &mut self.rows, &mut walk)?;
+        }
+        if !self.rows.is_empty() {
+            self.page_size = header_accessor::get_page_size(&pager)? as usize;
+        }
+        Ok(CursorResult::Ok(!self.rows.is_empty()))
+    }
+}
+
+/// Returns the walk of every b-tree of the database, in the order of their root pages like
+/// SQLite does.
+fn start_walk(pager: &Pager, schema: &Schema) -> Vec<WalkStep> {
+    if pager.is_empty.load(Ordering::SeqCst) < DB_STATE_INITIALIZED {
+        return Vec::new();
+    }
+    let mut btrees = schema
+        .tables
+        .values()
+        .filter_map(|table| table.btree())
+        .map(|table| (table.root_page, table.name.clone()))
+        .chain(
+            schema
+                .indexes
+                .values()
+                .flatten()
+                .map(|index| (index.root_page, index.name.clone())),
+        )
+        .filter(|(root_page, _)| *root_page > 0)
+        .collect::<Vec<_>>();
+    btrees.sort();
+    btrees
+        .into_iter()
+        .rev()
+        .map(|(root_page, name)| WalkStep::BTreePage {
+            name: name.into(),
+            page_idx: root_page,
+            path: "/".to_string(),
+        })
+        .collect()
+}
+
+/// Adds the row of the page visited by `step`, and pushes the pages below it onto `walk` so
+/// that they are visited next, in order.
+fn visit_page(
+    pager: &Pager,
+    step: WalkStep,
+    page: &PageRef,
+    rows: &mut Vec<DbStatRow>,
+    walk: &mut Vec<WalkStep>,
+) -> crate::Result<()> {
+    let usable_space = pager.usable_space();
+    let (name, page_idx, path) = match step {
+        WalkStep::BTreePage {
+            name,
+            page_idx,
+            path,
+        } => (name, page_idx, path),
+        WalkStep::OverflowPage {
+            name,
+            page_idx,
+            cell_path,
+            overflow_idx,
+            overflow_count,
+            overflow_payload,
+        } => {
+            // Every overflow page starts with the number of the next one, and the last one
+            // holds whatever remains of the payload.
+            let overflow_size = usable_space - 4;
+            let payload = if overflow_idx == overflow_count - 1 {
+                overflow_payload - (overflow_count - 1) * overflow_size
+            } else {
+                overflow_size
+            };
+            rows.push(DbStatRow {
+                name: name.to_string(),
+                path: format!("{cell_path}+{overflow_idx:06x}"),
+                pageno: page_idx,
+                pagetype: "overflow",
+                ncell: 0,
+                payload,
+                unused: overflow_size - payload,
+                mx_payload: 0,
+            });
+            if overflow_idx < overflow_count - 1 {
+                walk.push(WalkStep::OverflowPage {
+                    name,
+                    page_idx: page.get_contents().read_u32(0) as usize,
+                    cell_path,
+                    overflow_idx: overflow_idx + 1,
+                    overflow_count,
+                    overflow_payload,
+                });
+            }
+            return Ok(());
+        }
+    };
+
+    let contents = page.get_contents();
+    let page_type = contents.page_type();
+    let max_local = payload_overflow_threshold_max(page_type, usable_space as u16);
+    let min_local = payload_overflow_threshold_min(page_type, usable_space as u16);
+
+    // The pages below are pushed in reverse, so that the walk pops them in order.
+    let mut below = Vec::new();
+    let mut payload = 0;
+    let mut mx_payload = 0;
+    for cell_idx in 0..contents.cell_count() {
+        let (child_page, local_size, payload_size, first_overflow_page) =
+            match contents.cell_get(cell_idx, max_local, min_local, usable_space)? {
+                BTreeCell::TableInteriorCell(cell) => (Some(cell._left_child_page), 0, 0, None),
+                BTreeCell::TableLeafCell(cell) => (
+                    None,
+                    cell._payload.len(),
+                    cell.payload_size as usize,
+                    cell.first_overflow_page,
+                ),
+                BTreeCell::IndexInteriorCell(cell) => (
+                    Some(cell.left_child_page),
+                    cell.payload.len(),
+                    cell.payload_size as usize,
+                    cell.first_overflow_page,
+                ),
+                BTreeCell::IndexLeafCell(cell) => (
+                    None,
+                    cell.payload.len(),
+                    cell.payload_size as usize,
+                    cell.first_overflow_page,
+                ),
+            };
+        payload += local_size;
+        mx_payload = mx_payload.max(payload_size);
+
+        if let Some(first_overflow_page) = first_overflow_page {
+            let overflow_payload = payload_size - local_size;
+            below.push(WalkStep::OverflowPage {
+                name: name.clone(),
+                page_idx: first_overflow_page as usize,
+                cell_path: format!("{path}{cell_idx:03x}"),
+                overflow_idx: 0,
+                overflow_count: overflow_payload.div_ceil(usable_space - 4),
+                overflow_payload,
+            });
+        }
+        if let Some(child_page) = child_page {
+            below.push(WalkStep::BTreePage {
+                name: name.clone(),
+                page_idx: child_page as usize,
+                path: format!("{path}{cell_idx:03x}/"),
+            });
+        }
+    }
+    if let Some(right_child) = contents.rightmost_pointer() {
+        below.push(WalkStep::BTreePage {
+            name: name.clone(),
+            page_idx: right_child as usize,
+            path: format!("{path}{:03x}/", contents.cell_count()),
+        });
+    }
+
+    rows.push(DbStatRow {
+        name: name.to_string(),
+        path,
+        pageno: page_idx,
+        pagetype: match page_type {
+            PageType::TableInterior | PageType::IndexInterior => "internal",
+            PageType::TableLeaf | PageType::IndexLeaf => "leaf",
+        },
+        ncell: contents.cell_count(),
+        payload,
+        unused: unused_bytes(page_idx, page),
+        mx_payload,
+    });
+    walk.extend(below.into_iter().rev());
+    Ok(())
+}
+
+/// Returns the number of bytes of a b-tree page that are not used by the page header, the cell
+/// pointer array or the cells: the unallocated space, the free blocks and the fragments.
+fn unused_bytes(page_idx: usize, page: &PageRef) -> usize {
+    let contents = page.get_contents();
+    let header_size = if page_idx == 1 {
+        contents.header_size() + DATABASE_HEADER_SIZE
+    } else {
+        contents.header_size()
+    };
+    let cell_content_area = match contents.cell_content_area() {
+        0 => 65536,
+        offset => offset as usize,
+    };
+    let mut unused = cell_content_area - header_size - contents.cell_pointer_array_size()
+        + contents.num_frag_free_bytes() as usize;
+    let mut free_block = contents.first_freeblock() as usize;
+    while free_block != 0 {
+        unused += contents.read_u16_no_offset(free_block + 2) as usize;
+        free_block = contents.read_u16_no_offset(free_block) as usize;
+    }
+    unused
+}
@@ -57,6 +57,21 @@ pub enum LimboError {
     ReadOnly,
     #[error("Database is busy")]
     Busy,
+    #[error("database is closed")]
+    DatabaseClosed,
+    #[error("unable to close database: {connections} open connection(s) and {statements} unfinalized statement(s)")]
+    DatabaseInUse {
+        connections: usize,
+        statements: usize,
+    },
+    #[error("Page quota exceeded: the database file is limited to {0} pages")]
+    QuotaExceeded(u32),
+    #[error("database or disk is full")]
+    DatabaseFull,
+    #[error("Transaction aborted by the commit hook")]
+    CommitHookAborted,
+    #[error("Remote database error: {0}")]
+    RemoteError(String),
 }
 
 #[macro_export]
@@ -86,6 +101,9 @@ impl From<turso_ext::ResultCode> for LimboError {
     }
 }
 
+pub const SQLITE_ERROR: usize = 1;
 pub const SQLITE_CONSTRAINT: usize = 19;
 pub const SQLITE_CONSTRAINT_PRIMARYKEY: usize = SQLITE_CONSTRAINT | (6 << 8);
 pub const SQLITE_CONSTRAINT_NOTNULL: usize = SQLITE_CONSTRAINT | (5 << 8);
+pub const SQLITE_CONSTRAINT_FOREIGNKEY: usize = SQLITE_CONSTRAINT | (3 << 8);
+pub const SQLITE_CONSTRAINT_CHECK: usize = SQLITE_CONSTRAINT | (1 << 8);
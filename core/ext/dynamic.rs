@@ -1,10 +1,13 @@
 use crate::{
-    ext::{register_aggregate_function, register_scalar_function, register_vtab_module},
+    ext::{
+        register_aggregate_function, register_collation, register_scalar_function,
+        register_vtab_module,
+    },
     Connection, LimboError,
 };
 use libloading::{Library, Symbol};
 use std::{
-    ffi::{c_char, CString},
+    ffi::{c_char, CStr, CString},
     sync::{Arc, Mutex, OnceLock},
 };
 use turso_ext::{ExtensionApi, ExtensionApiRef, ExtensionEntryPoint, ResultCode, VfsImpl};
@@ -28,10 +31,17 @@ pub struct VfsMod {
 unsafe impl Send for VfsMod {}
 unsafe impl Sync for VfsMod {}
 
+/// The function called to register an extension when no entry point is given.
+const DEFAULT_ENTRY_POINT: &str = "register_extension";
+
 impl Connection {
+    /// Loads the extension in the shared library at `path`, like `sqlite3_load_extension`:
+    /// the library is opened and its `entry_point` function, `register_extension` by default,
+    /// is called to register the functions, virtual tables and VFSes of the extension.
     pub fn load_extension<P: AsRef<std::ffi::OsStr>>(
         self: &Arc<Connection>,
         path: P,
+        entry_point: Option<&str>,
     ) -> crate::Result<()> {
         use turso_ext::ExtensionApiRef;
 
@@ -39,7 +49,7 @@ impl Connection {
         let lib =
             unsafe { Library::new(path).map_err(|e| LimboError::ExtensionError(e.to_string()))? };
         let entry: Symbol<ExtensionEntryPoint> = unsafe {
-            lib.get(b"register_extension")
+            lib.get(entry_point.unwrap_or(DEFAULT_ENTRY_POINT).as_bytes())
                 .map_err(|e| LimboError::ExtensionError(e.to_string()))?
         };
         let api_ptr: *const ExtensionApi = Box::into_raw(api);
@@ -102,6 +112,7 @@ pub fn add_builtin_vfs_extensions(
             register_scalar_function,
             register_aggregate_function,
             register_vtab_module,
+            register_collation,
             vfs_interface: VfsInterface {
                 register_vfs,
                 builtin_vfs: vfslist.as_mut_ptr(),
@@ -150,6 +161,26 @@ fn register_static_vfs_modules(_api: &mut ExtensionApi) {
     /* Placeholder for any VFS modules to build in at compile time */
 }
 
+/// Registers a VFS built into the application, like the one returned by the
+/// `register_static_<Name>` function that `#[derive(VfsDerive)]` generates, so databases can
+/// be opened with it by name, the same as with a VFS of a loaded extension.
+///
+/// # Safety
+/// `vfs` must point to a valid [VfsImpl] that is never freed.
+pub unsafe fn register_static_vfs(vfs: *const VfsImpl) -> crate::Result<()> {
+    if vfs.is_null() {
+        return Err(LimboError::ExtensionError(
+            "unable to register vfs extension".to_string(),
+        ));
+    }
+    let name = unsafe { CStr::from_ptr((*vfs).name) }
+        .to_str()
+        .map_err(|_| LimboError::ExtensionError("unable to register vfs extension".to_string()))?
+        .to_string();
+    add_vfs_module(name, Arc::new(VfsMod { ctx: vfs }));
+    Ok(())
+}
+
 pub fn add_vfs_module(name: String, vfs: Arc<VfsMod>) {
     let mut modules = VFS_MODULES
         .get_or_init(|| Mutex::new(Vec::new()))
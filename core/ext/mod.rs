@@ -3,16 +3,22 @@ mod dynamic;
 mod vtab_xconnect;
 #[cfg(all(target_os = "linux", feature = "io_uring"))]
 use crate::UringIO;
-use crate::{function::ExternalFunc, Connection, Database, LimboError, IO};
+use crate::{
+    function::ExternalFunc, translate::collate::CollationSeq, Connection, Database, LimboError, IO,
+};
 #[cfg(feature = "fs")]
-pub use dynamic::{add_builtin_vfs_extensions, add_vfs_module, list_vfs_modules, VfsMod};
+pub use dynamic::{
+    add_builtin_vfs_extensions, add_vfs_module, get_vfs_modules, list_vfs_modules,
+    register_static_vfs, VfsMod,
+};
 use std::{
     ffi::{c_char, c_void, CStr, CString},
     rc::Rc,
     sync::Arc,
 };
 use turso_ext::{
-    ExtensionApi, InitAggFunction, ResultCode, ScalarFunction, VTabKind, VTabModuleImpl,
+    CollationFunction, ExtensionApi, InitAggFunction, ResultCode, ScalarFunction, VTabKind,
+    VTabModuleImpl,
 };
 pub use turso_ext::{FinalizeFunction, StepFunction, Value as ExtValue, ValueType as ExtValueType};
 pub use vtab_xconnect::{close, execute, prepare_stmt};
@@ -83,6 +89,26 @@ pub(crate) unsafe extern "C" fn register_vtab_module(
     conn.register_vtab_module_impl(&name_str, module, kind)
 }
 
+pub(crate) unsafe extern "C" fn register_collation(
+    _ctx: *mut c_void,
+    name: *const c_char,
+    func: CollationFunction,
+) -> ResultCode {
+    if name.is_null() {
+        return ResultCode::Error;
+    }
+    let c_str = unsafe { CStr::from_ptr(name) };
+    let name_str = match c_str.to_str() {
+        Ok(s) => s,
+        Err(_) => return ResultCode::InvalidArgs,
+    };
+    // Collations are shared by every connection, see CollationSeq::register.
+    match CollationSeq::register(name_str, func) {
+        Ok(()) => ResultCode::OK,
+        Err(_) => ResultCode::InvalidArgs,
+    }
+}
+
 impl Database {
     #[cfg(feature = "fs")]
     #[allow(clippy::arc_with_non_send_sync, dead_code)]
@@ -92,7 +118,6 @@ impl Database {
         vfs: &str,
     ) -> crate::Result<(Arc<dyn IO>, Arc<Database>)> {
         use crate::{MemoryIO, SyscallIO};
-        use dynamic::get_vfs_modules;
 
         let io: Arc<dyn IO> = match vfs {
             "memory" => Arc::new(MemoryIO::new()),
@@ -120,6 +145,7 @@ impl Connection {
             name.to_string(),
             Rc::new(ExternalFunc::new_scalar(name.to_string(), func)),
         );
+        self.query_shape_cache.borrow_mut().clear();
         ResultCode::OK
     }
 
@@ -133,6 +159,7 @@ impl Connection {
             name.to_string(),
             Rc::new(ExternalFunc::new_aggregate(name.to_string(), args, func)),
         );
+        self.query_shape_cache.borrow_mut().clear();
         ResultCode::OK
     }
 
@@ -160,6 +187,7 @@ impl Connection {
             register_scalar_function,
             register_aggregate_function,
             register_vtab_module,
+            register_collation,
             #[cfg(feature = "fs")]
             vfs_interface: turso_ext::VfsInterface {
                 register_vfs: dynamic::register_vfs,
@@ -43,7 +43,24 @@ pub unsafe extern "C" fn execute(
     };
     let conn_ptr = extcon._ctx as *const Arc<Connection>;
     let conn = &*conn_ptr;
-    match conn.query(&sql_str) {
+    // The statement runs inside the transaction of the statement that invoked the callback.
+    if let Err(e) = conn.enter_nested_statement() {
+        tracing::error!("execute: {}", e);
+        return ResultCode::Error;
+    }
+    let rc = execute_nested(conn, &sql_str, args, arg_count, last_insert_rowid);
+    conn.exit_nested_statement();
+    rc
+}
+
+unsafe fn execute_nested(
+    conn: &Arc<Connection>,
+    sql_str: &str,
+    args: *mut ExtValue,
+    arg_count: i32,
+    last_insert_rowid: *mut i64,
+) -> ResultCode {
+    match conn.query(sql_str) {
         Ok(Some(mut stmt)) => {
             if arg_count > 0 {
                 let args_slice = &mut std::slice::from_raw_parts_mut(args, arg_count as usize);
@@ -154,22 +171,28 @@ pub unsafe extern "C" fn stmt_step(stmt: *mut Stmt) -> ResultCode {
         tracing::error!("stmt_step: null connection or context");
         return ResultCode::Error;
     }
-    let conn: &Connection = unsafe { &*(stmt._conn as *const Connection) };
+    let conn: &Arc<Connection> = unsafe { &*(stmt._conn as *const Arc<Connection>) };
     let stmt_ctx: &mut Statement = unsafe { &mut *(stmt._ctx as *mut Statement) };
-    while let Ok(res) = stmt_ctx.step() {
-        match res {
-            StepResult::Row => return ResultCode::Row,
-            StepResult::Done => return ResultCode::EOF,
-            StepResult::IO => {
+    // The statement runs inside the transaction of the statement that invoked the callback.
+    if let Err(e) = conn.enter_nested_statement() {
+        tracing::error!("stmt_step: {}", e);
+        return ResultCode::Error;
+    }
+    let rc = loop {
+        match stmt_ctx.step() {
+            Ok(StepResult::Row) => break ResultCode::Row,
+            Ok(StepResult::Done) => break ResultCode::EOF,
+            Ok(StepResult::IO) => {
                 // always handle IO step result internally.
                 let _ = conn.pager.io.run_once();
-                continue;
             }
-            StepResult::Interrupt => return ResultCode::Interrupt,
-            StepResult::Busy => return ResultCode::Busy,
+            Ok(StepResult::Interrupt) => break ResultCode::Interrupt,
+            Ok(StepResult::Busy) => break ResultCode::Busy,
+            Err(_) => break ResultCode::Error,
         }
-    }
-    ResultCode::Error
+    };
+    conn.exit_nested_statement();
+    rc
 }
 
 /// Instead of returning a pointer to the row, sets the Stmt's 'cursor'/current_row
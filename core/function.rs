@@ -20,7 +20,8 @@ impl ExternalFunc {
 pub enum ExtFunc {
     Scalar(ScalarFunction),
     Aggregate {
-        argc: usize,
+        /// The number of arguments, or `None` if the function accepts any number of them.
+        argc: Option<usize>,
         init: InitAggFunction,
         step: StepFunction,
         finalize: FinalizeFunction,
@@ -28,12 +29,30 @@ pub enum ExtFunc {
 }
 
 impl ExtFunc {
-    pub fn agg_args(&self) -> Result<usize, ()> {
+    pub fn agg_args(&self) -> Result<Option<usize>, ()> {
         if let ExtFunc::Aggregate { argc, .. } = self {
             return Ok(*argc);
         }
         Err(())
     }
+
+    /// Returns the aggregate function as called with `argc` arguments.
+    pub fn with_agg_args(&self, argc: usize) -> Self {
+        match self {
+            ExtFunc::Aggregate {
+                init,
+                step,
+                finalize,
+                ..
+            } => ExtFunc::Aggregate {
+                argc: Some(argc),
+                init: *init,
+                step: *step,
+                finalize: *finalize,
+            },
+            ExtFunc::Scalar(_) => self.clone(),
+        }
+    }
 }
 
 impl ExternalFunc {
@@ -52,7 +71,8 @@ impl ExternalFunc {
         Self {
             name,
             func: ExtFunc::Aggregate {
-                argc: argc as usize,
+                // Like in SQLite, a negative number of arguments means any number of them.
+                argc: usize::try_from(argc).ok(),
                 init: func.0,
                 step: func.1,
                 finalize: func.2,
@@ -236,7 +256,7 @@ impl AggFunc {
             Self::JsonGroupArray | Self::JsonbGroupArray => 1,
             #[cfg(feature = "json")]
             Self::JsonGroupObject | Self::JsonbGroupObject => 2,
-            Self::External(func) => func.agg_args().unwrap_or(0),
+            Self::External(func) => func.agg_args().ok().flatten().unwrap_or(0),
         }
     }
 
@@ -304,6 +324,8 @@ pub enum ScalarFunc {
     Quote,
     SqliteVersion,
     SqliteSourceId,
+    #[cfg(feature = "offset_sql_func")]
+    SqliteOffset,
     UnixEpoch,
     JulianDay,
     Hex,
@@ -318,6 +340,8 @@ pub enum ScalarFunc {
     Likely,
     TimeDiff,
     Likelihood,
+    #[cfg(feature = "regexp")]
+    Regexp,
 }
 
 impl ScalarFunc {
@@ -361,6 +385,8 @@ impl ScalarFunc {
             ScalarFunc::Quote => true,
             ScalarFunc::SqliteVersion => true,
             ScalarFunc::SqliteSourceId => true,
+            #[cfg(feature = "offset_sql_func")]
+            ScalarFunc::SqliteOffset => false,
             ScalarFunc::UnixEpoch => false,
             ScalarFunc::JulianDay => false,
             ScalarFunc::Hex => true,
@@ -374,6 +400,8 @@ impl ScalarFunc {
             ScalarFunc::Printf => false,
             ScalarFunc::Likely => true,
             ScalarFunc::TimeDiff => false,
+            #[cfg(feature = "regexp")]
+            ScalarFunc::Regexp => true,
             ScalarFunc::Likelihood => true,
         }
     }
@@ -419,6 +447,8 @@ impl Display for ScalarFunc {
             Self::Quote => "quote".to_string(),
             Self::SqliteVersion => "sqlite_version".to_string(),
             Self::SqliteSourceId => "sqlite_source_id".to_string(),
+            #[cfg(feature = "offset_sql_func")]
+            Self::SqliteOffset => "sqlite_offset".to_string(),
             Self::JulianDay => "julianday".to_string(),
             Self::UnixEpoch => "unixepoch".to_string(),
             Self::Hex => "hex".to_string(),
@@ -433,6 +463,8 @@ impl Display for ScalarFunc {
             Self::Printf => "printf".to_string(),
             Self::Likely => "likely".to_string(),
             Self::TimeDiff => "timediff".to_string(),
+            #[cfg(feature = "regexp")]
+            Self::Regexp => "regexp".to_string(),
             Self::Likelihood => "likelihood".to_string(),
         };
         write!(f, "{}", str)
@@ -554,7 +586,7 @@ impl Display for MathFunc {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AlterTableFunc {
     RenameTable,
     RenameColumn,
@@ -569,7 +601,70 @@ impl Display for AlterTableFunc {
     }
 }
 
-#[derive(Debug)]
+/// Built-in window functions. These can only be called with an OVER clause, see
+/// translate/window.rs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFunc {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Ntile,
+    Lag,
+    Lead,
+    FirstValue,
+    LastValue,
+    NthValue,
+}
+
+impl WindowFunc {
+    pub fn resolve(name: &str) -> Option<Self> {
+        match name {
+            "row_number" => Some(Self::RowNumber),
+            "rank" => Some(Self::Rank),
+            "dense_rank" => Some(Self::DenseRank),
+            "ntile" => Some(Self::Ntile),
+            "lag" => Some(Self::Lag),
+            "lead" => Some(Self::Lead),
+            "first_value" => Some(Self::FirstValue),
+            "last_value" => Some(Self::LastValue),
+            "nth_value" => Some(Self::NthValue),
+            _ => None,
+        }
+    }
+
+    pub fn accepts_num_args(&self, num_args: usize) -> bool {
+        match self {
+            Self::RowNumber | Self::Rank | Self::DenseRank => num_args == 0,
+            Self::Ntile | Self::FirstValue | Self::LastValue => num_args == 1,
+            Self::NthValue => num_args == 2,
+            Self::Lag | Self::Lead => (1..=3).contains(&num_args),
+        }
+    }
+
+    /// Whether the value of the function depends on the frame of the current row.
+    pub fn uses_frame(&self) -> bool {
+        matches!(self, Self::FirstValue | Self::LastValue | Self::NthValue)
+    }
+}
+
+impl Display for WindowFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Self::RowNumber => "row_number",
+            Self::Rank => "rank",
+            Self::DenseRank => "dense_rank",
+            Self::Ntile => "ntile",
+            Self::Lag => "lag",
+            Self::Lead => "lead",
+            Self::FirstValue => "first_value",
+            Self::LastValue => "last_value",
+            Self::NthValue => "nth_value",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Func {
     Agg(AggFunc),
     Scalar(ScalarFunc),
@@ -596,7 +691,7 @@ impl Display for Func {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FuncCtx {
     pub func: Func,
     pub arg_count: usize,
@@ -679,6 +774,13 @@ impl Func {
                 }
                 Ok(Self::Scalar(ScalarFunc::TimeDiff))
             }
+            #[cfg(feature = "regexp")]
+            "regexp" => {
+                if arg_count != 2 {
+                    crate::bail_parse_error!("wrong number of arguments to function {}()", name)
+                }
+                Ok(Self::Scalar(ScalarFunc::Regexp))
+            }
             #[cfg(feature = "json")]
             "jsonb_group_array" => Ok(Self::Agg(AggFunc::JsonbGroupArray)),
             #[cfg(feature = "json")]
@@ -721,6 +823,13 @@ impl Func {
             "quote" => Ok(Self::Scalar(ScalarFunc::Quote)),
             "sqlite_version" => Ok(Self::Scalar(ScalarFunc::SqliteVersion)),
             "sqlite_source_id" => Ok(Self::Scalar(ScalarFunc::SqliteSourceId)),
+            #[cfg(feature = "offset_sql_func")]
+            "sqlite_offset" => {
+                if arg_count != 1 {
+                    crate::bail_parse_error!("wrong number of arguments to function {}()", name)
+                }
+                Ok(Self::Scalar(ScalarFunc::SqliteOffset))
+            }
             "replace" => Ok(Self::Scalar(ScalarFunc::Replace)),
             "likely" => Ok(Self::Scalar(ScalarFunc::Likely)),
             "likelihood" => Ok(Self::Scalar(ScalarFunc::Likelihood)),
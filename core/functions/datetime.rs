@@ -51,6 +51,23 @@ fn exec_datetime(values: &[Register], output_type: DateTimeOutput) -> Value {
         let now = parse_naive_date_time(&Value::build_text("now")).unwrap();
         return format_dt(now, output_type, false);
     }
+    if let Some(format) = values
+        .get(1)
+        .and_then(|modifier| parse_numeric_format(modifier.get_owned_value()))
+    {
+        // 'unixepoch', 'julianday' and 'auto' only tell how to read the numeric time value
+        let value = values[0].get_owned_value();
+        let dt = match (parse_numeric_time_value(value), format) {
+            (Some(number), format) => get_date_time_from_number(number, format),
+            // 'auto' leaves other time values alone
+            (None, NumericFormat::Auto) => parse_naive_date_time(value),
+            (None, _) => None,
+        };
+        let Some(mut dt) = dt else {
+            return Value::build_text("");
+        };
+        return modify_dt(&mut dt, &values[2..], output_type);
+    }
     if let Some(mut dt) = parse_naive_date_time(values[0].get_owned_value()) {
         // if successful, treat subsequent entries as modifiers
         modify_dt(&mut dt, &values[1..], output_type)
@@ -63,14 +80,24 @@ fn exec_datetime(values: &[Register], output_type: DateTimeOutput) -> Value {
 
 fn modify_dt(dt: &mut NaiveDateTime, mods: &[Register], output_type: DateTimeOutput) -> Value {
     let mut subsec_requested = false;
+    // days the last month or year shift moved past the end of its target month
+    let mut month_overflow = 0;
 
     for modifier in mods {
         if let Value::Text(ref text_rc) = modifier.get_owned_value() {
+            // 'floor' undoes the overflow of an immediately preceding month or year shift
+            if text_rc.as_str().trim().eq_ignore_ascii_case("floor") {
+                *dt -= TimeDelta::days(month_overflow as i64);
+                month_overflow = 0;
+                continue;
+            }
             // TODO: to prevent double conversion and properly support 'utc'/'localtime', we also
             // need to keep track of the current timezone and apply it to the modifier.
+            month_overflow = 0;
             match apply_modifier(dt, text_rc.as_str()) {
-                Ok(true) => subsec_requested = true,
-                Ok(false) => {}
+                Ok(ModifierEffect::Subsec) => subsec_requested = true,
+                Ok(ModifierEffect::MonthOverflow(days)) => month_overflow = days,
+                Ok(ModifierEffect::None) => {}
                 Err(_) => return Value::build_text(""),
             }
         } else {
@@ -134,9 +161,20 @@ fn strftime_format(dt: &NaiveDateTime, format_str: &str) -> String {
     }
 }
 
+/// What applying a modifier did besides shifting the date/time.
+#[derive(Debug, PartialEq)]
+enum ModifierEffect {
+    None,
+    /// 'subsec' was requested, which impacts the format string.
+    Subsec,
+    /// A month or year shift landed on a day past the end of the target month and advanced by
+    /// this many days, which a following 'floor' undoes.
+    MonthOverflow(u32),
+}
+
 // to prevent stripping the modifier string and comparing multiple times, this returns
-// whether the modifier was a subsec modifier because it impacts the format string
-fn apply_modifier(dt: &mut NaiveDateTime, modifier: &str) -> Result<bool> {
+// what the modifier did besides shifting the date/time
+fn apply_modifier(dt: &mut NaiveDateTime, modifier: &str) -> Result<ModifierEffect> {
     let parsed_modifier = parse_modifier(modifier)?;
 
     match parsed_modifier {
@@ -148,10 +186,12 @@ fn apply_modifier(dt: &mut NaiveDateTime, modifier: &str) -> Result<bool> {
             // Convert months to years + leftover months
             let years = m / 12;
             let leftover = m % 12;
-            add_years_and_months(dt, years, leftover)?;
+            let overflow = add_years_and_months(dt, years, leftover)?;
+            return Ok(ModifierEffect::MonthOverflow(overflow));
         }
         Modifier::Years(y) => {
-            add_years_and_months(dt, y, 0)?;
+            let overflow = add_years_and_months(dt, y, 0)?;
+            return Ok(ModifierEffect::MonthOverflow(overflow));
         }
         Modifier::TimeOffset(offset) => *dt += offset,
         Modifier::DateOffset {
@@ -174,8 +214,9 @@ fn apply_modifier(dt: &mut NaiveDateTime, modifier: &str) -> Result<bool> {
             *dt += chrono::Duration::days(days as i64);
             *dt += chrono::Duration::seconds(seconds.into());
         }
-        Modifier::Ceiling => todo!(),
-        Modifier::Floor => todo!(),
+        // 'ceiling' is how shifts resolve overflowing days anyway, and 'floor' needs to know
+        // about the preceding modifier, so modify_dt handles it
+        Modifier::Ceiling | Modifier::Floor => {}
         Modifier::StartOfMonth => {
             *dt = NaiveDate::from_ymd_opt(dt.year(), dt.month(), 1)
                 .unwrap()
@@ -197,9 +238,12 @@ fn apply_modifier(dt: &mut NaiveDateTime, modifier: &str) -> Result<bool> {
             let days_to_add = (target_day + 7 - current_day) % 7;
             *dt += TimeDelta::days(days_to_add as i64);
         }
-        Modifier::Auto => todo!(), // Will require storing info about the original arg passed when
-        Modifier::UnixEpoch => todo!(), // applying modifiers. All numbers passed to date/time/dt are
-        Modifier::JulianDay => todo!(), // assumed to be julianday, so adding these now is redundant
+        Modifier::Auto | Modifier::UnixEpoch | Modifier::JulianDay => {
+            return Err(InvalidModifier(format!(
+                "'{}' must immediately follow a numeric time value",
+                modifier.trim()
+            )));
+        }
         Modifier::Localtime => {
             let utc_dt = DateTime::<Utc>::from_naive_utc_and_offset(*dt, Utc);
             *dt = utc_dt.with_timezone(&chrono::Local).naive_local();
@@ -211,101 +255,85 @@ fn apply_modifier(dt: &mut NaiveDateTime, modifier: &str) -> Result<bool> {
         }
         Modifier::Subsec => {
             *dt = dt.with_nanosecond(dt.nanosecond()).unwrap();
-            return Ok(true);
+            return Ok(ModifierEffect::Subsec);
         }
     }
 
-    Ok(false)
+    Ok(ModifierEffect::None)
 }
 
-fn is_julian_day_value(value: f64) -> bool {
-    (0.0..5373484.5).contains(&value)
+/// How a numeric time value is read, chosen by a 'unixepoch', 'julianday' or 'auto' modifier
+/// right after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NumericFormat {
+    UnixEpoch,
+    JulianDay,
+    Auto,
 }
 
-fn add_years_and_months(dt: &mut NaiveDateTime, years: i32, months: i32) -> Result<()> {
-    add_whole_years(dt, years)?;
-    add_months_in_increments(dt, months)?;
-    Ok(())
+fn parse_numeric_format(modifier: &Value) -> Option<NumericFormat> {
+    let Value::Text(text) = modifier else {
+        return None;
+    };
+    match text.as_str().trim().to_lowercase().as_str() {
+        "unixepoch" => Some(NumericFormat::UnixEpoch),
+        "julianday" => Some(NumericFormat::JulianDay),
+        "auto" => Some(NumericFormat::Auto),
+        _ => None,
+    }
 }
 
-fn add_whole_years(dt: &mut NaiveDateTime, years: i32) -> Result<()> {
-    if years == 0 {
-        return Ok(());
-    }
-    let target_year = dt.year() + years;
-    let (m, d, hh, mm, ss) = (dt.month(), dt.day(), dt.hour(), dt.minute(), dt.second());
-
-    // attempt same (month, day) in new year
-    if let Some(date) = NaiveDate::from_ymd_opt(target_year, m, d) {
-        *dt = date
-            .and_hms_opt(hh, mm, ss)
-            .ok_or_else(|| InvalidModifier("Invalid datetime format".to_string()))?;
-        return Ok(());
-    }
-
-    // if invalid: compute overflow days
-    let last_day_in_feb = last_day_in_month(target_year, m);
-    if d > last_day_in_feb {
-        // leftover = d - last_day_in_feb
-        let leftover = d - last_day_in_feb;
-        // base date is last_day_in_feb
-        let base_date = NaiveDate::from_ymd_opt(target_year, m, last_day_in_feb)
-            .ok_or_else(|| InvalidModifier("Invalid datetime format".to_string()))?
-            .and_hms_opt(hh, mm, ss)
-            .ok_or_else(|| InvalidModifier("Invalid time format".to_string()))?;
-
-        *dt = base_date + chrono::Duration::days(leftover as i64);
-    } else {
-        // do we fall back here?
+fn parse_numeric_time_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        Value::Text(text) => text.as_str().trim().parse::<f64>().ok(),
+        _ => None,
     }
-    Ok(())
 }
 
-fn add_months_in_increments(dt: &mut NaiveDateTime, months: i32) -> Result<()> {
-    let step = if months >= 0 { 1 } else { -1 };
-    for _ in 0..months.abs() {
-        add_one_month(dt, step)?;
+fn get_date_time_from_number(number: f64, format: NumericFormat) -> Option<NaiveDateTime> {
+    // Unix timestamps of 0000-01-01 00:00:00 and 9999-12-31 23:59:59
+    const UNIXEPOCH_RANGE: std::ops::RangeInclusive<f64> = -62_167_219_200.0..=253_402_300_799.0;
+
+    let from_unixepoch = |seconds: f64| {
+        if !UNIXEPOCH_RANGE.contains(&seconds) {
+            return None;
+        }
+        DateTime::from_timestamp_millis((seconds * 1000.0).round() as i64).map(|dt| dt.naive_utc())
+    };
+    match format {
+        NumericFormat::UnixEpoch => from_unixepoch(number),
+        NumericFormat::JulianDay => get_date_time_from_time_value_float(number),
+        // numbers in the julian day range are julian days, others are unix timestamps
+        NumericFormat::Auto if is_julian_day_value(number) => {
+            get_date_time_from_time_value_float(number)
+        }
+        NumericFormat::Auto => from_unixepoch(number),
     }
-    Ok(())
 }
 
-// sqlite resolves any ambiguity between advancing months by using the 'ceiling'
-// value, computing overflow days and advancing to the next valid date
-// e.g. 2024-01-31 + 1 month = 2024-03-02
-//
-// the modifiers 'ceiling' and 'floor' will determine behavior, so we'll need to eagerly
-// evaluate modifiers in the future to support those, and 'julianday'/'unixepoch'
-fn add_one_month(dt: &mut NaiveDateTime, step: i32) -> Result<()> {
-    let (y0, m0, d0) = (dt.year(), dt.month(), dt.day());
-    let (hh, mm, ss) = (dt.hour(), dt.minute(), dt.second());
-
-    let mut new_year = y0;
-    let mut new_month = m0 as i32 + step;
-    if new_month > 12 {
-        new_month -= 12;
-        new_year += 1;
-    } else if new_month < 1 {
-        new_month += 12;
-        new_year -= 1;
-    }
-
-    let last_day = last_day_in_month(new_year, new_month as u32);
-    if d0 <= last_day {
-        // valid date
-        *dt = NaiveDate::from_ymd_opt(new_year, new_month as u32, d0)
-            .ok_or_else(|| InvalidModifier("Invalid Auto format".to_string()))?
-            .and_hms_opt(hh, mm, ss)
-            .ok_or_else(|| InvalidModifier("Invalid Auto format".to_string()))?;
-    } else {
-        let leftover = d0 - last_day;
-        let base_date = NaiveDate::from_ymd_opt(new_year, new_month as u32, last_day)
-            .ok_or_else(|| InvalidModifier("Invalid Auto format".to_string()))?
-            .and_hms_opt(hh, mm, ss)
-            .ok_or_else(|| InvalidModifier("Invalid Auto format".to_string()))?;
+fn is_julian_day_value(value: f64) -> bool {
+    (0.0..5373484.5).contains(&value)
+}
 
-        *dt = base_date + chrono::Duration::days(leftover as i64);
-    }
-    Ok(())
+// sqlite resolves a day of month that does not exist in the target month by advancing by the
+// overflow days ('ceiling', the default), e.g. 2024-01-31 + 1 month = 2024-03-02. The 'floor'
+// modifier moves back to the last day of the target month instead, see [modify_dt].
+fn add_years_and_months(dt: &mut NaiveDateTime, years: i32, months: i32) -> Result<u32> {
+    let total_months =
+        dt.year() as i64 * 12 + dt.month0() as i64 + years as i64 * 12 + months as i64;
+    let new_year = i32::try_from(total_months.div_euclid(12))
+        .map_err(|_| InvalidModifier("Invalid date offset".to_string()))?;
+    let new_month = total_months.rem_euclid(12) as u32 + 1;
+
+    let day = dt.day();
+    let last_day = last_day_in_month(new_year, new_month);
+    let date = NaiveDate::from_ymd_opt(new_year, new_month, day.min(last_day))
+        .ok_or_else(|| InvalidModifier("Invalid date offset".to_string()))?;
+    let overflow = day.saturating_sub(last_day);
+    *dt = date.and_time(dt.time()) + TimeDelta::days(overflow as i64);
+    Ok(overflow)
 }
 
 #[inline(always)]
@@ -524,6 +552,65 @@ fn parse_modifier_number(s: &str) -> Result<i64> {
         .map_err(|_| InvalidModifier(format!("Invalid number: {}", s)))
 }
 
+/// Splits the NNN of a 'NNN days' style modifier into its whole and fractional parts,
+/// e.g. "-1.5" into (-1, -0.5).
+fn parse_modifier_amount(s: &str) -> Result<(i64, f64)> {
+    if let Ok(whole) = parse_modifier_number(s) {
+        return Ok((whole, 0.0));
+    }
+    let amount = s
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|amount| amount.is_finite() && amount.abs() < i64::MAX as f64)
+        .ok_or_else(|| InvalidModifier(format!("Invalid number: {}", s)))?;
+    Ok((amount.trunc() as i64, amount.fract()))
+}
+
+/// 'NNN days', 'NNN hours', 'NNN minutes' and 'NNN seconds'. Like SQLite, a fractional NNN
+/// shifts the time by that fraction of the unit, e.g. '+1.5 days' adds 36 hours.
+fn parse_duration_modifier(
+    s: &str,
+    whole_units: fn(i64) -> Modifier,
+    unit_seconds: i64,
+) -> Result<Modifier> {
+    let (whole, fraction) = parse_modifier_amount(s)?;
+    if fraction == 0.0 {
+        return Ok(whole_units(whole));
+    }
+    let millis = whole
+        .checked_mul(unit_seconds * 1000)
+        .and_then(|millis| {
+            millis.checked_add((fraction * (unit_seconds * 1000) as f64).round() as i64)
+        })
+        .ok_or_else(|| InvalidModifier(format!("Invalid number: {}", s)))?;
+    Ok(Modifier::TimeOffset(TimeDelta::milliseconds(millis)))
+}
+
+/// 'NNN months' and 'NNN years'. Like SQLite, the fractional part of NNN is applied as a
+/// fraction of 30 days per month and 365 days per year.
+fn parse_calendar_modifier(s: &str, years: bool) -> Result<Modifier> {
+    let (whole, fraction) = parse_modifier_amount(s)?;
+    let whole =
+        i32::try_from(whole).map_err(|_| InvalidModifier(format!("Invalid number: {}", s)))?;
+    match (years, fraction == 0.0) {
+        (false, true) => Ok(Modifier::Months(whole)),
+        (true, true) => Ok(Modifier::Years(whole)),
+        (false, false) => Ok(Modifier::DateTimeOffset {
+            years: 0,
+            months: whole,
+            days: 0,
+            seconds: (fraction * 30.0 * 86_400.0).round() as i32,
+        }),
+        (true, false) => Ok(Modifier::DateTimeOffset {
+            years: whole,
+            months: 0,
+            days: 0,
+            seconds: (fraction * 365.0 * 86_400.0).round() as i32,
+        }),
+    }
+}
+
 /// supports YYYY-MM-DD format for time shift modifiers
 fn parse_modifier_date(s: &str) -> Result<NaiveDate> {
     NaiveDate::parse_from_str(s, "%Y-%m-%d")
@@ -570,36 +657,34 @@ fn parse_modifier(modifier: &str) -> Result<Modifier> {
         "localtime" => Ok(Modifier::Localtime),
         "utc" => Ok(Modifier::Utc),
         "subsec" | "subsecond" => Ok(Modifier::Subsec),
-        s if s.ends_with(" day") => Ok(Modifier::Days(parse_modifier_number(&s[..s.len() - 4])?)),
-        s if s.ends_with(" days") => Ok(Modifier::Days(parse_modifier_number(&s[..s.len() - 5])?)),
-        s if s.ends_with(" hour") => Ok(Modifier::Hours(parse_modifier_number(&s[..s.len() - 5])?)),
+        s if s.ends_with(" day") => {
+            parse_duration_modifier(&s[..s.len() - 4], Modifier::Days, 86_400)
+        }
+        s if s.ends_with(" days") => {
+            parse_duration_modifier(&s[..s.len() - 5], Modifier::Days, 86_400)
+        }
+        s if s.ends_with(" hour") => {
+            parse_duration_modifier(&s[..s.len() - 5], Modifier::Hours, 3_600)
+        }
         s if s.ends_with(" hours") => {
-            Ok(Modifier::Hours(parse_modifier_number(&s[..s.len() - 6])?))
+            parse_duration_modifier(&s[..s.len() - 6], Modifier::Hours, 3_600)
         }
         s if s.ends_with(" minute") => {
-            Ok(Modifier::Minutes(parse_modifier_number(&s[..s.len() - 7])?))
+            parse_duration_modifier(&s[..s.len() - 7], Modifier::Minutes, 60)
         }
         s if s.ends_with(" minutes") => {
-            Ok(Modifier::Minutes(parse_modifier_number(&s[..s.len() - 8])?))
+            parse_duration_modifier(&s[..s.len() - 8], Modifier::Minutes, 60)
         }
         s if s.ends_with(" second") => {
-            Ok(Modifier::Seconds(parse_modifier_number(&s[..s.len() - 7])?))
+            parse_duration_modifier(&s[..s.len() - 7], Modifier::Seconds, 1)
         }
         s if s.ends_with(" seconds") => {
-            Ok(Modifier::Seconds(parse_modifier_number(&s[..s.len() - 8])?))
+            parse_duration_modifier(&s[..s.len() - 8], Modifier::Seconds, 1)
         }
-        s if s.ends_with(" month") => Ok(Modifier::Months(
-            parse_modifier_number(&s[..s.len() - 6])? as i32,
-        )),
-        s if s.ends_with(" months") => Ok(Modifier::Months(
-            parse_modifier_number(&s[..s.len() - 7])? as i32,
-        )),
-        s if s.ends_with(" year") => Ok(Modifier::Years(
-            parse_modifier_number(&s[..s.len() - 5])? as i32
-        )),
-        s if s.ends_with(" years") => Ok(Modifier::Years(
-            parse_modifier_number(&s[..s.len() - 6])? as i32,
-        )),
+        s if s.ends_with(" month") => parse_calendar_modifier(&s[..s.len() - 6], false),
+        s if s.ends_with(" months") => parse_calendar_modifier(&s[..s.len() - 7], false),
+        s if s.ends_with(" year") => parse_calendar_modifier(&s[..s.len() - 5], true),
+        s if s.ends_with(" years") => parse_calendar_modifier(&s[..s.len() - 6], true),
         s if s.starts_with('+') || s.starts_with('-') => {
             let sign = if s.starts_with('-') { -1 } else { 1 };
             let parts: Vec<&str> = s[1..].split(' ').collect();
@@ -1044,6 +1129,28 @@ mod tests {
         assert_eq!(parse_modifier("+6  MONTHS").unwrap(), Modifier::Months(6));
     }
 
+    #[test]
+    fn test_parse_fractional_amounts() {
+        assert_eq!(
+            parse_modifier("+1.5 days").unwrap(),
+            Modifier::TimeOffset(TimeDelta::hours(36))
+        );
+        assert_eq!(
+            parse_modifier("-0.25 hours").unwrap(),
+            Modifier::TimeOffset(TimeDelta::minutes(-15))
+        );
+        assert_eq!(
+            parse_modifier("1.5 months").unwrap(),
+            Modifier::DateTimeOffset {
+                years: 0,
+                months: 1,
+                days: 0,
+                seconds: 15 * 86_400,
+            }
+        );
+        assert!(parse_modifier("1.5.1 days").is_err());
+    }
+
     #[test]
     fn test_parse_years() {
         assert_eq!(parse_modifier("2 years").unwrap(), Modifier::Years(2));
@@ -1240,6 +1347,49 @@ mod tests {
         assert_eq!(dt, create_datetime(2023, 6, 15, 12, 30, 25));
     }
 
+    #[test]
+    fn test_apply_modifier_months_overflow() {
+        let mut dt = create_datetime(2024, 1, 31, 12, 0, 0);
+        assert_eq!(
+            apply_modifier(&mut dt, "+2 months").unwrap(),
+            ModifierEffect::MonthOverflow(0)
+        );
+        assert_eq!(dt, create_datetime(2024, 3, 31, 12, 0, 0));
+
+        let mut dt = create_datetime(2023, 1, 31, 12, 0, 0);
+        assert_eq!(
+            apply_modifier(&mut dt, "+1 month").unwrap(),
+            ModifierEffect::MonthOverflow(3)
+        );
+        assert_eq!(dt, create_datetime(2023, 3, 3, 12, 0, 0));
+
+        let mut dt = create_datetime(2023, 3, 31, 12, 0, 0);
+        apply_modifier(&mut dt, "-13 months").unwrap();
+        assert_eq!(dt, create_datetime(2022, 3, 3, 12, 0, 0));
+    }
+
+    #[test]
+    fn test_numeric_time_value_modifiers() {
+        let unix = |seconds| get_date_time_from_number(seconds, NumericFormat::UnixEpoch);
+        assert_eq!(unix(0.0), Some(create_datetime(1970, 1, 1, 0, 0, 0)));
+        assert_eq!(
+            unix(1_700_000_000.0),
+            Some(create_datetime(2023, 11, 14, 22, 13, 20))
+        );
+        assert_eq!(unix(1e13), None);
+        assert_eq!(
+            get_date_time_from_number(2_460_000.5, NumericFormat::Auto),
+            Some(create_datetime(2023, 2, 25, 0, 0, 0))
+        );
+        assert_eq!(
+            get_date_time_from_number(1_700_000_000.0, NumericFormat::Auto),
+            Some(create_datetime(2023, 11, 14, 22, 13, 20))
+        );
+
+        let mut dt = setup_datetime();
+        assert!(apply_modifier(&mut dt, "unixepoch").is_err());
+    }
+
     #[test]
     fn test_apply_modifier_time_offset() {
         let mut dt = setup_datetime();
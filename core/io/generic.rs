@@ -128,6 +128,12 @@ impl File for GenericFile {
         let file = self.file.borrow();
         Ok(file.metadata().unwrap().len())
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let file = self.file.borrow();
+        file.set_len(len)?;
+        Ok(())
+    }
 }
 
 impl Drop for GenericFile {
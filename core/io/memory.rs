@@ -176,6 +176,19 @@ impl File for MemoryFile {
     fn size(&self) -> Result<u64> {
         Ok(self.size.get() as u64)
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let len = len as usize;
+        let pages = unsafe { &mut *self.pages.get() };
+        // Drop the pages entirely past the new end and zero the tail of the last one, so that
+        // extending the file again reads back zeroes.
+        pages.retain(|&page_no, _| page_no < len.div_ceil(PAGE_SIZE));
+        if let Some(page) = pages.get_mut(&(len / PAGE_SIZE)) {
+            page[len % PAGE_SIZE..].fill(0);
+        }
+        self.size.set(len);
+        Ok(())
+    }
 }
 
 impl Drop for MemoryFile {
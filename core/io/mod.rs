@@ -1,14 +1,14 @@
+use crate::storage::buffer_pool::PoolHandle;
 use crate::Result;
 use bitflags::bitflags;
 use cfg_block::cfg_block;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{
     cell::{Cell, Ref, RefCell, RefMut},
     fmt::Debug,
-    mem::ManuallyDrop,
     pin::Pin,
-    rc::Rc,
 };
 
 pub trait File: Send + Sync {
@@ -23,6 +23,20 @@ pub trait File: Send + Sync {
     ) -> Result<Arc<Completion>>;
     fn sync(&self, c: Completion) -> Result<Arc<Completion>>;
     fn size(&self) -> Result<u64>;
+    /// Truncates or extends the file to `len` bytes.
+    fn truncate(&self, len: u64) -> Result<()>;
+    /// Hints how urgent the IO issued against this file is. The hint is advisory and
+    /// implementations are free to ignore it.
+    fn set_io_priority(&self, _priority: IoPriority) {}
+}
+
+/// Advisory priority for IO issued against a [File].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Regular foreground IO.
+    Normal,
+    /// Bulk background IO, such as checkpoints, that should yield to foreground queries.
+    Background,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -33,6 +47,7 @@ bitflags! {
         const None = 0b00000000;
         const Create = 0b0000001;
         const ReadOnly = 0b0000010;
+        const SharedCache = 0b0000100;
     }
 }
 
@@ -52,6 +67,18 @@ pub trait IO: Clock + Send + Sync {
     fn generate_random_number(&self) -> i64;
 
     fn get_memory_io(&self) -> Arc<MemoryIO>;
+
+    /// Blocks the calling thread for `duration`. Used to wait out throttling, like the checkpoint
+    /// rate limit, when there is no IO in flight that [IO::run_once] could wait for.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    /// Whether a WAL can stay in a file of its own between commits. Databases opened with an
+    /// IO that can't, such as one backed by object storage, use `journal_mode=DELETE`.
+    fn supports_wal(&self) -> bool {
+        true
+    }
 }
 
 pub type Complete = dyn Fn(Arc<RefCell<Buffer>>);
@@ -153,12 +180,14 @@ impl SyncCompletion {
 
 pub type BufferData = Pin<Vec<u8>>;
 
-pub type BufferDropFn = Rc<dyn Fn(BufferData)>;
-
-#[derive(Clone)]
+/// An IO buffer.
+///
+/// Buffers checked out of a [crate::BufferPool] carry a [PoolHandle] and go back to the
+/// pool's arena when dropped; buffers built with [Buffer::new] or [Buffer::allocate]
+/// own their memory outright and free it on drop.
 pub struct Buffer {
-    data: ManuallyDrop<BufferData>,
-    drop: BufferDropFn,
+    data: BufferData,
+    pool: Option<PoolHandle>,
 }
 
 impl Debug for Buffer {
@@ -167,22 +196,44 @@ impl Debug for Buffer {
     }
 }
 
+impl Clone for Buffer {
+    /// Clones are never pooled: the copy owns its memory, so the original slot is
+    /// returned to the pool exactly once.
+    fn clone(&self) -> Self {
+        Self::new(self.data.clone())
+    }
+}
+
 impl Drop for Buffer {
     fn drop(&mut self) {
-        let data = unsafe { ManuallyDrop::take(&mut self.data) };
-        (self.drop)(data);
+        if let Some(handle) = self.pool.take() {
+            let data = std::mem::replace(&mut self.data, Pin::new(Vec::new()));
+            handle.release(data);
+        }
     }
 }
 
 impl Buffer {
-    pub fn allocate(size: usize, drop: BufferDropFn) -> Self {
-        let data = ManuallyDrop::new(Pin::new(vec![0; size]));
-        Self { data, drop }
+    /// Allocates a zeroed buffer that is not backed by a pool.
+    pub fn allocate(size: usize) -> Self {
+        Self::new(Pin::new(vec![0; size]))
     }
 
-    pub fn new(data: BufferData, drop: BufferDropFn) -> Self {
-        let data = ManuallyDrop::new(data);
-        Self { data, drop }
+    /// Wraps caller-provided memory, e.g. bytes handed over by an extension or a
+    /// serialized database image.
+    pub fn new(data: BufferData) -> Self {
+        Self { data, pool: None }
+    }
+
+    pub(crate) fn pooled(data: BufferData, handle: PoolHandle) -> Self {
+        Self {
+            data,
+            pool: Some(handle),
+        }
+    }
+
+    pub fn is_pooled(&self) -> bool {
+        self.pool.is_some()
     }
 
     pub fn len(&self) -> usize {
@@ -255,6 +306,8 @@ mod memory;
 #[cfg(feature = "fs")]
 mod vfs;
 pub use memory::MemoryIO;
+#[cfg(feature = "fs")]
+pub use vfs::{KeyedVfsMod, VfsKey};
 pub mod clock;
 mod common;
 pub use clock::Clock;
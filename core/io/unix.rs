@@ -11,7 +11,7 @@ use rustix::{
     io::Errno,
 };
 use std::{
-    cell::{RefCell, UnsafeCell},
+    cell::{Cell, RefCell, UnsafeCell},
     mem::MaybeUninit,
 };
 use std::{
@@ -212,6 +212,7 @@ impl IO for UnixIO {
             file: Arc::new(RefCell::new(file)),
             poller: BorrowedPollHandler(self.poller.as_mut().into()),
             callbacks: BorrowedCallbacks(self.callbacks.as_mut().into()),
+            saved_io_priority: Cell::new(None),
         });
         if std::env::var(common::ENV_DISABLE_FILE_LOCK).is_err() {
             unix_file.lock_file(!flags.contains(OpenFlags::ReadOnly))?;
@@ -289,6 +290,12 @@ pub struct UnixFile<'io> {
     file: Arc<RefCell<std::fs::File>>,
     poller: BorrowedPollHandler<'io>,
     callbacks: BorrowedCallbacks<'io>,
+    /// The IO priority the thread had before [IoPriority::Background] was applied, restored
+    /// when the file goes back to [IoPriority::Normal].
+    ///
+    /// [IoPriority::Background]: super::IoPriority::Background
+    /// [IoPriority::Normal]: super::IoPriority::Normal
+    saved_io_priority: Cell<Option<i32>>,
 }
 unsafe impl Send for UnixFile<'_> {}
 unsafe impl Sync for UnixFile<'_> {}
@@ -419,14 +426,79 @@ impl File for UnixFile<'_> {
         let file = self.file.borrow();
         Ok(file.metadata()?.len())
     }
+
+    #[cfg(target_os = "linux")]
+    fn set_io_priority(&self, priority: super::IoPriority) {
+        let advice = match priority {
+            super::IoPriority::Normal => fs::Advice::Normal,
+            // Background IO streams through the file once, so ask the kernel not to keep it
+            // cached at the expense of pages used by foreground queries.
+            super::IoPriority::Background => fs::Advice::NoReuse,
+        };
+        let file = self.file.borrow();
+        // The hint is advisory, failing to apply it is not an error.
+        let _ = fs::fadvise(file.as_fd(), 0, None, advice);
+        // The reads and writes themselves are issued from the calling thread, so its IO
+        // scheduling class decides how they compete with foreground queries on other threads.
+        match priority {
+            super::IoPriority::Background => {
+                if self.saved_io_priority.get().is_none() {
+                    if let Some(previous) = ioprio::get() {
+                        if ioprio::set(ioprio::BACKGROUND) {
+                            self.saved_io_priority.set(Some(previous));
+                        }
+                    }
+                }
+            }
+            super::IoPriority::Normal => {
+                if let Some(previous) = self.saved_io_priority.take() {
+                    ioprio::set(previous);
+                }
+            }
+        }
+    }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let file = self.file.borrow();
+        file.set_len(len)?;
+        Ok(())
+    }
 }
 
 impl Drop for UnixFile<'_> {
     fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(previous) = self.saved_io_priority.take() {
+            ioprio::set(previous);
+        }
         self.unlock_file().expect("Failed to unlock file");
     }
 }
 
+/// The `ioprio_get(2)`/`ioprio_set(2)` syscalls, which libc does not wrap.
+#[cfg(target_os = "linux")]
+mod ioprio {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+
+    /// The lowest level of the best-effort class. Unlike the idle class it still gets disk time
+    /// while foreground IO keeps the device busy, so a checkpoint can't starve forever.
+    pub const BACKGROUND: libc::c_int = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7;
+
+    /// Returns the IO priority of the calling thread.
+    pub fn get() -> Option<libc::c_int> {
+        // A `who` of 0 selects the calling thread.
+        let prio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+        (prio >= 0).then_some(prio as libc::c_int)
+    }
+
+    /// Sets the IO priority of the calling thread, returning whether the kernel accepted it.
+    pub fn set(prio: libc::c_int) -> bool {
+        unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) == 0 }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
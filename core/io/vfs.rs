@@ -5,7 +5,8 @@ use crate::io::CompletionType;
 use crate::{LimboError, Result};
 use std::cell::RefCell;
 use std::ffi::{c_void, CString};
-use std::sync::Arc;
+use std::sync::atomic::{compiler_fence, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use turso_ext::{VfsFileImpl, VfsImpl};
 
 impl Clock for VfsMod {
@@ -59,6 +60,112 @@ impl IO for VfsMod {
     fn get_memory_io(&self) -> Arc<MemoryIO> {
         Arc::new(MemoryIO::new())
     }
+
+    fn supports_wal(&self) -> bool {
+        if self.ctx.is_null() {
+            return true;
+        }
+        unsafe { (*self.ctx).supports_wal }
+    }
+}
+
+/// A key a VFS opens its files with, such as the passphrase of an encrypted database.
+///
+/// The key is wiped when the database it was given for is closed, and at the latest when the
+/// last [KeyedVfsMod] holding it is dropped.
+pub struct VfsKey(Mutex<Vec<u8>>);
+
+impl VfsKey {
+    pub fn new(key: &[u8]) -> Self {
+        Self(Mutex::new(key.to_vec()))
+    }
+
+    /// Overwrites the key with zeroes. Files can't be opened with it anymore afterwards.
+    pub fn wipe(&self) {
+        let mut key = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        for byte in key.iter_mut() {
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+        key.clear();
+    }
+}
+
+impl Drop for VfsKey {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+/// A VFS module that opens every file with the same key, through
+/// [turso_ext::VfsExtension::open_file_with_key].
+pub struct KeyedVfsMod {
+    vfs: Arc<VfsMod>,
+    key: Arc<VfsKey>,
+}
+
+impl KeyedVfsMod {
+    pub fn new(vfs: Arc<VfsMod>, key: Arc<VfsKey>) -> Self {
+        Self { vfs, key }
+    }
+}
+
+impl Clock for KeyedVfsMod {
+    fn now(&self) -> Instant {
+        self.vfs.now()
+    }
+}
+
+impl IO for KeyedVfsMod {
+    fn open_file(&self, path: &str, flags: OpenFlags, direct: bool) -> Result<Arc<dyn File>> {
+        let c_path = CString::new(path).map_err(|_| {
+            LimboError::ExtensionError("Failed to convert path to CString".to_string())
+        })?;
+        let key = self.key.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if key.is_empty() {
+            return Err(LimboError::ExtensionError(
+                "the key of the VFS was wiped".to_string(),
+            ));
+        }
+        let ctx = self.vfs.ctx as *mut c_void;
+        let vfs = unsafe { &*self.vfs.ctx };
+        let file = unsafe {
+            (vfs.open_with_key)(
+                ctx,
+                c_path.as_ptr(),
+                flags.0,
+                direct,
+                key.as_ptr(),
+                key.len(),
+            )
+        };
+        if file.is_null() {
+            return Err(LimboError::ExtensionError(format!(
+                "unable to open {path} with the given key"
+            )));
+        }
+        Ok(Arc::new(turso_ext::VfsFileImpl::new(file, self.vfs.ctx)?))
+    }
+
+    fn run_once(&self) -> Result<()> {
+        self.vfs.run_once()
+    }
+
+    fn wait_for_completion(&self, c: Arc<Completion>) -> Result<()> {
+        self.vfs.wait_for_completion(c)
+    }
+
+    fn generate_random_number(&self) -> i64 {
+        self.vfs.generate_random_number()
+    }
+
+    fn get_memory_io(&self) -> Arc<MemoryIO> {
+        self.vfs.get_memory_io()
+    }
+
+    fn supports_wal(&self) -> bool {
+        self.vfs.supports_wal()
+    }
 }
 
 impl VfsMod {
@@ -166,6 +273,16 @@ impl File for VfsFileImpl {
             Ok(result as u64)
         }
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let vfs = unsafe { &*self.vfs };
+        let result = unsafe { (vfs.truncate)(self.file, len as i64) };
+        if !result.is_ok() {
+            Err(LimboError::ExtensionError("truncate failed".to_string()))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Drop for VfsMod {
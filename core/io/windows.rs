@@ -120,4 +120,10 @@ impl File for WindowsFile {
         let file = self.file.borrow();
         Ok(file.metadata().unwrap().len())
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let file = self.file.borrow();
+        file.set_len(len)?;
+        Ok(())
+    }
 }
@@ -2171,6 +2171,46 @@ impl Jsonb {
         Ok(element_type)
     }
 
+    /// Returns the offsets of the direct children of the container at `pos`
+    /// as `(key_offset, value_offset)` pairs. Keys are only present for objects;
+    /// scalars have no children.
+    pub fn children_at(&self, pos: usize) -> Result<Vec<(Option<usize>, usize)>> {
+        let (header, header_skip) = self.read_header(pos)?;
+        let end = pos + header_skip + header.1;
+        let mut cursor = pos + header_skip;
+        let mut children = Vec::new();
+        match header.0 {
+            ElementType::ARRAY => {
+                while cursor < end {
+                    children.push((None, cursor));
+                    cursor = self.skip_element(cursor)?;
+                }
+            }
+            ElementType::OBJECT => {
+                while cursor < end {
+                    let value = self.skip_element(cursor)?;
+                    if value >= end {
+                        bail_parse_error!("malformed JSON");
+                    }
+                    children.push((Some(cursor), value));
+                    cursor = self.skip_element(value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(children)
+    }
+
+    /// Copies the element at `pos` into a standalone JSONB value.
+    pub fn element_at(&self, pos: usize) -> Result<(Jsonb, ElementType)> {
+        let (header, _) = self.read_header(pos)?;
+        let end = self.skip_element(pos)?;
+        match self.data.get(pos..end) {
+            Some(data) => Ok((Jsonb::from_raw_data(data), header.0)),
+            None => bail_parse_error!("malformed JSON"),
+        }
+    }
+
     pub fn array_len(&self) -> Result<usize> {
         let (header, header_skip) = self.read_header(0)?;
         if header.0 != ElementType::ARRAY {
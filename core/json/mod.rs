@@ -3,6 +3,7 @@ mod error;
 mod jsonb;
 mod ops;
 mod path;
+mod vtab;
 
 use crate::json::error::Error as JsonError;
 pub use crate::json::ops::{
@@ -17,6 +18,7 @@ pub use cache::JsonCacheCell;
 use jsonb::{ElementType, Jsonb, JsonbHeader, PathOperationMode, SearchOperation, SetOperation};
 use std::borrow::Cow;
 use std::str::FromStr;
+pub(crate) use vtab::{JsonVirtualTable, JsonVirtualTableCursor};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Conv {
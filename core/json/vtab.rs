@@ -0,0 +1,254 @@
+use super::jsonb::{ElementType, Jsonb, PathOperationMode};
+use super::path::PathElement;
+use super::{convert_dbtype_to_jsonb, json_path_from_owned_value, json_string_to_db_type};
+use super::{Conv, OutputVariant};
+use crate::types::Text;
+use crate::{LimboError, Value};
+
+const JSON_VTAB_SCHEMA: &str =
+    "CREATE TABLE x(key, value, type, atom, id, parent, fullkey, path, json HIDDEN, root HIDDEN)";
+
+/// Table-valued functions `json_each(json [, root])` and `json_tree(json [, root])`.
+///
+/// `json_each` walks the immediate children of the root element, while
+/// `json_tree` walks the root element and all of its descendants in pre-order.
+#[derive(Debug, Clone)]
+pub(crate) struct JsonVirtualTable {
+    name: String,
+    recursive: bool,
+}
+
+impl JsonVirtualTable {
+    pub(crate) fn create(name: &str) -> Option<(Self, String)> {
+        let recursive = match name {
+            "json_each" => false,
+            "json_tree" => true,
+            _ => return None,
+        };
+        Some((
+            JsonVirtualTable {
+                name: name.to_owned(),
+                recursive,
+            },
+            JSON_VTAB_SCHEMA.to_string(),
+        ))
+    }
+
+    pub(crate) fn open(&self) -> crate::Result<JsonVirtualTableCursor> {
+        Ok(JsonVirtualTableCursor {
+            name: self.name.clone(),
+            recursive: self.recursive,
+            rows: Vec::new(),
+            pos: 0,
+            args: Vec::new(),
+        })
+    }
+}
+
+struct JsonRow {
+    key: Value,
+    value: Value,
+    element_type: ElementType,
+    id: i64,
+    parent: Option<i64>,
+    fullkey: String,
+    path: String,
+}
+
+pub struct JsonVirtualTableCursor {
+    name: String,
+    recursive: bool,
+    rows: Vec<JsonRow>,
+    pos: usize,
+    args: Vec<Value>,
+}
+
+impl JsonVirtualTableCursor {
+    pub(crate) fn rowid(&self) -> i64 {
+        self.pos as i64
+    }
+
+    pub(crate) fn next(&mut self) -> crate::Result<bool> {
+        self.pos += 1;
+        Ok(self.pos < self.rows.len())
+    }
+
+    pub(crate) fn column(&self, idx: usize) -> crate::Result<Value> {
+        if idx >= 8 {
+            return Ok(self.args.get(idx - 8).cloned().unwrap_or(Value::Null));
+        }
+        let row = self
+            .rows
+            .get(self.pos)
+            .ok_or_else(|| LimboError::InternalError("No row available".into()))?;
+        let value = match idx {
+            0 => row.key.clone(),
+            1 => row.value.clone(),
+            2 => Value::from_text(&String::from(row.element_type)),
+            3 => match row.element_type {
+                ElementType::ARRAY | ElementType::OBJECT => Value::Null,
+                _ => row.value.clone(),
+            },
+            4 => Value::Integer(row.id),
+            5 => row.parent.map_or(Value::Null, Value::Integer),
+            6 => Value::from_text(&row.fullkey),
+            7 => Value::from_text(&row.path),
+            _ => Value::Null,
+        };
+        Ok(value)
+    }
+
+    pub(crate) fn filter(&mut self, args: Vec<Value>) -> crate::Result<bool> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(LimboError::ParseError(format!(
+                "wrong number of arguments to function {}()",
+                self.name
+            )));
+        }
+        self.rows.clear();
+        self.pos = 0;
+        self.args = args;
+
+        if let Value::Null = self.args[0] {
+            return Ok(false);
+        }
+        let mut json = convert_dbtype_to_jsonb(&self.args[0], Conv::Strict)?;
+        json.is_valid()?;
+
+        let (root_pos, root_key, root_fullkey, root_path) = match self.args.get(1) {
+            None => (0, Value::Null, "$".to_string(), "$".to_string()),
+            Some(root) => {
+                let Some(path) = json_path_from_owned_value(root, true)? else {
+                    return Ok(false);
+                };
+                let root_pos = if path.elements.len() > 1 {
+                    match json.navigate_path(&path, PathOperationMode::ReplaceExisting) {
+                        Ok(mut stack) => {
+                            let target = stack.pop().expect("Should exist");
+                            target.get_array_index().unwrap_or(target.field_value_index)
+                        }
+                        Err(_) => return Ok(false),
+                    }
+                } else {
+                    0
+                };
+                let elements = &path.elements;
+                let root_key = match elements.last() {
+                    Some(PathElement::Key(key, _)) => Value::from_text(key),
+                    Some(PathElement::ArrayLocator(Some(idx))) if *idx >= 0 => {
+                        Value::Integer(*idx as i64)
+                    }
+                    _ => Value::Null,
+                };
+                let root_path = format_path(&elements[..elements.len().saturating_sub(1)]);
+                (root_pos, root_key, format_path(elements), root_path)
+            }
+        };
+
+        let (root, element_type) = json.element_at(root_pos)?;
+        let is_container = matches!(element_type, ElementType::ARRAY | ElementType::OBJECT);
+        if self.recursive || !is_container {
+            self.rows.push(JsonRow {
+                key: root_key,
+                value: element_value(root, element_type)?,
+                element_type,
+                id: root_pos as i64,
+                parent: None,
+                fullkey: root_fullkey.clone(),
+                path: root_path,
+            });
+        }
+        if is_container {
+            let parent = self.recursive.then_some(root_pos as i64);
+            self.push_children(&json, root_pos, parent, &root_fullkey)?;
+        }
+
+        Ok(!self.rows.is_empty())
+    }
+
+    fn push_children(
+        &mut self,
+        json: &Jsonb,
+        pos: usize,
+        parent: Option<i64>,
+        fullkey: &str,
+    ) -> crate::Result<()> {
+        for (idx, (key_pos, value_pos)) in json.children_at(pos)?.into_iter().enumerate() {
+            let (key, child_fullkey, id) = match key_pos {
+                Some(key_pos) => {
+                    let key = key_text(json, key_pos)?;
+                    let child_fullkey = format!("{fullkey}.{}", quote_key(&key));
+                    (Value::from_text(&key), child_fullkey, key_pos)
+                }
+                None => (
+                    Value::Integer(idx as i64),
+                    format!("{fullkey}[{idx}]"),
+                    value_pos,
+                ),
+            };
+            let (value, element_type) = json.element_at(value_pos)?;
+            self.rows.push(JsonRow {
+                key,
+                value: element_value(value, element_type)?,
+                element_type,
+                id: id as i64,
+                parent,
+                fullkey: child_fullkey.clone(),
+                path: fullkey.to_string(),
+            });
+            if self.recursive && matches!(element_type, ElementType::ARRAY | ElementType::OBJECT) {
+                self.push_children(json, value_pos, Some(id as i64), &child_fullkey)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn element_value(json: Jsonb, element_type: ElementType) -> crate::Result<Value> {
+    let value = json_string_to_db_type(json, element_type, OutputVariant::ElementType)?;
+    match (element_type, value) {
+        (ElementType::ARRAY | ElementType::OBJECT, value) => Ok(value),
+        (_, Value::Text(text)) => Ok(Value::Text(Text::new(text.as_str()))),
+        (_, value) => Ok(value),
+    }
+}
+
+fn key_text(json: &Jsonb, pos: usize) -> crate::Result<String> {
+    let (key, element_type) = json.element_at(pos)?;
+    match json_string_to_db_type(key, element_type, OutputVariant::ElementType)? {
+        Value::Text(text) => Ok(text.as_str().to_string()),
+        _ => Err(LimboError::ParseError("malformed JSON".to_string())),
+    }
+}
+
+/// Object labels are appended as `.key` when they look like identifiers and as
+/// `."key"` otherwise, matching the `fullkey` column produced by SQLite.
+fn quote_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric());
+    if is_identifier {
+        key.to_string()
+    } else {
+        format!("\"{key}\"")
+    }
+}
+
+fn format_path(elements: &[PathElement]) -> String {
+    let mut path = String::from("$");
+    for element in elements {
+        match element {
+            PathElement::Root() => {}
+            PathElement::Key(key, _) => {
+                path.push('.');
+                path.push_str(&quote_key(key));
+            }
+            PathElement::ArrayLocator(Some(idx)) if *idx < 0 => {
+                path.push_str(&format!("[#{idx}]"));
+            }
+            PathElement::ArrayLocator(Some(idx)) => path.push_str(&format!("[{idx}]")),
+            PathElement::ArrayLocator(None) => path.push_str("[#]"),
+        }
+    }
+    path
+}
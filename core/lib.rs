@@ -1,6 +1,8 @@
 #![allow(clippy::arc_with_non_send_sync)]
 
 mod assert;
+mod backup;
+mod dbstat;
 mod error;
 mod ext;
 mod fast_lock;
@@ -14,6 +16,9 @@ pub mod mvcc;
 mod parameters;
 mod pragma;
 mod pseudo;
+mod read_connection;
+#[cfg(feature = "remote")]
+mod remote;
 pub mod result;
 mod schema;
 #[cfg(feature = "series")]
@@ -41,12 +46,16 @@ mod numeric;
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
+use crate::result::LimboResult;
 use crate::storage::{header_accessor, wal::DummyWAL};
-use crate::translate::optimizer::optimize_plan;
+use crate::types::CursorResult;
 use crate::util::{OpenMode, OpenOptions};
 use crate::vtab::VirtualTable;
+pub use backup::{BackupHandle, BackupStatus};
 use core::str;
 pub use error::LimboError;
+#[cfg(feature = "fs")]
+pub use ext::register_static_vfs;
 use fallible_iterator::FallibleIterator;
 pub use io::clock::{Clock, Instant};
 #[cfg(all(feature = "fs", target_family = "unix"))]
@@ -54,58 +63,247 @@ pub use io::UnixIO;
 #[cfg(all(feature = "fs", target_os = "linux", feature = "io_uring"))]
 pub use io::UringIO;
 pub use io::{
-    Buffer, Completion, CompletionType, File, MemoryIO, OpenFlags, PlatformIO, SyscallIO,
-    WriteCompletion, IO,
+    Buffer, Completion, CompletionType, File, IoPriority, MemoryIO, OpenFlags, PlatformIO,
+    SyscallIO, WriteCompletion, IO,
 };
+#[cfg(feature = "fs")]
+use io::{KeyedVfsMod, VfsKey};
 use parking_lot::RwLock;
+pub use read_connection::ReadConnection;
+#[cfg(feature = "remote")]
+pub use remote::{RemoteConnection, RemoteStatement};
 use schema::Schema;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
 use std::{
     borrow::Cow,
     cell::{Cell, RefCell, UnsafeCell},
     collections::HashMap,
     fmt::Display,
-    io::Write,
     num::NonZero,
     ops::Deref,
     rc::Rc,
-    sync::Arc,
+    sync::{Arc, Weak},
+    time::Duration,
 };
+use storage::checksum::CorruptionCallback;
 #[cfg(feature = "fs")]
 use storage::database::DatabaseFile;
-use storage::page_cache::DumbLruPageCache;
+use storage::page_cache::PageCache;
 pub use storage::pager::PagerCacheflushStatus;
 use storage::pager::{DB_STATE_INITIALIZED, DB_STATE_UNITIALIZED};
 pub use storage::{
     buffer_pool::BufferPool,
-    database::DatabaseStorage,
+    checksum::ChecksumMismatch,
+    database::{BytesStorage, DatabaseStorage},
     pager::PageRef,
-    pager::{Page, Pager},
-    wal::{CheckpointMode, CheckpointResult, CheckpointStatus, Wal, WalFile, WalFileShared},
+    pager::{
+        Page, PageAllocationStats, PageUsage, Pager, SynchronousMode, LOCALITY_HISTOGRAM_BOUNDS,
+    },
+    wal::{
+        CheckpointMode, CheckpointResult, CheckpointStatus, RecoveryReport, Wal, WalFile,
+        WalFileShared,
+    },
 };
 use tracing::{instrument, Level};
-use translate::select::prepare_select_plan;
-use turso_sqlite3_parser::{ast, ast::Cmd, lexer::sql::Parser};
+use translate::query_shape::QueryShapeCache;
+pub use translate::query_shape::QueryShapeCacheStats;
+use translate::schema::SQLITE_STAT1_TABLE;
+use turso_sqlite3_parser::{
+    ast,
+    ast::Cmd,
+    dialect::TokenType,
+    lexer::{
+        sql::{Parser, Tokenizer},
+        Scanner,
+    },
+};
 pub use types::RefValue;
 pub use types::Value;
-use util::parse_schema_rows;
+pub use util::unquote_ident;
+use util::{parse_schema_rows, parse_stat1_rows};
 use vdbe::builder::QueryMode;
-use vdbe::builder::TableRefIdCounter;
 
 pub type Result<T, E = LimboError> = std::result::Result<T, E>;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum TransactionState {
-    Write { change_schema: bool },
+    /// `exclusive` is set for `BEGIN EXCLUSIVE`, which also keeps other connections from
+    /// reading.
+    Write {
+        change_schema: bool,
+        exclusive: bool,
+    },
     Read,
     None,
 }
 
+/// Kind of transaction a connection has open, see [Connection::transaction_kind].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransactionKind {
+    /// Holds a read snapshot, e.g. after reading in a deferred transaction.
+    Read,
+    /// Holds the write lock, e.g. after `BEGIN IMMEDIATE` or writing in a deferred transaction.
+    Write,
+    /// Holds the write lock and keeps other connections from reading, after `BEGIN EXCLUSIVE`.
+    Exclusive,
+}
+
+/// Kind of row change reported to the callback of [Connection::set_update_hook].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UpdateType {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Callback of [Connection::set_update_hook], called with the kind of change, the database
+/// name, the table name and the rowid of the changed row.
+pub type UpdateHook = Box<dyn Fn(UpdateType, &str, &str, i64)>;
+
+/// Callback of [Connection::set_commit_hook]; returning false turns the commit into a rollback.
+pub type CommitHook = Box<dyn Fn() -> bool>;
+
+/// Callback of [Connection::set_rollback_hook].
+pub type RollbackHook = Box<dyn Fn()>;
+
+/// Callback of [Connection::set_trace_hook], called with the SQL text of a statement and the
+/// wall-clock time from its first step until it completed.
+pub type TraceHook = Box<dyn Fn(&str, Duration)>;
+
+/// Callback of [Connection::set_profile_hook], called with the SQL text of a statement and the
+/// nanoseconds of CPU time spent executing it.
+pub type ProfileHook = Box<dyn Fn(&str, u64)>;
+
 pub(crate) type MvStore = mvcc::MvStore<mvcc::LocalClock>;
 
 pub(crate) type MvCursor = mvcc::cursor::ScanCursor<mvcc::LocalClock>;
 
+/// Options for [Database::close].
+#[derive(Debug, Clone, Copy)]
+pub struct CloseOptions {
+    /// Close connections and statements that are still open instead of failing.
+    pub force: bool,
+    /// Checkpoint the whole WAL into the database file before closing.
+    pub checkpoint: bool,
+}
+
+impl Default for CloseOptions {
+    fn default() -> Self {
+        Self {
+            force: false,
+            checkpoint: true,
+        }
+    }
+}
+
+/// Settings a [Database] is opened with through a [DatabaseBuilder].
+#[derive(Clone, Copy, Debug, Default)]
+struct DatabaseOpts {
+    cache_size: Option<usize>,
+    page_size: Option<usize>,
+}
+
+impl DatabaseOpts {
+    fn page_cache(&self) -> PageCache {
+        match self.cache_size {
+            Some(pages) => PageCache::new(pages),
+            None => PageCache::default(),
+        }
+    }
+}
+
+/// Opens a [Database] with settings other than the defaults.
+///
+/// ```ignore
+/// let db = DatabaseBuilder::new(io, "app.db")
+///     .cache_size(10_000)
+///     .page_size(8192)
+///     .build()?;
+/// ```
+#[cfg(feature = "fs")]
+pub struct DatabaseBuilder {
+    io: Arc<dyn IO>,
+    path: String,
+    flags: OpenFlags,
+    enable_mvcc: bool,
+    enable_indexes: bool,
+    opts: DatabaseOpts,
+}
+
+#[cfg(feature = "fs")]
+impl DatabaseBuilder {
+    pub fn new(io: Arc<dyn IO>, path: &str) -> Self {
+        Self {
+            io,
+            path: path.to_string(),
+            flags: OpenFlags::default(),
+            enable_mvcc: false,
+            enable_indexes: false,
+            opts: DatabaseOpts::default(),
+        }
+    }
+
+    pub fn flags(mut self, flags: OpenFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn enable_mvcc(mut self, enable_mvcc: bool) -> Self {
+        self.enable_mvcc = enable_mvcc;
+        self
+    }
+
+    pub fn enable_indexes(mut self, enable_indexes: bool) -> Self {
+        self.enable_indexes = enable_indexes;
+        self
+    }
+
+    /// Number of pages the page cache of each connection, and the buffer pool backing it,
+    /// hold, instead of the default 2000. Like `PRAGMA cache_size`, connections can still
+    /// change it, and [Connection::reset_session] goes back to this size.
+    pub fn cache_size(mut self, pages: usize) -> Self {
+        self.opts.cache_size = Some(pages.max(storage::sqlite3_ondisk::MIN_PAGE_CACHE_SIZE));
+        self
+    }
+
+    /// Page size of the database if it's created, instead of the default 4096 bytes. The
+    /// page size of an existing database is the one in its header.
+    pub fn page_size(mut self, bytes: usize) -> Self {
+        self.opts.page_size = Some(bytes);
+        self
+    }
+
+    pub fn build(self) -> Result<Arc<Database>> {
+        if let Some(page_size) = self.opts.page_size {
+            let valid = (storage::sqlite3_ondisk::MIN_PAGE_SIZE as usize
+                ..=storage::sqlite3_ondisk::MAX_PAGE_SIZE as usize)
+                .contains(&page_size)
+                && page_size.is_power_of_two();
+            if !valid {
+                return Err(LimboError::InvalidArgument(format!(
+                    "invalid page size {page_size}: must be a power of two between 512 and 65536"
+                )));
+            }
+        }
+        let file = self.io.open_file(&self.path, self.flags, true)?;
+        let db_file = Arc::new(DatabaseFile::new(file));
+        Database::open_with_opts(
+            self.io,
+            &self.path,
+            db_file,
+            self.flags,
+            self.enable_mvcc,
+            self.enable_indexes,
+            self.opts,
+        )
+    }
+}
+
+/// The databases opened with `cache=shared` that are still alive, see [Database::open_uri].
+#[cfg(feature = "fs")]
+static SHARED_CACHE_DATABASES: OnceLock<Mutex<HashMap<String, Weak<Database>>>> = OnceLock::new();
+
 pub struct Database {
     mv_store: Option<Rc<MvStore>>,
     schema: Arc<RwLock<Schema>>,
@@ -114,11 +312,22 @@ pub struct Database {
     io: Arc<dyn IO>,
     // Shared structures of a Database are the parts that are common to multiple threads that might
     // create DB connections.
-    _shared_page_cache: Arc<RwLock<DumbLruPageCache>>,
+    shared_page_cache: Arc<RwLock<PageCache>>,
     maybe_shared_wal: RwLock<Option<Arc<UnsafeCell<WalFileShared>>>>,
     is_empty: Arc<AtomicUsize>,
     init_lock: Arc<Mutex<()>>,
     open_flags: OpenFlags,
+    corruption_callback: Arc<RwLock<Option<CorruptionCallback>>>,
+    page_quota: Arc<AtomicU32>,
+    recovery_report: RecoveryReport,
+    /// The state of the connections handed out by [Database::connect] and
+    /// [Database::connect_read], used to find the ones still open on close.
+    connections: Mutex<Vec<Weak<ConnectionCloseState>>>,
+    closed: AtomicBool,
+    opts: DatabaseOpts,
+    /// The key the files of the database were opened with, wiped when it is closed.
+    #[cfg(feature = "fs")]
+    vfs_key: Mutex<Option<Arc<VfsKey>>>,
 }
 
 unsafe impl Send for Database {}
@@ -148,6 +357,139 @@ impl Database {
         Self::open_with_flags(io, path, db_file, flags, enable_mvcc, enable_indexes)
     }
 
+    /// Opens a database from a SQLite URI filename such as
+    /// `file:data.db?mode=ro&cache=shared`. Strings without the `file:` scheme are
+    /// opened as plain paths.
+    ///
+    /// Supported query parameters are `mode` (`ro`, `rw`, `rwc` or `memory`), `cache`
+    /// (`shared` or `private`), `vfs`, `modeof`, `immutable`, `_journal_mode`, which
+    /// only accepts `wal`, and `key`, which is handed to the VFS when it opens the database and
+    /// its WAL, such as the passphrase of the `encrypted` VFS of the encryption extension. That
+    /// VFS is used when a key is given without `vfs`, and the extension providing it must be
+    /// loaded or registered with [register_static_vfs] first. The key is wiped when the database
+    /// is closed. Use [Database::io] to drive the I/O of the returned database.
+    ///
+    /// With `cache=shared`, opening a database that is already open with `cache=shared` and the
+    /// same mode returns the same [Database], like SQLite's shared-cache mode. This also lets
+    /// `file:name?mode=memory&cache=shared` open the same in-memory database more than once.
+    /// `_journal_mode=wal` is accepted for compatibility; other journal modes are set with
+    /// `PRAGMA journal_mode`.
+    #[cfg(feature = "fs")]
+    pub fn open_uri(uri: &str) -> Result<Arc<Database>> {
+        let opts = OpenOptions::parse(uri)?;
+        let (_, db) = Self::open_with_options(&opts, false, false)?;
+        Ok(db)
+    }
+
+    #[cfg(feature = "fs")]
+    fn open_with_options(
+        opts: &OpenOptions,
+        use_indexes: bool,
+        mvcc: bool,
+    ) -> Result<(Arc<dyn IO>, Arc<Database>)> {
+        let flags = opts.get_flags()?;
+        if flags.contains(OpenFlags::SharedCache) && opts.key.is_some() {
+            // A shared database would be handed out without checking the key.
+            return Err(LimboError::InvalidArgument(
+                "cache=shared can't be used with a key".to_string(),
+            ));
+        }
+        if !flags.contains(OpenFlags::SharedCache) {
+            return Self::open_with_options_uncached(opts, flags, use_indexes, mvcc);
+        }
+        // With `cache=shared`, every open of the same database in the process returns the same
+        // Database, so that their connections share its page cache, WAL and schema.
+        let key = if opts.path == util::MEMORY_PATH || matches!(opts.mode, OpenMode::Memory) {
+            format!("memory:{}", opts.path)
+        } else {
+            let path = std::fs::canonicalize(&opts.path)
+                .map_or_else(|_| opts.path.clone(), |p| p.to_string_lossy().into_owned());
+            format!("file:{path}")
+        };
+        let mut databases = SHARED_CACHE_DATABASES
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        databases.retain(|_, db| db.strong_count() > 0);
+        if let Some(db) = databases.get(&key).and_then(Weak::upgrade) {
+            if db.open_flags == flags && !db.closed.load(Ordering::Acquire) {
+                return Ok((db.io(), db));
+            }
+        }
+        let (io, db) = Self::open_with_options_uncached(opts, flags, use_indexes, mvcc)?;
+        databases.insert(key, Arc::downgrade(&db));
+        Ok((io, db))
+    }
+
+    #[cfg(feature = "fs")]
+    fn open_with_options_uncached(
+        opts: &OpenOptions,
+        flags: OpenFlags,
+        use_indexes: bool,
+        mvcc: bool,
+    ) -> Result<(Arc<dyn IO>, Arc<Database>)> {
+        use crate::util::MEMORY_PATH;
+        if opts.path == MEMORY_PATH || matches!(opts.mode, OpenMode::Memory) {
+            let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+            let db = Self::open_file_with_flags(io.clone(), MEMORY_PATH, flags, mvcc, use_indexes)?;
+            return Ok((io, db));
+        }
+        let (io, db) = match &opts.key {
+            None => Self::open_new(&opts.path, opts.vfs.as_deref(), flags, use_indexes, mvcc)?,
+            Some(key) => {
+                // The key only reaches the VFS when it opens the database and its WAL, and is
+                // wiped when the database is closed.
+                let vfs_name = opts.vfs.as_deref().unwrap_or("encrypted");
+                let Some((_, vfs)) = ext::get_vfs_modules()
+                    .into_iter()
+                    .find(|(name, _)| name == vfs_name)
+                else {
+                    return Err(LimboError::InvalidArgument(format!(
+                        "no such VFS: {vfs_name}, load the extension providing it first"
+                    )));
+                };
+                let key = Arc::new(VfsKey::new(key.as_bytes()));
+                let io: Arc<dyn IO> = Arc::new(KeyedVfsMod::new(vfs, key.clone()));
+                let db =
+                    Self::open_file_with_flags(io.clone(), &opts.path, flags, mvcc, use_indexes)?;
+                *db.vfs_key.lock().unwrap() = Some(key);
+                (io, db)
+            }
+        };
+        if let Some(modeof) = &opts.modeof {
+            let perms = std::fs::metadata(modeof)?;
+            std::fs::set_permissions(&opts.path, perms.permissions())?;
+        }
+        Ok((io, db))
+    }
+
+    /// Opens an existing database file for reading only.
+    ///
+    /// The file is never created or initialized, and connections to the returned
+    /// database refuse to prepare statements that would write, failing with
+    /// [LimboError::ReadOnly].
+    #[cfg(feature = "fs")]
+    pub fn open_readonly(io: Arc<dyn IO>, path: &str) -> Result<Arc<Database>> {
+        Self::open_file_with_flags(io, path, OpenFlags::ReadOnly, false, false)
+    }
+
+    /// Opens the database held in `bytes`, such as a `&'static [u8]` embedded in the binary
+    /// with `include_bytes!` or an `Arc<[u8]>` received over the network, for reading only.
+    /// Pages are read straight out of `bytes` rather than copied into an in-memory file
+    /// first, and no WAL is used.
+    ///
+    /// Fails with [LimboError::NotADB] if `bytes` doesn't start with a database header.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn open_readonly_bytes<B: AsRef<[u8]> + Send + Sync + 'static>(
+        bytes: B,
+    ) -> Result<Arc<Database>> {
+        let db_file = Arc::new(BytesStorage::new(bytes)?);
+        // Nothing is ever read from or written to it, but connections still need an I/O
+        // backend to drive.
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        Self::open_with_flags(io, ":memory:", db_file, OpenFlags::ReadOnly, false, false)
+    }
+
     #[allow(clippy::arc_with_non_send_sync)]
     pub fn open(
         io: Arc<dyn IO>,
@@ -174,6 +516,27 @@ impl Database {
         flags: OpenFlags,
         enable_mvcc: bool,
         enable_indexes: bool,
+    ) -> Result<Arc<Database>> {
+        Self::open_with_opts(
+            io,
+            path,
+            db_file,
+            flags,
+            enable_mvcc,
+            enable_indexes,
+            DatabaseOpts::default(),
+        )
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn open_with_opts(
+        io: Arc<dyn IO>,
+        path: &str,
+        db_file: Arc<dyn DatabaseStorage>,
+        flags: OpenFlags,
+        enable_mvcc: bool,
+        enable_indexes: bool,
+        opts: DatabaseOpts,
     ) -> Result<Arc<Database>> {
         let wal_path = format!("{}-wal", path);
         let maybe_shared_wal = WalFileShared::open_shared_if_exists(&io, wal_path.as_str())?;
@@ -191,25 +554,49 @@ impl Database {
             unsafe { &*wal.get() }.max_frame.load(Ordering::SeqCst) > 0
         });
 
+        let recovery_report = maybe_shared_wal
+            .as_ref()
+            .map_or_else(RecoveryReport::default, |wal| {
+                unsafe { &*wal.get() }.recovery_report.clone()
+            });
+        if recovery_report.dirty_shutdown() {
+            tracing::info!(
+                "recovered WAL of {} after dirty shutdown: {} frames scanned, {} applied, {} discarded, tail truncated at {:?}",
+                path,
+                recovery_report.frames_scanned,
+                recovery_report.frames_applied,
+                recovery_report.frames_discarded,
+                recovery_report.truncated_tail_offset
+            );
+        }
+
         let is_empty = if db_size == 0 && !wal_has_frames {
             DB_STATE_UNITIALIZED
         } else {
             DB_STATE_INITIALIZED
         };
 
-        let shared_page_cache = Arc::new(RwLock::new(DumbLruPageCache::default()));
+        let shared_page_cache = Arc::new(RwLock::new(opts.page_cache()));
         let schema = Arc::new(RwLock::new(Schema::new(enable_indexes)));
         let db = Database {
             mv_store,
             path: path.to_string(),
             schema: schema.clone(),
-            _shared_page_cache: shared_page_cache.clone(),
+            shared_page_cache: shared_page_cache.clone(),
             maybe_shared_wal: RwLock::new(maybe_shared_wal),
             db_file,
             io: io.clone(),
             open_flags: flags,
             is_empty: Arc::new(AtomicUsize::new(is_empty)),
             init_lock: Arc::new(Mutex::new(())),
+            corruption_callback: Arc::new(RwLock::new(None)),
+            page_quota: Arc::new(AtomicU32::new(0)),
+            recovery_report,
+            connections: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+            opts,
+            #[cfg(feature = "fs")]
+            vfs_key: Mutex::new(None),
         };
         let db = Arc::new(db);
 
@@ -220,23 +607,47 @@ impl Database {
             let schema_version = get_schema_version(&conn, &io)?;
             schema.write().schema_version = schema_version;
             let rows = conn.query("SELECT * FROM sqlite_schema")?;
-            let mut schema = schema
-                .try_write()
-                .expect("lock on schema should succeed first try");
+            // Stepping the statement reads the database schema, so it can't be locked for
+            // writing until the rows are parsed.
+            let mut new_schema = schema.read().clone();
             let syms = conn.syms.borrow();
             if let Err(LimboError::ExtensionError(e)) =
-                parse_schema_rows(rows, &mut schema, io, &syms, None)
+                parse_schema_rows(rows, &mut new_schema, io, &syms, None)
             {
                 // this means that a vtab exists and we no longer have the module loaded. we print
                 // a warning to the user to load the module
                 eprintln!("Warning: {}", e);
             }
+            drop(syms);
+            *schema
+                .try_write()
+                .expect("lock on schema should succeed first try") = new_schema;
+
+            // sqlite_stat1 can only be queried by a connection that knows about it.
+            if db.schema.read().get_table(SQLITE_STAT1_TABLE).is_some() {
+                let conn = db.connect()?;
+                conn.load_stats()?;
+                db.schema.write().stats = conn.schema.borrow().stats.clone();
+            }
         }
         Ok(db)
     }
 
     pub fn connect(self: &Arc<Database>) -> Result<Arc<Connection>> {
+        let conn = self.open_connection()?;
+        self.register_connection(&conn);
+        Ok(conn)
+    }
+
+    /// Opens a connection that [Database::close] does not know about.
+    fn open_connection(self: &Arc<Database>) -> Result<Arc<Connection>> {
+        if self.is_closed() {
+            return Err(LimboError::DatabaseClosed);
+        }
         let buffer_pool = Arc::new(BufferPool::new(None));
+        if let Some(pages) = self.opts.cache_size {
+            buffer_pool.set_max_buffers(pages);
+        }
 
         // Open existing WAL file if present
         if let Some(shared_wal) = self.maybe_shared_wal.read().clone() {
@@ -247,21 +658,22 @@ impl Database {
                 shared_wal,
                 buffer_pool.clone(),
             )));
-            let pager = Rc::new(Pager::new(
+            let mut pager = Pager::new(
                 self.db_file.clone(),
                 wal,
                 self.io.clone(),
-                Arc::new(RwLock::new(DumbLruPageCache::default())),
+                Arc::new(RwLock::new(self.opts.page_cache())),
                 buffer_pool,
                 is_empty,
                 self.init_lock.clone(),
-            )?);
-
-            let page_size = header_accessor::get_page_size(&pager)
-                .unwrap_or(storage::sqlite3_ondisk::DEFAULT_PAGE_SIZE)
-                as u32;
-            let default_cache_size = header_accessor::get_default_page_cache_size(&pager)
-                .unwrap_or(storage::sqlite3_ondisk::DEFAULT_CACHE_SIZE);
+            )?;
+            pager.set_shared_page_cache(self.shared_page_cache.clone());
+            pager.set_corruption_callback(self.corruption_callback.clone());
+            pager.set_page_quota_handle(self.page_quota.clone());
+            let pager = Rc::new(pager);
+
+            let page_size = self.connection_page_size(&pager);
+            let (default_cache_size, _) = self.connection_cache_size(&pager);
             pager.buffer_pool.set_page_size(page_size as usize);
             let conn = Arc::new(Connection {
                 _db: self.clone(),
@@ -270,14 +682,25 @@ impl Database {
                 last_insert_rowid: Cell::new(0),
                 auto_commit: Cell::new(true),
                 mv_transactions: RefCell::new(Vec::new()),
+                vtab_transactions: RefCell::new(Vec::new()),
                 transaction_state: Cell::new(TransactionState::None),
                 last_change: Cell::new(0),
                 syms: RefCell::new(SymbolTable::new()),
                 total_changes: Cell::new(0),
-                _shared_cache: false,
                 cache_size: Cell::new(default_cache_size),
+                analysis_limit: Cell::new(0),
+                foreign_keys: Cell::new(false),
+                ignore_check_constraints: Cell::new(false),
                 readonly: Cell::new(false),
                 wal_checkpoint_disabled: Cell::new(false),
+                nested_depth: Cell::new(0),
+                close_state: Arc::new(ConnectionCloseState::default()),
+                query_shape_cache: RefCell::new(QueryShapeCache::default()),
+                update_hook: RefCell::new(None),
+                commit_hook: RefCell::new(None),
+                rollback_hook: RefCell::new(None),
+                trace_hook: RefCell::new(None),
+                profile_hook: RefCell::new(None),
             });
             if let Err(e) = conn.register_builtins() {
                 return Err(LimboError::ExtensionError(e));
@@ -293,43 +716,59 @@ impl Database {
             self.db_file.clone(),
             dummy_wal,
             self.io.clone(),
-            Arc::new(RwLock::new(DumbLruPageCache::default())),
+            Arc::new(RwLock::new(self.opts.page_cache())),
             buffer_pool.clone(),
             is_empty,
             Arc::new(Mutex::new(())),
         )?;
-        let page_size = header_accessor::get_page_size(&pager)
-            .unwrap_or(storage::sqlite3_ondisk::DEFAULT_PAGE_SIZE) as u32;
-        let default_cache_size = header_accessor::get_default_page_cache_size(&pager)
-            .unwrap_or(storage::sqlite3_ondisk::DEFAULT_CACHE_SIZE);
-
-        let wal_path = format!("{}-wal", self.path);
-        let file = self.io.open_file(&wal_path, OpenFlags::Create, false)?;
-        let real_shared_wal = WalFileShared::new_shared(page_size, &self.io, file)?;
-        // Modify Database::maybe_shared_wal to point to the new WAL file so that other connections
-        // can open the existing WAL.
-        *self.maybe_shared_wal.write() = Some(real_shared_wal.clone());
-        let wal = Rc::new(RefCell::new(WalFile::new(
-            self.io.clone(),
-            real_shared_wal,
-            buffer_pool,
-        )));
-        pager.set_wal(wal);
+        pager.set_shared_page_cache(self.shared_page_cache.clone());
+        pager.set_corruption_callback(self.corruption_callback.clone());
+        pager.set_page_quota_handle(self.page_quota.clone());
+        let page_size = self.connection_page_size(&pager);
+        let (default_cache_size, _) = self.connection_cache_size(&pager);
+        pager.buffer_pool.set_page_size(page_size as usize);
+
+        // A read-only database never writes frames, so it doesn't get a WAL file of its own.
+        if !self.open_flags.contains(OpenFlags::ReadOnly) {
+            let wal_path = format!("{}-wal", self.path);
+            let file = self.io.open_file(&wal_path, OpenFlags::Create, false)?;
+            let real_shared_wal = WalFileShared::new_shared(page_size, &self.io, file)?;
+            // Modify Database::maybe_shared_wal to point to the new WAL file so that other connections
+            // can open the existing WAL.
+            *self.maybe_shared_wal.write() = Some(real_shared_wal.clone());
+            let wal = Rc::new(RefCell::new(WalFile::new(
+                self.io.clone(),
+                real_shared_wal,
+                buffer_pool,
+            )));
+            pager.set_wal(wal);
+        }
         let conn = Arc::new(Connection {
             _db: self.clone(),
             pager: Rc::new(pager),
             schema: RefCell::new(self.schema.read().clone()),
             auto_commit: Cell::new(true),
             mv_transactions: RefCell::new(Vec::new()),
+            vtab_transactions: RefCell::new(Vec::new()),
             transaction_state: Cell::new(TransactionState::None),
             last_insert_rowid: Cell::new(0),
             last_change: Cell::new(0),
             total_changes: Cell::new(0),
             syms: RefCell::new(SymbolTable::new()),
-            _shared_cache: false,
             cache_size: Cell::new(default_cache_size),
+            analysis_limit: Cell::new(0),
+            foreign_keys: Cell::new(false),
+            ignore_check_constraints: Cell::new(false),
             readonly: Cell::new(false),
             wal_checkpoint_disabled: Cell::new(false),
+            nested_depth: Cell::new(0),
+            close_state: Arc::new(ConnectionCloseState::default()),
+            query_shape_cache: RefCell::new(QueryShapeCache::default()),
+            update_hook: RefCell::new(None),
+            commit_hook: RefCell::new(None),
+            rollback_hook: RefCell::new(None),
+            trace_hook: RefCell::new(None),
+            profile_hook: RefCell::new(None),
         });
 
         if let Err(e) = conn.register_builtins() {
@@ -338,6 +777,207 @@ impl Database {
         Ok(conn)
     }
 
+    fn register_connection(&self, conn: &Connection) {
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain(|conn| conn.strong_count() > 0);
+        connections.push(Arc::downgrade(&conn.close_state));
+    }
+
+    /// Returns true once [Database::close] succeeded. Connections and statements that outlive
+    /// the database fail with [LimboError::DatabaseClosed].
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Closes the database: runs a final checkpoint of the WAL into the database file and
+    /// releases the lock on the database file.
+    ///
+    /// Fails with [LimboError::DatabaseInUse] while connections that were not closed with
+    /// [Connection::close] or statements that were not dropped are still around, unless
+    /// [CloseOptions::force] is set, in which case they fail with [LimboError::DatabaseClosed]
+    /// from then on. Closing a closed database is a no-op.
+    ///
+    /// A connection may be in use on another thread, so this never touches the connections
+    /// themselves: a connection that is still open when the database is force-closed rolls back
+    /// its transaction on its own thread, the next time it is used.
+    pub fn close(self: &Arc<Database>, opts: CloseOptions) -> Result<()> {
+        if self.is_closed() {
+            return Ok(());
+        }
+        let connections: Vec<Arc<ConnectionCloseState>> = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        let open_connections = connections
+            .iter()
+            .filter(|conn| !conn.closed.load(Ordering::Acquire))
+            .count();
+        let open_statements = connections
+            .iter()
+            .map(|conn| conn.open_statements.load(Ordering::Acquire))
+            .sum();
+        if (open_connections > 0 || open_statements > 0) && !opts.force {
+            return Err(LimboError::DatabaseInUse {
+                connections: open_connections,
+                statements: open_statements,
+            });
+        }
+        drop(connections);
+
+        if opts.checkpoint
+            && !self.open_flags.contains(OpenFlags::ReadOnly)
+            && self.maybe_shared_wal.read().is_some()
+        {
+            let conn = self.open_connection()?;
+            conn.close()?;
+        }
+        self.closed.store(true, Ordering::Release);
+        #[cfg(feature = "fs")]
+        if let Some(key) = self.vfs_key.lock().unwrap().take() {
+            key.wipe();
+        }
+        self.db_file.unlock()
+    }
+
+    /// Page size of the database, or the one it's going to be created with if it's empty.
+    fn connection_page_size(&self, pager: &Pager) -> u32 {
+        match header_accessor::get_page_size(pager) {
+            Ok(page_size) => page_size as u32,
+            Err(_) => self
+                .opts
+                .page_size
+                .map_or(storage::sqlite3_ondisk::DEFAULT_PAGE_SIZE as u32, |size| {
+                    size as u32
+                }),
+        }
+    }
+
+    /// The `cache_size` a new connection reports, and the number of pages its cache holds.
+    fn connection_cache_size(&self, pager: &Pager) -> (i32, usize) {
+        match self.opts.cache_size {
+            Some(pages) => (pages.min(i32::MAX as usize) as i32, pages),
+            None => (
+                header_accessor::get_default_page_cache_size(pager)
+                    .unwrap_or(storage::sqlite3_ondisk::DEFAULT_CACHE_SIZE),
+                storage::page_cache::DEFAULT_PAGE_CACHE_SIZE_IN_PAGES,
+            ),
+        }
+    }
+
+    /// Returns the I/O backend the database was opened with.
+    pub fn io(&self) -> Arc<dyn IO> {
+        self.io.clone()
+    }
+
+    /// Registers a callback invoked whenever a page read by any connection of this database
+    /// fails checksum verification. See `PRAGMA checksum_verification`.
+    pub fn set_corruption_callback<F>(&self, callback: F)
+    where
+        F: Fn(&ChecksumMismatch) + Send + Sync + 'static,
+    {
+        *self.corruption_callback.write() = Some(Arc::new(callback));
+    }
+
+    /// Limits the database file to `quota` pages for every connection; `None` removes the
+    /// limit. A statement that would grow the file past the quota fails with
+    /// [LimboError::QuotaExceeded] and its transaction is rolled back. See [Pager::set_page_quota].
+    pub fn set_page_quota(&self, quota: Option<u32>) {
+        self.page_quota.store(quota.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    /// Returns the page count, freelist size and quota of the database file as of the last
+    /// commit. See [Connection::page_usage] for the view of a connection's open transaction.
+    pub fn page_usage(self: &Arc<Database>) -> Result<PageUsage> {
+        // A connection of its own, not registered with the database, as it only lives for
+        // the duration of this call.
+        self.open_connection()?.page_usage()
+    }
+
+    /// Returns a copy of the database contents in the SQLite on-disk format, including the
+    /// changes committed to the WAL that have not been checkpointed yet. An empty database
+    /// serializes to an empty vector.
+    pub fn serialize(self: &Arc<Database>) -> Result<Vec<u8>> {
+        if self.is_empty.load(Ordering::SeqCst) < DB_STATE_INITIALIZED {
+            return Ok(Vec::new());
+        }
+        let conn = self.connect()?;
+        let pager = &conn.pager;
+        loop {
+            match pager.begin_read_tx()? {
+                CursorResult::Ok(LimboResult::Busy) => return Err(LimboError::Busy),
+                CursorResult::Ok(_) => break,
+                CursorResult::IO => self.io.run_once()?,
+            }
+        }
+        let result = self.read_all_pages(pager);
+        pager.end_read_tx()?;
+        result
+    }
+
+    fn read_all_pages(&self, pager: &Pager) -> Result<Vec<u8>> {
+        let page_size = header_accessor::get_page_size(pager)? as usize;
+        let database_size = header_accessor::get_database_size(pager)? as usize;
+        let mut bytes = Vec::with_capacity(page_size * database_size);
+        for page_idx in 1..=database_size {
+            let page = pager.read_page(page_idx)?;
+            while !page.is_loaded() || page.is_locked() {
+                // FIXME: LETS STOP DOING THESE SYNCHRONOUS IO HACKS
+                self.io.run_once()?;
+            }
+            let contents = page.get().contents.as_ref().unwrap();
+            bytes.extend_from_slice(&contents.as_ptr()[..page_size]);
+        }
+        Ok(bytes)
+    }
+
+    /// What recovery found in the WAL when this database was opened. See `PRAGMA recovery_report`.
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        &self.recovery_report
+    }
+
+    /// Opens a connection that only runs read-only statements and, unlike [Connection], can
+    /// be shared between threads. Open one per thread to run read queries in parallel.
+    pub fn connect_read(self: &Arc<Database>) -> Result<ReadConnection> {
+        let conn = self.open_connection()?;
+        self.register_connection(&conn);
+        Ok(ReadConnection::new(conn))
+    }
+
+    /// Starts an online backup of this database into `dest`. Both databases stay usable while
+    /// the backup runs; call [BackupHandle::step] until it returns [BackupStatus::Done],
+    /// running [BackupHandle::run_once] whenever it returns [BackupStatus::IO].
+    pub fn backup_to(self: &Arc<Database>, dest: &Arc<Database>) -> Result<BackupHandle> {
+        Ok(BackupHandle::new(self.connect()?, dest.connect()?))
+    }
+
+    /// Opens a new in-memory database pre-loaded with `bytes`, a database image in the
+    /// SQLite on-disk format such as the output of [Database::serialize]. Changes made to
+    /// the returned database are not reflected in `bytes`.
+    #[cfg(feature = "fs")]
+    pub fn deserialize(bytes: &[u8]) -> Result<Arc<Database>> {
+        use crate::util::MEMORY_PATH;
+        if !bytes.is_empty() && !bytes.starts_with(b"SQLite format 3\0") {
+            return Err(LimboError::NotADB);
+        }
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let file = io.open_file(MEMORY_PATH, OpenFlags::Create, false)?;
+        if !bytes.is_empty() {
+            let buffer = Buffer::new(std::pin::Pin::new(bytes.to_vec()));
+            let completion = Completion::new(CompletionType::Write(WriteCompletion::new(
+                Box::new(|_| {}),
+            )));
+            let completion = file.pwrite(0, Arc::new(RefCell::new(buffer)), completion)?;
+            while !completion.is_completed() {
+                io.run_once()?;
+            }
+        }
+        let db_file = Arc::new(DatabaseFile::new(file));
+        Self::open(io, MEMORY_PATH, db_file, false, false)
+    }
+
     /// Open a new database file with optionally specifying a VFS without an existing database
     /// connection and symbol table to register extensions.
     #[cfg(feature = "fs")]
@@ -434,6 +1074,17 @@ fn get_schema_version(conn: &Arc<Connection>, io: &Arc<dyn IO>) -> Result<u32> {
     }
 }
 
+/// The state of a [Connection] that [Database::close] reads. A connection is only used on one
+/// thread at a time, but the database can be closed from any thread, so this is shared through
+/// atomics rather than read from the connection itself.
+#[derive(Default)]
+struct ConnectionCloseState {
+    /// Set by [Connection::close].
+    closed: AtomicBool,
+    /// Number of [Statement]s prepared on the connection that were not dropped yet.
+    open_statements: AtomicUsize,
+}
+
 pub struct Connection {
     _db: Arc<Database>,
     pager: Rc<Pager>,
@@ -441,20 +1092,43 @@ pub struct Connection {
     /// Whether to automatically commit transaction
     auto_commit: Cell<bool>,
     mv_transactions: RefCell<Vec<crate::mvcc::database::TxID>>,
+    /// The virtual tables changed in the current transaction, which are told when it ends.
+    vtab_transactions: RefCell<Vec<Rc<VirtualTable>>>,
     transaction_state: Cell<TransactionState>,
     last_insert_rowid: Cell<i64>,
     last_change: Cell<i64>,
     total_changes: Cell<i64>,
     syms: RefCell<SymbolTable>,
-    _shared_cache: bool,
     cache_size: Cell<i32>,
+    /// `PRAGMA analysis_limit`; 0 means no limit.
+    analysis_limit: Cell<i32>,
+    /// `PRAGMA foreign_keys`
+    foreign_keys: Cell<bool>,
+    /// `PRAGMA ignore_check_constraints`
+    ignore_check_constraints: Cell<bool>,
     readonly: Cell<bool>,
     wal_checkpoint_disabled: Cell<bool>,
+    /// Number of statements issued by virtual table callbacks that are running on this
+    /// connection. They share the transaction of the statement that invoked the callback.
+    nested_depth: Cell<usize>,
+    close_state: Arc<ConnectionCloseState>,
+    query_shape_cache: RefCell<QueryShapeCache>,
+    /// Callback invoked for every row inserted, updated or deleted, see [Connection::set_update_hook].
+    update_hook: RefCell<Option<UpdateHook>>,
+    /// Callback invoked before a write transaction commits, see [Connection::set_commit_hook].
+    commit_hook: RefCell<Option<CommitHook>>,
+    /// Callback invoked after a write transaction rolls back, see [Connection::set_rollback_hook].
+    rollback_hook: RefCell<Option<RollbackHook>>,
+    /// Callback invoked after every statement completes, see [Connection::set_trace_hook].
+    trace_hook: RefCell<Option<TraceHook>>,
+    /// Callback invoked after every statement completes, see [Connection::set_profile_hook].
+    profile_hook: RefCell<Option<ProfileHook>>,
 }
 
 impl Connection {
     #[instrument(skip_all, level = Level::TRACE)]
     pub fn prepare(self: &Arc<Connection>, sql: impl AsRef<str>) -> Result<Statement> {
+        self.ensure_open()?;
         if sql.as_ref().is_empty() {
             return Err(LimboError::InvalidArgument(
                 "The supplied SQL string contains no statements".to_string(),
@@ -465,37 +1139,77 @@ impl Connection {
         tracing::trace!("Preparing: {}", sql);
         let mut parser = Parser::new(sql.as_bytes());
         let cmd = parser.next()?;
-        let syms = self.syms.borrow();
         let cmd = cmd.expect("Successful parse on nonempty input string should produce a command");
         let byte_offset_end = parser.offset();
         let input = str::from_utf8(&sql.as_bytes()[..byte_offset_end])
             .unwrap()
             .trim();
+        self.prepare_cmd(cmd, input)
+    }
+
+    /// Prepares every statement of `sql`, in order. Statements are split by the SQL
+    /// parser, so semicolons inside strings, identifiers and comments are handled.
+    ///
+    /// All statements are translated against the schema as it is when this is called, so a
+    /// statement that uses a table created by an earlier statement of the same input fails
+    /// to prepare. Use [Connection::execute_batch] to run such scripts.
+    #[instrument(skip_all, level = Level::TRACE)]
+    pub fn prepare_all(self: &Arc<Connection>, sql: impl AsRef<str>) -> Result<Vec<Statement>> {
+        let sql = sql.as_ref();
+        tracing::trace!("Preparing all: {}", sql);
+        let mut parser = Parser::new(sql.as_bytes());
+        let mut statements = Vec::new();
+        let mut last_offset = 0;
+        while let Some(cmd) = parser.next()? {
+            let byte_offset_end = parser.offset();
+            let input = str::from_utf8(&sql.as_bytes()[last_offset..byte_offset_end])
+                .unwrap()
+                .trim();
+            last_offset = byte_offset_end;
+            statements.push(self.prepare_cmd(cmd, input)?);
+        }
+        Ok(statements)
+    }
+
+    fn prepare_cmd(self: &Arc<Connection>, cmd: Cmd, input: &str) -> Result<Statement> {
+        let syms = self.syms.borrow();
         self.maybe_update_schema();
+        let query_mode = QueryMode::from(&cmd);
         match cmd {
-            Cmd::Stmt(stmt) => {
-                let program = Rc::new(translate::translate(
-                    self.schema.borrow().deref(),
-                    stmt,
-                    self.pager.clone(),
-                    self.clone(),
-                    &syms,
-                    QueryMode::Normal,
-                    input,
-                )?);
-                Ok(Statement::new(
-                    program,
-                    self._db.mv_store.clone(),
-                    self.pager.clone(),
-                ))
+            Cmd::Stmt(stmt) | Cmd::ExplainQueryPlan(stmt) => {
+                let program = Rc::new(match query_mode {
+                    QueryMode::Normal => translate::query_shape::translate_with_shape_cache(
+                        self.schema.borrow().deref(),
+                        stmt,
+                        self.pager.clone(),
+                        self.clone(),
+                        &syms,
+                        input,
+                    )?,
+                    _ => translate::translate(
+                        self.schema.borrow().deref(),
+                        stmt,
+                        self.pager.clone(),
+                        self.clone(),
+                        &syms,
+                        query_mode,
+                        input,
+                    )?,
+                });
+                let mut stmt =
+                    Statement::new(program, self._db.mv_store.clone(), self.pager.clone());
+                if query_mode == QueryMode::Normal {
+                    stmt.sql = Some(input.to_string());
+                }
+                Ok(stmt)
             }
             Cmd::Explain(_stmt) => todo!(),
-            Cmd::ExplainQueryPlan(_stmt) => todo!(),
         }
     }
 
     #[instrument(skip_all, level = Level::TRACE)]
     pub fn query(self: &Arc<Connection>, sql: impl AsRef<str>) -> Result<Option<Statement>> {
+        self.ensure_open()?;
         let sql = sql.as_ref();
         tracing::trace!("Querying: {}", sql);
         let mut parser = Parser::new(sql.as_bytes());
@@ -517,44 +1231,26 @@ impl Connection {
         input: &str,
     ) -> Result<Option<Statement>> {
         let syms = self.syms.borrow();
-        match cmd {
-            Cmd::Stmt(ref stmt) | Cmd::Explain(ref stmt) => {
-                let program = translate::translate(
-                    self.schema.borrow().deref(),
-                    stmt.clone(),
-                    self.pager.clone(),
-                    self.clone(),
-                    &syms,
-                    cmd.into(),
-                    input,
-                )?;
-                let stmt = Statement::new(
-                    program.into(),
-                    self._db.mv_store.clone(),
-                    self.pager.clone(),
-                );
-                Ok(Some(stmt))
-            }
-            Cmd::ExplainQueryPlan(stmt) => {
-                let mut table_ref_counter = TableRefIdCounter::new();
-                match stmt {
-                    ast::Stmt::Select(select) => {
-                        let mut plan = prepare_select_plan(
-                            self.schema.borrow().deref(),
-                            *select,
-                            &syms,
-                            &[],
-                            &mut table_ref_counter,
-                            translate::plan::QueryDestination::ResultRows,
-                        )?;
-                        optimize_plan(&mut plan, self.schema.borrow().deref())?;
-                        let _ = std::io::stdout().write_all(plan.to_string().as_bytes());
-                    }
-                    _ => todo!(),
-                }
-                Ok(None)
-            }
+        let query_mode = QueryMode::from(&cmd);
+        let (Cmd::Stmt(stmt) | Cmd::Explain(stmt) | Cmd::ExplainQueryPlan(stmt)) = cmd;
+        let program = translate::translate(
+            self.schema.borrow().deref(),
+            stmt,
+            self.pager.clone(),
+            self.clone(),
+            &syms,
+            query_mode,
+            input,
+        )?;
+        let mut stmt = Statement::new(
+            program.into(),
+            self._db.mv_store.clone(),
+            self.pager.clone(),
+        );
+        if query_mode == QueryMode::Normal {
+            stmt.sql = Some(input.to_string());
         }
+        Ok(Some(stmt))
     }
 
     pub fn query_runner<'a>(self: &'a Arc<Connection>, sql: &'a [u8]) -> QueryRunner<'a> {
@@ -565,6 +1261,7 @@ impl Connection {
     /// TODO: make this api async
     #[instrument(skip_all, level = Level::TRACE)]
     pub fn execute(self: &Arc<Connection>, sql: impl AsRef<str>) -> Result<()> {
+        self.ensure_open()?;
         let sql = sql.as_ref();
         let mut parser = Parser::new(sql.as_bytes());
         while let Some(cmd) = parser.next()? {
@@ -574,6 +1271,7 @@ impl Connection {
                 .unwrap()
                 .trim();
             self.maybe_update_schema();
+            let query_mode = QueryMode::from(&cmd);
             match cmd {
                 Cmd::Explain(stmt) => {
                     let program = translate::translate(
@@ -585,60 +1283,98 @@ impl Connection {
                         QueryMode::Explain,
                         input,
                     )?;
-                    let _ = std::io::stdout().write_all(program.explain().as_bytes());
+                    let _ = program.explain_to(&mut std::io::stdout());
                 }
-                Cmd::ExplainQueryPlan(_stmt) => todo!(),
-                Cmd::Stmt(stmt) => {
+                Cmd::Stmt(stmt) | Cmd::ExplainQueryPlan(stmt) => {
                     let program = translate::translate(
                         self.schema.borrow().deref(),
                         stmt,
                         self.pager.clone(),
                         self.clone(),
                         &syms,
-                        QueryMode::Normal,
+                        query_mode,
                         input,
                     )?;
 
                     let mut state =
                         vdbe::ProgramState::new(program.max_registers, program.cursor_ref.len());
+                    // Sampling the thread's CPU clock is a syscall, so only do it when a hook
+                    // is going to be told about it.
+                    let traced = self.has_trace_hooks();
+                    let started_at = std::time::Instant::now();
+                    let mut cpu_time = Duration::ZERO;
                     loop {
+                        let cpu_start = traced.then(util::thread_cpu_time);
                         let res = program.step(
                             &mut state,
                             self._db.mv_store.clone(),
                             self.pager.clone(),
                         )?;
+                        if let Some(cpu_start) = cpu_start {
+                            cpu_time += util::thread_cpu_time().saturating_sub(cpu_start);
+                        }
                         if matches!(res, StepResult::Done) {
                             break;
                         }
                         self._db.io.run_once()?;
                     }
+                    if traced {
+                        self.call_trace_hooks(input, started_at.elapsed(), cpu_time);
+                    }
                 }
             }
         }
         Ok(())
     }
 
+    /// Runs every statement of `sql`, such as a schema migration script, to completion,
+    /// discarding result rows. Statements are split by the SQL parser, so semicolons
+    /// inside strings, identifiers and comments are handled.
+    ///
+    /// Execution stops at the first statement that fails and its error is returned. If
+    /// the failing statement ran in an implicit transaction, that transaction is rolled
+    /// back; an explicit transaction is left for the caller to commit or roll back.
+    #[instrument(skip_all, level = Level::TRACE)]
+    pub fn execute_batch(self: &Arc<Connection>, sql: impl AsRef<str>) -> Result<()> {
+        let sql = sql.as_ref();
+        for stmt in self.query_runner(sql.as_bytes()) {
+            let Some(mut stmt) = stmt? else {
+                continue;
+            };
+            if let Err(err) = self.run_to_completion(&mut stmt) {
+                if self.auto_commit.get() {
+                    self.rollback_session_txn()?;
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn run_to_completion(&self, stmt: &mut Statement) -> Result<()> {
+        loop {
+            match stmt.step()? {
+                StepResult::Row => {}
+                StepResult::IO => self._db.io.run_once()?,
+                StepResult::Done => return Ok(()),
+                StepResult::Interrupt => {
+                    return Err(LimboError::InternalError(
+                        "statement was interrupted".to_string(),
+                    ))
+                }
+                StepResult::Busy => return Err(LimboError::Busy),
+            }
+        }
+    }
+
     #[cfg(feature = "fs")]
     pub fn from_uri(
         uri: &str,
         use_indexes: bool,
         mvcc: bool,
     ) -> Result<(Arc<dyn IO>, Arc<Connection>)> {
-        use crate::util::MEMORY_PATH;
         let opts = OpenOptions::parse(uri)?;
-        let flags = opts.get_flags()?;
-        if opts.path == MEMORY_PATH || matches!(opts.mode, OpenMode::Memory) {
-            let io = Arc::new(MemoryIO::new());
-            let db =
-                Database::open_file_with_flags(io.clone(), MEMORY_PATH, flags, mvcc, use_indexes)?;
-            let conn = db.connect()?;
-            return Ok((io, conn));
-        }
-        let (io, db) = Database::open_new(&opts.path, opts.vfs.as_ref(), flags, use_indexes, mvcc)?;
-        if let Some(modeof) = opts.modeof {
-            let perms = std::fs::metadata(modeof)?;
-            std::fs::set_permissions(&opts.path, perms.permissions())?;
-        }
+        let (io, db) = Database::open_with_options(&opts, use_indexes, mvcc)?;
         let conn = db.connect()?;
         conn.set_readonly(opts.immutable);
         Ok((io, conn))
@@ -648,6 +1384,131 @@ impl Connection {
         self.readonly.replace(readonly);
     }
 
+    /// Returns true if this connection must not write, either because it was marked
+    /// read-only or because the database was opened with [OpenFlags::ReadOnly].
+    pub fn is_readonly(&self) -> bool {
+        self.readonly.get() || self._db.open_flags.contains(OpenFlags::ReadOnly)
+    }
+
+    /// Sets how long, in milliseconds, a write keeps retrying while another connection holds
+    /// the write lock before failing with [LimboError::Busy]. Zero, the default, fails at once.
+    pub fn set_busy_timeout(&self, ms: u64) {
+        self.pager.set_busy_timeout(Duration::from_millis(ms));
+    }
+
+    /// Returns the busy timeout in milliseconds, see [Connection::set_busy_timeout].
+    pub fn busy_timeout(&self) -> u64 {
+        self.pager.busy_timeout().as_millis() as u64
+    }
+
+    /// Registers a callback invoked after every row inserted, updated or deleted through
+    /// this connection, with the kind of change, the database name, the table name and the
+    /// rowid of the row, like `sqlite3_update_hook`. Changes to internal tables such as
+    /// `sqlite_schema` are not reported. The callback must not use the connection.
+    pub fn set_update_hook(&self, hook: UpdateHook) {
+        self.update_hook.replace(Some(hook));
+    }
+
+    /// Removes the callback registered with [Connection::set_update_hook].
+    pub fn clear_update_hook(&self) {
+        self.update_hook.replace(None);
+    }
+
+    pub(crate) fn has_update_hook(&self) -> bool {
+        self.update_hook.borrow().is_some()
+    }
+
+    pub(crate) fn call_update_hook(&self, update_type: UpdateType, table_name: &str, rowid: i64) {
+        if table_name.starts_with("sqlite_") {
+            return;
+        }
+        if let Some(hook) = self.update_hook.borrow().as_ref() {
+            hook(update_type, "main", table_name, rowid);
+        }
+    }
+
+    /// Registers a callback invoked whenever a write transaction is about to commit, like
+    /// `sqlite3_commit_hook`. If it returns false the transaction is rolled back instead and
+    /// the statement fails with [LimboError::CommitHookAborted]. The callback must not use
+    /// the connection.
+    pub fn set_commit_hook(&self, hook: CommitHook) {
+        self.commit_hook.replace(Some(hook));
+    }
+
+    /// Removes the callback registered with [Connection::set_commit_hook].
+    pub fn clear_commit_hook(&self) {
+        self.commit_hook.replace(None);
+    }
+
+    /// Registers a callback invoked whenever a write transaction has been rolled back, like
+    /// `sqlite3_rollback_hook`. The callback must not use the connection.
+    pub fn set_rollback_hook(&self, hook: RollbackHook) {
+        self.rollback_hook.replace(Some(hook));
+    }
+
+    /// Removes the callback registered with [Connection::set_rollback_hook].
+    pub fn clear_rollback_hook(&self) {
+        self.rollback_hook.replace(None);
+    }
+
+    /// Returns false if the commit hook vetoes the commit.
+    pub(crate) fn call_commit_hook(&self) -> bool {
+        match self.commit_hook.borrow().as_ref() {
+            Some(hook) => hook(),
+            None => true,
+        }
+    }
+
+    pub(crate) fn call_rollback_hook(&self) {
+        if let Some(hook) = self.rollback_hook.borrow().as_ref() {
+            hook();
+        }
+    }
+
+    /// Registers a callback invoked after every statement run through this connection
+    /// completes, with its SQL text and the wall-clock time from its first step until it
+    /// completed, including the time the caller spent between steps. Statements that fail
+    /// or are reset before completing are not reported. The callback must not use the
+    /// connection.
+    pub fn set_trace_hook(&self, hook: TraceHook) {
+        self.trace_hook.replace(Some(hook));
+    }
+
+    /// Removes the callback registered with [Connection::set_trace_hook].
+    pub fn clear_trace_hook(&self) {
+        self.trace_hook.replace(None);
+    }
+
+    /// Registers a callback invoked after every statement run through this connection
+    /// completes, with its SQL text and the nanoseconds of CPU time spent executing it. Unlike
+    /// the wall-clock duration reported to [Connection::set_trace_hook], this only counts the
+    /// CPU time of the calling thread inside [Statement::step], not the time spent waiting for
+    /// I/O or locks, nor the time the caller spent handling rows. On platforms without a
+    /// per-thread CPU clock it is the wall-clock time spent inside [Statement::step]. The
+    /// callback must not use the connection.
+    pub fn set_profile_hook(&self, hook: ProfileHook) {
+        self.profile_hook.replace(Some(hook));
+    }
+
+    /// Removes the callback registered with [Connection::set_profile_hook].
+    pub fn clear_profile_hook(&self) {
+        self.profile_hook.replace(None);
+    }
+
+    pub(crate) fn has_trace_hooks(&self) -> bool {
+        self.trace_hook.borrow().is_some() || self.profile_hook.borrow().is_some()
+    }
+
+    /// Reports a completed statement to the trace and profile hooks.
+    pub(crate) fn call_trace_hooks(&self, sql: &str, elapsed: Duration, cpu_time: Duration) {
+        if let Some(hook) = self.trace_hook.borrow().as_ref() {
+            hook(sql, elapsed);
+        }
+        if let Some(hook) = self.profile_hook.borrow().as_ref() {
+            hook(sql, cpu_time.as_nanos().try_into().unwrap_or(u64::MAX));
+        }
+    }
+
     pub fn maybe_update_schema(&self) {
         let current_schema_version = self.schema.borrow().schema_version;
         if matches!(self.transaction_state.get(), TransactionState::None)
@@ -685,14 +1546,144 @@ impl Connection {
     }
 
     pub fn checkpoint(&self) -> Result<CheckpointResult> {
+        self.checkpoint_with_mode(CheckpointMode::Passive)
+    }
+
+    /// Checkpoints the WAL into the database file, see [CheckpointMode] for what each mode
+    /// does. Modes other than [CheckpointMode::Passive] fail with [LimboError::Busy] if
+    /// another connection is writing.
+    pub fn checkpoint_with_mode(&self, mode: CheckpointMode) -> Result<CheckpointResult> {
         self.pager
-            .wal_checkpoint(self.wal_checkpoint_disabled.get())
+            .wal_checkpoint(self.wal_checkpoint_disabled.get(), mode)
     }
 
-    /// Close a connection and checkpoint.
+    /// Marks the start of a statement issued by a virtual table callback, which runs inside the
+    /// invoking statement's transaction instead of committing on its own.
+    pub(crate) fn enter_nested_statement(&self) -> Result<()> {
+        let depth = self.nested_depth.get();
+        if depth >= MAX_NESTED_STATEMENT_DEPTH {
+            return Err(LimboError::ExtensionError(format!(
+                "too many levels of virtual table reentrancy (max {})",
+                MAX_NESTED_STATEMENT_DEPTH
+            )));
+        }
+        self.nested_depth.set(depth + 1);
+        Ok(())
+    }
+
+    pub(crate) fn exit_nested_statement(&self) {
+        let depth = self.nested_depth.get();
+        assert!(depth > 0, "unbalanced nested statement");
+        self.nested_depth.set(depth - 1);
+    }
+
+    /// Returns true while a statement issued by a virtual table callback is running.
+    pub(crate) fn is_nested(&self) -> bool {
+        self.nested_depth.get() > 0
+    }
+
+    /// Returns true while a checkpoint run by this connection is held back by
+    /// `PRAGMA checkpoint_rate_limit`, e.g. while [Connection::cacheflush] keeps returning IO
+    /// for an automatic checkpoint.
+    pub fn is_checkpoint_throttled(&self) -> bool {
+        self.pager.is_checkpoint_throttled()
+    }
+
+    /// Returns how many pages this connection took off the freelist or allocated by growing
+    /// the database file, and how close reused pages were to the btree pages they extend.
+    pub fn page_allocation_stats(&self) -> PageAllocationStats {
+        self.pager.allocation_stats()
+    }
+
+    /// Whether this connection reuses the free pages closest to the btree being grown, which
+    /// is the default, or takes them in freelist order.
+    pub fn set_freelist_locality(&self, enabled: bool) {
+        self.pager.set_freelist_locality(enabled);
+    }
+
+    /// Returns the page count, freelist size and quota of the database file as seen by this
+    /// connection: as of the last commit, or including the changes of its open transaction. An
+    /// empty database uses no pages.
+    pub fn page_usage(&self) -> Result<PageUsage> {
+        if self._db.is_empty.load(Ordering::SeqCst) < DB_STATE_INITIALIZED {
+            return Ok(PageUsage {
+                page_count: 0,
+                freelist_pages: 0,
+                high_water: 0,
+                quota: self.pager.page_quota(),
+            });
+        }
+        if !matches!(self.transaction_state.get(), TransactionState::None) {
+            return self.pager.page_usage();
+        }
+        loop {
+            match self.pager.begin_read_tx()? {
+                CursorResult::Ok(LimboResult::Busy) => return Err(LimboError::Busy),
+                CursorResult::Ok(_) => break,
+                CursorResult::IO => self._db.io.run_once()?,
+            }
+        }
+        let result = self.pager.page_usage();
+        self.pager.end_read_tx()?;
+        result
+    }
+
+    /// Close a connection and checkpoint. Statements prepared on the connection fail with
+    /// [LimboError::DatabaseClosed] afterwards. Closing a closed connection is a no-op.
     pub fn close(&self) -> Result<()> {
+        if self.is_closed() {
+            return Ok(());
+        }
         self.pager
-            .checkpoint_shutdown(self.wal_checkpoint_disabled.get())
+            .checkpoint_shutdown(self.wal_checkpoint_disabled.get())?;
+        self.close_state.closed.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns the database this connection was opened on.
+    pub fn database(&self) -> Arc<Database> {
+        self._db.clone()
+    }
+
+    /// Returns the hit and miss counters of the query-shape cache, which lets [Connection::prepare]
+    /// reuse the program of a statement that only differed in its literals.
+    pub fn query_shape_cache_stats(&self) -> QueryShapeCacheStats {
+        self.query_shape_cache.borrow().stats()
+    }
+
+    /// Enables or disables the query-shape cache. It is enabled by default; disabling it also
+    /// forgets every cached shape.
+    pub fn set_query_shape_cache(&self, enabled: bool) {
+        self.query_shape_cache.borrow_mut().set_enabled(enabled);
+    }
+
+    /// Returns true once the connection or its database was closed.
+    pub fn is_closed(&self) -> bool {
+        self.close_state.closed.load(Ordering::Acquire) || self._db.is_closed()
+    }
+
+    /// Fails with [LimboError::DatabaseClosed] once the connection or its database was closed.
+    /// If the database was force-closed while a transaction was open, the transaction is
+    /// rolled back here, on the thread that uses the connection.
+    fn ensure_open(&self) -> Result<()> {
+        if !self.is_closed() {
+            return Ok(());
+        }
+        if !matches!(self.transaction_state.get(), TransactionState::None) {
+            self.rollback_session_txn()?;
+        }
+        Err(LimboError::DatabaseClosed)
+    }
+
+    /// Sets after how many WAL frames a commit checkpoints the WAL, 0 to never do so
+    /// automatically. Applies to every connection to the database.
+    pub fn set_wal_autocheckpoint(&self, frames: u64) {
+        self.pager.set_wal_autocheckpoint(frames);
+    }
+
+    /// Returns the threshold set with [Connection::set_wal_autocheckpoint].
+    pub fn wal_autocheckpoint(&self) -> u64 {
+        self.pager.wal_autocheckpoint()
     }
 
     pub fn wal_disable_checkpoint(&self) {
@@ -713,6 +1704,11 @@ impl Connection {
         self.total_changes.set(prev_total_changes + nchange);
     }
 
+    /// Number of rows modified by the most recently completed statement.
+    pub fn changes(&self) -> i64 {
+        self.last_change.get()
+    }
+
     pub fn total_changes(&self) -> i64 {
         self.total_changes.get()
     }
@@ -724,6 +1720,131 @@ impl Connection {
         self.cache_size.set(size);
     }
 
+    pub fn get_analysis_limit(&self) -> i32 {
+        self.analysis_limit.get()
+    }
+    pub fn set_analysis_limit(&self, limit: i32) {
+        self.analysis_limit.set(limit);
+    }
+
+    pub fn foreign_keys_enabled(&self) -> bool {
+        self.foreign_keys.get()
+    }
+    pub fn set_foreign_keys(&self, enabled: bool) {
+        if self.foreign_keys.replace(enabled) != enabled {
+            // Cached programs were translated with or without foreign key checks.
+            self.query_shape_cache.borrow_mut().clear();
+        }
+    }
+
+    pub fn ignore_check_constraints(&self) -> bool {
+        self.ignore_check_constraints.get()
+    }
+    pub fn set_ignore_check_constraints(&self, ignore: bool) {
+        if self.ignore_check_constraints.replace(ignore) != ignore {
+            self.query_shape_cache.borrow_mut().clear();
+        }
+    }
+
+    /// Returns the connection to the state of a freshly opened one, so that a connection
+    /// pool can hand it out again:
+    ///
+    /// - an open transaction is rolled back,
+    /// - session pragmas (`cache_size`, `checksum_verification`, `checkpoint_rate_limit`,
+    ///   `analysis_limit`, `synchronous`, `foreign_keys`, `ignore_check_constraints`) go back
+    ///   to their defaults,
+    /// - functions registered on the connection, e.g. by loading an extension, are dropped,
+    /// - the last insert rowid and the change counters are cleared,
+    /// - the query-shape cache is emptied.
+    ///
+    /// The schema is not parsed again. Statements prepared before the reset must not be
+    /// stepped afterwards.
+    pub fn reset_session(&self) -> Result<()> {
+        self.rollback_session_txn()?;
+
+        let (default_cache_size, cache_pages) = self._db.connection_cache_size(&self.pager);
+        if self.cache_size.get() != default_cache_size {
+            self.cache_size.set(default_cache_size);
+            self.pager.change_page_cache_size(cache_pages)?;
+        }
+        self.pager.set_checksum_verification(false);
+        self.pager.set_checkpoint_rate_limit(0);
+        self.analysis_limit.set(0);
+        self.pager.set_synchronous(SynchronousMode::Full);
+        self.foreign_keys.set(false);
+        self.ignore_check_constraints.set(false);
+
+        self.syms.borrow_mut().functions.clear();
+        self.query_shape_cache.borrow_mut().clear();
+        if let Err(e) = self.register_builtins() {
+            return Err(LimboError::ExtensionError(e));
+        }
+
+        self.last_insert_rowid.set(0);
+        self.last_change.set(0);
+        self.total_changes.set(0);
+        Ok(())
+    }
+
+    /// Starts a transaction on the virtual table `vtab` before it is first changed in the
+    /// current transaction.
+    pub(crate) fn begin_vtab_transaction(&self, vtab: &Rc<VirtualTable>) -> Result<()> {
+        if self
+            .vtab_transactions
+            .borrow()
+            .iter()
+            .any(|t| Rc::ptr_eq(t, vtab))
+        {
+            return Ok(());
+        }
+        vtab.begin()?;
+        self.vtab_transactions.borrow_mut().push(vtab.clone());
+        Ok(())
+    }
+
+    /// Commits or rolls back the transactions of the virtual tables changed in the current
+    /// transaction. Every table is told even if one of them fails; the first error is returned.
+    pub(crate) fn end_vtab_transactions(&self, rollback: bool) -> Result<()> {
+        let vtabs = std::mem::take(&mut *self.vtab_transactions.borrow_mut());
+        let mut result = Ok(());
+        for vtab in vtabs {
+            let res = if rollback {
+                vtab.rollback()
+            } else {
+                vtab.commit()
+            };
+            if result.is_ok() {
+                result = res;
+            }
+        }
+        result
+    }
+
+    fn rollback_session_txn(&self) -> Result<()> {
+        if let Some(mv_store) = &self._db.mv_store {
+            for tx_id in self.mv_transactions.borrow_mut().drain(..) {
+                mv_store.rollback_tx(tx_id);
+            }
+        }
+        self.end_vtab_transactions(true)?;
+        match self.transaction_state.get() {
+            TransactionState::Write { change_schema, .. } => {
+                self.pager.rollback(change_schema, self)?;
+                self.pager.end_tx(
+                    true,
+                    change_schema,
+                    self,
+                    self.wal_checkpoint_disabled.get(),
+                )?;
+            }
+            TransactionState::Read => self.pager.end_read_tx()?,
+            TransactionState::None => {}
+        }
+        self.transaction_state.set(TransactionState::None);
+        self.auto_commit.set(true);
+        Ok(())
+    }
+
     #[cfg(feature = "fs")]
     pub fn open_new(&self, path: &str, vfs: &str) -> Result<(Arc<dyn IO>, Arc<Database>)> {
         Database::open_with_vfs(&self._db, path, vfs)
@@ -750,9 +1871,22 @@ impl Connection {
         self.auto_commit.get()
     }
 
+    /// Returns the kind of transaction the connection has open, or None outside of one. A
+    /// deferred transaction has none until its first statement reads or writes.
+    pub fn transaction_kind(&self) -> Option<TransactionKind> {
+        match self.transaction_state.get() {
+            TransactionState::Write {
+                exclusive: true, ..
+            } => Some(TransactionKind::Exclusive),
+            TransactionState::Write { .. } => Some(TransactionKind::Write),
+            TransactionState::Read => Some(TransactionKind::Read),
+            TransactionState::None => None,
+        }
+    }
+
     pub fn parse_schema_rows(self: &Arc<Connection>) -> Result<()> {
         let rows = self.query("SELECT * FROM sqlite_schema")?;
-        let mut schema = self.schema.borrow_mut();
+        let mut schema = self.schema.borrow().clone();
         {
             let syms = self.syms.borrow();
             if let Err(LimboError::ExtensionError(e)) =
@@ -763,6 +1897,19 @@ impl Connection {
                 eprintln!("Warning: {}", e);
             }
         }
+        self.schema.replace(schema);
+        self.load_stats()
+    }
+
+    /// Loads the statistics stored in `sqlite_stat1` by ANALYZE into the schema.
+    pub(crate) fn load_stats(self: &Arc<Connection>) -> Result<()> {
+        if self.schema.borrow().get_table(SQLITE_STAT1_TABLE).is_none() {
+            return Ok(());
+        }
+        let rows = self.query(format!("SELECT tbl, idx, stat FROM {SQLITE_STAT1_TABLE}"))?;
+        let mut schema = self.schema.borrow().clone();
+        parse_stat1_rows(rows, &mut schema, self.pager.io.clone(), None)?;
+        self.schema.replace(schema);
         Ok(())
     }
 
@@ -847,11 +1994,38 @@ impl Connection {
     }
 }
 
+/// Number of rows [Statement::execute_batch] runs before yielding with [StepResult::IO].
+pub const BATCH_YIELD_INTERVAL: usize = 1024;
+
+/// How deep virtual table callbacks may issue SQL that again invokes virtual table callbacks.
+pub const MAX_NESTED_STATEMENT_DEPTH: usize = 16;
+
+#[derive(Debug, Default)]
+struct BatchProgress {
+    /// Rows of the current batch that ran to completion.
+    rows_done: usize,
+    /// Rows completed since the batch last yielded to the caller.
+    rows_since_yield: usize,
+    /// Whether the parameters of a row are bound and the row has not finished yet.
+    in_row: bool,
+    /// Value of the program's change counter when the batch started.
+    start_changes: Option<i64>,
+}
+
 pub struct Statement {
     program: Rc<vdbe::Program>,
     state: vdbe::ProgramState,
     mv_store: Option<Rc<MvStore>>,
     pager: Rc<Pager>,
+    batch: BatchProgress,
+    /// SQL the statement was prepared from, used to translate it again after a schema change.
+    sql: Option<String>,
+    /// Schema version the program was translated against.
+    schema_version: u32,
+    /// When the current execution first stepped, if the connection has trace hooks.
+    started_at: Option<std::time::Instant>,
+    /// CPU time spent inside [Statement::step] during the current execution.
+    cpu_time: Duration,
 }
 
 impl Statement {
@@ -861,11 +2035,22 @@ impl Statement {
         pager: Rc<Pager>,
     ) -> Self {
         let state = vdbe::ProgramState::new(program.max_registers, program.cursor_ref.len());
+        let schema_version = program.connection.schema.borrow().schema_version;
+        program
+            .connection
+            .close_state
+            .open_statements
+            .fetch_add(1, Ordering::AcqRel);
         Self {
             program,
             state,
             mv_store,
             pager,
+            batch: BatchProgress::default(),
+            sql: None,
+            schema_version,
+            started_at: None,
+            cpu_time: Duration::ZERO,
         }
     }
 
@@ -877,25 +2062,134 @@ impl Statement {
         self.state.interrupt();
     }
 
+    /// Abandons the statement before it has run to completion and resets it. In
+    /// autocommit mode the transaction the statement started is rolled back, releasing
+    /// its locks; inside an explicit transaction the transaction is left alone.
+    pub fn cancel(&mut self) -> Result<()> {
+        self.reset();
+        let conn = &self.program.connection;
+        if conn.auto_commit.get() {
+            conn.rollback_session_txn()?;
+        }
+        Ok(())
+    }
+
     pub fn step(&mut self) -> Result<StepResult> {
+        self.program.connection.ensure_open()?;
+        if !self.program.connection.has_trace_hooks() {
+            return self.step_program();
+        }
+        let started_at = *self.started_at.get_or_insert_with(std::time::Instant::now);
+        let cpu_start = util::thread_cpu_time();
+        let res = self.step_program();
+        self.cpu_time += util::thread_cpu_time().saturating_sub(cpu_start);
+        if matches!(res, Ok(StepResult::Done)) {
+            self.started_at = None;
+            let cpu_time = std::mem::take(&mut self.cpu_time);
+            if let Some(sql) = &self.sql {
+                self.program
+                    .connection
+                    .call_trace_hooks(sql, started_at.elapsed(), cpu_time);
+            }
+        }
+        res
+    }
+
+    fn step_program(&mut self) -> Result<StepResult> {
+        if self.state.pc == 0 {
+            self.maybe_reprepare()?;
+        }
         self.program
             .step(&mut self.state, self.mv_store.clone(), self.pager.clone())
     }
 
+    /// Translates the statement again if the schema changed since it was prepared, so
+    /// that the program never runs against dropped tables, dropped indexes or stale
+    /// root pages. Bound parameters are kept.
+    fn maybe_reprepare(&mut self) -> Result<()> {
+        let Some(sql) = &self.sql else {
+            return Ok(());
+        };
+        let conn = self.program.connection.clone();
+        conn.maybe_update_schema();
+        let schema_version = conn.schema.borrow().schema_version;
+        if schema_version == self.schema_version {
+            return Ok(());
+        }
+        tracing::trace!("Repreparing: {}", sql);
+        let mut parser = Parser::new(sql.as_bytes());
+        let Some(Cmd::Stmt(stmt)) = parser.next()? else {
+            return Err(LimboError::InternalError(format!(
+                "cannot reprepare statement: {}",
+                sql
+            )));
+        };
+        let program = translate::translate(
+            conn.schema.borrow().deref(),
+            stmt,
+            self.pager.clone(),
+            conn.clone(),
+            &conn.syms.borrow(),
+            QueryMode::Normal,
+            sql,
+        )?;
+        program.n_change.set(self.program.n_change.get());
+        self.state
+            .reprepare(program.max_registers, program.cursor_ref.len());
+        self.program = Rc::new(program);
+        self.schema_version = schema_version;
+        Ok(())
+    }
+
     pub fn run_once(&self) -> Result<()> {
         self.pager.io.run_once()
     }
 
     pub fn num_columns(&self) -> usize {
-        self.program.result_columns.len()
+        self.column_count()
     }
 
     pub fn get_column_name(&self, idx: usize) -> Cow<str> {
-        let column = &self.program.result_columns.get(idx).expect("No column");
-        match column.name(&self.program.table_references) {
-            Some(name) => Cow::Borrowed(name),
-            None => Cow::Owned(column.expr.to_string()),
-        }
+        Cow::Borrowed(self.column_name(idx))
+    }
+
+    /// Returns true if executing the statement does not modify the database, so the caller
+    /// does not need to start a write transaction for it.
+    pub fn is_readonly(&self) -> bool {
+        self.program.is_readonly
+    }
+
+    /// Returns true if the statement is an EXPLAIN QUERY PLAN, whose rows describe the nodes
+    /// of a tree through their `id` and `parent` columns.
+    pub fn is_explain_query_plan(&self) -> bool {
+        self.program.query_mode == QueryMode::ExplainQueryPlan
+    }
+
+    /// Number of columns in the rows produced by this statement.
+    pub fn column_count(&self) -> usize {
+        self.program.result_column_metadata.len()
+    }
+
+    /// Name of the result column at `idx`: its alias, the name of the table column it reads,
+    /// or the text of its expression.
+    pub fn column_name(&self, idx: usize) -> &str {
+        &self
+            .program
+            .result_column_metadata
+            .get(idx)
+            .expect("No column")
+            .name
+    }
+
+    /// Declared type of the result column at `idx`, if it reads directly from a table column
+    /// that was declared with a type.
+    pub fn column_declared_type(&self, idx: usize) -> Option<&str> {
+        self.program
+            .result_column_metadata
+            .get(idx)
+            .expect("No column")
+            .declared_type
+            .as_deref()
     }
 
     pub fn parameters(&self) -> &parameters::Parameters {
@@ -906,12 +2200,151 @@ impl Statement {
         self.program.parameters.count()
     }
 
+    /// Returns the SQL text of the statement with every parameter replaced by the literal
+    /// of the value bound to it, like `sqlite3_expanded_sql`. Meant for logging and
+    /// debugging: text is quoted, blobs are written as `X'..'` and NULL as `NULL`.
+    ///
+    /// Fails with [LimboError::InvalidArgument] if a parameter is not bound.
+    pub fn expanded_sql(&self) -> Result<String> {
+        let Some(sql) = &self.sql else {
+            return Err(LimboError::InvalidArgument(
+                "statement has no SQL text".to_string(),
+            ));
+        };
+        // The n-th anonymous `?` in the text is the n-th anonymous parameter of the program.
+        let mut anonymous = self
+            .program
+            .parameters
+            .list
+            .iter()
+            .filter_map(|p| match p {
+                parameters::Parameter::Anonymous(index) => Some(*index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        anonymous.sort();
+        anonymous.dedup();
+        let mut anonymous = anonymous.into_iter();
+
+        let mut expanded = String::with_capacity(sql.len());
+        let mut copied = 0;
+        let mut scanner = Scanner::new(Tokenizer::new());
+        loop {
+            let (start, token, end) = scanner.scan(sql.as_bytes())?;
+            let Some((name, token_type)) = token else {
+                break;
+            };
+            if !matches!(token_type, TokenType::TK_VARIABLE) {
+                continue;
+            }
+            let name = str::from_utf8(name).unwrap();
+            let index = if name.is_empty() {
+                anonymous.next()
+            } else if let Ok(index) = name.parse::<usize>() {
+                NonZero::new(index)
+            } else {
+                self.program.parameters.index(name)
+            };
+            let Some(value) = index.and_then(|index| self.state.bound_parameter(index)) else {
+                return Err(LimboError::InvalidArgument(format!(
+                    "parameter {} is not bound",
+                    &sql[start..end]
+                )));
+            };
+            expanded.push_str(&sql[copied..start]);
+            expanded.push_str(&sql_literal(value));
+            copied = end;
+        }
+        expanded.push_str(&sql[copied..]);
+        Ok(expanded)
+    }
+
     pub fn bind_at(&mut self, index: NonZero<usize>, value: Value) {
         self.state.bind_at(index, value);
     }
 
     pub fn reset(&mut self) {
+        self.end_batch();
         self.state.reset();
+        self.started_at = None;
+        self.cpu_time = Duration::ZERO;
+    }
+
+    /// Executes the statement once for every row of parameters produced by `rows`,
+    /// binding the values of each row to parameters `1..=row.len()`. Result rows, if
+    /// any, are discarded.
+    ///
+    /// The program stays resident for the whole batch. Returns [StepResult::IO] when
+    /// I/O is pending and after every [BATCH_YIELD_INTERVAL] rows, so that the caller
+    /// can drive I/O and observe progress with [Statement::batch_rows_done]; call it
+    /// again with the same iterator to resume. Returns [StepResult::Done] once the
+    /// iterator is exhausted. The changes of the batch are reported to the connection
+    /// once, when the batch ends.
+    ///
+    /// If a row fails, the batch ends, the error is returned and
+    /// [Statement::batch_rows_done] is the index of the failing row. In autocommit
+    /// mode every row commits on its own, so the rows before the failing one stay
+    /// committed; inside an explicit transaction the caller decides whether to commit
+    /// or roll back.
+    pub fn execute_batch<I>(&mut self, rows: &mut I) -> Result<StepResult>
+    where
+        I: Iterator<Item = Vec<Value>>,
+    {
+        if self.batch.start_changes.is_none() {
+            self.batch = BatchProgress {
+                start_changes: Some(self.program.n_change.get()),
+                ..Default::default()
+            };
+            self.state.defer_change_count = true;
+        }
+        loop {
+            if !self.batch.in_row {
+                if self.batch.rows_since_yield == BATCH_YIELD_INTERVAL {
+                    self.batch.rows_since_yield = 0;
+                    return Ok(StepResult::IO);
+                }
+                let Some(row) = rows.next() else {
+                    self.end_batch();
+                    return Ok(StepResult::Done);
+                };
+                self.state.reset();
+                for (i, value) in row.into_iter().enumerate() {
+                    self.state.bind_at(NonZero::new(i + 1).unwrap(), value);
+                }
+                self.batch.in_row = true;
+            }
+            match self.step() {
+                Ok(StepResult::Row) => {}
+                Ok(StepResult::Done) => {
+                    self.batch.in_row = false;
+                    self.batch.rows_done += 1;
+                    self.batch.rows_since_yield += 1;
+                }
+                Ok(res) => return Ok(res),
+                Err(err) => {
+                    self.end_batch();
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Number of rows of the current or last batch that ran to completion.
+    pub fn batch_rows_done(&self) -> usize {
+        self.batch.rows_done
+    }
+
+    fn end_batch(&mut self) {
+        self.batch.in_row = false;
+        self.batch.rows_since_yield = 0;
+        if let Some(start_changes) = self.batch.start_changes.take() {
+            self.state.defer_change_count = false;
+            if self.program.change_cnt_on {
+                self.program
+                    .connection
+                    .set_changes(self.program.n_change.get() - start_changes);
+            }
+        }
     }
 
     pub fn row(&self) -> Option<&Row> {
@@ -921,6 +2354,21 @@ impl Statement {
     pub fn explain(&self) -> String {
         self.program.explain()
     }
+
+    /// Writes the bytecode of the statement to `w` in SQLite's EXPLAIN format.
+    pub fn explain_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        self.program.explain_to(w)
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        self.program
+            .connection
+            .close_state
+            .open_statements
+            .fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 pub type Row = vdbe::Row;
@@ -942,6 +2390,25 @@ impl std::fmt::Debug for SymbolTable {
     }
 }
 
+/// Writes `value` as an SQL literal, see [Statement::expanded_sql].
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_nan() => "NULL".to_string(),
+        // Out of range literals are read back as infinity.
+        Value::Float(f) if f.is_infinite() => if f.is_sign_positive() {
+            "9e999"
+        } else {
+            "-9e999"
+        }
+        .to_string(),
+        Value::Float(_) => value.to_string(),
+        Value::Text(t) => format!("'{}'", t.as_str().replace('\'', "''")),
+        Value::Blob(b) => format!("X'{}'", hex::encode_upper(b)),
+    }
+}
+
 fn is_shared_library(path: &std::path::Path) -> bool {
     path.extension()
         .map_or(false, |ext| ext == "so" || ext == "dylib" || ext == "dll")
@@ -25,7 +25,7 @@ impl Parameter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Parameters {
     index: NonZero<usize>,
     pub list: Vec<Parameter>,
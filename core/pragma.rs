@@ -34,6 +34,11 @@ fn pragma_for(pragma: PragmaName) -> Pragma {
     use PragmaName::*;
 
     match pragma {
+        AnalysisLimit => Pragma::new(PragmaFlags::Result0, &["analysis_limit"]),
+        CachePolicy => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["cache_policy"],
+        ),
         CacheSize => Pragma::new(
             PragmaFlags::NeedSchema
                 | PragmaFlags::Result0
@@ -41,6 +46,22 @@ fn pragma_for(pragma: PragmaName) -> Pragma {
                 | PragmaFlags::NoColumns1,
             &["cache_size"],
         ),
+        CheckpointRateLimit => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["checkpoint_rate_limit"],
+        ),
+        ChecksumVerification => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["checksum_verification"],
+        ),
+        ForeignKeys => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["foreign_keys"],
+        ),
+        IgnoreCheckConstraints => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["ignore_check_constraints"],
+        ),
         JournalMode => Pragma::new(
             PragmaFlags::NeedSchema | PragmaFlags::Result0 | PragmaFlags::SchemaReq,
             &["journal_mode"],
@@ -56,10 +77,24 @@ fn pragma_for(pragma: PragmaName) -> Pragma {
             PragmaFlags::Result0 | PragmaFlags::SchemaReq | PragmaFlags::NoColumns1,
             &["page_size"],
         ),
+        RecoveryReport => Pragma::new(
+            PragmaFlags::ReadOnly | PragmaFlags::Result0,
+            &[
+                "dirty_shutdown",
+                "frames_scanned",
+                "frames_applied",
+                "frames_discarded",
+                "truncated_tail_offset",
+            ],
+        ),
         SchemaVersion => Pragma::new(
             PragmaFlags::NoColumns1 | PragmaFlags::Result0,
             &["schema_version"],
         ),
+        Synchronous => Pragma::new(
+            PragmaFlags::NeedSchema | PragmaFlags::Result0 | PragmaFlags::SchemaReq,
+            &["synchronous"],
+        ),
         TableInfo => Pragma::new(
             PragmaFlags::NeedSchema | PragmaFlags::Result1 | PragmaFlags::SchemaOpt,
             &["cid", "name", "type", "notnull", "dflt_value", "pk"],
@@ -68,11 +103,23 @@ fn pragma_for(pragma: PragmaName) -> Pragma {
             PragmaFlags::NoColumns1 | PragmaFlags::Result0,
             &["user_version"],
         ),
+        WalAutocheckpoint => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["wal_autocheckpoint"],
+        ),
         WalCheckpoint => Pragma::new(PragmaFlags::NeedSchema, &["busy", "log", "checkpointed"]),
         AutoVacuum => Pragma::new(
             PragmaFlags::NoColumns1 | PragmaFlags::Result0,
             &["auto_vacuum"],
         ),
+        IndexInfo => Pragma::new(
+            PragmaFlags::NeedSchema | PragmaFlags::Result1 | PragmaFlags::SchemaOpt,
+            &["seqno", "cid", "name"],
+        ),
+        IndexList => Pragma::new(
+            PragmaFlags::NeedSchema | PragmaFlags::Result1 | PragmaFlags::SchemaOpt,
+            &["seq", "name", "unique", "origin", "partial"],
+        ),
         IntegrityCheck => Pragma::new(
             PragmaFlags::NeedSchema | PragmaFlags::ReadOnly | PragmaFlags::Result0,
             &["message"],
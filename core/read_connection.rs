@@ -0,0 +1,75 @@
+//! Read-only connections that can be shared between threads.
+//!
+//! A [Connection] keeps its pager, schema and statements behind `Rc` and `RefCell`, so it
+//! must stay on the thread that created it. A [ReadConnection] wraps one behind a mutex and
+//! only hands out owned rows: a statement is prepared, run to completion and dropped while
+//! the lock is held, so the state of the connection is never touched by two threads at once
+//! and nothing that refers to it leaves the lock. [crate::Database::close] only reads the
+//! atomics the connection shares with it. Every [ReadConnection] has its own [Connection], so
+//! several of them opened on the same [crate::Database] run queries in parallel, each in its
+//! own read transaction.
+
+use std::num::NonZero;
+use std::sync::{Arc, Mutex};
+
+use crate::{Connection, LimboError, Result, StepResult, Value};
+
+/// A connection that only runs read-only statements and can be shared between threads,
+/// created by [crate::Database::connect_read].
+pub struct ReadConnection {
+    conn: Mutex<Arc<Connection>>,
+}
+
+// SAFETY: the connection is only reachable through the mutex, which this type holds while
+// using it. [crate::Database::close] never touches the connection itself. Only owned values
+// are returned to the caller, see the module documentation.
+unsafe impl Send for ReadConnection {}
+unsafe impl Sync for ReadConnection {}
+
+impl ReadConnection {
+    pub(crate) fn new(conn: Arc<Connection>) -> Self {
+        conn.set_readonly(true);
+        Self {
+            conn: Mutex::new(conn),
+        }
+    }
+
+    /// Runs the statement `sql` and returns all its rows.
+    pub fn query(&self, sql: impl AsRef<str>) -> Result<Vec<Vec<Value>>> {
+        self.query_with_params(sql, &[])
+    }
+
+    /// Runs the statement `sql` with its parameters bound to `params`, in order, and returns
+    /// all its rows.
+    pub fn query_with_params(
+        &self,
+        sql: impl AsRef<str>,
+        params: &[Value],
+    ) -> Result<Vec<Vec<Value>>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| LimboError::InternalError("read connection lock poisoned".to_string()))?;
+        let mut stmt = conn.prepare(sql)?;
+        for (i, param) in params.iter().enumerate() {
+            stmt.bind_at(NonZero::new(i + 1).unwrap(), param.clone());
+        }
+        let mut rows = Vec::new();
+        loop {
+            match stmt.step()? {
+                StepResult::Row => {
+                    let row = stmt.row().expect("a row was just returned");
+                    rows.push(row.get_values().cloned().collect());
+                }
+                StepResult::IO => stmt.run_once()?,
+                StepResult::Done => return Ok(rows),
+                StepResult::Interrupt => {
+                    return Err(LimboError::InternalError(
+                        "statement was interrupted".to_string(),
+                    ))
+                }
+                StepResult::Busy => return Err(LimboError::Busy),
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Connections to databases served over the libSQL HTTP protocol, such as Turso databases.
+//!
+//! A [RemoteConnection] sends statements to the server's `/v2/pipeline` endpoint and gets
+//! their rows back in the response, so a [RemoteStatement] is stepped through the same way as
+//! a local [crate::Statement]. The server keeps the connection state, transactions included,
+//! in a stream that every request after the first one refers to by its baton.
+//!
+//! Statements are sent when first stepped and their whole result comes back at once.
+//! Statements run through [RemoteConnection::execute], whose rows nobody reads, are queued
+//! instead and ride along with the next request, as does closing the stream, so that a run of
+//! writes followed by a query costs a single round trip. [RemoteConnection::execute_batch]
+//! sends all the statements of a script in one request.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZero;
+use std::sync::Arc;
+
+use base64::Engine;
+use serde_json::json;
+
+use crate::vdbe::{Register, ResultColumnMetadata, Row};
+use crate::{LimboError, Result, StepResult, Value};
+
+/// A connection to a database on a libSQL server.
+pub struct RemoteConnection {
+    /// Where the pipeline requests go, which the server may move to keep a stream on the same
+    /// instance.
+    base_url: RefCell<String>,
+    auth_token: String,
+    /// Identifies the server side stream once the first request opened it.
+    baton: RefCell<Option<String>>,
+    /// Requests queued to be sent ahead of the next one.
+    pending: RefCell<Vec<serde_json::Value>>,
+    last_insert_rowid: Cell<i64>,
+    changes: Cell<i64>,
+}
+
+impl RemoteConnection {
+    /// Opens a connection to the database at `url`, authenticating with `auth_token` unless
+    /// it's empty. `libsql://` URLs are reached over HTTPS.
+    pub fn open(url: &str, auth_token: &str) -> Result<Arc<RemoteConnection>> {
+        let base_url = match url.strip_prefix("libsql://") {
+            Some(host) => format!("https://{host}"),
+            None if url.starts_with("https://") || url.starts_with("http://") => url.to_string(),
+            None => {
+                return Err(LimboError::InvalidArgument(format!(
+                    "unsupported database URL: {url}"
+                )))
+            }
+        };
+        Ok(Arc::new(RemoteConnection {
+            base_url: RefCell::new(base_url.trim_end_matches('/').to_string()),
+            auth_token: auth_token.to_string(),
+            baton: RefCell::new(None),
+            pending: RefCell::new(Vec::new()),
+            last_insert_rowid: Cell::new(0),
+            changes: Cell::new(0),
+        }))
+    }
+
+    pub fn prepare(self: &Arc<RemoteConnection>, sql: impl AsRef<str>) -> Result<RemoteStatement> {
+        Ok(RemoteStatement {
+            conn: self.clone(),
+            sql: sql.as_ref().to_string(),
+            parameters: HashMap::new(),
+            columns: Vec::new(),
+            rows: VecDeque::new(),
+            current: Vec::new(),
+            result_row: None,
+            executed: false,
+        })
+    }
+
+    /// Queues the statement `sql`, discarding its rows. It's sent with the next request, which
+    /// fails with its error if it does.
+    pub fn execute(&self, sql: impl AsRef<str>) -> Result<()> {
+        self.pending.borrow_mut().push(json!({
+            "type": "execute",
+            "stmt": { "sql": sql.as_ref(), "want_rows": false },
+        }));
+        Ok(())
+    }
+
+    /// Runs every statement of `sql` in a single request.
+    pub fn execute_batch(&self, sql: impl AsRef<str>) -> Result<()> {
+        self.pipeline(vec![json!({
+            "type": "sequence",
+            "sql": sql.as_ref(),
+        })])?;
+        Ok(())
+    }
+
+    /// Sends the queued statements.
+    pub fn flush(&self) -> Result<()> {
+        if !self.pending.borrow().is_empty() {
+            self.pipeline(Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the rowid of the last insert sent to the server, queued ones not included.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.last_insert_rowid.get()
+    }
+
+    /// Returns the rows changed by the last statement sent to the server, queued ones not
+    /// included.
+    pub fn changes(&self) -> i64 {
+        self.changes.get()
+    }
+
+    /// Sends the queued statements and closes the stream on the server in the same request,
+    /// rolling back any transaction left open.
+    pub fn close(&self) -> Result<()> {
+        if self.baton.borrow().is_some() || !self.pending.borrow().is_empty() {
+            let result = self.pipeline(vec![json!({ "type": "close" })]);
+            self.baton.replace(None);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Sends the queued requests followed by `requests` in one pipeline request, and returns
+    /// the responses to `requests`, or the error of the first request that failed.
+    fn pipeline(&self, requests: Vec<serde_json::Value>) -> Result<Vec<serde_json::Value>> {
+        let queued = self.pending.take();
+        let num_queued = queued.len();
+        let body = json!({
+            "baton": *self.baton.borrow(),
+            "requests": queued.into_iter().chain(requests).collect::<Vec<_>>(),
+        });
+        let url = format!("{}/v2/pipeline", self.base_url.borrow());
+        tracing::debug!("pipeline({url}, {body})");
+        let mut request = ureq::post(&url);
+        if !self.auth_token.is_empty() {
+            request = request.set("Authorization", &format!("Bearer {}", self.auth_token));
+        }
+        let response: serde_json::Value = request
+            .send_json(body)
+            .map_err(|e| LimboError::RemoteError(e.to_string()))?
+            .into_json()
+            .map_err(|e| LimboError::RemoteError(e.to_string()))?;
+
+        self.baton
+            .replace(response["baton"].as_str().map(String::from));
+        if let Some(base_url) = response["base_url"].as_str() {
+            self.base_url
+                .replace(base_url.trim_end_matches('/').to_string());
+        }
+        let results = response["results"]
+            .as_array()
+            .ok_or_else(|| LimboError::RemoteError("response has no results".to_string()))?;
+        let responses = results
+            .iter()
+            .map(|result| match result["type"].as_str() {
+                Some("ok") => Ok(result["response"].clone()),
+                _ => Err(LimboError::RemoteError(
+                    result["error"]["message"]
+                        .as_str()
+                        .unwrap_or("request failed")
+                        .to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for response in responses.iter().take(num_queued) {
+            self.update_counters(&response["result"]);
+        }
+        Ok(responses.into_iter().skip(num_queued).collect())
+    }
+
+    /// Records the changes and last rowid of a statement result.
+    fn update_counters(&self, result: &serde_json::Value) {
+        if let Some(changes) = result["affected_row_count"].as_i64() {
+            self.changes.set(changes);
+        }
+        if let Some(rowid) = result["last_insert_rowid"]
+            .as_str()
+            .and_then(|rowid| rowid.parse().ok())
+        {
+            self.last_insert_rowid.set(rowid);
+        }
+    }
+}
+
+/// A statement prepared on a [RemoteConnection].
+pub struct RemoteStatement {
+    conn: Arc<RemoteConnection>,
+    sql: String,
+    parameters: HashMap<NonZero<usize>, Value>,
+    columns: Vec<ResultColumnMetadata>,
+    /// Rows the server returned that weren't stepped to yet.
+    rows: VecDeque<Vec<Register>>,
+    /// Values of the row [RemoteStatement::row] points to.
+    current: Vec<Register>,
+    result_row: Option<Row>,
+    executed: bool,
+}
+
+impl RemoteStatement {
+    pub fn step(&mut self) -> Result<StepResult> {
+        if !self.executed {
+            self.execute()?;
+        }
+        self.result_row = None;
+        match self.rows.pop_front() {
+            Some(row) => {
+                self.current = row;
+                self.result_row = Some(Row::new(&self.current, &self.columns));
+                Ok(StepResult::Row)
+            }
+            None => Ok(StepResult::Done),
+        }
+    }
+
+    pub fn row(&self) -> Option<&Row> {
+        self.result_row.as_ref()
+    }
+
+    /// Returns the number of columns of the rows, which is only known after the first step.
+    pub fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn get_column_name(&self, idx: usize) -> &str {
+        &self.columns[idx].name
+    }
+
+    pub fn bind_at(&mut self, index: NonZero<usize>, value: Value) {
+        self.parameters.insert(index, value);
+    }
+
+    /// Makes the next step run the statement again.
+    pub fn reset(&mut self) {
+        self.rows.clear();
+        self.result_row = None;
+        self.executed = false;
+    }
+
+    fn execute(&mut self) -> Result<()> {
+        let args = match self.parameters.keys().max() {
+            Some(max) => (1..=max.get())
+                .map(|i| {
+                    let value = NonZero::new(i).and_then(|i| self.parameters.get(&i));
+                    value_to_json(value.unwrap_or(&Value::Null))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let responses = self.conn.pipeline(vec![json!({
+            "type": "execute",
+            "stmt": { "sql": self.sql, "args": args },
+        })])?;
+        let result = &responses[0]["result"];
+        self.columns = result["cols"]
+            .as_array()
+            .map(|cols| {
+                cols.iter()
+                    .map(|col| ResultColumnMetadata {
+                        name: col["name"].as_str().unwrap_or("").to_string(),
+                        declared_type: col["decltype"].as_str().map(String::from),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.rows = match result["rows"].as_array() {
+            Some(rows) => rows
+                .iter()
+                .map(|row| {
+                    row.as_array()
+                        .map(Vec::as_slice)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|value| json_to_value(value).map(Register::Value))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<_>>()?,
+            None => VecDeque::new(),
+        };
+        self.conn.update_counters(result);
+        self.executed = true;
+        Ok(())
+    }
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => json!({ "type": "null" }),
+        Value::Integer(i) => json!({ "type": "integer", "value": i.to_string() }),
+        Value::Float(f) => json!({ "type": "float", "value": f }),
+        Value::Text(t) => json!({ "type": "text", "value": t.as_str() }),
+        Value::Blob(b) => json!({
+            "type": "blob",
+            "base64": base64::engine::general_purpose::STANDARD_NO_PAD.encode(b),
+        }),
+    }
+}
+
+fn json_to_value(value: &serde_json::Value) -> Result<Value> {
+    let invalid = || LimboError::RemoteError(format!("invalid value in response: {value}"));
+    match value["type"].as_str() {
+        Some("null") => Ok(Value::Null),
+        Some("integer") => value["value"]
+            .as_str()
+            .and_then(|i| i.parse().ok())
+            .map(Value::Integer)
+            .ok_or_else(invalid),
+        Some("float") => value["value"]
+            .as_f64()
+            .map(Value::Float)
+            .ok_or_else(invalid),
+        Some("text") => value["value"]
+            .as_str()
+            .map(Value::build_text)
+            .ok_or_else(invalid),
+        Some("blob") => value["base64"]
+            .as_str()
+            .and_then(|b| {
+                // The server may or may not pad the encoding.
+                base64::engine::general_purpose::STANDARD_NO_PAD
+                    .decode(b.trim_end_matches('='))
+                    .ok()
+            })
+            .map(Value::Blob)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_json_round_trip() {
+        for value in [
+            Value::Null,
+            Value::Integer(i64::MIN),
+            Value::Float(1.5),
+            Value::build_text("hello"),
+            Value::Blob(vec![0, 1, 2, 255]),
+        ] {
+            assert_eq!(json_to_value(&value_to_json(&value)).unwrap(), value);
+        }
+        assert!(json_to_value(&json!({ "type": "integer", "value": "x" })).is_err());
+    }
+
+    #[test]
+    fn test_open_url() {
+        let conn = RemoteConnection::open("libsql://db.example.com/", "").unwrap();
+        assert_eq!(*conn.base_url.borrow(), "https://db.example.com");
+        let conn = RemoteConnection::open("http://127.0.0.1:8080", "").unwrap();
+        assert_eq!(*conn.base_url.borrow(), "http://127.0.0.1:8080");
+        assert!(RemoteConnection::open("db.example.com", "").is_err());
+    }
+
+    #[test]
+    fn test_execute_is_queued() {
+        // Nothing listens on the port, so any request fails.
+        let conn = RemoteConnection::open("http://127.0.0.1:1", "").unwrap();
+        conn.execute("CREATE TABLE t (x)").unwrap();
+        conn.execute("INSERT INTO t VALUES (1)").unwrap();
+        assert_eq!(conn.pending.borrow().len(), 2);
+
+        // The queued statements go out with the close request, in a single request.
+        assert!(conn.close().is_err());
+        assert!(conn.pending.borrow().is_empty());
+        conn.close().unwrap();
+    }
+}
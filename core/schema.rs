@@ -1,9 +1,11 @@
 use crate::translate::collate::CollationSeq;
-use crate::translate::plan::SelectPlan;
-use crate::{util::normalize_ident, Result};
+use crate::translate::plan::{RecursiveCte, SelectPlan};
+use crate::util::{normalize_ident, unquote_ident};
+use crate::Result;
 use crate::{LimboError, VirtualTable};
 use core::fmt;
 use fallible_iterator::FallibleIterator;
+use std::cell::OnceCell;
 use std::collections::{BTreeSet, HashMap};
 use std::rc::Rc;
 use std::sync::Arc;
@@ -11,9 +13,13 @@ use tracing::trace;
 use turso_sqlite3_parser::ast::{self, ColumnDefinition, Expr, Literal, SortOrder, TableOptions};
 use turso_sqlite3_parser::{
     ast::{Cmd, CreateTableBody, QualifiedName, ResultColumn, Stmt},
+    dialect::quote_identifier,
     lexer::sql::Parser,
 };
 
+/// Maximum number of columns in a table or result set (SQLITE_MAX_COLUMN).
+pub const MAX_COLUMNS: usize = 2000;
+
 const SCHEMA_TABLE_NAME: &str = "sqlite_schema";
 const SCHEMA_TABLE_NAME_ALT: &str = "sqlite_master";
 
@@ -25,6 +31,8 @@ pub struct Schema {
     pub has_indexes: std::collections::HashSet<String>,
     pub indexes_enabled: bool,
     pub schema_version: u32,
+    /// table_name to the statistics ANALYZE stored for the table in `sqlite_stat1`
+    pub stats: HashMap<String, TableStats>,
 }
 
 impl Schema {
@@ -43,6 +51,7 @@ impl Schema {
             has_indexes,
             indexes_enabled,
             schema_version: 0,
+            stats: HashMap::new(),
         }
     }
 
@@ -76,6 +85,7 @@ impl Schema {
     pub fn remove_table(&mut self, table_name: &str) {
         let name = normalize_ident(table_name);
         self.tables.remove(&name);
+        self.stats.remove(&name);
     }
 
     pub fn get_btree_table(&self, name: &str) -> Option<Rc<BTreeTable>> {
@@ -124,16 +134,66 @@ impl Schema {
     }
 
     pub fn table_has_indexes(&self, table_name: &str) -> bool {
-        self.has_indexes.contains(table_name)
+        self.has_indexes.contains(&normalize_ident(table_name))
     }
 
     pub fn table_set_has_index(&mut self, table_name: &str) {
-        self.has_indexes.insert(table_name.to_string());
+        self.has_indexes.insert(normalize_ident(table_name));
     }
 
     pub fn indexes_enabled(&self) -> bool {
         self.indexes_enabled
     }
+
+    pub fn get_table_stats(&self, table_name: &str) -> Option<&TableStats> {
+        self.stats.get(&normalize_ident(table_name))
+    }
+
+    /// Replaces the statistics with the rows of `sqlite_stat1`, given as (tbl, idx, stat).
+    /// Rows whose stat can't be parsed are ignored, like SQLite does.
+    pub fn set_stats(&mut self, rows: impl IntoIterator<Item = (String, Option<String>, String)>) {
+        self.stats.clear();
+        for (table_name, index_name, stat) in rows {
+            let Some((row_count, rows_per_key)) = parse_stat(&stat) else {
+                continue;
+            };
+            let stats = self.stats.entry(normalize_ident(&table_name)).or_default();
+            stats.row_count = stats.row_count.max(row_count);
+            if let Some(index_name) = index_name {
+                stats
+                    .index_rows_per_key
+                    .insert(normalize_ident(&index_name), rows_per_key);
+            }
+        }
+    }
+}
+
+/// Statistics gathered by ANALYZE for a table and its indexes.
+#[derive(Debug, Clone, Default)]
+pub struct TableStats {
+    /// The number of rows in the table.
+    pub row_count: u64,
+    /// index_name to the average number of rows sharing the same value of the first 1, 2, ...
+    /// columns of the index.
+    pub index_rows_per_key: HashMap<String, Vec<u64>>,
+}
+
+impl TableStats {
+    pub fn index_rows_per_key(&self, index_name: &str) -> Option<&[u64]> {
+        self.index_rows_per_key
+            .get(&normalize_ident(index_name))
+            .map(|v| v.as_slice())
+    }
+}
+
+/// Parses the `stat` column of a `sqlite_stat1` row: the row count followed by the average
+/// number of rows per distinct key prefix. Trailing `key=value` options are ignored.
+fn parse_stat(stat: &str) -> Option<(u64, Vec<u64>)> {
+    let mut numbers = stat
+        .split_ascii_whitespace()
+        .map_while(|n| n.parse::<u64>().ok());
+    let row_count = numbers.next()?;
+    Some((row_count, numbers.collect()))
 }
 
 #[derive(Clone, Debug)]
@@ -170,6 +230,19 @@ impl Table {
         }
     }
 
+    /// Returns the position and definition of the column named `name`,
+    /// which must already be normalized.
+    pub fn get_column_by_name(&self, name: &str) -> Option<(usize, &Column)> {
+        match self {
+            Self::BTree(table) => table.get_column_normalized(name),
+            _ => self.columns().iter().enumerate().find(|(_, c)| {
+                c.name
+                    .as_ref()
+                    .is_some_and(|col_name| col_name.eq_ignore_ascii_case(name))
+            }),
+        }
+    }
+
     pub fn columns(&self) -> &Vec<Column> {
         match self {
             Self::BTree(table) => &table.columns,
@@ -212,7 +285,109 @@ pub struct BTreeTable {
     pub columns: Vec<Column>,
     pub has_rowid: bool,
     pub is_strict: bool,
+    /// Whether the rowid alias column is declared `INTEGER PRIMARY KEY AUTOINCREMENT`, in
+    /// which case the largest rowid ever used is kept in `sqlite_sequence`.
+    pub has_autoincrement: bool,
     pub unique_sets: Option<Vec<Vec<(String, SortOrder)>>>,
+    /// Column name to position map, built on first lookup. Keeps name
+    /// resolution O(1) on tables with thousands of columns.
+    pub(crate) column_lookup: OnceCell<HashMap<String, usize>>,
+    pub foreign_keys: Vec<ForeignKey>,
+    /// CHECK constraints of the table and its columns, in declaration order.
+    pub checks: Vec<CheckConstraint>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckConstraint {
+    pub name: Option<String>,
+    pub expr: Expr,
+}
+
+impl CheckConstraint {
+    /// How the constraint is referred to in the error raised when it fails: its name, or
+    /// the expression if it has none.
+    pub fn description(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.expr.to_string(),
+        }
+    }
+}
+
+/// A `FOREIGN KEY` constraint, declared either as a table constraint or as a `REFERENCES`
+/// column constraint.
+#[derive(Debug, Clone)]
+pub struct ForeignKey {
+    /// Columns of the table that declares the constraint (the child table).
+    pub child_columns: Vec<String>,
+    pub parent_table: String,
+    /// Referenced columns of the parent table. Empty if the constraint refers to the
+    /// parent's primary key.
+    pub parent_columns: Vec<String>,
+    pub on_delete: ast::RefAct,
+    pub on_update: ast::RefAct,
+}
+
+impl ForeignKey {
+    fn new(child_columns: Vec<String>, clause: &ast::ForeignKeyClause) -> Result<Self> {
+        let parent_columns = match &clause.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|column| normalize_ident(&column.col_name.0))
+                .collect(),
+            None => vec![],
+        };
+        if !parent_columns.is_empty() && parent_columns.len() != child_columns.len() {
+            return Err(LimboError::ParseError(
+                "number of columns in foreign key does not match the number of columns in the referenced table".to_string(),
+            ));
+        }
+        let mut on_delete = ast::RefAct::NoAction;
+        let mut on_update = ast::RefAct::NoAction;
+        for arg in &clause.args {
+            match arg {
+                ast::RefArg::OnDelete(action) => on_delete = *action,
+                ast::RefArg::OnUpdate(action) => on_update = *action,
+                ast::RefArg::OnInsert(_) | ast::RefArg::Match(_) => {}
+            }
+        }
+        Ok(Self {
+            child_columns,
+            parent_table: normalize_ident(&clause.tbl_name.0),
+            parent_columns,
+            on_delete,
+            on_update,
+        })
+    }
+
+    fn to_sql(&self) -> String {
+        let quote_list = |columns: &[String]| {
+            columns
+                .iter()
+                .map(|column| quote_identifier(column))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut sql = format!(
+            "FOREIGN KEY ({}) REFERENCES {}",
+            quote_list(&self.child_columns),
+            quote_identifier(&self.parent_table)
+        );
+        if !self.parent_columns.is_empty() {
+            sql.push_str(&format!(" ({})", quote_list(&self.parent_columns)));
+        }
+        for (event, action) in [("DELETE", self.on_delete), ("UPDATE", self.on_update)] {
+            let action = match action {
+                ast::RefAct::NoAction => continue,
+                ast::RefAct::SetNull => "SET NULL",
+                ast::RefAct::SetDefault => "SET DEFAULT",
+                ast::RefAct::Cascade => "CASCADE",
+                ast::RefAct::Restrict => "RESTRICT",
+            };
+            sql.push_str(&format!(" ON {event} {action}"));
+        }
+        sql
+    }
 }
 
 impl BTreeTable {
@@ -235,12 +410,115 @@ impl BTreeTable {
     /// E.g. if table is CREATE TABLE t(a, b, c)
     /// then get_column("b") returns (1, &Column { .. })
     pub fn get_column(&self, name: &str) -> Option<(usize, &Column)> {
-        let name = normalize_ident(name);
+        self.get_column_normalized(&normalize_ident(name))
+    }
+
+    /// Like [BTreeTable::get_column], but `name` must already be normalized.
+    pub fn get_column_normalized(&self, name: &str) -> Option<(usize, &Column)> {
+        let lookup = self.column_lookup.get_or_init(|| {
+            let mut lookup = HashMap::with_capacity(self.columns.len());
+            for (idx, column) in self.columns.iter().enumerate() {
+                if let Some(name) = &column.name {
+                    lookup.entry(name.to_lowercase()).or_insert(idx);
+                }
+            }
+            lookup
+        });
+        let idx = *lookup.get(name)?;
+        Some((idx, &self.columns[idx]))
+    }
+
+    pub fn add_column(&mut self, column: Column) {
+        self.columns.push(column);
+        self.column_lookup = OnceCell::new();
+    }
+
+    pub fn remove_column(&mut self, index: usize) -> Column {
+        self.column_lookup = OnceCell::new();
+        self.columns.remove(index)
+    }
 
+    /// The affinity character of every column, in order, as expected by `Insn::Affinity`.
+    pub fn column_affinities(&self) -> String {
         self.columns
             .iter()
-            .enumerate()
-            .find(|(_, column)| column.name.as_ref() == Some(&name))
+            .map(|column| column.affinity().aff_mask())
+            .collect()
+    }
+
+    /// The columns stored in the table's records, in record order. VIRTUAL generated columns
+    /// are not stored, and the records of a WITHOUT ROWID table start with its primary key.
+    pub fn record_columns(&self) -> Vec<usize> {
+        let primary_key = if self.has_rowid {
+            vec![]
+        } else {
+            self.primary_key_columns
+                .iter()
+                .filter_map(|(name, _)| self.get_column(name).map(|(idx, _)| idx))
+                .collect()
+        };
+        let rest = (0..self.columns.len())
+            .filter(|idx| !self.columns[*idx].is_virtual() && !primary_key.contains(idx))
+            .collect::<Vec<_>>();
+        [primary_key, rest].concat()
+    }
+
+    /// Position of the column in the table's records, or `None` for a VIRTUAL generated
+    /// column, which is not stored.
+    pub fn column_record_pos(&self, column: usize) -> Option<usize> {
+        if self.has_rowid {
+            if self.columns[column].is_virtual() {
+                return None;
+            }
+            return Some(
+                self.columns[..column]
+                    .iter()
+                    .filter(|column| !column.is_virtual())
+                    .count(),
+            );
+        }
+        self.record_columns().iter().position(|idx| *idx == column)
+    }
+
+    /// The index b-tree a WITHOUT ROWID table is stored in: it is keyed by the primary key
+    /// columns, followed by the other columns.
+    pub fn without_rowid_index(&self) -> Index {
+        assert!(!self.has_rowid);
+        let columns = self
+            .record_columns()
+            .into_iter()
+            .map(|idx| {
+                let column = &self.columns[idx];
+                let name = column.name.clone().expect("column name is None");
+                let order = self
+                    .primary_key_columns
+                    .iter()
+                    .find(|(pk_name, _)| *pk_name == name)
+                    .map_or(SortOrder::Asc, |(_, order)| *order);
+                IndexColumn {
+                    name,
+                    order,
+                    pos_in_table: idx,
+                    collation: column.collation,
+                    default: column.default.clone(),
+                    expr: None,
+                }
+            })
+            .collect();
+        Index {
+            name: format!("sqlite_autoindex_{}_1", self.name),
+            table_name: self.name.clone(),
+            root_page: self.root_page,
+            columns,
+            unique: true,
+            ephemeral: false,
+            has_rowid: false,
+            where_clause: None,
+        }
+    }
+
+    pub fn has_virtual_columns(&self) -> bool {
+        self.columns.iter().any(|column| column.is_virtual())
     }
 
     pub fn from_sql(sql: &str, root_page: usize) -> Result<BTreeTable> {
@@ -255,12 +533,14 @@ impl BTreeTable {
     }
 
     pub fn to_sql(&self) -> String {
-        let mut sql = format!("CREATE TABLE {} (", self.name);
+        let mut sql = format!("CREATE TABLE {} (", quote_identifier(&self.name));
         for (i, column) in self.columns.iter().enumerate() {
             if i > 0 {
                 sql.push_str(", ");
             }
-            sql.push_str(column.name.as_ref().expect("column name is None"));
+            sql.push_str(&quote_identifier(
+                column.name.as_ref().expect("column name is None"),
+            ));
             if !matches!(column.ty, Type::Null) {
                 sql.push(' ');
             }
@@ -272,12 +552,35 @@ impl BTreeTable {
 
             if column.primary_key {
                 sql.push_str(" PRIMARY KEY");
+                if column.is_rowid_alias && self.has_autoincrement {
+                    sql.push_str(" AUTOINCREMENT");
+                }
             }
 
             if let Some(default) = &column.default {
                 sql.push_str(" DEFAULT ");
                 sql.push_str(&default.to_string());
             }
+
+            if let Some(expr) = &column.generated_expr {
+                sql.push_str(&format!(" GENERATED ALWAYS AS ({expr})"));
+                sql.push_str(if column.generated_stored {
+                    " STORED"
+                } else {
+                    " VIRTUAL"
+                });
+            }
+        }
+        for check in &self.checks {
+            sql.push_str(", ");
+            if let Some(name) = &check.name {
+                sql.push_str(&format!("CONSTRAINT {} ", quote_identifier(name)));
+            }
+            sql.push_str(&format!("CHECK ({})", check.expr));
+        }
+        for foreign_key in &self.foreign_keys {
+            sql.push_str(", ");
+            sql.push_str(&foreign_key.to_sql());
         }
         sql.push(')');
         sql
@@ -317,6 +620,8 @@ pub struct FromClauseSubquery {
     /// The start register for the result columns of the derived table;
     /// must be set before data is read from it.
     pub result_columns_start_reg: Option<usize>,
+    /// Set if the derived table is a recursive CTE or the reference to one in its recursive SELECT.
+    pub recursive_cte: Option<RecursiveCte>,
 }
 
 #[derive(Debug, Eq)]
@@ -351,11 +656,15 @@ fn create_table(
     let table_name = normalize_ident(&tbl_name.name.0);
     trace!("Creating table {}", table_name);
     let mut has_rowid = true;
+    let mut has_autoincrement = false;
     let mut primary_key_columns = vec![];
     let mut cols = vec![];
     let is_strict: bool;
     // BtreeSet here to preserve order of inserted keys
     let mut unique_sets: Vec<BTreeSet<UniqueColumnProps>> = vec![];
+    let mut foreign_keys = vec![];
+    let mut checks = vec![];
+    let mut table_checks = vec![];
     match body {
         CreateTableBody::ColumnsAndConstraints {
             columns,
@@ -366,15 +675,16 @@ fn create_table(
             if let Some(constraints) = constraints {
                 for c in constraints {
                     if let turso_sqlite3_parser::ast::TableConstraint::PrimaryKey {
-                        columns, ..
+                        columns,
+                        auto_increment,
+                        ..
                     } = c.constraint
                     {
+                        has_autoincrement |= auto_increment;
                         for column in columns {
                             let col_name = match column.expr {
                                 Expr::Id(id) => normalize_ident(&id.0),
-                                Expr::Literal(Literal::String(value)) => {
-                                    value.trim_matches('\'').to_owned()
-                                }
+                                Expr::Literal(Literal::String(value)) => normalize_ident(&value),
                                 _ => {
                                     todo!("Unsupported primary key expression");
                                 }
@@ -406,11 +716,29 @@ fn create_table(
                             })
                             .collect();
                         unique_sets.push(unique_set);
+                    } else if let turso_sqlite3_parser::ast::TableConstraint::ForeignKey {
+                        columns,
+                        clause,
+                        ..
+                    } = &c.constraint
+                    {
+                        let child_columns = columns
+                            .iter()
+                            .map(|column| normalize_ident(&column.col_name.0))
+                            .collect();
+                        foreign_keys.push(ForeignKey::new(child_columns, clause)?);
+                    } else if let turso_sqlite3_parser::ast::TableConstraint::Check(expr) =
+                        &c.constraint
+                    {
+                        table_checks.push(CheckConstraint {
+                            name: c.name.as_ref().map(|name| unquote_ident(&name.0)),
+                            expr: expr.clone(),
+                        });
                     }
                 }
             }
             for (col_name, col_def) in columns {
-                let name = col_name.0.to_string();
+                let name = normalize_ident(&col_name.0);
                 // Regular sqlite tables have an integer rowid that uniquely identifies a row.
                 // Even if you create a table with a column e.g. 'id INT PRIMARY KEY', there will still
                 // be a separate hidden rowid, and the 'id' column will have a separate index built for it.
@@ -464,13 +792,17 @@ fn create_table(
                 let mut order = SortOrder::Asc;
                 let mut unique = false;
                 let mut collation = None;
+                let mut generated_expr = None;
+                let mut generated_stored = false;
                 for c_def in &col_def.constraints {
                     match &c_def.constraint {
                         turso_sqlite3_parser::ast::ColumnConstraint::PrimaryKey {
                             order: o,
+                            auto_increment,
                             ..
                         } => {
                             primary_key = true;
+                            has_autoincrement |= auto_increment;
                             if let Some(o) = o {
                                 order = *o;
                             }
@@ -491,6 +823,21 @@ fn create_table(
                         turso_sqlite3_parser::ast::ColumnConstraint::Collate { collation_name } => {
                             collation = Some(CollationSeq::new(collation_name.0.as_str())?);
                         }
+                        turso_sqlite3_parser::ast::ColumnConstraint::ForeignKey {
+                            clause, ..
+                        } => {
+                            foreign_keys.push(ForeignKey::new(vec![name.clone()], clause)?);
+                        }
+                        turso_sqlite3_parser::ast::ColumnConstraint::Check(expr) => {
+                            checks.push(CheckConstraint {
+                                name: c_def.name.as_ref().map(|name| unquote_ident(&name.0)),
+                                expr: expr.clone(),
+                            });
+                        }
+                        turso_sqlite3_parser::ast::ColumnConstraint::Generated { expr, typ } => {
+                            generated_expr = Some(expr.clone());
+                            generated_stored = generated_column_is_stored(typ)?;
+                        }
                         // Collate
                         _ => {}
                     }
@@ -506,7 +853,7 @@ fn create_table(
                 }
 
                 cols.push(Column {
-                    name: Some(normalize_ident(&name)),
+                    name: Some(name),
                     ty,
                     ty_str,
                     primary_key,
@@ -515,6 +862,8 @@ fn create_table(
                     default,
                     unique,
                     collation,
+                    generated_expr,
+                    generated_stored,
                 });
             }
             if options.contains(TableOptions::WITHOUT_ROWID) {
@@ -523,6 +872,8 @@ fn create_table(
         }
         CreateTableBody::AsSelect(_) => todo!(),
     };
+    // Table constraints follow the column definitions, so their checks run last.
+    checks.extend(table_checks);
     // flip is_rowid_alias back to false if the table has multiple primary keys
     // or if the table has no rowid
     if !has_rowid || primary_key_columns.len() > 1 {
@@ -537,6 +888,9 @@ fn create_table(
         primary_key_columns,
         columns: cols,
         is_strict,
+        has_autoincrement,
+        foreign_keys,
+        checks,
         unique_sets: if unique_sets.is_empty() {
             None
         } else {
@@ -553,6 +907,7 @@ fn create_table(
                     .collect(),
             )
         },
+        column_lookup: Default::default(),
     })
 }
 
@@ -586,11 +941,38 @@ pub struct Column {
     pub default: Option<Expr>,
     pub unique: bool,
     pub collation: Option<CollationSeq>,
+    /// The expression of a `GENERATED ALWAYS AS (expr)` column.
+    pub generated_expr: Option<Expr>,
+    /// Whether a generated column is STORED in the table's records. VIRTUAL columns are
+    /// computed whenever they are read.
+    pub generated_stored: bool,
 }
 
 impl Column {
     pub fn affinity(&self) -> Affinity {
-        affinity(&self.ty_str)
+        affinity(&self.ty_str.to_uppercase())
+    }
+
+    pub fn is_generated(&self) -> bool {
+        self.generated_expr.is_some()
+    }
+
+    /// Whether this is a VIRTUAL generated column, which has no value in the table's records.
+    pub fn is_virtual(&self) -> bool {
+        self.is_generated() && !self.generated_stored
+    }
+}
+
+/// Parses the `STORED` / `VIRTUAL` keyword of a generated column, VIRTUAL being the default.
+fn generated_column_is_stored(typ: &Option<ast::Id>) -> Result<bool> {
+    match typ {
+        None => Ok(false),
+        Some(typ) if typ.0.eq_ignore_ascii_case("virtual") => Ok(false),
+        Some(typ) if typ.0.eq_ignore_ascii_case("stored") => Ok(true),
+        Some(typ) => Err(LimboError::ParseError(format!(
+            "unknown generated column type: {}",
+            typ.0
+        ))),
     }
 }
 
@@ -604,6 +986,8 @@ impl From<ColumnDefinition> for Column {
         let mut primary_key = false;
         let mut unique = false;
         let mut collation = None;
+        let mut generated_expr = None;
+        let mut generated_stored = false;
 
         for ast::NamedColumnConstraint { constraint, .. } in value.constraints {
             match constraint {
@@ -619,6 +1003,10 @@ impl From<ColumnDefinition> for Column {
                             .expect("collation should have been set correctly in create table"),
                     );
                 }
+                ast::ColumnConstraint::Generated { expr, typ } => {
+                    generated_stored = typ.is_some_and(|typ| typ.0.eq_ignore_ascii_case("stored"));
+                    generated_expr.replace(expr);
+                }
                 _ => {}
             };
         }
@@ -664,6 +1052,8 @@ impl From<ColumnDefinition> for Column {
             is_rowid_alias: primary_key && matches!(ty, Type::Integer),
             unique,
             collation,
+            generated_expr,
+            generated_stored,
         }
     }
 }
@@ -868,6 +1258,7 @@ pub fn sqlite_schema_table() -> BTreeTable {
         name: "sqlite_schema".to_string(),
         has_rowid: true,
         is_strict: false,
+        has_autoincrement: false,
         primary_key_columns: vec![],
         columns: vec![
             Column {
@@ -880,6 +1271,8 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             },
             Column {
                 name: Some("name".to_string()),
@@ -891,6 +1284,8 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             },
             Column {
                 name: Some("tbl_name".to_string()),
@@ -902,6 +1297,8 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             },
             Column {
                 name: Some("rootpage".to_string()),
@@ -913,6 +1310,8 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             },
             Column {
                 name: Some("sql".to_string()),
@@ -924,9 +1323,14 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             },
         ],
         unique_sets: None,
+        column_lookup: Default::default(),
+        foreign_keys: vec![],
+        checks: vec![],
     }
 }
 
@@ -942,9 +1346,11 @@ pub struct Index {
     /// Does the index have a rowid as the last column?
     /// This is the case for btree indexes (persistent or ephemeral) that
     /// have been created based on a table with a rowid.
-    /// For example, WITHOUT ROWID tables (not supported in Limbo yet),
+    /// For example, the b-tree of a WITHOUT ROWID table ([BTreeTable::without_rowid_index]),
     /// and  SELECT DISTINCT ephemeral indexes will not have a rowid.
     pub has_rowid: bool,
+    /// The WHERE clause of a partial index, which holds only the rows that satisfy it.
+    pub where_clause: Option<Box<Expr>>,
 }
 
 #[allow(dead_code)]
@@ -957,11 +1363,19 @@ pub struct IndexColumn {
     /// CREATE TABLE t(a,b,c)
     /// CREATE INDEX idx ON t(b)
     /// b.pos_in_table == 1
+    ///
+    /// [EXPR_INDEX_SENTINEL] for a column of an expression index.
     pub pos_in_table: usize,
     pub collation: Option<CollationSeq>,
     pub default: Option<Expr>,
+    /// The indexed expression, for a column of an expression index such as
+    /// CREATE INDEX idx ON t(lower(b)).
+    pub expr: Option<Box<Expr>>,
 }
 
+/// The [IndexColumn::pos_in_table] of an indexed expression, which is not a column of the table.
+pub const EXPR_INDEX_SENTINEL: usize = usize::MAX;
+
 impl Index {
     pub fn from_sql(sql: &str, root_page: usize, table: &BTreeTable) -> Result<Index> {
         let mut parser = Parser::new(sql.as_bytes());
@@ -972,12 +1386,24 @@ impl Index {
                 tbl_name,
                 columns,
                 unique,
+                where_clause,
                 ..
             })) => {
                 let index_name = normalize_ident(&idx_name.name.0);
                 let mut index_columns = Vec::with_capacity(columns.len());
                 for col in columns.into_iter() {
                     let name = normalize_ident(&col.expr.to_string());
+                    if !matches!(col.expr, Expr::Id(_) | Expr::Name(_)) {
+                        index_columns.push(IndexColumn {
+                            name,
+                            order: col.order.unwrap_or(SortOrder::Asc),
+                            pos_in_table: EXPR_INDEX_SENTINEL,
+                            collation: None,
+                            default: None,
+                            expr: Some(Box::new(col.expr)),
+                        });
+                        continue;
+                    }
                     let Some((pos_in_table, _)) = table.get_column(&name) else {
                         return Err(crate::LimboError::InternalError(format!(
                             "Column {} is in index {} but not found in table {}",
@@ -991,6 +1417,7 @@ impl Index {
                         pos_in_table,
                         collation: column.collation,
                         default: column.default.clone(),
+                        expr: None,
                     });
                 }
                 Ok(Index {
@@ -1001,6 +1428,7 @@ impl Index {
                     unique,
                     ephemeral: false,
                     has_rowid: table.has_rowid,
+                    where_clause,
                 })
             }
             _ => todo!("Expected create index statement"),
@@ -1055,6 +1483,7 @@ impl Index {
                         pos_in_table,
                         collation: column.collation,
                         default: column.default.clone(),
+                        expr: None,
                     }
                 })
                 .collect::<Vec<_>>();
@@ -1067,6 +1496,7 @@ impl Index {
                 unique: true,
                 ephemeral: false,
                 has_rowid: table.has_rowid,
+                where_clause: None,
             });
         }
 
@@ -1097,10 +1527,12 @@ impl Index {
                             pos_in_table,
                             collation: column.collation,
                             default: column.default.clone(),
+                            expr: None,
                         }],
                         unique: true,
                         ephemeral: false,
                         has_rowid: table.has_rowid,
+                        where_clause: None,
                     })
                 } else {
                     None
@@ -1165,6 +1597,7 @@ impl Index {
                             pos_in_table,
                             collation: column.collation,
                             default: column.default.clone(),
+                            expr: None,
                         }
                     });
                     Index {
@@ -1175,6 +1608,7 @@ impl Index {
                         unique: true,
                         ephemeral: false,
                         has_rowid: table.has_rowid,
+                        where_clause: None,
                     }
                 });
             indices.extend(unique_set_indices);
@@ -1198,6 +1632,11 @@ impl Index {
             .iter()
             .position(|c| c.pos_in_table == table_pos)
     }
+
+    /// Does the index have a column that is an expression rather than a column of the table?
+    pub fn has_expressions(&self) -> bool {
+        self.columns.iter().any(|c| c.expr.is_some())
+    }
 }
 
 #[cfg(test)]
@@ -1271,6 +1710,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn test_autoincrement() -> Result<()> {
+        let sql = r#"CREATE TABLE t1 (a INTEGER PRIMARY KEY AUTOINCREMENT, b TEXT);"#;
+        let table = BTreeTable::from_sql(sql, 0)?;
+        assert!(table.has_autoincrement);
+        assert_eq!(
+            table.to_sql(),
+            "CREATE TABLE t1 (a INTEGER PRIMARY KEY AUTOINCREMENT, b TEXT)"
+        );
+
+        let sql = r#"CREATE TABLE t1 (a INTEGER, b TEXT, PRIMARY KEY(a AUTOINCREMENT));"#;
+        assert!(BTreeTable::from_sql(sql, 0)?.has_autoincrement);
+
+        let sql = r#"CREATE TABLE t1 (a INTEGER PRIMARY KEY, b TEXT);"#;
+        assert!(!BTreeTable::from_sql(sql, 0)?.has_autoincrement);
+        Ok(())
+    }
+
     #[test]
     pub fn test_column_is_rowid_alias_single_integer_without_rowid() -> Result<()> {
         let sql = r#"CREATE TABLE t1 (a INTEGER PRIMARY KEY, b TEXT) WITHOUT ROWID;"#;
@@ -1420,6 +1877,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn test_primary_key_separate_keyword_differently_quoted() -> Result<()> {
+        let sql = r#"CREATE TABLE t1 ("Order" INTEGER, b TEXT, PRIMARY KEY([order]));"#;
+        let table = BTreeTable::from_sql(sql, 0)?;
+        let column = table.get_column("order").unwrap().1;
+        assert!(column.primary_key, "column 'order' should be a primary key");
+        assert_eq!(
+            vec![("order".to_string(), SortOrder::Asc)],
+            table.primary_key_columns,
+            "primary key column names should be ['order']"
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_to_sql_quotes_keywords() -> Result<()> {
+        let sql = r#"CREATE TABLE "group" ([order] INTEGER, `a"b` TEXT, c);"#;
+        let table = BTreeTable::from_sql(sql, 0)?;
+        assert_eq!(
+            table.to_sql(),
+            r#"CREATE TABLE "group" ("order" INTEGER, "a""b" TEXT, c)"#
+        );
+        Ok(())
+    }
+
     #[test]
     pub fn test_default_value() -> Result<()> {
         let sql = r#"CREATE TABLE t1 (a INTEGER DEFAULT 23);"#;
@@ -1562,6 +2044,7 @@ mod tests {
             name: "t1".to_string(),
             has_rowid: true,
             is_strict: false,
+            has_autoincrement: false,
             primary_key_columns: vec![("nonexistent".to_string(), SortOrder::Asc)],
             columns: vec![Column {
                 name: Some("a".to_string()),
@@ -1573,8 +2056,13 @@ mod tests {
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             }],
             unique_sets: None,
+            column_lookup: Default::default(),
+            foreign_keys: vec![],
+            checks: vec![],
         };
 
         let _result = Index::automatic_from_primary_key_and_unique(
@@ -573,7 +573,7 @@ impl BTreeCursor {
     pub fn has_rowid(&self) -> bool {
         match &self.index_key_info {
             Some(index_key_info) => index_key_info.has_rowid,
-            None => true, // currently we don't support WITHOUT ROWID tables
+            None => true,
         }
     }
 
@@ -2200,7 +2200,7 @@ impl BTreeCursor {
                         record,
                         self.usable_space() as u16,
                         self.pager.clone(),
-                    );
+                    )?;
 
                     // insert
                     let overflow = {
@@ -2310,7 +2310,7 @@ impl BTreeCursor {
                     }
 
                     if !self.stack.has_parent() {
-                        self.balance_root();
+                        self.balance_root()?;
                     }
 
                     let write_info = self.state.mut_write_info().unwrap();
@@ -2885,7 +2885,14 @@ impl BTreeCursor {
                         pages_to_balance_new[i].replace(page.clone());
                     } else {
                         // FIXME: handle page cache is full
-                        let page = self.allocate_page(page_type, 0);
+                        // Prefer a free page close to the siblings so scans stay sequential.
+                        let last_sibling_id =
+                            pages_to_balance_new[i - 1].as_ref().unwrap().get().get().id;
+                        let page = self.pager.do_allocate_page(
+                            page_type,
+                            0,
+                            BtreePageAllocMode::Near(last_sibling_id as u32),
+                        )?;
                         pages_to_balance_new[i].replace(page);
                         // Since this page didn't exist before, we can set it to cells length as it
                         // marks them as empty since it is a prefix sum of cells.
@@ -3780,7 +3787,7 @@ impl BTreeCursor {
     /// Balance the root page.
     /// This is done when the root page overflows, and we need to create a new root page.
     /// See e.g. https://en.wikipedia.org/wiki/B-tree
-    fn balance_root(&mut self) {
+    fn balance_root(&mut self) -> Result<()> {
         /* todo: balance deeper, create child and copy contents of root there. Then split root */
         /* if we are in root page then we just need to create a new root and push key there */
 
@@ -3795,9 +3802,11 @@ impl BTreeCursor {
         let root = root_btree.get();
         let root_contents = root.get_contents();
         // FIXME: handle page cache is full
-        let child_btree =
-            self.pager
-                .do_allocate_page(root_contents.page_type(), 0, BtreePageAllocMode::Any);
+        let child_btree = self.pager.do_allocate_page(
+            root_contents.page_type(),
+            0,
+            BtreePageAllocMode::Near(root.get().id as u32),
+        )?;
 
         tracing::debug!(
             "balance_root(root={}, rightmost={}, page_type={:?})",
@@ -3855,6 +3864,7 @@ impl BTreeCursor {
         self.stack.push(root_btree.clone());
         self.stack.set_cell_index(0); // leave parent pointing at the rightmost pointer (in this case 0, as there are no cells), since we will be balancing the rightmost child page.
         self.stack.push(child_btree.clone());
+        Ok(())
     }
 
     fn usable_space(&self) -> usize {
@@ -3984,6 +3994,11 @@ impl BTreeCursor {
         self.root_page
     }
 
+    /// The pager this cursor reads and writes through. Ephemeral tables have their own.
+    pub fn pager(&self) -> &Rc<Pager> {
+        &self.pager
+    }
+
     pub fn rewind(&mut self) -> Result<CursorResult<()>> {
         if self.mv_cursor.is_some() {
             let cursor_has_record = return_if_io!(self.get_next_record());
@@ -4073,6 +4088,23 @@ impl BTreeCursor {
         }
     }
 
+    /// Returns the page number of the cell the cursor is pointing at, and the offset within
+    /// that page at which the cell's record begins.
+    pub fn record_location(&self) -> Result<CursorResult<Option<(usize, usize)>>> {
+        if self.mv_cursor.is_some() || !self.has_record.get() {
+            return Ok(CursorResult::Ok(None));
+        }
+        let page = self.stack.top();
+        return_if_locked_maybe_load!(self.pager, page);
+        let page = page.get();
+        let contents = page.get_contents();
+        let cell_idx = self.stack.current_cell_index() as usize;
+        let offset = contents.cell_payload_offset(cell_idx)?;
+        Ok(CursorResult::Ok(
+            offset.map(|offset| (page.get().id, offset)),
+        ))
+    }
+
     #[instrument(skip(self), level = Level::TRACE)]
     pub fn seek(&mut self, key: SeekKey<'_>, op: SeekOp) -> Result<CursorResult<bool>> {
         assert!(self.mv_cursor.is_none());
@@ -4930,7 +4962,7 @@ impl BTreeCursor {
             record,
             self.usable_space() as u16,
             self.pager.clone(),
-        );
+        )?;
 
         // figure out old cell offset & size
         let (old_offset, old_local_size) = {
@@ -5137,7 +5169,7 @@ impl BTreeCursor {
         btree_read_page(&self.pager, page_idx)
     }
 
-    pub fn allocate_page(&self, page_type: PageType, offset: usize) -> BTreePage {
+    pub fn allocate_page(&self, page_type: PageType, offset: usize) -> Result<BTreePage> {
         self.pager
             .do_allocate_page(page_type, offset, BtreePageAllocMode::Any)
     }
@@ -6371,7 +6403,7 @@ fn fill_cell_payload(
     record: &ImmutableRecord,
     usable_space: u16,
     pager: Rc<Pager>,
-) {
+) -> Result<()> {
     assert!(matches!(
         page_type,
         PageType::TableLeaf | PageType::IndexLeaf
@@ -6397,7 +6429,7 @@ fn fill_cell_payload(
     if record_buf.len() <= payload_overflow_threshold_max {
         // enough allowed space to fit inside a btree page
         cell_payload.extend_from_slice(record_buf.as_slice());
-        return;
+        return Ok(());
     }
 
     let payload_overflow_threshold_min = payload_overflow_threshold_min(page_type, usable_space);
@@ -6430,7 +6462,7 @@ fn fill_cell_payload(
 
         // we still have bytes to add, we will need to allocate new overflow page
         // FIXME: handle page cache is full
-        let overflow_page = pager.allocate_overflow_page();
+        let overflow_page = pager.allocate_overflow_page()?;
         overflow_pages.push(overflow_page.clone());
         {
             let id = overflow_page.get().id as u32;
@@ -6451,6 +6483,7 @@ fn fill_cell_payload(
     }
 
     assert_eq!(cell_size, cell_payload.len());
+    Ok(())
 }
 
 /// Returns the maximum payload size (X) that can be stored directly on a b-tree page without spilling to overflow pages.
@@ -6463,7 +6496,7 @@ fn fill_cell_payload(
 /// - Give a minimum fanout of 4 for index b-trees
 /// - Ensure enough payload is on the b-tree page that the record header can usually be accessed
 ///   without consulting an overflow page
-fn payload_overflow_threshold_max(page_type: PageType, usable_space: u16) -> usize {
+pub(crate) fn payload_overflow_threshold_max(page_type: PageType, usable_space: u16) -> usize {
     match page_type {
         PageType::IndexInterior | PageType::IndexLeaf => {
             ((usable_space as usize - 12) * 64 / 255) - 23 // Index page formula
@@ -6483,7 +6516,7 @@ fn payload_overflow_threshold_max(page_type: PageType, usable_space: u16) -> usi
 /// - Otherwise: store M bytes on page
 ///
 /// The remaining bytes are stored on overflow pages in both cases.
-fn payload_overflow_threshold_min(_page_type: PageType, usable_space: u16) -> usize {
+pub(crate) fn payload_overflow_threshold_min(_page_type: PageType, usable_space: u16) -> usize {
     // Same formula for all page types
     ((usable_space as usize - 12) * 32 / 255) - 23
 }
@@ -6536,7 +6569,7 @@ mod tests {
     use super::*;
     use crate::{
         io::{Buffer, Completion, CompletionType, MemoryIO, OpenFlags, IO},
-        storage::{database::DatabaseFile, page_cache::DumbLruPageCache},
+        storage::{database::DatabaseFile, page_cache::PageCache},
         types::Text,
         vdbe::Register,
         BufferPool, Connection, StepResult, WalFile, WalFileShared, WriteCompletion,
@@ -6553,7 +6586,6 @@ mod tests {
     use tempfile::TempDir;
 
     use crate::{
-        io::BufferData,
         storage::{
             btree::{
                 compute_free_space, fill_cell_payload, payload_overflow_threshold_max,
@@ -6571,14 +6603,7 @@ mod tests {
     fn get_page(id: usize) -> BTreePage {
         let page = Arc::new(Page::new(id));
 
-        let drop_fn = Rc::new(|_| {});
-        let inner = PageContent::new(
-            0,
-            Arc::new(RefCell::new(Buffer::new(
-                BufferData::new(vec![0; 4096]),
-                drop_fn,
-            ))),
-        );
+        let inner = PageContent::new(0, Arc::new(RefCell::new(Buffer::allocate(4096))));
         page.get().contents.replace(inner);
         let page = Arc::new(BTreePageInner {
             page: RefCell::new(page),
@@ -6632,7 +6657,8 @@ mod tests {
             &record,
             4096,
             conn.pager.clone(),
-        );
+        )
+        .unwrap();
         insert_into_cell(page, &payload, pos, 4096).unwrap();
         payload
     }
@@ -7013,7 +7039,7 @@ mod tests {
             for insert_id in 0..inserts {
                 let do_validate = do_validate_btree || (insert_id % VALIDATE_INTERVAL == 0);
                 run_until_done(|| pager.begin_read_tx(), &pager).unwrap();
-                run_until_done(|| pager.begin_write_tx(), &pager).unwrap();
+                run_until_done(|| pager.begin_write_tx(false), &pager).unwrap();
                 let size = size(&mut rng);
                 let key = {
                     let result;
@@ -7147,7 +7173,7 @@ mod tests {
             tracing::info!("seed: {}", seed);
             for i in 0..inserts {
                 pager.begin_read_tx().unwrap();
-                pager.begin_write_tx().unwrap();
+                pager.begin_write_tx(false).unwrap();
                 let key = {
                     let result;
                     loop {
@@ -7375,12 +7401,6 @@ mod tests {
 
         let buffer_pool = Arc::new(BufferPool::new(Some(page_size as usize)));
 
-        // Initialize buffer pool with correctly sized buffers
-        for _ in 0..10 {
-            let vec = vec![0; page_size as usize]; // Initialize with correct length, not just capacity
-            buffer_pool.put(Pin::new(vec));
-        }
-
         let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
         let db_file = Arc::new(DatabaseFile::new(
             io.open_file("test.db", OpenFlags::Create, false).unwrap(),
@@ -7399,7 +7419,7 @@ mod tests {
                 db_file,
                 wal,
                 io,
-                Arc::new(parking_lot::RwLock::new(DumbLruPageCache::new(10))),
+                Arc::new(parking_lot::RwLock::new(PageCache::new(10))),
                 buffer_pool,
                 Arc::new(AtomicUsize::new(0)),
                 Arc::new(Mutex::new(())),
@@ -7434,11 +7454,9 @@ mod tests {
         // Setup overflow pages (2, 3, 4) with linking
         let mut current_page = 2u32;
         while current_page <= 4 {
-            let drop_fn = Rc::new(|_buf| {});
             #[allow(clippy::arc_with_non_send_sync)]
             let buf = Arc::new(RefCell::new(Buffer::allocate(
                 header_accessor::get_page_size(&pager)? as usize,
-                drop_fn,
             )));
             let write_complete = Box::new(|_| {});
             let c = Completion::new(CompletionType::Write(WriteCompletion::new(write_complete)));
@@ -7571,11 +7589,11 @@ mod tests {
         let mut cursor = BTreeCursor::new_table(None, pager.clone(), 2);
 
         // Initialize page 2 as a root page (interior)
-        let root_page = cursor.allocate_page(PageType::TableInterior, 0);
+        let root_page = cursor.allocate_page(PageType::TableInterior, 0)?;
 
         // Allocate two leaf pages
-        let page3 = cursor.allocate_page(PageType::TableLeaf, 0);
-        let page4 = cursor.allocate_page(PageType::TableLeaf, 0);
+        let page3 = cursor.allocate_page(PageType::TableLeaf, 0)?;
+        let page4 = cursor.allocate_page(PageType::TableLeaf, 0)?;
 
         // Configure the root page to point to the two leaf pages
         {
@@ -7760,7 +7778,8 @@ mod tests {
                         &record,
                         4096,
                         conn.pager.clone(),
-                    );
+                    )
+                    .unwrap();
                     if (free as usize) < payload.len() + 2 {
                         // do not try to insert overflow pages because they require balancing
                         continue;
@@ -7838,7 +7857,8 @@ mod tests {
                             &record,
                             4096,
                             conn.pager.clone(),
-                        );
+                        )
+                        .unwrap();
                         if (free as usize) < payload.len() - 2 {
                             // do not try to insert overflow pages because they require balancing
                             continue;
@@ -8222,7 +8242,8 @@ mod tests {
             &record,
             4096,
             conn.pager.clone(),
-        );
+        )
+        .unwrap();
         let page = page.get();
         insert(0, page.get_contents());
         defragment(page.get_contents());
@@ -8297,7 +8318,8 @@ mod tests {
             &record,
             4096,
             conn.pager.clone(),
-        );
+        )
+        .unwrap();
         insert_into_cell(page.get().get_contents(), &payload, 0, 4096).unwrap();
         let free = compute_free_space(page.get().get_contents(), usable_space);
         let total_size = payload.len() + 2;
@@ -8725,7 +8747,8 @@ mod tests {
             &record,
             pager.usable_space() as u16,
             pager.clone(),
-        );
+        )
+        .unwrap();
         insert_into_cell(contents, &payload, i as usize, pager.usable_space() as u16).unwrap();
     }
 }
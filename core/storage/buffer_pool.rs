@@ -1,11 +1,49 @@
-use crate::io::BufferData;
+use crate::io::{Buffer, BufferData};
 use parking_lot::Mutex;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A slot in the pool's arena. While its buffer is checked out the memory lives in
+/// the [Buffer] guard and `data` is `None`.
+struct Slot {
+    data: Option<BufferData>,
+    /// Bumped every time the slot's buffer comes back, so that a handle from an
+    /// earlier checkout can be told apart from the current one.
+    #[cfg(debug_assertions)]
+    generation: u64,
+}
+
+#[derive(Default)]
+struct Arena {
+    slots: Vec<Slot>,
+    /// Indexes of slots whose buffer is sitting in the pool.
+    free: Vec<usize>,
+    /// Indexes of slots whose buffer was freed after the pool shrank.
+    retired: Vec<usize>,
+    /// Number of buffers allocated by the pool, whether checked out or not.
+    allocated: usize,
+}
 
 pub struct BufferPool {
-    pub free_buffers: Mutex<Vec<BufferData>>,
+    arena: Mutex<Arena>,
     page_size: AtomicUsize,
+    /// Most buffers the pool allocates, `usize::MAX` when unbounded.
+    max_buffers: AtomicUsize,
+}
+
+/// Ties a checked-out [Buffer] to the arena slot it came from.
+pub struct PoolHandle {
+    pool: Arc<BufferPool>,
+    slot: usize,
+    #[cfg(debug_assertions)]
+    generation: u64,
+}
+
+impl PoolHandle {
+    pub(crate) fn release(self, data: BufferData) {
+        self.pool.put(&self, data);
+    }
 }
 
 const DEFAULT_PAGE_SIZE: usize = 4096;
@@ -13,22 +51,131 @@ const DEFAULT_PAGE_SIZE: usize = 4096;
 impl BufferPool {
     pub fn new(page_size: Option<usize>) -> Self {
         Self {
-            free_buffers: Mutex::new(Vec::new()),
+            arena: Mutex::new(Arena::default()),
             page_size: AtomicUsize::new(page_size.unwrap_or(DEFAULT_PAGE_SIZE)),
+            max_buffers: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// Limits the number of buffers the pool allocates. When it shrinks, buffers sitting in
+    /// the pool beyond the new limit are freed right away, and checked out ones when they
+    /// come back.
+    pub fn set_max_buffers(&self, max_buffers: usize) {
+        self.max_buffers.store(max_buffers, Ordering::Relaxed);
+        let mut arena = self.arena.lock();
+        let arena = &mut *arena;
+        while arena.allocated > max_buffers {
+            let Some(slot) = arena.free.pop() else {
+                break;
+            };
+            arena.slots[slot].data = None;
+            arena.retired.push(slot);
+            arena.allocated -= 1;
         }
     }
 
+    pub fn max_buffers(&self) -> usize {
+        self.max_buffers.load(Ordering::Relaxed)
+    }
+
     pub fn set_page_size(&self, page_size: usize) {
         self.page_size.store(page_size, Ordering::Relaxed);
     }
 
-    pub fn get(&self) -> BufferData {
-        let buffer = self.free_buffers.lock().pop();
-        buffer.unwrap_or_else(|| Pin::new(vec![0; self.page_size.load(Ordering::Relaxed)]))
+    pub fn get_page_size(&self) -> usize {
+        self.page_size.load(Ordering::Relaxed)
+    }
+
+    /// Checks a buffer out of the pool. The buffer returns to the pool when dropped.
+    ///
+    /// When all of the pool's buffers are checked out, this allocates one that is freed when
+    /// dropped, so that callers with nothing to wait for never fail. Callers that can wait
+    /// for buffers to come back use [BufferPool::try_get] instead.
+    pub fn get(self: &Arc<Self>) -> Buffer {
+        self.try_get()
+            .unwrap_or_else(|| Buffer::allocate(self.page_size.load(Ordering::Relaxed)))
+    }
+
+    /// Checks a buffer out of the pool, or returns `None` if all of its buffers are checked
+    /// out. The buffer returns to the pool when dropped.
+    pub fn try_get(self: &Arc<Self>) -> Option<Buffer> {
+        let mut arena = self.arena.lock();
+        let arena = &mut *arena;
+        let (slot, data) = match arena.free.pop() {
+            Some(slot) => {
+                let data = arena.slots[slot]
+                    .data
+                    .take()
+                    .expect("free buffer slot must hold its buffer");
+                (slot, data)
+            }
+            None if arena.allocated >= self.max_buffers.load(Ordering::Relaxed) => {
+                return None;
+            }
+            None => {
+                let slot = arena.retired.pop().unwrap_or_else(|| {
+                    arena.slots.push(Slot {
+                        data: None,
+                        #[cfg(debug_assertions)]
+                        generation: 0,
+                    });
+                    arena.slots.len() - 1
+                });
+                arena.allocated += 1;
+                let data = Pin::new(vec![0; self.page_size.load(Ordering::Relaxed)]);
+                (slot, data)
+            }
+        };
+        let handle = PoolHandle {
+            pool: self.clone(),
+            slot,
+            #[cfg(debug_assertions)]
+            generation: arena.slots[slot].generation,
+        };
+        Some(Buffer::pooled(data, handle))
+    }
+
+    /// Whether all of the pool's buffers are checked out.
+    pub fn is_exhausted(&self) -> bool {
+        let arena = self.arena.lock();
+        arena.free.is_empty() && arena.allocated >= self.max_buffers.load(Ordering::Relaxed)
+    }
+
+    fn put(&self, handle: &PoolHandle, data: BufferData) {
+        let mut arena = self.arena.lock();
+        let arena = &mut *arena;
+        let slot = &mut arena.slots[handle.slot];
+        #[cfg(debug_assertions)]
+        {
+            assert_eq!(
+                slot.generation, handle.generation,
+                "buffer slot {} returned by a stale handle",
+                handle.slot
+            );
+            slot.generation += 1;
+        }
+        assert!(
+            slot.data.is_none(),
+            "buffer slot {} returned twice",
+            handle.slot
+        );
+        if arena.allocated > self.max_buffers.load(Ordering::Relaxed) {
+            arena.retired.push(handle.slot);
+            arena.allocated -= 1;
+            return;
+        }
+        slot.data = Some(data);
+        arena.free.push(handle.slot);
+    }
+
+    /// Number of buffers currently sitting in the pool.
+    pub fn free_buffers(&self) -> usize {
+        self.arena.lock().free.len()
     }
 
-    pub fn put(&self, buffer: BufferData) {
-        self.free_buffers.lock().push(buffer);
+    /// Number of buffers the pool has allocated and not freed, whether checked out or not.
+    pub fn allocated_buffers(&self) -> usize {
+        self.arena.lock().allocated
     }
 }
 
@@ -42,4 +189,123 @@ mod tests {
     fn test_send_sync() {
         is_send_sync_static::<BufferPool>();
     }
+
+    #[test]
+    fn test_buffer_returns_to_pool_on_drop() {
+        let pool = Arc::new(BufferPool::new(Some(512)));
+        let buf = pool.get();
+        assert!(buf.is_pooled());
+        assert_eq!(buf.len(), 512);
+        assert_eq!(pool.free_buffers(), 0);
+        drop(buf);
+        assert_eq!(pool.free_buffers(), 1);
+
+        // The returned buffer is reused rather than allocating a new one.
+        let buf = pool.get();
+        assert_eq!(pool.allocated_buffers(), 1);
+        assert_eq!(pool.free_buffers(), 0);
+        drop(buf);
+        assert_eq!(pool.free_buffers(), 1);
+    }
+
+    #[test]
+    fn test_buffer_reuse_keeps_contents_addressable() {
+        let pool = Arc::new(BufferPool::new(Some(512)));
+        let mut buf = pool.get();
+        buf.as_mut_slice()[0] = 42;
+        let ptr = buf.as_ptr();
+        drop(buf);
+        let buf = pool.get();
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf.as_slice()[0], 42);
+    }
+
+    #[test]
+    fn test_cloned_buffer_is_not_pooled() {
+        let pool = Arc::new(BufferPool::new(Some(512)));
+        let buf = pool.get();
+        let copy = buf.clone();
+        assert!(!copy.is_pooled());
+        drop(copy);
+        assert_eq!(pool.free_buffers(), 0);
+        drop(buf);
+        assert_eq!(pool.free_buffers(), 1);
+        assert_eq!(pool.allocated_buffers(), 1);
+    }
+
+    #[test]
+    fn test_exhausted_pool() {
+        let pool = Arc::new(BufferPool::new(Some(512)));
+        pool.set_max_buffers(2);
+        let buf1 = pool.try_get().unwrap();
+        let buf2 = pool.try_get().unwrap();
+        assert!(pool.is_exhausted());
+        assert!(pool.try_get().is_none());
+
+        // get() doesn't wait, it hands out a buffer from outside the pool.
+        let overflow = pool.get();
+        assert!(!overflow.is_pooled());
+        assert_eq!(overflow.len(), 512);
+        drop(overflow);
+        assert_eq!(pool.allocated_buffers(), 2);
+
+        drop(buf1);
+        assert!(!pool.is_exhausted());
+        assert!(pool.try_get().is_some());
+        drop(buf2);
+    }
+
+    #[test]
+    fn test_shrink_pool() {
+        let pool = Arc::new(BufferPool::new(Some(512)));
+        let bufs: Vec<_> = (0..4).map(|_| pool.get()).collect();
+        let mut bufs = bufs.into_iter();
+        drop(bufs.next());
+        drop(bufs.next());
+        assert_eq!(pool.free_buffers(), 2);
+
+        // Idle buffers are freed right away, checked out ones once they come back.
+        pool.set_max_buffers(1);
+        assert_eq!(pool.free_buffers(), 0);
+        assert_eq!(pool.allocated_buffers(), 2);
+        drop(bufs.next());
+        assert_eq!(pool.allocated_buffers(), 1);
+        assert_eq!(pool.free_buffers(), 0);
+        drop(bufs.next());
+        assert_eq!(pool.allocated_buffers(), 1);
+        assert_eq!(pool.free_buffers(), 1);
+
+        // Freed slots are reused when the pool grows again.
+        pool.set_max_buffers(3);
+        let bufs: Vec<_> = (0..3).map(|_| pool.try_get().unwrap()).collect();
+        assert!(pool.try_get().is_none());
+        drop(bufs);
+        assert_eq!(pool.allocated_buffers(), 3);
+        assert_eq!(pool.free_buffers(), 3);
+    }
+
+    #[test]
+    fn test_unpooled_buffer() {
+        let buf = Buffer::allocate(100);
+        assert!(!buf.is_pooled());
+        assert_eq!(buf.len(), 100);
+        assert!(buf.as_slice().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "stale handle")]
+    fn test_stale_handle_is_caught() {
+        let pool = Arc::new(BufferPool::new(Some(512)));
+        drop(pool.get());
+        // Forge a handle from the first checkout and return it after the slot was
+        // already recycled.
+        let stale = PoolHandle {
+            pool: pool.clone(),
+            slot: 0,
+            generation: 0,
+        };
+        let _current = pool.get();
+        stale.release(Pin::new(vec![0; 512]));
+    }
 }
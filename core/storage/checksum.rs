@@ -0,0 +1,157 @@
+//! Per-page checksums stored in the reserved bytes region at the end of each page.
+//!
+//! A database opts into page checksums when page 1 is created with at least
+//! [CHECKSUM_SIZE] bytes of reserved space and [CHECKSUM_MARKER] written into the
+//! "reserved for expansion" area of the database header. Databases without the
+//! marker are read and written exactly as before.
+//!
+//! The checksum occupies the last [CHECKSUM_SIZE] bytes of the page and covers
+//! every byte that precedes it, using the same cumulative algorithm as WAL frames.
+use std::sync::Arc;
+
+use crate::{LimboError, Result};
+
+/// Number of reserved bytes at the end of a page used to store its checksum.
+pub const CHECKSUM_SIZE: usize = 8;
+
+/// Marker stored at [CHECKSUM_MARKER_OFFSET] of the database header when pages carry checksums.
+pub const CHECKSUM_MARKER: u32 = 0x4C50_4353; // "LPCS"
+
+/// Offset of the checksum marker in the database header: the first four bytes
+/// of the "reserved for expansion" area.
+pub const CHECKSUM_MARKER_OFFSET: usize = 72;
+
+const HEADER_OFFSET_RESERVED_SPACE: usize = 20;
+
+/// Details about a page that failed checksum verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub page_id: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// Callback invoked with the details of every page that fails checksum verification.
+pub type CorruptionCallback = Arc<dyn Fn(&ChecksumMismatch) + Send + Sync>;
+
+/// Computes the checksum of a page, excluding the trailing checksum slot itself.
+pub fn page_checksum(page: &[u8]) -> u64 {
+    let data = &page[..page.len() - CHECKSUM_SIZE];
+    assert_eq!(data.len() % 8, 0, "page data must be a multiple of 8");
+    let mut s0: u32 = 0;
+    let mut s1: u32 = 0;
+    for chunk in data.chunks_exact(8) {
+        let v0 = u32::from_be_bytes(chunk[0..4].try_into().unwrap());
+        let v1 = u32::from_be_bytes(chunk[4..8].try_into().unwrap());
+        s0 = s0.wrapping_add(v0.wrapping_add(s1));
+        s1 = s1.wrapping_add(v1.wrapping_add(s0));
+    }
+    ((s0 as u64) << 32) | s1 as u64
+}
+
+/// Returns the checksum currently stored in the page's trailing checksum slot.
+pub fn stored_checksum(page: &[u8]) -> u64 {
+    let start = page.len() - CHECKSUM_SIZE;
+    u64::from_be_bytes(page[start..].try_into().unwrap())
+}
+
+/// Computes the page checksum and stores it in the trailing checksum slot.
+pub fn write_page_checksum(page: &mut [u8]) {
+    let checksum = page_checksum(page);
+    let start = page.len() - CHECKSUM_SIZE;
+    page[start..].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Returns true if the database header contained in `page1` announces page checksums.
+pub fn header_has_checksums(page1: &[u8]) -> bool {
+    page1[HEADER_OFFSET_RESERVED_SPACE] as usize >= CHECKSUM_SIZE
+        && page1[CHECKSUM_MARKER_OFFSET..CHECKSUM_MARKER_OFFSET + 4]
+            == CHECKSUM_MARKER.to_be_bytes()
+}
+
+/// Verifies page checksums as pages are read from the database file or the WAL.
+#[derive(Clone)]
+pub struct PageChecksumVerifier {
+    callback: Option<CorruptionCallback>,
+}
+
+impl PageChecksumVerifier {
+    pub fn new(callback: Option<CorruptionCallback>) -> Self {
+        Self { callback }
+    }
+
+    /// Checks the checksum of `page`. On mismatch the corruption callback is invoked
+    /// and [LimboError::Corrupt] is returned.
+    ///
+    /// Page 1 carries the database header, so it is only verified if the header itself
+    /// announces checksums; this lets the verifier run before the header has been read.
+    pub fn verify(&self, page_id: usize, page: &[u8]) -> Result<()> {
+        if page_id == 1 && !header_has_checksums(page) {
+            return Ok(());
+        }
+        let expected = stored_checksum(page);
+        let actual = page_checksum(page);
+        if expected == actual {
+            return Ok(());
+        }
+        let mismatch = ChecksumMismatch {
+            page_id,
+            expected,
+            actual,
+        };
+        tracing::error!("page checksum mismatch: {:?}", mismatch);
+        if let Some(callback) = &self.callback {
+            callback(&mismatch);
+        }
+        Err(checksum_error(page_id))
+    }
+}
+
+pub(crate) fn checksum_error(page_id: usize) -> LimboError {
+    LimboError::Corrupt(format!("checksum mismatch on page {}", page_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn checksummed_page() -> Vec<u8> {
+        let mut page = (0..4096).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        write_page_checksum(&mut page);
+        page
+    }
+
+    #[test]
+    fn test_checksum_roundtrip() {
+        let page = checksummed_page();
+        assert_eq!(stored_checksum(&page), page_checksum(&page));
+        assert!(PageChecksumVerifier::new(None).verify(2, &page).is_ok());
+    }
+
+    #[test]
+    fn test_checksum_detects_bit_flip() {
+        let mut page = checksummed_page();
+        page[1234] ^= 0x01;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let callback: CorruptionCallback = {
+            let calls = calls.clone();
+            Arc::new(move |mismatch: &ChecksumMismatch| {
+                assert_eq!(mismatch.page_id, 2);
+                assert_ne!(mismatch.expected, mismatch.actual);
+                calls.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        let result = PageChecksumVerifier::new(Some(callback)).verify(2, &page);
+        assert!(matches!(result, Err(LimboError::Corrupt(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_page1_without_marker_is_not_verified() {
+        let mut page = vec![0u8; 4096];
+        page[1234] = 0xFF;
+        assert!(!header_has_checksums(&page));
+        assert!(PageChecksumVerifier::new(None).verify(1, &page).is_ok());
+    }
+}
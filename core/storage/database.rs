@@ -18,6 +18,10 @@ pub trait DatabaseStorage: Send + Sync {
     ) -> Result<()>;
     fn sync(&self, c: Completion) -> Result<()>;
     fn size(&self) -> Result<u64>;
+    /// Releases the lock taken on the underlying file when it was opened.
+    fn unlock(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(feature = "fs")]
@@ -68,6 +72,10 @@ impl DatabaseStorage for DatabaseFile {
     fn size(&self) -> Result<u64> {
         self.file.size()
     }
+
+    fn unlock(&self) -> Result<()> {
+        self.file.unlock_file()
+    }
 }
 
 #[cfg(feature = "fs")]
@@ -130,3 +138,82 @@ impl FileMemoryStorage {
         Self { file }
     }
 }
+
+/// A read-only database held in memory, such as one embedded in the binary with
+/// `include_bytes!`. Pages are copied straight out of the region, there is no file or
+/// [crate::io::IO] backend behind it. Writes fail with [LimboError::ReadOnly].
+pub struct BytesStorage {
+    bytes: Box<dyn AsRef<[u8]> + Send + Sync>,
+}
+
+impl BytesStorage {
+    /// Wraps `bytes`, which must hold a whole database file with a valid header. Borrowed
+    /// `&'static [u8]` and shared `Arc<[u8]>` regions are used as they are, without a copy.
+    pub fn new<B: AsRef<[u8]> + Send + Sync + 'static>(bytes: B) -> Result<Self> {
+        let bytes: Box<dyn AsRef<[u8]> + Send + Sync> = Box::new(bytes);
+        let data = (*bytes).as_ref();
+        if data.len() < 100 || &data[..16] != b"SQLite format 3\0" {
+            return Err(LimboError::NotADB);
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        if !(512..=65536).contains(&page_size) || page_size & (page_size - 1) != 0 {
+            return Err(LimboError::NotADB);
+        }
+        if data.len() % page_size != 0 {
+            return Err(LimboError::Corrupt(format!(
+                "database size {} is not a multiple of the page size {}",
+                data.len(),
+                page_size
+            )));
+        }
+        Ok(Self { bytes })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        (*self.bytes).as_ref()
+    }
+}
+
+impl DatabaseStorage for BytesStorage {
+    fn read_page(&self, page_idx: usize, c: Completion) -> Result<()> {
+        let r = c.as_read();
+        let size = r.buf().len();
+        assert!(page_idx > 0);
+        if !(512..=65536).contains(&size) || size & (size - 1) != 0 {
+            return Err(LimboError::NotADB);
+        }
+        let bytes = self.bytes();
+        let pos = ((page_idx - 1) * size).min(bytes.len());
+        let end = (pos + size).min(bytes.len());
+        {
+            let mut buf = r.buf_mut();
+            let buf = buf.as_mut_slice();
+            // Like a short read from a file, whatever lies past the end of the region reads as zeroes.
+            buf[..end - pos].copy_from_slice(&bytes[pos..end]);
+            buf[end - pos..].fill(0);
+        }
+        c.complete(size as i32);
+        Ok(())
+    }
+
+    fn write_page(
+        &self,
+        _page_idx: usize,
+        _buffer: Arc<RefCell<Buffer>>,
+        _c: Completion,
+    ) -> Result<()> {
+        Err(LimboError::ReadOnly)
+    }
+
+    fn sync(&self, c: Completion) -> Result<()> {
+        c.complete(0);
+        Ok(())
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.bytes().len() as u64)
+    }
+}
@@ -29,6 +29,8 @@ const HEADER_OFFSET_USER_VERSION: usize = 60;
 const HEADER_OFFSET_INCREMENTAL_VACUUM_ENABLED: usize = 64;
 const HEADER_OFFSET_APPLICATION_ID: usize = 68;
 //const HEADER_OFFSET_RESERVED_FOR_EXPANSION: usize = 72;
+/// Page checksum marker, stored in the first bytes of the "reserved for expansion" area.
+const HEADER_OFFSET_CHECKSUM_MARKER: usize = storage::checksum::CHECKSUM_MARKER_OFFSET;
 const HEADER_OFFSET_VERSION_VALID_FOR: usize = 92;
 const HEADER_OFFSET_VERSION_NUMBER: usize = 96;
 
@@ -156,6 +158,7 @@ impl_header_field_accessor!(
     HEADER_OFFSET_INCREMENTAL_VACUUM_ENABLED
 );
 impl_header_field_accessor!(application_id, u32, HEADER_OFFSET_APPLICATION_ID);
+impl_header_field_accessor!(checksum_marker, u32, HEADER_OFFSET_CHECKSUM_MARKER);
 //impl_header_field_accessor!(reserved_for_expansion, [u8; 20], HEADER_OFFSET_RESERVED_FOR_EXPANSION);
 impl_header_field_accessor!(version_valid_for, u32, HEADER_OFFSET_VERSION_VALID_FOR);
 impl_header_field_accessor!(version_number, u32, HEADER_OFFSET_VERSION_NUMBER);
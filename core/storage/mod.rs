@@ -12,6 +12,7 @@
 //! for the database, also either local or remote.
 pub(crate) mod btree;
 pub(crate) mod buffer_pool;
+pub(crate) mod checksum;
 pub(crate) mod database;
 pub(crate) mod header_accessor;
 pub(crate) mod page_cache;
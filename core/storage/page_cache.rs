@@ -1,15 +1,20 @@
-use std::{cell::RefCell, ptr::NonNull};
+use std::{cell::RefCell, fmt, ptr::NonNull, str::FromStr};
 
 use std::sync::Arc;
 use tracing::{debug, trace};
 
 use super::pager::PageRef;
 
-const DEFAULT_PAGE_CACHE_SIZE_IN_PAGES: usize = 2000;
+pub(crate) const DEFAULT_PAGE_CACHE_SIZE_IN_PAGES: usize = 2000;
 
 #[derive(Debug, Eq, Hash, PartialEq, Clone)]
 pub struct PageCacheKey {
     pgno: usize,
+    /// WAL frame the cached version of the page was read from, `None` for the database file.
+    frame: Option<u64>,
+    /// Checkpoint sequence of the WAL when the page was read, as frame numbers are reused
+    /// once the WAL restarts.
+    checkpoint_seq: u32,
 }
 
 #[allow(dead_code)]
@@ -20,14 +25,58 @@ struct PageCacheEntry {
     next: Option<NonNull<PageCacheEntry>>,
 }
 
-pub struct DumbLruPageCache {
+/// How the page cache chooses the pages it keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// New pages go to the head of the LRU list, and the least recently used page is evicted.
+    #[default]
+    Lru,
+    /// The least frequently accessed of the pages near the tail of the LRU list is evicted, and
+    /// a new page only goes to the head if it has been accessed more often than that page.
+    /// Otherwise it is kept at the tail, to be evicted next, so that a scan reading many pages
+    /// once doesn't push out the pages the workload keeps coming back to. Frequencies are
+    /// estimated by a count-min sketch.
+    TinyLfu,
+}
+
+impl CachePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CachePolicy::Lru => "lru",
+            CachePolicy::TinyLfu => "tinylfu",
+        }
+    }
+}
+
+impl fmt::Display for CachePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CachePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(CachePolicy::Lru),
+            "tinylfu" => Ok(CachePolicy::TinyLfu),
+            _ => Err(format!("unknown cache policy: {s}")),
+        }
+    }
+}
+
+pub struct PageCache {
     capacity: usize,
     map: RefCell<PageHashMap>,
     head: RefCell<Option<NonNull<PageCacheEntry>>>,
     tail: RefCell<Option<NonNull<PageCacheEntry>>>,
+    policy: CachePolicy,
+    /// Access frequencies of pages, only maintained under [CachePolicy::TinyLfu].
+    sketch: FrequencySketch,
 }
-unsafe impl Send for DumbLruPageCache {}
-unsafe impl Sync for DumbLruPageCache {}
+unsafe impl Send for PageCache {}
+unsafe impl Sync for PageCache {}
 
 struct PageHashMap {
     // FIXME: do we prefer array buckets or list? Deletes will be slower here which I guess happens often. I will do this for now to test how well it does.
@@ -60,10 +109,25 @@ pub enum CacheResizeResult {
 
 impl PageCacheKey {
     pub fn new(pgno: usize) -> Self {
-        Self { pgno }
+        Self {
+            pgno,
+            frame: None,
+            checkpoint_seq: 0,
+        }
+    }
+
+    /// Key of the committed version of page `pgno` found in WAL `frame`, or in the database
+    /// file if `None`. Versions never change once committed, so caches shared by connections
+    /// reading different snapshots use these keys.
+    pub fn versioned(pgno: usize, frame: Option<u64>, checkpoint_seq: u32) -> Self {
+        Self {
+            pgno,
+            frame,
+            checkpoint_seq,
+        }
     }
 }
-impl DumbLruPageCache {
+impl PageCache {
     pub fn new(capacity: usize) -> Self {
         assert!(capacity > 0, "capacity of cache should be at least 1");
         Self {
@@ -71,6 +135,21 @@ impl DumbLruPageCache {
             map: RefCell::new(PageHashMap::new(capacity)),
             head: RefCell::new(None),
             tail: RefCell::new(None),
+            policy: CachePolicy::default(),
+            sketch: FrequencySketch::new(capacity),
+        }
+    }
+
+    pub fn policy(&self) -> CachePolicy {
+        self.policy
+    }
+
+    /// Switches to `policy`. Cached pages stay where they are, access frequencies are only
+    /// tracked from now on.
+    pub fn set_policy(&mut self, policy: CachePolicy) {
+        if self.policy != policy {
+            self.policy = policy;
+            self.sketch = FrequencySketch::new(self.capacity);
         }
     }
 
@@ -108,6 +187,10 @@ impl DumbLruPageCache {
                 return Err(CacheError::KeyExists);
             }
         }
+        let admit = match self.policy {
+            CachePolicy::Lru => true,
+            CachePolicy::TinyLfu => self.admit(&key),
+        };
         self.make_room_for(1)?;
         let entry = Box::new(PageCacheEntry {
             key: key.clone(),
@@ -117,7 +200,11 @@ impl DumbLruPageCache {
         });
         let ptr_raw = Box::into_raw(entry);
         let ptr = unsafe { NonNull::new_unchecked(ptr_raw) };
-        self.touch(ptr);
+        if admit {
+            self.touch(ptr);
+        } else {
+            self.push_tail(ptr);
+        }
 
         self.map.borrow_mut().insert(key, ptr);
         Ok(())
@@ -144,6 +231,44 @@ impl DumbLruPageCache {
         Ok(())
     }
 
+    /// Records an access to the page about to be inserted and decides whether it's worth
+    /// keeping over the page it would evict.
+    fn admit(&mut self, key: &PageCacheKey) -> bool {
+        self.sketch.increment(key.pgno);
+        if self.len() < self.capacity {
+            return true;
+        }
+        match self.tinylfu_victim() {
+            Some(victim) => self.sketch.frequency(key.pgno) > self.sketch.frequency(victim.pgno),
+            None => true,
+        }
+    }
+
+    /// The least frequently accessed of the first [TINYLFU_EVICTION_SAMPLE] evictable pages
+    /// from the tail of the LRU list, the closest to the tail on ties.
+    fn tinylfu_victim(&self) -> Option<PageCacheKey> {
+        let mut victim: Option<(&PageCacheKey, u8)> = None;
+        let mut sampled = 0;
+        let mut current = *self.tail.borrow();
+        while let Some(node) = current {
+            let entry = unsafe { node.as_ref() };
+            current = entry.prev;
+            if entry.page.is_locked() || entry.page.is_dirty() {
+                continue;
+            }
+            let frequency = self.sketch.frequency(entry.key.pgno);
+            match victim {
+                Some((_, lowest)) if lowest <= frequency => {}
+                _ => victim = Some((&entry.key, frequency)),
+            }
+            sampled += 1;
+            if sampled == TINYLFU_EVICTION_SAMPLE {
+                break;
+            }
+        }
+        victim.map(|(key, _)| key.clone())
+    }
+
     fn get_ptr(&mut self, key: &PageCacheKey) -> Option<NonNull<PageCacheEntry>> {
         let m = self.map.borrow_mut();
         let ptr = m.get(key);
@@ -160,6 +285,9 @@ impl DumbLruPageCache {
         let mut ptr = self.get_ptr(key)?;
         let page = unsafe { ptr.as_mut().page.clone() };
         if touch {
+            if self.policy == CachePolicy::TinyLfu {
+                self.sketch.increment(key.pgno);
+            }
             self.unlink(ptr);
             self.touch(ptr);
         }
@@ -172,6 +300,7 @@ impl DumbLruPageCache {
         let new_map = self.map.borrow().rehash(capacity);
         self.map.replace(new_map);
         self.capacity = capacity;
+        self.sketch = FrequencySketch::new(capacity);
         match self.make_room_for(0) {
             Ok(_) => CacheResizeResult::Done,
             Err(_) => CacheResizeResult::PendingEvictions,
@@ -250,6 +379,22 @@ impl DumbLruPageCache {
         self.head.borrow_mut().replace(entry);
     }
 
+    /// inserts into tail, assuming we detached first
+    fn push_tail(&mut self, mut entry: NonNull<PageCacheEntry>) {
+        if let Some(mut tail) = *self.tail.borrow_mut() {
+            unsafe {
+                entry.as_mut().prev.replace(tail);
+                let tail = tail.as_mut();
+                tail.next = Some(entry);
+            }
+        }
+
+        if self.head.borrow().is_none() {
+            self.head.borrow_mut().replace(entry);
+        }
+        self.tail.borrow_mut().replace(entry);
+    }
+
     pub fn make_room_for(&mut self, n: usize) -> Result<(), CacheError> {
         if n > self.capacity {
             return Err(CacheError::Full);
@@ -273,14 +418,26 @@ impl DumbLruPageCache {
         let x = n.saturating_sub(available);
         let mut need_to_evict = x.saturating_add(len.saturating_sub(self.capacity));
 
-        let mut current_opt = Some(tail);
-        while need_to_evict > 0 && current_opt.is_some() {
-            let current = current_opt.unwrap();
-            let entry = unsafe { current.as_ref() };
-            current_opt = entry.prev; // Pick prev before modifying entry
-            match self.delete(entry.key.clone()) {
-                Err(_) => {}
-                Ok(_) => need_to_evict -= 1,
+        if self.policy == CachePolicy::TinyLfu {
+            while need_to_evict > 0 {
+                let Some(victim) = self.tinylfu_victim() else {
+                    break;
+                };
+                if self.delete(victim).is_err() {
+                    break;
+                }
+                need_to_evict -= 1;
+            }
+        } else {
+            let mut current_opt = Some(tail);
+            while need_to_evict > 0 && current_opt.is_some() {
+                let current = current_opt.unwrap();
+                let entry = unsafe { current.as_ref() };
+                current_opt = entry.prev; // Pick prev before modifying entry
+                match self.delete(entry.key.clone()) {
+                    Err(_) => {}
+                    Ok(_) => need_to_evict -= 1,
+                }
             }
         }
 
@@ -347,6 +504,10 @@ impl DumbLruPageCache {
         self.map.borrow().len()
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     #[cfg(test)]
     fn get_entry_ptr(&self, key: &PageCacheKey) -> Option<NonNull<PageCacheEntry>> {
         self.map.borrow().get(key).copied()
@@ -483,9 +644,83 @@ impl DumbLruPageCache {
     }
 }
 
-impl Default for DumbLruPageCache {
+impl Default for PageCache {
     fn default() -> Self {
-        DumbLruPageCache::new(DEFAULT_PAGE_CACHE_SIZE_IN_PAGES)
+        PageCache::new(DEFAULT_PAGE_CACHE_SIZE_IN_PAGES)
+    }
+}
+
+/// Number of pages from the tail of the LRU list considered when evicting under
+/// [CachePolicy::TinyLfu].
+const TINYLFU_EVICTION_SAMPLE: usize = 8;
+
+const SKETCH_DEPTH: usize = 4;
+/// Odd multipliers hashing page numbers into each row of the sketch.
+const SKETCH_SEEDS: [u64; SKETCH_DEPTH] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0xd6e8_feb8_6659_fd93,
+];
+const SKETCH_MAX_FREQUENCY: u8 = 15;
+
+/// Count-min sketch estimating how often each page was accessed recently. Counters saturate
+/// at 15 and are all halved once the sketch has counted ten accesses per cache slot, so that
+/// pages that used to be popular eventually make way for the ones that are now.
+struct FrequencySketch {
+    counters: Vec<u8>,
+    mask: usize,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl FrequencySketch {
+    fn new(capacity: usize) -> Self {
+        let width = capacity.max(16).next_power_of_two();
+        Self {
+            counters: vec![0; width * SKETCH_DEPTH],
+            mask: width - 1,
+            additions: 0,
+            sample_size: capacity.max(16) * 10,
+        }
+    }
+
+    fn index(&self, pgno: usize, row: usize) -> usize {
+        let hash = (pgno as u64)
+            .wrapping_add(1)
+            .wrapping_mul(SKETCH_SEEDS[row]);
+        row * (self.mask + 1) + ((hash >> 32) as usize & self.mask)
+    }
+
+    fn increment(&mut self, pgno: usize) {
+        let mut added = false;
+        for row in 0..SKETCH_DEPTH {
+            let idx = self.index(pgno, row);
+            if self.counters[idx] < SKETCH_MAX_FREQUENCY {
+                self.counters[idx] += 1;
+                added = true;
+            }
+        }
+        if added {
+            self.additions += 1;
+            if self.additions >= self.sample_size {
+                self.age();
+            }
+        }
+    }
+
+    fn frequency(&self, pgno: usize) -> u8 {
+        (0..SKETCH_DEPTH)
+            .map(|row| self.counters[self.index(pgno, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn age(&mut self) {
+        for counter in self.counters.iter_mut() {
+            *counter >>= 1;
+        }
+        self.additions /= 2;
     }
 }
 
@@ -593,12 +828,12 @@ impl PageHashMap {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::io::{Buffer, BufferData};
+    use crate::io::Buffer;
     use crate::storage::page_cache::CacheError;
     use crate::storage::pager::{Page, PageRef};
     use crate::storage::sqlite3_ondisk::PageContent;
     use std::ptr::NonNull;
-    use std::{cell::RefCell, num::NonZeroUsize, pin::Pin, rc::Rc, sync::Arc};
+    use std::{cell::RefCell, num::NonZeroUsize, sync::Arc};
 
     use lru::LruCache;
     use rand_chacha::{
@@ -614,8 +849,7 @@ mod tests {
     pub fn page_with_content(page_id: usize) -> PageRef {
         let page = Arc::new(Page::new(page_id));
         {
-            let buffer_drop_fn = Rc::new(|_data: BufferData| {});
-            let buffer = Buffer::new(Pin::new(vec![0; 4096]), buffer_drop_fn);
+            let buffer = Buffer::allocate(4096);
             let page_content = PageContent {
                 offset: 0,
                 buffer: Arc::new(RefCell::new(buffer)),
@@ -627,7 +861,7 @@ mod tests {
         page
     }
 
-    fn insert_page(cache: &mut DumbLruPageCache, id: usize) -> PageCacheKey {
+    fn insert_page(cache: &mut PageCache, id: usize) -> PageCacheKey {
         let key = create_key(id);
         let page = page_with_content(id);
         assert!(cache.insert(key.clone(), page).is_ok());
@@ -639,7 +873,7 @@ mod tests {
     }
 
     fn insert_and_get_entry(
-        cache: &mut DumbLruPageCache,
+        cache: &mut PageCache,
         id: usize,
     ) -> (PageCacheKey, NonNull<PageCacheEntry>) {
         let key = create_key(id);
@@ -651,7 +885,7 @@ mod tests {
 
     #[test]
     fn test_detach_only_element() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = insert_page(&mut cache, 1);
         cache.verify_list_integrity();
         assert_eq!(cache.len(), 1);
@@ -677,7 +911,7 @@ mod tests {
 
     #[test]
     fn test_detach_head() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let _key1 = insert_page(&mut cache, 1); // Tail
         let key2 = insert_page(&mut cache, 2); // Middle
         let key3 = insert_page(&mut cache, 3); // Head
@@ -721,7 +955,7 @@ mod tests {
 
     #[test]
     fn test_detach_tail() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = insert_page(&mut cache, 1); // Tail
         let key2 = insert_page(&mut cache, 2); // Middle
         let _key3 = insert_page(&mut cache, 3); // Head
@@ -775,7 +1009,7 @@ mod tests {
 
     #[test]
     fn test_detach_middle() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = insert_page(&mut cache, 1); // Tail
         let key2 = insert_page(&mut cache, 2); // Middle
         let key3 = insert_page(&mut cache, 3); // Middle
@@ -824,7 +1058,7 @@ mod tests {
     #[test]
     #[ignore = "for now let's not track active refs"]
     fn test_detach_via_delete() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = create_key(1);
         let page1 = page_with_content(1);
         assert!(cache.insert(key1.clone(), page1.clone()).is_ok());
@@ -846,7 +1080,7 @@ mod tests {
     #[test]
     #[should_panic(expected = "Attempted to insert different page with same key")]
     fn test_insert_existing_key_fail() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = create_key(1);
         let page1_v1 = page_with_content(1);
         let page1_v2 = page_with_content(1);
@@ -858,7 +1092,7 @@ mod tests {
 
     #[test]
     fn test_detach_nonexistent_key() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key_nonexist = create_key(99);
 
         assert!(cache.delete(key_nonexist.clone()).is_ok()); // no-op
@@ -866,7 +1100,7 @@ mod tests {
 
     #[test]
     fn test_page_cache_evict() {
-        let mut cache = DumbLruPageCache::new(1);
+        let mut cache = PageCache::new(1);
         let key1 = insert_page(&mut cache, 1);
         let key2 = insert_page(&mut cache, 2);
         assert_eq!(cache.get(&key2).unwrap().get().id, 2);
@@ -875,7 +1109,7 @@ mod tests {
 
     #[test]
     fn test_detach_locked_page() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (_, mut entry) = insert_and_get_entry(&mut cache, 1);
         unsafe { entry.as_mut().page.set_locked() };
         assert_eq!(cache.detach(entry, false), Err(CacheError::Locked));
@@ -884,7 +1118,7 @@ mod tests {
 
     #[test]
     fn test_detach_dirty_page() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (key, mut entry) = insert_and_get_entry(&mut cache, 1);
         cache.get(&key).expect("Page should exist");
         unsafe { entry.as_mut().page.set_dirty() };
@@ -898,7 +1132,7 @@ mod tests {
     #[test]
     #[ignore = "for now let's not track active refs"]
     fn test_detach_with_active_reference_clean() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (key, entry) = insert_and_get_entry(&mut cache, 1);
         let page_ref = cache.get(&key);
         assert_eq!(cache.detach(entry, true), Err(CacheError::ActiveRefs));
@@ -909,7 +1143,7 @@ mod tests {
     #[test]
     #[ignore = "for now let's not track active refs"]
     fn test_detach_with_active_reference_no_clean() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (key, entry) = insert_and_get_entry(&mut cache, 1);
         cache.get(&key).expect("Page should exist");
         assert!(cache.detach(entry, false).is_ok());
@@ -919,7 +1153,7 @@ mod tests {
 
     #[test]
     fn test_detach_without_cleaning() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (key, entry) = insert_and_get_entry(&mut cache, 1);
         assert!(cache.detach(entry, false).is_ok());
         assert!(cache.map.borrow_mut().remove(&key).is_some());
@@ -929,7 +1163,7 @@ mod tests {
 
     #[test]
     fn test_detach_with_cleaning() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (key, entry) = insert_and_get_entry(&mut cache, 1);
         let page = cache.get(&key).expect("Page should exist");
         assert!(page_has_content(&page));
@@ -944,7 +1178,7 @@ mod tests {
 
     #[test]
     fn test_detach_only_element_preserves_integrity() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (_, entry) = insert_and_get_entry(&mut cache, 1);
         assert!(cache.detach(entry, false).is_ok());
         assert!(
@@ -959,7 +1193,7 @@ mod tests {
 
     #[test]
     fn test_detach_with_multiple_pages() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let (key1, _) = insert_and_get_entry(&mut cache, 1);
         let (key2, entry2) = insert_and_get_entry(&mut cache, 2);
         let (key3, _) = insert_and_get_entry(&mut cache, 3);
@@ -995,7 +1229,7 @@ mod tests {
         let mut rng = ChaCha8Rng::seed_from_u64(seed);
         tracing::info!("super seed: {}", seed);
         let max_pages = 10;
-        let mut cache = DumbLruPageCache::new(10);
+        let mut cache = PageCache::new(10);
         let mut lru = LruCache::new(NonZeroUsize::new(10).unwrap());
 
         for _ in 0..10000 {
@@ -1058,7 +1292,7 @@ mod tests {
         }
     }
 
-    pub fn compare_to_lru(cache: &mut DumbLruPageCache, lru: &LruCache<PageCacheKey, PageRef>) {
+    pub fn compare_to_lru(cache: &mut PageCache, lru: &LruCache<PageCacheKey, PageRef>) {
         let this_keys = cache.keys();
         let mut lru_keys = Vec::new();
         for (lru_key, _) in lru {
@@ -1075,7 +1309,7 @@ mod tests {
 
     #[test]
     fn test_page_cache_insert_and_get() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = insert_page(&mut cache, 1);
         let key2 = insert_page(&mut cache, 2);
         assert_eq!(cache.get(&key1).unwrap().get().id, 1);
@@ -1084,7 +1318,7 @@ mod tests {
 
     #[test]
     fn test_page_cache_over_capacity() {
-        let mut cache = DumbLruPageCache::new(2);
+        let mut cache = PageCache::new(2);
         let key1 = insert_page(&mut cache, 1);
         let key2 = insert_page(&mut cache, 2);
         let key3 = insert_page(&mut cache, 3);
@@ -1095,7 +1329,7 @@ mod tests {
 
     #[test]
     fn test_page_cache_delete() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = insert_page(&mut cache, 1);
         assert!(cache.delete(key1.clone()).is_ok());
         assert!(cache.get(&key1).is_none());
@@ -1103,7 +1337,7 @@ mod tests {
 
     #[test]
     fn test_page_cache_clear() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let key1 = insert_page(&mut cache, 1);
         let key2 = insert_page(&mut cache, 2);
         assert!(cache.clear().is_ok());
@@ -1113,7 +1347,7 @@ mod tests {
 
     #[test]
     fn test_page_cache_insert_sequential() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         for i in 0..10000 {
             let key = insert_page(&mut cache, i);
             assert_eq!(cache.peek(&key, false).unwrap().get().id, i);
@@ -1122,7 +1356,7 @@ mod tests {
 
     #[test]
     fn test_resize_smaller_success() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         for i in 1..=5 {
             let _ = insert_page(&mut cache, i);
         }
@@ -1137,7 +1371,7 @@ mod tests {
     #[test]
     #[should_panic(expected = "Attempted to insert different page with same key")]
     fn test_resize_larger() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let _ = insert_page(&mut cache, 1);
         let _ = insert_page(&mut cache, 2);
         assert_eq!(cache.len(), 2);
@@ -1159,7 +1393,7 @@ mod tests {
     #[test]
     #[ignore = "for now let's not track active refs"]
     fn test_resize_with_active_references() {
-        let mut cache = DumbLruPageCache::default();
+        let mut cache = PageCache::default();
         let page1 = page_with_content(1);
         let page2 = page_with_content(2);
         let page3 = page_with_content(3);
@@ -1181,7 +1415,7 @@ mod tests {
 
     #[test]
     fn test_resize_same_capacity() {
-        let mut cache = DumbLruPageCache::new(3);
+        let mut cache = PageCache::new(3);
         for i in 1..=3 {
             let _ = insert_page(&mut cache, i);
         }
@@ -1193,13 +1427,69 @@ mod tests {
         assert!(cache.insert(create_key(4), page_with_content(4)).is_ok());
     }
 
+    #[test]
+    fn test_tinylfu_scan_keeps_frequent_pages() {
+        let mut cache = PageCache::new(4);
+        cache.set_policy(CachePolicy::TinyLfu);
+        let hot1 = insert_page(&mut cache, 1);
+        let hot2 = insert_page(&mut cache, 2);
+        for _ in 0..3 {
+            assert!(cache.get(&hot1).is_some());
+            assert!(cache.get(&hot2).is_some());
+        }
+        for i in 100..200 {
+            let _ = insert_page(&mut cache, i);
+            cache.verify_list_integrity();
+        }
+        assert_eq!(cache.len(), 4);
+        assert!(cache.get(&hot1).is_some());
+        assert!(cache.get(&hot2).is_some());
+        assert!(cache.get(&create_key(199)).is_some());
+    }
+
+    #[test]
+    fn test_lru_scan_evicts_frequent_pages() {
+        let mut cache = PageCache::new(4);
+        let hot1 = insert_page(&mut cache, 1);
+        for _ in 0..3 {
+            assert!(cache.get(&hot1).is_some());
+        }
+        for i in 100..104 {
+            let _ = insert_page(&mut cache, i);
+        }
+        assert!(cache.get(&hot1).is_none());
+    }
+
+    #[test]
+    fn test_tinylfu_admits_page_more_frequent_than_victim() {
+        let mut cache = PageCache::new(2);
+        cache.set_policy(CachePolicy::TinyLfu);
+        let _ = insert_page(&mut cache, 1);
+        let _ = insert_page(&mut cache, 2);
+        let key3 = insert_page(&mut cache, 3);
+        assert!(cache.delete(key3.clone()).is_ok());
+        let _ = insert_page(&mut cache, 4);
+        // Page 3 was accessed before, so it outweighs the pages it competes with.
+        let _ = insert_page(&mut cache, 3);
+        assert_eq!(cache.keys(), vec![key3, create_key(4)]);
+        cache.verify_list_integrity();
+    }
+
+    #[test]
+    fn test_cache_policy_from_str() {
+        assert_eq!("lru".parse::<CachePolicy>(), Ok(CachePolicy::Lru));
+        assert_eq!("TinyLFU".parse::<CachePolicy>(), Ok(CachePolicy::TinyLfu));
+        assert!("clock".parse::<CachePolicy>().is_err());
+        assert_eq!(CachePolicy::TinyLfu.to_string(), "tinylfu");
+    }
+
     #[test]
     #[ignore = "long running test, remove to verify"]
     fn test_clear_memory_stability() {
         let initial_memory = memory_stats::memory_stats().unwrap().physical_mem;
 
         for _ in 0..100000 {
-            let mut cache = DumbLruPageCache::new(1000);
+            let mut cache = PageCache::new(1000);
 
             for i in 0..1000 {
                 let key = create_key(i);
@@ -1,24 +1,31 @@
 use crate::result::LimboResult;
 use crate::storage::btree::BTreePageInner;
 use crate::storage::buffer_pool::BufferPool;
+use crate::storage::checksum::{
+    self, CorruptionCallback, PageChecksumVerifier, CHECKSUM_MARKER, CHECKSUM_MARKER_OFFSET,
+    CHECKSUM_SIZE,
+};
 use crate::storage::database::DatabaseStorage;
 use crate::storage::header_accessor;
 use crate::storage::sqlite3_ondisk::{self, DatabaseHeader, PageContent, PageType};
-use crate::storage::wal::{CheckpointResult, Wal, WalFsyncStatus};
+use crate::storage::wal::{CheckpointResult, JournalMode, Wal, WalFsyncStatus};
 use crate::types::CursorResult;
-use crate::{Buffer, Connection, LimboError, Result};
 use crate::{Completion, WalFile};
+use crate::{Connection, LimboError, Result};
 use parking_lot::RwLock;
-use std::cell::{OnceCell, RefCell, UnsafeCell};
+use std::cell::{Cell, OnceCell, RefCell, UnsafeCell};
 use std::collections::HashSet;
 use std::rc::Rc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tracing::{trace, Level};
 
 use super::btree::{btree_init_page, BTreePage};
-use super::page_cache::{CacheError, CacheResizeResult, DumbLruPageCache, PageCacheKey};
-use super::sqlite3_ondisk::{begin_write_btree_page, DATABASE_HEADER_SIZE};
+use super::page_cache::{CacheError, CachePolicy, CacheResizeResult, PageCache, PageCacheKey};
+use super::sqlite3_ondisk::{
+    begin_write_btree_page, DATABASE_HEADER_PAGE_ID, DATABASE_HEADER_SIZE,
+};
 use super::wal::{CheckpointMode, CheckpointStatus};
 
 #[cfg(not(feature = "omit_autovacuum"))]
@@ -174,6 +181,36 @@ pub enum BtreePageAllocMode {
     Exact(u32),
     /// Allocate a page number less than or equal to the parameter
     Le(u32),
+    /// Allocate any btree page, preferring free pages numerically close to the parameter
+    Near(u32),
+}
+
+/// How often the pager fsyncs when committing (`PRAGMA synchronous`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SynchronousMode {
+    /// Never fsync; a crash or power loss may lose or corrupt recent transactions.
+    Off,
+    /// Fsync the WAL only before checkpointing it, so a power loss may roll back
+    /// recent transactions but never corrupts the database.
+    Normal,
+    /// Fsync the WAL on every commit.
+    Full,
+}
+
+/// Upper bounds of the buckets of [PageAllocationStats::locality_histogram]. The last bucket
+/// counts every distance above the last bound.
+pub const LOCALITY_HISTOGRAM_BOUNDS: [u32; 4] = [1, 16, 256, 4096];
+
+/// Counters describing where the pager found the pages it allocated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageAllocationStats {
+    /// Pages taken off the freelist.
+    pub freelist_hits: u64,
+    /// Pages allocated by growing the database file.
+    pub file_extensions: u64,
+    /// Distance between a page taken off the freelist and the page it was requested near,
+    /// bucketed by [LOCALITY_HISTOGRAM_BOUNDS].
+    pub locality_histogram: [u64; LOCALITY_HISTOGRAM_BOUNDS.len() + 1],
 }
 
 /// This will keep track of the state of current cache flush in order to not repeat work
@@ -197,13 +234,25 @@ pub const DB_STATE_INITIALIZED: usize = 2;
 /// The pager interface implements the persistence layer by providing access
 /// to pages of the database file, including caching, concurrency control, and
 /// transaction management.
+/// First delay between two attempts to acquire the write lock when a busy timeout is set.
+const BUSY_INITIAL_DELAY: Duration = Duration::from_millis(1);
+/// Upper bound of the delay between two attempts to acquire the write lock.
+const BUSY_MAX_DELAY: Duration = Duration::from_millis(100);
+
 pub struct Pager {
     /// Source of the database pages.
     pub db_file: Arc<dyn DatabaseStorage>,
     /// The write-ahead log (WAL) for the database.
     wal: Rc<RefCell<dyn Wal>>,
-    /// A page cache for the database.
-    page_cache: Arc<RwLock<DumbLruPageCache>>,
+    /// Pages of the current write transaction, which modifies them in place. Outside of write
+    /// transactions pages are read through `shared_page_cache` when there is one.
+    page_cache: Arc<RwLock<PageCache>>,
+    /// Committed pages shared by every connection to the database, keyed by version so that
+    /// connections reading different snapshots each find their own.
+    shared_page_cache: Option<Arc<RwLock<PageCache>>>,
+    /// Whether this pager holds the write lock, in which case it works on private copies of
+    /// the pages.
+    write_tx: Cell<bool>,
     /// Buffer pool for temporary data storage.
     pub buffer_pool: Arc<BufferPool>,
     /// I/O interface for input/output operations.
@@ -227,6 +276,39 @@ pub struct Pager {
     /// to change it.
     page_size: OnceCell<u16>,
     reserved_space: OnceCell<u8>,
+    /// Whether page checksums are verified on read (`PRAGMA checksum_verification`).
+    checksum_verification: Cell<bool>,
+    /// When commits and checkpoints fsync (`PRAGMA synchronous`).
+    synchronous: Cell<SynchronousMode>,
+    /// Whether the pages of this database carry checksums. Only known once page 1 exists.
+    has_page_checksums: Cell<Option<bool>>,
+    /// Callback invoked when a page fails checksum verification, shared with the `Database`.
+    corruption_callback: Arc<RwLock<Option<CorruptionCallback>>>,
+    allocation_stats: Cell<PageAllocationStats>,
+    /// Whether free pages are picked by their distance to the page they're allocated near,
+    /// rather than in freelist order.
+    freelist_locality: Cell<bool>,
+    /// Maximum number of pages the database file may grow to, 0 meaning no quota. Shared with
+    /// the `Database` so that every connection enforces the same quota.
+    page_quota: Arc<AtomicU32>,
+    /// Largest page count this pager has grown the database file to.
+    page_high_water: Cell<u32>,
+    /// How long [Pager::begin_write_tx] keeps retrying while another connection holds the
+    /// write lock before giving up with [LimboResult::Busy]. Zero means no retries.
+    busy_timeout: Cell<Duration>,
+}
+
+/// Page usage of a database file, see [Pager::page_usage].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageUsage {
+    /// Number of pages in the database file, including the freelist.
+    pub page_count: u32,
+    /// Number of pages on the freelist, which are reused before the file grows.
+    pub freelist_pages: u32,
+    /// Largest page count the database file reached through this pager.
+    pub high_water: u32,
+    /// The page quota set with [Pager::set_page_quota], if any.
+    pub quota: Option<u32>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -263,7 +345,7 @@ impl Pager {
         db_file: Arc<dyn DatabaseStorage>,
         wal: Rc<RefCell<dyn Wal>>,
         io: Arc<dyn crate::io::IO>,
-        page_cache: Arc<RwLock<DumbLruPageCache>>,
+        page_cache: Arc<RwLock<PageCache>>,
         buffer_pool: Arc<BufferPool>,
         is_empty: Arc<AtomicUsize>,
         init_lock: Arc<Mutex<()>>,
@@ -277,6 +359,8 @@ impl Pager {
             db_file,
             wal,
             page_cache,
+            shared_page_cache: None,
+            write_tx: Cell::new(false),
             io,
             dirty_pages: Rc::new(RefCell::new(HashSet::new())),
             flush_info: RefCell::new(FlushInfo {
@@ -293,6 +377,15 @@ impl Pager {
             allocate_page1_state,
             page_size: OnceCell::new(),
             reserved_space: OnceCell::new(),
+            checksum_verification: Cell::new(false),
+            synchronous: Cell::new(SynchronousMode::Full),
+            has_page_checksums: Cell::new(None),
+            corruption_callback: Arc::new(RwLock::new(None)),
+            allocation_stats: Cell::new(PageAllocationStats::default()),
+            freelist_locality: Cell::new(true),
+            page_quota: Arc::new(AtomicU32::new(0)),
+            page_high_water: Cell::new(0),
+            busy_timeout: Cell::new(Duration::ZERO),
         })
     }
 
@@ -300,6 +393,116 @@ impl Pager {
         self.wal = wal;
     }
 
+    /// Reads committed pages through `page_cache`, which other connections to the database
+    /// share, instead of the private page cache.
+    pub fn set_shared_page_cache(&mut self, page_cache: Arc<RwLock<PageCache>>) {
+        self.shared_page_cache = Some(page_cache);
+    }
+
+    pub fn set_corruption_callback(
+        &mut self,
+        corruption_callback: Arc<RwLock<Option<CorruptionCallback>>>,
+    ) {
+        self.corruption_callback = corruption_callback;
+    }
+
+    pub fn set_page_quota_handle(&mut self, page_quota: Arc<AtomicU32>) {
+        self.page_quota = page_quota;
+    }
+
+    /// Limits the database file to `quota` pages; `None` removes the limit. Allocating a page
+    /// that would grow the file past the quota fails with [LimboError::QuotaExceeded], while
+    /// pages on the freelist can always be reused. The quota is shared by every connection
+    /// of the database.
+    pub fn set_page_quota(&self, quota: Option<u32>) {
+        self.page_quota.store(quota.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    pub fn page_quota(&self) -> Option<u32> {
+        match self.page_quota.load(Ordering::SeqCst) {
+            0 => None,
+            quota => Some(quota),
+        }
+    }
+
+    /// Returns the page usage of the database file, read from the database header.
+    pub fn page_usage(&self) -> Result<PageUsage> {
+        let page_count = header_accessor::get_database_size(self)?;
+        Ok(PageUsage {
+            page_count,
+            freelist_pages: header_accessor::get_freelist_pages(self)?,
+            high_water: self.page_high_water.get().max(page_count),
+            quota: self.page_quota(),
+        })
+    }
+
+    pub fn get_checksum_verification(&self) -> bool {
+        self.checksum_verification.get()
+    }
+
+    /// Enables or disables page checksum verification on read.
+    /// If page 1 has not been allocated yet, enabling verification also makes the new
+    /// database reserve space for, and write, page checksums.
+    pub fn set_checksum_verification(&self, enabled: bool) {
+        self.checksum_verification.set(enabled);
+    }
+
+    pub fn get_synchronous(&self) -> SynchronousMode {
+        self.synchronous.get()
+    }
+
+    pub fn set_synchronous(&self, mode: SynchronousMode) {
+        self.synchronous.set(mode);
+    }
+
+    /// Returns the checkpoint rate limit in bytes per second, 0 if checkpoints are not throttled.
+    pub fn get_checkpoint_rate_limit(&self) -> u64 {
+        self.wal.borrow().get_checkpoint_rate_limit()
+    }
+
+    /// Limits how fast checkpoints copy pages from the WAL into the database file
+    /// (`PRAGMA checkpoint_rate_limit`). A limit of 0 disables throttling.
+    pub fn set_checkpoint_rate_limit(&self, bytes_per_sec: u64) {
+        self.wal
+            .borrow_mut()
+            .set_checkpoint_rate_limit(bytes_per_sec);
+    }
+
+    /// Returns true while the ongoing checkpoint is waiting for the rate limit.
+    pub fn is_checkpoint_throttled(&self) -> bool {
+        self.wal.borrow().is_checkpoint_throttled()
+    }
+
+    /// Returns whether pages of this database carry checksums, as announced by the header.
+    pub fn has_page_checksums(&self) -> bool {
+        if let Some(has_checksums) = self.has_page_checksums.get() {
+            return has_checksums;
+        }
+        if self.is_empty.load(Ordering::SeqCst) < DB_STATE_INITIALIZED {
+            return false;
+        }
+        let has_checksums = header_accessor::get_reserved_space(self).unwrap_or_default() as usize
+            >= CHECKSUM_SIZE
+            && header_accessor::get_checksum_marker(self).unwrap_or_default() == CHECKSUM_MARKER;
+        self.has_page_checksums.set(Some(has_checksums));
+        has_checksums
+    }
+
+    /// Returns the verifier to run when `page_idx` is read, if checksums should be verified.
+    fn checksum_verifier(&self, page_idx: usize) -> Option<PageChecksumVerifier> {
+        if !self.checksum_verification.get() {
+            return None;
+        }
+        // Page 1 carries the header announcing checksums, so the verifier inspects it directly
+        // instead of reading the header through the pager.
+        if page_idx != DATABASE_HEADER_PAGE_ID && !self.has_page_checksums() {
+            return None;
+        }
+        Some(PageChecksumVerifier::new(
+            self.corruption_callback.read().clone(),
+        ))
+    }
+
     pub fn get_auto_vacuum_mode(&self) -> AutoVacuumMode {
         *self.auto_vacuum_mode.borrow()
     }
@@ -479,7 +682,7 @@ impl Pager {
         };
         #[cfg(feature = "omit_autovacuum")]
         {
-            let page = self.do_allocate_page(page_type, 0, BtreePageAllocMode::Any);
+            let page = self.do_allocate_page(page_type, 0, BtreePageAllocMode::Any)?;
             let page_id = page.get().get().id;
             Ok(CursorResult::Ok(page_id as u32))
         }
@@ -490,7 +693,7 @@ impl Pager {
             let auto_vacuum_mode = self.auto_vacuum_mode.borrow();
             match *auto_vacuum_mode {
                 AutoVacuumMode::None => {
-                    let page = self.do_allocate_page(page_type, 0, BtreePageAllocMode::Any);
+                    let page = self.do_allocate_page(page_type, 0, BtreePageAllocMode::Any)?;
                     let page_id = page.get().get().id;
                     Ok(CursorResult::Ok(page_id as u32))
                 }
@@ -514,7 +717,7 @@ impl Pager {
                         page_type,
                         0,
                         BtreePageAllocMode::Exact(root_page_num),
-                    );
+                    )?;
                     let allocated_page_id = page.get().get().id as u32;
                     if allocated_page_id != root_page_num {
                         //  TODO(Zaid): Handle swapping the allocated page with the desired root page
@@ -538,8 +741,8 @@ impl Pager {
     /// Allocate a new overflow page.
     /// This is done when a cell overflows and new space is needed.
     // FIXME: handle no room in page cache
-    pub fn allocate_overflow_page(&self) -> PageRef {
-        let page = self.allocate_page().unwrap();
+    pub fn allocate_overflow_page(&self) -> Result<PageRef> {
+        let page = self.allocate_page_near(None)?;
         tracing::debug!("Pager::allocate_overflow_page(id={})", page.get().id);
 
         // setup overflow page
@@ -547,7 +750,7 @@ impl Pager {
         let buf = contents.as_ptr();
         buf.fill(0);
 
-        page
+        Ok(page)
     }
 
     /// Allocate a new page to the btree via the pager.
@@ -557,9 +760,13 @@ impl Pager {
         &self,
         page_type: PageType,
         offset: usize,
-        _alloc_mode: BtreePageAllocMode,
-    ) -> BTreePage {
-        let page = self.allocate_page().unwrap();
+        alloc_mode: BtreePageAllocMode,
+    ) -> Result<BTreePage> {
+        let page = match alloc_mode {
+            BtreePageAllocMode::Any => self.allocate_page_near(None),
+            BtreePageAllocMode::Near(near) => self.allocate_page_near(Some(near)),
+            BtreePageAllocMode::Exact(_) | BtreePageAllocMode::Le(_) => self.allocate_page(),
+        }?;
         let page = Arc::new(BTreePageInner {
             page: RefCell::new(page),
         });
@@ -569,7 +776,7 @@ impl Pager {
             page.get().get().id,
             page.get().get_contents().page_type()
         );
-        page
+        Ok(page)
     }
 
     /// The "usable size" of a database page is the page size specified by the 2-byte integer at offset 16
@@ -588,6 +795,23 @@ impl Pager {
         (page_size as usize) - (reserved_space as usize)
     }
 
+    /// Returns the byte offset in the database file of `offset_in_page` within page `page_idx`,
+    /// or None if the current version of the page is not in the database file, i.e. the page
+    /// is dirty or its latest version lives in the WAL.
+    pub fn db_file_offset(&self, page_idx: usize, offset_in_page: usize) -> Result<Option<u64>> {
+        if self.dirty_pages.borrow().contains(&page_idx)
+            || self.wal.borrow().find_frame(page_idx as u64)?.is_some()
+        {
+            return Ok(None);
+        }
+        let page_size = *self
+            .page_size
+            .get_or_init(|| header_accessor::get_page_size(self).unwrap_or_default());
+        Ok(Some(
+            (page_idx as u64 - 1) * page_size as u64 + offset_in_page as u64,
+        ))
+    }
+
     #[inline(always)]
     pub fn begin_read_tx(&self) -> Result<CursorResult<LimboResult>> {
         // We allocate the first page lazily in the first transaction
@@ -620,15 +844,64 @@ impl Pager {
         }
     }
 
+    /// Takes the WAL write lock. With `exclusive`, as for `BEGIN EXCLUSIVE`, other connections
+    /// are kept from reading until the transaction ends as well; that fails right away, without
+    /// waiting for the busy timeout, if another connection is reading already.
     #[inline(always)]
-    pub fn begin_write_tx(&self) -> Result<CursorResult<LimboResult>> {
+    pub fn begin_write_tx(&self, exclusive: bool) -> Result<CursorResult<LimboResult>> {
         // TODO(Diego): The only possibly allocate page1 here is because OpenEphemeral needs a write transaction
         // we should have a unique API to begin transactions, something like sqlite3BtreeBeginTrans
         match self.maybe_allocate_page1()? {
             CursorResult::Ok(_) => {}
             CursorResult::IO => return Ok(CursorResult::IO),
         }
-        Ok(CursorResult::Ok(self.wal.borrow_mut().begin_write_tx()?))
+        let result = self.acquire_write_lock()?;
+        if !matches!(result, LimboResult::Ok) || !exclusive {
+            self.write_tx.set(matches!(result, LimboResult::Ok));
+            return Ok(CursorResult::Ok(result));
+        }
+        let mut wal = self.wal.borrow_mut();
+        let result = wal.begin_exclusive_tx()?;
+        if matches!(result, LimboResult::Busy) {
+            wal.end_write_tx()?;
+        }
+        self.write_tx.set(matches!(result, LimboResult::Ok));
+        Ok(CursorResult::Ok(result))
+    }
+
+    fn acquire_write_lock(&self) -> Result<LimboResult> {
+        let result = self.wal.borrow_mut().begin_write_tx()?;
+        if !matches!(result, LimboResult::Busy) || self.busy_timeout.get().is_zero() {
+            return Ok(result);
+        }
+        // Another connection holds the write lock: back off exponentially, with some jitter so
+        // that competing writers don't wake up in lockstep, until the busy timeout expires.
+        let deadline = Instant::now() + self.busy_timeout.get();
+        let mut delay = BUSY_INITIAL_DELAY;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(LimboResult::Busy);
+            }
+            let jitter = (self.io.generate_random_number() as u64) % (delay.as_micros() as u64 + 1);
+            let sleep = (delay + Duration::from_micros(jitter)).min(deadline - now);
+            std::thread::sleep(sleep);
+            let result = self.wal.borrow_mut().begin_write_tx()?;
+            if !matches!(result, LimboResult::Busy) {
+                return Ok(result);
+            }
+            delay = (delay * 2).min(BUSY_MAX_DELAY);
+        }
+    }
+
+    /// Sets how long [Pager::begin_write_tx] retries while the write lock is held by another
+    /// connection, see [crate::Connection::set_busy_timeout].
+    pub fn set_busy_timeout(&self, timeout: Duration) {
+        self.busy_timeout.set(timeout);
+    }
+
+    pub fn busy_timeout(&self) -> Duration {
+        self.busy_timeout.get()
     }
 
     pub fn end_tx(
@@ -641,9 +914,17 @@ impl Pager {
         tracing::trace!("end_tx(rollback={})", rollback);
         if rollback {
             self.wal.borrow().end_write_tx()?;
+            self.write_tx.set(false);
             self.wal.borrow().end_read_tx()?;
+            connection.call_rollback_hook();
             return Ok(PagerCacheflushStatus::Done(PagerCacheflushResult::Rollback));
         }
+        // Ask the commit hook once, before any frame of the transaction is written to the WAL.
+        if matches!(self.flush_info.borrow().state, FlushState::Start)
+            && !connection.call_commit_hook()
+        {
+            return Err(LimboError::CommitHookAborted);
+        }
         let cacheflush_status = self.cacheflush(wal_checkpoint_disabled)?;
         match cacheflush_status {
             PagerCacheflushStatus::IO => Ok(PagerCacheflushStatus::IO),
@@ -657,10 +938,22 @@ impl Pager {
                     None
                 };
                 self.wal.borrow().end_write_tx()?;
+                self.write_tx.set(false);
                 self.wal.borrow().end_read_tx()?;
                 if let Some((schema, mut db_schema)) = maybe_schema_pair {
                     *db_schema = schema;
                 }
+                if matches!(
+                    cacheflush_status,
+                    PagerCacheflushStatus::Done(PagerCacheflushResult::Checkpointed(_))
+                ) && self.journal_mode() == JournalMode::Delete
+                {
+                    // The commit was checkpointed and the database file synced, so the WAL
+                    // isn't needed anymore. It can only be truncated once this connection
+                    // let go of its locks. Readers of older snapshots may still keep it from
+                    // being truncated, in which case the next commit tries again.
+                    self.wal_checkpoint(wal_checkpoint_disabled, CheckpointMode::Truncate)?;
+                }
                 Ok(cacheflush_status)
             }
         }
@@ -675,46 +968,64 @@ impl Pager {
     #[tracing::instrument(skip_all, level = Level::DEBUG)]
     pub fn read_page(&self, page_idx: usize) -> Result<PageRef, LimboError> {
         tracing::trace!("read_page(page_idx = {})", page_idx);
-        let mut page_cache = self.page_cache.write();
-        let page_key = PageCacheKey::new(page_idx);
+        let checksum_verifier = self.checksum_verifier(page_idx);
+        let frame = self.wal.borrow().find_frame(page_idx as u64)?;
+        let (page_cache, page_key) = self.cache_for_read(page_idx, frame);
+        let mut page_cache = page_cache.write();
         if let Some(page) = page_cache.get(&page_key) {
+            if page.is_error() {
+                // The page failed checksum verification after it was read asynchronously.
+                let _ = page_cache.delete(page_key);
+                return Err(checksum::checksum_error(page_idx));
+            }
             tracing::trace!("read_page(page_idx = {}) = cached", page_idx);
             return Ok(page.clone());
         }
-        let page = Arc::new(Page::new(page_idx));
-        page.set_locked();
-
-        if let Some(frame_id) = self.wal.borrow().find_frame(page_idx as u64)? {
-            self.wal
-                .borrow()
-                .read_frame(frame_id, page.clone(), self.buffer_pool.clone())?;
-            {
-                page.set_uptodate();
-            }
-            // TODO(pere) should probably first insert to page cache, and if successful,
-            // read frame or page
-            match page_cache.insert(page_key, page.clone()) {
-                Ok(_) => {}
-                Err(CacheError::Full) => return Err(LimboError::CacheFull),
-                Err(CacheError::KeyExists) => {
-                    unreachable!("Page should not exist in cache after get() miss")
+        let page = match self.copy_shared_page(page_idx, frame) {
+            Some(page) => page,
+            None => {
+                // Evict first, so that the buffer of the evicted page can be reused for this one.
+                match page_cache.make_room_for(1) {
+                    Ok(_) => {}
+                    Err(CacheError::Full) => return Err(LimboError::CacheFull),
+                    Err(e) => {
+                        return Err(LimboError::InternalError(format!(
+                            "Failed to make room in page cache: {:?}",
+                            e
+                        )))
+                    }
                 }
-                Err(e) => {
-                    return Err(LimboError::InternalError(format!(
-                        "Failed to insert page into cache: {:?}",
-                        e
-                    )))
+                let buffer = self.read_buffer(&mut page_cache)?;
+                let page = Arc::new(Page::new(page_idx));
+                page.set_locked();
+                if let Some(frame_id) = frame {
+                    self.wal.borrow().read_frame(
+                        frame_id,
+                        page.clone(),
+                        buffer,
+                        checksum_verifier,
+                    )?;
+                    if page.is_error() {
+                        return Err(checksum::checksum_error(page_idx));
+                    }
+                    page.set_uptodate();
+                } else {
+                    sqlite3_ondisk::begin_read_page(
+                        self.db_file.clone(),
+                        buffer,
+                        page.clone(),
+                        page_idx,
+                        checksum_verifier,
+                    )?;
+                    if page.is_error() {
+                        return Err(checksum::checksum_error(page_idx));
+                    }
                 }
+                page
             }
-            return Ok(page);
-        }
-
-        sqlite3_ondisk::begin_read_page(
-            self.db_file.clone(),
-            self.buffer_pool.clone(),
-            page.clone(),
-            page_idx,
-        )?;
+        };
+        // TODO(pere) should probably first insert to page cache, and if successful,
+        // read frame or page
         match page_cache.insert(page_key, page.clone()) {
             Ok(_) => {}
             Err(CacheError::Full) => return Err(LimboError::CacheFull),
@@ -731,6 +1042,87 @@ impl Pager {
         Ok(page)
     }
 
+    /// Checks a buffer out of the buffer pool to read a page into.
+    ///
+    /// The pool holds as many buffers as the page cache holds pages. When all of them are
+    /// checked out, the read waits for one to come back: it evicts more pages from the cache,
+    /// and runs the IO in flight, whose completions drop the buffers they're done with. Only
+    /// when the buffers are all held by pages that can't be evicted, like the dirty pages of
+    /// a large write transaction, does the read take a buffer from outside the pool.
+    fn read_buffer(&self, page_cache: &mut PageCache) -> Result<crate::io::Buffer> {
+        let mut ran_io = false;
+        loop {
+            if let Some(buffer) = self.buffer_pool.try_get() {
+                return Ok(buffer);
+            }
+            let len = page_cache.len();
+            let evict = page_cache.capacity().saturating_sub(len) + 1;
+            if len > 0 && page_cache.make_room_for(evict).is_ok() && page_cache.len() < len {
+                continue;
+            }
+            if ran_io {
+                tracing::debug!("read_buffer: buffer pool exhausted by pages in use");
+                return Ok(self.buffer_pool.get());
+            }
+            self.io.run_once()?;
+            ran_io = true;
+        }
+    }
+
+    /// Returns the cache a page is read into and its key there. A write transaction modifies
+    /// its pages in place, so it keeps them in the private cache where other connections can't
+    /// see them. Otherwise pages go to the shared cache under the version the snapshot sees,
+    /// `frame` being the WAL frame holding it.
+    fn cache_for_read(
+        &self,
+        page_idx: usize,
+        frame: Option<u64>,
+    ) -> (&Arc<RwLock<PageCache>>, PageCacheKey) {
+        match &self.shared_page_cache {
+            Some(shared_page_cache) if !self.write_tx.get() => {
+                let checkpoint_seq = self.wal.borrow().checkpoint_seq();
+                (
+                    shared_page_cache,
+                    PageCacheKey::versioned(page_idx, frame, checkpoint_seq),
+                )
+            }
+            _ => (&self.page_cache, PageCacheKey::new(page_idx)),
+        }
+    }
+
+    /// During a write transaction, returns a private copy of the version of the page in `frame`
+    /// if another connection already has it in the shared cache.
+    fn copy_shared_page(&self, page_idx: usize, frame: Option<u64>) -> Option<PageRef> {
+        let shared_page_cache = self.shared_page_cache.as_ref()?;
+        if !self.write_tx.get() {
+            return None;
+        }
+        let checkpoint_seq = self.wal.borrow().checkpoint_seq();
+        let key = PageCacheKey::versioned(page_idx, frame, checkpoint_seq);
+        let shared_page = shared_page_cache.write().get(&key)?;
+        if !shared_page.is_loaded() || shared_page.is_locked() || shared_page.is_error() {
+            return None;
+        }
+        Some(copy_page(&shared_page))
+    }
+
+    /// Makes the pages this connection just appended to the WAL available to the others in
+    /// the shared cache, under the frames they were written to.
+    fn publish_page(&self, page: &PageRef) -> Result<()> {
+        let Some(shared_page_cache) = &self.shared_page_cache else {
+            return Ok(());
+        };
+        let page_idx = page.get().id;
+        let frame = self.wal.borrow().find_frame(page_idx as u64)?;
+        let checkpoint_seq = self.wal.borrow().checkpoint_seq();
+        let key = PageCacheKey::versioned(page_idx, frame, checkpoint_seq);
+        // The cache being full only means other connections will read the page themselves.
+        let _ = shared_page_cache
+            .write()
+            .insert_ignore_existing(key, copy_page(page));
+        Ok(())
+    }
+
     // Get a page from the cache, if it exists.
     pub fn cache_get(&self, page_idx: usize) -> Option<PageRef> {
         tracing::trace!("read_page(page_idx = {})", page_idx);
@@ -739,10 +1131,35 @@ impl Pager {
         page_cache.get(&page_key)
     }
 
-    /// Changes the size of the page cache.
+    /// Changes the size of the page cache, which is shared with the other connections to the
+    /// database if they share one, and of the buffer pool backing it.
     pub fn change_page_cache_size(&self, capacity: usize) -> Result<CacheResizeResult> {
-        let mut page_cache = self.page_cache.write();
-        Ok(page_cache.resize(capacity))
+        self.buffer_pool.set_max_buffers(capacity);
+        let mut result = self.page_cache.write().resize(capacity);
+        if let Some(shared_page_cache) = &self.shared_page_cache {
+            if let CacheResizeResult::PendingEvictions = shared_page_cache.write().resize(capacity)
+            {
+                result = CacheResizeResult::PendingEvictions;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns the eviction policy of the page cache.
+    pub fn page_cache_policy(&self) -> CachePolicy {
+        match &self.shared_page_cache {
+            Some(shared_page_cache) => shared_page_cache.read().policy(),
+            None => self.page_cache.read().policy(),
+        }
+    }
+
+    /// Changes the eviction policy of the page cache, which is shared with the other
+    /// connections to the database if they share one.
+    pub fn set_page_cache_policy(&self, policy: CachePolicy) {
+        self.page_cache.write().set_policy(policy);
+        if let Some(shared_page_cache) = &self.shared_page_cache {
+            shared_page_cache.write().set_policy(policy);
+        }
     }
 
     pub fn add_dirty(&self, page_id: usize) {
@@ -755,6 +1172,33 @@ impl Pager {
         Ok(self.wal.borrow().get_max_frame_in_wal())
     }
 
+    /// Returns after how many WAL frames a commit checkpoints, 0 if it never does.
+    pub fn wal_autocheckpoint(&self) -> u64 {
+        self.wal.borrow().checkpoint_threshold()
+    }
+
+    /// Sets after how many WAL frames a commit checkpoints, 0 to only checkpoint when asked
+    /// to. The threshold is shared by every connection to the database.
+    pub fn set_wal_autocheckpoint(&self, frames: u64) {
+        self.wal.borrow_mut().set_checkpoint_threshold(frames);
+    }
+
+    pub fn journal_mode(&self) -> JournalMode {
+        self.wal.borrow().journal_mode()
+    }
+
+    /// Sets the journal mode of every connection to the database, and returns the mode it is
+    /// in afterwards. An IO that can't hold a WAL between commits keeps it in DELETE mode.
+    pub fn set_journal_mode(&self, mode: JournalMode) -> JournalMode {
+        let mode = if self.io.supports_wal() {
+            mode
+        } else {
+            JournalMode::Delete
+        };
+        self.wal.borrow_mut().set_journal_mode(mode);
+        self.journal_mode()
+    }
+
     /// Flush dirty pages to disk.
     /// In the base case, it will write the dirty pages to the WAL and then fsync the WAL.
     /// If the WAL size is over the checkpoint threshold, it will checkpoint the WAL to
@@ -767,6 +1211,7 @@ impl Pager {
             match state {
                 FlushState::Start => {
                     let db_size = header_accessor::get_database_size(self)?;
+                    let has_page_checksums = self.has_page_checksums();
                     for (dirty_page_idx, page_id) in self.dirty_pages.borrow().iter().enumerate() {
                         let is_last_frame = dirty_page_idx == self.dirty_pages.borrow().len() - 1;
                         let mut cache = self.page_cache.write();
@@ -774,6 +1219,9 @@ impl Pager {
                         let page = cache.get(&page_key).expect("we somehow added a page to dirty list but we didn't mark it as dirty, causing cache to drop it.");
                         let page_type = page.get().contents.as_ref().unwrap().maybe_page_type();
                         trace!("cacheflush(page={}, page_type={:?}", page_id, page_type);
+                        if has_page_checksums {
+                            checksum::write_page_checksum(page.get_contents().as_ptr());
+                        }
                         let db_size = if is_last_frame { db_size } else { 0 };
                         self.wal.borrow_mut().append_frame(
                             page.clone(),
@@ -781,6 +1229,7 @@ impl Pager {
                             self.flush_info.borrow().in_flight_writes.clone(),
                         )?;
                         page.clear_dirty();
+                        self.publish_page(&page)?;
                     }
                     // This is okay assuming we use shared cache by default.
                     {
@@ -801,11 +1250,18 @@ impl Pager {
                     }
                 }
                 FlushState::SyncWal => {
-                    if WalFsyncStatus::IO == self.wal.borrow_mut().sync()? {
+                    let should_checkpoint =
+                        !wal_checkpoint_disabled && self.wal.borrow().should_checkpoint();
+                    let sync_wal = match self.synchronous.get() {
+                        SynchronousMode::Full => true,
+                        SynchronousMode::Normal => should_checkpoint,
+                        SynchronousMode::Off => false,
+                    };
+                    if sync_wal && WalFsyncStatus::IO == self.wal.borrow_mut().sync()? {
                         return Ok(PagerCacheflushStatus::IO);
                     }
 
-                    if wal_checkpoint_disabled || !self.wal.borrow().should_checkpoint() {
+                    if !should_checkpoint {
                         self.flush_info.borrow_mut().state = FlushState::Start;
                         return Ok(PagerCacheflushStatus::Done(
                             PagerCacheflushResult::WalWritten,
@@ -820,10 +1276,13 @@ impl Pager {
                             self.flush_info.borrow_mut().state = FlushState::SyncDbFile;
                         }
                         CheckpointStatus::IO => return Ok(PagerCacheflushStatus::IO),
+                        CheckpointStatus::Throttled(delay) => self.io.sleep(delay),
                     };
                 }
                 FlushState::SyncDbFile => {
-                    sqlite3_ondisk::begin_sync(self.db_file.clone(), self.syncing.clone())?;
+                    if self.synchronous.get() != SynchronousMode::Off {
+                        sqlite3_ondisk::begin_sync(self.db_file.clone(), self.syncing.clone())?;
+                    }
                     self.flush_info.borrow_mut().state = FlushState::WaitSyncDbFile;
                 }
                 FlushState::WaitSyncDbFile => {
@@ -870,6 +1329,7 @@ impl Pager {
                         CheckpointMode::Passive,
                     )? {
                         CheckpointStatus::IO => return Ok(CheckpointStatus::IO),
+                        CheckpointStatus::Throttled(delay) => self.io.sleep(delay),
                         CheckpointStatus::Done(res) => {
                             checkpoint_result = res;
                             self.checkpoint_state.replace(CheckpointState::SyncDbFile);
@@ -877,7 +1337,9 @@ impl Pager {
                     };
                 }
                 CheckpointState::SyncDbFile => {
-                    sqlite3_ondisk::begin_sync(self.db_file.clone(), self.syncing.clone())?;
+                    if self.synchronous.get() != SynchronousMode::Off {
+                        sqlite3_ondisk::begin_sync(self.db_file.clone(), self.syncing.clone())?;
+                    }
                     self.checkpoint_state
                         .replace(CheckpointState::WaitSyncDbFile);
                 }
@@ -928,32 +1390,33 @@ impl Pager {
                 attempts += 1;
             }
         }
-        self.wal_checkpoint(wal_checkpoint_disabled)?;
+        self.wal_checkpoint(wal_checkpoint_disabled, CheckpointMode::Passive)?;
         Ok(())
     }
 
-    pub fn wal_checkpoint(&self, wal_checkpoint_disabled: bool) -> Result<CheckpointResult> {
+    pub fn wal_checkpoint(
+        &self,
+        wal_checkpoint_disabled: bool,
+        mode: CheckpointMode,
+    ) -> Result<CheckpointResult> {
         if wal_checkpoint_disabled {
-            return Ok(CheckpointResult {
-                num_wal_frames: 0,
-                num_checkpointed_frames: 0,
-            });
+            return Ok(CheckpointResult::default());
         }
         let checkpoint_result: CheckpointResult;
         loop {
-            match self.wal.borrow_mut().checkpoint(
-                self,
-                Rc::new(RefCell::new(0)),
-                CheckpointMode::Passive,
-            ) {
-                Ok(CheckpointStatus::IO) => {
+            match self
+                .wal
+                .borrow_mut()
+                .checkpoint(self, Rc::new(RefCell::new(0)), mode)?
+            {
+                CheckpointStatus::IO => {
                     let _ = self.io.run_once();
                 }
-                Ok(CheckpointStatus::Done(res)) => {
+                CheckpointStatus::Throttled(delay) => self.io.sleep(delay),
+                CheckpointStatus::Done(res) => {
                     checkpoint_result = res;
                     break;
                 }
-                Err(err) => panic!("error while clearing cache {}", err),
             }
         }
         // TODO: only clear cache of things that are really invalidated
@@ -1044,10 +1507,21 @@ impl Pager {
                 self.is_empty.store(DB_STATE_INITIALIZING, Ordering::SeqCst);
                 let mut default_header = DatabaseHeader::default();
                 default_header.database_size += 1;
+                default_header.update_page_size(self.buffer_pool.get_page_size() as u32);
+                // Enabling checksum verification before the database exists opts it into page checksums.
+                let with_checksums = self.checksum_verification.get();
+                if with_checksums {
+                    default_header.reserved_space = CHECKSUM_SIZE as u8;
+                }
+                self.has_page_checksums.set(Some(with_checksums));
                 let page = allocate_page(1, &self.buffer_pool, 0);
 
                 let contents = page.get_contents();
                 contents.write_database_header(&default_header);
+                if with_checksums {
+                    contents.as_ptr()[CHECKSUM_MARKER_OFFSET..CHECKSUM_MARKER_OFFSET + 4]
+                        .copy_from_slice(&CHECKSUM_MARKER.to_be_bytes());
+                }
 
                 let page1 = Arc::new(BTreePageInner {
                     page: RefCell::new(page),
@@ -1062,6 +1536,9 @@ impl Pager {
                     DATABASE_HEADER_SIZE,
                     (default_header.get_page_size() - default_header.reserved_space as u32) as u16,
                 );
+                if with_checksums {
+                    checksum::write_page_checksum(page1.get().get_contents().as_ptr());
+                }
                 let write_counter = Rc::new(RefCell::new(0));
                 begin_write_btree_page(self, &page1.get(), write_counter.clone())?;
 
@@ -1082,8 +1559,8 @@ impl Pager {
                 }
                 tracing::trace!("allocate_page1(Writing done)");
                 let page1_ref = page.get();
-                let page_key = PageCacheKey::new(page1_ref.get().id);
-                let mut cache = self.page_cache.write();
+                let (cache, page_key) = self.cache_for_read(DATABASE_HEADER_PAGE_ID, None);
+                let mut cache = cache.write();
                 cache.insert(page_key, page1_ref.clone()).map_err(|e| {
                     LimboError::InternalError(format!(
                         "Failed to insert page 1 into cache: {:?}",
@@ -1105,10 +1582,9 @@ impl Pager {
         )
     }
 
-    /*
-        Gets a new page that increasing the size of the page or uses a free page.
-        Currently free list pages are not yet supported.
-    */
+    /// Gets a new page by growing the database file, see [Pager::allocate_page_near] for
+    /// reusing free pages. Growing the file past the page quota fails with
+    /// [LimboError::QuotaExceeded] before anything is modified.
     // FIXME: handle no room in page cache
     #[allow(clippy::readonly_write_lock)]
     pub fn allocate_page(&self) -> Result<PageRef> {
@@ -1118,35 +1594,44 @@ impl Pager {
 
         tracing::debug!("allocate_page(database_size={})", new_db_size);
 
+        //  If autovacuum is enabled and the next page is a pointer map page, it is allocated too
         #[cfg(not(feature = "omit_autovacuum"))]
-        {
-            //  If the following conditions are met, allocate a pointer map page, add to cache and increment the database size
-            //  - autovacuum is enabled
-            //  - the last page is a pointer map page
-            if matches!(*self.auto_vacuum_mode.borrow(), AutoVacuumMode::Full)
-                && is_ptrmap_page(new_db_size, header_accessor::get_page_size(self)? as usize)
-            {
-                let page = allocate_page(new_db_size as usize, &self.buffer_pool, 0);
-                page.set_dirty();
-                self.add_dirty(page.get().id);
-
-                let page_key = PageCacheKey::new(page.get().id);
-                let mut cache = self.page_cache.write();
-                match cache.insert(page_key, page.clone()) {
-                    Ok(_) => (),
-                    Err(CacheError::Full) => return Err(LimboError::CacheFull),
-                    Err(_) => {
-                        return Err(LimboError::InternalError(
-                            "Unknown error inserting page to cache".into(),
-                        ))
-                    }
+        let needs_ptrmap_page = matches!(*self.auto_vacuum_mode.borrow(), AutoVacuumMode::Full)
+            && is_ptrmap_page(new_db_size, header_accessor::get_page_size(self)? as usize);
+        #[cfg(feature = "omit_autovacuum")]
+        let needs_ptrmap_page = false;
+
+        if let Some(quota) = self.page_quota() {
+            if new_db_size + needs_ptrmap_page as u32 > quota {
+                return Err(LimboError::QuotaExceeded(quota));
+            }
+        }
+
+        #[cfg(not(feature = "omit_autovacuum"))]
+        if needs_ptrmap_page {
+            let page = allocate_page(new_db_size as usize, &self.buffer_pool, 0);
+            page.set_dirty();
+            self.add_dirty(page.get().id);
+
+            let page_key = PageCacheKey::new(page.get().id);
+            let mut cache = self.page_cache.write();
+            match cache.insert(page_key, page.clone()) {
+                Ok(_) => (),
+                Err(CacheError::Full) => return Err(LimboError::CacheFull),
+                Err(_) => {
+                    return Err(LimboError::InternalError(
+                        "Unknown error inserting page to cache".into(),
+                    ))
                 }
-                // we allocated a ptrmap page, so the next data page will be at new_db_size + 1
-                new_db_size += 1;
             }
+            // we allocated a ptrmap page, so the next data page will be at new_db_size + 1
+            new_db_size += 1;
         }
 
         header_accessor::set_database_size(self, new_db_size)?;
+        self.update_allocation_stats(|stats| stats.file_extensions += 1);
+        self.page_high_water
+            .set(self.page_high_water.get().max(new_db_size));
 
         // FIXME: should reserve page cache entry before modifying the database
         let page = allocate_page(new_db_size as usize, &self.buffer_pool, 0);
@@ -1167,6 +1652,152 @@ impl Pager {
         }
     }
 
+    /// Allocates a page, reusing a free page if there is one. `near` is the page number the
+    /// caller would like the new page to be close to, e.g. a sibling in the btree being grown.
+    fn allocate_page_near(&self, near: Option<u32>) -> Result<PageRef> {
+        // Free pages can't be handed out with autovacuum on until their pointer map entries
+        // are maintained, so the file is always grown instead.
+        #[cfg(not(feature = "omit_autovacuum"))]
+        let use_freelist = matches!(*self.auto_vacuum_mode.borrow(), AutoVacuumMode::None);
+        #[cfg(feature = "omit_autovacuum")]
+        let use_freelist = true;
+        if use_freelist {
+            if let Some(page) = self.allocate_from_freelist(near)? {
+                return Ok(page);
+            }
+        }
+        self.allocate_page()
+    }
+
+    /// Takes a page off the first freelist trunk, picking the leaf closest to `near` if given.
+    /// Once a trunk has no leaves left, the trunk page itself is handed out.
+    /// Returns `None` if the freelist is empty or its pages are still being read.
+    fn allocate_from_freelist(&self, near: Option<u32>) -> Result<Option<PageRef>> {
+        const TRUNK_PAGE_HEADER_SIZE: usize = 8;
+        const LEAF_ENTRY_SIZE: usize = 4;
+
+        const TRUNK_PAGE_NEXT_PAGE_OFFSET: usize = 0; // Offset to next trunk page pointer
+        const TRUNK_PAGE_LEAF_COUNT_OFFSET: usize = 4; // Offset to leaf count
+
+        let leaf_offset = |idx: usize| TRUNK_PAGE_HEADER_SIZE + idx * LEAF_ENTRY_SIZE;
+
+        let trunk_page_id = header_accessor::get_freelist_trunk_page(self)?;
+        if trunk_page_id == 0 {
+            return Ok(None);
+        }
+        let trunk_page = self.read_page(trunk_page_id as usize)?;
+        if trunk_page.is_locked() {
+            return Ok(None);
+        }
+        let trunk_contents = trunk_page.get().contents.as_ref().unwrap();
+        let number_of_leaf_pages = trunk_contents.read_u32(TRUNK_PAGE_LEAF_COUNT_OFFSET) as usize;
+
+        let pick_near = near.filter(|_| self.freelist_locality.get());
+        let leaf_idx = match (number_of_leaf_pages, pick_near) {
+            (0, _) => None,
+            (_, Some(near)) => (0..number_of_leaf_pages)
+                .min_by_key(|idx| trunk_contents.read_u32(leaf_offset(*idx)).abs_diff(near)),
+            (_, None) => Some(number_of_leaf_pages - 1),
+        };
+        let page_id = match leaf_idx {
+            Some(idx) => trunk_contents.read_u32(leaf_offset(idx)),
+            None => trunk_page_id,
+        };
+        if page_id < 2 || page_id > header_accessor::get_database_size(self)? {
+            return Err(LimboError::Corrupt(format!(
+                "Invalid page number {} in freelist",
+                page_id
+            )));
+        }
+
+        let page_key = PageCacheKey::new(page_id as usize);
+        let cached_page = self.page_cache.write().get(&page_key);
+        if cached_page.as_ref().is_some_and(|page| page.is_locked()) {
+            return Ok(None);
+        }
+
+        match leaf_idx {
+            Some(idx) => {
+                // Keep the leaf array dense by moving the last leaf into the taken slot.
+                let last_leaf = trunk_contents.read_u32(leaf_offset(number_of_leaf_pages - 1));
+                trunk_contents.write_u32(leaf_offset(idx), last_leaf);
+                trunk_contents.write_u32(
+                    TRUNK_PAGE_LEAF_COUNT_OFFSET,
+                    (number_of_leaf_pages - 1) as u32,
+                );
+                trunk_page.set_dirty();
+                self.add_dirty(trunk_page_id as usize);
+            }
+            None => {
+                let next_trunk_page_id = trunk_contents.read_u32(TRUNK_PAGE_NEXT_PAGE_OFFSET);
+                header_accessor::set_freelist_trunk_page(self, next_trunk_page_id)?;
+            }
+        }
+        header_accessor::set_freelist_pages(self, header_accessor::get_freelist_pages(self)? - 1)?;
+
+        // The previous content of a free page is meaningless, so it is never read from disk.
+        let page = match cached_page {
+            Some(page) => {
+                if page.get().contents.is_none() {
+                    let fresh_page = allocate_page(page_id as usize, &self.buffer_pool, 0);
+                    page.get().contents = fresh_page.get().contents.take();
+                }
+                page.set_loaded();
+                page
+            }
+            None => {
+                let page = allocate_page(page_id as usize, &self.buffer_pool, 0);
+                match self.page_cache.write().insert(page_key, page.clone()) {
+                    Ok(_) => {}
+                    Err(CacheError::Full) => return Err(LimboError::CacheFull),
+                    Err(_) => {
+                        return Err(LimboError::InternalError(
+                            "Unknown error inserting page to cache".into(),
+                        ))
+                    }
+                }
+                page
+            }
+        };
+        page.set_dirty();
+        self.add_dirty(page_id as usize);
+
+        tracing::debug!(
+            "allocate_from_freelist(page_id={}, near={:?})",
+            page_id,
+            near
+        );
+        self.update_allocation_stats(|stats| {
+            stats.freelist_hits += 1;
+            if let Some(near) = near {
+                let distance = page_id.abs_diff(near);
+                let bucket = LOCALITY_HISTOGRAM_BOUNDS
+                    .iter()
+                    .position(|bound| distance <= *bound)
+                    .unwrap_or(LOCALITY_HISTOGRAM_BOUNDS.len());
+                stats.locality_histogram[bucket] += 1;
+            }
+        });
+        Ok(Some(page))
+    }
+
+    /// Returns counters describing how pages were allocated by this pager.
+    pub fn allocation_stats(&self) -> PageAllocationStats {
+        self.allocation_stats.get()
+    }
+
+    /// Sets whether free pages are picked close to the btree being grown, or handed out in
+    /// freelist order. Allocations record their distance in the stats either way.
+    pub fn set_freelist_locality(&self, enabled: bool) {
+        self.freelist_locality.set(enabled);
+    }
+
+    fn update_allocation_stats(&self, f: impl FnOnce(&mut PageAllocationStats)) {
+        let mut stats = self.allocation_stats.get();
+        f(&mut stats);
+        self.allocation_stats.set(stats);
+    }
+
     pub fn update_dirty_loaded_page_in_cache(
         &self,
         id: usize,
@@ -1196,6 +1827,7 @@ impl Pager {
     }
 
     pub fn rollback(&self, change_schema: bool, connection: &Connection) -> Result<(), LimboError> {
+        self.write_tx.set(false);
         self.dirty_pages.borrow_mut().clear();
         let mut cache = self.page_cache.write();
         cache.unset_dirty_all_pages();
@@ -1210,22 +1842,26 @@ impl Pager {
     }
 }
 
+/// Returns a loaded page holding a copy of the contents of `page`.
+fn copy_page(page: &PageRef) -> PageRef {
+    let copy = Arc::new(Page::new(page.get().id));
+    copy.get().contents = page.get().contents.clone();
+    copy.set_loaded();
+    copy.set_uptodate();
+    copy
+}
+
 pub fn allocate_page(page_id: usize, buffer_pool: &Arc<BufferPool>, offset: usize) -> PageRef {
     let page = Arc::new(Page::new(page_id));
     {
-        let buffer = buffer_pool.get();
-        let bp = buffer_pool.clone();
-        let drop_fn = Rc::new(move |buf| {
-            bp.put(buf);
-        });
-        let buffer = Arc::new(RefCell::new(Buffer::new(buffer, drop_fn)));
+        let buffer = Arc::new(RefCell::new(buffer_pool.get()));
         page.set_loaded();
         page.get().contents = Some(PageContent::new(offset, buffer));
     }
     page
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct CreateBTreeFlags(pub u8);
 impl CreateBTreeFlags {
     pub const TABLE: u8 = 0b0001;
@@ -1429,18 +2065,26 @@ mod ptrmap {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Mutex};
 
     use parking_lot::RwLock;
 
-    use crate::storage::page_cache::{DumbLruPageCache, PageCacheKey};
+    use crate::io::{MemoryIO, OpenFlags, IO};
+    use crate::storage::buffer_pool::BufferPool;
+    use crate::storage::database::{DatabaseFile, DatabaseStorage};
+    use crate::storage::page_cache::{PageCache, PageCacheKey};
+    use crate::storage::sqlite3_ondisk::PageContent;
+    use crate::storage::wal::{WalFile, WalFileShared};
 
-    use super::Page;
+    use super::{Page, PageRef, Pager};
 
     #[test]
     fn test_shared_cache() {
         // ensure cache can be shared between threads
-        let cache = Arc::new(RwLock::new(DumbLruPageCache::new(10)));
+        let cache = Arc::new(RwLock::new(PageCache::new(10)));
 
         let thread = {
             let cache = cache.clone();
@@ -1456,6 +2100,85 @@ mod tests {
         let page = cache.get(&page_key);
         assert_eq!(page.unwrap().get().id, 1);
     }
+
+    fn pager_with_pool(page_cache: Arc<RwLock<PageCache>>, buffer_pool: Arc<BufferPool>) -> Pager {
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let db_file: Arc<dyn DatabaseStorage> = Arc::new(DatabaseFile::new(
+            io.open_file("test.db", OpenFlags::Create, true).unwrap(),
+        ));
+        let wal = Rc::new(RefCell::new(WalFile::new(
+            io.clone(),
+            WalFileShared::new_shared(
+                512,
+                &io,
+                io.open_file("test.db-wal", OpenFlags::Create, false)
+                    .unwrap(),
+            )
+            .unwrap(),
+            buffer_pool.clone(),
+        )));
+        Pager::new(
+            db_file,
+            wal,
+            io,
+            page_cache,
+            buffer_pool,
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(Mutex::new(())),
+        )
+        .unwrap()
+    }
+
+    /// Fills the cache with loaded pages that hold all of the pool's buffers.
+    fn fill_cache(cache: &mut PageCache, buffer_pool: &Arc<BufferPool>) -> Vec<PageRef> {
+        let mut pages = Vec::new();
+        while let Some(buffer) = buffer_pool.try_get() {
+            let page = Arc::new(Page::new(pages.len() + 2));
+            page.get().contents = Some(PageContent::new(0, Arc::new(RefCell::new(buffer))));
+            page.set_loaded();
+            cache
+                .insert(PageCacheKey::new(page.get().id), page.clone())
+                .unwrap();
+            pages.push(page);
+        }
+        pages
+    }
+
+    #[test]
+    fn test_read_buffer_waits_for_evicted_page() {
+        let buffer_pool = Arc::new(BufferPool::new(Some(512)));
+        buffer_pool.set_max_buffers(2);
+        let page_cache = Arc::new(RwLock::new(PageCache::new(2)));
+        let pager = pager_with_pool(page_cache.clone(), buffer_pool.clone());
+        let mut cache = page_cache.write();
+        drop(fill_cache(&mut cache, &buffer_pool));
+        assert!(buffer_pool.is_exhausted());
+
+        // The buffer of the evicted page is the one handed out.
+        let buffer = pager.read_buffer(&mut cache).unwrap();
+        assert!(buffer.is_pooled());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(buffer_pool.allocated_buffers(), 2);
+    }
+
+    #[test]
+    fn test_read_buffer_with_dirty_pages() {
+        let buffer_pool = Arc::new(BufferPool::new(Some(512)));
+        buffer_pool.set_max_buffers(2);
+        let page_cache = Arc::new(RwLock::new(PageCache::new(2)));
+        let pager = pager_with_pool(page_cache.clone(), buffer_pool.clone());
+        let mut cache = page_cache.write();
+        for page in fill_cache(&mut cache, &buffer_pool) {
+            page.set_dirty();
+        }
+
+        // Dirty pages can't be evicted, so no buffer comes back and the read has to go past
+        // the pool.
+        let buffer = pager.read_buffer(&mut cache).unwrap();
+        assert!(!buffer.is_pooled());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(buffer_pool.allocated_buffers(), 2);
+    }
 }
 
 #[cfg(test)]
@@ -1470,7 +2193,7 @@ mod ptrmap_tests {
     use crate::io::{MemoryIO, OpenFlags, IO};
     use crate::storage::buffer_pool::BufferPool;
     use crate::storage::database::{DatabaseFile, DatabaseStorage};
-    use crate::storage::page_cache::DumbLruPageCache;
+    use crate::storage::page_cache::PageCache;
     use crate::storage::pager::Pager;
     use crate::storage::sqlite3_ondisk::MIN_PAGE_SIZE;
     use crate::storage::wal::{WalFile, WalFileShared};
@@ -1497,7 +2220,7 @@ mod ptrmap_tests {
 
         //  Construct interfaces for the pager
         let buffer_pool = Arc::new(BufferPool::new(Some(page_size as usize)));
-        let page_cache = Arc::new(RwLock::new(DumbLruPageCache::new(
+        let page_cache = Arc::new(RwLock::new(PageCache::new(
             (initial_db_pages + 10) as usize,
         )));
 
@@ -50,7 +50,7 @@ use crate::fast_lock::SpinLock;
 use crate::io::{
     Buffer, Complete, Completion, CompletionType, ReadCompletion, SyncCompletion, WriteCompletion,
 };
-use crate::storage::buffer_pool::BufferPool;
+use crate::storage::checksum::PageChecksumVerifier;
 use crate::storage::database::DatabaseStorage;
 use crate::storage::pager::Pager;
 use crate::types::{
@@ -66,7 +66,7 @@ use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
 use super::pager::PageRef;
-use super::wal::LimboRwLock;
+use super::wal::{JournalMode, LimboRwLock, RecoveryReport, DEFAULT_CHECKPOINT_THRESHOLD};
 
 /// The size of the database header in bytes.
 pub const DATABASE_HEADER_SIZE: usize = 100;
@@ -81,7 +81,7 @@ pub const MIN_PAGE_CACHE_SIZE: usize = 10;
 pub const MIN_PAGE_SIZE: u32 = 512;
 
 /// The maximum page size in bytes.
-const MAX_PAGE_SIZE: u32 = 65536;
+pub const MAX_PAGE_SIZE: u32 = 65536;
 
 /// The default page size in bytes.
 pub const DEFAULT_PAGE_SIZE: u16 = 4096;
@@ -623,6 +623,30 @@ impl PageContent {
         (self.offset + header_size, self.cell_pointer_array_size())
     }
 
+    /// Returns the offset within the page at which the payload, i.e. the record, of the cell
+    /// at `idx` begins. Table interior cells have no payload, so None is returned for them.
+    pub fn cell_payload_offset(&self, idx: usize) -> Result<Option<usize>> {
+        let buf = self.as_ptr();
+        let (cell_pointer_array_start, _) = self.cell_pointer_array_offset_and_size();
+        let cell_pointer = self.read_u16_no_offset(cell_pointer_array_start + (idx * 2)) as usize;
+        let page_type = self.page_type();
+        let mut pos = cell_pointer;
+        match page_type {
+            PageType::TableInterior => return Ok(None),
+            // Skip the left child pointer.
+            PageType::IndexInterior => pos += 4,
+            PageType::IndexLeaf | PageType::TableLeaf => {}
+        }
+        let (_, nr) = read_varint(&buf[pos..])?;
+        pos += nr;
+        if page_type == PageType::TableLeaf {
+            // Skip the rowid.
+            let (_, nr) = read_varint(&buf[pos..])?;
+            pos += nr;
+        }
+        Ok(Some(pos))
+    }
+
     /// Get region of a cell's payload
     pub fn cell_get_raw_region(
         &self,
@@ -729,20 +753,22 @@ impl PageContent {
 
 pub fn begin_read_page(
     db_file: Arc<dyn DatabaseStorage>,
-    buffer_pool: Arc<BufferPool>,
+    buffer: Buffer,
     page: PageRef,
     page_idx: usize,
+    checksum_verifier: Option<PageChecksumVerifier>,
 ) -> Result<()> {
     tracing::trace!("begin_read_btree_page(page_idx = {})", page_idx);
-    let buf = buffer_pool.get();
-    let drop_fn = Rc::new(move |buf| {
-        let buffer_pool = buffer_pool.clone();
-        buffer_pool.put(buf);
-    });
     #[allow(clippy::arc_with_non_send_sync)]
-    let buf = Arc::new(RefCell::new(Buffer::new(buf, drop_fn)));
+    let buf = Arc::new(RefCell::new(buffer));
     let complete = Box::new(move |buf: Arc<RefCell<Buffer>>| {
         let page = page.clone();
+        if let Some(verifier) = &checksum_verifier {
+            if verifier.verify(page_idx, buf.borrow().as_slice()).is_err() {
+                fail_read_page(&page);
+                return;
+            }
+        }
         if finish_read_page(page_idx, buf, page.clone()).is_err() {
             page.set_error();
         }
@@ -773,6 +799,13 @@ pub fn finish_read_page(
     Ok(())
 }
 
+/// Marks a page whose contents could not be trusted as errored instead of loaded, so that
+/// the next [Pager::read_page] of it reports the failure.
+pub fn fail_read_page(page: &PageRef) {
+    page.set_error();
+    page.clear_locked();
+}
+
 pub fn begin_write_btree_page(
     pager: &Pager,
     page: &PageRef,
@@ -1288,10 +1321,9 @@ pub fn write_varint_to_vec(value: u64, payload: &mut Vec<u8>) {
 
 /// We need to read the WAL file on open to reconstruct the WAL frame cache.
 pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFileShared>>> {
-    let drop_fn = Rc::new(|_buf| {});
     let size = file.size()?;
     #[allow(clippy::arc_with_non_send_sync)]
-    let buf_for_pread = Arc::new(RefCell::new(Buffer::allocate(size as usize, drop_fn)));
+    let buf_for_pread = Arc::new(RefCell::new(Buffer::allocate(size as usize)));
     let header = Arc::new(SpinLock::new(WalHeader::default()));
     #[allow(clippy::arc_with_non_send_sync)]
     let wal_file_shared_ret = Arc::new(UnsafeCell::new(WalFileShared {
@@ -1302,6 +1334,7 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
         frame_cache: Arc::new(SpinLock::new(HashMap::new())),
         pages_in_frames: Arc::new(SpinLock::new(Vec::new())),
         last_checksum: (0, 0),
+        recovery_report: RecoveryReport::default(),
         file: file.clone(),
         read_locks: [
             LimboRwLock::new(),
@@ -1311,7 +1344,10 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
             LimboRwLock::new(),
         ],
         write_lock: LimboRwLock::new(),
+        exclusive: AtomicBool::new(false),
         loaded: AtomicBool::new(false),
+        checkpoint_threshold: AtomicU64::new(DEFAULT_CHECKPOINT_THRESHOLD),
+        journal_mode: SpinLock::new(JournalMode::Wal),
     }));
     let wal_file_shared_for_completion = wal_file_shared_ret.clone();
 
@@ -1376,6 +1412,7 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
         let mut frame_idx = 1_u64;
 
         let wfs_data = unsafe { &mut *wal_file_shared_for_completion.get() };
+        let mut report = RecoveryReport::default();
 
         while current_offset + WAL_FRAME_HEADER_SIZE + page_size <= buf_slice.len() {
             let frame_header_slice =
@@ -1404,6 +1441,16 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
                 );
                 break;
             }
+            report.frames_scanned += 1;
+
+            // The checksum is cumulative, so once a frame fails it none of the following ones
+            // can be trusted either.
+            if report.frames_discarded > 0 {
+                report.frames_discarded += 1;
+                frame_idx += 1;
+                current_offset += WAL_FRAME_HEADER_SIZE + page_size;
+                continue;
+            }
 
             let checksum_after_fh_meta = checksum_wal(
                 &frame_header_slice[0..8],
@@ -1419,13 +1466,18 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
             );
 
             if calculated_frame_checksum != (frame_h_checksum_1, frame_h_checksum_2) {
-                panic!(
-                    "WAL frame checksum mismatch. Expected ({}, {}), Got ({}, {})",
+                tracing::warn!(
+                    "WAL frame {} checksum mismatch. Expected ({}, {}), Got ({}, {}), discarding the rest of the WAL",
+                    frame_idx,
                     frame_h_checksum_1,
                     frame_h_checksum_2,
                     calculated_frame_checksum.0,
                     calculated_frame_checksum.1
                 );
+                report.frames_discarded += 1;
+                frame_idx += 1;
+                current_offset += WAL_FRAME_HEADER_SIZE + page_size;
+                continue;
             }
 
             cumulative_checksum = calculated_frame_checksum;
@@ -1451,6 +1503,13 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
             current_offset += WAL_FRAME_HEADER_SIZE + page_size;
         }
 
+        report.frames_applied = wfs_data.max_frame.load(Ordering::SeqCst);
+        let recovered_end = WAL_HEADER_SIZE as u64
+            + report.frames_applied * (WAL_FRAME_HEADER_SIZE + page_size) as u64;
+        if (buf_slice.len() as u64) > recovered_end {
+            report.truncated_tail_offset = Some(recovered_end);
+        }
+        wfs_data.recovery_report = report;
         wfs_data.loaded.store(true, Ordering::SeqCst);
     });
     let c = Completion::new(CompletionType::Read(ReadCompletion::new(
@@ -1465,16 +1524,11 @@ pub fn read_entire_wal_dumb(file: &Arc<dyn File>) -> Result<Arc<UnsafeCell<WalFi
 pub fn begin_read_wal_frame(
     io: &Arc<dyn File>,
     offset: usize,
-    buffer_pool: Arc<BufferPool>,
+    buffer: Buffer,
     complete: Box<dyn Fn(Arc<RefCell<Buffer>>)>,
 ) -> Result<Arc<Completion>> {
     tracing::trace!("begin_read_wal_frame(offset={})", offset);
-    let buf = buffer_pool.get();
-    let drop_fn = Rc::new(move |buf| {
-        let buffer_pool = buffer_pool.clone();
-        buffer_pool.put(buf);
-    });
-    let buf = Arc::new(RefCell::new(Buffer::new(buf, drop_fn)));
+    let buf = Arc::new(RefCell::new(buffer));
     #[allow(clippy::arc_with_non_send_sync)]
     let c = Completion::new(CompletionType::Read(ReadCompletion::new(buf, complete)));
     let c = io.pread(offset, c)?;
@@ -1508,12 +1562,7 @@ pub fn begin_write_wal_frame(
     let (buffer, checksums) = {
         let page = page.get();
         let contents = page.contents.as_ref().unwrap();
-        let drop_fn = Rc::new(|_buf| {});
-
-        let mut buffer = Buffer::allocate(
-            contents.buffer.borrow().len() + WAL_FRAME_HEADER_SIZE,
-            drop_fn,
-        );
+        let mut buffer = Buffer::allocate(contents.buffer.borrow().len() + WAL_FRAME_HEADER_SIZE);
         let buf = buffer.as_mut_slice();
         buf[0..4].copy_from_slice(&header.page_number.to_be_bytes());
         buf[4..8].copy_from_slice(&header.db_size.to_be_bytes());
@@ -1571,9 +1620,7 @@ pub fn begin_write_wal_frame(
 
 pub fn begin_write_wal_header(io: &Arc<dyn File>, header: &WalHeader) -> Result<()> {
     let buffer = {
-        let drop_fn = Rc::new(|_buf| {});
-
-        let mut buffer = Buffer::allocate(512, drop_fn);
+        let mut buffer = Buffer::allocate(512);
         let buf = buffer.as_mut_slice();
 
         buf[0..4].copy_from_slice(&header.magic.to_be_bytes());
@@ -14,26 +14,38 @@ use std::{
     fmt,
     rc::Rc,
     sync::Arc,
+    time::Duration,
 };
 
 use crate::fast_lock::SpinLock;
-use crate::io::{CompletionType, File, SyncCompletion, IO};
+use crate::io::clock::Instant;
+use crate::io::{CompletionType, File, IoPriority, SyncCompletion, IO};
 use crate::result::LimboResult;
 use crate::storage::sqlite3_ondisk::{
-    begin_read_wal_frame, begin_write_wal_frame, finish_read_page, WAL_FRAME_HEADER_SIZE,
-    WAL_HEADER_SIZE,
+    begin_read_wal_frame, begin_write_wal_frame, fail_read_page, finish_read_page,
+    WAL_FRAME_HEADER_SIZE, WAL_HEADER_SIZE,
 };
-use crate::{Buffer, Result};
+use crate::{Buffer, LimboError, Result};
 use crate::{Completion, Page};
 
-use self::sqlite3_ondisk::{checksum_wal, PageContent, WAL_MAGIC_BE, WAL_MAGIC_LE};
+use self::sqlite3_ondisk::{checksum_wal, WAL_MAGIC_BE, WAL_MAGIC_LE};
 
 use super::buffer_pool::BufferPool;
+use super::checksum::PageChecksumVerifier;
 use super::pager::{PageRef, Pager};
 use super::sqlite3_ondisk::{self, begin_write_btree_page, WalHeader};
 
 pub const READMARK_NOT_USED: u32 = 0xffffffff;
 
+/// Number of pages a checkpoint reads from the WAL and writes to the database file at once. All
+/// the writes of a batch are queued before waiting for any of them, so that backends like
+/// io_uring submit them together. Kept well under the io_uring queue depth.
+const CHECKPOINT_BATCH_SIZE: usize = 64;
+
+/// Number of frames in the WAL after which a commit checkpoints it, unless changed with
+/// `PRAGMA wal_autocheckpoint`. Same as SQLite.
+pub const DEFAULT_CHECKPOINT_THRESHOLD: u64 = 1000;
+
 pub const NO_LOCK: u32 = 0;
 pub const SHARED_LOCK: u32 = 1;
 pub const WRITE_LOCK: u32 = 2;
@@ -44,6 +56,9 @@ pub struct CheckpointResult {
     pub num_wal_frames: u64,
     /// number of frames moved successfully from WAL to db file after checkpoint
     pub num_checkpointed_frames: u64,
+    /// Whether other connections kept the checkpoint from doing everything its mode asks
+    /// for, which SQLite reports as SQLITE_BUSY.
+    pub busy: bool,
 }
 
 impl Default for CheckpointResult {
@@ -57,6 +72,7 @@ impl CheckpointResult {
         Self {
             num_wal_frames: 0,
             num_checkpointed_frames: 0,
+            busy: false,
         }
     }
 }
@@ -74,6 +90,37 @@ pub enum CheckpointMode {
     Truncate,
 }
 
+/// What a commit leaves behind in the WAL. Set with `PRAGMA journal_mode`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum JournalMode {
+    /// Committed frames stay in the WAL until it grows past the `wal_autocheckpoint` threshold.
+    Wal,
+    /// Every commit is checkpointed into the database file right away, and the WAL is truncated
+    /// once the database file is synced. Between transactions the database is then a single
+    /// file, like with a rollback journal in SQLite's DELETE mode. Databases opened with an IO
+    /// that can't hold a WAL between commits, see [IO::supports_wal], always use this mode.
+    Delete,
+}
+
+impl JournalMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Wal => "wal",
+            JournalMode::Delete => "delete",
+        }
+    }
+
+    /// The journal mode databases opened with `io` start in.
+    pub fn for_io(io: &Arc<dyn IO>) -> Self {
+        if io.supports_wal() {
+            JournalMode::Wal
+        } else {
+            JournalMode::Delete
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct LimboRwLock {
     lock: AtomicU32,
@@ -201,6 +248,11 @@ pub trait Wal {
     /// Begin a write transaction.
     fn begin_write_tx(&mut self) -> Result<LimboResult>;
 
+    /// Keep every other connection out of the WAL until the write transaction ends, as
+    /// `BEGIN EXCLUSIVE` does. Must be called while holding the write lock. Returns
+    /// [LimboResult::Busy] right away if another connection is reading.
+    fn begin_exclusive_tx(&mut self) -> Result<LimboResult>;
+
     /// End a read transaction.
     fn end_read_tx(&self) -> Result<LimboResult>;
 
@@ -210,8 +262,15 @@ pub trait Wal {
     /// Find the latest frame containing a page.
     fn find_frame(&self, page_id: u64) -> Result<Option<u64>>;
 
-    /// Read a frame from the WAL.
-    fn read_frame(&self, frame_id: u64, page: PageRef, buffer_pool: Arc<BufferPool>) -> Result<()>;
+    /// Read a frame from the WAL into `buffer`.
+    /// If a checksum verifier is given, the frame's page checksum is verified once it's read.
+    fn read_frame(
+        &self,
+        frame_id: u64,
+        page: PageRef,
+        buffer: Buffer,
+        checksum_verifier: Option<PageChecksumVerifier>,
+    ) -> Result<()>;
 
     /// Read a frame from the WAL.
     fn read_frame_raw(
@@ -239,6 +298,14 @@ pub trait Wal {
     fn finish_append_frames_commit(&mut self) -> Result<()>;
 
     fn should_checkpoint(&self) -> bool;
+    /// Number of frames in the WAL after which [Wal::should_checkpoint] asks for a
+    /// checkpoint, 0 if automatic checkpoints are disabled.
+    fn checkpoint_threshold(&self) -> u64;
+    fn set_checkpoint_threshold(&mut self, frames: u64);
+    fn journal_mode(&self) -> JournalMode;
+    fn set_journal_mode(&mut self, mode: JournalMode);
+    /// Number of times the WAL was restarted, which reuses its frame numbers.
+    fn checkpoint_seq(&self) -> u32;
     fn checkpoint(
         &mut self,
         pager: &Pager,
@@ -250,6 +317,13 @@ pub trait Wal {
     fn get_max_frame(&self) -> u64;
     fn get_min_frame(&self) -> u64;
     fn rollback(&mut self) -> Result<()>;
+
+    /// Limits how fast checkpoints copy pages into the database file, in bytes per second.
+    /// A limit of 0 disables throttling.
+    fn set_checkpoint_rate_limit(&mut self, bytes_per_sec: u64);
+    fn get_checkpoint_rate_limit(&self) -> u64;
+    /// Returns true while the ongoing checkpoint is waiting for the rate limit to allow more IO.
+    fn is_checkpoint_throttled(&self) -> bool;
 }
 
 /// A dummy WAL implementation that does nothing.
@@ -271,6 +345,10 @@ impl Wal for DummyWAL {
         Ok(LimboResult::Ok)
     }
 
+    fn begin_exclusive_tx(&mut self) -> Result<LimboResult> {
+        Ok(LimboResult::Ok)
+    }
+
     fn end_write_tx(&self) -> Result<LimboResult> {
         Ok(LimboResult::Ok)
     }
@@ -283,7 +361,8 @@ impl Wal for DummyWAL {
         &self,
         _frame_id: u64,
         _page: crate::PageRef,
-        _buffer_pool: Arc<BufferPool>,
+        _buffer: Buffer,
+        _checksum_verifier: Option<PageChecksumVerifier>,
     ) -> Result<()> {
         Ok(())
     }
@@ -311,6 +390,22 @@ impl Wal for DummyWAL {
         false
     }
 
+    fn checkpoint_threshold(&self) -> u64 {
+        0
+    }
+
+    fn set_checkpoint_threshold(&mut self, _frames: u64) {}
+
+    fn journal_mode(&self) -> JournalMode {
+        JournalMode::Wal
+    }
+
+    fn set_journal_mode(&mut self, _mode: JournalMode) {}
+
+    fn checkpoint_seq(&self) -> u32 {
+        0
+    }
+
     fn checkpoint(
         &mut self,
         _pager: &Pager,
@@ -346,6 +441,16 @@ impl Wal for DummyWAL {
     fn rollback(&mut self) -> Result<()> {
         Ok(())
     }
+
+    fn set_checkpoint_rate_limit(&mut self, _bytes_per_sec: u64) {}
+
+    fn get_checkpoint_rate_limit(&self) -> u64 {
+        0
+    }
+
+    fn is_checkpoint_throttled(&self) -> bool {
+        false
+    }
 }
 
 // Syncing requires a state machine because we need to schedule a sync and then wait until it is
@@ -376,22 +481,36 @@ pub enum WalFsyncStatus {
 pub enum CheckpointStatus {
     Done(CheckpointResult),
     IO,
+    /// The checkpoint copied as much as its rate limit allows for now. Nothing is in flight, so
+    /// the caller should wait for the given duration before resuming it instead of running IO.
+    Throttled(Duration),
 }
 
 // Checkpointing is a state machine that has multiple steps. Since there are multiple steps we save
-// in flight information of the checkpoint in OngoingCheckpoint. page is just a helper Page to do
-// page operations like reading a frame to a page, and writing a page to disk. This page should not
-// be placed back in pager page cache or anything, it's just a helper.
+// in flight information of the checkpoint in OngoingCheckpoint. pages are just helper Pages to do
+// page operations like reading a frame to a page, and writing a page to disk, for up to
+// CHECKPOINT_BATCH_SIZE pages at a time. The first batch_len of them make up the batch in flight.
+// These pages should not be placed back in pager page cache or anything, they're just helpers.
 // min_frame and max_frame is the range of frames that can be safely transferred from WAL to db
 // file.
 // current_page is a helper to iterate through all the pages that might have a frame in the safe
 // range. This is inefficient for now.
+// started_at and bytes_written drive the rate limit: before reading the next frame we compare the
+// bytes copied so far with what the limit allows for the time elapsed since the checkpoint started,
+// and if we are ahead we return CheckpointStatus::Throttled with the time left until we are not.
 struct OngoingCheckpoint {
-    page: PageRef,
+    pages: Vec<PageRef>,
+    batch_len: usize,
     state: CheckpointState,
     min_frame: u64,
     max_frame: u64,
     current_page: u64,
+    started_at: Option<Instant>,
+    bytes_written: u64,
+    throttled: bool,
+    /// Whether the checkpoint took the write lock to keep writers out, which every mode but
+    /// [CheckpointMode::Passive] does.
+    holds_write_lock: bool,
 }
 
 impl fmt::Debug for OngoingCheckpoint {
@@ -401,6 +520,9 @@ impl fmt::Debug for OngoingCheckpoint {
             .field("min_frame", &self.min_frame)
             .field("max_frame", &self.max_frame)
             .field("current_page", &self.current_page)
+            .field("bytes_written", &self.bytes_written)
+            .field("throttled", &self.throttled)
+            .field("batch_len", &self.batch_len)
             .finish()
     }
 }
@@ -415,7 +537,8 @@ pub struct WalFile {
 
     shared: Arc<UnsafeCell<WalFileShared>>,
     ongoing_checkpoint: OngoingCheckpoint,
-    checkpoint_threshold: usize,
+    /// Maximum checkpoint copy rate in bytes per second, 0 means unlimited.
+    checkpoint_rate_limit: u64,
     // min and max frames for this connection
     /// This is the index to the read_lock in WalFileShared that we are holding. This lock contains
     /// the max frame for this connection.
@@ -430,6 +553,9 @@ pub struct WalFile {
     /// Hack for now in case of rollback, will not be needed once we remove this bullshit frame cache.
     start_pages_in_frames: usize,
 
+    /// Whether this connection set [WalFileShared::exclusive] in [Wal::begin_exclusive_tx].
+    holds_exclusive_lock: Cell<bool>,
+
     /// Private copy of WalHeader
     pub header: WalHeader,
 }
@@ -442,7 +568,7 @@ impl fmt::Debug for WalFile {
             .field("page_size", &self.page_size())
             .field("shared", &self.shared)
             .field("ongoing_checkpoint", &self.ongoing_checkpoint)
-            .field("checkpoint_threshold", &self.checkpoint_threshold)
+            .field("checkpoint_rate_limit", &self.checkpoint_rate_limit)
             .field("max_frame_read_lock_index", &self.max_frame_read_lock_index)
             .field("max_frame", &self.max_frame)
             .field("min_frame", &self.min_frame)
@@ -451,6 +577,30 @@ impl fmt::Debug for WalFile {
     }
 }
 
+/// What was found in the WAL when the database was opened, see [crate::Database::recovery_report].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Frames read from the WAL, up to the first one left over from before the last reset.
+    pub frames_scanned: u64,
+    /// Frames up to and including the last valid commit frame, which are visible again.
+    pub frames_applied: u64,
+    /// Frames that failed checksum verification. Checksums are cumulative, so every frame after
+    /// the first bad one is discarded as well.
+    pub frames_discarded: u64,
+    /// WAL offset at which the recovered frames end, if anything came after them: frames of a
+    /// transaction that never committed, frames that failed their checksum or a torn frame.
+    pub truncated_tail_offset: Option<u64>,
+}
+
+impl RecoveryReport {
+    /// Whether the WAL still held frames when the database was opened, i.e. the last connection
+    /// did not shut down cleanly. A passive checkpoint does not reset the WAL yet, so frames that
+    /// were already checkpointed count too.
+    pub fn dirty_shutdown(&self) -> bool {
+        self.frames_scanned > 0 || self.truncated_tail_offset.is_some()
+    }
+}
+
 // TODO(pere): lock only important parts + pin WalFileShared
 /// WalFileShared is the part of a WAL that will be shared between threads. A wal has information
 /// that needs to be communicated between threads so this struct does the job.
@@ -470,6 +620,8 @@ pub struct WalFileShared {
     // Another memory inefficient array made to just keep track of pages that are in frame_cache.
     pub pages_in_frames: Arc<SpinLock<Vec<u64>>>,
     pub last_checksum: (u32, u32), // Check of last frame in WAL, this is a cumulative checksum over all frames in the WAL
+    /// What recovery found in the WAL when it was opened.
+    pub recovery_report: RecoveryReport,
     pub file: Arc<dyn File>,
     /// read_locks is a list of read locks that can coexist with the max_frame number stored in
     /// value. There is a limited amount because and unbounded amount of connections could be
@@ -479,7 +631,16 @@ pub struct WalFileShared {
     /// There is only one write allowed in WAL mode. This lock takes care of ensuring there is only
     /// one used.
     pub write_lock: LimboRwLock,
+    /// Set by the holder of `write_lock` while it runs an exclusive transaction. Read
+    /// transactions started by other connections are refused while it's set.
+    pub exclusive: AtomicBool,
     pub loaded: AtomicBool,
+    /// Number of frames in the WAL after which a commit checkpoints it, 0 to never
+    /// checkpoint automatically. Shared so that `PRAGMA wal_autocheckpoint` applies to every
+    /// connection of the database.
+    pub checkpoint_threshold: AtomicU64,
+    /// Shared for the same reason as `checkpoint_threshold`.
+    pub journal_mode: SpinLock<JournalMode>,
 }
 
 impl fmt::Debug for WalFileShared {
@@ -492,6 +653,8 @@ impl fmt::Debug for WalFileShared {
             .field("frame_cache", &self.frame_cache)
             .field("pages_in_frames", &self.pages_in_frames)
             .field("last_checksum", &self.last_checksum)
+            .field("checkpoint_threshold", &self.checkpoint_threshold)
+            .field("journal_mode", &self.journal_mode)
             // Excluding `file`, `read_locks`, and `write_lock`
             .finish()
     }
@@ -541,6 +704,12 @@ impl Wal for WalFile {
             if busy {
                 return Ok(LimboResult::Busy);
             }
+            // The read lock is taken before looking at the flag, and begin_exclusive_tx sets the
+            // flag before counting readers, so one of the two always sees the other.
+            if shared.exclusive.load(Ordering::SeqCst) && !self.holds_exclusive_lock.get() {
+                lock.unlock();
+                return Ok(LimboResult::Busy);
+            }
             (
                 shared.nbackfills.load(Ordering::SeqCst) + 1,
                 shared.last_checksum,
@@ -581,9 +750,31 @@ impl Wal for WalFile {
         Ok(LimboResult::Ok)
     }
 
+    /// Begin an exclusive transaction, the write lock must be held already.
+    fn begin_exclusive_tx(&mut self) -> Result<LimboResult> {
+        let shared = self.get_shared();
+        shared.exclusive.store(true, Ordering::SeqCst);
+        // This connection holds a read lock of its own.
+        let readers: u32 = shared
+            .read_locks
+            .iter()
+            .map(|lock| lock.nreads.load(Ordering::SeqCst))
+            .sum();
+        tracing::debug!("begin_exclusive_tx(readers={})", readers);
+        if readers > 1 {
+            shared.exclusive.store(false, Ordering::SeqCst);
+            return Ok(LimboResult::Busy);
+        }
+        self.holds_exclusive_lock.set(true);
+        Ok(LimboResult::Ok)
+    }
+
     /// End a write transaction
     fn end_write_tx(&self) -> Result<LimboResult> {
         tracing::debug!("end_write_txn");
+        if self.holds_exclusive_lock.replace(false) {
+            self.get_shared().exclusive.store(false, Ordering::SeqCst);
+        }
         self.get_shared().write_lock.unlock();
         Ok(LimboResult::Ok)
     }
@@ -606,19 +797,34 @@ impl Wal for WalFile {
     }
 
     /// Read a frame from the WAL.
-    fn read_frame(&self, frame_id: u64, page: PageRef, buffer_pool: Arc<BufferPool>) -> Result<()> {
+    fn read_frame(
+        &self,
+        frame_id: u64,
+        page: PageRef,
+        buffer: Buffer,
+        checksum_verifier: Option<PageChecksumVerifier>,
+    ) -> Result<()> {
         tracing::debug!("read_frame({})", frame_id);
         let offset = self.frame_offset(frame_id);
         page.set_locked();
         let frame = page.clone();
         let complete = Box::new(move |buf: Arc<RefCell<Buffer>>| {
             let frame = frame.clone();
+            if let Some(verifier) = &checksum_verifier {
+                if verifier
+                    .verify(page.get().id, buf.borrow().as_slice())
+                    .is_err()
+                {
+                    fail_read_page(&frame);
+                    return;
+                }
+            }
             finish_read_page(page.get().id, buf, frame).unwrap();
         });
         begin_read_wal_frame(
             &self.get_shared().file,
             offset + WAL_FRAME_HEADER_SIZE,
-            buffer_pool,
+            buffer,
             complete,
         )?;
         Ok(())
@@ -643,7 +849,7 @@ impl Wal for WalFile {
         let c = begin_read_wal_frame(
             &self.get_shared().file,
             offset + WAL_FRAME_HEADER_SIZE,
-            buffer_pool,
+            buffer_pool.get(),
             complete,
         )?;
         Ok(c)
@@ -670,6 +876,11 @@ impl Wal for WalFile {
             let shared = self.get_shared();
             let header = shared.wal_header.clone();
             let header = header.lock();
+            if frame_id == 1 {
+                // The WAL is new or was restarted by a checkpoint, which may have given it a
+                // new header.
+                sqlite3_ondisk::begin_write_wal_header(&shared.file, &header)?;
+            }
             let checksums = self.last_checksum;
             begin_write_wal_frame(
                 &shared.file,
@@ -701,8 +912,36 @@ impl Wal for WalFile {
 
     fn should_checkpoint(&self) -> bool {
         let shared = self.get_shared();
-        let frame_id = shared.max_frame.load(Ordering::SeqCst) as usize;
-        frame_id >= self.checkpoint_threshold
+        let threshold = shared.checkpoint_threshold.load(Ordering::SeqCst);
+        let frame_id = shared.max_frame.load(Ordering::SeqCst);
+        if *shared.journal_mode.lock() == JournalMode::Delete {
+            return frame_id > 0;
+        }
+        threshold != 0 && frame_id >= threshold
+    }
+
+    fn checkpoint_threshold(&self) -> u64 {
+        self.get_shared()
+            .checkpoint_threshold
+            .load(Ordering::SeqCst)
+    }
+
+    fn set_checkpoint_threshold(&mut self, frames: u64) {
+        self.get_shared()
+            .checkpoint_threshold
+            .store(frames, Ordering::SeqCst);
+    }
+
+    fn journal_mode(&self) -> JournalMode {
+        *self.get_shared().journal_mode.lock()
+    }
+
+    fn set_journal_mode(&mut self, mode: JournalMode) {
+        *self.get_shared().journal_mode.lock() = mode;
+    }
+
+    fn checkpoint_seq(&self) -> u32 {
+        self.get_shared().wal_header.lock().checkpoint_seq
     }
 
     #[instrument(skip_all, level = Level::TRACE)]
@@ -712,17 +951,23 @@ impl Wal for WalFile {
         write_counter: Rc<RefCell<usize>>,
         mode: CheckpointMode,
     ) -> Result<CheckpointStatus> {
-        assert!(
-            matches!(mode, CheckpointMode::Passive),
-            "only passive mode supported for now"
-        );
-        'checkpoint_loop: loop {
+        loop {
             let state = self.ongoing_checkpoint.state;
             tracing::debug!(?state);
             match state {
                 CheckpointState::Start => {
+                    if !matches!(mode, CheckpointMode::Passive) {
+                        // Keep writers out until every frame in the WAL is checkpointed.
+                        if !self.get_shared().write_lock.write() {
+                            return Err(LimboError::Busy);
+                        }
+                        self.ongoing_checkpoint.holds_write_lock = true;
+                    }
                     // TODO(pere): check what frames are safe to checkpoint between many readers!
-                    self.ongoing_checkpoint.min_frame = self.min_frame;
+                    // The checkpoint may run outside of a read transaction, so start from what
+                    // was backfilled last rather than from this connection's snapshot.
+                    self.ongoing_checkpoint.min_frame =
+                        self.get_shared().nbackfills.load(Ordering::SeqCst) + 1;
                     let shared = self.get_shared();
                     let mut max_safe_frame = shared.max_frame.load(Ordering::SeqCst);
                     for (read_lock_idx, read_lock) in shared.read_locks.iter_mut().enumerate() {
@@ -742,8 +987,13 @@ impl Wal for WalFile {
                             }
                         }
                     }
+                    if self.checkpoint_rate_limit > 0 {
+                        shared.file.set_io_priority(IoPriority::Background);
+                    }
                     self.ongoing_checkpoint.max_frame = max_safe_frame;
                     self.ongoing_checkpoint.current_page = 0;
+                    self.ongoing_checkpoint.started_at = Some(self.io.now());
+                    self.ongoing_checkpoint.bytes_written = 0;
                     self.ongoing_checkpoint.state = CheckpointState::ReadFrame;
                     tracing::trace!(
                         "checkpoint_start(min_frame={}, max_frame={})",
@@ -752,6 +1002,11 @@ impl Wal for WalFile {
                     );
                 }
                 CheckpointState::ReadFrame => {
+                    if let Some(delay) = self.checkpoint_throttle_delay() {
+                        self.ongoing_checkpoint.throttled = true;
+                        return Ok(CheckpointStatus::Throttled(delay));
+                    }
+                    self.ongoing_checkpoint.throttled = false;
                     let shared = self.get_shared();
                     let min_frame = self.ongoing_checkpoint.min_frame;
                     let max_frame = self.ongoing_checkpoint.max_frame;
@@ -761,70 +1016,75 @@ impl Wal for WalFile {
                     let frame_cache = shared.frame_cache.clone();
                     let frame_cache = frame_cache.lock();
                     assert!(self.ongoing_checkpoint.current_page as usize <= pages_in_frames.len());
-                    if self.ongoing_checkpoint.current_page as usize == pages_in_frames.len() {
-                        self.ongoing_checkpoint.state = CheckpointState::Done;
-                        continue 'checkpoint_loop;
-                    }
-                    let page = pages_in_frames[self.ongoing_checkpoint.current_page as usize];
-                    let frames = frame_cache
-                        .get(&page)
-                        .expect("page must be in frame cache if it's in list");
-
-                    for frame in frames.iter().rev() {
-                        if *frame >= min_frame && *frame <= max_frame {
-                            tracing::debug!(
-                                "checkpoint page(state={:?}, page={}, frame={})",
-                                state,
-                                page,
-                                *frame
-                            );
-                            self.ongoing_checkpoint.page.get().id = page as usize;
-
-                            self.read_frame(
-                                *frame,
-                                self.ongoing_checkpoint.page.clone(),
-                                self.buffer_pool.clone(),
-                            )?;
-                            self.ongoing_checkpoint.state = CheckpointState::WaitReadFrame;
-                            continue 'checkpoint_loop;
+                    // Gather the next batch of pages with a frame in the safe range, reading
+                    // the latest such frame of each of them.
+                    assert_eq!(self.ongoing_checkpoint.batch_len, 0);
+                    while self.ongoing_checkpoint.batch_len < CHECKPOINT_BATCH_SIZE
+                        && (self.ongoing_checkpoint.current_page as usize) < pages_in_frames.len()
+                    {
+                        let page = pages_in_frames[self.ongoing_checkpoint.current_page as usize];
+                        self.ongoing_checkpoint.current_page += 1;
+                        let frames = frame_cache
+                            .get(&page)
+                            .expect("page must be in frame cache if it's in list");
+                        let Some(frame) = frames
+                            .iter()
+                            .rev()
+                            .find(|frame| **frame >= min_frame && **frame <= max_frame)
+                        else {
+                            continue;
+                        };
+                        tracing::debug!(
+                            "checkpoint page(state={:?}, page={}, frame={})",
+                            state,
+                            page,
+                            *frame
+                        );
+                        let batch_len = self.ongoing_checkpoint.batch_len;
+                        if batch_len == self.ongoing_checkpoint.pages.len() {
+                            self.ongoing_checkpoint.pages.push(Arc::new(Page::new(0)));
                         }
+                        let checkpoint_page = self.ongoing_checkpoint.pages[batch_len].clone();
+                        checkpoint_page.get().id = page as usize;
+                        self.read_frame(*frame, checkpoint_page, self.buffer_pool.get(), None)?;
+                        self.ongoing_checkpoint.batch_len += 1;
                     }
-                    self.ongoing_checkpoint.current_page += 1;
+                    self.ongoing_checkpoint.state = if self.ongoing_checkpoint.batch_len == 0 {
+                        CheckpointState::Done
+                    } else {
+                        CheckpointState::WaitReadFrame
+                    };
                 }
                 CheckpointState::WaitReadFrame => {
-                    if self.ongoing_checkpoint.page.is_locked() {
+                    if self.checkpoint_batch().iter().any(|page| page.is_locked()) {
                         return Ok(CheckpointStatus::IO);
                     } else {
                         self.ongoing_checkpoint.state = CheckpointState::WritePage;
                     }
                 }
                 CheckpointState::WritePage => {
-                    self.ongoing_checkpoint.page.set_dirty();
-                    begin_write_btree_page(
-                        pager,
-                        &self.ongoing_checkpoint.page,
-                        write_counter.clone(),
-                    )?;
+                    // Queue every write of the batch before waiting for any of them.
+                    for page in self.checkpoint_batch() {
+                        page.set_dirty();
+                        begin_write_btree_page(pager, page, write_counter.clone())?;
+                    }
                     self.ongoing_checkpoint.state = CheckpointState::WaitWritePage;
                 }
                 CheckpointState::WaitWritePage => {
                     if *write_counter.borrow() > 0 {
                         return Ok(CheckpointStatus::IO);
                     }
-                    // If page was in cache clear it.
-                    if let Some(page) = pager.cache_get(self.ongoing_checkpoint.page.get().id) {
-                        page.clear_dirty();
-                    }
-                    self.ongoing_checkpoint.page.clear_dirty();
-                    let shared = self.get_shared();
-                    if (self.ongoing_checkpoint.current_page as usize)
-                        < shared.pages_in_frames.lock().len()
-                    {
-                        self.ongoing_checkpoint.current_page += 1;
-                        self.ongoing_checkpoint.state = CheckpointState::ReadFrame;
-                    } else {
-                        self.ongoing_checkpoint.state = CheckpointState::Done;
+                    for checkpoint_page in self.checkpoint_batch() {
+                        // If page was in cache clear it.
+                        if let Some(page) = pager.cache_get(checkpoint_page.get().id) {
+                            page.clear_dirty();
+                        }
+                        checkpoint_page.clear_dirty();
                     }
+                    self.ongoing_checkpoint.bytes_written +=
+                        self.ongoing_checkpoint.batch_len as u64 * self.page_size() as u64;
+                    self.ongoing_checkpoint.batch_len = 0;
+                    self.ongoing_checkpoint.state = CheckpointState::ReadFrame;
                 }
                 CheckpointState::Done => {
                     if *write_counter.borrow() > 0 {
@@ -834,35 +1094,50 @@ impl Wal for WalFile {
 
                     // Record two num pages fields to return as checkpoint result to caller.
                     // Ref: pnLog, pnCkpt on https://www.sqlite.org/c3ref/wal_checkpoint_v2.html
-                    let checkpoint_result = CheckpointResult {
+                    let mut checkpoint_result = CheckpointResult {
                         num_wal_frames: shared.max_frame.load(Ordering::SeqCst),
                         num_checkpointed_frames: self.ongoing_checkpoint.max_frame,
+                        busy: false,
                     };
                     let everything_backfilled = shared.max_frame.load(Ordering::SeqCst)
                         == self.ongoing_checkpoint.max_frame;
                     if everything_backfilled {
                         // TODO: Even in Passive mode, if everything was backfilled we should
                         // truncate and fsync the *db file*
-
-                        // To properly reset the *wal file* we will need restart and/or truncate mode.
-                        // Currently, it will grow the WAL file indefinetly, but don't resetting is better than breaking.
-                        // Check: https://github.com/sqlite/sqlite/blob/2bd9f69d40dd240c4122c6d02f1ff447e7b5c098/src/wal.c#L2193
-                        if !matches!(mode, CheckpointMode::Passive) {
-                            // Here we know that we backfilled everything, therefore we can safely
-                            // reset the wal.
-                            shared.frame_cache.lock().clear();
-                            shared.pages_in_frames.lock().clear();
-                            shared.max_frame.store(0, Ordering::SeqCst);
-                            shared.nbackfills.store(0, Ordering::SeqCst);
-                            // TODO: if all frames were backfilled into the db file, calls fsync
-                            // TODO(pere): truncate wal file here.
+                        match mode {
+                            CheckpointMode::Passive | CheckpointMode::Full => {
+                                shared
+                                    .nbackfills
+                                    .store(self.ongoing_checkpoint.max_frame, Ordering::SeqCst);
+                            }
+                            CheckpointMode::Restart | CheckpointMode::Truncate => {
+                                if !self.restart_log() {
+                                    checkpoint_result.busy = true;
+                                } else if matches!(mode, CheckpointMode::Truncate) {
+                                    let truncated = self.get_shared().file.truncate(0);
+                                    if truncated.is_err() {
+                                        self.release_checkpoint_write_lock();
+                                    }
+                                    truncated?;
+                                    checkpoint_result.num_wal_frames = 0;
+                                    checkpoint_result.num_checkpointed_frames = 0;
+                                }
+                            }
                         }
                     } else {
-                        shared
+                        self.get_shared()
                             .nbackfills
                             .store(self.ongoing_checkpoint.max_frame, Ordering::SeqCst);
+                        // Readers of older snapshots kept some frames from being checkpointed.
+                        checkpoint_result.busy = !matches!(mode, CheckpointMode::Passive);
+                    }
+                    if self.checkpoint_rate_limit > 0 {
+                        self.get_shared().file.set_io_priority(IoPriority::Normal);
                     }
+                    self.release_checkpoint_write_lock();
                     self.ongoing_checkpoint.state = CheckpointState::Start;
+                    self.ongoing_checkpoint.started_at = None;
+                    self.ongoing_checkpoint.throttled = false;
                     return Ok(CheckpointStatus::Done(checkpoint_result));
                 }
             }
@@ -947,6 +1222,18 @@ impl Wal for WalFile {
         shared.last_checksum = self.last_checksum;
         Ok(())
     }
+
+    fn set_checkpoint_rate_limit(&mut self, bytes_per_sec: u64) {
+        self.checkpoint_rate_limit = bytes_per_sec;
+    }
+
+    fn get_checkpoint_rate_limit(&self) -> u64 {
+        self.checkpoint_rate_limit
+    }
+
+    fn is_checkpoint_throttled(&self) -> bool {
+        self.ongoing_checkpoint.throttled
+    }
 }
 
 impl WalFile {
@@ -955,19 +1242,6 @@ impl WalFile {
         shared: Arc<UnsafeCell<WalFileShared>>,
         buffer_pool: Arc<BufferPool>,
     ) -> Self {
-        let checkpoint_page = Arc::new(Page::new(0));
-        let buffer = buffer_pool.get();
-        {
-            let buffer_pool = buffer_pool.clone();
-            let drop_fn = Rc::new(move |buf| {
-                buffer_pool.put(buf);
-            });
-            checkpoint_page.get().contents = Some(PageContent::new(
-                0,
-                Arc::new(RefCell::new(Buffer::new(buffer, drop_fn))),
-            ));
-        }
-
         let header = unsafe { shared.get().as_mut().unwrap().wal_header.lock() };
         Self {
             io,
@@ -975,13 +1249,18 @@ impl WalFile {
             max_frame: unsafe { (*shared.get()).max_frame.load(Ordering::SeqCst) },
             shared,
             ongoing_checkpoint: OngoingCheckpoint {
-                page: checkpoint_page,
+                pages: Vec::with_capacity(CHECKPOINT_BATCH_SIZE),
+                batch_len: 0,
                 state: CheckpointState::Start,
                 min_frame: 0,
                 max_frame: 0,
                 current_page: 0,
+                started_at: None,
+                bytes_written: 0,
+                throttled: false,
+                holds_write_lock: false,
             },
-            checkpoint_threshold: 1000,
+            checkpoint_rate_limit: 0,
             buffer_pool,
             syncing: Rc::new(Cell::new(false)),
             sync_state: Cell::new(SyncState::NotSyncing),
@@ -989,6 +1268,7 @@ impl WalFile {
             max_frame_read_lock_index: 0,
             last_checksum: (0, 0),
             start_pages_in_frames: 0,
+            holds_exclusive_lock: Cell::new(false),
             header: *header,
         }
     }
@@ -997,6 +1277,25 @@ impl WalFile {
         self.get_shared().wal_header.lock().page_size
     }
 
+    /// If the ongoing checkpoint has already copied more bytes than the rate limit allows for the
+    /// time elapsed since it started, returns how long it has to wait until it is back under it.
+    fn checkpoint_throttle_delay(&self) -> Option<Duration> {
+        if self.checkpoint_rate_limit == 0 {
+            return None;
+        }
+        let started_at = self.ongoing_checkpoint.started_at?;
+        let now = self.io.now();
+        let elapsed_micros = (now.secs - started_at.secs) as i128 * 1_000_000 + now.micros as i128
+            - started_at.micros as i128;
+        let allowed_bytes = self.checkpoint_rate_limit as i128 * elapsed_micros.max(0) / 1_000_000;
+        let excess_bytes = self.ongoing_checkpoint.bytes_written as i128 - allowed_bytes;
+        if excess_bytes <= 0 {
+            return None;
+        }
+        let delay_micros = excess_bytes * 1_000_000 / self.checkpoint_rate_limit as i128;
+        Some(Duration::from_micros(delay_micros.max(1) as u64))
+    }
+
     fn frame_offset(&self, frame_id: u64) -> usize {
         assert!(frame_id > 0, "Frame ID must be 1-based");
         let page_offset = (frame_id - 1) * (self.page_size() + WAL_FRAME_HEADER_SIZE as u32) as u64;
@@ -1008,6 +1307,70 @@ impl WalFile {
     fn get_shared(&self) -> &mut WalFileShared {
         unsafe { self.shared.get().as_mut().unwrap() }
     }
+
+    /// Starts the WAL over from its first frame once all of it was checkpointed. The header
+    /// gets a new checkpoint sequence number and salts, so that frames left over from before
+    /// the restart are never mistaken for new ones. Returns false, leaving the WAL alone, if
+    /// some reader may still be reading frames from it.
+    fn restart_log(&mut self) -> bool {
+        let shared = self.get_shared();
+        for i in 0..shared.read_locks.len() {
+            if !shared.read_locks[i].write() {
+                for lock in &mut shared.read_locks[..i] {
+                    lock.unlock();
+                }
+                return false;
+            }
+        }
+        shared.frame_cache.lock().clear();
+        shared.pages_in_frames.lock().clear();
+        shared.max_frame.store(0, Ordering::SeqCst);
+        shared.nbackfills.store(0, Ordering::SeqCst);
+        {
+            let mut header = shared.wal_header.lock();
+            header.checkpoint_seq = header.checkpoint_seq.wrapping_add(1);
+            header.salt_1 = header.salt_1.wrapping_add(1);
+            header.salt_2 = self.io.generate_random_number() as u32;
+            set_wal_header_checksum(&mut header);
+            shared.last_checksum = (header.checksum_1, header.checksum_2);
+        }
+        for (i, lock) in shared.read_locks.iter_mut().enumerate() {
+            let mark = if i == 0 { 0 } else { READMARK_NOT_USED };
+            lock.value.store(mark, Ordering::SeqCst);
+            lock.unlock();
+        }
+        self.last_checksum = shared.last_checksum;
+        self.min_frame = 1;
+        self.max_frame = 0;
+        true
+    }
+
+    /// Returns the helper pages of the batch the checkpoint has in flight.
+    fn checkpoint_batch(&self) -> &[PageRef] {
+        &self.ongoing_checkpoint.pages[..self.ongoing_checkpoint.batch_len]
+    }
+
+    fn release_checkpoint_write_lock(&mut self) {
+        if self.ongoing_checkpoint.holds_write_lock {
+            self.get_shared().write_lock.unlock();
+            self.ongoing_checkpoint.holds_write_lock = false;
+        }
+    }
+}
+
+/// Computes the checksum of the first 24 bytes of `wal_header` into its checksum fields.
+fn set_wal_header_checksum(wal_header: &mut WalHeader) {
+    // If the target is big endian the header is already in the byte order the checksum is
+    // computed in, otherwise its bytes need swapping.
+    let native = cfg!(target_endian = "big");
+    let checksums = checksum_wal(
+        &wal_header.as_bytes()[..WAL_HEADER_SIZE - 2 * 4], // first 24 bytes
+        wal_header,
+        (0, 0),
+        native,
+    );
+    wal_header.checksum_1 = checksums.0;
+    wal_header.checksum_2 = checksums.1;
 }
 
 impl WalFileShared {
@@ -1030,6 +1393,7 @@ impl WalFileShared {
                     panic!("WAL file not loaded");
                 }
             }
+            *unsafe { &*wal_file_shared.get() }.journal_mode.lock() = JournalMode::for_io(io);
             Ok(Some(wal_file_shared))
         } else {
             Ok(None)
@@ -1056,19 +1420,7 @@ impl WalFileShared {
             checksum_1: 0,
             checksum_2: 0,
         };
-        let native = cfg!(target_endian = "big"); // if target_endian is
-                                                  // already big then we don't care but if isn't, header hasn't yet been
-                                                  // encoded to big endian, therefore we want to swap bytes to compute this
-                                                  // checksum.
-        let checksums = (0, 0);
-        let checksums = checksum_wal(
-            &wal_header.as_bytes()[..WAL_HEADER_SIZE - 2 * 4], // first 24 bytes
-            &wal_header,
-            checksums,
-            native, // this is false because we haven't encoded the wal header yet
-        );
-        wal_header.checksum_1 = checksums.0;
-        wal_header.checksum_2 = checksums.1;
+        set_wal_header_checksum(&mut wal_header);
         sqlite3_ondisk::begin_write_wal_header(&file, &wal_header)?;
         let header = Arc::new(SpinLock::new(wal_header));
         let checksum = {
@@ -1082,6 +1434,7 @@ impl WalFileShared {
             nbackfills: AtomicU64::new(0),
             frame_cache: Arc::new(SpinLock::new(HashMap::new())),
             last_checksum: checksum,
+            recovery_report: RecoveryReport::default(),
             file,
             pages_in_frames: Arc::new(SpinLock::new(Vec::new())),
             read_locks: array::from_fn(|_| LimboRwLock {
@@ -1094,7 +1447,10 @@ impl WalFileShared {
                 nreads: AtomicU32::new(0),
                 value: AtomicU32::new(READMARK_NOT_USED),
             },
+            exclusive: AtomicBool::new(false),
             loaded: AtomicBool::new(true),
+            checkpoint_threshold: AtomicU64::new(DEFAULT_CHECKPOINT_THRESHOLD),
+            journal_mode: SpinLock::new(JournalMode::for_io(io)),
         };
         Ok(Arc::new(UnsafeCell::new(shared)))
     }
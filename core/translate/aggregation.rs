@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use turso_sqlite3_parser::ast;
 
 use crate::{
@@ -153,28 +155,15 @@ pub fn translate_aggregation_step(
             let delimiter_reg = program.alloc_register();
 
             let expr = &agg.args[0];
-            let delimiter_expr: ast::Expr;
-
-            if agg.args.len() == 2 {
-                match &agg.args[1] {
-                    ast::Expr::Column { .. } => {
-                        delimiter_expr = agg.args[1].clone();
-                    }
-                    ast::Expr::Literal(ast::Literal::String(s)) => {
-                        delimiter_expr = ast::Expr::Literal(ast::Literal::String(s.to_string()));
-                    }
-                    _ => crate::bail_parse_error!("Incorrect delimiter parameter"),
-                };
-            } else {
-                delimiter_expr = ast::Expr::Literal(ast::Literal::String(String::from("\",\"")));
-            }
+            let default_delimiter = ast::Expr::Literal(ast::Literal::String(String::from("\",\"")));
+            let delimiter_expr = agg.args.get(1).unwrap_or(&default_delimiter);
 
             translate_expr(program, Some(referenced_tables), expr, expr_reg, resolver)?;
             handle_distinct(program, agg, expr_reg);
             translate_expr(
                 program,
                 Some(referenced_tables),
-                &delimiter_expr,
+                delimiter_expr,
                 delimiter_reg,
                 resolver,
             )?;
@@ -339,11 +328,12 @@ pub fn translate_aggregation_step(
                     "External aggregate function called with wrong number of arguments".to_string(),
                 )
             })?;
-            if argc != agg.args.len() {
+            if argc.is_some_and(|argc| argc != agg.args.len()) {
                 crate::bail_parse_error!(
                     "External aggregate function called with wrong number of arguments"
                 );
             }
+            let argc = agg.args.len();
             for i in 0..argc {
                 if i != 0 {
                     let _ = program.alloc_register();
@@ -364,7 +354,7 @@ pub fn translate_aggregation_step(
                 acc_reg: target_register,
                 col: expr_reg,
                 delimiter: 0,
-                func: AggFunc::External(func.clone()),
+                func: AggFunc::External(Rc::new(func.with_agg_args(argc))),
             });
             target_register
         }
@@ -3,8 +3,8 @@ use turso_sqlite3_parser::{ast, lexer::sql::Parser};
 
 use crate::{
     function::{AlterTableFunc, Func},
-    schema::{Column, Schema},
-    util::normalize_ident,
+    schema::{Column, Schema, MAX_COLUMNS},
+    util::{escape_string_literal, normalize_ident, unquote_ident},
     vdbe::{
         builder::ProgramBuilder,
         insn::{Cookie, Insn, RegisterOrLiteral},
@@ -79,15 +79,40 @@ pub fn translate_alter_table(
                 )));
             }
 
-            btree.columns.remove(dropped_index);
+            if btree.foreign_keys.iter().any(|foreign_key| {
+                foreign_key
+                    .child_columns
+                    .iter()
+                    .any(|name| name == &normalize_ident(&column_name))
+            }) {
+                return Err(LimboError::ParseError(format!(
+                    "cannot drop column \"{column_name}\": FOREIGN KEY"
+                )));
+            }
 
-            let sql = btree.to_sql();
+            // The records of a table with generated columns can't be rewritten column by column.
+            if btree.columns.iter().any(|column| column.is_generated()) {
+                return Err(LimboError::ParseError(format!(
+                    "cannot drop column \"{column_name}\": table has generated columns"
+                )));
+            }
+
+            if !btree.has_rowid {
+                return Err(LimboError::ParseError(format!(
+                    "cannot drop column \"{column_name}\": WITHOUT ROWID tables are not supported"
+                )));
+            }
+
+            btree.remove_column(dropped_index);
+
+            let sql = escape_string_literal(&btree.to_sql());
+            let name = escape_string_literal(&unquote_ident(&table_name));
 
             let stmt = format!(
                 r#"
                     UPDATE {SQLITE_TABLEID}
                     SET sql = '{sql}'
-                    WHERE name = '{table_name}' COLLATE NOCASE AND type = 'table'
+                    WHERE name = '{name}' COLLATE NOCASE AND type = 'table'
                 "#,
             );
 
@@ -156,6 +181,9 @@ pub fn translate_alter_table(
             })?
         }
         ast::AlterTableBody::AddColumn(col_def) => {
+            if btree.columns.len() >= MAX_COLUMNS {
+                crate::bail_parse_error!("too many columns on {}", btree.name);
+            }
             let column = Column::from(col_def);
 
             if let Some(default) = &column.default {
@@ -176,23 +204,22 @@ pub fn translate_alter_table(
                 }
             }
 
-            btree.columns.push(column);
+            if column.generated_stored {
+                return Err(LimboError::ParseError(
+                    "cannot add a STORED column".to_string(),
+                ));
+            }
 
-            let sql = btree.to_sql();
-            let mut escaped = String::with_capacity(sql.len());
+            btree.add_column(column);
 
-            for ch in sql.chars() {
-                match ch {
-                    '\'' => escaped.push_str("''"),
-                    ch => escaped.push(ch),
-                }
-            }
+            let sql = escape_string_literal(&btree.to_sql());
+            let name = escape_string_literal(&unquote_ident(&table_name));
 
             let stmt = format!(
                 r#"
                     UPDATE {SQLITE_TABLEID}
-                    SET sql = '{escaped}'
-                    WHERE name = '{table_name}' COLLATE NOCASE AND type = 'table'
+                    SET sql = '{sql}'
+                    WHERE name = '{name}' COLLATE NOCASE AND type = 'table'
                 "#,
             );
 
@@ -0,0 +1,524 @@
+//! ANALYZE gathers statistics about tables and their indexes and stores them in
+//! `sqlite_stat1`, in the same format as SQLite, so that the optimizer can use them.
+//!
+//! Each row of `sqlite_stat1` is `(tbl, idx, stat)`. For an index, `stat` is the number of
+//! rows in the index followed by, for every prefix of the index columns, the average number of
+//! rows sharing a value of that prefix. A table without indexes gets a single row with a NULL
+//! `idx` and its row count.
+//!
+//! With `PRAGMA analysis_limit = N`, only the first N rows of every index are read. The row count
+//! is then taken from the b-tree pages, which is much cheaper than reading the rows, and the
+//! averages are computed over the rows that were read.
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast;
+
+use crate::schema::{BTreeTable, Index, Schema};
+use crate::storage::pager::CreateBTreeFlags;
+use crate::translate::collate::CollationSeq;
+use crate::util::normalize_ident;
+use crate::vdbe::builder::{CursorType, ProgramBuilder, ProgramBuilderOpts};
+use crate::vdbe::insn::{CmpInsFlags, Cookie, InsertFlags, Insn, RegisterOrLiteral};
+use crate::vdbe::CursorID;
+use crate::{bail_parse_error, Result};
+
+use super::emitter::TransactionMode;
+use super::schema::{emit_schema_entry, SchemaEntryType, SQLITE_STAT1_TABLE, SQLITE_TABLEID};
+
+/// What a single ANALYZE statement gathers statistics about.
+enum AnalyzeTarget {
+    /// Every index of every table, or every table if it has no indexes.
+    Tables(Vec<Rc<BTreeTable>>),
+    /// A single index.
+    Index(Rc<BTreeTable>, Arc<Index>),
+}
+
+pub fn translate_analyze(
+    name: Option<ast::QualifiedName>,
+    schema: &Schema,
+    analysis_limit: i32,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let opts = ProgramBuilderOpts {
+        num_cursors: 2,
+        approx_num_insns: 60,
+        approx_num_labels: 8,
+    };
+    program.extend(&opts);
+    let target = resolve_analyze_target(name, schema)?;
+
+    // Open sqlite_stat1, creating it if this is the first ANALYZE.
+    let stat1_sql = format!("CREATE TABLE {SQLITE_STAT1_TABLE}(tbl,idx,stat)");
+    let (stat1_table, stat1_root_page) = match schema.get_btree_table(SQLITE_STAT1_TABLE) {
+        Some(table) => {
+            let root_page = RegisterOrLiteral::Literal(table.root_page);
+            (table, root_page)
+        }
+        None => {
+            let root_reg = program.alloc_register();
+            program.emit_insn(Insn::CreateBtree {
+                db: 0,
+                root: root_reg,
+                flags: CreateBTreeFlags::new_table(),
+            });
+            let sqlite_schema = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+            let sqlite_schema_cursor_id =
+                program.alloc_cursor_id(CursorType::BTreeTable(sqlite_schema));
+            program.emit_insn(Insn::OpenWrite {
+                cursor_id: sqlite_schema_cursor_id,
+                root_page: 1usize.into(),
+                name: SQLITE_TABLEID.to_string(),
+            });
+            emit_schema_entry(
+                &mut program,
+                sqlite_schema_cursor_id,
+                SchemaEntryType::Table,
+                SQLITE_STAT1_TABLE,
+                SQLITE_STAT1_TABLE,
+                root_reg,
+                Some(stat1_sql.clone()),
+            );
+            let table = Rc::new(BTreeTable::from_sql(&stat1_sql, 0)?);
+            (table, RegisterOrLiteral::Register(root_reg))
+        }
+    };
+    let stat1_created = matches!(stat1_root_page, RegisterOrLiteral::Register(_));
+    let stat1_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(stat1_table));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: stat1_cursor_id,
+        root_page: stat1_root_page,
+        name: SQLITE_STAT1_TABLE.to_string(),
+    });
+
+    if !stat1_created {
+        emit_delete_stale_stats(&mut program, stat1_cursor_id, &target);
+    }
+
+    match &target {
+        AnalyzeTarget::Tables(tables) => {
+            for table in tables {
+                let indexes = if table.has_rowid {
+                    schema.get_indices(&table.name).to_vec()
+                } else {
+                    // A WITHOUT ROWID table is its own primary key index.
+                    vec![Arc::new(table.without_rowid_index())]
+                };
+                if indexes.is_empty() {
+                    emit_table_stat(&mut program, stat1_cursor_id, table);
+                }
+                for index in indexes {
+                    emit_index_stat(&mut program, stat1_cursor_id, table, &index, analysis_limit);
+                }
+            }
+        }
+        AnalyzeTarget::Index(table, index) => {
+            emit_index_stat(&mut program, stat1_cursor_id, table, index, analysis_limit);
+        }
+    }
+
+    // Bump the schema version so that other connections pick up the new statistics.
+    program.emit_insn(Insn::SetCookie {
+        db: 0,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+    if stat1_created {
+        program.emit_insn(Insn::ParseSchema {
+            db: 0,
+            where_clause: Some(format!("tbl_name = '{SQLITE_STAT1_TABLE}'")),
+        });
+    }
+    program.emit_insn(Insn::LoadAnalysis { db: 0 });
+    program.epilogue(TransactionMode::Write);
+    Ok(program)
+}
+
+fn resolve_analyze_target(
+    name: Option<ast::QualifiedName>,
+    schema: &Schema,
+) -> Result<AnalyzeTarget> {
+    let analyze_all = || {
+        let mut tables = schema
+            .tables
+            .values()
+            .filter_map(|table| table.btree())
+            .filter(|table| is_analyzable(table))
+            .collect::<Vec<_>>();
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        AnalyzeTarget::Tables(tables)
+    };
+    let Some(name) = name else {
+        return Ok(analyze_all());
+    };
+    let object_name = normalize_ident(&name.name.0);
+    match &name.db_name {
+        Some(db_name) if normalize_ident(&db_name.0) != "main" => {
+            bail_parse_error!("unknown database {}", db_name.0)
+        }
+        // `ANALYZE main` analyzes the whole database.
+        None if object_name == "main" => return Ok(analyze_all()),
+        _ => {}
+    }
+    if let Some(table) = schema.get_btree_table(&object_name) {
+        let tables = if is_analyzable(&table) {
+            vec![table]
+        } else {
+            vec![]
+        };
+        return Ok(AnalyzeTarget::Tables(tables));
+    }
+    let index = schema
+        .indexes
+        .values()
+        .flatten()
+        .find(|index| normalize_ident(&index.name) == object_name);
+    if let Some(index) = index {
+        let table = schema
+            .get_btree_table(&index.table_name)
+            .expect("index must belong to a table");
+        return Ok(AnalyzeTarget::Index(table, index.clone()));
+    }
+    bail_parse_error!("no such table: {}", name.name.0)
+}
+
+/// SQLite never gathers statistics about its own tables.
+fn is_analyzable(table: &BTreeTable) -> bool {
+    !table.name.to_ascii_lowercase().starts_with("sqlite_")
+}
+
+/// Deletes the rows of `sqlite_stat1` that are about to be replaced.
+fn emit_delete_stale_stats(
+    program: &mut ProgramBuilder,
+    stat1_cursor_id: CursorID,
+    target: &AnalyzeTarget,
+) {
+    let label_end = program.allocate_label();
+    let label_loop = program.allocate_label();
+    let label_delete = program.allocate_label();
+    let label_next = program.allocate_label();
+    let tbl_reg = program.alloc_register();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: stat1_cursor_id,
+        pc_if_empty: label_end,
+    });
+    program.preassign_label_to_next_insn(label_loop);
+    program.emit_column(stat1_cursor_id, 0, tbl_reg);
+    match target {
+        AnalyzeTarget::Tables(tables) => {
+            for table in tables {
+                let name_reg = program.emit_string8_new_reg(table.name.clone());
+                program.emit_insn(Insn::Eq {
+                    lhs: tbl_reg,
+                    rhs: name_reg,
+                    target_pc: label_delete,
+                    flags: CmpInsFlags::default(),
+                    collation: Some(CollationSeq::NoCase),
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: label_next,
+            });
+        }
+        AnalyzeTarget::Index(table, index) => {
+            let name_reg = program.emit_string8_new_reg(table.name.clone());
+            program.emit_insn(Insn::Ne {
+                lhs: tbl_reg,
+                rhs: name_reg,
+                target_pc: label_next,
+                flags: CmpInsFlags::default(),
+                collation: Some(CollationSeq::NoCase),
+            });
+            let idx_reg = program.alloc_register();
+            program.emit_column(stat1_cursor_id, 1, idx_reg);
+            let index_name_reg = program.emit_string8_new_reg(index.name.clone());
+            program.emit_insn(Insn::Ne {
+                lhs: idx_reg,
+                rhs: index_name_reg,
+                target_pc: label_next,
+                flags: CmpInsFlags::default().jump_if_null(),
+                collation: Some(CollationSeq::NoCase),
+            });
+        }
+    }
+    program.preassign_label_to_next_insn(label_delete);
+    program.emit_insn(Insn::Delete {
+        cursor_id: stat1_cursor_id,
+        table_name: SQLITE_STAT1_TABLE.to_string(),
+    });
+    program.preassign_label_to_next_insn(label_next);
+    program.emit_insn(Insn::Next {
+        cursor_id: stat1_cursor_id,
+        pc_if_next: label_loop,
+    });
+    program.preassign_label_to_next_insn(label_end);
+}
+
+/// Writes the row count of a table without indexes to `sqlite_stat1`, unless it is empty.
+fn emit_table_stat(
+    program: &mut ProgramBuilder,
+    stat1_cursor_id: CursorID,
+    table: &Rc<BTreeTable>,
+) {
+    let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    program.emit_insn(Insn::OpenRead {
+        cursor_id,
+        root_page: table.root_page,
+    });
+    let row_count_reg = program.alloc_register();
+    program.emit_insn(Insn::Count {
+        cursor_id,
+        target_reg: row_count_reg,
+        exact: true,
+    });
+    let label_skip = program.allocate_label();
+    program.emit_insn(Insn::IfNot {
+        reg: row_count_reg,
+        target_pc: label_skip,
+        jump_if_null: true,
+    });
+    let stat_reg = program.emit_string8_new_reg(String::new());
+    program.emit_insn(Insn::Concat {
+        lhs: stat_reg,
+        rhs: row_count_reg,
+        dest: stat_reg,
+    });
+    emit_stat1_row(program, stat1_cursor_id, &table.name, None, stat_reg);
+    program.preassign_label_to_next_insn(label_skip);
+    program.emit_insn(Insn::Close { cursor_id });
+}
+
+/// Scans an index in order, counting its rows and the distinct values of every prefix of its
+/// columns, and writes the resulting statistics to `sqlite_stat1`, unless the index is empty.
+/// If `analysis_limit` is positive, the scan stops after that many rows.
+fn emit_index_stat(
+    program: &mut ProgramBuilder,
+    stat1_cursor_id: CursorID,
+    table: &BTreeTable,
+    index: &Arc<Index>,
+    analysis_limit: i32,
+) {
+    // The primary key index of a WITHOUT ROWID table is keyed by the primary key columns only,
+    // and its statistics are stored under the name of the table.
+    let (column_count, index_name) = if index.root_page == table.root_page {
+        (table.primary_key_columns.len(), table.name.clone())
+    } else {
+        (index.columns.len(), index.name.clone())
+    };
+
+    let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+    program.emit_insn(Insn::OpenRead {
+        cursor_id,
+        root_page: index.root_page,
+    });
+    let one_reg = program.alloc_register();
+    program.emit_insn(Insn::Integer {
+        value: 1,
+        dest: one_reg,
+    });
+    let row_count_reg = program.alloc_register();
+    let distinct_start_reg = program.alloc_registers(column_count);
+    for reg in
+        std::iter::once(row_count_reg).chain(distinct_start_reg..distinct_start_reg + column_count)
+    {
+        program.emit_insn(Insn::Integer {
+            value: 0,
+            dest: reg,
+        });
+    }
+    let is_first_row_reg = program.alloc_register();
+    program.emit_insn(Insn::Integer {
+        value: 1,
+        dest: is_first_row_reg,
+    });
+    let prev_start_reg = program.alloc_registers(column_count);
+    let cur_start_reg = program.alloc_registers(column_count);
+    let limit_reg = (analysis_limit > 0).then(|| {
+        let reg = program.alloc_register();
+        program.emit_insn(Insn::Integer {
+            value: analysis_limit as i64,
+            dest: reg,
+        });
+        reg
+    });
+
+    let label_end = program.allocate_label();
+    let label_loop = program.allocate_label();
+    let label_next = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: label_end,
+    });
+    program.preassign_label_to_next_insn(label_loop);
+    program.emit_insn(Insn::Add {
+        lhs: row_count_reg,
+        rhs: one_reg,
+        dest: row_count_reg,
+    });
+    for i in 0..column_count {
+        program.emit_column(cursor_id, i, cur_start_reg + i);
+    }
+    // Find the shortest prefix whose value differs from the previous row: that prefix and all
+    // the longer ones have one more distinct value. NULLs compare equal to each other here.
+    let labels_changed = (0..column_count)
+        .map(|_| program.allocate_label())
+        .collect::<Vec<_>>();
+    program.emit_insn(Insn::If {
+        reg: is_first_row_reg,
+        target_pc: labels_changed[0],
+        jump_if_null: false,
+    });
+    for i in 0..column_count {
+        program.emit_insn(Insn::Ne {
+            lhs: cur_start_reg + i,
+            rhs: prev_start_reg + i,
+            target_pc: labels_changed[i],
+            flags: CmpInsFlags::default().null_eq(),
+            collation: index.columns[i].collation,
+        });
+    }
+    program.emit_insn(Insn::Goto {
+        target_pc: label_next,
+    });
+    for (i, label_changed) in labels_changed.into_iter().enumerate() {
+        program.preassign_label_to_next_insn(label_changed);
+        program.emit_insn(Insn::Add {
+            lhs: distinct_start_reg + i,
+            rhs: one_reg,
+            dest: distinct_start_reg + i,
+        });
+    }
+    program.emit_insn(Insn::Copy {
+        src_reg: cur_start_reg,
+        dst_reg: prev_start_reg,
+        amount: column_count - 1,
+    });
+    program.emit_insn(Insn::Integer {
+        value: 0,
+        dest: is_first_row_reg,
+    });
+    program.preassign_label_to_next_insn(label_next);
+    let label_sampled = program.allocate_label();
+    if let Some(limit_reg) = limit_reg {
+        program.emit_insn(Insn::DecrJumpZero {
+            reg: limit_reg,
+            target_pc: label_sampled,
+        });
+    }
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: label_loop,
+    });
+    // The number of rows in the index. If the scan stopped early, the rows that were read are a
+    // sample: the averages below are computed over the sample, and only the row count is scaled
+    // up to the whole index.
+    let total_rows_reg = program.alloc_register();
+    program.emit_insn(Insn::Copy {
+        src_reg: row_count_reg,
+        dst_reg: total_rows_reg,
+        amount: 0,
+    });
+    if limit_reg.is_some() {
+        let label_counted = program.allocate_label();
+        program.emit_insn(Insn::Goto {
+            target_pc: label_counted,
+        });
+        program.preassign_label_to_next_insn(label_sampled);
+        program.emit_insn(Insn::Count {
+            cursor_id,
+            target_reg: total_rows_reg,
+            exact: true,
+        });
+        program.preassign_label_to_next_insn(label_counted);
+    }
+
+    // stat = "<rows> <avg rows per prefix 1> ... <avg rows per prefix N>", where the average is
+    // rounded up, like SQLite does.
+    let stat_reg = program.emit_string8_new_reg(String::new());
+    program.emit_insn(Insn::Concat {
+        lhs: stat_reg,
+        rhs: total_rows_reg,
+        dest: stat_reg,
+    });
+    let space_reg = program.emit_string8_new_reg(" ".to_string());
+    let avg_reg = program.alloc_register();
+    for i in 0..column_count {
+        program.emit_insn(Insn::Add {
+            lhs: row_count_reg,
+            rhs: distinct_start_reg + i,
+            dest: avg_reg,
+        });
+        program.emit_insn(Insn::Subtract {
+            lhs: avg_reg,
+            rhs: one_reg,
+            dest: avg_reg,
+        });
+        program.emit_insn(Insn::Divide {
+            lhs: avg_reg,
+            rhs: distinct_start_reg + i,
+            dest: avg_reg,
+        });
+        program.emit_insn(Insn::Concat {
+            lhs: stat_reg,
+            rhs: space_reg,
+            dest: stat_reg,
+        });
+        program.emit_insn(Insn::Concat {
+            lhs: stat_reg,
+            rhs: avg_reg,
+            dest: stat_reg,
+        });
+    }
+    emit_stat1_row(
+        program,
+        stat1_cursor_id,
+        &table.name,
+        Some(&index_name),
+        stat_reg,
+    );
+    program.preassign_label_to_next_insn(label_end);
+    program.emit_insn(Insn::Close { cursor_id });
+}
+
+/// Appends the row `(tbl, idx, stat)` to `sqlite_stat1`.
+fn emit_stat1_row(
+    program: &mut ProgramBuilder,
+    stat1_cursor_id: CursorID,
+    table_name: &str,
+    index_name: Option<&str>,
+    stat_reg: usize,
+) {
+    let start_reg = program.alloc_registers(3);
+    program.emit_string8(table_name.to_string(), start_reg);
+    match index_name {
+        Some(index_name) => program.emit_string8(index_name.to_string(), start_reg + 1),
+        None => program.emit_null(start_reg + 1, None),
+    }
+    program.emit_insn(Insn::Copy {
+        src_reg: stat_reg,
+        dst_reg: start_reg + 2,
+        amount: 0,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg,
+        count: 3,
+        dest_reg: record_reg,
+        index_name: None,
+    });
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: stat1_cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: stat1_cursor_id,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new().internal(),
+        table_name: SQLITE_STAT1_TABLE.to_string(),
+    });
+}
@@ -0,0 +1,141 @@
+use turso_sqlite3_parser::ast::{self, TableInternalId};
+
+use crate::error::SQLITE_CONSTRAINT_CHECK;
+use crate::schema::BTreeTable;
+use crate::util::normalize_ident;
+use crate::vdbe::builder::ProgramBuilder;
+use crate::vdbe::insn::Insn;
+use crate::{bail_parse_error, Result};
+
+use super::emitter::Resolver;
+use super::expr::{translate_expr, walk_expr, walk_expr_mut, WalkControl};
+use super::fkey::RowRegisters;
+
+/// Replaces the column references of an expression in `table`'s definition (a CHECK
+/// constraint or a generated column) with [ast::Expr::Column] and [ast::Expr::RowId] nodes
+/// referring to `table_id`.
+pub(crate) fn bind_table_expr(
+    table: &BTreeTable,
+    expr: &ast::Expr,
+    table_id: TableInternalId,
+) -> Result<ast::Expr> {
+    let mut expr = expr.clone();
+    walk_expr_mut(&mut expr, &mut |expr: &mut ast::Expr| -> Result<()> {
+        let name = match expr {
+            ast::Expr::Id(id) => id.0.clone(),
+            ast::Expr::Qualified(tbl, id) if normalize_ident(&tbl.0) == table.name => id.0.clone(),
+            _ => return Ok(()),
+        };
+        if let Some((column, col)) = table.get_column(&name) {
+            *expr = ast::Expr::Column {
+                database: None,
+                table: table_id,
+                column,
+                is_rowid_alias: col.is_rowid_alias,
+            };
+        } else if ["rowid", "_rowid_", "oid"].contains(&normalize_ident(&name).as_str()) {
+            *expr = ast::Expr::RowId {
+                database: None,
+                table: table_id,
+            };
+        } else {
+            bail_parse_error!("no such column: {}", name);
+        }
+        Ok(())
+    })?;
+    Ok(expr)
+}
+
+/// Emits the CHECK constraints of `table` for the row held by `row`, halting with
+/// `SQLITE_CONSTRAINT_CHECK` when one evaluates to false. With `changed_columns`, only
+/// constraints that refer to one of those columns are evaluated, like SQLite does for
+/// UPDATE.
+pub fn emit_check_constraints(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    row: RowRegisters,
+    changed_columns: Option<&[usize]>,
+) -> Result<()> {
+    if table.checks.is_empty() {
+        return Ok(());
+    }
+    let mut bound = Vec::with_capacity(table.checks.len());
+    for check in &table.checks {
+        // The row is held in registers, which are resolved through the expression cache.
+        let expr = bind_table_expr(table, &check.expr, TableInternalId::default())?;
+        if let Some(changed_columns) = changed_columns {
+            let mut refers_to_changed_column = false;
+            walk_expr(&expr, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+                match expr {
+                    ast::Expr::Column { column, .. } => {
+                        refers_to_changed_column |= changed_columns.contains(column);
+                    }
+                    ast::Expr::RowId { .. } => {
+                        refers_to_changed_column |= table
+                            .columns
+                            .iter()
+                            .position(|col| col.is_rowid_alias)
+                            .is_some_and(|idx| changed_columns.contains(&idx));
+                    }
+                    _ => {}
+                }
+                Ok(WalkControl::Continue)
+            })?;
+            if !refers_to_changed_column {
+                continue;
+            }
+        }
+        bound.push((check, expr));
+    }
+
+    let columns = row_register_exprs(table, row);
+    let mut resolver = Resolver::new(resolver.schema, resolver.symbol_table);
+    resolver.enable_expr_to_reg_cache();
+    resolver
+        .expr_to_reg_cache
+        .extend(columns.iter().map(|(expr, reg)| (expr, *reg)));
+
+    for (check, expr) in bound {
+        let reg = program.alloc_register();
+        translate_expr(program, None, &expr, reg, &resolver)?;
+        // A CHECK that evaluates to NULL passes.
+        let label_ok = program.allocate_label();
+        program.emit_insn(Insn::If {
+            reg,
+            target_pc: label_ok,
+            jump_if_null: true,
+        });
+        program.emit_insn(Insn::Halt {
+            err_code: SQLITE_CONSTRAINT_CHECK,
+            description: check.description(),
+        });
+        program.preassign_label_to_next_insn(label_ok);
+    }
+    Ok(())
+}
+
+/// The [ast::Expr::Column] and [ast::Expr::RowId] nodes produced by [bind_table_expr] for the
+/// default table id, paired with the registers of `row` they resolve to.
+pub(crate) fn row_register_exprs(table: &BTreeTable, row: RowRegisters) -> Vec<(ast::Expr, usize)> {
+    (0..table.columns.len())
+        .map(|column| {
+            (
+                ast::Expr::Column {
+                    database: None,
+                    table: TableInternalId::default(),
+                    column,
+                    is_rowid_alias: table.columns[column].is_rowid_alias,
+                },
+                row.column(table, column),
+            )
+        })
+        .chain(std::iter::once((
+            ast::Expr::RowId {
+                database: None,
+                table: TableInternalId::default(),
+            },
+            row.rowid,
+        )))
+        .collect()
+}
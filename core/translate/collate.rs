@@ -1,12 +1,15 @@
-use std::{cmp::Ordering, str::FromStr as _};
+use std::{cmp::Ordering, fmt, str::FromStr as _, sync::RwLock};
 
 use tracing::Level;
+use turso_ext::CollationFunction;
 
-// TODO: in the future allow user to define collation sequences
-// Will have to meddle with ffi for this
-#[derive(
-    Debug, Clone, Copy, Eq, PartialEq, strum_macros::Display, strum_macros::EnumString, Default,
-)]
+/// Collation sequences registered by extensions, see [CollationSeq::register].
+/// [CollationSeq::Custom] holds the position of its collation in this list.
+/// Collations are looked up by name while the schema is parsed, so they are
+/// registered for the whole process rather than for a connection.
+static CUSTOM_COLLATIONS: RwLock<Vec<(String, CollationFunction)>> = RwLock::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, strum_macros::EnumString, Default)]
 #[strum(ascii_case_insensitive)]
 /// **Pre defined collation sequences**\
 /// Collating functions only matter when comparing string values.
@@ -19,13 +22,48 @@ pub enum CollationSeq {
     NoCase,
     /// Same as Binary but with trimmed whitespace
     Rtrim,
+    /// A collation sequence registered by an extension
+    #[strum(disabled)]
+    Custom(usize),
 }
 
 impl CollationSeq {
     pub fn new(collation: &str) -> crate::Result<Self> {
-        CollationSeq::from_str(collation).map_err(|_| {
-            crate::LimboError::ParseError(format!("no such collation sequence: {}", collation))
-        })
+        CollationSeq::from_str(collation)
+            .ok()
+            .or_else(|| Self::find_custom(collation))
+            .ok_or_else(|| {
+                crate::LimboError::ParseError(format!("no such collation sequence: {}", collation))
+            })
+    }
+
+    /// Registers the collation sequence `name`, replacing the collation registered before
+    /// with the same name. The built-in collation sequences can't be replaced.
+    pub fn register(name: &str, func: CollationFunction) -> crate::Result<()> {
+        if CollationSeq::from_str(name).is_ok() {
+            return Err(crate::LimboError::InvalidArgument(format!(
+                "cannot replace built-in collation sequence: {}",
+                name
+            )));
+        }
+        let mut collations = CUSTOM_COLLATIONS.write().unwrap();
+        match collations
+            .iter_mut()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+        {
+            Some((_, existing)) => *existing = func,
+            None => collations.push((name.to_string(), func)),
+        }
+        Ok(())
+    }
+
+    fn find_custom(name: &str) -> Option<Self> {
+        CUSTOM_COLLATIONS
+            .read()
+            .unwrap()
+            .iter()
+            .position(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(CollationSeq::Custom)
     }
 
     pub fn compare_strings(&self, lhs: &str, rhs: &str) -> Ordering {
@@ -34,6 +72,7 @@ impl CollationSeq {
             CollationSeq::Binary => Self::binary_cmp(lhs, rhs),
             CollationSeq::NoCase => Self::nocase_cmp(lhs, rhs),
             CollationSeq::Rtrim => Self::rtrim_cmp(lhs, rhs),
+            CollationSeq::Custom(id) => Self::custom_cmp(*id, lhs, rhs),
         }
     }
 
@@ -50,4 +89,21 @@ impl CollationSeq {
     fn rtrim_cmp(lhs: &str, rhs: &str) -> Ordering {
         lhs.trim_end().cmp(rhs.trim_end())
     }
+
+    fn custom_cmp(id: usize, lhs: &str, rhs: &str) -> Ordering {
+        let func = CUSTOM_COLLATIONS.read().unwrap()[id].1;
+        let result = unsafe { func(lhs.as_ptr(), lhs.len(), rhs.as_ptr(), rhs.len()) };
+        result.cmp(&0)
+    }
+}
+
+impl fmt::Display for CollationSeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollationSeq::Binary => write!(f, "Binary"),
+            CollationSeq::NoCase => write!(f, "NoCase"),
+            CollationSeq::Rtrim => write!(f, "Rtrim"),
+            CollationSeq::Custom(id) => write!(f, "{}", CUSTOM_COLLATIONS.read().unwrap()[*id].0),
+        }
+    }
 }
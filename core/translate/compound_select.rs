@@ -1,4 +1,5 @@
-use crate::schema::{Index, IndexColumn, Schema};
+use crate::schema::{Index, IndexColumn, PseudoCursorType, Schema};
+use crate::translate::collate::CollationSeq;
 use crate::translate::emitter::{emit_query, LimitCtx, TransactionMode, TranslateCtx};
 use crate::translate::plan::{Plan, QueryDestination, SelectPlan};
 use crate::vdbe::builder::{CursorType, ProgramBuilder};
@@ -7,7 +8,7 @@ use crate::vdbe::BranchOffset;
 use crate::SymbolTable;
 use std::sync::Arc;
 use tracing::instrument;
-use turso_sqlite3_parser::ast::{CompoundOperator, SortOrder};
+use turso_sqlite3_parser::ast::{self, CompoundOperator, SortOrder};
 
 use tracing::Level;
 
@@ -22,6 +23,7 @@ pub fn emit_program_for_compound_select(
         left: _left,
         right_most,
         limit,
+        order_by,
         ..
     } = &plan
     else {
@@ -29,6 +31,7 @@ pub fn emit_program_for_compound_select(
     };
 
     let right_plan = right_most.clone();
+    let collations = compound_select_collations(&plan)?;
     // Trivial exit on LIMIT 0
     if let Some(limit) = limit {
         if *limit == 0 {
@@ -50,6 +53,14 @@ pub fn emit_program_for_compound_select(
         LimitCtx::new_shared(reg)
     });
 
+    if order_by.is_some() {
+        emit_compound_select_with_order_by(program, plan, schema, syms, &collations, limit_ctx)?;
+        program.epilogue(TransactionMode::Read);
+        program.result_columns = right_plan.result_columns;
+        program.table_references.extend(right_plan.table_references);
+        return Ok(());
+    }
+
     // When a compound SELECT is part of a query that yields results to a coroutine (e.g. within an INSERT clause),
     // we must allocate registers for the result columns to be yielded. Each subselect will then yield to
     // the coroutine using the same set of registers.
@@ -66,6 +77,7 @@ pub fn emit_program_for_compound_select(
         plan,
         schema,
         syms,
+        &collations,
         limit_ctx,
         yield_reg,
         reg_result_cols_start,
@@ -85,6 +97,7 @@ fn emit_compound_select(
     plan: Plan,
     schema: &Schema,
     syms: &SymbolTable,
+    collations: &[Option<CollationSeq>],
     limit_ctx: Option<LimitCtx>,
     yield_reg: Option<usize>,
     reg_result_cols_start: Option<usize>,
@@ -129,6 +142,7 @@ fn emit_compound_select(
                     compound_select,
                     schema,
                     syms,
+                    collations,
                     limit_ctx,
                     yield_reg,
                     reg_result_cols_start,
@@ -155,7 +169,7 @@ fn emit_compound_select(
                     }
                     _ => {
                         new_dedupe_index = true;
-                        create_dedupe_index(program, &right_most, schema)?
+                        create_dedupe_index(program, &right_most, schema, collations)?
                     }
                 };
                 plan.query_destination = QueryDestination::EphemeralIndex {
@@ -174,6 +188,7 @@ fn emit_compound_select(
                     compound_select,
                     schema,
                     syms,
+                    collations,
                     None,
                     yield_reg,
                     reg_result_cols_start,
@@ -198,7 +213,7 @@ fn emit_compound_select(
                     program.preassign_label_to_next_insn(label_jump_over_dedupe);
                 }
             }
-            CompoundOperator::Intersect => {
+            CompoundOperator::Intersect | CompoundOperator::Except => {
                 let mut target_cursor_id = None;
                 if let QueryDestination::EphemeralIndex { cursor_id, .. } =
                     right_most.query_destination
@@ -207,7 +222,7 @@ fn emit_compound_select(
                 }
 
                 let (left_cursor_id, left_index) =
-                    create_dedupe_index(program, &right_most, schema)?;
+                    create_dedupe_index(program, &right_most, schema, collations)?;
                 plan.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: left_cursor_id,
                     index: left_index.clone(),
@@ -224,20 +239,22 @@ fn emit_compound_select(
                     compound_select,
                     schema,
                     syms,
+                    collations,
                     None,
                     yield_reg,
                     reg_result_cols_start,
                 )?;
 
                 let (right_cursor_id, right_index) =
-                    create_dedupe_index(program, &right_most, schema)?;
+                    create_dedupe_index(program, &right_most, schema, collations)?;
                 right_most.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: right_cursor_id,
                     index: right_index,
                 };
                 emit_query(program, &mut right_most, &mut right_most_ctx)?;
-                read_intersect_rows(
+                read_intersect_or_except_rows(
                     program,
+                    operator,
                     left_cursor_id,
                     &left_index,
                     right_cursor_id,
@@ -246,9 +263,6 @@ fn emit_compound_select(
                     yield_reg,
                 );
             }
-            _ => {
-                crate::bail_parse_error!("unimplemented compound select operator: {:?}", operator);
-            }
         },
         None => {
             if let Some(limit_ctx) = limit_ctx {
@@ -262,21 +276,253 @@ fn emit_compound_select(
     Ok(())
 }
 
+/// Emits bytecode for a compound SELECT with an ORDER BY clause. The compound SELECT runs as a
+/// coroutine whose rows are inserted into a sorter, and the rows are then read back in order.
+/// The LIMIT applies to the sorted rows, so it is not passed down to the sub-selects.
+fn emit_compound_select_with_order_by(
+    program: &mut ProgramBuilder,
+    plan: Plan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    collations: &[Option<CollationSeq>],
+    limit_ctx: Option<LimitCtx>,
+) -> crate::Result<()> {
+    let Plan::CompoundSelect {
+        mut left,
+        mut right_most,
+        offset,
+        order_by: Some(order_by),
+        ..
+    } = plan
+    else {
+        unreachable!()
+    };
+    let destination = right_most.query_destination.clone();
+    let column_count = right_most.result_columns.len();
+
+    // The ORDER BY terms were resolved to column numbers when the plan was built,
+    // see resolve_compound_order_by().
+    let mut key_columns = Vec::with_capacity(order_by.len());
+    let mut key_collations = Vec::with_capacity(order_by.len());
+    for (expr, _) in order_by.iter() {
+        let (expr, collation) = match expr {
+            ast::Expr::Collate(expr, collation_name) => {
+                (expr.as_ref(), Some(CollationSeq::new(collation_name)?))
+            }
+            expr => (expr, None),
+        };
+        let ast::Expr::Literal(ast::Literal::Numeric(num)) = expr else {
+            crate::bail_parse_error!("ORDER BY term does not match any column in the result set");
+        };
+        let column = num.parse::<usize>()? - 1;
+        key_columns.push(column);
+        key_collations.push(collation.or(collations[column]));
+    }
+
+    // The result columns of the coroutine must follow its yield register.
+    let yield_reg = program.alloc_register();
+    let reg_result_cols_start = program.alloc_registers(column_count);
+    let label_coroutine_start = program.allocate_label();
+    let label_coroutine_end = program.allocate_label();
+    let coroutine_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start: label_coroutine_start,
+    };
+    right_most.query_destination = coroutine_destination.clone();
+    for (plan, _) in left.iter_mut() {
+        plan.query_destination = coroutine_destination.clone();
+    }
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: label_coroutine_end,
+        start_offset: label_coroutine_start,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_start);
+    emit_compound_select(
+        program,
+        Plan::CompoundSelect {
+            left,
+            right_most,
+            limit: None,
+            offset,
+            order_by: None,
+        },
+        schema,
+        syms,
+        collations,
+        None,
+        Some(yield_reg),
+        Some(reg_result_cols_start),
+    )?;
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(label_coroutine_end);
+
+    // Insert every row of the compound SELECT into the sorter: the sort keys first, then the
+    // result columns.
+    let sort_cursor = program.alloc_cursor_id(CursorType::Sorter);
+    program.emit_insn(Insn::SorterOpen {
+        cursor_id: sort_cursor,
+        columns: key_columns.len(),
+        order: order_by.iter().map(|(_, order)| *order).collect(),
+        collations: key_collations,
+    });
+    let sorter_column_count = key_columns.len() + column_count;
+    let label_yield = program.allocate_label();
+    let label_sort = program.allocate_label();
+    program.preassign_label_to_next_insn(label_yield);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: label_sort,
+    });
+    let sorter_cols_start_reg = program.alloc_registers(sorter_column_count);
+    for (i, column) in key_columns.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_result_cols_start + column,
+            dst_reg: sorter_cols_start_reg + i,
+            amount: 0,
+        });
+    }
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_result_cols_start,
+        dst_reg: sorter_cols_start_reg + key_columns.len(),
+        amount: column_count - 1,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: sorter_cols_start_reg,
+        count: sorter_column_count,
+        dest_reg: record_reg,
+        index_name: None,
+    });
+    program.emit_insn(Insn::SorterInsert {
+        cursor_id: sort_cursor,
+        record_reg,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_yield,
+    });
+
+    // Read the sorted rows back.
+    program.preassign_label_to_next_insn(label_sort);
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: sorter_column_count,
+    }));
+    let reg_sorter_data = program.alloc_register();
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: sorter_column_count,
+    });
+    let label_sort_loop_start = program.allocate_label();
+    let label_sort_loop_end = program.allocate_label();
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sort_cursor,
+        pc_if_empty: label_sort_loop_end,
+    });
+    program.preassign_label_to_next_insn(label_sort_loop_start);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sort_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+    let cols_start_reg = match destination {
+        QueryDestination::CoroutineYield { yield_reg, .. } => yield_reg + 1,
+        _ => program.alloc_registers(column_count),
+    };
+    for i in 0..column_count {
+        program.emit_column(pseudo_cursor, key_columns.len() + i, cols_start_reg + i);
+    }
+    match destination {
+        QueryDestination::ResultRows => {
+            program.emit_insn(Insn::ResultRow {
+                start_reg: cols_start_reg,
+                count: column_count,
+            });
+        }
+        QueryDestination::CoroutineYield { yield_reg, .. } => {
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: BranchOffset::Offset(0),
+            });
+        }
+        QueryDestination::EphemeralIndex { .. }
+        | QueryDestination::EphemeralTable { .. }
+        | QueryDestination::RecursiveCteQueue { .. } => {
+            crate::bail_parse_error!("ORDER BY is not supported for this compound SELECT");
+        }
+    }
+    if let Some(limit_ctx) = limit_ctx {
+        program.emit_insn(Insn::DecrJumpZero {
+            reg: limit_ctx.reg_limit,
+            target_pc: label_sort_loop_end,
+        });
+    }
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sort_cursor,
+        pc_if_next: label_sort_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_sort_loop_end);
+    Ok(())
+}
+
+/// Returns the collation of each result column of a compound SELECT. As in SQLite, the left-most
+/// SELECT whose column has a collation decides it: an explicit COLLATE, or the collation of a
+/// table column, which is BINARY unless declared otherwise. Every ephemeral index of the compound
+/// SELECT compares rows with these collations, whichever SELECT the rows come from.
+fn compound_select_collations(plan: &Plan) -> crate::Result<Vec<Option<CollationSeq>>> {
+    let Plan::CompoundSelect {
+        left, right_most, ..
+    } = plan
+    else {
+        crate::bail_parse_error!("expected compound select plan");
+    };
+    let mut collations = vec![None; right_most.result_columns.len()];
+    for select in left
+        .iter()
+        .map(|(plan, _)| plan)
+        .chain(std::iter::once(right_most))
+    {
+        for (collation, result_column) in collations.iter_mut().zip(&select.result_columns) {
+            if collation.is_none() {
+                *collation = result_column_collation(&result_column.expr, select)?;
+            }
+        }
+    }
+    Ok(collations)
+}
+
+pub(crate) fn result_column_collation(
+    expr: &ast::Expr,
+    select: &SelectPlan,
+) -> crate::Result<Option<CollationSeq>> {
+    match expr {
+        ast::Expr::Collate(_, collation_name) => CollationSeq::new(collation_name).map(Some),
+        ast::Expr::Column { table, column, .. } => Ok(select
+            .table_references
+            .find_table_by_internal_id(*table)
+            .and_then(|table| table.get_column_at(*column))
+            .map(|column| column.collation.unwrap_or_default())),
+        _ => Ok(None),
+    }
+}
+
 // Creates an ephemeral index that will be used to deduplicate the results of any sub-selects
-fn create_dedupe_index(
+pub(crate) fn create_dedupe_index(
     program: &mut ProgramBuilder,
     select: &SelectPlan,
     schema: &Schema,
+    collations: &[Option<CollationSeq>],
 ) -> crate::Result<(usize, Arc<Index>)> {
     if !schema.indexes_enabled {
-        crate::bail_parse_error!("UNION OR INTERSECT is not supported without indexes");
+        crate::bail_parse_error!("UNION, INTERSECT OR EXCEPT is not supported without indexes");
     }
 
     let dedupe_index = Arc::new(Index {
         columns: select
             .result_columns
             .iter()
-            .map(|c| IndexColumn {
+            .zip(collations)
+            .map(|(c, collation)| IndexColumn {
                 name: c
                     .name(&select.table_references)
                     .map(|n| n.to_string())
@@ -284,7 +530,8 @@ fn create_dedupe_index(
                 order: SortOrder::Asc,
                 pos_in_table: 0,
                 default: None,
-                collation: None, // FIXME: this should be inferred
+                expr: None,
+                collation: *collation,
             })
             .collect(),
         name: "compound_dedupe".to_string(),
@@ -293,6 +540,7 @@ fn create_dedupe_index(
         table_name: String::new(),
         unique: true,
         has_rowid: false,
+        where_clause: None,
     });
     let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(dedupe_index.clone()));
     program.emit_insn(Insn::OpenEphemeral {
@@ -361,9 +609,12 @@ fn read_deduplicated_union_rows(
     });
 }
 
-// Emits the bytecode for Reading rows from the intersection of two cursors.
-fn read_intersect_rows(
+// Emits the bytecode for reading the rows of the left cursor that are also in the right cursor
+// (INTERSECT), or that are not in the right cursor (EXCEPT).
+#[allow(clippy::too_many_arguments)]
+fn read_intersect_or_except_rows(
     program: &mut ProgramBuilder,
+    operator: CompoundOperator,
     left_cursor_id: usize,
     index: &Index,
     right_cursor_id: usize,
@@ -385,12 +636,21 @@ fn read_intersect_rows(
         dest: row_content_reg,
     });
     let label_next = program.allocate_label();
-    program.emit_insn(Insn::NotFound {
-        cursor_id: right_cursor_id,
-        target_pc: label_next,
-        record_reg: row_content_reg,
-        num_regs: 0,
-    });
+    if operator == CompoundOperator::Except {
+        program.emit_insn(Insn::Found {
+            cursor_id: right_cursor_id,
+            target_pc: label_next,
+            record_reg: row_content_reg,
+            num_regs: 0,
+        });
+    } else {
+        program.emit_insn(Insn::NotFound {
+            cursor_id: right_cursor_id,
+            target_pc: label_next,
+            record_reg: row_content_reg,
+            num_regs: 0,
+        });
+    }
     let column_count = index.columns.len();
     let cols_start_reg = if let Some(yield_reg) = yield_reg {
         yield_reg + 1
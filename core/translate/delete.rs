@@ -3,6 +3,7 @@ use crate::translate::emitter::emit_program;
 use crate::translate::optimizer::optimize_plan;
 use crate::translate::plan::{DeletePlan, Operation, Plan};
 use crate::translate::planner::{parse_limit, parse_where};
+use crate::util::normalize_ident;
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts, TableRefIdCounter};
 use crate::{schema::Schema, Result, SymbolTable};
 use turso_sqlite3_parser::ast::{Expr, Limit, QualifiedName};
@@ -14,6 +15,7 @@ pub fn translate_delete(
     tbl_name: &QualifiedName,
     where_clause: Option<Box<Expr>>,
     limit: Option<Box<Limit>>,
+    enforce_foreign_keys: bool,
     syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
@@ -29,6 +31,7 @@ pub fn translate_delete(
         tbl_name,
         where_clause,
         limit,
+        enforce_foreign_keys,
         &mut program.table_reference_counter,
     )?;
     optimize_plan(&mut delete_plan, schema)?;
@@ -50,6 +53,7 @@ pub fn prepare_delete_plan(
     tbl_name: &QualifiedName,
     where_clause: Option<Box<Expr>>,
     limit: Option<Box<Limit>>,
+    enforce_foreign_keys: bool,
     table_ref_counter: &mut TableRefIdCounter,
 ) -> Result<Plan> {
     let table = match schema.get_table(tbl_name.name.0.as_str()) {
@@ -63,7 +67,7 @@ pub fn prepare_delete_plan(
     } else {
         crate::bail_parse_error!("Table is neither a virtual table nor a btree table");
     };
-    let name = tbl_name.name.0.as_str().to_string();
+    let name = normalize_ident(&tbl_name.name.0);
     let indexes = schema.get_indices(table.get_name()).to_vec();
     let joined_tables = vec![JoinedTable {
         table,
@@ -100,6 +104,7 @@ pub fn prepare_delete_plan(
         offset: resolved_offset,
         contains_constant_false_condition: false,
         indexes,
+        enforce_foreign_keys,
     };
 
     Ok(Plan::Delete(plan))
@@ -2,14 +2,19 @@ use core::fmt;
 use std::fmt::{Display, Formatter};
 
 use turso_sqlite3_parser::{
-    ast::{SortOrder, TableInternalId},
+    ast::TableInternalId,
+    dialect::quote_identifier,
     to_sql_string::{ToSqlContext, ToSqlString},
 };
 
-use crate::{schema::Table, translate::plan::TableReferences};
+use crate::{
+    schema::{Index, Table},
+    translate::plan::TableReferences,
+};
 
 use super::plan::{
-    Aggregate, DeletePlan, JoinedTable, Operation, Plan, Search, SelectPlan, UpdatePlan,
+    Aggregate, DeletePlan, Distinctness, JoinedTable, Operation, Plan, Search, SelectPlan,
+    UpdatePlan,
 };
 
 impl Display for Aggregate {
@@ -24,206 +29,158 @@ impl Display for Aggregate {
     }
 }
 
-/// For EXPLAIN QUERY PLAN
-impl Display for Plan {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Select(select_plan) => select_plan.fmt(f),
-            Self::CompoundSelect {
-                left,
-                right_most,
-                limit,
-                offset,
-                order_by,
-            } => {
-                for (plan, operator) in left {
-                    plan.fmt(f)?;
-                    writeln!(f, "{}", operator)?;
-                }
-                right_most.fmt(f)?;
-                if let Some(limit) = limit {
-                    writeln!(f, "LIMIT: {}", limit)?;
-                }
-                if let Some(offset) = offset {
-                    writeln!(f, "OFFSET: {}", offset)?;
-                }
-                if let Some(order_by) = order_by {
-                    writeln!(f, "ORDER BY:")?;
-                    for (expr, dir) in order_by {
-                        writeln!(
-                            f,
-                            "  - {} {}",
-                            expr,
-                            if *dir == SortOrder::Asc {
-                                "ASC"
-                            } else {
-                                "DESC"
-                            }
-                        )?;
-                    }
-                }
-                Ok(())
-            }
-            Self::Delete(delete_plan) => delete_plan.fmt(f),
-            Self::Update(update_plan) => update_plan.fmt(f),
-        }
-    }
+/// A row of EXPLAIN QUERY PLAN output. The rows form a tree: `parent` is the `id` of the
+/// row this one is nested under, or 0 for top-level rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlanRow {
+    pub id: usize,
+    pub parent: usize,
+    pub detail: String,
 }
 
-impl Display for SelectPlan {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        writeln!(f, "QUERY PLAN")?;
-
-        // Print each table reference with appropriate indentation based on join depth
-        for (i, reference) in self.table_references.joined_tables().iter().enumerate() {
-            let is_last = i == self.table_references.joined_tables().len() - 1;
-            let indent = if i == 0 {
-                if is_last { "`--" } else { "|--" }.to_string()
-            } else {
-                format!(
-                    "   {}{}",
-                    "|  ".repeat(i - 1),
-                    if is_last { "`--" } else { "|--" }
-                )
-            };
-
-            match &reference.op {
-                Operation::Scan { .. } => {
-                    let table_name = if reference.table.get_name() == reference.identifier {
-                        reference.identifier.clone()
-                    } else {
-                        format!("{} AS {}", reference.table.get_name(), reference.identifier)
-                    };
-
-                    writeln!(f, "{}SCAN {}", indent, table_name)?;
-                }
-                Operation::Search(search) => match search {
-                    Search::RowidEq { .. } | Search::Seek { index: None, .. } => {
-                        writeln!(
-                            f,
-                            "{}SEARCH {} USING INTEGER PRIMARY KEY (rowid=?)",
-                            indent, reference.identifier
-                        )?;
-                    }
-                    Search::Seek {
-                        index: Some(index), ..
-                    } => {
-                        writeln!(
-                            f,
-                            "{}SEARCH {} USING INDEX {}",
-                            indent, reference.identifier, index.name
-                        )?;
-                    }
-                },
+/// Serializes `plan` into the rows of EXPLAIN QUERY PLAN, in the same shape as SQLite's.
+pub fn query_plan_rows(plan: &Plan) -> Vec<QueryPlanRow> {
+    let mut rows = QueryPlanRows::default();
+    match plan {
+        Plan::Select(select) => rows.select(select, 0),
+        Plan::CompoundSelect {
+            left,
+            right_most,
+            order_by,
+            ..
+        } => {
+            let compound = rows.push(0, "COMPOUND QUERY".to_string());
+            let mut detail = "LEFT-MOST SUBQUERY".to_string();
+            for (select, operator) in left {
+                let id = rows.push(compound, detail);
+                rows.select(select, id);
+                detail = operator.to_string();
+            }
+            let id = rows.push(compound, detail);
+            rows.select(right_most, id);
+            if order_by.is_some() {
+                rows.push(0, "USE TEMP B-TREE FOR ORDER BY".to_string());
             }
         }
-        Ok(())
-    }
-}
-
-impl Display for DeletePlan {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        writeln!(f, "QUERY PLAN")?;
-
-        // Delete plan should only have one table reference
-        if let Some(reference) = self.table_references.joined_tables().first() {
-            let indent = "`--";
-
-            match &reference.op {
-                Operation::Scan { .. } => {
-                    let table_name = if reference.table.get_name() == reference.identifier {
-                        reference.identifier.clone()
-                    } else {
-                        format!("{} AS {}", reference.table.get_name(), reference.identifier)
-                    };
-
-                    writeln!(f, "{}DELETE FROM {}", indent, table_name)?;
-                }
-                Operation::Search { .. } => {
-                    panic!("DELETE plans should not contain search operations");
-                }
+        Plan::Delete(delete) => {
+            for reference in delete.table_references.joined_tables() {
+                rows.table(reference, 0, false);
+            }
+            if delete.order_by.is_some() {
+                rows.push(0, "USE TEMP B-TREE FOR ORDER BY".to_string());
+            }
+        }
+        Plan::Update(update) => {
+            for reference in update.table_references.joined_tables() {
+                rows.table(reference, 0, false);
+            }
+            if update.order_by.is_some() {
+                rows.push(0, "USE TEMP B-TREE FOR ORDER BY".to_string());
             }
         }
-        Ok(())
     }
+    rows.0
 }
 
-impl fmt::Display for UpdatePlan {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "QUERY PLAN")?;
+#[derive(Default)]
+struct QueryPlanRows(Vec<QueryPlanRow>);
 
-        for (i, reference) in self.table_references.joined_tables().iter().enumerate() {
-            let is_last = i == self.table_references.joined_tables().len() - 1;
-            let indent = if i == 0 {
-                if is_last { "`--" } else { "|--" }.to_string()
-            } else {
-                format!(
-                    "   {}{}",
-                    "|  ".repeat(i - 1),
-                    if is_last { "`--" } else { "|--" }
-                )
-            };
-
-            match &reference.op {
-                Operation::Scan { .. } => {
-                    let table_name = if reference.table.get_name() == reference.identifier {
-                        reference.identifier.clone()
-                    } else {
-                        format!("{} AS {}", reference.table.get_name(), reference.identifier)
-                    };
+impl QueryPlanRows {
+    fn push(&mut self, parent: usize, detail: String) -> usize {
+        let id = self.0.len() + 1;
+        self.0.push(QueryPlanRow { id, parent, detail });
+        id
+    }
 
-                    if i == 0 {
-                        writeln!(f, "{}UPDATE {}", indent, table_name)?;
-                    } else {
-                        writeln!(f, "{}SCAN {}", indent, table_name)?;
-                    }
-                }
-                Operation::Search(search) => match search {
-                    Search::RowidEq { .. } | Search::Seek { index: None, .. } => {
-                        writeln!(
-                            f,
-                            "{}SEARCH {} USING INTEGER PRIMARY KEY (rowid=?)",
-                            indent, reference.identifier
-                        )?;
-                    }
-                    Search::Seek {
-                        index: Some(index), ..
-                    } => {
-                        writeln!(
-                            f,
-                            "{}SEARCH {} USING INDEX {}",
-                            indent, reference.identifier, index.name
-                        )?;
-                    }
-                },
+    fn select(&mut self, plan: &SelectPlan, parent: usize) {
+        let tables = plan.joined_tables();
+        if tables.is_empty() {
+            let detail = match plan.values.len() {
+                0 | 1 => "SCAN CONSTANT ROW".to_string(),
+                n => format!("SCAN {} CONSTANT ROWS", n),
+            };
+            self.push(parent, detail);
+        } else if plan.join_order.is_empty() {
+            for reference in tables {
+                self.table(reference, parent, true);
             }
-        }
-        if let Some(order_by) = &self.order_by {
-            writeln!(f, "ORDER BY:")?;
-            for (expr, dir) in order_by {
-                writeln!(
-                    f,
-                    "  - {} {}",
-                    expr,
-                    if *dir == SortOrder::Asc {
-                        "ASC"
-                    } else {
-                        "DESC"
-                    }
-                )?;
+        } else {
+            for member in &plan.join_order {
+                self.table(&tables[member.original_idx], parent, true);
             }
         }
-        if let Some(limit) = self.limit {
-            writeln!(f, "LIMIT: {}", limit)?;
+        if plan
+            .group_by
+            .as_ref()
+            .is_some_and(|group_by| group_by.sort_order.is_some())
+        {
+            self.push(parent, "USE TEMP B-TREE FOR GROUP BY".to_string());
         }
-        if let Some(ret) = &self.returning {
-            writeln!(f, "RETURNING:")?;
-            for col in ret {
-                writeln!(f, "  - {}", col.expr)?;
-            }
+        if let Distinctness::Distinct { .. } = plan.distinctness {
+            self.push(parent, "USE TEMP B-TREE FOR DISTINCT".to_string());
+        }
+        if plan.order_by.is_some() {
+            self.push(parent, "USE TEMP B-TREE FOR ORDER BY".to_string());
         }
+    }
 
-        Ok(())
+    /// Adds the row describing how `reference` is read. Covering indexes are only reported
+    /// for SELECT, the only statement that reads from them without the table.
+    fn table(&mut self, reference: &JoinedTable, parent: usize, select: bool) {
+        let name = &reference.identifier;
+        let detail = match (&reference.table, &reference.op) {
+            (Table::FromClauseSubquery(subquery), _) => {
+                let id = self.push(parent, format!("CO-ROUTINE {}", name));
+                self.select(&subquery.plan, id);
+                format!("SCAN {}", name)
+            }
+            (Table::Virtual(_), _) => format!("SCAN {} VIRTUAL TABLE", name),
+            (_, Operation::Scan { index: None, .. }) => format!("SCAN {}", name),
+            (
+                _,
+                Operation::Scan {
+                    index: Some(index), ..
+                },
+            ) => format!(
+                "SCAN {} USING {}",
+                name,
+                index_description(reference, index, select)
+            ),
+            (_, Operation::Search(Search::RowidEq { .. }))
+            | (_, Operation::Search(Search::Seek { index: None, .. })) => {
+                format!("SEARCH {} USING INTEGER PRIMARY KEY (rowid=?)", name)
+            }
+            (
+                _,
+                Operation::Search(Search::Seek {
+                    index: Some(index), ..
+                }),
+            ) => format!(
+                "SEARCH {} USING {}",
+                name,
+                index_description(reference, index, select)
+            ),
+            (_, Operation::Search(Search::HashJoin { index, probe_keys })) => {
+                let keys = index
+                    .columns
+                    .iter()
+                    .take(probe_keys.len())
+                    .map(|col| format!("{}=?", col.name))
+                    .collect::<Vec<_>>();
+                format!("SEARCH {} USING HASH JOIN ({})", name, keys.join(" AND "))
+            }
+        };
+        self.push(parent, detail);
+    }
+}
+
+fn index_description(reference: &JoinedTable, index: &Index, select: bool) -> String {
+    if index.ephemeral {
+        "AUTOMATIC INDEX".to_string()
+    } else if select && reference.utilizes_covering_index() {
+        format!("COVERING INDEX {}", index.name)
+    } else {
+        format!("INDEX {}", index.name)
     }
 }
 
@@ -321,7 +278,9 @@ impl ToSqlString for JoinedTable {
     ) -> String {
         let table_or_subquery =
             match &self.table {
-                Table::BTree(..) | Table::Virtual(..) => self.table.get_name().to_string(),
+                Table::BTree(..) | Table::Virtual(..) => {
+                    quote_identifier(self.table.get_name()).into_owned()
+                }
                 Table::FromClauseSubquery(from_clause_subquery) => {
                     // Could possibly merge the contexts together here
                     format!(
@@ -336,8 +295,10 @@ impl ToSqlString for JoinedTable {
         format!(
             "{}{}",
             table_or_subquery,
-            if self.identifier != table_or_subquery {
-                format!(" AS {}", self.identifier)
+            if matches!(self.table, Table::FromClauseSubquery(..))
+                || self.identifier != self.table.get_name()
+            {
+                format!(" AS {}", quote_identifier(&self.identifier))
             } else {
                 "".to_string()
             }
@@ -1,32 +1,45 @@
 // This module contains code for emitting bytecode instructions for SQL query execution.
 // It handles translating high-level SQL operations into low-level bytecode that can be executed by the virtual machine.
 
+use std::num::NonZeroUsize;
 use std::rc::Rc;
 
 use tracing::{instrument, Level};
 use turso_sqlite3_parser::ast::{self, Expr};
 
 use super::aggregation::emit_ungrouped_aggregation;
-use super::expr::translate_expr;
+use super::check::emit_check_constraints;
+use super::expr::{translate_expr, walk_expr, WalkControl};
+use super::fkey::{emit_fk_child_checks, emit_fk_parent_actions, RowRegisters};
+use super::generated::{
+    emit_generated_columns, emit_table_record, with_dependent_generated_columns,
+};
 use super::group_by::{
     group_by_agg_phase, group_by_emit_row_phase, init_group_by, GroupByMetadata, GroupByRowSource,
 };
+use super::index::{
+    emit_index_key, emit_index_key_for_cursor, emit_partial_index_check,
+    emit_partial_index_check_for_cursor,
+};
 use super::main_loop::{
-    close_loop, emit_loop, init_distinct, init_loop, open_loop, LeftJoinMetadata, LoopLabels,
+    close_loop, emit_full_join_unmatched_rows, emit_loop, init_distinct, init_loop, open_loop,
+    LeftJoinMetadata, LoopLabels,
 };
+use super::optimizer::Optimizable;
 use super::order_by::{emit_order_by, init_order_by, SortMetadata};
 use super::plan::{
     Distinctness, JoinOrderMember, Operation, SelectPlan, TableReferences, UpdatePlan,
 };
 use super::select::emit_simple_count;
 use super::subquery::emit_subqueries;
+use super::window::{emit_window, init_window, WindowMetadata};
 use crate::error::SQLITE_CONSTRAINT_PRIMARYKEY;
 use crate::function::Func;
 use crate::schema::Schema;
 use crate::translate::compound_select::emit_program_for_compound_select;
 use crate::translate::plan::{DeletePlan, Plan, QueryDestination, Search};
 use crate::translate::values::emit_values;
-use crate::util::exprs_are_equivalent;
+use crate::util::{exprs_are_equivalent, normalize_ident};
 use crate::vdbe::builder::{CursorKey, CursorType, ProgramBuilder};
 use crate::vdbe::insn::{CmpInsFlags, IdxInsertFlags, InsertFlags, RegisterOrLiteral};
 use crate::vdbe::CursorID;
@@ -149,6 +162,8 @@ pub struct TranslateCtx<'a> {
     /// - First: all `GROUP BY` expressions, in the order they appear in the `GROUP BY` clause.
     /// - Then: remaining non-aggregate expressions that are not part of `GROUP BY`.
     pub non_aggregate_expressions: Vec<(&'a Expr, bool)>,
+    // metadata for the window functions
+    pub meta_window: Option<WindowMetadata>,
 }
 
 impl<'a> TranslateCtx<'a> {
@@ -175,6 +190,7 @@ impl<'a> TranslateCtx<'a> {
             result_columns_to_skip_in_orderby_sorter: None,
             resolver: Resolver::new(schema, syms),
             non_aggregate_expressions: Vec::new(),
+            meta_window: None,
         }
     }
 }
@@ -255,6 +271,109 @@ fn emit_program_for_select(
     Ok(())
 }
 
+/// Returns whether `expr` is a scalar function call that is worth compiling as a subroutine
+/// when it occurs more than once.
+fn is_subroutine_candidate(expr: &Expr, resolver: &Resolver) -> bool {
+    let Expr::FunctionCall {
+        name,
+        args: Some(args),
+        filter_over: None,
+        ..
+    } = expr
+    else {
+        return false;
+    };
+    if args.is_empty() || expr.is_constant(resolver) {
+        return false;
+    }
+    match resolver.resolve_function(&normalize_ident(&name.0), args.len()) {
+        Some(Func::Agg(_) | Func::External(_)) | None => false,
+        Some(_) => !args.iter().any(|arg| contains_aggregate(arg, resolver)),
+    }
+}
+
+fn is_aggregate(expr: &Expr, resolver: &Resolver) -> bool {
+    match expr {
+        Expr::FunctionCall {
+            name,
+            args,
+            filter_over,
+            ..
+        } => {
+            filter_over.is_some()
+                || matches!(
+                    resolver.resolve_function(
+                        &normalize_ident(&name.0),
+                        args.as_ref().map_or(0, |args| args.len())
+                    ),
+                    Some(Func::Agg(_))
+                )
+        }
+        Expr::FunctionCallStar { name, filter_over } => {
+            filter_over.is_some()
+                || matches!(
+                    resolver.resolve_function(&normalize_ident(&name.0), 0),
+                    Some(Func::Agg(_))
+                )
+        }
+        _ => false,
+    }
+}
+
+fn contains_aggregate(expr: &Expr, resolver: &Resolver) -> bool {
+    let mut found = false;
+    let _ = walk_expr(expr, &mut |e: &Expr| {
+        if is_aggregate(e, resolver) {
+            found = true;
+            return Ok(WalkControl::SkipChildren);
+        }
+        Ok(WalkControl::Continue)
+    });
+    found
+}
+
+/// Finds the function calls that are evaluated more than once per row of the main loop of
+/// `plan`, e.g. `f(x)` in `WHERE f(x) > 0 AND f(x) < 100`, so that they can be compiled once
+/// as subroutines. Aggregates are skipped, since their arguments are visited separately.
+fn repeated_subexpressions(plan: &SelectPlan, resolver: &Resolver) -> Vec<Expr> {
+    let mut exprs: Vec<&Expr> = plan.where_clause.iter().map(|term| &term.expr).collect();
+    exprs.extend(plan.aggregates.iter().flat_map(|agg| agg.args.iter()));
+    if let Some(group_by) = &plan.group_by {
+        exprs.extend(group_by.exprs.iter());
+    } else if plan.window.is_none() {
+        // Without GROUP BY or window functions, the result columns and ORDER BY keys are
+        // evaluated in the main loop too.
+        exprs.extend(plan.result_columns.iter().map(|col| &col.expr));
+        if let Some(order_by) = &plan.order_by {
+            exprs.extend(order_by.iter().map(|(expr, _)| expr));
+        }
+    }
+
+    let mut candidates: Vec<(&Expr, usize)> = Vec::new();
+    for expr in exprs {
+        let _ = walk_expr(expr, &mut |e: &Expr| {
+            if is_aggregate(e, resolver) {
+                return Ok(WalkControl::SkipChildren);
+            }
+            if is_subroutine_candidate(e, resolver) {
+                match candidates
+                    .iter_mut()
+                    .find(|(candidate, _)| exprs_are_equivalent(candidate, e))
+                {
+                    Some((_, count)) => *count += 1,
+                    None => candidates.push((e, 1)),
+                }
+            }
+            Ok(WalkControl::Continue)
+        });
+    }
+    candidates
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(expr, _)| expr.clone())
+        .collect()
+}
+
 #[instrument(skip_all, level = Level::TRACE)]
 pub fn emit_query<'a>(
     program: &mut ProgramBuilder,
@@ -319,6 +438,10 @@ pub fn emit_query<'a>(
         t_ctx.reg_agg_start = Some(program.alloc_registers_and_init_w_null(plan.aggregates.len()));
     }
 
+    if let Some(ref window) = plan.window {
+        init_window(program, t_ctx, window, &plan.table_references)?;
+    }
+
     let distinct_ctx = if let Distinctness::Distinct { .. } = &plan.distinctness {
         Some(init_distinct(program, plan))
     } else {
@@ -342,6 +465,9 @@ pub fn emit_query<'a>(
         return Ok(t_ctx.reg_result_cols_start.unwrap());
     }
 
+    // Expressions repeated within the main loop are compiled once as subroutines
+    program.set_expr_subroutines(repeated_subexpressions(plan, &t_ctx.resolver));
+
     // Set up main query execution loop
     open_loop(
         program,
@@ -364,6 +490,11 @@ pub fn emit_query<'a>(
         None,
     )?;
 
+    program.clear_expr_subroutines();
+
+    // Emit the rows of a FULL OUTER JOIN's right table that matched no row of the left tables
+    emit_full_join_unmatched_rows(program, t_ctx, &plan.table_references, &plan.join_order)?;
+
     program.preassign_label_to_next_insn(after_main_loop_label);
 
     let mut order_by_necessary = plan.order_by.is_some() && !plan.contains_constant_false_condition;
@@ -387,6 +518,11 @@ pub fn emit_query<'a>(
         order_by_necessary = false;
     }
 
+    // Compute the window functions, which sends the rows on to ORDER BY or the result
+    if plan.window.is_some() {
+        emit_window(program, t_ctx, plan)?;
+    }
+
     // Process ORDER BY results if needed
     if order_by.is_some() && order_by_necessary {
         emit_order_by(program, t_ctx, plan)?;
@@ -450,7 +586,12 @@ fn emit_program_for_delete(
         None,
     )?;
 
-    emit_delete_insns(program, &mut t_ctx, &plan.table_references)?;
+    emit_delete_insns(
+        program,
+        &mut t_ctx,
+        &plan.table_references,
+        plan.enforce_foreign_keys,
+    )?;
 
     // Clean up and close the main execution loop
     close_loop(
@@ -473,6 +614,7 @@ fn emit_delete_insns(
     program: &mut ProgramBuilder,
     t_ctx: &mut TranslateCtx,
     table_references: &TableReferences,
+    enforce_foreign_keys: bool,
 ) -> Result<()> {
     let table_reference = table_references.joined_tables().first().unwrap();
     let cursor_id = match &table_reference.op {
@@ -489,6 +631,7 @@ fn emit_delete_insns(
                 table_reference.internal_id,
                 index.clone(),
             )),
+            Search::HashJoin { .. } => unreachable!("DELETE does not use hash joins"),
         },
     };
     let main_table_cursor_id =
@@ -496,10 +639,21 @@ fn emit_delete_insns(
 
     // Emit the instructions to delete the row
     let key_reg = program.alloc_register();
-    program.emit_insn(Insn::RowId {
-        cursor_id: main_table_cursor_id,
-        dest: key_reg,
-    });
+    if table_reference
+        .btree()
+        .is_some_and(|table| !table.has_rowid)
+    {
+        // A WITHOUT ROWID table has no rowid, the cursor is positioned on the row by its key.
+        program.emit_insn(Insn::Null {
+            dest: key_reg,
+            dest_end: None,
+        });
+    } else {
+        program.emit_insn(Insn::RowId {
+            cursor_id: main_table_cursor_id,
+            dest: key_reg,
+        });
+    }
 
     if table_reference.virtual_table().is_some() {
         let conflict_action = 0u16;
@@ -517,6 +671,22 @@ fn emit_delete_insns(
             conflict_action,
         });
     } else {
+        if enforce_foreign_keys {
+            let btree_table = table_reference
+                .btree()
+                .expect("DELETE target is a btree table");
+            emit_fk_parent_actions(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                main_table_cursor_id,
+                key_reg,
+                None,
+                None,
+                &mut vec![btree_table.name.clone()],
+            )?;
+        }
+
         // Delete from all indexes before deleting from the main table.
         let indexes = t_ctx
             .resolver
@@ -539,21 +709,33 @@ fn emit_delete_insns(
         });
 
         if let Some(index_refs) = index_refs_opt {
+            let btree_table = table_reference
+                .btree()
+                .expect("DELETE target is a btree table");
             for (index, index_cursor_id) in index_refs {
+                // A partial index only holds the rows that satisfy its WHERE clause.
+                let label_skip_index = program.allocate_label();
+                emit_partial_index_check_for_cursor(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    &index,
+                    main_table_cursor_id,
+                    key_reg,
+                    label_skip_index,
+                )?;
                 let num_regs = index.columns.len() + 1;
                 let start_reg = program.alloc_registers(num_regs);
                 // Emit columns that are part of the index
-                index
-                    .columns
-                    .iter()
-                    .enumerate()
-                    .for_each(|(reg_offset, column_index)| {
-                        program.emit_column(
-                            main_table_cursor_id,
-                            column_index.pos_in_table,
-                            start_reg + reg_offset,
-                        );
-                    });
+                emit_index_key_for_cursor(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    &index,
+                    main_table_cursor_id,
+                    key_reg,
+                    start_reg,
+                )?;
                 program.emit_insn(Insn::RowId {
                     cursor_id: main_table_cursor_id,
                     dest: start_reg + num_regs - 1,
@@ -563,11 +745,13 @@ fn emit_delete_insns(
                     num_regs,
                     cursor_id: index_cursor_id,
                 });
+                program.preassign_label_to_next_insn(label_skip_index);
             }
         }
 
         program.emit_insn(Insn::Delete {
             cursor_id: main_table_cursor_id,
+            table_name: table_reference.table.get_name().to_string(),
         });
     }
     if let Some(limit_ctx) = t_ctx.limit_ctx {
@@ -733,6 +917,7 @@ fn emit_update_insns(
                 )),
                 false,
             ),
+            Search::HashJoin { .. } => unreachable!("UPDATE does not use hash joins"),
         },
     };
 
@@ -883,7 +1068,57 @@ fn emit_update_insns(
         }
     }
 
+    if let Some(btree_table) = table_ref.btree() {
+        if !btree_table.is_strict {
+            program.emit_insn(Insn::Affinity {
+                start_reg: start,
+                count: NonZeroUsize::new(table_ref.columns().len()).unwrap(),
+                affinities: btree_table.column_affinities(),
+            });
+        }
+        emit_generated_columns(
+            program,
+            &t_ctx.resolver,
+            &btree_table,
+            RowRegisters {
+                rowid: rowid_set_clause_reg.unwrap_or(beg),
+                columns_start: start,
+            },
+        )?;
+        if plan.enforce_check_constraints {
+            let changed_columns = with_dependent_generated_columns(
+                &btree_table,
+                plan.set_clauses.iter().map(|(idx, _)| *idx).collect(),
+            )?;
+            emit_check_constraints(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                RowRegisters {
+                    rowid: rowid_set_clause_reg.unwrap_or(beg),
+                    columns_start: start,
+                },
+                Some(&changed_columns),
+            )?;
+        }
+    }
+
     for (index, (idx_cursor_id, record_reg)) in plan.indexes_to_update.iter().zip(&index_cursors) {
+        // A partial index only holds the rows that satisfy its WHERE clause.
+        let label_skip_index = program.allocate_label();
+        let btree_table = table_ref.btree().expect("UPDATE target is a btree table");
+        emit_partial_index_check(
+            program,
+            &t_ctx.resolver,
+            &btree_table,
+            index,
+            RowRegisters {
+                rowid: rowid_set_clause_reg.unwrap_or(beg),
+                columns_start: start,
+            },
+            label_skip_index,
+        )?;
+
         let num_cols = index.columns.len();
         // allocate scratch registers for the index columns plus rowid
         let idx_start_reg = program.alloc_registers(num_cols + 1);
@@ -898,18 +1133,20 @@ fn emit_update_insns(
         } else {
             beg
         };
-        let idx_cols_start_reg = beg + 1;
 
-        // copy each index column from the table's column registers into these scratch regs
-        for (i, col) in index.columns.iter().enumerate() {
-            // copy from the table's column register over to the index's scratch register
-
-            program.emit_insn(Insn::Copy {
-                src_reg: idx_cols_start_reg + col.pos_in_table,
-                dst_reg: idx_start_reg + i,
-                amount: 0,
-            });
-        }
+        // copy each index column from the table's column registers into these scratch regs,
+        // evaluating the indexed expressions
+        emit_index_key(
+            program,
+            &t_ctx.resolver,
+            &btree_table,
+            index,
+            RowRegisters {
+                rowid: rowid_set_clause_reg.unwrap_or(beg),
+                columns_start: start,
+            },
+            idx_start_reg,
+        )?;
         // last register is the rowid
         program.emit_insn(Insn::Copy {
             src_reg: rowid_reg,
@@ -926,6 +1163,7 @@ fn emit_update_insns(
         });
 
         if !index.unique {
+            program.preassign_label_to_next_insn(label_skip_index);
             continue;
         }
 
@@ -938,19 +1176,24 @@ fn emit_update_insns(
             num_regs: num_cols,
         });
 
-        let column_names = index.columns.iter().enumerate().fold(
-            String::with_capacity(50),
-            |mut accum, (idx, col)| {
-                if idx > 0 {
-                    accum.push_str(", ");
-                }
-                accum.push_str(table_ref.table.get_name());
-                accum.push('.');
-                accum.push_str(&col.name);
-
-                accum
-            },
-        );
+        // Like SQLite, a unique index on expressions is reported by name.
+        let column_names = if index.has_expressions() {
+            format!("index '{}'", index.name)
+        } else {
+            index.columns.iter().enumerate().fold(
+                String::with_capacity(50),
+                |mut accum, (idx, col)| {
+                    if idx > 0 {
+                        accum.push_str(", ");
+                    }
+                    accum.push_str(table_ref.table.get_name());
+                    accum.push('.');
+                    accum.push_str(&col.name);
+
+                    accum
+                },
+            )
+        };
 
         let idx_rowid_reg = program.alloc_register();
         program.emit_insn(Insn::IdxRowId {
@@ -972,6 +1215,7 @@ fn emit_update_insns(
         });
 
         program.preassign_label_to_next_insn(constraint_check);
+        program.preassign_label_to_next_insn(label_skip_index);
     }
 
     if let Some(btree_table) = table_ref.btree() {
@@ -984,6 +1228,27 @@ fn emit_update_insns(
             });
         }
 
+        let changed_columns = with_dependent_generated_columns(
+            &btree_table,
+            plan.set_clauses.iter().map(|(idx, _)| *idx).collect(),
+        )?;
+        let new_row = RowRegisters {
+            rowid: rowid_set_clause_reg.unwrap_or(beg),
+            columns_start: start,
+        };
+        if plan.enforce_foreign_keys {
+            emit_fk_parent_actions(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                cursor_id,
+                beg,
+                Some(new_row),
+                Some(&changed_columns),
+                &mut vec![btree_table.name.clone()],
+            )?;
+        }
+
         if has_user_provided_rowid {
             let record_label = program.allocate_label();
             let idx = rowid_alias_index.unwrap();
@@ -1021,12 +1286,7 @@ fn emit_update_insns(
         }
 
         let record_reg = program.alloc_register();
-        program.emit_insn(Insn::MakeRecord {
-            start_reg: start,
-            count: table_ref.columns().len(),
-            dest_reg: record_reg,
-            index_name: None,
-        });
+        emit_table_record(program, &btree_table, new_row, record_reg);
 
         if has_user_provided_rowid {
             program.emit_insn(Insn::NotExists {
@@ -1042,18 +1302,26 @@ fn emit_update_insns(
             let num_regs = index.columns.len() + 1;
             let start_reg = program.alloc_registers(num_regs);
 
-            // Delete existing index key
-            index
-                .columns
-                .iter()
-                .enumerate()
-                .for_each(|(reg_offset, column_index)| {
-                    program.emit_column(
-                        cursor_id,
-                        column_index.pos_in_table,
-                        start_reg + reg_offset,
-                    );
-                });
+            // Delete existing index key, if the old row is in the index.
+            let label_skip_delete = program.allocate_label();
+            emit_partial_index_check_for_cursor(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                index,
+                cursor_id,
+                beg,
+                label_skip_delete,
+            )?;
+            emit_index_key_for_cursor(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                index,
+                cursor_id,
+                beg,
+                start_reg,
+            )?;
 
             program.emit_insn(Insn::RowId {
                 cursor_id,
@@ -1065,8 +1333,19 @@ fn emit_update_insns(
                 num_regs,
                 cursor_id: idx_cursor_id,
             });
-
-            // Insert new index key (filled further above with values from set_clauses)
+            program.preassign_label_to_next_insn(label_skip_delete);
+
+            // Insert new index key (filled further above with values from set_clauses), if the
+            // new row is in the index.
+            let label_skip_insert = program.allocate_label();
+            emit_partial_index_check(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                index,
+                new_row,
+                label_skip_insert,
+            )?;
             program.emit_insn(Insn::IdxInsert {
                 cursor_id: idx_cursor_id,
                 record_reg,
@@ -1074,13 +1353,17 @@ fn emit_update_insns(
                 unpacked_count: Some((index.columns.len() + 1) as u16),
                 flags: IdxInsertFlags::new(),
             });
+            program.preassign_label_to_next_insn(label_skip_insert);
         }
 
         // If we are updating the rowid, we cannot rely on overwrite on the
         // Insert instruction to update the cell. We need to first delete the current cell
         // and later insert the updated record
         if has_user_provided_rowid {
-            program.emit_insn(Insn::Delete { cursor_id });
+            program.emit_insn(Insn::Delete {
+                cursor_id,
+                table_name: String::new(),
+            });
         }
 
         program.emit_insn(Insn::Insert {
@@ -1090,6 +1373,16 @@ fn emit_update_insns(
             flag: InsertFlags::new().update(true),
             table_name: table_ref.identifier.clone(),
         });
+
+        if plan.enforce_foreign_keys {
+            emit_fk_child_checks(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                new_row,
+                Some(&changed_columns),
+            )?;
+        }
     } else if table_ref.virtual_table().is_some() {
         let arg_count = table_ref.columns().len() + 2;
         program.emit_insn(Insn::VUpdate {
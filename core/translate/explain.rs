@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, Delete};
+
+use crate::schema::Schema;
+use crate::translate::delete::prepare_delete_plan;
+use crate::translate::display::query_plan_rows;
+use crate::translate::emitter::TransactionMode;
+use crate::translate::optimizer::optimize_plan;
+use crate::translate::plan::QueryDestination;
+use crate::translate::select::prepare_select_plan;
+use crate::translate::update::prepare_update_plan;
+use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
+use crate::vdbe::insn::Insn;
+use crate::{Connection, Result, SymbolTable};
+
+/// Translates EXPLAIN QUERY PLAN into a program returning one row per node of the plan
+/// of `stmt`, with the same columns as SQLite: `id`, `parent`, `notused` and `detail`.
+/// The statement itself is planned but not run. Statements without a query plan, such
+/// as CREATE TABLE, return no rows.
+pub fn translate_explain_query_plan(
+    schema: &Schema,
+    stmt: ast::Stmt,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let plan = match stmt {
+        ast::Stmt::Select(select) => Some(prepare_select_plan(
+            schema,
+            *select,
+            syms,
+            &[],
+            &mut program.table_reference_counter,
+            QueryDestination::ResultRows,
+        )?),
+        ast::Stmt::Delete(delete) => {
+            let Delete {
+                tbl_name,
+                where_clause,
+                limit,
+                ..
+            } = *delete;
+            Some(prepare_delete_plan(
+                schema,
+                &tbl_name,
+                where_clause,
+                limit,
+                connection.foreign_keys_enabled(),
+                &mut program.table_reference_counter,
+            )?)
+        }
+        ast::Stmt::Update(mut update) => Some(prepare_update_plan(
+            &mut program,
+            schema,
+            &mut update,
+            connection.foreign_keys_enabled(),
+            !connection.ignore_check_constraints(),
+        )?),
+        _ => None,
+    };
+    let rows = match plan {
+        Some(mut plan) => {
+            optimize_plan(&mut plan, schema)?;
+            query_plan_rows(&plan)
+        }
+        None => Vec::new(),
+    };
+
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 0,
+        approx_num_insns: rows.len() * 5 + 2,
+        approx_num_labels: 0,
+    });
+    for name in ["id", "parent", "notused", "detail"] {
+        program.add_pragma_result_column(name.to_string());
+    }
+    let start_reg = program.alloc_registers(4);
+    for row in rows {
+        program.emit_int(row.id as i64, start_reg);
+        program.emit_int(row.parent as i64, start_reg + 1);
+        program.emit_int(0, start_reg + 2);
+        program.emit_string8(row.detail, start_reg + 3);
+        program.emit_insn(Insn::ResultRow {
+            start_reg,
+            count: 4,
+        });
+    }
+    program.epilogue(TransactionMode::None);
+    Ok(program)
+}
@@ -2,6 +2,7 @@ use tracing::{instrument, Level};
 use turso_sqlite3_parser::ast::{self, Expr, UnaryOperator};
 
 use super::emitter::Resolver;
+use super::generated::{bind_generated_column, emit_generated_column_affinity};
 use super::optimizer::Optimizable;
 use super::plan::TableReferences;
 #[cfg(feature = "json")]
@@ -10,7 +11,7 @@ use crate::function::{Func, FuncCtx, MathFuncArity, ScalarFunc, VectorFunc};
 use crate::functions::datetime;
 use crate::schema::{Affinity, Table, Type};
 use crate::util::{exprs_are_equivalent, normalize_ident, parse_numeric_literal};
-use crate::vdbe::builder::CursorKey;
+use crate::vdbe::builder::{CursorKey, ExprSubroutineState};
 use crate::vdbe::{
     builder::ProgramBuilder,
     insn::{CmpInsFlags, Insn},
@@ -466,13 +467,31 @@ pub fn translate_expr(
         return Ok(target_register);
     }
 
+    // The expression cache maps expressions to registers of e.g. a GROUP BY sorter, which a
+    // subroutine emitted before the cache was enabled would not read from.
+    if !resolver.expr_to_reg_cache_enabled {
+        if let Some(idx) = program.resolve_expr_subroutine(expr) {
+            emit_expr_subroutine_call(
+                program,
+                referenced_tables,
+                idx,
+                expr,
+                target_register,
+                resolver,
+            )?;
+            return Ok(target_register);
+        }
+    }
+
     match expr {
         ast::Expr::Between { .. } => {
             unreachable!("expression should have been rewritten in optmizer")
         }
         ast::Expr::Binary(e1, op, e2) => {
-            // Check if both sides of the expression are equivalent and reuse the same register if so
-            if exprs_are_equivalent(e1, e2) {
+            if emit_folded_constant(program, expr, target_register) {
+                Ok(target_register)
+            } else if exprs_are_equivalent(e1, e2) {
+                // Both sides of the expression are equivalent, so reuse the same register
                 let shared_reg = program.alloc_register();
                 translate_expr(program, referenced_tables, e1, shared_reg, resolver)?;
 
@@ -1189,15 +1208,17 @@ pub fn translate_expr(
                         }
                         #[cfg(feature = "fs")]
                         ScalarFunc::LoadExtension => {
-                            let args = expect_arguments_exact!(args, 1, srf);
-                            let start_reg = program.alloc_register();
-                            translate_expr(
-                                program,
-                                referenced_tables,
-                                &args[0],
-                                start_reg,
-                                resolver,
-                            )?;
+                            let args = expect_arguments_max!(args, 2, srf);
+                            let start_reg = program.alloc_registers(args.len());
+                            for (i, arg) in args.iter().enumerate() {
+                                translate_expr(
+                                    program,
+                                    referenced_tables,
+                                    arg,
+                                    start_reg + i,
+                                    resolver,
+                                )?;
+                            }
                             program.emit_insn(Insn::Function {
                                 constant_mask: 0,
                                 start_reg,
@@ -1583,6 +1604,47 @@ pub fn translate_expr(
                             });
                             Ok(target_register)
                         }
+                        #[cfg(feature = "offset_sql_func")]
+                        ScalarFunc::SqliteOffset => {
+                            let args = expect_arguments_exact!(args, 1, srf);
+                            // Like SQLite, anything but a column of a b-tree table evaluates to NULL.
+                            let cursor_id =
+                                match &args[0] {
+                                    ast::Expr::Column {
+                                        table: table_ref_id,
+                                        ..
+                                    } => referenced_tables.and_then(|tables| {
+                                        let table_reference = tables
+                                            .find_joined_table_by_internal_id(*table_ref_id)?;
+                                        table_reference.table.btree()?;
+                                        match table_reference.op.index() {
+                                            Some(index)
+                                                if table_reference.utilizes_covering_index() =>
+                                            {
+                                                Some(program.resolve_cursor_id(&CursorKey::index(
+                                                    *table_ref_id,
+                                                    index.clone(),
+                                                )))
+                                            }
+                                            _ => Some(program.resolve_cursor_id(
+                                                &CursorKey::table(*table_ref_id),
+                                            )),
+                                        }
+                                    }),
+                                    _ => None,
+                                };
+                            match cursor_id {
+                                Some(cursor_id) => program.emit_insn(Insn::Offset {
+                                    cursor_id,
+                                    dest: target_register,
+                                }),
+                                None => program.emit_insn(Insn::Null {
+                                    dest: target_register,
+                                    dest_end: None,
+                                }),
+                            }
+                            Ok(target_register)
+                        }
                         ScalarFunc::Replace => {
                             let args = if let Some(args) = args {
                                 if !args.len() == 3 {
@@ -1740,6 +1802,27 @@ pub fn translate_expr(
 
                             Ok(target_register)
                         }
+                        #[cfg(feature = "regexp")]
+                        ScalarFunc::Regexp => {
+                            let args = expect_arguments_exact!(args, 2, srf);
+                            let start_reg = program.alloc_registers(2);
+                            for (i, arg) in args.iter().enumerate() {
+                                translate_expr(
+                                    program,
+                                    referenced_tables,
+                                    arg,
+                                    start_reg + i,
+                                    resolver,
+                                )?;
+                            }
+                            program.emit_insn(Insn::Function {
+                                constant_mask: matches!(args[0], ast::Expr::Literal(_)) as i32,
+                                start_reg,
+                                dest: target_register,
+                                func: func_ctx,
+                            });
+                            Ok(target_register)
+                        }
                     }
                 }
                 Func::Math(math_func) => match math_func.arity() {
@@ -1856,6 +1939,16 @@ pub fn translate_expr(
             // the table and read the column from the cursor.
             // If we have a covering index, we don't have an open table cursor so we read from the index cursor.
             match &table {
+                Table::BTree(btree) if table_column.is_virtual() => {
+                    // VIRTUAL generated columns are not stored; compute them from the columns
+                    // they refer to.
+                    let expr = bind_generated_column(btree, *column, *table_ref_id)?;
+                    translate_expr(program, referenced_tables, &expr, target_register, resolver)?;
+                    emit_generated_column_affinity(program, btree, *column, target_register);
+                    program
+                        .set_collation(Some((table_column.collation.unwrap_or_default(), false)));
+                    Ok(target_register)
+                }
                 Table::BTree(_) => {
                     let table_cursor_id = if use_covering_index {
                         None
@@ -2224,6 +2317,121 @@ pub fn translate_expr(
     Ok(target_register)
 }
 
+/// Emits a call to the subroutine `idx` that computes `expr`, and copies its result into
+/// `target_register`. The body of the subroutine is emitted, jumped over, at the first call.
+fn emit_expr_subroutine_call(
+    program: &mut ProgramBuilder,
+    referenced_tables: Option<&TableReferences>,
+    idx: usize,
+    expr: &ast::Expr,
+    target_register: usize,
+    resolver: &Resolver,
+) -> Result<()> {
+    if let ExprSubroutineState::NotEmitted = program.expr_subroutine_state(idx) {
+        let label_start = program.allocate_label();
+        let label_end = program.allocate_label();
+        let reg_return = program.alloc_register();
+        let reg_result = program.alloc_register();
+        program.emit_insn(Insn::Goto {
+            target_pc: label_end,
+        });
+        program.preassign_label_to_next_insn(label_start);
+        program.set_expr_subroutine_state(idx, ExprSubroutineState::Emitting);
+        translate_expr(program, referenced_tables, expr, reg_result, resolver)?;
+        program.emit_insn(Insn::Return {
+            return_reg: reg_return,
+            can_fallthrough: false,
+        });
+        program.preassign_label_to_next_insn(label_end);
+        program.set_expr_subroutine_state(
+            idx,
+            ExprSubroutineState::Emitted {
+                label_start,
+                reg_return,
+                reg_result,
+                collation: program.curr_collation_ctx(),
+            },
+        );
+    }
+    let ExprSubroutineState::Emitted {
+        label_start,
+        reg_return,
+        reg_result,
+        collation,
+    } = program.expr_subroutine_state(idx)
+    else {
+        unreachable!("expression subroutine must be emitted before it is called");
+    };
+    program.emit_insn(Insn::Gosub {
+        target_pc: label_start,
+        return_reg: reg_return,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_result,
+        dst_reg: target_register,
+        amount: 0,
+    });
+    program.set_collation(collation);
+    Ok(())
+}
+
+/// Evaluates `expr` at translation time if it is arithmetic on numeric literals, using the
+/// same arithmetic as the VDBE so that the result is identical to evaluating it at runtime.
+fn fold_constant_expr(expr: &ast::Expr) -> Option<Value> {
+    match expr {
+        ast::Expr::Literal(ast::Literal::Numeric(value)) => parse_numeric_literal(value).ok(),
+        ast::Expr::Parenthesized(exprs) if exprs.len() == 1 => fold_constant_expr(&exprs[0]),
+        ast::Expr::Unary(UnaryOperator::Positive, expr) => fold_constant_expr(expr),
+        // Negated literals are parsed with their sign, like in translate_expr(), so that
+        // e.g. -9223372036854775808 stays an integer.
+        ast::Expr::Unary(UnaryOperator::Negative, expr) => match expr.as_ref() {
+            ast::Expr::Literal(ast::Literal::Numeric(value)) => {
+                parse_numeric_literal(&format!("-{value}")).ok()
+            }
+            expr => Some(Value::Integer(0).exec_subtract(&fold_constant_expr(expr)?)),
+        },
+        ast::Expr::Binary(lhs, op, rhs) => {
+            let lhs = fold_constant_expr(lhs)?;
+            let rhs = fold_constant_expr(rhs)?;
+            match op {
+                ast::Operator::Add => Some(lhs.exec_add(&rhs)),
+                ast::Operator::Subtract => Some(lhs.exec_subtract(&rhs)),
+                ast::Operator::Multiply => Some(lhs.exec_multiply(&rhs)),
+                ast::Operator::Divide => Some(lhs.exec_divide(&rhs)),
+                ast::Operator::Modulus => Some(lhs.exec_remainder(&rhs)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Emits a single `Integer` or `Real` load for `expr` if it can be constant folded. Returns
+/// false, emitting nothing, if it can't, or if it evaluates to NULL (e.g. division by zero).
+fn emit_folded_constant(
+    program: &mut ProgramBuilder,
+    expr: &ast::Expr,
+    target_register: usize,
+) -> bool {
+    match fold_constant_expr(expr) {
+        Some(Value::Integer(value)) => {
+            program.emit_insn(Insn::Integer {
+                value,
+                dest: target_register,
+            });
+            true
+        }
+        Some(Value::Float(value)) => {
+            program.emit_insn(Insn::Real {
+                value,
+                dest: target_register,
+            });
+            true
+        }
+        _ => false,
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn emit_binary_insn(
     program: &mut ProgramBuilder,
@@ -2534,7 +2742,27 @@ fn translate_like_base(
             });
         }
         ast::LikeOperator::Match => todo!(),
-        ast::LikeOperator::Regexp => todo!(),
+        ast::LikeOperator::Regexp => {
+            // "X REGEXP Y" is a call to the function regexp(Y, X), which is either the built-in
+            // one or one registered by an extension.
+            let Some(func) = resolver.resolve_function("regexp", 2) else {
+                crate::bail_parse_error!("no such function: REGEXP");
+            };
+            let start_reg = program.alloc_registers(2);
+            let mut constant_mask = 0;
+            translate_expr(program, referenced_tables, lhs, start_reg + 1, resolver)?;
+            translate_expr(program, referenced_tables, rhs, start_reg, resolver)?;
+            if matches!(rhs.as_ref(), ast::Expr::Literal(_)) {
+                program.mark_last_insn_constant();
+                constant_mask = 1;
+            }
+            program.emit_insn(Insn::Function {
+                constant_mask,
+                start_reg,
+                dest: target_register,
+                func: FuncCtx { func, arg_count: 2 },
+            });
+        }
     }
 
     Ok(target_register)
@@ -3086,14 +3314,19 @@ pub fn get_expr_affinity(
             }
             Affinity::Blob
         }
+        ast::Expr::RowId { .. } => Affinity::Integer,
         ast::Expr::Cast { type_name, .. } => {
             if let Some(type_name) = type_name {
-                crate::schema::affinity(&type_name.name)
+                crate::schema::affinity(&type_name.name.to_uppercase())
             } else {
                 Affinity::Blob
             }
         }
         ast::Expr::Collate(expr, _) => get_expr_affinity(expr, referenced_tables),
+        // A parenthesized expression has the affinity of its (first) operand.
+        ast::Expr::Parenthesized(exprs) if !exprs.is_empty() => {
+            get_expr_affinity(&exprs[0], referenced_tables)
+        }
         // Literals have NO affinity in SQLite!
         ast::Expr::Literal(_) => Affinity::Blob, // No affinity!
         _ => Affinity::Blob,                     // This may need to change. For now this works.
@@ -0,0 +1,773 @@
+//! Foreign key enforcement (`PRAGMA foreign_keys = ON`).
+//!
+//! Two kinds of code are generated here:
+//!
+//! - child checks, emitted after a row of a table that declares foreign keys has been
+//!   written: every non-NULL key must match a row of the parent table.
+//! - parent actions, emitted before a row that other tables may reference is deleted or
+//!   has its key changed: referencing rows either fail the statement (`NO ACTION`,
+//!   `RESTRICT`) or are deleted/updated by a nested DML loop (`CASCADE`, `SET NULL`,
+//!   `SET DEFAULT`).
+//!
+//! Unlike SQLite, violations are reported as soon as the offending row is written instead
+//! of at the end of the statement, so a statement that only temporarily violates a
+//! constraint fails.
+
+use std::num::NonZeroUsize;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::RefAct;
+
+use crate::error::{SQLITE_CONSTRAINT_FOREIGNKEY, SQLITE_CONSTRAINT_NOTNULL};
+use crate::schema::{BTreeTable, ForeignKey, Index};
+use crate::vdbe::builder::{CursorType, ProgramBuilder};
+use crate::vdbe::insn::{CmpInsFlags, IdxInsertFlags, InsertFlags, Insn, RegisterOrLiteral};
+use crate::{bail_parse_error, LimboError, Result};
+
+use super::emitter::Resolver;
+use super::expr::translate_expr;
+use super::generated::{emit_generated_columns, emit_table_record};
+use super::index::{
+    emit_index_key, emit_index_key_for_cursor, emit_partial_index_check,
+    emit_partial_index_check_for_cursor,
+};
+
+/// Registers holding one row of a table: the rowid and one register per column, in table
+/// order. Like in a table record, the register of a rowid alias column is not used; its
+/// value is the rowid.
+#[derive(Debug, Clone, Copy)]
+pub struct RowRegisters {
+    pub rowid: usize,
+    pub columns_start: usize,
+}
+
+impl RowRegisters {
+    pub(crate) fn column(&self, table: &BTreeTable, idx: usize) -> usize {
+        if table.columns[idx].is_rowid_alias {
+            self.rowid
+        } else {
+            self.columns_start + idx
+        }
+    }
+}
+
+/// How a parent key is looked up.
+enum ParentLookup {
+    /// The key is the parent's rowid.
+    Rowid,
+    /// The key is covered by a unique index. `order[i]` is the position in the key of the
+    /// i-th index column.
+    Index {
+        index: Arc<Index>,
+        order: Vec<usize>,
+    },
+    /// The key is declared unique but no index exists for it, e.g. because indexes are
+    /// disabled, so the parent table is scanned.
+    Scan,
+}
+
+struct ParentKey {
+    table: Rc<BTreeTable>,
+    /// Positions of the key columns in the parent table, in the order of the foreign key.
+    columns: Vec<usize>,
+    lookup: ParentLookup,
+}
+
+fn mismatch(child: &BTreeTable, foreign_key: &ForeignKey) -> LimboError {
+    LimboError::ParseError(format!(
+        "foreign key mismatch - \"{}\" referencing \"{}\"",
+        child.name, foreign_key.parent_table
+    ))
+}
+
+fn child_columns(child: &BTreeTable, foreign_key: &ForeignKey) -> Result<Vec<usize>> {
+    foreign_key
+        .child_columns
+        .iter()
+        .map(|name| {
+            child
+                .get_column(name)
+                .map(|(idx, _)| idx)
+                .ok_or_else(|| mismatch(child, foreign_key))
+        })
+        .collect()
+}
+
+fn resolve_parent_key(
+    resolver: &Resolver,
+    child: &BTreeTable,
+    foreign_key: &ForeignKey,
+) -> Result<ParentKey> {
+    let Some(parent) = resolver.schema.get_btree_table(&foreign_key.parent_table) else {
+        bail_parse_error!("no such table: main.{}", foreign_key.parent_table);
+    };
+    let names = if foreign_key.parent_columns.is_empty() {
+        parent
+            .primary_key_columns
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    } else {
+        foreign_key.parent_columns.clone()
+    };
+    if names.is_empty() || names.len() != foreign_key.child_columns.len() {
+        return Err(mismatch(child, foreign_key));
+    }
+    let columns = names
+        .iter()
+        .map(|name| parent.get_column(name).map(|(idx, _)| idx))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| mismatch(child, foreign_key))?;
+
+    if columns.len() == 1 && parent.columns[columns[0]].is_rowid_alias {
+        return Ok(ParentKey {
+            table: parent,
+            columns,
+            lookup: ParentLookup::Rowid,
+        });
+    }
+
+    // The key must be the primary key or carry a UNIQUE constraint.
+    let same_columns = |other: &mut dyn Iterator<Item = &String>| {
+        let other = other.collect::<Vec<_>>();
+        other.len() == names.len() && names.iter().all(|name| other.contains(&name))
+    };
+    let is_unique = same_columns(&mut parent.primary_key_columns.iter().map(|(name, _)| name))
+        || (columns.len() == 1 && parent.columns[columns[0]].unique)
+        || parent.unique_sets.as_ref().is_some_and(|sets| {
+            sets.iter()
+                .any(|set| same_columns(&mut set.iter().map(|(name, _)| name)))
+        });
+    if !is_unique {
+        return Err(mismatch(child, foreign_key));
+    }
+
+    let index = resolver
+        .schema
+        .get_indices(&parent.name)
+        .iter()
+        .find(|index| {
+            index.unique
+                && index.where_clause.is_none()
+                && index.columns.len() == columns.len()
+                && index
+                    .columns
+                    .iter()
+                    .all(|column| columns.contains(&column.pos_in_table))
+        })
+        .cloned();
+    let lookup = match index {
+        Some(index) => {
+            let order = index
+                .columns
+                .iter()
+                .map(|column| {
+                    columns
+                        .iter()
+                        .position(|pos| *pos == column.pos_in_table)
+                        .unwrap()
+                })
+                .collect();
+            ParentLookup::Index { index, order }
+        }
+        None => ParentLookup::Scan,
+    };
+    Ok(ParentKey {
+        table: parent,
+        columns,
+        lookup,
+    })
+}
+
+/// Emits the checks that every foreign key of `table` in the row held by `row` refers to an
+/// existing parent row. With `changed_columns`, only foreign keys that include one of those
+/// columns are checked.
+pub fn emit_fk_child_checks(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    row: RowRegisters,
+    changed_columns: Option<&[usize]>,
+) -> Result<()> {
+    for foreign_key in &table.foreign_keys {
+        let columns = child_columns(table, foreign_key)?;
+        if let Some(changed_columns) = changed_columns {
+            if !columns.iter().any(|idx| changed_columns.contains(idx)) {
+                continue;
+            }
+        }
+        emit_fk_child_check(program, resolver, table, foreign_key, &columns, row)?;
+    }
+    Ok(())
+}
+
+fn emit_fk_child_check(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    foreign_key: &ForeignKey,
+    columns: &[usize],
+    row: RowRegisters,
+) -> Result<()> {
+    let parent_key = resolve_parent_key(resolver, table, foreign_key)?;
+    let parent = &parent_key.table;
+    let label_ok = program.allocate_label();
+
+    // A key with a NULL column doesn't refer to anything.
+    for idx in columns {
+        program.emit_insn(Insn::IsNull {
+            reg: row.column(table, *idx),
+            target_pc: label_ok,
+        });
+    }
+
+    // Copy the key in the order the lookup wants it and convert it the way the parent
+    // columns were converted when they were stored.
+    let order = match &parent_key.lookup {
+        ParentLookup::Index { order, .. } => order.clone(),
+        ParentLookup::Rowid | ParentLookup::Scan => (0..columns.len()).collect(),
+    };
+    let key_start = program.alloc_registers(columns.len());
+    for (i, key_pos) in order.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: row.column(table, columns[*key_pos]),
+            dst_reg: key_start + i,
+            amount: 0,
+        });
+    }
+    let affinities = order
+        .iter()
+        .map(|key_pos| {
+            parent.columns[parent_key.columns[*key_pos]]
+                .affinity()
+                .aff_mask()
+        })
+        .collect();
+    program.emit_insn(Insn::Affinity {
+        start_reg: key_start,
+        count: NonZeroUsize::new(columns.len()).unwrap(),
+        affinities,
+    });
+
+    match &parent_key.lookup {
+        ParentLookup::Rowid => {
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(parent.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: parent.root_page,
+            });
+            let label_violation = program.allocate_label();
+            program.emit_insn(Insn::SeekRowid {
+                cursor_id,
+                src_reg: key_start,
+                target_pc: label_violation,
+            });
+            program.emit_insn(Insn::Goto {
+                target_pc: label_ok,
+            });
+            program.preassign_label_to_next_insn(label_violation);
+        }
+        ParentLookup::Index { index, .. } => {
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: index.root_page,
+            });
+            program.emit_insn(Insn::Found {
+                cursor_id,
+                target_pc: label_ok,
+                record_reg: key_start,
+                num_regs: columns.len(),
+            });
+        }
+        ParentLookup::Scan => {
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(parent.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: parent.root_page,
+            });
+            let label_loop_start = program.allocate_label();
+            let label_next = program.allocate_label();
+            let label_violation = program.allocate_label();
+            program.emit_insn(Insn::Rewind {
+                cursor_id,
+                pc_if_empty: label_violation,
+            });
+            program.preassign_label_to_next_insn(label_loop_start);
+            let value_reg = program.alloc_register();
+            for (i, parent_pos) in parent_key.columns.iter().enumerate() {
+                emit_table_column(program, parent, cursor_id, *parent_pos, value_reg);
+                program.emit_insn(Insn::Ne {
+                    lhs: value_reg,
+                    rhs: key_start + i,
+                    target_pc: label_next,
+                    flags: CmpInsFlags::default().jump_if_null(),
+                    collation: parent.columns[*parent_pos].collation,
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: label_ok,
+            });
+            program.preassign_label_to_next_insn(label_next);
+            program.emit_insn(Insn::Next {
+                cursor_id,
+                pc_if_next: label_loop_start,
+            });
+            program.preassign_label_to_next_insn(label_violation);
+        }
+    }
+
+    program.emit_insn(Insn::Halt {
+        err_code: SQLITE_CONSTRAINT_FOREIGNKEY,
+        description: String::new(),
+    });
+    program.preassign_label_to_next_insn(label_ok);
+    Ok(())
+}
+
+fn emit_table_column(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    cursor_id: usize,
+    idx: usize,
+    dest: usize,
+) {
+    if table.columns[idx].is_rowid_alias {
+        program.emit_insn(Insn::RowId { cursor_id, dest });
+    } else {
+        program.emit_column(cursor_id, idx, dest);
+    }
+}
+
+/// Emits the actions of the foreign keys that refer to `table` for the row `cursor_id` is
+/// positioned on, before that row is deleted (`new_row` is `None`) or overwritten with
+/// `new_row`. `changed_columns` lists the columns an UPDATE assigns; keys that don't
+/// include any of them are left alone.
+///
+/// `tables_being_modified` holds the tables written by the statement and by the actions
+/// enclosing this one. An action that would write one of them again (a cycle of cascading
+/// keys, or a cascading key of a table that refers to itself) is not supported, as the
+/// nested loop would move under the cursor of the enclosing one.
+#[allow(clippy::too_many_arguments)]
+pub fn emit_fk_parent_actions(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    cursor_id: usize,
+    rowid_reg: usize,
+    new_row: Option<RowRegisters>,
+    changed_columns: Option<&[usize]>,
+    tables_being_modified: &mut Vec<String>,
+) -> Result<()> {
+    let mut children = resolver
+        .schema
+        .tables
+        .values()
+        .filter_map(|child| child.btree())
+        .filter(|child| {
+            child
+                .foreign_keys
+                .iter()
+                .any(|foreign_key| foreign_key.parent_table == table.name)
+        })
+        .collect::<Vec<_>>();
+    children.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for child in children {
+        for foreign_key in child
+            .foreign_keys
+            .iter()
+            .filter(|foreign_key| foreign_key.parent_table == table.name)
+        {
+            let parent_key = resolve_parent_key(resolver, &child, foreign_key)?;
+            if let Some(changed_columns) = changed_columns {
+                if !parent_key
+                    .columns
+                    .iter()
+                    .any(|idx| changed_columns.contains(idx))
+                {
+                    continue;
+                }
+            }
+            let action = if new_row.is_some() {
+                foreign_key.on_update
+            } else {
+                foreign_key.on_delete
+            };
+            emit_fk_parent_action(
+                program,
+                resolver,
+                table,
+                cursor_id,
+                rowid_reg,
+                new_row,
+                &child,
+                foreign_key,
+                &parent_key.columns,
+                action,
+                tables_being_modified,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn emit_fk_parent_action(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    cursor_id: usize,
+    rowid_reg: usize,
+    new_row: Option<RowRegisters>,
+    child: &Rc<BTreeTable>,
+    foreign_key: &ForeignKey,
+    key_columns: &[usize],
+    action: RefAct,
+    tables_being_modified: &mut Vec<String>,
+) -> Result<()> {
+    let modifies_child = matches!(
+        action,
+        RefAct::Cascade | RefAct::SetNull | RefAct::SetDefault
+    );
+    if modifies_child && tables_being_modified.contains(&child.name) {
+        bail_parse_error!(
+            "foreign key action on table {} would modify a table that is already being modified",
+            child.name
+        );
+    }
+    if !child.has_rowid {
+        bail_parse_error!(
+            "foreign key actions on WITHOUT ROWID table {} are not supported",
+            child.name
+        );
+    }
+    let columns = child_columns(child, foreign_key)?;
+    if modifies_child {
+        if let Some(idx) = columns
+            .iter()
+            .find(|idx| child.columns[**idx].is_rowid_alias)
+        {
+            bail_parse_error!(
+                "foreign key action modifying INTEGER PRIMARY KEY column {}.{} is not supported",
+                child.name,
+                child.columns[*idx].name.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    let label_done = program.allocate_label();
+
+    // Load the old key. Rows with a NULL in it are not referenced by anything.
+    let old_key_start = program.alloc_registers(key_columns.len());
+    for (i, idx) in key_columns.iter().enumerate() {
+        let reg = old_key_start + i;
+        if table.columns[*idx].is_rowid_alias {
+            program.emit_insn(Insn::Copy {
+                src_reg: rowid_reg,
+                dst_reg: reg,
+                amount: 0,
+            });
+        } else {
+            program.emit_column(cursor_id, *idx, reg);
+        }
+        program.emit_insn(Insn::IsNull {
+            reg,
+            target_pc: label_done,
+        });
+    }
+
+    // An UPDATE that keeps the key as it is doesn't affect referencing rows.
+    if let Some(new_row) = new_row {
+        let label_changed = program.allocate_label();
+        for (i, idx) in key_columns.iter().enumerate() {
+            program.emit_insn(Insn::Ne {
+                lhs: old_key_start + i,
+                rhs: new_row.column(table, *idx),
+                target_pc: label_changed,
+                flags: CmpInsFlags::default().null_eq(),
+                collation: table.columns[*idx].collation,
+            });
+        }
+        program.emit_insn(Insn::Goto {
+            target_pc: label_done,
+        });
+        program.preassign_label_to_next_insn(label_changed);
+    }
+
+    let child_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(child.clone()));
+    let child_indexes = if modifies_child {
+        program.emit_insn(Insn::OpenWrite {
+            cursor_id: child_cursor_id,
+            root_page: RegisterOrLiteral::Literal(child.root_page),
+            name: child.name.clone(),
+        });
+        resolver
+            .schema
+            .get_indices(&child.name)
+            .iter()
+            .map(|index| {
+                let index_cursor_id =
+                    program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+                program.emit_insn(Insn::OpenWrite {
+                    cursor_id: index_cursor_id,
+                    root_page: RegisterOrLiteral::Literal(index.root_page),
+                    name: index.name.clone(),
+                });
+                (index.clone(), index_cursor_id)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        program.emit_insn(Insn::OpenRead {
+            cursor_id: child_cursor_id,
+            root_page: child.root_page,
+        });
+        vec![]
+    };
+
+    let label_loop_start = program.allocate_label();
+    let label_next = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: child_cursor_id,
+        pc_if_empty: label_done,
+    });
+    program.preassign_label_to_next_insn(label_loop_start);
+
+    let value_reg = program.alloc_register();
+    for (i, idx) in columns.iter().enumerate() {
+        emit_table_column(program, child, child_cursor_id, *idx, value_reg);
+        program.emit_insn(Insn::Ne {
+            lhs: value_reg,
+            rhs: old_key_start + i,
+            target_pc: label_next,
+            flags: CmpInsFlags::default().jump_if_null(),
+            collation: table.columns[key_columns[i]].collation,
+        });
+    }
+    let child_rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::RowId {
+        cursor_id: child_cursor_id,
+        dest: child_rowid_reg,
+    });
+    // A row that refers to itself goes away together with the reference.
+    if new_row.is_none() && child.name == table.name {
+        program.emit_insn(Insn::Eq {
+            lhs: child_rowid_reg,
+            rhs: rowid_reg,
+            target_pc: label_next,
+            flags: CmpInsFlags::default(),
+            collation: None,
+        });
+    }
+
+    tables_being_modified.push(child.name.clone());
+    match action {
+        RefAct::NoAction | RefAct::Restrict => {
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_FOREIGNKEY,
+                description: String::new(),
+            });
+        }
+        RefAct::Cascade if new_row.is_none() => {
+            emit_fk_parent_actions(
+                program,
+                resolver,
+                child,
+                child_cursor_id,
+                child_rowid_reg,
+                None,
+                None,
+                tables_being_modified,
+            )?;
+            for (index, index_cursor_id) in &child_indexes {
+                emit_index_delete(
+                    program,
+                    resolver,
+                    child,
+                    index,
+                    child_cursor_id,
+                    *index_cursor_id,
+                    child_rowid_reg,
+                )?;
+            }
+            program.emit_insn(Insn::Delete {
+                cursor_id: child_cursor_id,
+                table_name: child.name.clone(),
+            });
+        }
+        RefAct::Cascade | RefAct::SetNull | RefAct::SetDefault => {
+            let row = RowRegisters {
+                rowid: child_rowid_reg,
+                columns_start: program.alloc_registers(child.columns.len()),
+            };
+            for idx in 0..child.columns.len() {
+                let reg = row.columns_start + idx;
+                if let Some(i) = columns.iter().position(|column| *column == idx) {
+                    match (action, &child.columns[idx].default) {
+                        (RefAct::Cascade, _) => {
+                            // `new_row` is set, a cascading delete is handled above.
+                            let new_row = new_row.unwrap();
+                            program.emit_insn(Insn::Copy {
+                                src_reg: new_row.column(table, key_columns[i]),
+                                dst_reg: reg,
+                                amount: 0,
+                            });
+                        }
+                        (RefAct::SetDefault, Some(default)) => {
+                            translate_expr(program, None, default, reg, resolver)?;
+                        }
+                        _ => program.emit_null(reg, None),
+                    }
+                    if child.columns[idx].notnull {
+                        program.emit_insn(Insn::HaltIfNull {
+                            target_reg: reg,
+                            err_code: SQLITE_CONSTRAINT_NOTNULL,
+                            description: format!(
+                                "{}.{}",
+                                child.name,
+                                child.columns[idx].name.as_deref().unwrap_or_default()
+                            ),
+                        });
+                    }
+                } else if child.columns[idx].is_rowid_alias {
+                    program.emit_null(reg, None);
+                } else {
+                    program.emit_column(child_cursor_id, idx, reg);
+                }
+            }
+            if child.is_strict {
+                program.emit_insn(Insn::TypeCheck {
+                    start_reg: row.columns_start,
+                    count: child.columns.len(),
+                    check_generated: true,
+                    table_reference: child.clone(),
+                });
+            } else {
+                program.emit_insn(Insn::Affinity {
+                    start_reg: row.columns_start,
+                    count: NonZeroUsize::new(child.columns.len()).unwrap(),
+                    affinities: child.column_affinities(),
+                });
+            }
+            emit_generated_columns(program, resolver, child, row)?;
+
+            emit_fk_parent_actions(
+                program,
+                resolver,
+                child,
+                child_cursor_id,
+                child_rowid_reg,
+                Some(row),
+                Some(&columns),
+                tables_being_modified,
+            )?;
+
+            for (index, index_cursor_id) in &child_indexes {
+                emit_index_delete(
+                    program,
+                    resolver,
+                    child,
+                    index,
+                    child_cursor_id,
+                    *index_cursor_id,
+                    child_rowid_reg,
+                )?;
+                let label_skip_insert = program.allocate_label();
+                emit_partial_index_check(program, resolver, child, index, row, label_skip_insert)?;
+                let num_regs = index.columns.len() + 1;
+                let start_reg = program.alloc_registers(num_regs);
+                emit_index_key(program, resolver, child, index, row, start_reg)?;
+                program.emit_insn(Insn::Copy {
+                    src_reg: child_rowid_reg,
+                    dst_reg: start_reg + num_regs - 1,
+                    amount: 0,
+                });
+                let record_reg = program.alloc_register();
+                program.emit_insn(Insn::MakeRecord {
+                    start_reg,
+                    count: num_regs,
+                    dest_reg: record_reg,
+                    index_name: Some(index.name.clone()),
+                });
+                program.emit_insn(Insn::IdxInsert {
+                    cursor_id: *index_cursor_id,
+                    record_reg,
+                    unpacked_start: Some(start_reg),
+                    unpacked_count: Some(num_regs as u16),
+                    flags: IdxInsertFlags::new(),
+                });
+                program.preassign_label_to_next_insn(label_skip_insert);
+            }
+
+            let record_reg = program.alloc_register();
+            emit_table_record(program, child, row, record_reg);
+            program.emit_insn(Insn::Insert {
+                cursor: child_cursor_id,
+                key_reg: child_rowid_reg,
+                record_reg,
+                flag: InsertFlags::new().update(true),
+                table_name: child.name.clone(),
+            });
+
+            // The default value has to refer to an existing parent row as well.
+            if action == RefAct::SetDefault {
+                emit_fk_child_check(program, resolver, child, foreign_key, &columns, row)?;
+            }
+        }
+    }
+    tables_being_modified.pop();
+
+    program.preassign_label_to_next_insn(label_next);
+    program.emit_insn(Insn::Next {
+        cursor_id: child_cursor_id,
+        pc_if_next: label_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+fn emit_index_delete(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    table_cursor_id: usize,
+    index_cursor_id: usize,
+    rowid_reg: usize,
+) -> Result<()> {
+    // A partial index only holds the rows that satisfy its WHERE clause.
+    let label_skip = program.allocate_label();
+    emit_partial_index_check_for_cursor(
+        program,
+        resolver,
+        table,
+        index,
+        table_cursor_id,
+        rowid_reg,
+        label_skip,
+    )?;
+    let num_regs = index.columns.len() + 1;
+    let start_reg = program.alloc_registers(num_regs);
+    emit_index_key_for_cursor(
+        program,
+        resolver,
+        table,
+        index,
+        table_cursor_id,
+        rowid_reg,
+        start_reg,
+    )?;
+    program.emit_insn(Insn::Copy {
+        src_reg: rowid_reg,
+        dst_reg: start_reg + num_regs - 1,
+        amount: 0,
+    });
+    program.emit_insn(Insn::IdxDelete {
+        start_reg,
+        num_regs,
+        cursor_id: index_cursor_id,
+    });
+    program.preassign_label_to_next_insn(label_skip);
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! Generated columns (`GENERATED ALWAYS AS (expr) [STORED | VIRTUAL]`).
+//!
+//! STORED columns are computed when a row is inserted or updated and written to the record
+//! like any other column. VIRTUAL columns are not part of the record: they are computed when
+//! a row is written (so that indexes and constraints can see them) and whenever they are read.
+
+use std::num::NonZeroUsize;
+
+use turso_sqlite3_parser::ast::{self, TableInternalId};
+
+use crate::schema::BTreeTable;
+use crate::vdbe::builder::ProgramBuilder;
+use crate::vdbe::insn::Insn;
+use crate::{bail_parse_error, Result};
+
+use super::check::{bind_table_expr, row_register_exprs};
+use super::emitter::Resolver;
+use super::expr::{translate_expr, walk_expr, WalkControl};
+use super::fkey::RowRegisters;
+
+/// The columns of `table` that the generation expression of `column` refers to.
+pub(crate) fn generated_column_dependencies(
+    table: &BTreeTable,
+    column: usize,
+) -> Result<Vec<usize>> {
+    let Some(expr) = &table.columns[column].generated_expr else {
+        return Ok(vec![]);
+    };
+    let expr = bind_table_expr(table, expr, TableInternalId::default())?;
+    let mut dependencies = vec![];
+    walk_expr(&expr, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+        if let ast::Expr::Column { column, .. } = expr {
+            dependencies.push(*column);
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(dependencies)
+}
+
+/// Appends `column` to `order` after the generated columns it depends on.
+fn visit_generated_column(
+    table: &BTreeTable,
+    column: usize,
+    visiting: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<()> {
+    if order.contains(&column) || !table.columns[column].is_generated() {
+        return Ok(());
+    }
+    if visiting.contains(&column) {
+        bail_parse_error!(
+            "generated column loop on \"{}\"",
+            table.columns[column].name.as_deref().unwrap_or_default()
+        );
+    }
+    visiting.push(column);
+    for dependency in generated_column_dependencies(table, column)? {
+        visit_generated_column(table, dependency, visiting, order)?;
+    }
+    visiting.pop();
+    order.push(column);
+    Ok(())
+}
+
+/// The generated columns of `table`, ordered so that every column comes after the generated
+/// columns it depends on.
+fn generated_columns_in_order(table: &BTreeTable) -> Result<Vec<usize>> {
+    let mut order = vec![];
+    for column in 0..table.columns.len() {
+        visit_generated_column(table, column, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Extends the columns changed by an UPDATE with the generated columns computed from them.
+pub(crate) fn with_dependent_generated_columns(
+    table: &BTreeTable,
+    mut changed_columns: Vec<usize>,
+) -> Result<Vec<usize>> {
+    for column in generated_columns_in_order(table)? {
+        if generated_column_dependencies(table, column)?
+            .iter()
+            .any(|dependency| changed_columns.contains(dependency))
+        {
+            changed_columns.push(column);
+        }
+    }
+    Ok(changed_columns)
+}
+
+/// Returns the generation expression of the generated `column`, with its column references
+/// bound to the table reference `table_id`.
+pub(crate) fn bind_generated_column(
+    table: &BTreeTable,
+    column: usize,
+    table_id: TableInternalId,
+) -> Result<ast::Expr> {
+    visit_generated_column(table, column, &mut vec![], &mut vec![])?;
+    let expr = table.columns[column]
+        .generated_expr
+        .as_ref()
+        .expect("column should be generated");
+    bind_table_expr(table, expr, table_id)
+}
+
+/// Applies the affinity of `column` to `reg`, like SQLite does for the value of a generated
+/// column.
+pub(crate) fn emit_generated_column_affinity(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    column: usize,
+    reg: usize,
+) {
+    program.emit_insn(Insn::Affinity {
+        start_reg: reg,
+        count: NonZeroUsize::new(1).unwrap(),
+        affinities: table.columns[column].affinity().aff_mask().to_string(),
+    });
+}
+
+/// Computes every generated column of the row held by `row` into its register. The other
+/// columns must already hold their final values.
+pub fn emit_generated_columns(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    row: RowRegisters,
+) -> Result<()> {
+    let order = generated_columns_in_order(table)?;
+    if order.is_empty() {
+        return Ok(());
+    }
+    let columns = row_register_exprs(table, row);
+    let mut resolver = Resolver::new(resolver.schema, resolver.symbol_table);
+    resolver.enable_expr_to_reg_cache();
+    resolver
+        .expr_to_reg_cache
+        .extend(columns.iter().map(|(expr, reg)| (expr, *reg)));
+
+    for column in order {
+        let expr = bind_generated_column(table, column, TableInternalId::default())?;
+        let reg = row.column(table, column);
+        translate_expr(program, None, &expr, reg, &resolver)?;
+        emit_generated_column_affinity(program, table, column, reg);
+    }
+    Ok(())
+}
+
+/// Emits a `MakeRecord` of the row held by `row` into `dest_reg`, leaving out the VIRTUAL
+/// generated columns.
+pub fn emit_table_record(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    row: RowRegisters,
+    dest_reg: usize,
+) {
+    if !table.has_virtual_columns() {
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: row.columns_start,
+            count: table.columns.len(),
+            dest_reg,
+            index_name: None,
+        });
+        return;
+    }
+    let stored_columns = (0..table.columns.len())
+        .filter(|&column| !table.columns[column].is_virtual())
+        .collect::<Vec<_>>();
+    let start_reg = program.alloc_registers(stored_columns.len());
+    for (i, column) in stored_columns.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: row.columns_start + column,
+            dst_reg: start_reg + i,
+            amount: 0,
+        });
+    }
+    program.emit_insn(Insn::MakeRecord {
+        start_reg,
+        count: stored_columns.len(),
+        dest_reg,
+        index_name: None,
+    });
+}
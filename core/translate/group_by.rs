@@ -949,33 +949,21 @@ pub fn translate_aggregation_step_groupby(
                 crate::bail_parse_error!("group_concat bad number of arguments");
             }
 
-            let delimiter_reg = program.alloc_register();
-
-            let delimiter_expr: ast::Expr;
-
-            if num_args == 2 {
-                match &agg_arg_source.args()[1] {
-                    ast::Expr::Column { .. } => {
-                        delimiter_expr = agg_arg_source.args()[1].clone();
-                    }
-                    ast::Expr::Literal(ast::Literal::String(s)) => {
-                        delimiter_expr = ast::Expr::Literal(ast::Literal::String(s.to_string()));
-                    }
-                    _ => crate::bail_parse_error!("Incorrect delimiter parameter"),
-                };
-            } else {
-                delimiter_expr = ast::Expr::Literal(ast::Literal::String(String::from("\",\"")));
-            }
-
             let expr_reg = agg_arg_source.translate(program, 0)?;
             handle_distinct(program, agg_arg_source.aggregate(), expr_reg);
-            translate_expr(
-                program,
-                Some(referenced_tables),
-                &delimiter_expr,
-                delimiter_reg,
-                resolver,
-            )?;
+            let delimiter_reg = if num_args == 2 {
+                agg_arg_source.translate(program, 1)?
+            } else {
+                let delimiter_reg = program.alloc_register();
+                translate_expr(
+                    program,
+                    Some(referenced_tables),
+                    &ast::Expr::Literal(ast::Literal::String(String::from("\",\""))),
+                    delimiter_reg,
+                    resolver,
+                )?;
+                delimiter_reg
+            };
 
             program.emit_insn(Insn::AggStep {
                 acc_reg: target_register,
@@ -2,24 +2,35 @@ use std::sync::Arc;
 
 use crate::vdbe::insn::{CmpInsFlags, Cookie};
 use crate::{
-    schema::{BTreeTable, Column, Index, IndexColumn, PseudoCursorType, Schema},
+    schema::{BTreeTable, Index, IndexColumn, PseudoCursorType, Schema, EXPR_INDEX_SENTINEL},
     storage::pager::CreateBTreeFlags,
     util::normalize_ident,
     vdbe::{
         builder::{CursorType, ProgramBuilder},
         insn::{IdxInsertFlags, Insn, RegisterOrLiteral},
+        BranchOffset, CursorID,
     },
+    Result, SymbolTable,
 };
-use turso_sqlite3_parser::ast::{self, Expr, Id, SortOrder, SortedColumn};
-
+use turso_sqlite3_parser::ast::{self, Expr, Id, SortOrder, SortedColumn, TableInternalId};
+use turso_sqlite3_parser::dialect::quote_identifier;
+
+use super::check::{bind_table_expr, row_register_exprs};
+use super::emitter::Resolver;
+use super::expr::{translate_expr, walk_expr, WalkControl};
+use super::fkey::RowRegisters;
+use super::generated::emit_generated_columns;
 use super::schema::{emit_schema_entry, SchemaEntryType, SQLITE_TABLEID};
 
+#[allow(clippy::too_many_arguments)]
 pub fn translate_create_index(
     unique_if_not_exists: (bool, bool),
     idx_name: &str,
     tbl_name: &str,
     columns: &[SortedColumn],
+    where_clause: Option<Box<Expr>>,
     schema: &Schema,
+    syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> crate::Result<ProgramBuilder> {
     if !schema.indexes_enabled() {
@@ -47,25 +58,23 @@ pub fn translate_create_index(
     let Some(tbl) = tbl.btree() else {
         crate::bail_parse_error!("Error: table '{tbl_name}' is not a b-tree table.");
     };
+    if !tbl.has_rowid {
+        crate::bail_parse_error!("Error: indexes on WITHOUT ROWID tables are not supported.");
+    }
     let columns = resolve_sorted_columns(&tbl, columns)?;
+    if let Some(where_clause) = &where_clause {
+        validate_index_expr(&tbl, where_clause, "partial index WHERE clauses")?;
+    }
 
     let idx = Arc::new(Index {
         name: idx_name.clone(),
         table_name: tbl.name.clone(),
         root_page: 0, //  we dont have access till its created, after we parse the schema table
-        columns: columns
-            .iter()
-            .map(|((pos_in_table, col), order)| IndexColumn {
-                name: col.name.as_ref().unwrap().clone(),
-                order: *order,
-                pos_in_table: *pos_in_table,
-                collation: col.collation,
-                default: col.default.clone(),
-            })
-            .collect(),
+        columns,
         unique: unique_if_not_exists.0,
         ephemeral: false,
         has_rowid: tbl.has_rowid,
+        where_clause: where_clause.clone(),
     });
 
     // Allocate the necessary cursors:
@@ -99,7 +108,13 @@ pub fn translate_create_index(
         root_page: RegisterOrLiteral::Literal(sqlite_table.root_page),
         name: sqlite_table.name.clone(),
     });
-    let sql = create_idx_stmt_to_sql(&tbl_name, &idx_name, unique_if_not_exists, &columns);
+    let sql = create_idx_stmt_to_sql(
+        &tbl_name,
+        &idx_name,
+        unique_if_not_exists,
+        &idx.columns,
+        where_clause.as_deref(),
+    );
     emit_schema_entry(
         &mut program,
         sqlite_schema_cursor_id,
@@ -115,7 +130,7 @@ pub fn translate_create_index(
     // open the sorter and the pseudo table
     program.emit_insn(Insn::SorterOpen {
         cursor_id: sorter_cursor_id,
-        columns: columns.len(),
+        columns: idx.columns.len(),
         order,
         collations: tbl.column_collations(),
     });
@@ -123,7 +138,7 @@ pub fn translate_create_index(
     program.emit_insn(Insn::OpenPseudo {
         cursor_id: pseudo_cursor_id,
         content_reg,
-        num_fields: columns.len() + 1,
+        num_fields: idx.columns.len() + 1,
     });
 
     // open the table we are creating the index on for reading
@@ -145,19 +160,37 @@ pub fn translate_create_index(
     // emit MakeRecord (index key + rowid) into record_reg.
     //
     // Then insert the record into the sorter
-    let start_reg = program.alloc_registers(columns.len() + 1);
-    for (i, (col, _)) in columns.iter().enumerate() {
-        program.emit_column(table_cursor_id, col.0, start_reg + i);
-    }
-    let rowid_reg = start_reg + columns.len();
+    let start_reg = program.alloc_registers(idx.columns.len() + 1);
+    let rowid_reg = start_reg + idx.columns.len();
     program.emit_insn(Insn::RowId {
         cursor_id: table_cursor_id,
         dest: rowid_reg,
     });
+    // A partial index only holds the rows that satisfy its WHERE clause.
+    let label_next = program.allocate_label();
+    let resolver = Resolver::new(schema, syms);
+    emit_partial_index_check_for_cursor(
+        &mut program,
+        &resolver,
+        &tbl,
+        &idx,
+        table_cursor_id,
+        rowid_reg,
+        label_next,
+    )?;
+    emit_index_key_for_cursor(
+        &mut program,
+        &resolver,
+        &tbl,
+        &idx,
+        table_cursor_id,
+        rowid_reg,
+        start_reg,
+    )?;
     let record_reg = program.alloc_register();
     program.emit_insn(Insn::MakeRecord {
         start_reg,
-        count: columns.len() + 1,
+        count: idx.columns.len() + 1,
         dest_reg: record_reg,
         index_name: Some(idx_name.clone()),
     });
@@ -166,6 +199,7 @@ pub fn translate_create_index(
         record_reg,
     });
 
+    program.preassign_label_to_next_insn(label_next);
     program.emit_insn(Insn::Next {
         cursor_id: table_cursor_id,
         pc_if_next: loop_start_label,
@@ -242,25 +276,49 @@ pub fn translate_create_index(
     Ok(program)
 }
 
-fn resolve_sorted_columns<'a>(
-    table: &'a BTreeTable,
+fn resolve_sorted_columns(
+    table: &BTreeTable,
     cols: &[SortedColumn],
-) -> crate::Result<Vec<((usize, &'a Column), SortOrder)>> {
+) -> crate::Result<Vec<IndexColumn>> {
     let mut resolved = Vec::with_capacity(cols.len());
     for sc in cols {
-        let ident = normalize_ident(match &sc.expr {
-            // SQLite supports indexes on arbitrary expressions, but we don't (yet).
-            // See "How to use indexes on expressions" in https://www.sqlite.org/expridx.html
-            Expr::Id(Id(col_name)) | Expr::Name(ast::Name(col_name)) => col_name,
-            _ => crate::bail_parse_error!("Error: cannot use expressions in CREATE INDEX"),
-        });
-        let Some(col) = table.get_column(&ident) else {
+        let order = sc.order.unwrap_or(SortOrder::Asc);
+        let ident = match &sc.expr {
+            Expr::Id(Id(col_name)) | Expr::Name(ast::Name(col_name)) => normalize_ident(col_name),
+            // An index on an expression, see https://www.sqlite.org/expridx.html
+            expr => {
+                validate_index_expr(table, expr, "index expressions")?;
+                resolved.push(IndexColumn {
+                    name: normalize_ident(&expr.to_string()),
+                    order,
+                    pos_in_table: EXPR_INDEX_SENTINEL,
+                    collation: None,
+                    default: None,
+                    expr: Some(Box::new(expr.clone())),
+                });
+                continue;
+            }
+        };
+        let Some((pos_in_table, col)) = table.get_column(&ident) else {
             crate::bail_parse_error!(
                 "Error: column '{ident}' does not exist in table '{}'",
                 table.name
             );
         };
-        resolved.push((col, sc.order.unwrap_or(SortOrder::Asc)));
+        if col.is_virtual() {
+            crate::bail_parse_error!(
+                "cannot index VIRTUAL generated column \"{}\"",
+                col.name.as_deref().unwrap_or_default()
+            );
+        }
+        resolved.push(IndexColumn {
+            name: col.name.as_ref().unwrap().clone(),
+            order,
+            pos_in_table,
+            collation: col.collation,
+            default: col.default.clone(),
+            expr: None,
+        });
     }
     Ok(resolved)
 }
@@ -269,7 +327,8 @@ fn create_idx_stmt_to_sql(
     tbl_name: &str,
     idx_name: &str,
     unique_if_not_exists: (bool, bool),
-    cols: &[((usize, &Column), SortOrder)],
+    cols: &[IndexColumn],
+    where_clause: Option<&Expr>,
 ) -> String {
     let mut sql = String::with_capacity(128);
     sql.push_str("CREATE ");
@@ -280,23 +339,201 @@ fn create_idx_stmt_to_sql(
     if unique_if_not_exists.1 {
         sql.push_str("IF NOT EXISTS ");
     }
-    sql.push_str(idx_name);
+    sql.push_str(&quote_identifier(idx_name));
     sql.push_str(" ON ");
-    sql.push_str(tbl_name);
+    sql.push_str(&quote_identifier(tbl_name));
     sql.push_str(" (");
-    for (i, (col, order)) in cols.iter().enumerate() {
+    for (i, col) in cols.iter().enumerate() {
         if i > 0 {
             sql.push_str(", ");
         }
-        sql.push_str(col.1.name.as_ref().unwrap());
-        if *order == SortOrder::Desc {
+        match &col.expr {
+            Some(expr) => sql.push_str(&expr.to_string()),
+            None => sql.push_str(&quote_identifier(&col.name)),
+        }
+        if col.order == SortOrder::Desc {
             sql.push_str(" DESC");
         }
     }
     sql.push(')');
+    if let Some(where_clause) = where_clause {
+        sql.push_str(" WHERE ");
+        sql.push_str(&where_clause.to_string());
+    }
     sql
 }
 
+/// Checks that an indexed expression or the WHERE clause of a partial index only refers to
+/// columns of its table and does not use subqueries, like SQLite does.
+fn validate_index_expr(table: &BTreeTable, expr: &Expr, what: &str) -> Result<()> {
+    walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        if matches!(
+            expr,
+            Expr::Subquery(_) | Expr::Exists(_) | Expr::InSelect { .. } | Expr::InTable { .. }
+        ) {
+            crate::bail_parse_error!("subqueries prohibited in {what}");
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    bind_table_expr(table, expr, TableInternalId::default())?;
+    Ok(())
+}
+
+/// The columns of `table` that the entries of `index` depend on: its key columns, the
+/// columns its indexed expressions refer to and the columns its WHERE clause refers to.
+pub(crate) fn index_column_dependencies(table: &BTreeTable, index: &Index) -> Result<Vec<usize>> {
+    let mut dependencies = vec![];
+    let mut exprs = vec![];
+    for column in &index.columns {
+        match &column.expr {
+            Some(expr) => exprs.push(expr),
+            None => dependencies.push(column.pos_in_table),
+        }
+    }
+    exprs.extend(&index.where_clause);
+    for expr in exprs {
+        let expr = bind_table_expr(table, expr, TableInternalId::default())?;
+        walk_expr(&expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            match expr {
+                Expr::Column { column, .. } => dependencies.push(*column),
+                Expr::RowId { .. } => {
+                    dependencies.extend(table.get_rowid_alias_column().map(|(pos, _)| pos))
+                }
+                _ => {}
+            }
+            Ok(WalkControl::Continue)
+        })?;
+    }
+    Ok(dependencies)
+}
+
+/// Evaluates `expr`, an expression of `table`'s schema, for the row held by `row` into `reg`.
+fn emit_row_expr(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    expr: &Expr,
+    row: RowRegisters,
+    reg: usize,
+) -> Result<()> {
+    let expr = bind_table_expr(table, expr, TableInternalId::default())?;
+    let columns = row_register_exprs(table, row);
+    let mut resolver = Resolver::new(resolver.schema, resolver.symbol_table);
+    resolver.enable_expr_to_reg_cache();
+    resolver
+        .expr_to_reg_cache
+        .extend(columns.iter().map(|(expr, reg)| (expr, *reg)));
+    translate_expr(program, None, &expr, reg, &resolver)?;
+    Ok(())
+}
+
+/// Loads the row `cursor_id` is positioned on, whose rowid is held by `rowid_reg`, into
+/// registers, computing its generated columns.
+fn emit_cursor_row(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    cursor_id: CursorID,
+    rowid_reg: usize,
+) -> Result<RowRegisters> {
+    let row = RowRegisters {
+        rowid: rowid_reg,
+        columns_start: program.alloc_registers(table.columns.len()),
+    };
+    for (idx, column) in table.columns.iter().enumerate() {
+        if !column.is_rowid_alias {
+            program.emit_column(cursor_id, idx, row.columns_start + idx);
+        }
+    }
+    emit_generated_columns(program, resolver, table, row)?;
+    Ok(row)
+}
+
+/// Emits a jump to `label_skip` unless the row held by `row` satisfies the WHERE clause of
+/// the partial index `index`. Does nothing for an index that is not partial.
+pub(crate) fn emit_partial_index_check(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    row: RowRegisters,
+    label_skip: BranchOffset,
+) -> Result<()> {
+    let Some(where_clause) = &index.where_clause else {
+        return Ok(());
+    };
+    let reg = program.alloc_register();
+    emit_row_expr(program, resolver, table, where_clause, row, reg)?;
+    program.emit_insn(Insn::IfNot {
+        reg,
+        target_pc: label_skip,
+        jump_if_null: true,
+    });
+    Ok(())
+}
+
+/// Like [emit_partial_index_check], for the row `cursor_id` is positioned on, whose rowid is
+/// held by `rowid_reg`.
+pub(crate) fn emit_partial_index_check_for_cursor(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    cursor_id: CursorID,
+    rowid_reg: usize,
+    label_skip: BranchOffset,
+) -> Result<()> {
+    if index.where_clause.is_none() {
+        return Ok(());
+    }
+    let row = emit_cursor_row(program, resolver, table, cursor_id, rowid_reg)?;
+    emit_partial_index_check(program, resolver, table, index, row, label_skip)
+}
+
+/// Emits the key columns of `index` for the row held by `row` into consecutive registers
+/// starting at `start_reg`, evaluating the indexed expressions.
+pub(crate) fn emit_index_key(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    row: RowRegisters,
+    start_reg: usize,
+) -> Result<()> {
+    for (i, column) in index.columns.iter().enumerate() {
+        match &column.expr {
+            Some(expr) => emit_row_expr(program, resolver, table, expr, row, start_reg + i)?,
+            None => program.emit_insn(Insn::Copy {
+                src_reg: row.column(table, column.pos_in_table),
+                dst_reg: start_reg + i,
+                amount: 0,
+            }),
+        }
+    }
+    Ok(())
+}
+
+/// Like [emit_index_key], for the row `cursor_id` is positioned on, whose rowid is held by
+/// `rowid_reg`.
+pub(crate) fn emit_index_key_for_cursor(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    cursor_id: CursorID,
+    rowid_reg: usize,
+    start_reg: usize,
+) -> Result<()> {
+    if !index.has_expressions() {
+        for (i, column) in index.columns.iter().enumerate() {
+            program.emit_column(cursor_id, column.pos_in_table, start_reg + i);
+        }
+        return Ok(());
+    }
+    let row = emit_cursor_row(program, resolver, table, cursor_id, rowid_reg)?;
+    emit_index_key(program, resolver, table, index, row, start_reg)
+}
+
 pub fn translate_drop_index(
     idx_name: &str,
     if_exists: bool,
@@ -417,6 +654,7 @@ pub fn translate_drop_index(
 
     program.emit_insn(Insn::Delete {
         cursor_id: sqlite_schema_cursor_id,
+        table_name: SQLITE_TABLEID.to_string(),
     });
 
     program.resolve_label(next_label, program.offset());
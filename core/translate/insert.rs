@@ -1,14 +1,17 @@
+use std::num::NonZeroUsize;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use turso_sqlite3_parser::ast::{
     DistinctNames, Expr, InsertBody, OneSelect, QualifiedName, ResolveType, ResultColumn, With,
 };
 
 use crate::error::{SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY};
-use crate::schema::{IndexColumn, Table};
+use crate::schema::{BTreeTable, IndexColumn, Table, EXPR_INDEX_SENTINEL};
+use crate::translate::collate::CollationSeq;
 use crate::util::normalize_ident;
 use crate::vdbe::builder::ProgramBuilderOpts;
-use crate::vdbe::insn::{IdxInsertFlags, InsertFlags, RegisterOrLiteral};
+use crate::vdbe::insn::{CmpInsFlags, IdxInsertFlags, InsertFlags, RegisterOrLiteral};
 use crate::vdbe::BranchOffset;
 use crate::{
     schema::{Column, Schema},
@@ -19,10 +22,15 @@ use crate::{
 };
 use crate::{Result, SymbolTable, VirtualTable};
 
+use super::check::emit_check_constraints;
 use super::emitter::Resolver;
 use super::expr::{translate_expr, translate_expr_no_constant_opt, NoConstantOptReason};
+use super::fkey::{emit_fk_child_checks, RowRegisters};
+use super::generated::{emit_generated_columns, emit_table_record};
+use super::index::{emit_index_key, emit_partial_index_check};
 use super::optimizer::rewrite_expr;
 use super::plan::QueryDestination;
+use super::schema::SQLITE_SEQUENCE_TABLE;
 use super::select::translate_select;
 
 struct TempTableCtx {
@@ -31,6 +39,19 @@ struct TempTableCtx {
     loop_end_label: BranchOffset,
 }
 
+/// Registers tracking the `sqlite_sequence` row of an AUTOINCREMENT table during an INSERT.
+#[derive(Debug, Clone, Copy)]
+struct AutoincrementCtx {
+    /// Write cursor on `sqlite_sequence`.
+    cursor_id: usize,
+    /// Rowid of the table's row in `sqlite_sequence`, NULL if it has none yet.
+    seq_rowid_reg: usize,
+    /// Largest rowid used so far, updated as rows are inserted.
+    seq_reg: usize,
+    /// The value of `seq_reg` before the INSERT.
+    seq_orig_reg: usize,
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn translate_insert(
     schema: &Schema,
@@ -40,6 +61,8 @@ pub fn translate_insert(
     columns: Option<DistinctNames>,
     mut body: InsertBody,
     _returning: Option<Vec<ResultColumn>>,
+    enforce_foreign_keys: bool,
+    enforce_check_constraints: bool,
     syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
@@ -87,11 +110,13 @@ pub fn translate_insert(
     let Some(btree_table) = table.btree() else {
         crate::bail_parse_error!("no such table: {}", table_name);
     };
-    if !btree_table.has_rowid {
-        crate::bail_parse_error!("INSERT into WITHOUT ROWID table is not supported");
-    }
-
     let root_page = btree_table.root_page;
+    // A WITHOUT ROWID table is stored in an index b-tree keyed by its primary key.
+    let table_cursor_type = if btree_table.has_rowid {
+        CursorType::BTreeTable(btree_table.clone())
+    } else {
+        CursorType::BTreeIndex(Arc::new(btree_table.without_rowid_index()))
+    };
 
     let mut values: Option<Vec<Expr>> = None;
     let inserting_multiple_rows = match &mut body {
@@ -116,6 +141,16 @@ pub fn translate_insert(
     let halt_label = program.allocate_label();
     let loop_start_label = program.allocate_label();
 
+    let autoincrement = if btree_table.has_autoincrement {
+        Some(emit_autoincrement_begin(
+            &mut program,
+            schema,
+            &btree_table.name,
+        )?)
+    } else {
+        None
+    };
+
     let mut yield_reg_opt = None;
     let mut temp_table_ctx = None;
     let (num_values, cursor_id) = match body {
@@ -126,7 +161,7 @@ pub fn translate_insert(
             {
                 (
                     values.as_ref().unwrap().len(),
-                    program.alloc_cursor_id(CursorType::BTreeTable(btree_table.clone())),
+                    program.alloc_cursor_id(table_cursor_type),
                 )
             } else {
                 // Multiple rows - use coroutine for value population
@@ -153,8 +188,7 @@ pub fn translate_insert(
                 program.emit_insn(Insn::EndCoroutine { yield_reg });
                 program.preassign_label_to_next_insn(jump_on_definition_label);
 
-                let cursor_id =
-                    program.alloc_cursor_id(CursorType::BTreeTable(btree_table.clone()));
+                let cursor_id = program.alloc_cursor_id(table_cursor_type);
 
                 // From SQLite
                 /* Set useTempTable to TRUE if the result of the SELECT statement
@@ -247,10 +281,7 @@ pub fn translate_insert(
                 (result.num_result_cols, cursor_id)
             }
         }
-        InsertBody::DefaultValues => (
-            0,
-            program.alloc_cursor_id(CursorType::BTreeTable(btree_table.clone())),
-        ),
+        InsertBody::DefaultValues => (0, program.alloc_cursor_id(table_cursor_type)),
     };
 
     // allocate cursor id's for each btree index cursor we'll need to populate the indexes
@@ -361,45 +392,55 @@ pub fn translate_insert(
         });
     }
 
-    // Create new rowid if a) not provided by user or b) provided by user but is NULL
-    program.emit_insn(Insn::NewRowid {
-        cursor: cursor_id,
-        rowid_reg,
-        prev_largest_reg: 0,
-    });
-
-    if let Some(must_be_int_label) = check_rowid_is_integer_label {
-        program.resolve_label(must_be_int_label, program.offset());
-        // If the user provided a rowid, it must be an integer.
-        program.emit_insn(Insn::MustBeInt { reg: rowid_reg });
-    }
-
-    // Check uniqueness constraint for rowid if it was provided by user.
-    // When the DB allocates it there are no need for separate uniqueness checks.
-    if has_user_provided_rowid {
-        let make_record_label = program.allocate_label();
-        program.emit_insn(Insn::NotExists {
+    // A WITHOUT ROWID table has no rowid to allocate, its rows are keyed by the primary key.
+    if btree_table.has_rowid {
+        // Create new rowid if a) not provided by user or b) provided by user but is NULL
+        program.emit_insn(Insn::NewRowid {
             cursor: cursor_id,
             rowid_reg,
-            target_pc: make_record_label,
+            prev_largest_reg: autoincrement.map_or(0, |ctx| ctx.seq_reg),
         });
-        let rowid_column_name = if let Some(index) = rowid_alias_index {
-            btree_table
-                .columns
-                .get(index)
-                .unwrap()
-                .name
-                .as_ref()
-                .expect("column name is None")
-        } else {
-            "rowid"
-        };
 
-        program.emit_insn(Insn::Halt {
-            err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
-            description: format!("{}.{}", table_name.0, rowid_column_name),
-        });
-        program.preassign_label_to_next_insn(make_record_label);
+        if let Some(must_be_int_label) = check_rowid_is_integer_label {
+            program.resolve_label(must_be_int_label, program.offset());
+            // If the user provided a rowid, it must be an integer.
+            program.emit_insn(Insn::MustBeInt { reg: rowid_reg });
+        }
+
+        // Check uniqueness constraint for rowid if it was provided by user.
+        // When the DB allocates it there are no need for separate uniqueness checks.
+        if has_user_provided_rowid {
+            let make_record_label = program.allocate_label();
+            program.emit_insn(Insn::NotExists {
+                cursor: cursor_id,
+                rowid_reg,
+                target_pc: make_record_label,
+            });
+            let rowid_column_name = if let Some(index) = rowid_alias_index {
+                btree_table
+                    .columns
+                    .get(index)
+                    .unwrap()
+                    .name
+                    .as_ref()
+                    .expect("column name is None")
+            } else {
+                "rowid"
+            };
+
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                description: format!("{}.{}", table.get_name(), rowid_column_name),
+            });
+            program.preassign_label_to_next_insn(make_record_label);
+        }
+
+        if let Some(ctx) = autoincrement {
+            program.emit_insn(Insn::MemMax {
+                dest_reg: ctx.seq_reg,
+                src_reg: rowid_reg,
+            });
+        }
     }
 
     match table.btree() {
@@ -411,7 +452,38 @@ pub fn translate_insert(
                 table_reference: Rc::clone(&t),
             });
         }
-        _ => (),
+        // Values are converted to the column affinities before they reach the table and its indexes.
+        Some(t) => {
+            program.emit_insn(Insn::Affinity {
+                start_reg: column_registers_start,
+                count: NonZeroUsize::new(num_cols).unwrap(),
+                affinities: t.column_affinities(),
+            });
+        }
+        None => (),
+    }
+
+    emit_generated_columns(
+        &mut program,
+        &resolver,
+        &btree_table,
+        RowRegisters {
+            rowid: rowid_reg,
+            columns_start: column_registers_start,
+        },
+    )?;
+
+    if enforce_check_constraints {
+        emit_check_constraints(
+            &mut program,
+            &resolver,
+            &btree_table,
+            RowRegisters {
+                rowid: rowid_reg,
+                columns_start: column_registers_start,
+            },
+            None,
+        )?;
     }
 
     let index_col_mappings = resolve_indicies_for_insert(schema, table.as_ref(), &column_mappings)?;
@@ -423,20 +495,41 @@ pub fn translate_insert(
             .map(|(_, _, c_id)| *c_id)
             .expect("no cursor found for index");
 
+        let index = schema
+            .get_index(&table_name.0, &index_col_mapping.idx_name)
+            .expect("index should be present");
+
+        // A partial index only holds the rows that satisfy its WHERE clause.
+        let label_skip_index = program.allocate_label();
+        emit_partial_index_check(
+            &mut program,
+            &resolver,
+            &btree_table,
+            index,
+            RowRegisters {
+                rowid: rowid_reg,
+                columns_start: column_registers_start,
+            },
+            label_skip_index,
+        )?;
+
         let num_cols = index_col_mapping.columns.len();
         // allocate scratch registers for the index columns plus rowid
         let idx_start_reg = program.alloc_registers(num_cols + 1);
 
-        // copy each index column from the table's column registers into these scratch regs
-        for (i, col) in index_col_mapping.columns.iter().enumerate() {
-            // copy from the table's column register over to the index's scratch register
-
-            program.emit_insn(Insn::Copy {
-                src_reg: column_registers_start + col.0,
-                dst_reg: idx_start_reg + i,
-                amount: 0,
-            });
-        }
+        // copy each index column from the table's column registers into these scratch regs,
+        // evaluating the indexed expressions
+        emit_index_key(
+            &mut program,
+            &resolver,
+            &btree_table,
+            index,
+            RowRegisters {
+                rowid: rowid_reg,
+                columns_start: column_registers_start,
+            },
+            idx_start_reg,
+        )?;
         // last register is the rowid
         program.emit_insn(Insn::Copy {
             src_reg: rowid_reg,
@@ -444,10 +537,6 @@ pub fn translate_insert(
             amount: 0,
         });
 
-        let index = schema
-            .get_index(&table_name.0, &index_col_mapping.idx_name)
-            .expect("index should be present");
-
         let record_reg = program.alloc_register();
         program.emit_insn(Insn::MakeRecord {
             start_reg: idx_start_reg,
@@ -464,28 +553,33 @@ pub fn translate_insert(
                 record_reg: idx_start_reg,
                 num_regs: num_cols,
             });
-            let column_names = index_col_mapping.columns.iter().enumerate().fold(
-                String::with_capacity(50),
-                |mut accum, (idx, (index, _))| {
-                    if idx > 0 {
-                        accum.push_str(", ");
-                    }
-
-                    accum.push_str(&btree_table.name);
-                    accum.push('.');
-
-                    let name = btree_table
-                        .columns
-                        .get(*index)
-                        .unwrap()
-                        .name
-                        .as_ref()
-                        .expect("column name is None");
-                    accum.push_str(name);
-
-                    accum
-                },
-            );
+            // Like SQLite, a unique index on expressions is reported by name.
+            let column_names = if index.has_expressions() {
+                format!("index '{}'", index.name)
+            } else {
+                index_col_mapping.columns.iter().enumerate().fold(
+                    String::with_capacity(50),
+                    |mut accum, (idx, (index, _))| {
+                        if idx > 0 {
+                            accum.push_str(", ");
+                        }
+
+                        accum.push_str(&btree_table.name);
+                        accum.push('.');
+
+                        let name = btree_table
+                            .columns
+                            .get(*index)
+                            .unwrap()
+                            .name
+                            .as_ref()
+                            .expect("column name is None");
+                        accum.push_str(name);
+
+                        accum
+                    },
+                )
+            };
 
             program.emit_insn(Insn::Halt {
                 err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
@@ -504,12 +598,14 @@ pub fn translate_insert(
             // TODO: figure out how to determine whether or not we need to seek prior to insert.
             flags: IdxInsertFlags::new(),
         });
+        program.preassign_label_to_next_insn(label_skip_index);
     }
 
+    // The primary key columns of a WITHOUT ROWID table are implicitly NOT NULL.
     for (i, col) in column_mappings
         .iter()
         .enumerate()
-        .filter(|(_, col)| col.column.notnull)
+        .filter(|(_, col)| col.column.notnull || (!btree_table.has_rowid && col.column.primary_key))
     {
         let target_reg = i + column_registers_start;
         program.emit_insn(Insn::HaltIfNull {
@@ -517,7 +613,7 @@ pub fn translate_insert(
             err_code: SQLITE_CONSTRAINT_NOTNULL,
             description: format!(
                 "{}.{}",
-                table_name,
+                table.get_name(),
                 col.column
                     .name
                     .as_ref()
@@ -526,20 +622,46 @@ pub fn translate_insert(
         });
     }
     // Create and insert the record
-    program.emit_insn(Insn::MakeRecord {
-        start_reg: column_registers_start,
-        count: num_cols,
-        dest_reg: record_register,
-        index_name: None,
-    });
+    if btree_table.has_rowid {
+        emit_table_record(
+            &mut program,
+            &btree_table,
+            RowRegisters {
+                rowid: rowid_reg,
+                columns_start: column_registers_start,
+            },
+            record_register,
+        );
 
-    program.emit_insn(Insn::Insert {
-        cursor: cursor_id,
-        key_reg: rowid_reg,
-        record_reg: record_register,
-        flag: InsertFlags::new(),
-        table_name: table_name.to_string(),
-    });
+        program.emit_insn(Insn::Insert {
+            cursor: cursor_id,
+            key_reg: rowid_reg,
+            record_reg: record_register,
+            flag: InsertFlags::new(),
+            table_name: table_name.to_string(),
+        });
+    } else {
+        emit_without_rowid_insert(
+            &mut program,
+            &btree_table,
+            cursor_id,
+            column_registers_start,
+            record_register,
+        );
+    }
+
+    if enforce_foreign_keys {
+        emit_fk_child_checks(
+            &mut program,
+            &resolver,
+            &btree_table,
+            RowRegisters {
+                rowid: rowid_reg,
+                columns_start: column_registers_start,
+            },
+            None,
+        )?;
+    }
 
     if inserting_multiple_rows {
         if let Some(temp_table_ctx) = temp_table_ctx {
@@ -561,11 +683,201 @@ pub fn translate_insert(
     }
 
     program.resolve_label(halt_label, program.offset());
+    if let Some(ctx) = autoincrement {
+        emit_autoincrement_end(&mut program, ctx, &btree_table.name);
+    }
     program.epilogue(super::emitter::TransactionMode::Write);
 
     Ok(program)
 }
 
+/// Opens `sqlite_sequence` and loads the largest rowid ever used by the AUTOINCREMENT table
+/// `table_name`, or 0 if the table has no row there yet.
+fn emit_autoincrement_begin(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    table_name: &str,
+) -> Result<AutoincrementCtx> {
+    let Some(sequence_table) = schema.get_btree_table(SQLITE_SEQUENCE_TABLE) else {
+        crate::bail_parse_error!("no such table: {}", SQLITE_SEQUENCE_TABLE);
+    };
+    let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(sequence_table.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id,
+        root_page: RegisterOrLiteral::Literal(sequence_table.root_page),
+        name: SQLITE_SEQUENCE_TABLE.to_string(),
+    });
+
+    let ctx = AutoincrementCtx {
+        cursor_id,
+        seq_rowid_reg: program.alloc_register(),
+        seq_reg: program.alloc_register(),
+        seq_orig_reg: program.alloc_register(),
+    };
+    program.emit_insn(Insn::Null {
+        dest: ctx.seq_rowid_reg,
+        dest_end: None,
+    });
+    program.emit_insn(Insn::Integer {
+        value: 0,
+        dest: ctx.seq_reg,
+    });
+
+    let name_reg = program.alloc_register();
+    program.emit_insn(Insn::String8 {
+        value: table_name.to_string(),
+        dest: name_reg,
+    });
+    let loop_end = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: loop_end,
+    });
+    let loop_start = program.allocate_label();
+    let next = program.allocate_label();
+    program.preassign_label_to_next_insn(loop_start);
+    let value_reg = program.alloc_register();
+    program.emit_column(cursor_id, 0, value_reg);
+    program.emit_insn(Insn::Ne {
+        lhs: value_reg,
+        rhs: name_reg,
+        target_pc: next,
+        flags: CmpInsFlags::default().jump_if_null(),
+        collation: Some(CollationSeq::NoCase),
+    });
+    program.emit_insn(Insn::RowId {
+        cursor_id,
+        dest: ctx.seq_rowid_reg,
+    });
+    program.emit_column(cursor_id, 1, ctx.seq_reg);
+    program.emit_insn(Insn::Goto {
+        target_pc: loop_end,
+    });
+    program.preassign_label_to_next_insn(next);
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: loop_start,
+    });
+    program.preassign_label_to_next_insn(loop_end);
+    program.emit_insn(Insn::Copy {
+        src_reg: ctx.seq_reg,
+        dst_reg: ctx.seq_orig_reg,
+        amount: 0,
+    });
+    Ok(ctx)
+}
+
+/// Writes the largest rowid used by the INSERT back to `sqlite_sequence`, if it grew.
+fn emit_autoincrement_end(program: &mut ProgramBuilder, ctx: AutoincrementCtx, table_name: &str) {
+    let done = program.allocate_label();
+    program.emit_insn(Insn::Le {
+        lhs: ctx.seq_reg,
+        rhs: ctx.seq_orig_reg,
+        target_pc: done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    let has_row = program.allocate_label();
+    program.emit_insn(Insn::NotNull {
+        reg: ctx.seq_rowid_reg,
+        target_pc: has_row,
+    });
+    program.emit_insn(Insn::NewRowid {
+        cursor: ctx.cursor_id,
+        rowid_reg: ctx.seq_rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.preassign_label_to_next_insn(has_row);
+    let record_start = program.alloc_registers(2);
+    program.emit_insn(Insn::String8 {
+        value: table_name.to_string(),
+        dest: record_start,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: ctx.seq_reg,
+        dst_reg: record_start + 1,
+        amount: 0,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: record_start,
+        count: 2,
+        dest_reg: record_reg,
+        index_name: None,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: ctx.cursor_id,
+        key_reg: ctx.seq_rowid_reg,
+        record_reg,
+        flag: InsertFlags::new().internal(),
+        table_name: SQLITE_SEQUENCE_TABLE.to_string(),
+    });
+    program.preassign_label_to_next_insn(done);
+}
+
+/// Inserts the row held in the registers starting at `column_registers_start` into the
+/// WITHOUT ROWID table opened by `cursor_id`. The record holds the primary key columns first,
+/// and the primary key must not already be present in the table.
+fn emit_without_rowid_insert(
+    program: &mut ProgramBuilder,
+    btree_table: &BTreeTable,
+    cursor_id: usize,
+    column_registers_start: usize,
+    record_register: usize,
+) {
+    let record_columns = btree_table.record_columns();
+    let start_reg = program.alloc_registers(record_columns.len());
+    for (i, column) in record_columns.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: column_registers_start + column,
+            dst_reg: start_reg + i,
+            amount: 0,
+        });
+    }
+    program.emit_insn(Insn::MakeRecord {
+        start_reg,
+        count: record_columns.len(),
+        dest_reg: record_register,
+        index_name: None,
+    });
+
+    let num_pk_cols = btree_table.primary_key_columns.len();
+    let label_insert = program.allocate_label();
+    program.emit_insn(Insn::NoConflict {
+        cursor_id,
+        target_pc: label_insert,
+        record_reg: start_reg,
+        num_regs: num_pk_cols,
+    });
+    let column_names = record_columns[..num_pk_cols]
+        .iter()
+        .map(|column| {
+            format!(
+                "{}.{}",
+                btree_table.name,
+                btree_table.columns[*column]
+                    .name
+                    .as_ref()
+                    .expect("column name is None")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    program.emit_insn(Insn::Halt {
+        err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+        description: column_names,
+    });
+    program.preassign_label_to_next_insn(label_insert);
+
+    program.emit_insn(Insn::IdxInsert {
+        cursor_id,
+        record_reg: record_register,
+        unpacked_start: Some(start_reg),
+        unpacked_count: Some(record_columns.len() as u16),
+        flags: IdxInsertFlags::new(),
+    });
+}
+
 #[derive(Debug)]
 /// Represents how a column should be populated during an INSERT.
 /// Contains both the column definition and optionally the index into the VALUES tuple.
@@ -603,22 +915,31 @@ fn resolve_columns_for_insert<'a>(
     let table_columns = table.columns();
     // Case 1: No columns specified - map values to columns in order
     if columns.is_none() {
-        if num_values != table_columns.len() {
+        // Generated columns don't take a value.
+        let num_insertable_columns = table_columns
+            .iter()
+            .filter(|col| !col.is_generated())
+            .count();
+        if num_values != num_insertable_columns {
             crate::bail_parse_error!(
                 "table {} has {} columns but {} values were supplied",
                 &table.get_name(),
-                table_columns.len(),
+                num_insertable_columns,
                 num_values
             );
         }
 
         // Map each column to either its corresponding value index or None
+        let mut value_indexes = 0..num_values;
         return Ok(table_columns
             .iter()
-            .enumerate()
-            .map(|(i, col)| ColumnMapping {
+            .map(|col| ColumnMapping {
                 column: col,
-                value_index: if i < num_values { Some(i) } else { None },
+                value_index: if col.is_generated() {
+                    None
+                } else {
+                    value_indexes.next()
+                },
                 default_value: col.default.as_ref(),
             })
             .collect());
@@ -650,6 +971,9 @@ fn resolve_columns_for_insert<'a>(
                 column_name
             );
         };
+        if table_columns[table_index].is_generated() {
+            crate::bail_parse_error!("cannot INSERT into generated column \"{}\"", column_name);
+        }
 
         mappings[table_index].value_index = Some(value_index);
     }
@@ -695,6 +1019,11 @@ fn resolve_indicies_for_insert(
         // For each column in the index (in the order defined by the index),
         // try to find the corresponding column in the insert’s column mapping.
         for idx_col in &index.columns {
+            if idx_col.expr.is_some() {
+                idx_map.columns.push((EXPR_INDEX_SENTINEL, idx_col.clone()));
+                idx_map.value_indicies.push(None);
+                continue;
+            }
             let target_name = normalize_ident(idx_col.name.as_str());
             if let Some((i, col_mapping)) = columns.iter().enumerate().find(|(_, mapping)| {
                 mapping
@@ -738,11 +1067,13 @@ fn populate_columns_multiple_rows(
             // Decrement as we have now seen a value index instead
             other_values_seen -= 1;
             if let Some(temp_table_ctx) = temp_table_ctx {
-                program.emit_column(
-                    temp_table_ctx.cursor_id,
-                    value_index_seen,
-                    column_registers_start + i,
-                );
+                // The temp table holds the SELECT's result columns, not the table's columns.
+                program.emit_insn(Insn::Column {
+                    cursor_id: temp_table_ctx.cursor_id,
+                    column: value_index_seen,
+                    dest: column_registers_start + i,
+                    default: None,
+                });
             } else {
                 program.emit_insn(Insn::Copy {
                     src_reg: yield_reg + value_index_seen,
@@ -1,7 +1,7 @@
 use turso_ext::VTabKind;
 use turso_sqlite3_parser::ast::{self, SortOrder};
 
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc};
 
 use crate::{
     schema::{Affinity, Index, IndexColumn, Table},
@@ -20,9 +20,9 @@ use crate::{
 
 use super::{
     aggregation::translate_aggregation_step,
-    emitter::{OperationMode, TranslateCtx},
+    emitter::{OperationMode, Resolver, TranslateCtx},
     expr::{
-        translate_condition_expr, translate_expr, translate_expr_no_constant_opt,
+        compare_affinity, translate_condition_expr, translate_expr, translate_expr_no_constant_opt,
         ConditionMetadata, NoConstantOptReason,
     },
     group_by::{group_by_agg_phase, GroupByMetadata, GroupByRowSource},
@@ -32,6 +32,7 @@ use super::{
         convert_where_to_vtab_constraint, Aggregate, GroupBy, IterationDirection, JoinOrderMember,
         Operation, QueryDestination, Search, SeekDef, SelectPlan, TableReferences, WhereTerm,
     },
+    window::window_sorter_insert,
 };
 
 // Metadata for handling LEFT JOIN operations
@@ -43,6 +44,20 @@ pub struct LeftJoinMetadata {
     pub label_match_flag_set_true: BranchOffset,
     // label for the instruction that checks if the match flag is true
     pub label_match_flag_check_value: BranchOffset,
+    // bookkeeping for the second pass of a FULL OUTER JOIN, if this is one
+    pub full_join: Option<FullJoinMetadata>,
+}
+
+// Metadata for handling the second pass of a FULL OUTER JOIN, which emits the rows of the
+// right table that did not match any row of the left tables.
+#[derive(Debug)]
+pub struct FullJoinMetadata {
+    // ephemeral index of the rowids of the right table rows that matched in the main loop
+    pub matched_rowids: DistinctCtx,
+    // integer register that is set to true while the second pass is running
+    pub reg_second_pass: usize,
+    // label for the instruction that advances the right table in the second pass
+    pub label_second_pass_next: BranchOffset,
 }
 
 /// Jump labels for each loop in the query's main execution loop
@@ -83,10 +98,12 @@ pub fn init_distinct(program: &mut ProgramBuilder, plan: &SelectPlan) -> Distinc
                 pos_in_table: i,
                 collation: None, // FIXME: this should be determined based on the result column expression!
                 default: None, // FIXME: this should be determined based on the result column expression!
+                expr: None,
             })
             .collect(),
         unique: false,
         has_rowid: false,
+        where_clause: None,
     });
     let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
     let ctx = DistinctCtx {
@@ -139,9 +156,11 @@ pub fn init_loop(
                 pos_in_table: 0,
                 collation: None, // FIXME: this should be inferred from the expression
                 default: None,   // FIXME: this should be inferred from the expression
+                expr: None,
             }],
             has_rowid: false,
             unique: false,
+            where_clause: None,
         });
         let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
         if group_by.is_none() {
@@ -164,10 +183,49 @@ pub fn init_loop(
         // Initialize bookkeeping for OUTER JOIN
         if let Some(join_info) = table.join_info.as_ref() {
             if join_info.outer {
+                let full_join = if join_info.full {
+                    let index_name = format!("full_join_{}", table.identifier);
+                    let index = Arc::new(Index {
+                        name: index_name.clone(),
+                        table_name: String::new(),
+                        ephemeral: true,
+                        root_page: 0,
+                        columns: vec![IndexColumn {
+                            name: "rowid".to_string(),
+                            order: SortOrder::Asc,
+                            pos_in_table: 0,
+                            collation: None,
+                            default: None,
+                            expr: None,
+                        }],
+                        unique: false,
+                        has_rowid: false,
+                        where_clause: None,
+                    });
+                    let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index));
+                    program.emit_insn(Insn::OpenEphemeral {
+                        cursor_id,
+                        is_table: false,
+                    });
+                    let reg_second_pass = program.alloc_register();
+                    program.emit_int(0, reg_second_pass);
+                    Some(FullJoinMetadata {
+                        matched_rowids: DistinctCtx {
+                            cursor_id,
+                            ephemeral_index_name: index_name,
+                            label_on_conflict: program.allocate_label(),
+                        },
+                        reg_second_pass,
+                        label_second_pass_next: program.allocate_label(),
+                    })
+                } else {
+                    None
+                };
                 let lj_metadata = LeftJoinMetadata {
                     reg_match_flag: program.alloc_register(),
                     label_match_flag_set_true: program.allocate_label(),
                     label_match_flag_check_value: program.allocate_label(),
+                    full_join,
                 };
                 t_ctx.meta_left_joins[table_index] = Some(lj_metadata);
             }
@@ -363,6 +421,14 @@ pub fn open_loop(
     predicates: &[WhereTerm],
     temp_cursor_id: Option<CursorID>,
 ) -> Result<()> {
+    // With a FULL OUTER JOIN, the WHERE terms filter the joined rows, including the rows
+    // with NULLs for either side, so they are all evaluated after the join's bookkeeping.
+    let has_full_join = join_order.last().is_some_and(|member| {
+        table_references.joined_tables()[member.original_idx]
+            .join_info
+            .as_ref()
+            .is_some_and(|join_info| join_info.full)
+    });
     for (join_index, join) in join_order.iter().enumerate() {
         let joined_table_index = join.original_idx;
         let table = &table_references.joined_tables()[joined_table_index];
@@ -430,8 +496,8 @@ pub fn open_loop(
                                 // We then materialise the RHS/LHS into registers before issuing VFilter.
                                 let converted_constraints = predicates
                                     .iter()
-                                    .filter(|p| p.should_eval_at_loop(join_index, join_order))
                                     .enumerate()
+                                    .filter(|(_, p)| p.should_eval_at_loop(join_index, join_order))
                                     .filter_map(|(i, p)| {
                                         // Build ConstraintInfo from the predicates
                                         convert_where_to_vtab_constraint(
@@ -574,10 +640,10 @@ pub fn open_loop(
                     }
                 }
 
-                for cond in predicates
-                    .iter()
-                    .filter(|cond| cond.should_eval_at_loop(join_index, join_order))
-                {
+                for cond in predicates.iter().filter(|cond| {
+                    cond.should_eval_at_loop(join_index, join_order)
+                        && !(has_full_join && cond.from_outer_join.is_none())
+                }) {
                     let jump_target_when_true = program.allocate_label();
                     let condition_metadata = ConditionMetadata {
                         jump_if_condition_is_true: false,
@@ -616,6 +682,48 @@ pub fn open_loop(
                         src_reg,
                         target_pc: next,
                     });
+                } else if let Search::HashJoin { index, probe_keys } = search {
+                    let hash_cursor_id =
+                        index_cursor_id.expect("a hash join must have a hash table cursor");
+                    // The keys are compared like the two sides of the '=' they come from.
+                    let affinities = probe_keys
+                        .iter()
+                        .zip(index.columns.iter())
+                        .map(|(probe_key, col)| {
+                            let column_affinity = table.columns()[col.pos_in_table].affinity();
+                            compare_affinity(probe_key, column_affinity, Some(table_references))
+                                .aff_mask()
+                        })
+                        .collect::<String>();
+                    emit_hash_table_build(
+                        program,
+                        index,
+                        &affinities,
+                        table_cursor_id.expect("a hash join must have a source table cursor"),
+                        hash_cursor_id,
+                    );
+                    let key_start_reg = program.alloc_registers(probe_keys.len());
+                    for (i, probe_key) in probe_keys.iter().enumerate() {
+                        translate_expr(
+                            program,
+                            Some(table_references),
+                            probe_key,
+                            key_start_reg + i,
+                            &t_ctx.resolver,
+                        )?;
+                    }
+                    program.emit_insn(Insn::Affinity {
+                        start_reg: key_start_reg,
+                        count: NonZeroUsize::new(probe_keys.len()).unwrap(),
+                        affinities,
+                    });
+                    program.emit_insn(Insn::HashProbe {
+                        cursor_id: hash_cursor_id,
+                        key_start_reg,
+                        num_keys: probe_keys.len(),
+                        target_pc: loop_end,
+                    });
+                    program.preassign_label_to_next_insn(loop_start);
                 } else {
                     // Otherwise, it's an index/rowid scan, i.e. first a seek is performed and then a scan until the comparison expression is not satisfied anymore.
                     if let Search::Seek {
@@ -689,10 +797,10 @@ pub fn open_loop(
                     }
                 }
 
-                for cond in predicates
-                    .iter()
-                    .filter(|cond| cond.should_eval_at_loop(join_index, join_order))
-                {
+                for cond in predicates.iter().filter(|cond| {
+                    cond.should_eval_at_loop(join_index, join_order)
+                        && !(has_full_join && cond.from_outer_join.is_none())
+                }) {
                     let jump_target_when_true = program.allocate_label();
                     let condition_metadata = ConditionMetadata {
                         jump_if_condition_is_true: false,
@@ -718,11 +826,30 @@ pub fn open_loop(
         if let Some(join_info) = table.join_info.as_ref() {
             if join_info.outer {
                 let lj_meta = t_ctx.meta_left_joins[joined_table_index].as_ref().unwrap();
+                // For a FULL OUTER JOIN, remember that this row of the right table matched,
+                // so that the second pass does not emit it again with NULLs for the left tables.
+                if let Some(full_join) = &lj_meta.full_join {
+                    let rowid_reg = emit_full_join_rowid(program, table_cursor_id, index_cursor_id);
+                    full_join
+                        .matched_rowids
+                        .emit_deduplication_insns(program, 1, rowid_reg);
+                    program
+                        .preassign_label_to_next_insn(full_join.matched_rowids.label_on_conflict);
+                }
                 program.resolve_label(lj_meta.label_match_flag_set_true, program.offset());
                 program.emit_insn(Insn::Integer {
                     value: 1,
                     dest: lj_meta.reg_match_flag,
                 });
+                if lj_meta.full_join.is_some() {
+                    emit_full_join_where_terms(
+                        program,
+                        table_references,
+                        predicates,
+                        next,
+                        &t_ctx.resolver,
+                    )?;
+                }
             }
         }
     }
@@ -730,6 +857,59 @@ pub fn open_loop(
     Ok(())
 }
 
+/// Reads the rowid of the right table of a FULL OUTER JOIN into a new register.
+fn emit_full_join_rowid(
+    program: &mut ProgramBuilder,
+    table_cursor_id: Option<CursorID>,
+    index_cursor_id: Option<CursorID>,
+) -> usize {
+    let rowid_reg = program.alloc_register();
+    if let Some(index_cursor_id) = index_cursor_id {
+        program.emit_insn(Insn::IdxRowId {
+            cursor_id: index_cursor_id,
+            dest: rowid_reg,
+        });
+    } else {
+        program.emit_insn(Insn::RowId {
+            cursor_id: table_cursor_id.expect("FULL JOIN requires a table or index cursor"),
+            dest: rowid_reg,
+        });
+    }
+    rowid_reg
+}
+
+/// Evaluates the WHERE terms of a query with a FULL OUTER JOIN, jumping to `jump_target_when_false`
+/// if any of them is false. Terms consumed by the optimizer are evaluated as well, because the
+/// NULL-extended rows are not produced by the seeks that consumed them.
+fn emit_full_join_where_terms(
+    program: &mut ProgramBuilder,
+    table_references: &TableReferences,
+    predicates: &[WhereTerm],
+    jump_target_when_false: BranchOffset,
+    resolver: &Resolver,
+) -> Result<()> {
+    for cond in predicates
+        .iter()
+        .filter(|cond| cond.from_outer_join.is_none())
+    {
+        let jump_target_when_true = program.allocate_label();
+        let condition_metadata = ConditionMetadata {
+            jump_if_condition_is_true: false,
+            jump_target_when_true,
+            jump_target_when_false,
+        };
+        translate_condition_expr(
+            program,
+            table_references,
+            &cond.expr,
+            condition_metadata,
+            resolver,
+        )?;
+        program.preassign_label_to_next_insn(jump_target_when_true);
+    }
+    Ok(())
+}
+
 /// SQLite (and so Limbo) processes joins as a nested loop.
 /// The loop may emit rows to various destinations depending on the query:
 /// - a GROUP BY sorter (grouping is done by sorting based on the GROUP BY keys and aggregating while the GROUP BY keys match)
@@ -737,7 +917,9 @@ pub fn open_loop(
 /// - an ORDER BY sorter (when there is no GROUP BY, but there is an ORDER BY)
 /// - an AggStep (the columns are collected for aggregation, which is finished later)
 /// - a QueryResult (there is none of the above, so the loop either emits a ResultRow, or if it's a subquery, yields to the parent query)
+/// - a Window sorter (the rows are sorted by partition first, and the window functions are computed later)
 enum LoopEmitTarget {
+    Window,
     GroupBy,
     OrderBySorter,
     AggStep,
@@ -751,6 +933,11 @@ pub fn emit_loop(
     t_ctx: &mut TranslateCtx,
     plan: &SelectPlan,
 ) -> Result<()> {
+    // if we have window functions, we emit a record into the window sorter.
+    // any grouping and aggregation was already done by the window subquery, see window.rs.
+    if plan.window.is_some() {
+        return emit_loop_source(program, t_ctx, plan, LoopEmitTarget::Window);
+    }
     // if we have a group by, we emit a record into the group by sorter,
     // or if the rows are already sorted, we do the group by aggregation phase directly.
     if plan.group_by.is_some() {
@@ -779,6 +966,7 @@ fn emit_loop_source(
     emit_target: LoopEmitTarget,
 ) -> Result<()> {
     match emit_target {
+        LoopEmitTarget::Window => window_sorter_insert(program, t_ctx, plan),
         LoopEmitTarget::GroupBy => {
             // This function either:
             // - creates a sorter for GROUP BY operations by allocating registers and translating expressions for three types of columns:
@@ -947,11 +1135,21 @@ fn emit_loop_source(
                 plan.aggregates.is_empty(),
                 "We should not get here with aggregates"
             );
-            let offset_jump_to = t_ctx
-                .labels_main_loop
-                .first()
-                .map(|l| l.next)
-                .or(t_ctx.label_main_loop_end);
+            // The second pass of a FULL OUTER JOIN must continue with its next row when
+            // a row is skipped by OFFSET, which happens at the loop of the right table.
+            let full_join_table = plan.join_order.last().filter(|member| {
+                t_ctx.meta_left_joins[member.original_idx]
+                    .as_ref()
+                    .is_some_and(|lj_meta| lj_meta.full_join.is_some())
+            });
+            let offset_jump_to = match full_join_table {
+                Some(member) => Some(t_ctx.labels_main_loop[member.original_idx].next),
+                None => t_ctx
+                    .labels_main_loop
+                    .first()
+                    .map(|l| l.next)
+                    .or(t_ctx.label_main_loop_end),
+            };
             emit_select_result(
                 program,
                 &t_ctx.resolver,
@@ -1005,6 +1203,7 @@ pub fn close_loop(
         match &table.op {
             Operation::Scan { iter_dir, .. } => {
                 program.resolve_label(loop_labels.next, program.offset());
+                emit_full_join_second_pass_check(program, t_ctx, table_index);
                 match &table.table {
                     Table::BTree(_) => {
                         let iteration_cursor_id = temp_cursor_id.unwrap_or_else(|| {
@@ -1050,6 +1249,7 @@ pub fn close_loop(
                     "Subqueries do not support index seeks"
                 );
                 program.resolve_label(loop_labels.next, program.offset());
+                emit_full_join_second_pass_check(program, t_ctx, table_index);
                 let iteration_cursor_id = temp_cursor_id.unwrap_or_else(|| {
                     index_cursor_id.unwrap_or_else(|| {
                         table_cursor_id
@@ -1057,13 +1257,13 @@ pub fn close_loop(
                     })
                 });
                 // Rowid equality point lookups are handled with a SeekRowid instruction which does not loop, so there is no need to emit a Next instruction.
-                if !matches!(search, Search::RowidEq { .. }) {
-                    let iter_dir = match search {
-                        Search::Seek { seek_def, .. } => seek_def.iter_dir,
-                        Search::RowidEq { .. } => unreachable!(),
-                    };
-
-                    if iter_dir == IterationDirection::Backwards {
+                if let Search::HashJoin { .. } = search {
+                    program.emit_insn(Insn::HashNext {
+                        cursor_id: iteration_cursor_id,
+                        pc_if_next: loop_labels.loop_start,
+                    });
+                } else if let Search::Seek { seek_def, .. } = search {
+                    if seek_def.iter_dir == IterationDirection::Backwards {
                         program.emit_insn(Insn::Prev {
                             cursor_id: iteration_cursor_id,
                             pc_if_prev: loop_labels.loop_start,
@@ -1125,6 +1325,109 @@ pub fn close_loop(
     Ok(())
 }
 
+/// The second pass of a FULL OUTER JOIN jumps into the loop of the right table to emit its
+/// unmatched rows. When such a row is done, this jumps back to the second pass instead of
+/// advancing the right table in the main loop.
+fn emit_full_join_second_pass_check(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    table_index: usize,
+) {
+    if let Some(full_join) = t_ctx.meta_left_joins[table_index]
+        .as_ref()
+        .and_then(|lj_meta| lj_meta.full_join.as_ref())
+    {
+        program.emit_insn(Insn::If {
+            reg: full_join.reg_second_pass,
+            target_pc: full_join.label_second_pass_next,
+            jump_if_null: false,
+        });
+    }
+}
+
+/// Emits the second pass of a FULL OUTER JOIN, after the main loop has been closed.
+/// The right table is scanned again, and each row whose rowid was not recorded as matched
+/// in the main loop is emitted with NULLs for the left tables. The rows are emitted by
+/// jumping into the main loop right after the match flag is set, so that the WHERE terms,
+/// the result columns and any sorting or aggregation are shared with the main loop.
+pub fn emit_full_join_unmatched_rows(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    tables: &TableReferences,
+    join_order: &[JoinOrderMember],
+) -> Result<()> {
+    let Some((right, left)) = join_order.split_last() else {
+        return Ok(());
+    };
+    let Some(lj_meta) = t_ctx.meta_left_joins[right.original_idx].as_ref() else {
+        return Ok(());
+    };
+    let Some(full_join) = lj_meta.full_join.as_ref() else {
+        return Ok(());
+    };
+    program.emit_int(1, full_join.reg_second_pass);
+
+    // Every column of the left tables reads as NULL from now on.
+    for member in left {
+        let table = &tables.joined_tables()[member.original_idx];
+        if let Table::FromClauseSubquery(subquery) = &table.table {
+            let start_reg = subquery
+                .result_columns_start_reg
+                .expect("Subquery result_columns_start_reg must be set");
+            program.emit_insn(Insn::Null {
+                dest: start_reg,
+                dest_end: Some(start_reg + subquery.columns.len() - 1),
+            });
+            continue;
+        }
+        let (table_cursor_id, index_cursor_id) = table.resolve_cursors(program)?;
+        [table_cursor_id, index_cursor_id]
+            .iter()
+            .filter_map(|maybe_cursor_id| maybe_cursor_id.as_ref())
+            .for_each(|cursor_id| {
+                program.emit_insn(Insn::NullRow {
+                    cursor_id: *cursor_id,
+                });
+            });
+    }
+
+    let table = &tables.joined_tables()[right.original_idx];
+    let (table_cursor_id, index_cursor_id) = table.resolve_cursors(program)?;
+    let iteration_cursor_id = index_cursor_id
+        .or(table_cursor_id)
+        .expect("FULL JOIN requires a table or index cursor");
+    let loop_start = program.allocate_label();
+    let loop_end = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: iteration_cursor_id,
+        pc_if_empty: loop_end,
+    });
+    program.preassign_label_to_next_insn(loop_start);
+    if let (Some(table_cursor_id), Some(index_cursor_id)) = (table_cursor_id, index_cursor_id) {
+        program.emit_insn(Insn::DeferredSeek {
+            index_cursor_id,
+            table_cursor_id,
+        });
+    }
+    let rowid_reg = emit_full_join_rowid(program, table_cursor_id, index_cursor_id);
+    program.emit_insn(Insn::Found {
+        cursor_id: full_join.matched_rowids.cursor_id,
+        target_pc: full_join.label_second_pass_next,
+        record_reg: rowid_reg,
+        num_regs: 1,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: lj_meta.label_match_flag_set_true,
+    });
+    program.preassign_label_to_next_insn(full_join.label_second_pass_next);
+    program.emit_insn(Insn::Next {
+        cursor_id: iteration_cursor_id,
+        pc_if_next: loop_start,
+    });
+    program.preassign_label_to_next_insn(loop_end);
+    Ok(())
+}
+
 /// Emits instructions for an index seek. See e.g. [crate::translate::plan::SeekDef]
 /// for more details about the seek definition.
 ///
@@ -1417,7 +1720,7 @@ fn emit_autoindex(
     let label_ephemeral_build_loop_start = program.allocate_label();
     program.emit_insn(Insn::Rewind {
         cursor_id: table_cursor_id,
-        pc_if_empty: label_ephemeral_build_loop_start,
+        pc_if_empty: label_ephemeral_build_end,
     });
     program.preassign_label_to_next_insn(label_ephemeral_build_loop_start);
     // Emit all columns from source table that are needed in the ephemeral index.
@@ -1455,3 +1758,65 @@ fn emit_autoindex(
     program.preassign_label_to_next_insn(label_ephemeral_build_end);
     Ok(index_cursor_id)
 }
+
+/// Open a hash table cursor and read every row of a table into it, which is the build side of a
+/// [Search::HashJoin]. A row holds the columns of `index` followed by the rowid, if the table
+/// has one, and is stored under its first `affinities.len()` columns, with the affinities applied.
+fn emit_hash_table_build(
+    program: &mut ProgramBuilder,
+    index: &Arc<Index>,
+    affinities: &str,
+    table_cursor_id: CursorID,
+    hash_cursor_id: CursorID,
+) {
+    let label_build_end = program.allocate_label();
+    // Since this typically happens in an inner loop, we only build it once.
+    program.emit_insn(Insn::Once {
+        target_pc_when_reentered: label_build_end,
+    });
+    program.emit_insn(Insn::HashOpen {
+        cursor_id: hash_cursor_id,
+    });
+    let label_build_loop_start = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: table_cursor_id,
+        pc_if_empty: label_build_end,
+    });
+    program.preassign_label_to_next_insn(label_build_loop_start);
+    let num_columns = index.columns.len() + index.has_rowid as usize;
+    let row_start_reg = program.alloc_registers(num_columns);
+    for (i, col) in index.columns.iter().enumerate() {
+        program.emit_column(table_cursor_id, col.pos_in_table, row_start_reg + i);
+    }
+    if index.has_rowid {
+        program.emit_insn(Insn::RowId {
+            cursor_id: table_cursor_id,
+            dest: row_start_reg + index.columns.len(),
+        });
+    }
+    // The affinities only apply to the comparison, the row keeps the values as they are stored.
+    let num_keys = affinities.len();
+    let key_start_reg = program.alloc_registers(num_keys);
+    program.emit_insn(Insn::Copy {
+        src_reg: row_start_reg,
+        dst_reg: key_start_reg,
+        amount: num_keys - 1,
+    });
+    program.emit_insn(Insn::Affinity {
+        start_reg: key_start_reg,
+        count: NonZeroUsize::new(num_keys).unwrap(),
+        affinities: affinities.to_string(),
+    });
+    program.emit_insn(Insn::HashInsert {
+        cursor_id: hash_cursor_id,
+        key_start_reg,
+        num_keys,
+        row_start_reg,
+        num_columns,
+    });
+    program.emit_insn(Insn::Next {
+        cursor_id: table_cursor_id,
+        pc_if_next: label_build_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_build_end);
+}
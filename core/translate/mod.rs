@@ -9,12 +9,17 @@
 
 pub(crate) mod aggregation;
 pub(crate) mod alter;
+pub(crate) mod analyze;
+pub(crate) mod check;
 pub(crate) mod collate;
 mod compound_select;
 pub(crate) mod delete;
 pub(crate) mod display;
 pub(crate) mod emitter;
+pub(crate) mod explain;
 pub(crate) mod expr;
+pub(crate) mod fkey;
+pub(crate) mod generated;
 pub(crate) mod group_by;
 pub(crate) mod index;
 pub(crate) mod insert;
@@ -25,6 +30,7 @@ pub(crate) mod order_by;
 pub(crate) mod plan;
 pub(crate) mod planner;
 pub(crate) mod pragma;
+pub(crate) mod query_shape;
 pub(crate) mod result_row;
 pub(crate) mod rollback;
 pub(crate) mod schema;
@@ -33,14 +39,17 @@ pub(crate) mod subquery;
 pub(crate) mod transaction;
 pub(crate) mod update;
 mod values;
+pub(crate) mod window;
 
 use crate::schema::Schema;
 use crate::storage::pager::Pager;
 use crate::translate::delete::translate_delete;
+use crate::translate::explain::translate_explain_query_plan;
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts, QueryMode};
 use crate::vdbe::Program;
-use crate::{bail_parse_error, Connection, Result, SymbolTable};
+use crate::{bail_parse_error, Connection, LimboError, Result, SymbolTable};
 use alter::translate_alter_table;
+use analyze::translate_analyze;
 use index::{translate_create_index, translate_drop_index};
 use insert::translate_insert;
 use rollback::translate_rollback;
@@ -65,13 +74,18 @@ pub fn translate(
     _input: &str, // TODO: going to be used for CREATE VIEW
 ) -> Result<Program> {
     tracing::trace!("querying {}", _input);
-    let change_cnt_on = matches!(
-        stmt,
-        ast::Stmt::CreateIndex { .. }
-            | ast::Stmt::Delete(..)
-            | ast::Stmt::Insert(..)
-            | ast::Stmt::Update(..)
-    );
+    let explain_query_plan = query_mode == QueryMode::ExplainQueryPlan;
+    if connection.is_readonly() && !explain_query_plan && is_write_stmt(&stmt) {
+        return Err(LimboError::ReadOnly);
+    }
+    let change_cnt_on = !explain_query_plan
+        && matches!(
+            stmt,
+            ast::Stmt::CreateIndex { .. }
+                | ast::Stmt::Delete(..)
+                | ast::Stmt::Insert(..)
+                | ast::Stmt::Update(..)
+        );
 
     let mut program = ProgramBuilder::new(
         query_mode,
@@ -86,6 +100,9 @@ pub fn translate(
     program.prologue();
 
     program = match stmt {
+        stmt if explain_query_plan => {
+            translate_explain_query_plan(schema, stmt, syms, &connection, program)?
+        }
         // There can be no nesting with pragma, so lift it up here
         ast::Stmt::Pragma(name, body) => pragma::translate_pragma(
             schema,
@@ -95,7 +112,7 @@ pub fn translate(
             connection.clone(),
             program,
         )?,
-        stmt => translate_inner(schema, stmt, syms, program)?,
+        stmt => translate_inner(schema, stmt, syms, program, &connection)?,
     };
 
     // TODO: bring epilogue here when I can sort out what instructions correspond to a Write or a Read transaction
@@ -103,6 +120,37 @@ pub fn translate(
     Ok(program.build(connection, change_cnt_on))
 }
 
+/// Returns true if the statement modifies the database contents or its schema, or takes the
+/// write lock to do so.
+fn is_write_stmt(stmt: &ast::Stmt) -> bool {
+    if let ast::Stmt::Pragma(name, body) = stmt {
+        return pragma::pragma_writes_database(name, body.as_deref());
+    }
+    matches!(
+        stmt,
+        ast::Stmt::AlterTable(..)
+            | ast::Stmt::Analyze(..)
+            | ast::Stmt::Begin(
+                Some(ast::TransactionType::Immediate | ast::TransactionType::Exclusive),
+                _
+            )
+            | ast::Stmt::CreateIndex { .. }
+            | ast::Stmt::CreateTable { .. }
+            | ast::Stmt::CreateTrigger { .. }
+            | ast::Stmt::CreateView { .. }
+            | ast::Stmt::CreateVirtualTable(..)
+            | ast::Stmt::Delete(..)
+            | ast::Stmt::DropIndex { .. }
+            | ast::Stmt::DropTable { .. }
+            | ast::Stmt::DropTrigger { .. }
+            | ast::Stmt::DropView { .. }
+            | ast::Stmt::Insert(..)
+            | ast::Stmt::Reindex { .. }
+            | ast::Stmt::Update(..)
+            | ast::Stmt::Vacuum(..)
+    )
+}
+
 // TODO: for now leaving the return value as a Program. But ideally to support nested parsing of arbitraty
 // statements, we would have to return a program builder instead
 /// Translate SQL statement into bytecode program.
@@ -111,10 +159,15 @@ pub fn translate_inner(
     stmt: ast::Stmt,
     syms: &SymbolTable,
     program: ProgramBuilder,
+    connection: &Arc<Connection>,
 ) -> Result<ProgramBuilder> {
+    let enforce_foreign_keys = connection.foreign_keys_enabled();
+    let enforce_check_constraints = !connection.ignore_check_constraints();
     let program = match stmt {
         ast::Stmt::AlterTable(alter) => translate_alter_table(*alter, syms, schema, program)?,
-        ast::Stmt::Analyze(_) => bail_parse_error!("ANALYZE not supported yet"),
+        ast::Stmt::Analyze(name) => {
+            translate_analyze(name, schema, connection.get_analysis_limit(), program)?
+        }
         ast::Stmt::Attach { .. } => bail_parse_error!("ATTACH not supported yet"),
         ast::Stmt::Begin(tx_type, tx_name) => translate_tx_begin(tx_type, tx_name, program)?,
         ast::Stmt::Commit(tx_name) => translate_tx_commit(tx_name, program)?,
@@ -124,13 +177,15 @@ pub fn translate_inner(
             idx_name,
             tbl_name,
             columns,
-            ..
+            where_clause,
         } => translate_create_index(
             (unique, if_not_exists),
             &idx_name.name.0,
             &tbl_name.0,
             &columns,
+            where_clause,
             schema,
+            syms,
             program,
         )?,
         ast::Stmt::CreateTable {
@@ -151,7 +206,15 @@ pub fn translate_inner(
                 limit,
                 ..
             } = *delete;
-            translate_delete(schema, &tbl_name, where_clause, limit, syms, program)?
+            translate_delete(
+                schema,
+                &tbl_name,
+                where_clause,
+                limit,
+                enforce_foreign_keys,
+                syms,
+                program,
+            )?
         }
         ast::Stmt::Detach(_) => bail_parse_error!("DETACH not supported yet"),
         ast::Stmt::DropIndex {
@@ -184,7 +247,14 @@ pub fn translate_inner(
             )?
             .program
         }
-        ast::Stmt::Update(mut update) => translate_update(schema, &mut update, syms, program)?,
+        ast::Stmt::Update(mut update) => translate_update(
+            schema,
+            &mut update,
+            enforce_foreign_keys,
+            enforce_check_constraints,
+            syms,
+            program,
+        )?,
         ast::Stmt::Vacuum(_, _) => bail_parse_error!("VACUUM not supported yet"),
         ast::Stmt::Insert(insert) => {
             let Insert {
@@ -203,6 +273,8 @@ pub fn translate_inner(
                 columns,
                 body,
                 returning,
+                enforce_foreign_keys,
+                enforce_check_constraints,
                 syms,
                 program,
             )?
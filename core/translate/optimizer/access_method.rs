@@ -9,8 +9,10 @@ use crate::{
 };
 
 use super::{
-    constraints::{usable_constraints_for_join_order, ConstraintRef, TableConstraints},
-    cost::{estimate_cost_for_scan_or_seek, Cost, IndexInfo},
+    constraints::{
+        usable_constraints_for_join_order, usable_hash_join_keys, ConstraintRef, TableConstraints,
+    },
+    cost::{estimate_cost_for_hash_join, estimate_cost_for_scan_or_seek, Cost, IndexInfo},
     order::OrderTarget,
 };
 
@@ -29,19 +31,38 @@ pub struct AccessMethod<'a> {
     /// An empty list of constraint refs means a scan (full table or index);
     /// a non-empty list means a search.
     pub constraint_refs: &'a [ConstraintRef],
+    /// The keys of a hash join, if the table is hash joined with the tables to its left
+    /// instead of being scanned or searched for each of their rows.
+    pub hash_join_keys: Vec<ConstraintRef>,
 }
 
 impl AccessMethod<'_> {
     pub fn is_scan(&self) -> bool {
-        self.constraint_refs.is_empty()
+        self.constraint_refs.is_empty() && !self.is_hash_join()
     }
 
-    pub fn new_table_scan(input_cardinality: f64, iter_dir: IterationDirection) -> Self {
+    pub fn is_hash_join(&self) -> bool {
+        !self.hash_join_keys.is_empty()
+    }
+
+    pub fn new_table_scan(
+        input_cardinality: f64,
+        table_rows: f64,
+        iter_dir: IterationDirection,
+    ) -> Self {
         Self {
-            cost: estimate_cost_for_scan_or_seek(None, &[], &[], input_cardinality),
+            cost: estimate_cost_for_scan_or_seek(
+                None,
+                &[],
+                &[],
+                input_cardinality,
+                table_rows,
+                None,
+            ),
             iter_dir,
             index: None,
             constraint_refs: &[],
+            hash_join_keys: Vec::new(),
         }
     }
 }
@@ -55,8 +76,9 @@ pub fn find_best_access_method_for_join_order<'a>(
     input_cardinality: f64,
 ) -> Result<AccessMethod<'a>> {
     let table_no = join_order.last().unwrap().table_id;
+    let table_rows = rhs_constraints.estimated_row_count();
     let mut best_access_method =
-        AccessMethod::new_table_scan(input_cardinality, IterationDirection::Forwards);
+        AccessMethod::new_table_scan(input_cardinality, table_rows, IterationDirection::Forwards);
     let rowid_column_idx = rhs_table.columns().iter().position(|c| c.is_rowid_alias);
 
     // Estimate cost for each candidate index (including the rowid index) and replace best_access_method if the cost is lower.
@@ -83,6 +105,11 @@ pub fn find_best_access_method_for_join_order<'a>(
             &rhs_constraints.constraints,
             usable_constraint_refs,
             input_cardinality,
+            table_rows,
+            candidate
+                .index
+                .as_ref()
+                .and_then(|index| rhs_constraints.index_rows_per_key(index)),
         );
 
         // All other things being equal, prefer an access method that satisfies the order target.
@@ -140,6 +167,27 @@ pub fn find_best_access_method_for_join_order<'a>(
                 index: candidate.index.clone(),
                 iter_dir,
                 constraint_refs: usable_constraint_refs,
+                hash_join_keys: Vec::new(),
+            };
+        }
+    }
+
+    // Without an index to search the table with, it may be cheaper to read it into a hash table
+    // once than to scan it for every row of the tables to its left.
+    if best_access_method.is_scan() {
+        let hash_join_keys = usable_hash_join_keys(
+            &rhs_constraints.constraints,
+            &rhs_constraints.hash_join_keys,
+            join_order,
+        );
+        let cost = estimate_cost_for_hash_join(input_cardinality, table_rows);
+        if !hash_join_keys.is_empty() && cost < best_access_method.cost {
+            best_access_method = AccessMethod {
+                cost,
+                iter_dir: IterationDirection::Forwards,
+                index: None,
+                constraint_refs: &[],
+                hash_join_keys,
             };
         }
     }
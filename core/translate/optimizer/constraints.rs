@@ -1,12 +1,15 @@
 use std::{cmp::Ordering, collections::HashMap, sync::Arc};
 
 use crate::{
-    schema::{Column, Index},
+    schema::{Column, Index, TableStats, EXPR_INDEX_SENTINEL},
     translate::{
-        expr::as_binary_components,
-        plan::{JoinOrderMember, TableReferences, WhereTerm},
+        check::bind_table_expr,
+        collate::CollationSeq,
+        expr::{as_binary_components, walk_expr, WalkControl},
+        plan::{JoinOrderMember, JoinedTable, TableReferences, WhereTerm},
         planner::{table_mask_from_expr, TableMask},
     },
+    util::exprs_are_equivalent,
     Result,
 };
 use turso_sqlite3_parser::ast::{self, SortOrder, TableInternalId};
@@ -38,8 +41,12 @@ pub struct Constraint {
     pub where_clause_pos: (usize, BinaryExprSide),
     /// The comparison operator (e.g., `=`, `>`, `<`) used in the constraint.
     pub operator: ast::Operator,
-    /// The zero-based index of the constrained column within the table's schema.
+    /// The zero-based index of the constrained column within the table's schema, or
+    /// [EXPR_INDEX_SENTINEL] if an indexed expression is constrained.
     pub table_col_pos: usize,
+    /// The constrained expression, when it is an indexed expression rather than a column,
+    /// e.g. 'lower(t.x)' in SELECT * FROM t WHERE lower(t.x) = 'a'.
+    pub expr: Option<ast::Expr>,
     /// A bitmask representing the set of tables that appear on the *constraining* side
     /// of the comparison expression. For example, in SELECT * FROM t1,t2,t3 WHERE t1.x = t2.x + t3.x,
     /// the lhs_mask contains t2 and t3. Thus, this constraint can only be used if t2 and t3
@@ -139,6 +146,27 @@ pub struct TableConstraints {
     pub constraints: Vec<Constraint>,
     /// Candidates for indexes that may use the constraints to perform a lookup.
     pub candidates: Vec<ConstraintUseCandidate>,
+    /// The statistics ANALYZE gathered for the table, if any.
+    pub stats: Option<TableStats>,
+    /// The constraints that can be the keys of a hash join on the table, see [hash_join_keys].
+    pub hash_join_keys: Vec<ConstraintRef>,
+}
+
+impl TableConstraints {
+    /// The estimated number of rows in the table.
+    pub fn estimated_row_count(&self) -> f64 {
+        self.stats
+            .as_ref()
+            .map_or(ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64, |stats| {
+                stats.row_count.max(1) as f64
+            })
+    }
+
+    /// The average number of rows sharing a value of the first 1, 2, ... columns of `index`,
+    /// if ANALYZE gathered statistics about it.
+    pub fn index_rows_per_key(&self, index: &Index) -> Option<&[u64]> {
+        self.stats.as_ref()?.index_rows_per_key(&index.name)
+    }
 }
 
 /// In lieu of statistics, we estimate that an equality filter will reduce the output set to 1% of its size.
@@ -152,14 +180,16 @@ const SELECTIVITY_UNIQUE_EQUALITY: f64 = 1.0 / ESTIMATED_HARDCODED_ROWS_PER_TABL
 
 /// Estimate the selectivity of a constraint based on the operator and the column type.
 fn estimate_selectivity(column: &Column, op: ast::Operator) -> f64 {
+    if op == ast::Operator::Equals && (column.is_rowid_alias || column.primary_key) {
+        return SELECTIVITY_UNIQUE_EQUALITY;
+    }
+    estimate_expr_selectivity(op)
+}
+
+/// Estimate the selectivity of a constraint on an indexed expression based on the operator.
+fn estimate_expr_selectivity(op: ast::Operator) -> f64 {
     match op {
-        ast::Operator::Equals => {
-            if column.is_rowid_alias || column.primary_key {
-                SELECTIVITY_UNIQUE_EQUALITY
-            } else {
-                SELECTIVITY_EQ
-            }
-        }
+        ast::Operator::Equals => SELECTIVITY_EQ,
         ast::Operator::Greater => SELECTIVITY_RANGE,
         ast::Operator::GreaterEquals => SELECTIVITY_RANGE,
         ast::Operator::Less => SELECTIVITY_RANGE,
@@ -168,6 +198,88 @@ fn estimate_selectivity(column: &Column, op: ast::Operator) -> f64 {
     }
 }
 
+/// Returns true if `index` is not a partial index, or if the WHERE clause guarantees that
+/// every row of `table_reference` the query needs satisfies the WHERE clause of `index`.
+fn where_clause_implies_partial_index(
+    index: &Index,
+    table_reference: &JoinedTable,
+    where_clause: &[WhereTerm],
+) -> bool {
+    let Some(index_where_clause) = &index.where_clause else {
+        return true;
+    };
+    let Some(table) = table_reference.btree() else {
+        return false;
+    };
+    let Ok(index_where_clause) =
+        bind_table_expr(&table, index_where_clause, table_reference.internal_id)
+    else {
+        return false;
+    };
+    // Terms of the ON clause of another table's outer join don't filter this table.
+    let terms = where_clause
+        .iter()
+        .filter(|term| {
+            term.from_outer_join
+                .map_or(true, |table_id| table_id == table_reference.internal_id)
+        })
+        .collect::<Vec<_>>();
+    let mut conjuncts = vec![&index_where_clause];
+    while let Some(conjunct) = conjuncts.pop() {
+        match conjunct {
+            ast::Expr::Binary(lhs, ast::Operator::And, rhs) => {
+                conjuncts.push(lhs);
+                conjuncts.push(rhs);
+            }
+            ast::Expr::Parenthesized(exprs) if exprs.len() == 1 => conjuncts.push(&exprs[0]),
+            _ => {
+                if !terms.iter().any(|term| expr_implies(&term.expr, conjunct)) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Returns true if `expr` being true guarantees that `implied` is true. Like SQLite, only a
+/// few simple cases are recognized: the same expression, one side of an OR, and `x IS NOT
+/// NULL` implied by a comparison of `x`.
+fn expr_implies(expr: &ast::Expr, implied: &ast::Expr) -> bool {
+    if exprs_are_equivalent(expr, implied) {
+        return true;
+    }
+    match implied {
+        ast::Expr::Binary(lhs, ast::Operator::Or, rhs) => {
+            expr_implies(expr, lhs) || expr_implies(expr, rhs)
+        }
+        ast::Expr::Parenthesized(exprs) if exprs.len() == 1 => expr_implies(expr, &exprs[0]),
+        ast::Expr::NotNull(operand) => comparison_implies_not_null(expr, operand),
+        ast::Expr::Binary(operand, ast::Operator::IsNot, null)
+            if matches!(null.as_ref(), ast::Expr::Literal(ast::Literal::Null)) =>
+        {
+            comparison_implies_not_null(expr, operand)
+        }
+        _ => false,
+    }
+}
+
+/// A comparison is never true if one of its operands is NULL.
+fn comparison_implies_not_null(expr: &ast::Expr, operand: &ast::Expr) -> bool {
+    let ast::Expr::Binary(lhs, op, rhs) = expr else {
+        return false;
+    };
+    matches!(
+        op,
+        ast::Operator::Equals
+            | ast::Operator::NotEquals
+            | ast::Operator::Greater
+            | ast::Operator::GreaterEquals
+            | ast::Operator::Less
+            | ast::Operator::LessEquals
+    ) && (exprs_are_equivalent(lhs, operand) || exprs_are_equivalent(rhs, operand))
+}
+
 /// Precompute all potentially usable [Constraints] from a WHERE clause.
 /// The resulting list of [TableConstraints] is then used to evaluate the best access methods for various join orders.
 pub fn constraints_from_where_clause(
@@ -184,20 +296,50 @@ pub fn constraints_from_where_clause(
             .iter()
             .position(|c| c.is_rowid_alias);
 
+        // A partial index can only be used if the query only needs rows that are in it.
+        let table_indexes = available_indexes
+            .get(table_reference.table.get_name())
+            .map_or(Vec::new(), |indexes| {
+                indexes
+                    .iter()
+                    .filter(|index| {
+                        where_clause_implies_partial_index(index, table_reference, where_clause)
+                    })
+                    .cloned()
+                    .collect()
+            });
+
+        // The indexed expressions of the table, bound to this table reference so that they can
+        // be compared with the terms of the WHERE clause.
+        let mut index_exprs = Vec::new();
+        if let Some(table) = table_reference.btree() {
+            for index in table_indexes.iter() {
+                for (pos, column) in index.columns.iter().enumerate() {
+                    if let Some(expr) = &column.expr {
+                        let expr = bind_table_expr(&table, expr, table_reference.internal_id)?;
+                        index_exprs.push((index.clone(), pos, expr));
+                    }
+                }
+            }
+        }
+        let is_indexed_expr = |expr: &ast::Expr| {
+            index_exprs
+                .iter()
+                .any(|(_, _, index_expr)| exprs_are_equivalent(expr, index_expr))
+        };
+
         let mut cs = TableConstraints {
             table_id: table_reference.internal_id,
             constraints: Vec::new(),
-            candidates: available_indexes
-                .get(table_reference.table.get_name())
-                .map_or(Vec::new(), |indexes| {
-                    indexes
-                        .iter()
-                        .map(|index| ConstraintUseCandidate {
-                            index: Some(index.clone()),
-                            refs: Vec::new(),
-                        })
-                        .collect()
-                }),
+            candidates: table_indexes
+                .iter()
+                .map(|index| ConstraintUseCandidate {
+                    index: Some(index.clone()),
+                    refs: Vec::new(),
+                })
+                .collect(),
+            stats: None,
+            hash_join_keys: Vec::new(),
         };
         // Add a candidate for the rowid index, which is always available when the table has a rowid alias.
         cs.candidates.push(ConstraintUseCandidate {
@@ -219,14 +361,18 @@ pub fn constraints_from_where_clause(
             }
 
             // If either the LHS or RHS of the constraint is a column from the table, add the constraint.
+            // VIRTUAL generated columns have no stored value to seek on, so they are only filtered.
             match lhs {
                 ast::Expr::Column { table, column, .. } => {
-                    if *table == table_reference.internal_id {
+                    if *table == table_reference.internal_id
+                        && !table_reference.table.columns()[*column].is_virtual()
+                    {
                         let table_column = &table_reference.table.columns()[*column];
                         cs.constraints.push(Constraint {
                             where_clause_pos: (i, BinaryExprSide::Rhs),
                             operator,
                             table_col_pos: *column,
+                            expr: None,
                             lhs_mask: table_mask_from_expr(rhs, table_references)?,
                             selectivity: estimate_selectivity(table_column, operator),
                         });
@@ -243,21 +389,35 @@ pub fn constraints_from_where_clause(
                             where_clause_pos: (i, BinaryExprSide::Rhs),
                             operator,
                             table_col_pos: rowid_alias_column.unwrap(),
+                            expr: None,
                             lhs_mask: table_mask_from_expr(rhs, table_references)?,
                             selectivity: estimate_selectivity(table_column, operator),
                         });
                     }
                 }
+                lhs if is_indexed_expr(lhs) => {
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Rhs),
+                        operator,
+                        table_col_pos: EXPR_INDEX_SENTINEL,
+                        expr: Some(lhs.clone()),
+                        lhs_mask: table_mask_from_expr(rhs, table_references)?,
+                        selectivity: estimate_expr_selectivity(operator),
+                    });
+                }
                 _ => {}
             };
             match rhs {
                 ast::Expr::Column { table, column, .. } => {
-                    if *table == table_reference.internal_id {
+                    if *table == table_reference.internal_id
+                        && !table_reference.table.columns()[*column].is_virtual()
+                    {
                         let table_column = &table_reference.table.columns()[*column];
                         cs.constraints.push(Constraint {
                             where_clause_pos: (i, BinaryExprSide::Lhs),
                             operator: opposite_cmp_op(operator),
                             table_col_pos: *column,
+                            expr: None,
                             lhs_mask: table_mask_from_expr(lhs, table_references)?,
                             selectivity: estimate_selectivity(table_column, operator),
                         });
@@ -271,11 +431,22 @@ pub fn constraints_from_where_clause(
                             where_clause_pos: (i, BinaryExprSide::Lhs),
                             operator: opposite_cmp_op(operator),
                             table_col_pos: rowid_alias_column.unwrap(),
+                            expr: None,
                             lhs_mask: table_mask_from_expr(lhs, table_references)?,
                             selectivity: estimate_selectivity(table_column, operator),
                         });
                     }
                 }
+                rhs if is_indexed_expr(rhs) => {
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Lhs),
+                        operator: opposite_cmp_op(operator),
+                        table_col_pos: EXPR_INDEX_SENTINEL,
+                        expr: Some(rhs.clone()),
+                        lhs_mask: table_mask_from_expr(lhs, table_references)?,
+                        selectivity: estimate_expr_selectivity(operator),
+                    });
+                }
                 _ => {}
             };
         }
@@ -311,13 +482,15 @@ pub fn constraints_from_where_clause(
                     sort_order: SortOrder::Asc,
                 });
             }
-            for index in available_indexes
-                .get(table_reference.table.get_name())
-                .unwrap_or(&Vec::new())
-            {
-                if let Some(position_in_index) =
-                    index.column_table_pos_to_index_pos(constraint.table_col_pos)
-                {
+            for index in table_indexes.iter() {
+                let position_in_index = match &constraint.expr {
+                    Some(expr) => index_exprs.iter().find_map(|(i, pos, index_expr)| {
+                        (Arc::ptr_eq(index, i) && exprs_are_equivalent(expr, index_expr))
+                            .then_some(*pos)
+                    }),
+                    None => index.column_table_pos_to_index_pos(constraint.table_col_pos),
+                };
+                if let Some(position_in_index) = position_in_index {
                     let index_candidate = cs
                         .candidates
                         .iter_mut()
@@ -402,6 +575,94 @@ pub fn usable_constraints_for_join_order<'a>(
     &refs[..usable_until]
 }
 
+/// Find the constraints that can be the keys of a hash join on a table: equalities between one
+/// of its columns and an expression of other tables, compared with the BINARY collation.
+/// A hash table only finds rows whose keys are identical, so other collations can't be used.
+pub fn hash_join_keys(
+    table_reference: &JoinedTable,
+    constraints: &[Constraint],
+    where_clause: &[WhereTerm],
+    table_references: &TableReferences,
+) -> Result<Vec<ConstraintRef>> {
+    let columns = table_reference.columns();
+    // VIRTUAL generated columns are computed from the columns they refer to, which the hash
+    // table might not hold.
+    if (0..columns.len()).any(|i| columns[i].is_virtual() && table_reference.column_is_used(i)) {
+        return Ok(Vec::new());
+    }
+    let is_binary = |collation: Option<CollationSeq>| {
+        collation.map_or(true, |collation| collation == CollationSeq::Binary)
+    };
+    let mut keys = Vec::new();
+    for (pos, constraint) in constraints.iter().enumerate() {
+        if constraint.operator != ast::Operator::Equals
+            || constraint.expr.is_some()
+            || constraint.lhs_mask.is_empty()
+        {
+            continue;
+        }
+        let column = &columns[constraint.table_col_pos];
+        if column.is_rowid_alias || !is_binary(column.collation) {
+            continue;
+        }
+        let mut binary = true;
+        walk_expr(
+            &where_clause[constraint.where_clause_pos.0].expr,
+            &mut |expr: &ast::Expr| -> Result<WalkControl> {
+                match expr {
+                    ast::Expr::Collate(..) => binary = false,
+                    ast::Expr::Column { table, column, .. } => {
+                        let collation = table_references
+                            .find_table_by_internal_id(*table)
+                            .and_then(|table| table.get_column_at(*column))
+                            .and_then(|column| column.collation);
+                        binary &= is_binary(collation);
+                    }
+                    _ => {}
+                }
+                Ok(WalkControl::Continue)
+            },
+        )?;
+        if binary {
+            keys.push(ConstraintRef {
+                constraint_vec_pos: pos,
+                index_col_pos: keys.len(),
+                sort_order: SortOrder::Asc,
+            });
+        }
+    }
+    Ok(keys)
+}
+
+/// Find which hash join keys are usable for a given join order, i.e. only refer to tables on
+/// the left side of the table in the join order. A column is only used as a key once.
+pub fn usable_hash_join_keys(
+    constraints: &[Constraint],
+    keys: &[ConstraintRef],
+    join_order: &[JoinOrderMember],
+) -> Vec<ConstraintRef> {
+    let lhs_mask = TableMask::from_table_number_iter(
+        join_order
+            .iter()
+            .take(join_order.len() - 1)
+            .map(|j| j.original_idx),
+    );
+    let mut usable: Vec<ConstraintRef> = Vec::new();
+    for key in keys.iter() {
+        let constraint = &constraints[key.constraint_vec_pos];
+        let column_is_key = usable
+            .iter()
+            .any(|k| constraints[k.constraint_vec_pos].table_col_pos == constraint.table_col_pos);
+        if lhs_mask.contains_all(&constraint.lhs_mask) && !column_is_key {
+            usable.push(ConstraintRef {
+                index_col_pos: usable.len(),
+                ..key.clone()
+            });
+        }
+    }
+    usable
+}
+
 fn opposite_cmp_op(op: ast::Operator) -> ast::Operator {
     match op {
         ast::Operator::Equals => ast::Operator::Equals,
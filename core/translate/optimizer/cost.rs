@@ -1,3 +1,5 @@
+use turso_sqlite3_parser::ast;
+
 use super::constraints::{Constraint, ConstraintRef};
 
 /// A simple newtype wrapper over a f64 that represents the cost of an operation.
@@ -31,42 +33,74 @@ pub struct IndexInfo {
 
 pub const ESTIMATED_HARDCODED_ROWS_PER_TABLE: usize = 1000000;
 pub const ESTIMATED_HARDCODED_ROWS_PER_PAGE: usize = 50; // roughly 80 bytes per 4096 byte page
+/// The estimated cost of inserting a row into the in-memory hash table of a hash join,
+/// as a fraction of a page fetch.
+const HASH_JOIN_BUILD_COST_PER_ROW: f64 = 0.5 / ESTIMATED_HARDCODED_ROWS_PER_PAGE as f64;
+/// The estimated cost of probing the hash table of a hash join with one row of the outer tables.
+const HASH_JOIN_PROBE_COST_PER_ROW: f64 = 0.25 / ESTIMATED_HARDCODED_ROWS_PER_PAGE as f64;
 
 pub fn estimate_page_io_cost(rowcount: f64) -> Cost {
     Cost((rowcount / ESTIMATED_HARDCODED_ROWS_PER_PAGE as f64).ceil())
 }
 
+/// Estimate the cost of a hash join: the table is read once into a hash table, which is then
+/// probed once for every row of the outer tables.
+///
+/// Building costs more per row than probing, so of two tables the smaller one ends up being
+/// hashed, and the larger one is scanned once to probe it.
+pub fn estimate_cost_for_hash_join(input_cardinality: f64, table_rows: f64) -> Cost {
+    estimate_page_io_cost(table_rows)
+        + Cost(table_rows * HASH_JOIN_BUILD_COST_PER_ROW)
+        + Cost(input_cardinality * HASH_JOIN_PROBE_COST_PER_ROW)
+}
+
 /// Estimate the cost of a scan or seek operation.
 ///
 /// This is a very simple model that estimates the number of pages read
 /// based on the number of rows read, ignoring any CPU costs.
+/// `rows_per_key` are the statistics gathered by ANALYZE for the index, if any.
 pub fn estimate_cost_for_scan_or_seek(
     index_info: Option<IndexInfo>,
     constraints: &[Constraint],
     usable_constraint_refs: &[ConstraintRef],
     input_cardinality: f64,
+    table_rows: f64,
+    rows_per_key: Option<&[u64]>,
 ) -> Cost {
     let Some(index_info) = index_info else {
-        return estimate_page_io_cost(
-            input_cardinality * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64,
-        );
+        return estimate_page_io_cost(input_cardinality * table_rows);
     };
 
-    let selectivity_multiplier: f64 = usable_constraint_refs
+    // With statistics, equalities on the first n columns of the index select the average number
+    // of rows sharing a value of those columns.
+    let eq_prefix_len = usable_constraint_refs
         .iter()
-        .map(|cref| {
-            let constraint = &constraints[cref.constraint_vec_pos];
-            constraint.selectivity
-        })
-        .product();
+        .take_while(|cref| constraints[cref.constraint_vec_pos].operator == ast::Operator::Equals)
+        .count();
+    let (prefix_selectivity, other_constraint_refs) = match eq_prefix_len
+        .checked_sub(1)
+        .and_then(|i| rows_per_key?.get(i))
+    {
+        Some(rows) => (
+            (*rows as f64 / table_rows).min(1.0),
+            &usable_constraint_refs[eq_prefix_len..],
+        ),
+        None => (1.0, usable_constraint_refs),
+    };
+
+    let selectivity_multiplier: f64 = prefix_selectivity
+        * other_constraint_refs
+            .iter()
+            .map(|cref| {
+                let constraint = &constraints[cref.constraint_vec_pos];
+                constraint.selectivity
+            })
+            .product::<f64>();
 
     // little cheeky bonus for covering indexes
     let covering_multiplier = if index_info.covering { 0.9 } else { 1.0 };
 
     estimate_page_io_cost(
-        selectivity_multiplier
-            * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64
-            * input_cardinality
-            * covering_multiplier,
+        selectivity_multiplier * table_rows * input_cardinality * covering_multiplier,
     )
 }
@@ -0,0 +1,246 @@
+use std::rc::Rc;
+
+use turso_sqlite3_parser::ast::{self, TableInternalId};
+
+use crate::{
+    schema::Table,
+    translate::{
+        expr::walk_expr_mut,
+        plan::{JoinOrderMember, SelectPlan},
+    },
+    Result,
+};
+
+/// Merges simple FROM clause subqueries into the query that uses them, like SQLite's
+/// query flattener (see https://www.sqlite.org/optoverview.html#flattening).
+/// For example:
+/// ```sql
+/// SELECT * FROM (SELECT * FROM t WHERE x > 5) WHERE y < 10
+/// ```
+/// is rewritten into
+/// ```sql
+/// SELECT * FROM t WHERE x > 5 AND y < 10
+/// ```
+///
+/// The tables of the subquery take its place in the FROM clause, its WHERE terms are added to
+/// the outer WHERE clause and every reference to a column of the subquery is replaced with the
+/// expression of the corresponding result column. This avoids running the subquery as a
+/// coroutine, and lets the optimizer choose access methods and a join order for all the tables
+/// at once.
+///
+/// Must run before the subqueries themselves are optimized, since their tables are planned as
+/// part of the outer query afterwards.
+pub(crate) fn flatten_from_clause_subqueries(plan: &mut SelectPlan) -> Result<()> {
+    // Window functions are always computed over a FROM clause subquery, see translate/window.rs.
+    if plan.window.is_some() {
+        return Ok(());
+    }
+    let mut flattened_any = false;
+    let mut i = 0;
+    while i < plan.joined_tables().len() {
+        let joined_table = &mut plan.table_references.joined_tables_mut()[i];
+        let Table::FromClauseSubquery(subquery) = &mut joined_table.table else {
+            i += 1;
+            continue;
+        };
+        flatten_from_clause_subqueries(&mut subquery.plan)?;
+        if !can_flatten(plan, i) {
+            i += 1;
+            continue;
+        }
+
+        // The names of the result columns are derived from the subquery's columns, so keep them.
+        for rc in plan.result_columns.iter_mut() {
+            if rc.alias.is_none() {
+                rc.alias = Some(
+                    rc.name(&plan.table_references)
+                        .map(String::from)
+                        .unwrap_or_else(|| rc.expr.to_string()),
+                );
+            }
+        }
+
+        let joined_table = plan.table_references.joined_tables_mut().remove(i);
+        let Table::FromClauseSubquery(subquery) = joined_table.table else {
+            unreachable!("table was checked to be a FROM clause subquery");
+        };
+        let mut subplan = *subquery.plan;
+        let mut tables = std::mem::take(subplan.table_references.joined_tables_mut());
+        // The first table of the subquery is joined to the preceding tables like the subquery was.
+        tables[0].join_info = joined_table.join_info;
+        let table_count = tables.len();
+        plan.table_references
+            .joined_tables_mut()
+            .splice(i..i, tables);
+
+        let columns = subplan
+            .result_columns
+            .into_iter()
+            .map(|rc| rc.expr)
+            .collect::<Vec<_>>();
+        replace_subquery_columns(plan, joined_table.internal_id, &columns)?;
+        plan.where_clause.append(&mut subplan.where_clause);
+        flattened_any = true;
+        i += table_count;
+    }
+
+    if flattened_any {
+        plan.join_order = plan
+            .joined_tables()
+            .iter()
+            .enumerate()
+            .map(|(i, t)| JoinOrderMember {
+                table_id: t.internal_id,
+                original_idx: i,
+                is_outer: t.join_info.as_ref().map_or(false, |j| j.outer),
+            })
+            .collect();
+    }
+    Ok(())
+}
+
+/// Returns true if the FROM clause subquery at position `idx` of the joined tables of `plan`
+/// can be flattened into `plan`.
+fn can_flatten(plan: &SelectPlan, idx: usize) -> bool {
+    let joined_table = &plan.joined_tables()[idx];
+    let Table::FromClauseSubquery(subquery) = &joined_table.table else {
+        return false;
+    };
+    // The plan of a recursive CTE only seeds it; the rows it yields come from running its
+    // recursive SELECT.
+    if subquery.recursive_cte.is_some() {
+        return false;
+    }
+    let subplan = &subquery.plan;
+
+    // The subquery must only filter the rows of its tables: anything that groups, sorts, limits
+    // or deduplicates them changes the result of the outer query when flattened.
+    if !subplan.aggregates.is_empty()
+        || subplan
+            .result_columns
+            .iter()
+            .any(|rc| rc.contains_aggregates)
+        || subplan.group_by.is_some()
+        || subplan.order_by.is_some()
+        || subplan.limit.is_some()
+        || subplan.offset.is_some()
+        || subplan.distinctness.is_distinct()
+        || subplan.window.is_some()
+        || !subplan.values.is_empty()
+        || subplan.joined_tables().is_empty()
+    {
+        return false;
+    }
+    // OUTER JOINs inside the subquery and correlated references are not flattened.
+    if subplan
+        .joined_tables()
+        .iter()
+        .any(|t| t.join_info.as_ref().map_or(false, |j| j.outer))
+        || subplan
+            .table_references
+            .outer_query_refs()
+            .iter()
+            .any(|t| t.is_used())
+    {
+        return false;
+    }
+    // The WHERE terms of the subquery can't be moved into the ON clause of an OUTER JOIN.
+    if joined_table
+        .join_info
+        .as_ref()
+        .map_or(false, |j| j.outer || j.using.is_some())
+        || plan
+            .joined_tables()
+            .iter()
+            .any(|t| t.join_info.as_ref().map_or(false, |j| j.full))
+    {
+        return false;
+    }
+    // A CTE that is used more than once is planned once and cloned, so the tables of its
+    // copies share their internal ids.
+    subplan.joined_tables().iter().all(|inner| {
+        plan.joined_tables()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != idx)
+            .all(|(_, t)| !uses_table_id(&t.table, t.internal_id, inner.internal_id))
+    })
+}
+
+/// Returns true if the joined table `table` with id `table_id`, or one of the tables of its
+/// subquery, has the internal id `id`.
+fn uses_table_id(table: &Table, table_id: TableInternalId, id: TableInternalId) -> bool {
+    if table_id == id {
+        return true;
+    }
+    let Table::FromClauseSubquery(subquery) = table else {
+        return false;
+    };
+    subquery
+        .plan
+        .joined_tables()
+        .iter()
+        .any(|t| uses_table_id(&t.table, t.internal_id, id))
+}
+
+/// Replaces the references to the columns of the subquery `subquery_id` in `plan` with the
+/// expressions of the subquery's result columns.
+fn replace_subquery_columns(
+    plan: &mut SelectPlan,
+    subquery_id: TableInternalId,
+    columns: &[ast::Expr],
+) -> Result<()> {
+    let replace =
+        |expr: &mut ast::Expr| replace_subquery_column_refs(expr, subquery_id, columns);
+    for rc in plan.result_columns.iter_mut() {
+        replace(&mut rc.expr)?;
+    }
+    for term in plan.where_clause.iter_mut() {
+        replace(&mut term.expr)?;
+    }
+    if let Some(group_by) = &mut plan.group_by {
+        for expr in group_by.exprs.iter_mut() {
+            replace(expr)?;
+        }
+        for expr in group_by.having.iter_mut().flatten() {
+            replace(expr)?;
+        }
+    }
+    for (expr, _) in plan.order_by.iter_mut().flatten() {
+        replace(expr)?;
+    }
+    for agg in plan.aggregates.iter_mut() {
+        for arg in agg.args.iter_mut() {
+            replace(arg)?;
+        }
+        replace(&mut agg.original_expr)?;
+    }
+    for joined_table in plan.table_references.joined_tables_mut() {
+        let Table::Virtual(vtab) = &mut joined_table.table else {
+            continue;
+        };
+        if vtab.args.is_some() {
+            for arg in Rc::make_mut(vtab).args.iter_mut().flatten() {
+                replace(arg)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the references to the columns of the subquery `subquery_id` in `expr` with the
+/// expressions of the subquery's result columns, `columns`.
+pub(crate) fn replace_subquery_column_refs(
+    expr: &mut ast::Expr,
+    subquery_id: TableInternalId,
+    columns: &[ast::Expr],
+) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut ast::Expr| -> Result<()> {
+        if let ast::Expr::Column { table, column, .. } = expr {
+            if *table == subquery_id {
+                *expr = columns[*column].clone();
+            }
+        }
+        Ok(())
+    })
+}
@@ -1,9 +1,11 @@
 use std::{cell::RefCell, collections::HashMap};
 
-use turso_sqlite3_parser::ast::TableInternalId;
+use turso_sqlite3_parser::ast::{self, TableInternalId};
 
 use crate::{
+    schema::Table,
     translate::{
+        expr::{walk_expr, WalkControl},
         optimizer::{cost::Cost, order::plan_satisfies_order_target},
         plan::{JoinOrderMember, JoinedTable},
         planner::TableMask,
@@ -14,7 +16,6 @@ use crate::{
 use super::{
     access_method::{find_best_access_method_for_join_order, AccessMethod},
     constraints::TableConstraints,
-    cost::ESTIMATED_HARDCODED_ROWS_PER_TABLE,
     order::OrderTarget,
 };
 
@@ -41,6 +42,24 @@ impl JoinN {
     }
 }
 
+/// Returns the ids of the tables referred to by the arguments of `joined_table`, if it is a
+/// table-valued function.
+fn table_valued_function_arg_tables(joined_table: &JoinedTable) -> Result<Vec<TableInternalId>> {
+    let Table::Virtual(vtab) = &joined_table.table else {
+        return Ok(vec![]);
+    };
+    let mut table_ids = vec![];
+    for arg in vtab.args.iter().flatten() {
+        walk_expr(arg, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+            if let ast::Expr::Column { table, .. } | ast::Expr::RowId { table, .. } = expr {
+                table_ids.push(*table);
+            }
+            Ok(WalkControl::Continue)
+        })?;
+    }
+    Ok(table_ids)
+}
+
 /// Join n-1 tables with the n'th table.
 /// Returns None if the plan is worse than the provided cost upper bound.
 pub fn join_lhs_and_rhs<'a>(
@@ -95,7 +114,7 @@ pub fn join_lhs_and_rhs<'a>(
     // If this table is the rightmost table in the join order, we multiply by the input cardinality,
     // which is the output cardinality of the previous tables.
     let output_cardinality = (input_cardinality as f64
-        * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64
+        * rhs_constraints.estimated_row_count()
         * output_cardinality_multiplier)
         .ceil() as usize;
 
@@ -214,31 +233,37 @@ pub fn compute_best_join_order<'a>(
     // Example:
     // "a LEFT JOIN b" can NOT be reordered as "b LEFT JOIN a".
     // If there are outer joins in the plan, ensure correct ordering.
+    // Table-valued functions must also come after the tables their arguments refer to.
     let left_join_illegal_map = {
-        let left_join_count = joined_tables
-            .iter()
-            .filter(|t| t.join_info.as_ref().map_or(false, |j| j.outer))
-            .count();
-        if left_join_count == 0 {
-            None
-        } else {
-            // map from rhs table index to lhs table index
-            let mut left_join_illegal_map: HashMap<usize, TableMask> =
-                HashMap::with_capacity(left_join_count);
-            for (i, _) in joined_tables.iter().enumerate() {
-                for (j, joined_table) in joined_tables.iter().enumerate().skip(i + 1) {
-                    if joined_table.join_info.as_ref().map_or(false, |j| j.outer) {
-                        // bitwise OR the masks
-                        if let Some(illegal_lhs) = left_join_illegal_map.get_mut(&i) {
-                            illegal_lhs.add_table(j);
-                        } else {
-                            let mut mask = TableMask::new();
-                            mask.add_table(j);
-                            left_join_illegal_map.insert(i, mask);
-                        }
-                    }
+        // map from rhs table index to lhs table index
+        let mut left_join_illegal_map: HashMap<usize, TableMask> = HashMap::new();
+        for (i, _) in joined_tables.iter().enumerate() {
+            for (j, joined_table) in joined_tables.iter().enumerate().skip(i + 1) {
+                if joined_table.join_info.as_ref().map_or(false, |j| j.outer) {
+                    left_join_illegal_map
+                        .entry(i)
+                        .or_insert_with(TableMask::new)
+                        .add_table(j);
                 }
             }
+        }
+        for (j, joined_table) in joined_tables.iter().enumerate() {
+            for table_id in table_valued_function_arg_tables(joined_table)? {
+                if let Some(i) = joined_tables
+                    .iter()
+                    .position(|t| t.internal_id == table_id)
+                    .filter(|i| *i != j)
+                {
+                    left_join_illegal_map
+                        .entry(i)
+                        .or_insert_with(TableMask::new)
+                        .add_table(j);
+                }
+            }
+        }
+        if left_join_illegal_map.is_empty() {
+            None
+        } else {
             Some(left_join_illegal_map)
         }
     };
@@ -496,9 +521,13 @@ mod tests {
 
     use super::*;
     use crate::{
-        schema::{BTreeTable, Column, Index, IndexColumn, Table, Type},
+        schema::{
+            BTreeTable, Column, Index, IndexColumn, Table, TableStats, Type, EXPR_INDEX_SENTINEL,
+        },
         translate::{
-            optimizer::constraints::{constraints_from_where_clause, BinaryExprSide},
+            optimizer::constraints::{
+                constraints_from_where_clause, hash_join_keys, BinaryExprSide,
+            },
             plan::{
                 ColumnUsedMask, IterationDirection, JoinInfo, Operation, TableReferences, WhereTerm,
             },
@@ -654,11 +683,13 @@ mod tests {
                 pos_in_table: 0,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: true,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            where_clause: None,
         });
         available_indexes.insert("test_table".to_string(), vec![index]);
 
@@ -702,6 +733,7 @@ mod tests {
                 t2.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    full: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -722,11 +754,13 @@ mod tests {
                 pos_in_table: 0,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: true,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            where_clause: None,
         });
         available_indexes.insert("table1".to_string(), vec![index1]);
 
@@ -770,6 +804,352 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test that the statistics gathered by ANALYZE make [compute_best_join_order] search the large
+    /// table with its index instead of scanning it.
+    fn test_compute_best_join_order_uses_stats() {
+        let big = _create_btree_table("big", _create_column_list(&["a"], Type::Integer));
+        let small = _create_btree_table("small", _create_column_list(&["a"], Type::Integer));
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![
+            _create_table_reference(big.clone(), None, table_id_counter.next()),
+            _create_table_reference(
+                small.clone(),
+                Some(JoinInfo {
+                    outer: false,
+                    full: false,
+                    using: None,
+                }),
+                table_id_counter.next(),
+            ),
+        ];
+
+        const BIG: usize = 0;
+        const SMALL: usize = 1;
+
+        let mut available_indexes = HashMap::new();
+        for table_name in ["big", "small"] {
+            let index = Arc::new(Index {
+                name: format!("{table_name}_a"),
+                table_name: table_name.to_string(),
+                columns: vec![IndexColumn {
+                    name: "a".to_string(),
+                    order: SortOrder::Asc,
+                    pos_in_table: 0,
+                    collation: None,
+                    default: None,
+                    expr: None,
+                }],
+                unique: false,
+                ephemeral: false,
+                root_page: 1,
+                has_rowid: true,
+                where_clause: None,
+            });
+            available_indexes.insert(table_name.to_string(), vec![index]);
+        }
+
+        // SELECT * FROM big JOIN small WHERE big.a = small.a
+        let where_clause = vec![_create_binary_expr(
+            _create_column_expr(joined_tables[BIG].internal_id, 0, false), // big.a
+            ast::Operator::Equals,
+            _create_column_expr(joined_tables[SMALL].internal_id, 0, false), // small.a
+        )];
+
+        let table_references = TableReferences::new(joined_tables, vec![]);
+
+        // Without statistics both tables look the same, so the tables are joined in the order
+        // they are given and big is scanned.
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints =
+            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            best_plan.table_numbers().collect::<Vec<_>>(),
+            vec![BIG, SMALL]
+        );
+        assert!(access_methods_arena.borrow()[best_plan.data[0].1].is_scan());
+
+        // With statistics, small is scanned and big is searched with its index.
+        let access_methods_arena = RefCell::new(Vec::new());
+        let mut table_constraints =
+            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                .unwrap();
+        for (i, (table_name, row_count)) in [("big", 100_000), ("small", 10)].iter().enumerate() {
+            table_constraints[i].stats = Some(TableStats {
+                row_count: *row_count,
+                index_rows_per_key: HashMap::from([(format!("{table_name}_a"), vec![1])]),
+            });
+        }
+        let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            best_plan.table_numbers().collect::<Vec<_>>(),
+            vec![SMALL, BIG]
+        );
+        assert!(access_methods_arena.borrow()[best_plan.data[0].1].is_scan());
+        let access_method = &access_methods_arena.borrow()[best_plan.data[1].1];
+        assert!(!access_method.is_scan());
+        assert!(access_method.index.as_ref().unwrap().name == "big_a");
+    }
+
+    #[test]
+    /// Test that without indexes [compute_best_join_order] hash joins the smaller table,
+    /// whichever order the tables are given in.
+    fn test_compute_best_join_order_hash_join_builds_smaller_table() {
+        let t1 = _create_btree_table("t1", _create_column_list(&["a"], Type::Integer));
+        let t2 = _create_btree_table("t2", _create_column_list(&["a"], Type::Integer));
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![
+            _create_table_reference(t1.clone(), None, table_id_counter.next()),
+            _create_table_reference(
+                t2.clone(),
+                Some(JoinInfo {
+                    outer: false,
+                    full: false,
+                    using: None,
+                }),
+                table_id_counter.next(),
+            ),
+        ];
+
+        const T1: usize = 0;
+        const T2: usize = 1;
+
+        // SELECT * FROM t1 JOIN t2 WHERE t1.a = t2.a
+        let where_clause = vec![_create_binary_expr(
+            _create_column_expr(joined_tables[T1].internal_id, 0, false), // t1.a
+            ast::Operator::Equals,
+            _create_column_expr(joined_tables[T2].internal_id, 0, false), // t2.a
+        )];
+
+        let table_references = TableReferences::new(joined_tables, vec![]);
+        let available_indexes = HashMap::new();
+
+        for (row_counts, expected_order) in [([100_000, 10], [T1, T2]), ([10, 100_000], [T2, T1])] {
+            let access_methods_arena = RefCell::new(Vec::new());
+            let mut table_constraints =
+                constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                    .unwrap();
+            for (i, row_count) in row_counts.iter().enumerate() {
+                table_constraints[i].stats = Some(TableStats {
+                    row_count: *row_count,
+                    index_rows_per_key: HashMap::new(),
+                });
+                table_constraints[i].hash_join_keys = hash_join_keys(
+                    &table_references.joined_tables()[i],
+                    &table_constraints[i].constraints,
+                    &where_clause,
+                    &table_references,
+                )
+                .unwrap();
+            }
+            let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+                table_references.joined_tables(),
+                None,
+                &table_constraints,
+                &access_methods_arena,
+            )
+            .unwrap()
+            .unwrap();
+            // The large table is scanned once and probes the hash table built from the small one.
+            assert_eq!(
+                best_plan.table_numbers().collect::<Vec<_>>(),
+                expected_order.to_vec()
+            );
+            assert!(access_methods_arena.borrow()[best_plan.data[0].1].is_scan());
+            let access_method = &access_methods_arena.borrow()[best_plan.data[1].1];
+            assert!(access_method.is_hash_join());
+            assert!(access_method.hash_join_keys.len() == 1);
+        }
+    }
+
+    #[test]
+    /// Test that a partial index is only used when the WHERE clause implies its predicate.
+    fn test_partial_index_requires_implied_predicate() {
+        let t = _create_btree_table("t", _create_column_list(&["a", "b"], Type::Integer));
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![_create_table_reference(t, None, table_id_counter.next())];
+        let table_id = joined_tables[0].internal_id;
+        let table_references = TableReferences::new(joined_tables, vec![]);
+
+        // CREATE INDEX t_a ON t(a) WHERE b = 1
+        let mut available_indexes = HashMap::new();
+        let index = Arc::new(Index {
+            name: "t_a".to_string(),
+            table_name: "t".to_string(),
+            columns: vec![IndexColumn {
+                name: "a".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: false,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            where_clause: Some(Box::new(Expr::Binary(
+                Box::new(Expr::Id(ast::Id("b".to_string()))),
+                Operator::Equals,
+                Box::new(_create_numeric_literal("1")),
+            ))),
+        });
+        available_indexes.insert("t".to_string(), vec![index]);
+
+        let a_equals_5 = _create_binary_expr(
+            _create_column_expr(table_id, 0, false),
+            Operator::Equals,
+            _create_numeric_literal("5"),
+        );
+        let b_equals_1 = _create_binary_expr(
+            _create_column_expr(table_id, 1, false),
+            Operator::Equals,
+            _create_numeric_literal("1"),
+        );
+
+        // SELECT * FROM t WHERE a = 5: rows with b <> 1 are not in the index.
+        let where_clause = vec![a_equals_5.clone()];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints =
+            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap()
+        .unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        assert!(access_method.is_scan());
+        assert!(access_method.index.is_none());
+
+        // SELECT * FROM t WHERE a = 5 AND b = 1
+        let where_clause = vec![a_equals_5, b_equals_1];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints =
+            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap()
+        .unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        assert!(!access_method.is_scan());
+        assert!(access_method.index.as_ref().unwrap().name == "t_a");
+    }
+
+    #[test]
+    /// An index on an expression is used for a term constraining that expression, and only then.
+    fn test_expression_index_used_for_matching_expression() {
+        let t = _create_btree_table("t", _create_column_list(&["a", "b"], Type::Integer));
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![_create_table_reference(t, None, table_id_counter.next())];
+        let table_id = joined_tables[0].internal_id;
+        let table_references = TableReferences::new(joined_tables, vec![]);
+
+        // CREATE INDEX t_a_plus_b ON t(a + b)
+        let mut available_indexes = HashMap::new();
+        let indexed_expr = Expr::Binary(
+            Box::new(Expr::Id(ast::Id("a".to_string()))),
+            Operator::Add,
+            Box::new(Expr::Id(ast::Id("b".to_string()))),
+        );
+        let index = Arc::new(Index {
+            name: "t_a_plus_b".to_string(),
+            table_name: "t".to_string(),
+            columns: vec![IndexColumn {
+                name: "a + b".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: EXPR_INDEX_SENTINEL,
+                collation: None,
+                default: None,
+                expr: Some(Box::new(indexed_expr)),
+            }],
+            unique: false,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            where_clause: None,
+        });
+        available_indexes.insert("t".to_string(), vec![index]);
+
+        // SELECT * FROM t WHERE a = 5
+        let where_clause = vec![_create_binary_expr(
+            _create_column_expr(table_id, 0, false),
+            Operator::Equals,
+            _create_numeric_literal("5"),
+        )];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints =
+            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap()
+        .unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        assert!(access_method.is_scan());
+        assert!(access_method.index.is_none());
+
+        // SELECT * FROM t WHERE 5 = a + b
+        let where_clause = vec![_create_binary_expr(
+            _create_numeric_literal("5"),
+            Operator::Equals,
+            Expr::Binary(
+                Box::new(_create_column_expr(table_id, 0, false)),
+                Operator::Add,
+                Box::new(_create_column_expr(table_id, 1, false)),
+            ),
+        )];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints =
+            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
+                .unwrap();
+        let constraint = &table_constraints[0].constraints[0];
+        assert!(constraint.table_col_pos == EXPR_INDEX_SENTINEL);
+        assert!(constraint.where_clause_pos == (0, BinaryExprSide::Lhs));
+        let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap()
+        .unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        assert!(!access_method.is_scan());
+        assert!(access_method.index.as_ref().unwrap().name == "t_a_plus_b");
+        assert!(access_method.constraint_refs.len() == 1);
+    }
+
     #[test]
     /// Test that [compute_best_join_order] returns a sensible order and plan for three tables, each with indexes.
     fn test_compute_best_join_order_three_tables_indexed() {
@@ -805,6 +1185,7 @@ mod tests {
                 table_customers.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    full: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -813,6 +1194,7 @@ mod tests {
                 table_order_items.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    full: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -838,11 +1220,13 @@ mod tests {
                         pos_in_table: 0,
                         collation: None,
                         default: None,
+                        expr: None,
                     }],
                     unique: true,
                     ephemeral: false,
                     root_page: 1,
                     has_rowid: true,
+                    where_clause: None,
                 });
                 available_indexes.insert(table_name.to_string(), vec![index]);
             });
@@ -855,11 +1239,13 @@ mod tests {
                 pos_in_table: 1,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: false,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            where_clause: None,
         });
         let order_id_idx = Arc::new(Index {
             name: "order_items_order_id_idx".to_string(),
@@ -870,11 +1256,13 @@ mod tests {
                 pos_in_table: 1,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: false,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            where_clause: None,
         });
 
         available_indexes
@@ -989,6 +1377,7 @@ mod tests {
                 t2.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    full: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -997,6 +1386,7 @@ mod tests {
                 t3.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    full: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -1095,6 +1485,7 @@ mod tests {
                     t.clone(),
                     Some(JoinInfo {
                         outer: false,
+                        full: false,
                         using: None,
                     }),
                     table_id_counter.next(),
@@ -1104,6 +1495,7 @@ mod tests {
                 fact_table.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    full: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -1285,6 +1677,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "y".to_string(),
@@ -1292,12 +1685,14 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             unique: false,
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            where_clause: None,
         });
 
         let mut available_indexes = HashMap::new();
@@ -1371,6 +1766,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c2".to_string(),
@@ -1378,6 +1774,7 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c3".to_string(),
@@ -1385,12 +1782,14 @@ mod tests {
                     pos_in_table: 2,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             unique: false,
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            where_clause: None,
         });
         available_indexes.insert("t1".to_string(), vec![index]);
 
@@ -1487,6 +1886,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c2".to_string(),
@@ -1494,6 +1894,7 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c3".to_string(),
@@ -1501,12 +1902,14 @@ mod tests {
                     pos_in_table: 2,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
             unique: false,
+            where_clause: None,
         });
         available_indexes.insert("t1".to_string(), vec![index]);
 
@@ -1613,6 +2016,8 @@ mod tests {
             default: None,
             unique: false,
             collation: None,
+            generated_expr: None,
+            generated_stored: false,
         }
     }
     fn _create_column_of_type(name: &str, ty: Type) -> Column {
@@ -1647,7 +2052,11 @@ mod tests {
             columns,
             has_rowid: true,
             is_strict: false,
+            has_autoincrement: false,
             unique_sets: None,
+            column_lookup: Default::default(),
+            foreign_keys: vec![],
+            checks: vec![],
         })
     }
 
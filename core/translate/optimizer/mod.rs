@@ -1,12 +1,15 @@
 use std::{cell::RefCell, cmp::Ordering, collections::HashMap, sync::Arc};
 
 use constraints::{
-    constraints_from_where_clause, usable_constraints_for_join_order, Constraint, ConstraintRef,
+    constraints_from_where_clause, hash_join_keys, usable_constraints_for_join_order, Constraint,
+    ConstraintRef,
 };
 use cost::Cost;
+use flatten_subqueries::flatten_from_clause_subqueries;
 use join::{compute_best_join_order, BestJoinOrderResult};
 use lift_common_subexpressions::lift_common_subexpressions_from_binary_or_terms;
 use order::{compute_order_target, plan_satisfies_order_target, EliminatesSortBy};
+use subquery_push_down::push_down_into_from_clause_subqueries;
 use turso_sqlite3_parser::{
     ast::{self, Expr, SortOrder},
     to_sql_string::ToSqlString as _,
@@ -24,16 +27,18 @@ use super::{
     emitter::Resolver,
     plan::{
         DeletePlan, GroupBy, IterationDirection, JoinOrderMember, JoinedTable, Operation, Plan,
-        Search, SeekDef, SeekKey, SelectPlan, TableReferences, UpdatePlan, WhereTerm,
+        RecursiveCte, Search, SeekDef, SeekKey, SelectPlan, TableReferences, UpdatePlan, WhereTerm,
     },
 };
 
 pub(crate) mod access_method;
 pub(crate) mod constraints;
 pub(crate) mod cost;
+pub(crate) mod flatten_subqueries;
 pub(crate) mod join;
 pub(crate) mod lift_common_subexpressions;
 pub(crate) mod order;
+pub(crate) mod subquery_push_down;
 
 #[tracing::instrument(skip_all, level = tracing::Level::DEBUG)]
 pub fn optimize_plan(plan: &mut Plan, schema: &Schema) -> Result<()> {
@@ -61,6 +66,8 @@ pub fn optimize_plan(plan: &mut Plan, schema: &Schema) -> Result<()> {
  * but having them separate makes them easier to understand
  */
 pub fn optimize_select_plan(plan: &mut SelectPlan, schema: &Schema) -> Result<()> {
+    flatten_from_clause_subqueries(plan)?;
+    push_down_into_from_clause_subqueries(plan)?;
     optimize_subqueries(plan, schema)?;
     rewrite_exprs_select(plan)?;
     if let ConstantConditionEliminationResult::ImpossibleCondition =
@@ -131,6 +138,11 @@ fn optimize_subqueries(plan: &mut SelectPlan, schema: &Schema) -> Result<()> {
     for table in plan.table_references.joined_tables_mut() {
         if let Table::FromClauseSubquery(from_clause_subquery) = &mut table.table {
             optimize_select_plan(&mut from_clause_subquery.plan, schema)?;
+            if let Some(RecursiveCte::Definition { step, .. }) =
+                &mut from_clause_subquery.recursive_cte
+            {
+                optimize_select_plan(step, schema)?;
+            }
         }
     }
 
@@ -158,8 +170,32 @@ fn optimize_table_access(
 ) -> Result<Option<Vec<JoinOrderMember>>> {
     let access_methods_arena = RefCell::new(Vec::new());
     let maybe_order_target = compute_order_target(order_by, group_by.as_mut());
-    let constraints_per_table =
+    let mut constraints_per_table =
         constraints_from_where_clause(where_clause, table_references, available_indexes)?;
+    // The unmatched rows of a FULL OUTER JOIN are emitted after the main loop,
+    // so its output is never in the order of the join.
+    let has_full_join = table_references
+        .joined_tables()
+        .iter()
+        .any(|t| t.join_info.as_ref().is_some_and(|j| j.full));
+    for (table_constraints, table) in constraints_per_table
+        .iter_mut()
+        .zip(table_references.joined_tables())
+    {
+        if let Table::BTree(btree) = &table.table {
+            table_constraints.stats = schema.get_table_stats(&btree.name).cloned();
+            // The second pass of a FULL OUTER JOIN uses the cursors of every table after the
+            // main loop, but a hash table is only built once its loop is reached.
+            if !has_full_join {
+                table_constraints.hash_join_keys = hash_join_keys(
+                    table,
+                    &table_constraints.constraints,
+                    where_clause,
+                    table_references,
+                )?;
+            }
+        }
+    }
     let Some(best_join_order_result) = compute_best_join_order(
         table_references.joined_tables_mut(),
         maybe_order_target.as_ref(),
@@ -195,7 +231,7 @@ fn optimize_table_access(
     };
 
     // Eliminate sorting if possible.
-    if let Some(order_target) = maybe_order_target {
+    if let Some(order_target) = maybe_order_target.filter(|_| !has_full_join) {
         let satisfies_order_target = plan_satisfies_order_target(
             &best_plan,
             &access_methods_arena,
@@ -239,7 +275,31 @@ fn optimize_table_access(
     for (i, join_order_member) in best_join_order.iter().enumerate() {
         let table_idx = join_order_member.original_idx;
         let access_method = &access_methods_arena.borrow()[best_access_methods[i]];
-        if access_method.is_scan() {
+        if access_method.is_hash_join() {
+            let table_constraints = &constraints_per_table[table_idx];
+            let hash_join_keys = &access_method.hash_join_keys;
+            for key in hash_join_keys.iter() {
+                let constraint = &table_constraints.constraints[key.constraint_vec_pos];
+                where_clause[constraint.where_clause_pos.0]
+                    .consumed
+                    .set(true);
+            }
+            let index = ephemeral_index_build(
+                &joined_tables[table_idx],
+                &table_constraints.constraints,
+                hash_join_keys,
+            );
+            joined_tables[table_idx].op = Operation::Search(Search::HashJoin {
+                index: Arc::new(index),
+                probe_keys: hash_join_keys
+                    .iter()
+                    .map(|key| {
+                        table_constraints.constraints[key.constraint_vec_pos]
+                            .get_constraining_expr(where_clause)
+                    })
+                    .collect(),
+            });
+        } else if access_method.is_scan() {
             let try_to_build_ephemeral_index = if schema.indexes_enabled() {
                 let is_leftmost_table = i == 0;
                 let uses_index = access_method.index.is_some();
@@ -247,7 +307,12 @@ fn optimize_table_access(
                     &joined_tables[table_idx].table,
                     Table::FromClauseSubquery(_)
                 );
-                !is_leftmost_table && !uses_index && !source_table_is_from_clause_subquery
+                // The second pass of a FULL OUTER JOIN uses the cursors of every table after
+                // the main loop, but an ephemeral index is only built once its loop is reached.
+                !is_leftmost_table
+                    && !uses_index
+                    && !source_table_is_from_clause_subquery
+                    && !has_full_join
             } else {
                 false
             };
@@ -271,7 +336,9 @@ fn optimize_table_access(
                 };
                 continue;
             };
+            // The ephemeral index only holds columns, so indexed expressions can't be keys.
             let temp_constraint_refs = (0..table_constraints.constraints.len())
+                .filter(|&i| table_constraints.constraints[i].expr.is_none())
                 .map(|i| ConstraintRef {
                     constraint_vec_pos: i,
                     index_col_pos: table_constraints.constraints[i].table_col_pos,
@@ -781,6 +848,7 @@ fn ephemeral_index_build(
             pos_in_table: i,
             collation: c.collation,
             default: c.default.clone(),
+            expr: None,
         })
         // only include columns that are used in the query
         .filter(|c| table_reference.column_is_used(c.pos_in_table))
@@ -817,6 +885,7 @@ fn ephemeral_index_build(
             .table
             .btree()
             .map_or(false, |btree| btree.has_rowid),
+        where_clause: None,
     };
 
     ephemeral_index
@@ -172,6 +172,10 @@ pub fn plan_satisfies_order_target(
 
         // Check if this table has an access method that provides the right ordering.
         let access_method = &access_methods_arena.borrow()[*access_method_index];
+        // The rows of a hash join come in the order of the probes, not of the table.
+        if access_method.is_hash_join() {
+            return false;
+        }
         let iter_dir = access_method.iter_dir;
         let index = access_method.index.as_ref();
         match index {
@@ -0,0 +1,140 @@
+use std::cell::Cell;
+
+use turso_sqlite3_parser::ast::{self, TableInternalId};
+
+use crate::{
+    function::Func,
+    schema::Table,
+    translate::{
+        expr::{walk_expr, WalkControl},
+        plan::{SelectPlan, WhereTerm},
+    },
+    util::normalize_ident,
+    Result,
+};
+
+use super::flatten_subqueries::replace_subquery_column_refs;
+
+/// Moves the WHERE terms that only refer to the columns of a FROM clause subquery into the
+/// WHERE clause of that subquery, like SQLite's push-down optimization
+/// (see https://www.sqlite.org/optoverview.html#pushdown).
+/// For example, in:
+/// ```sql
+/// SELECT * FROM (SELECT DISTINCT x, y FROM t) WHERE x > 5
+/// ```
+/// the rows of `t` are filtered before they are deduplicated, and an index on `t.x` can be used,
+/// instead of filtering every row the subquery yields.
+///
+/// Subqueries that can be flattened are merged into the outer query before this runs, see
+/// [super::flatten_subqueries::flatten_from_clause_subqueries].
+pub(crate) fn push_down_into_from_clause_subqueries(plan: &mut SelectPlan) -> Result<()> {
+    // Window functions are always computed over a FROM clause subquery, see translate/window.rs.
+    // The unmatched rows of a FULL JOIN would change if one side were filtered first.
+    if plan.window.is_some()
+        || plan
+            .joined_tables()
+            .iter()
+            .any(|t| t.join_info.as_ref().map_or(false, |j| j.full))
+    {
+        return Ok(());
+    }
+    for idx in 0..plan.joined_tables().len() {
+        let joined_table = &plan.joined_tables()[idx];
+        let Table::FromClauseSubquery(subquery) = &joined_table.table else {
+            continue;
+        };
+        // The plan of a recursive CTE only seeds it, so filtering it doesn't filter the rows
+        // produced by its recursive SELECT.
+        if subquery.recursive_cte.is_some() || !can_push_down_into(&subquery.plan) {
+            continue;
+        }
+        let subquery_id = joined_table.internal_id;
+        // The WHERE terms also filter the NULL rows of the right side of an OUTER JOIN, so only
+        // the terms of its ON clause can be pushed down.
+        let from_outer_join = joined_table
+            .join_info
+            .as_ref()
+            .filter(|j| j.outer)
+            .map(|_| subquery_id);
+
+        let mut pushed = vec![];
+        let mut i = 0;
+        while i < plan.where_clause.len() {
+            let term = &plan.where_clause[i];
+            if term.from_outer_join == from_outer_join
+                && only_refers_to_table(&term.expr, subquery_id)?
+            {
+                pushed.push(plan.where_clause.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        if pushed.is_empty() {
+            continue;
+        }
+
+        let Table::FromClauseSubquery(subquery) =
+            &mut plan.table_references.joined_tables_mut()[idx].table
+        else {
+            unreachable!("table was checked to be a FROM clause subquery");
+        };
+        let columns = subquery
+            .plan
+            .result_columns
+            .iter()
+            .map(|rc| rc.expr.clone())
+            .collect::<Vec<_>>();
+        for mut term in pushed {
+            replace_subquery_column_refs(&mut term.expr, subquery_id, &columns)?;
+            subquery.plan.where_clause.push(WhereTerm {
+                expr: term.expr,
+                from_outer_join: None,
+                consumed: Cell::new(false),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if filtering the rows of `subplan` before it yields them gives the same result
+/// as filtering the rows it yields.
+fn can_push_down_into(subplan: &SelectPlan) -> bool {
+    // Grouping and LIMIT/OFFSET see every row, so the rows can't be filtered before.
+    subplan.aggregates.is_empty()
+        && !subplan
+            .result_columns
+            .iter()
+            .any(|rc| rc.contains_aggregates)
+        && subplan.group_by.is_none()
+        && subplan.limit.is_none()
+        && subplan.offset.is_none()
+        && subplan.window.is_none()
+        && subplan.values.is_empty()
+        && !subplan.joined_tables().is_empty()
+}
+
+/// Returns true if `expr` refers to columns of the table `table_id`, and to no other table,
+/// and only calls deterministic functions.
+fn only_refers_to_table(expr: &ast::Expr, table_id: TableInternalId) -> Result<bool> {
+    let mut refers_to_table = false;
+    let mut can_push_down = true;
+    walk_expr(expr, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+        match expr {
+            ast::Expr::Column { table, .. } => {
+                refers_to_table = true;
+                can_push_down &= *table == table_id;
+            }
+            ast::Expr::RowId { .. } => can_push_down = false,
+            ast::Expr::FunctionCall { name, args, .. } => {
+                // Functions that are not builtins, e.g. the ones of extensions, may not be
+                // deterministic.
+                let arg_count = args.as_ref().map_or(0, |a| a.len());
+                can_push_down &= Func::resolve_function(&normalize_ident(&name.0), arg_count)
+                    .is_ok_and(|function| function.is_deterministic());
+            }
+            _ => {}
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(refers_to_table && can_push_down)
+}
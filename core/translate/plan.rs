@@ -3,8 +3,8 @@ use turso_ext::{ConstraintInfo, ConstraintOp};
 use turso_sqlite3_parser::ast::{self, SortOrder};
 
 use crate::{
-    function::AggFunc,
-    schema::{BTreeTable, Column, FromClauseSubquery, Index, Table},
+    function::{AggFunc, WindowFunc},
+    schema::{affinity, Affinity, BTreeTable, Column, FromClauseSubquery, Index, Table},
     vdbe::{
         builder::{CursorKey, CursorType, ProgramBuilder},
         insn::{IdxInsertFlags, Insn},
@@ -12,11 +12,18 @@ use crate::{
     },
     Result, VirtualTable,
 };
-use crate::{schema::Type, types::SeekOp, util::can_pushdown_predicate};
+use crate::{
+    schema::Type,
+    types::SeekOp,
+    util::{can_pushdown_predicate, normalize_ident},
+};
 
 use turso_sqlite3_parser::ast::TableInternalId;
 
-use super::{emitter::OperationMode, planner::determine_where_to_eval_term};
+use super::{
+    emitter::OperationMode, generated::generated_column_dependencies,
+    planner::determine_where_to_eval_term,
+};
 
 #[derive(Debug, Clone)]
 pub struct ResultSetColumn {
@@ -53,6 +60,35 @@ impl ResultSetColumn {
             _ => None,
         }
     }
+
+    /// The declared type of the column this result column reads from, if it reads directly
+    /// from a table column. Like SQLite, aliased columns keep the declared type of their source.
+    pub fn declared_type<'a>(&'a self, tables: &'a TableReferences) -> Option<&'a str> {
+        Self::expr_declared_type(&self.expr, tables)
+    }
+
+    fn expr_declared_type<'a>(expr: &'a ast::Expr, tables: &'a TableReferences) -> Option<&'a str> {
+        match expr {
+            ast::Expr::Column { table, column, .. } => {
+                let table_ref = tables.find_table_by_internal_id(*table)?;
+                let ty = table_ref.get_column_at(*column)?.ty_str.as_str();
+                (!ty.is_empty()).then_some(ty)
+            }
+            ast::Expr::RowId { table, .. } => {
+                let table_ref = tables.find_table_by_internal_id(*table)?;
+                if let Table::BTree(table) = &table_ref {
+                    if let Some((_, rowid_alias_column)) = table.get_rowid_alias_column() {
+                        return Some(&rowid_alias_column.ty_str);
+                    }
+                }
+                Some("INTEGER")
+            }
+            ast::Expr::Parenthesized(exprs) if exprs.len() == 1 => {
+                Self::expr_declared_type(&exprs[0], tables)
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -333,6 +369,33 @@ pub enum QueryDestination {
         /// The table that will be used to store the results.
         table: Rc<BTreeTable>,
     },
+    /// The results of the query are appended to the queue of a recursive CTE,
+    /// from which the CTE yields its rows one at a time.
+    RecursiveCteQueue {
+        /// The cursor ID of the ephemeral table that holds the rows waiting to be yielded.
+        cursor_id: CursorID,
+        /// For `UNION`, the ephemeral index holding every row queued so far;
+        /// rows that are already in it are not queued again.
+        dedupe: Option<(CursorID, Arc<Index>)>,
+    },
+}
+
+/// The role of a FROM clause subquery in a `WITH RECURSIVE` query.
+#[derive(Debug, Clone)]
+pub enum RecursiveCte {
+    /// The recursive CTE itself. The subquery's plan is the initial SELECT that seeds the queue;
+    /// `step` is run once for every row taken off the queue and appends its results to the queue.
+    Definition {
+        /// The recursive SELECT, which reads the current row through a [RecursiveCte::CurrentRow] table.
+        step: Box<SelectPlan>,
+        /// `UNION` rather than `UNION ALL`: rows that were already produced are discarded.
+        distinct: bool,
+        /// The maximum number of rows the CTE yields, from a LIMIT on the CTE's SELECT.
+        limit: Option<isize>,
+    },
+    /// The reference to the CTE in its recursive SELECT. It yields the single row that was
+    /// last taken off the queue; its registers are bound when the definition is emitted.
+    CurrentRow,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -440,6 +503,8 @@ pub struct SelectPlan {
     pub distinctness: Distinctness,
     /// values: https://sqlite.org/syntax/select-core.html
     pub values: Vec<Vec<Expr>>,
+    /// the window the window functions of this query are computed over, see translate/window.rs
+    pub window: Option<Window>,
 }
 
 impl SelectPlan {
@@ -516,6 +581,8 @@ pub struct DeletePlan {
     pub contains_constant_false_condition: bool,
     /// Indexes that must be updated by the delete operation.
     pub indexes: Vec<Arc<Index>>,
+    /// Whether foreign key actions are run for the deleted rows (`PRAGMA foreign_keys`).
+    pub enforce_foreign_keys: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -532,6 +599,10 @@ pub struct UpdatePlan {
     // whether the WHERE clause is always false
     pub contains_constant_false_condition: bool,
     pub indexes_to_update: Vec<Arc<Index>>,
+    // whether foreign keys are checked and their actions run (PRAGMA foreign_keys)
+    pub enforce_foreign_keys: bool,
+    // whether CHECK constraints are evaluated (off with PRAGMA ignore_check_constraints)
+    pub enforce_check_constraints: bool,
     // If the table's rowid alias is used, gather all the target rowids into an ephemeral table, and then use that table as the single JoinedTable for the actual UPDATE loop.
     pub ephemeral_plan: Option<SelectPlan>,
 }
@@ -543,11 +614,67 @@ pub enum IterationDirection {
 }
 
 pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn>) {
+    let star_columns_start = out_columns.len();
     for table in tables.iter() {
         let maybe_using_cols = table
             .join_info
             .as_ref()
             .and_then(|join_info| join_info.using.as_ref());
+        // The columns of a FULL JOIN ... USING are NULL on the side that has no match,
+        // so the deduplicated column takes its value from whichever side has one.
+        if let Some(using_cols) = maybe_using_cols.filter(|_| {
+            table
+                .join_info
+                .as_ref()
+                .is_some_and(|join_info| join_info.full)
+        }) {
+            for using_col in using_cols.iter() {
+                let is_using_col = |col: &Column| {
+                    col.name.as_ref().is_some_and(|name| {
+                        name.eq_ignore_ascii_case(&normalize_ident(&using_col.0))
+                    })
+                };
+                let Some((right_idx, right_col)) = table
+                    .columns()
+                    .iter()
+                    .enumerate()
+                    .find(|(_, col)| is_using_col(col))
+                else {
+                    continue;
+                };
+                let column_of = |expr: &ast::Expr| {
+                    let ast::Expr::Column { table, column, .. } = expr else {
+                        return None;
+                    };
+                    tables
+                        .iter()
+                        .find(|t| t.internal_id == *table)
+                        .and_then(|t| t.columns().get(*column))
+                };
+                let left_column = out_columns[star_columns_start..]
+                    .iter_mut()
+                    .find(|rc| column_of(&rc.expr).is_some_and(is_using_col));
+                if let Some(left_column) = left_column {
+                    let left_expr = left_column.expr.clone();
+                    left_column.alias = column_of(&left_expr).and_then(|col| col.name.clone());
+                    left_column.expr = ast::Expr::FunctionCall {
+                        name: ast::Id("coalesce".to_string()),
+                        distinctness: None,
+                        args: Some(vec![
+                            left_expr,
+                            ast::Expr::Column {
+                                database: None,
+                                table: table.internal_id,
+                                column: right_idx,
+                                is_rowid_alias: right_col.is_rowid_alias,
+                            },
+                        ]),
+                        order_by: None,
+                        filter_over: None,
+                    };
+                }
+            }
+        }
         out_columns.extend(
             table
                 .columns()
@@ -558,9 +685,9 @@ pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn
                     // that are also present in the USING clause.
                     if let Some(using_cols) = maybe_using_cols {
                         !using_cols.iter().any(|using_col| {
-                            col.name
-                                .as_ref()
-                                .map_or(false, |name| name.eq_ignore_ascii_case(&using_col.0))
+                            col.name.as_ref().map_or(false, |name| {
+                                name.eq_ignore_ascii_case(&normalize_ident(&using_col.0))
+                            })
                         })
                     } else {
                         true
@@ -585,6 +712,10 @@ pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn
 pub struct JoinInfo {
     /// Whether this is an OUTER JOIN.
     pub outer: bool,
+    /// Whether this is a FULL OUTER JOIN. `outer` is also set, since the left tables are joined
+    /// to this table like in a LEFT JOIN; the rows of this table that matched no row are emitted
+    /// afterwards, see [crate::translate::main_loop::emit_full_join_unmatched_rows].
+    pub full: bool,
     /// The USING clause for the join, if any. NATURAL JOIN is transformed into USING (col1, col2, ...).
     pub using: Option<ast::DistinctNames>,
 }
@@ -598,7 +729,7 @@ pub struct JoinInfo {
 /// - all have [Operation::Scan]
 /// - identifiers are `t`, `p`, `sub`
 /// - `t` and `p` are [Table::BTree] while `sub` is [Table::FromClauseSubquery]
-/// - join_info is None for the first table reference, and Some(JoinInfo { outer: false, using: None }) for the second and third table references
+/// - join_info is None for the first table reference, and Some(JoinInfo { outer: false, full: false, using: None }) for the second and third table references
 #[derive(Debug, Clone)]
 pub struct JoinedTable {
     /// The operation that this table reference performs.
@@ -827,33 +958,35 @@ impl TableReferences {
     }
 }
 
+/// Bitset of the columns of a table that are referenced by a query.
+/// Grows on demand so that tables of any width can be tracked.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
-#[repr(transparent)]
-pub struct ColumnUsedMask(u128);
+pub struct ColumnUsedMask(Vec<u64>);
 
 impl ColumnUsedMask {
     pub fn set(&mut self, index: usize) {
-        assert!(
-            index < 128,
-            "ColumnUsedMask only supports up to 128 columns"
-        );
-        self.0 |= 1 << index;
+        let word = index / 64;
+        if word >= self.0.len() {
+            self.0.resize(word + 1, 0);
+        }
+        self.0[word] |= 1 << (index % 64);
     }
 
     pub fn get(&self, index: usize) -> bool {
-        assert!(
-            index < 128,
-            "ColumnUsedMask only supports up to 128 columns"
-        );
-        self.0 & (1 << index) != 0
+        self.0
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
     }
 
     pub fn contains_all_set_bits_of(&self, other: &Self) -> bool {
-        self.0 & other.0 == other.0
+        other.0.iter().enumerate().all(|(i, &bits)| {
+            let ours = self.0.get(i).copied().unwrap_or(0);
+            ours & bits == bits
+        })
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0 == 0
+        self.0.iter().all(|&word| word == 0)
     }
 }
 
@@ -879,6 +1012,7 @@ impl Operation {
             Operation::Scan { index, .. } => index.as_ref(),
             Operation::Search(Search::RowidEq { .. }) => None,
             Operation::Search(Search::Seek { index, .. }) => index.as_ref(),
+            Operation::Search(Search::HashJoin { index, .. }) => Some(index),
         }
     }
 }
@@ -908,16 +1042,32 @@ impl JoinedTable {
         let columns = plan
             .result_columns
             .iter()
-            .map(|rc| Column {
-                name: rc.name(&plan.table_references).map(String::from),
-                ty: Type::Blob, // FIXME: infer proper type
-                ty_str: "BLOB".to_string(),
-                is_rowid_alias: false,
-                primary_key: false,
-                notnull: false,
-                default: None,
-                unique: false,
-                collation: None, // FIXME: infer collation from subquery
+            .map(|rc| {
+                // Like SQLite, a subquery column keeps the declared type of the table column it reads.
+                let ty_str = rc
+                    .declared_type(&plan.table_references)
+                    .unwrap_or_default()
+                    .to_string();
+                let ty = match affinity(&ty_str.to_uppercase()) {
+                    Affinity::Integer => Type::Integer,
+                    Affinity::Text => Type::Text,
+                    Affinity::Blob => Type::Blob,
+                    Affinity::Real => Type::Real,
+                    Affinity::Numeric => Type::Numeric,
+                };
+                Column {
+                    name: rc.name(&plan.table_references).map(String::from),
+                    ty,
+                    ty_str,
+                    is_rowid_alias: false,
+                    primary_key: false,
+                    notnull: false,
+                    default: None,
+                    unique: false,
+                    collation: None, // FIXME: infer collation from subquery
+                    generated_expr: None,
+                    generated_stored: false,
+                }
             })
             .collect();
 
@@ -926,6 +1076,7 @@ impl JoinedTable {
             plan: Box::new(plan),
             columns,
             result_columns_start_reg: None,
+            recursive_cte: None,
         });
         Self {
             op: Operation::Scan {
@@ -947,7 +1098,20 @@ impl JoinedTable {
     /// Mark a column as used in the query.
     /// This is used to determine whether a covering index can be used.
     pub fn mark_column_used(&mut self, index: usize) {
+        if self.col_used_mask.get(index) {
+            return;
+        }
         self.col_used_mask.set(index);
+        // A VIRTUAL generated column is read through the columns it is computed from.
+        if let Some(btree) = self
+            .table
+            .btree()
+            .filter(|btree| btree.columns[index].is_virtual())
+        {
+            for dependency in generated_column_dependencies(&btree, index).unwrap_or_default() {
+                self.mark_column_used(dependency);
+            }
+        }
     }
 
     /// Open the necessary cursors for this table reference.
@@ -974,9 +1138,15 @@ impl JoinedTable {
                     ))
                 };
                 let index_cursor_id = index.map(|index| {
+                    let cursor_type = match &self.op {
+                        Operation::Search(Search::HashJoin { .. }) => {
+                            CursorType::HashTable(index.clone())
+                        }
+                        _ => CursorType::BTreeIndex(index.clone()),
+                    };
                     program.alloc_cursor_id_keyed(
                         CursorKey::index(self.internal_id, index.clone()),
-                        CursorType::BTreeIndex(index.clone()),
+                        cursor_type,
                     )
                 });
                 Ok((table_cursor_id, index_cursor_id))
@@ -1015,7 +1185,7 @@ impl JoinedTable {
             return false;
         }
         let mut index_cols_mask = ColumnUsedMask::default();
-        for col in index.columns.iter() {
+        for col in index.columns.iter().filter(|col| col.expr.is_none()) {
             index_cols_mask.set(col.pos_in_table);
         }
 
@@ -1118,6 +1288,17 @@ pub enum Search {
         index: Option<Arc<Index>>,
         seek_def: SeekDef,
     },
+    /// A hash join. The table is read once into an in-memory hash table keyed by the join columns
+    /// (the build side), which is then probed with the join key of every row of the outer tables.
+    /// Only used when the table can't be searched with one of its own indexes.
+    HashJoin {
+        /// The layout of the rows of the hash table: the key columns first, then the other
+        /// columns the query uses. The hash table covers the query, so the table's columns are
+        /// read from it like from a covering index.
+        index: Arc<Index>,
+        /// The expressions of the outer tables that are compared with the key columns, in order.
+        probe_keys: Vec<ast::Expr>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -1133,3 +1314,24 @@ impl Aggregate {
         self.distinctness.is_distinct()
     }
 }
+
+/// A window and the window functions computed over it.
+///
+/// Queries with window functions are rewritten so that the window is always computed over the
+/// rows of a FROM clause subquery, which means all the expressions here only read columns of
+/// that subquery.
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub partition_by: Vec<ast::Expr>,
+    pub order_by: Vec<(ast::Expr, SortOrder)>,
+    pub functions: Vec<WindowFunction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowFunction {
+    pub func: WindowFunc,
+    pub args: Vec<ast::Expr>,
+    /// The frame of the OVER clause, if any. Only used by functions for which [WindowFunc::uses_frame] is true.
+    pub frame: Option<ast::FrameClause>,
+    pub original_expr: ast::Expr,
+}
@@ -1,11 +1,11 @@
-use std::cell::Cell;
+use std::{cell::Cell, rc::Rc};
 
 use super::{
     expr::walk_expr,
     plan::{
         Aggregate, ColumnUsedMask, Distinctness, EvalAt, IterationDirection, JoinInfo,
         JoinOrderMember, JoinedTable, Operation, OuterQueryReference, Plan, QueryDestination,
-        ResultSetColumn, TableReferences, WhereTerm,
+        RecursiveCte, ResultSetColumn, SelectPlan, TableReferences, WhereTerm,
     },
     select::prepare_select_plan,
     SymbolTable,
@@ -20,7 +20,8 @@ use crate::{
     Result,
 };
 use turso_sqlite3_parser::ast::{
-    self, Expr, FromClause, JoinType, Limit, Materialized, TableInternalId, UnaryOperator, With,
+    self, CommonTableExpr, CompoundOperator, Expr, FromClause, IndexedColumn, JoinType, Limit,
+    Materialized, OneSelect, SelectBody, SelectTable, TableInternalId, UnaryOperator, With,
 };
 
 pub const ROWID: &str = "rowid";
@@ -120,7 +121,12 @@ pub fn bind_column_references(
                 }
                 let normalized_id = normalize_ident(id.0.as_str());
 
-                if !referenced_tables.joined_tables().is_empty() {
+                // A WITHOUT ROWID table has no rowid to refer to.
+                if referenced_tables
+                    .joined_tables()
+                    .first()
+                    .is_some_and(|table| table.btree().map_or(true, |table| table.has_rowid))
+                {
                     if let Some(row_id_expr) = parse_row_id(
                         &normalized_id,
                         referenced_tables.joined_tables()[0].internal_id,
@@ -132,24 +138,38 @@ pub fn bind_column_references(
                     }
                 }
                 let mut match_result = None;
+                let mut full_join_using_match = None;
 
                 // First check joined tables
                 for joined_table in referenced_tables.joined_tables().iter() {
-                    let col_idx = joined_table.table.columns().iter().position(|c| {
-                        c.name
-                            .as_ref()
-                            .map_or(false, |name| name.eq_ignore_ascii_case(&normalized_id))
-                    });
-                    if col_idx.is_some() {
+                    if let Some((col_idx, col)) =
+                        joined_table.table.get_column_by_name(&normalized_id)
+                    {
                         if match_result.is_some() {
+                            // A column named in USING is the column it is joined with on the left,
+                            // or whichever of the two is not NULL for a FULL JOIN.
+                            let join_info = joined_table.join_info.as_ref();
+                            if join_info
+                                .and_then(|join_info| join_info.using.as_ref())
+                                .is_some_and(|using| {
+                                    using
+                                        .iter()
+                                        .any(|name| normalize_ident(&name.0) == normalized_id)
+                                })
+                            {
+                                if join_info.is_some_and(|join_info| join_info.full) {
+                                    full_join_using_match = Some((
+                                        joined_table.internal_id,
+                                        col_idx,
+                                        col.is_rowid_alias,
+                                    ));
+                                }
+                                continue;
+                            }
                             crate::bail_parse_error!("Column {} is ambiguous", id.0);
                         }
-                        let col = joined_table.table.columns().get(col_idx.unwrap()).unwrap();
-                        match_result = Some((
-                            joined_table.internal_id,
-                            col_idx.unwrap(),
-                            col.is_rowid_alias,
-                        ));
+                        match_result =
+                            Some((joined_table.internal_id, col_idx, col.is_rowid_alias));
                     }
                 }
 
@@ -163,18 +183,14 @@ pub fn bind_column_references(
                 // - x in the inner query refers to t2.x.
                 if match_result.is_none() {
                     for outer_ref in referenced_tables.outer_query_refs().iter() {
-                        let col_idx = outer_ref.table.columns().iter().position(|c| {
-                            c.name
-                                .as_ref()
-                                .map_or(false, |name| name.eq_ignore_ascii_case(&normalized_id))
-                        });
-                        if col_idx.is_some() {
+                        if let Some((col_idx, col)) =
+                            outer_ref.table.get_column_by_name(&normalized_id)
+                        {
                             if match_result.is_some() {
                                 crate::bail_parse_error!("Column {} is ambiguous", id.0);
                             }
-                            let col = outer_ref.table.columns().get(col_idx.unwrap()).unwrap();
                             match_result =
-                                Some((outer_ref.internal_id, col_idx.unwrap(), col.is_rowid_alias));
+                                Some((outer_ref.internal_id, col_idx, col.is_rowid_alias));
                         }
                     }
                 }
@@ -187,6 +203,22 @@ pub fn bind_column_references(
                         is_rowid_alias,
                     };
                     referenced_tables.mark_column_used(table_id, col_idx);
+                    if let Some((table_id, col_idx, is_rowid_alias)) = full_join_using_match {
+                        let right_expr = Expr::Column {
+                            database: None,
+                            table: table_id,
+                            column: col_idx,
+                            is_rowid_alias,
+                        };
+                        referenced_tables.mark_column_used(table_id, col_idx);
+                        *expr = Expr::FunctionCall {
+                            name: ast::Id("coalesce".to_string()),
+                            distinctness: None,
+                            args: Some(vec![expr.clone(), right_expr]),
+                            order_by: None,
+                            filter_over: None,
+                        };
+                    }
                     return Ok(());
                 }
 
@@ -213,20 +245,16 @@ pub fn bind_column_references(
                 let (tbl_id, tbl) = matching_tbl.unwrap();
                 let normalized_id = normalize_ident(id.0.as_str());
 
-                if let Some(row_id_expr) = parse_row_id(&normalized_id, tbl_id, || false)? {
-                    *expr = row_id_expr;
+                if tbl.btree().map_or(true, |table| table.has_rowid) {
+                    if let Some(row_id_expr) = parse_row_id(&normalized_id, tbl_id, || false)? {
+                        *expr = row_id_expr;
 
-                    return Ok(());
+                        return Ok(());
+                    }
                 }
-                let col_idx = tbl.columns().iter().position(|c| {
-                    c.name
-                        .as_ref()
-                        .map_or(false, |name| name.eq_ignore_ascii_case(&normalized_id))
-                });
-                let Some(col_idx) = col_idx else {
+                let Some((col_idx, col)) = tbl.get_column_by_name(&normalized_id) else {
                     crate::bail_parse_error!("Column {} not found", normalized_id);
                 };
-                let col = tbl.columns().get(col_idx).unwrap();
                 *expr = Expr::Column {
                     database: None, // TODO: support different databases
                     table: tbl_id,
@@ -270,7 +298,7 @@ fn parse_from_clause_table(
                         ast::As::As(id) => id,
                         ast::As::Elided(id) => id,
                     })
-                    .map(|a| a.0);
+                    .map(|a| normalize_ident(&a.0));
                 let tbl_ref = if let Table::Virtual(tbl) = table.as_ref() {
                     Table::Virtual(tbl.clone())
                 } else if let Table::BTree(table) = table.as_ref() {
@@ -320,6 +348,29 @@ fn parse_from_clause_table(
                 }
             }
 
+            // DBSTAT is an eponymous virtual table: it can be used without being created first.
+            if normalized_qualified_name == crate::dbstat::DBSTAT_NAME {
+                let vtab = crate::VirtualTable::function(&normalized_qualified_name, None, syms)?;
+                let alias = maybe_alias
+                    .map(|a| match a {
+                        ast::As::As(id) => normalize_ident(&id.0),
+                        ast::As::Elided(id) => normalize_ident(&id.0),
+                    })
+                    .unwrap_or(normalized_qualified_name);
+                table_references.add_joined_table(JoinedTable {
+                    op: Operation::Scan {
+                        iter_dir: IterationDirection::Forwards,
+                        index: None,
+                    },
+                    join_info: None,
+                    table: Table::Virtual(vtab),
+                    identifier: alias,
+                    internal_id: table_ref_counter.next(),
+                    col_used_mask: ColumnUsedMask::default(),
+                });
+                return Ok(());
+            }
+
             crate::bail_parse_error!("Table {} not found", normalized_qualified_name);
         }
         ast::SelectTable::Select(subselect, maybe_alias) => {
@@ -340,8 +391,8 @@ fn parse_from_clause_table(
             let cur_table_index = table_references.joined_tables().len();
             let identifier = maybe_alias
                 .map(|a| match a {
-                    ast::As::As(id) => id.0.clone(),
-                    ast::As::Elided(id) => id.0.clone(),
+                    ast::As::As(id) => normalize_ident(&id.0),
+                    ast::As::Elided(id) => normalize_ident(&id.0),
                 })
                 .unwrap_or(format!("subquery_{}", cur_table_index));
             table_references.add_joined_table(JoinedTable::new_subquery(
@@ -358,8 +409,8 @@ fn parse_from_clause_table(
             let alias = maybe_alias
                 .as_ref()
                 .map(|a| match a {
-                    ast::As::As(id) => id.0.clone(),
-                    ast::As::Elided(id) => id.0.clone(),
+                    ast::As::As(id) => normalize_ident(&id.0),
+                    ast::As::Elided(id) => normalize_ident(&id.0),
                 })
                 .unwrap_or(normalized_name.to_string());
 
@@ -397,16 +448,10 @@ pub fn parse_from(
     let mut ctes_as_subqueries = vec![];
 
     if let Some(with) = with {
-        if with.recursive {
-            crate::bail_parse_error!("Recursive CTEs are not yet supported");
-        }
         for cte in with.ctes {
             if cte.materialized == Materialized::Yes {
                 crate::bail_parse_error!("Materialized CTEs are not yet supported");
             }
-            if cte.columns.is_some() {
-                crate::bail_parse_error!("CTE columns are not yet supported");
-            }
 
             // Check if normalized name conflicts with catalog tables or other CTEs
             // TODO: sqlite actually allows overriding a catalog table with a CTE.
@@ -440,6 +485,18 @@ pub fn parse_from(
                 }
             }));
 
+            if with.recursive && is_recursive_cte(&cte_name_normalized, &cte.select.body) {
+                ctes_as_subqueries.push(parse_recursive_cte(
+                    schema,
+                    cte,
+                    cte_name_normalized,
+                    syms,
+                    &outer_query_refs_for_cte,
+                    table_ref_counter,
+                )?);
+                continue;
+            }
+
             // CTE can refer to other CTEs that came before it, plus any schema tables or tables in the outer scope.
             let cte_plan = prepare_select_plan(
                 schema,
@@ -452,9 +509,12 @@ pub fn parse_from(
                     coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
                 },
             )?;
-            let Plan::Select(cte_plan) = cte_plan else {
+            let Plan::Select(mut cte_plan) = cte_plan else {
                 crate::bail_parse_error!("Only SELECT queries are currently supported in CTEs");
             };
+            if let Some(columns) = &cte.columns {
+                apply_cte_column_names(&cte_name_normalized, &mut cte_plan, columns)?;
+            }
             ctes_as_subqueries.push(JoinedTable::new_subquery(
                 cte_name_normalized,
                 cte_plan,
@@ -488,9 +548,204 @@ pub fn parse_from(
         )?;
     }
 
+    bind_table_valued_function_args(table_references)?;
+
+    Ok(())
+}
+
+/// Binds the arguments of the table-valued functions in the FROM clause, which can refer to the
+/// columns of the other tables, e.g. `SELECT * FROM t, pragma_table_info(t.name)`.
+fn bind_table_valued_function_args(table_references: &mut TableReferences) -> Result<()> {
+    for i in 0..table_references.joined_tables().len() {
+        let Table::Virtual(vtab) = &table_references.joined_tables()[i].table else {
+            continue;
+        };
+        let Some(mut args) = vtab.args.clone() else {
+            continue;
+        };
+        for arg in args.iter_mut() {
+            bind_column_references(arg, table_references, None)?;
+        }
+        let Table::Virtual(vtab) = &mut table_references.joined_tables_mut()[i].table else {
+            unreachable!("table was checked to be a virtual table");
+        };
+        Rc::make_mut(vtab).args = Some(args);
+    }
+    Ok(())
+}
+
+/// Renames the result columns of a CTE to the column names declared with it, e.g. `WITH c(x, y) AS (...)`.
+fn apply_cte_column_names(
+    cte_name: &str,
+    plan: &mut SelectPlan,
+    columns: &[IndexedColumn],
+) -> Result<()> {
+    if columns.len() != plan.result_columns.len() {
+        crate::bail_parse_error!(
+            "table {} has {} values for {} columns",
+            cte_name,
+            plan.result_columns.len(),
+            columns.len()
+        );
+    }
+    for (result_column, column) in plan.result_columns.iter_mut().zip(columns) {
+        result_column.alias = Some(normalize_ident(&column.col_name.0));
+    }
     Ok(())
 }
 
+/// Returns true if the CTE is an initial SELECT followed by `UNION [ALL]` and a SELECT that reads
+/// the CTE itself in its FROM clause.
+fn is_recursive_cte(cte_name: &str, body: &SelectBody) -> bool {
+    let Some(recursive) = body.compounds.as_ref().and_then(|c| c.last()) else {
+        return false;
+    };
+    if !matches!(
+        recursive.operator,
+        CompoundOperator::Union | CompoundOperator::UnionAll
+    ) {
+        return false;
+    }
+    let OneSelect::Select(select) = recursive.select.as_ref() else {
+        return false;
+    };
+    let Some(from) = &select.from else {
+        return false;
+    };
+    from.select
+        .iter()
+        .map(|t| t.as_ref())
+        .chain(from.joins.iter().flatten().map(|j| &j.table))
+        .any(|t| matches!(t, SelectTable::Table(name, ..) if normalize_ident(&name.name.0) == cte_name))
+}
+
+/// Plans a recursive CTE, e.g. `WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 10)`.
+///
+/// The initial SELECT becomes the plan of the returned subquery, and the recursive SELECT is kept in
+/// [RecursiveCte::Definition]. Inside the recursive SELECT, the CTE refers to the single row
+/// currently being processed; see [crate::translate::subquery] for how the recursion is emitted.
+fn parse_recursive_cte(
+    schema: &Schema,
+    cte: CommonTableExpr,
+    cte_name: String,
+    syms: &SymbolTable,
+    outer_query_refs: &[OuterQueryReference],
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<JoinedTable> {
+    let ast::Select {
+        with,
+        body,
+        order_by,
+        limit,
+    } = *cte.select;
+    if with.is_some() {
+        crate::bail_parse_error!("WITH clauses in recursive CTEs are not yet supported");
+    }
+    if order_by.is_some() {
+        crate::bail_parse_error!("ORDER BY in recursive CTEs is not yet supported");
+    }
+    let limit = match limit {
+        Some(limit) => {
+            let (limit, offset) = parse_limit(&limit)?;
+            if offset.is_some_and(|offset| offset > 0) {
+                crate::bail_parse_error!("OFFSET in recursive CTEs is not yet supported");
+            }
+            limit
+        }
+        None => None,
+    };
+    let mut compounds = body.compounds.unwrap_or_default();
+    let recursive = compounds
+        .pop()
+        .expect("recursive CTE must have a recursive SELECT");
+    if !compounds.is_empty() {
+        crate::bail_parse_error!(
+            "Recursive CTEs with more than one initial or recursive SELECT are not yet supported"
+        );
+    }
+    let distinct = recursive.operator == CompoundOperator::Union;
+    let single_select = |select: Box<OneSelect>| ast::Select {
+        with: None,
+        body: SelectBody {
+            select,
+            compounds: None,
+        },
+        order_by: None,
+        limit: None,
+    };
+    let subquery_destination = || QueryDestination::CoroutineYield {
+        yield_reg: usize::MAX, // will be set later in bytecode emission
+        coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+    };
+
+    let Plan::Select(mut initial_plan) = prepare_select_plan(
+        schema,
+        single_select(body.select),
+        syms,
+        outer_query_refs,
+        table_ref_counter,
+        subquery_destination(),
+    )?
+    else {
+        unreachable!("a single SELECT is never planned as a compound SELECT");
+    };
+    if let Some(columns) = &cte.columns {
+        apply_cte_column_names(&cte_name, &mut initial_plan, columns)?;
+    }
+    let mut cte_table = JoinedTable::new_subquery(
+        cte_name.clone(),
+        initial_plan,
+        None,
+        table_ref_counter.next(),
+    );
+    let Table::FromClauseSubquery(definition) = &mut cte_table.table else {
+        unreachable!("new_subquery always creates a FromClauseSubquery");
+    };
+
+    // In the recursive SELECT, the CTE's name refers to the row currently being processed.
+    let mut current_row = definition.clone();
+    current_row.recursive_cte = Some(RecursiveCte::CurrentRow);
+    let mut outer_query_refs_for_step = outer_query_refs.to_vec();
+    outer_query_refs_for_step.push(OuterQueryReference {
+        identifier: cte_name.clone(),
+        internal_id: table_ref_counter.next(),
+        table: Table::FromClauseSubquery(current_row),
+        col_used_mask: ColumnUsedMask::default(),
+    });
+    let Plan::Select(step) = prepare_select_plan(
+        schema,
+        single_select(recursive.select),
+        syms,
+        &outer_query_refs_for_step,
+        table_ref_counter,
+        subquery_destination(),
+    )?
+    else {
+        unreachable!("a single SELECT is never planned as a compound SELECT");
+    };
+    let num_references = step
+        .joined_tables()
+        .iter()
+        .filter(|t| {
+            matches!(&t.table, Table::FromClauseSubquery(subquery)
+                if matches!(subquery.recursive_cte, Some(RecursiveCte::CurrentRow)))
+        })
+        .count();
+    if num_references > 1 {
+        crate::bail_parse_error!("multiple references to recursive table: {}", cte_name);
+    }
+    if !step.aggregates.is_empty() || step.group_by.is_some() {
+        crate::bail_parse_error!("recursive aggregate queries not supported");
+    }
+
+    definition.recursive_cte = Some(RecursiveCte::Definition {
+        step: Box::new(step),
+        distinct,
+        limit,
+    });
+    Ok(cte_table)
+}
+
 pub fn parse_where(
     where_clause: Option<Expr>,
     table_references: &mut TableReferences,
@@ -705,6 +960,16 @@ fn parse_join(
         constraint,
     } = join;
 
+    // The unmatched rows of a FULL JOIN are emitted after the main loop, which is only
+    // implemented for a FULL JOIN that is the innermost loop.
+    if table_references
+        .joined_tables()
+        .iter()
+        .any(|t| t.join_info.as_ref().is_some_and(|j| j.full))
+    {
+        crate::bail_parse_error!("FULL JOIN must be the last join in the FROM clause");
+    }
+
     parse_from_clause_table(
         schema,
         table,
@@ -714,15 +979,20 @@ fn parse_join(
         table_ref_counter,
     )?;
 
-    let (outer, natural) = match join_operator {
+    let (outer, full, natural) = match join_operator {
         ast::JoinOperator::TypedJoin(Some(join_type)) => {
             let is_outer = join_type.contains(JoinType::OUTER);
+            let is_full = join_type.contains(JoinType::LEFT | JoinType::RIGHT);
             let is_natural = join_type.contains(JoinType::NATURAL);
-            (is_outer, is_natural)
+            (is_outer, is_full, is_natural)
         }
-        _ => (false, false),
+        _ => (false, false, false),
     };
 
+    if full {
+        validate_full_join(table_references, out_where_clause)?;
+    }
+
     let mut using = None;
 
     if natural && constraint.is_some() {
@@ -765,11 +1035,8 @@ fn parse_join(
                 }
             }
         }
-        if let Some(distinct_names) = distinct_names {
-            Some(ast::JoinConstraint::Using(distinct_names))
-        } else {
-            crate::bail_parse_error!("No columns found to NATURAL join on");
-        }
+        // Without common columns, a NATURAL JOIN is a cross join.
+        distinct_names.map(ast::JoinConstraint::Using)
     } else {
         constraint
     };
@@ -820,7 +1087,7 @@ fn parse_join(
                     }
                     if left_col.is_none() {
                         crate::bail_parse_error!(
-                            "cannot join using column {} - column not present in all tables",
+                            "cannot join using column {} - column not present in both tables",
                             distinct_name.0
                         );
                     }
@@ -831,7 +1098,7 @@ fn parse_join(
                     });
                     if right_col.is_none() {
                         crate::bail_parse_error!(
-                            "cannot join using column {} - column not present in all tables",
+                            "cannot join using column {} - column not present in both tables",
                             distinct_name.0
                         );
                     }
@@ -884,8 +1151,39 @@ fn parse_join(
         .joined_tables_mut()
         .get_mut(last_idx)
         .unwrap();
-    rightmost_table.join_info = Some(JoinInfo { outer, using });
+    rightmost_table.join_info = Some(JoinInfo { outer, full, using });
+
+    Ok(())
+}
 
+/// Checks that a FULL JOIN of the rightmost table in `table_references` can be planned.
+/// The right table is scanned for unmatched rows by rowid after the main loop, and
+/// the WHERE clause is evaluated against the NULL-extended rows, so it must not contain
+/// the ON terms of inner joins between the left tables.
+fn validate_full_join(
+    table_references: &TableReferences,
+    where_clause: &[WhereTerm],
+) -> Result<()> {
+    let (right_table, left_tables) = table_references
+        .joined_tables()
+        .split_last()
+        .expect("a join has at least two tables");
+    match &right_table.table {
+        Table::BTree(btree) if btree.has_rowid => {}
+        _ => crate::bail_parse_error!(
+            "FULL JOIN is only supported on tables with a rowid: {}",
+            right_table.identifier
+        ),
+    }
+    if left_tables
+        .iter()
+        .any(|t| matches!(t.table, Table::Virtual(_)))
+    {
+        crate::bail_parse_error!("FULL JOIN with virtual tables is not supported");
+    }
+    if where_clause.iter().any(|t| t.from_outer_join.is_none()) {
+        crate::bail_parse_error!("FULL JOIN after an inner join with ON or USING is not supported");
+    }
     Ok(())
 }
 
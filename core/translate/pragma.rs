@@ -6,11 +6,12 @@ use std::sync::Arc;
 use turso_sqlite3_parser::ast::PragmaName;
 use turso_sqlite3_parser::ast::{self, Expr};
 
-use crate::schema::Schema;
-use crate::storage::pager::AutoVacuumMode;
+use crate::schema::{BTreeTable, Index, Schema};
+use crate::storage::page_cache::CachePolicy;
+use crate::storage::pager::{AutoVacuumMode, SynchronousMode};
 use crate::storage::sqlite3_ondisk::MIN_PAGE_CACHE_SIZE;
-use crate::storage::wal::CheckpointMode;
-use crate::util::{normalize_ident, parse_signed_number};
+use crate::storage::wal::{CheckpointMode, JournalMode};
+use crate::util::{normalize_ident, parse_pragma_bool, parse_signed_number};
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
 use crate::vdbe::insn::{Cookie, Insn};
 use crate::{bail_parse_error, storage, LimboError, Value};
@@ -45,7 +46,6 @@ pub fn translate_pragma(
         approx_num_labels: 0,
     };
     program.extend(&opts);
-    let mut write = false;
 
     if name.name.0.eq_ignore_ascii_case("pragma_list") {
         list_pragmas(&mut program);
@@ -56,29 +56,55 @@ pub fn translate_pragma(
         Ok(pragma) => pragma,
         Err(_) => bail_parse_error!("Not a valid pragma name"),
     };
+    let wal_pragma = matches!(
+        pragma,
+        PragmaName::JournalMode | PragmaName::WalAutocheckpoint | PragmaName::WalCheckpoint
+    );
+    let write = writes_database(pragma, body.is_some());
 
     match body {
         None => {
             query_pragma(pragma, schema, None, pager, connection, &mut program)?;
         }
         Some(ast::PragmaBody::Equals(value) | ast::PragmaBody::Call(value)) => match pragma {
-            PragmaName::TableInfo => {
+            PragmaName::TableInfo | PragmaName::IndexList | PragmaName::IndexInfo => {
                 query_pragma(pragma, schema, Some(value), pager, connection, &mut program)?;
             }
             _ => {
-                write = true;
                 update_pragma(pragma, schema, value, pager, connection, &mut program)?;
             }
         },
     };
-    program.epilogue(match write {
-        false => super::emitter::TransactionMode::Read,
-        true => super::emitter::TransactionMode::Write,
+    program.epilogue(match (wal_pragma, write) {
+        // A checkpoint locks the WAL itself, a transaction around it would only get in its way.
+        (true, _) => super::emitter::TransactionMode::None,
+        (false, false) => super::emitter::TransactionMode::Read,
+        (false, true) => super::emitter::TransactionMode::Write,
     });
 
     Ok(program)
 }
 
+/// Returns true if the pragma statement writes to the database file, as opposed to reading it
+/// or only changing a setting of the connection.
+pub(crate) fn pragma_writes_database(
+    name: &ast::QualifiedName,
+    body: Option<&ast::PragmaBody>,
+) -> bool {
+    PragmaName::from_str(&name.name.0).is_ok_and(|pragma| writes_database(pragma, body.is_some()))
+}
+
+fn writes_database(pragma: PragmaName, has_value: bool) -> bool {
+    match pragma {
+        PragmaName::WalCheckpoint => true,
+        PragmaName::AutoVacuum
+        | PragmaName::JournalMode
+        | PragmaName::SchemaVersion
+        | PragmaName::UserVersion => has_value,
+        _ => false,
+    }
+}
+
 fn update_pragma(
     pragma: PragmaName,
     schema: &Schema,
@@ -88,6 +114,42 @@ fn update_pragma(
     program: &mut ProgramBuilder,
 ) -> crate::Result<()> {
     match pragma {
+        PragmaName::AnalysisLimit => {
+            // Like SQLite, a negative or non-integer limit leaves the current one in place,
+            // and the statement reports the limit in effect either way.
+            if let Value::Integer(limit) = parse_signed_number(&value)? {
+                if limit >= 0 {
+                    connection.set_analysis_limit((limit & 0x7fffffff) as i32);
+                }
+            }
+            query_pragma(
+                PragmaName::AnalysisLimit,
+                schema,
+                None,
+                pager,
+                connection,
+                program,
+            )?;
+            Ok(())
+        }
+        PragmaName::CachePolicy => {
+            let policy = match value {
+                Expr::Name(name) => normalize_ident(&name.0),
+                Expr::Literal(ast::Literal::String(s)) => normalize_ident(&s),
+                _ => bail_parse_error!("Invalid value for cache_policy pragma"),
+            };
+            let policy = CachePolicy::from_str(&policy).map_err(LimboError::InvalidArgument)?;
+            pager.set_page_cache_policy(policy);
+            query_pragma(
+                PragmaName::CachePolicy,
+                schema,
+                None,
+                pager,
+                connection,
+                program,
+            )?;
+            Ok(())
+        }
         PragmaName::CacheSize => {
             let cache_size = match parse_signed_number(&value)? {
                 Value::Integer(size) => size,
@@ -97,7 +159,55 @@ fn update_pragma(
             update_cache_size(cache_size, pager, connection)?;
             Ok(())
         }
+        PragmaName::CheckpointRateLimit => {
+            let mb_per_sec = match parse_signed_number(&value)? {
+                Value::Integer(mb) => mb,
+                Value::Float(mb) => mb as i64,
+                _ => bail_parse_error!("Invalid value for checkpoint_rate_limit pragma"),
+            };
+            // Negative limits disable throttling, like 0.
+            let bytes_per_sec = (mb_per_sec.max(0) as u64).saturating_mul(1024 * 1024);
+            pager.set_checkpoint_rate_limit(bytes_per_sec);
+            Ok(())
+        }
+        PragmaName::ChecksumVerification => {
+            let enabled = parse_pragma_bool(&value)?;
+            pager.set_checksum_verification(enabled);
+            Ok(())
+        }
+        PragmaName::ForeignKeys => {
+            let enabled = parse_pragma_bool(&value)?;
+            connection.set_foreign_keys(enabled);
+            Ok(())
+        }
+        PragmaName::IgnoreCheckConstraints => {
+            let enabled = parse_pragma_bool(&value)?;
+            connection.set_ignore_check_constraints(enabled);
+            Ok(())
+        }
         PragmaName::JournalMode => {
+            let mode = match value {
+                Expr::Name(name) => normalize_ident(&name.0),
+                Expr::Literal(ast::Literal::String(s)) => normalize_ident(&s),
+                _ => bail_parse_error!("Invalid value for journal_mode pragma"),
+            };
+            // Like SQLite when a journal mode change is not possible, e.g. to one of the modes
+            // Limbo doesn't have, leave the mode as is and report the current one.
+            if let Ok(mode) = JournalMode::from_str(&mode) {
+                let previous = pager.journal_mode();
+                if pager.set_journal_mode(mode) == JournalMode::Delete
+                    && previous == JournalMode::Wal
+                {
+                    // Leave the database in a single file right away, not after the next
+                    // commit.
+                    let dest = program.alloc_registers(3);
+                    program.emit_insn(Insn::Checkpoint {
+                        database: 0,
+                        checkpoint_mode: CheckpointMode::Truncate,
+                        dest,
+                    });
+                }
+            }
             query_pragma(
                 PragmaName::JournalMode,
                 schema,
@@ -109,6 +219,24 @@ fn update_pragma(
             Ok(())
         }
         PragmaName::LegacyFileFormat => Ok(()),
+        PragmaName::WalAutocheckpoint => {
+            // Like SQLite, zero or a negative number disables automatic checkpoints.
+            let frames = match parse_signed_number(&value)? {
+                Value::Integer(frames) => frames,
+                Value::Float(frames) => frames as i64,
+                _ => bail_parse_error!("Invalid value for wal_autocheckpoint pragma"),
+            };
+            pager.set_wal_autocheckpoint(frames.max(0) as u64);
+            query_pragma(
+                PragmaName::WalAutocheckpoint,
+                schema,
+                None,
+                pager,
+                connection,
+                program,
+            )?;
+            Ok(())
+        }
         PragmaName::WalCheckpoint => {
             query_pragma(
                 PragmaName::WalCheckpoint,
@@ -151,7 +279,11 @@ fn update_pragma(
             // TODO: Implement updating schema_version
             todo!("updating schema_version not yet implemented")
         }
-        PragmaName::TableInfo => {
+        PragmaName::Synchronous => {
+            pager.set_synchronous(parse_synchronous_mode(&value)?);
+            Ok(())
+        }
+        PragmaName::TableInfo | PragmaName::IndexList | PragmaName::IndexInfo => {
             // because we need control over the write parameter for the transaction,
             // this should be unreachable. We have to force-call query_pragma before
             // getting here
@@ -160,6 +292,9 @@ fn update_pragma(
         PragmaName::PageSize => {
             bail_parse_error!("Updating database page size is not supported.");
         }
+        PragmaName::RecoveryReport => {
+            bail_parse_error!("recovery_report cannot be set");
+        }
         PragmaName::AutoVacuum => {
             let auto_vacuum_mode = match value {
                 Expr::Name(name) => {
@@ -220,6 +355,48 @@ fn update_pragma(
     }
 }
 
+/// How an index came to exist, as reported by `PRAGMA index_list`: "c" for
+/// `CREATE INDEX`, "u" for a UNIQUE constraint and "pk" for a PRIMARY KEY.
+fn index_origin(table: &BTreeTable, index: &Index) -> &'static str {
+    if !index.name.starts_with("sqlite_autoindex_") {
+        return "c";
+    }
+    let is_primary_key = index.columns.len() == table.primary_key_columns.len()
+        && index
+            .columns
+            .iter()
+            .zip(table.primary_key_columns.iter())
+            .all(|(column, (pk_name, _))| column.name.eq_ignore_ascii_case(pk_name));
+    if is_primary_key {
+        "pk"
+    } else {
+        "u"
+    }
+}
+
+fn parse_synchronous_mode(value: &ast::Expr) -> crate::Result<SynchronousMode> {
+    let level = match value {
+        Expr::Name(name) => normalize_ident(&name.0),
+        Expr::Literal(ast::Literal::Keyword(keyword)) => keyword.to_lowercase(),
+        Expr::Literal(ast::Literal::String(s)) => normalize_ident(s),
+        _ => match parse_signed_number(value)? {
+            Value::Integer(i) => i.to_string(),
+            Value::Float(f) => (f as i64).to_string(),
+            _ => unreachable!(),
+        },
+    };
+    match level.as_str() {
+        "off" | "0" => Ok(SynchronousMode::Off),
+        "normal" | "1" => Ok(SynchronousMode::Normal),
+        // EXTRA has no additional meaning in WAL mode.
+        "full" | "2" | "extra" | "3" => Ok(SynchronousMode::Full),
+        _ => Err(LimboError::InvalidArgument(format!(
+            "invalid synchronous mode: {}",
+            level
+        ))),
+    }
+}
+
 fn query_pragma(
     pragma: PragmaName,
     schema: &Schema,
@@ -230,17 +407,53 @@ fn query_pragma(
 ) -> crate::Result<()> {
     let register = program.alloc_register();
     match pragma {
+        PragmaName::AnalysisLimit => {
+            program.emit_int(connection.get_analysis_limit() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
+        PragmaName::CachePolicy => {
+            program.emit_string8(pager.page_cache_policy().to_string(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
         PragmaName::CacheSize => {
             program.emit_int(connection.get_cache_size() as i64, register);
             program.emit_result_row(register, 1);
             program.add_pragma_result_column(pragma.to_string());
         }
+        PragmaName::CheckpointRateLimit => {
+            let mb_per_sec = pager.get_checkpoint_rate_limit() / (1024 * 1024);
+            program.emit_int(mb_per_sec as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
+        PragmaName::ChecksumVerification => {
+            program.emit_bool(pager.get_checksum_verification(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
+        PragmaName::ForeignKeys => {
+            program.emit_bool(connection.foreign_keys_enabled(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
+        PragmaName::IgnoreCheckConstraints => {
+            program.emit_bool(connection.ignore_check_constraints(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
         PragmaName::JournalMode => {
-            program.emit_string8("wal".into(), register);
+            program.emit_string8(pager.journal_mode().as_str().into(), register);
             program.emit_result_row(register, 1);
             program.add_pragma_result_column(pragma.to_string());
         }
         PragmaName::LegacyFileFormat => {}
+        PragmaName::WalAutocheckpoint => {
+            program.emit_int(pager.wal_autocheckpoint() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
         PragmaName::WalCheckpoint => {
             // Checkpoint uses 3 registers: P1, P2, P3. Ref Insn::Checkpoint for more info.
             // Allocate two more here as one was allocated at the top.
@@ -254,12 +467,6 @@ fn query_pragma(
                 _ => CheckpointMode::Passive,
             };
 
-            if !matches!(mode, CheckpointMode::Passive) {
-                return Err(LimboError::ParseError(
-                    "only Passive mode supported".to_string(),
-                ));
-            }
-
             program.alloc_registers(2);
             program.emit_insn(Insn::Checkpoint {
                 database: 0,
@@ -288,7 +495,13 @@ fn query_pragma(
             let base_reg = register;
             program.alloc_registers(5);
             if let Some(table) = table {
-                for (i, column) in table.columns().iter().enumerate() {
+                // Like SQLite, generated columns are hidden from table_info.
+                for (i, column) in table
+                    .columns()
+                    .iter()
+                    .filter(|column| !column.is_generated())
+                    .enumerate()
+                {
                     // cid
                     program.emit_int(i as i64, base_reg);
                     // name
@@ -321,6 +534,69 @@ fn query_pragma(
                 program.add_pragma_result_column(name.into());
             }
         }
+        PragmaName::IndexList => {
+            let table = match value {
+                Some(ast::Expr::Name(name)) => schema.get_btree_table(&name.0),
+                _ => None,
+            };
+
+            let base_reg = register;
+            program.alloc_registers(4);
+            if let Some(table) = table {
+                // Like SQLite, list the most recently created index first.
+                for (seq, index) in schema.get_indices(&table.name).iter().rev().enumerate() {
+                    // seq
+                    program.emit_int(seq as i64, base_reg);
+                    // name
+                    program.emit_string8(index.name.clone(), base_reg + 1);
+                    // unique
+                    program.emit_bool(index.unique, base_reg + 2);
+                    // origin
+                    program.emit_string8(index_origin(&table, index).into(), base_reg + 3);
+                    // partial
+                    program.emit_bool(false, base_reg + 4);
+
+                    program.emit_result_row(base_reg, 5);
+                }
+            }
+            let col_names = ["seq", "name", "unique", "origin", "partial"];
+            for name in col_names {
+                program.add_pragma_result_column(name.into());
+            }
+        }
+        PragmaName::IndexInfo => {
+            let index = match value {
+                Some(ast::Expr::Name(name)) => {
+                    let index_name = normalize_ident(&name.0);
+                    schema
+                        .indexes
+                        .values()
+                        .flatten()
+                        .find(|index| index.name == index_name)
+                        .cloned()
+                }
+                _ => None,
+            };
+
+            let base_reg = register;
+            program.alloc_registers(2);
+            if let Some(index) = index {
+                for (seqno, column) in index.columns.iter().enumerate() {
+                    // seqno
+                    program.emit_int(seqno as i64, base_reg);
+                    // cid
+                    program.emit_int(column.pos_in_table as i64, base_reg + 1);
+                    // name
+                    program.emit_string8(column.name.clone(), base_reg + 2);
+
+                    program.emit_result_row(base_reg, 3);
+                }
+            }
+            let col_names = ["seqno", "cid", "name"];
+            for name in col_names {
+                program.add_pragma_result_column(name.into());
+            }
+        }
         PragmaName::UserVersion => {
             program.emit_insn(Insn::ReadCookie {
                 db: 0,
@@ -339,6 +615,16 @@ fn query_pragma(
             program.add_pragma_result_column(pragma.to_string());
             program.emit_result_row(register, 1);
         }
+        PragmaName::Synchronous => {
+            let mode = match pager.get_synchronous() {
+                SynchronousMode::Off => 0,
+                SynchronousMode::Normal => 1,
+                SynchronousMode::Full => 2,
+            };
+            program.emit_int(mode, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+        }
         PragmaName::PageSize => {
             program.emit_int(
                 header_accessor::get_page_size(&pager)
@@ -348,6 +634,29 @@ fn query_pragma(
             program.emit_result_row(register, 1);
             program.add_pragma_result_column(pragma.to_string());
         }
+        PragmaName::RecoveryReport => {
+            let report = connection._db.recovery_report();
+            program.alloc_registers(4);
+            program.emit_bool(report.dirty_shutdown(), register);
+            program.emit_int(report.frames_scanned as i64, register + 1);
+            program.emit_int(report.frames_applied as i64, register + 2);
+            program.emit_int(report.frames_discarded as i64, register + 3);
+            match report.truncated_tail_offset {
+                Some(offset) => program.emit_int(offset as i64, register + 4),
+                None => program.emit_null(register + 4, None),
+            }
+            program.emit_result_row(register, 5);
+            let col_names = [
+                "dirty_shutdown",
+                "frames_scanned",
+                "frames_applied",
+                "frames_discarded",
+                "truncated_tail_offset",
+            ];
+            for name in col_names {
+                program.add_pragma_result_column(name.into());
+            }
+        }
         PragmaName::AutoVacuum => {
             let auto_vacuum_mode = pager.get_auto_vacuum_mode();
             let auto_vacuum_mode_i64: i64 = match auto_vacuum_mode {
@@ -0,0 +1,471 @@
+//! Query-shape cache.
+//!
+//! OLTP workloads run many statements that only differ in their literals, e.g.
+//! `SELECT * FROM users WHERE id = 1` and `SELECT * FROM users WHERE id = 2`. Such statements
+//! share a *shape*: the statement with its literals replaced by placeholders.
+//!
+//! The first time a shape is seen, the statement is translated twice: once as written and once
+//! with every replaced literal swapped for a sentinel value. If the two programs only differ in
+//! the constants loaded for those literals, the literal values did not influence planning and the
+//! sentinel program is kept as a template. Later statements of the same shape clone the template
+//! and patch their own literal values into it, skipping planning, optimization and code
+//! generation.
+//!
+//! Only literals whose value cannot change the plan are replaced: operands of a comparison with a
+//! non-literal, IN list items, BETWEEN bounds, INSERT VALUES items and UPDATE SET values. Literals
+//! anywhere else, e.g. LIKE patterns, LIMIT or ORDER BY terms, stay part of the shape.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, fmt::ToTokens};
+
+use super::expr::{sanitize_string, walk_expr_mut};
+use super::plan::{ResultSetColumn, TableReferences};
+use super::{is_write_stmt, translate};
+use crate::parameters::Parameters;
+use crate::schema::Schema;
+use crate::storage::pager::Pager;
+use crate::util::parse_numeric_literal;
+use crate::vdbe::builder::{CursorKey, CursorType, QueryMode};
+use crate::vdbe::execute::InsnFunction;
+use crate::vdbe::insn::Insn;
+use crate::vdbe::{Program, ResultColumnMetadata};
+use crate::{Connection, LimboError, Result, SymbolTable, Value};
+
+/// Number of shapes a connection remembers. The cache is emptied when it fills up.
+const QUERY_SHAPE_CACHE_CAPACITY: usize = 256;
+
+/// Integer sentinels count up from here; real sentinels from here plus one half.
+const SENTINEL_BASE: i64 = 7_305_113_000_000;
+
+/// Hit and miss counters of a connection's query-shape cache, see
+/// [Connection::query_shape_cache_stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryShapeCacheStats {
+    /// Statements whose program was instantiated from a cached template.
+    pub hits: u64,
+    /// Statements that had literals to replace but were translated from scratch.
+    pub misses: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct QueryShapeCache {
+    entries: HashMap<String, ShapeEntry>,
+    stats: QueryShapeCacheStats,
+    disabled: bool,
+}
+
+impl QueryShapeCache {
+    pub(crate) fn stats(&self) -> QueryShapeCacheStats {
+        self.stats
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.disabled = !enabled;
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    /// Forgets every shape, e.g. because the functions a statement can resolve changed.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+struct ShapeEntry {
+    schema_version: u32,
+    /// None if programs of this shape depend on the literal values.
+    template: Option<Template>,
+}
+
+/// A program translated with sentinel literals, minus the connection it was translated for, so
+/// that the cache does not keep its own connection alive.
+struct Template {
+    max_registers: usize,
+    insns: Vec<(Insn, InsnFunction)>,
+    cursor_ref: Vec<(Option<CursorKey>, CursorType)>,
+    comments: Option<Vec<(u32, &'static str)>>,
+    parameters: Parameters,
+    change_cnt_on: bool,
+    is_readonly: bool,
+    result_columns: Vec<ResultSetColumn>,
+    result_column_metadata: Vec<ResultColumnMetadata>,
+    table_references: TableReferences,
+    /// Instruction index and literal index of every constant to patch.
+    patches: Vec<(usize, usize)>,
+}
+
+impl Template {
+    /// Builds a template from the program of the statement as written and the program of the
+    /// same statement with sentinel literals. Returns None if the programs differ in anything
+    /// but the constants loaded for the replaced literals.
+    fn derive(original: &Program, sentinel: Program, literals: &[Value]) -> Option<Self> {
+        if original.insns.len() != sentinel.insns.len()
+            || original.max_registers != sentinel.max_registers
+            || original.cursor_ref.len() != sentinel.cursor_ref.len()
+            || original.parameters.count() != sentinel.parameters.count()
+            || original.result_column_metadata.len() != sentinel.result_column_metadata.len()
+        {
+            return None;
+        }
+        // Result column names derived from an expression that contains a literal differ.
+        let same_columns = original
+            .result_column_metadata
+            .iter()
+            .zip(sentinel.result_column_metadata.iter())
+            .all(|(a, b)| a.name == b.name && a.declared_type == b.declared_type);
+        if !same_columns {
+            return None;
+        }
+
+        let sentinels = (0..literals.len())
+            .map(|idx| literal_value(&sentinel_literal(idx, &literals[idx])))
+            .collect::<Option<Vec<_>>>()?;
+        let mut patches = Vec::new();
+        for (idx, ((original_insn, _), (sentinel_insn, _))) in
+            original.insns.iter().zip(sentinel.insns.iter()).enumerate()
+        {
+            if let Some(literal_idx) = sentinels
+                .iter()
+                .position(|value| constant_equals(sentinel_insn, value))
+            {
+                if !constant_equals(original_insn, &literals[literal_idx]) {
+                    return None;
+                }
+                patches.push((idx, literal_idx));
+            } else if format!("{:?}", original_insn) != format!("{:?}", sentinel_insn) {
+                return None;
+            }
+        }
+        // A literal that never made it into the program was folded into the plan.
+        if (0..literals.len()).any(|idx| !patches.iter().any(|(_, l)| *l == idx)) {
+            return None;
+        }
+
+        let Program {
+            max_registers,
+            insns,
+            cursor_ref,
+            comments,
+            parameters,
+            change_cnt_on,
+            is_readonly,
+            result_columns,
+            result_column_metadata,
+            table_references,
+            ..
+        } = sentinel;
+        Some(Self {
+            max_registers,
+            insns,
+            cursor_ref,
+            comments,
+            parameters,
+            change_cnt_on,
+            is_readonly,
+            result_columns,
+            result_column_metadata,
+            table_references,
+            patches,
+        })
+    }
+
+    fn instantiate(&self, literals: &[Value], connection: Arc<Connection>) -> Program {
+        let mut insns = self.insns.clone();
+        for &(idx, literal_idx) in &self.patches {
+            set_constant(&mut insns[idx].0, &literals[literal_idx]);
+        }
+        Program {
+            max_registers: self.max_registers,
+            insns,
+            cursor_ref: self.cursor_ref.clone(),
+            comments: self.comments.clone(),
+            parameters: self.parameters.clone(),
+            connection,
+            n_change: Cell::new(0),
+            change_cnt_on: self.change_cnt_on,
+            is_readonly: self.is_readonly,
+            result_columns: self.result_columns.clone(),
+            result_column_metadata: self.result_column_metadata.clone(),
+            table_references: self.table_references.clone(),
+            query_mode: QueryMode::Normal,
+        }
+    }
+}
+
+/// Translates `stmt` like [translate] does, reusing the program of a previously translated
+/// statement of the same shape when possible.
+pub(crate) fn translate_with_shape_cache(
+    schema: &Schema,
+    stmt: ast::Stmt,
+    pager: Rc<Pager>,
+    connection: Arc<Connection>,
+    syms: &SymbolTable,
+    input: &str,
+) -> Result<Program> {
+    let parameterized = if connection.query_shape_cache.borrow().disabled {
+        None
+    } else {
+        parameterize(&stmt)
+    };
+    let Some((shape, literals)) = parameterized else {
+        return translate(
+            schema,
+            stmt,
+            pager,
+            connection,
+            syms,
+            QueryMode::Normal,
+            input,
+        );
+    };
+    let key = shape
+        .format()
+        .map_err(|e| LimboError::InternalError(e.to_string()))?;
+
+    let depends_on_literals = {
+        let mut guard = connection.query_shape_cache.borrow_mut();
+        let cache = &mut *guard;
+        match cache.entries.get(&key) {
+            Some(entry) if entry.schema_version == schema.schema_version => match &entry.template {
+                Some(template) => {
+                    if connection.is_readonly() && is_write_stmt(&stmt) {
+                        return Err(LimboError::ReadOnly);
+                    }
+                    cache.stats.hits += 1;
+                    return Ok(template.instantiate(&literals, connection.clone()));
+                }
+                None => {
+                    cache.stats.misses += 1;
+                    true
+                }
+            },
+            _ => false,
+        }
+    };
+    if depends_on_literals {
+        return translate(
+            schema,
+            stmt,
+            pager,
+            connection,
+            syms,
+            QueryMode::Normal,
+            input,
+        );
+    }
+
+    let program = translate(
+        schema,
+        stmt,
+        pager.clone(),
+        connection.clone(),
+        syms,
+        QueryMode::Normal,
+        input,
+    )?;
+    let template = translate(
+        schema,
+        shape,
+        pager,
+        connection.clone(),
+        syms,
+        QueryMode::Normal,
+        input,
+    )
+    .ok()
+    .and_then(|sentinel| Template::derive(&program, sentinel, &literals));
+
+    let mut cache = connection.query_shape_cache.borrow_mut();
+    if cache.entries.len() >= QUERY_SHAPE_CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.entries.insert(
+        key,
+        ShapeEntry {
+            schema_version: schema.schema_version,
+            template,
+        },
+    );
+    cache.stats.misses += 1;
+    Ok(program)
+}
+
+/// Returns `stmt` with its replaceable literals swapped for sentinels, and the values of the
+/// replaced literals in the order they were found. Returns None if there is nothing to replace.
+fn parameterize(stmt: &ast::Stmt) -> Option<(ast::Stmt, Vec<Value>)> {
+    let mut shape = stmt.clone();
+    let mut literals = Vec::new();
+    match &mut shape {
+        ast::Stmt::Select(select) => {
+            for one in one_selects(&mut select.body) {
+                if let ast::OneSelect::Select(inner) = one {
+                    if let Some(where_clause) = &mut inner.where_clause {
+                        replace_in_condition(where_clause, &mut literals);
+                    }
+                }
+            }
+        }
+        ast::Stmt::Insert(insert) => {
+            if let ast::InsertBody::Select(select, _) = &mut insert.body {
+                if let ast::OneSelect::Values(rows) = select.body.select.as_mut() {
+                    for value in rows.iter_mut().flatten() {
+                        replace_literal(value, &mut literals);
+                    }
+                }
+            }
+        }
+        ast::Stmt::Update(update) => {
+            for set in update.sets.iter_mut() {
+                replace_literal(&mut set.expr, &mut literals);
+            }
+            if let Some(where_clause) = &mut update.where_clause {
+                replace_in_condition(where_clause, &mut literals);
+            }
+        }
+        ast::Stmt::Delete(delete) => {
+            if let Some(where_clause) = &mut delete.where_clause {
+                replace_in_condition(where_clause, &mut literals);
+            }
+        }
+        _ => return None,
+    }
+    if literals.is_empty() {
+        return None;
+    }
+    Some((shape, literals))
+}
+
+fn one_selects(body: &mut ast::SelectBody) -> impl Iterator<Item = &mut ast::OneSelect> {
+    std::iter::once(body.select.as_mut()).chain(
+        body.compounds
+            .iter_mut()
+            .flatten()
+            .map(|compound| compound.select.as_mut()),
+    )
+}
+
+fn replace_in_condition(expr: &mut ast::Expr, literals: &mut Vec<Value>) {
+    let _ = walk_expr_mut(expr, &mut |e| {
+        replace_operands(e, literals);
+        Ok(())
+    });
+}
+
+/// Replaces the literal operands of `expr` whose value cannot change the plan.
+fn replace_operands(expr: &mut ast::Expr, literals: &mut Vec<Value>) {
+    match expr {
+        ast::Expr::Binary(lhs, op, rhs) if is_comparison(op) => {
+            let (lhs_literal, rhs_literal) = (is_literal(lhs), is_literal(rhs));
+            if !lhs_literal {
+                replace_literal(rhs, literals);
+            }
+            if !rhs_literal {
+                replace_literal(lhs, literals);
+            }
+        }
+        ast::Expr::InList {
+            lhs,
+            rhs: Some(list),
+            ..
+        } if !is_literal(lhs) => {
+            for item in list.iter_mut() {
+                replace_literal(item, literals);
+            }
+        }
+        ast::Expr::Between {
+            lhs, start, end, ..
+        } if !is_literal(lhs) => {
+            replace_literal(start, literals);
+            replace_literal(end, literals);
+        }
+        _ => {}
+    }
+}
+
+fn replace_literal(expr: &mut ast::Expr, literals: &mut Vec<Value>) {
+    let ast::Expr::Literal(literal) = expr else {
+        return;
+    };
+    let Some(value) = literal_value(literal) else {
+        return;
+    };
+    *literal = sentinel_literal(literals.len(), &value);
+    literals.push(value);
+}
+
+fn is_comparison(op: &ast::Operator) -> bool {
+    matches!(
+        op,
+        ast::Operator::Equals
+            | ast::Operator::NotEquals
+            | ast::Operator::Less
+            | ast::Operator::LessEquals
+            | ast::Operator::Greater
+            | ast::Operator::GreaterEquals
+            | ast::Operator::Is
+            | ast::Operator::IsNot
+    )
+}
+
+fn is_literal(expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::Literal(_))
+}
+
+/// The value the translator loads for `literal`, if it loads one.
+fn literal_value(literal: &ast::Literal) -> Option<Value> {
+    match literal {
+        ast::Literal::Numeric(text) => parse_numeric_literal(text).ok(),
+        ast::Literal::String(text) => Some(Value::build_text(sanitize_string(text))),
+        ast::Literal::Blob(hex) => {
+            let bytes = hex
+                .as_bytes()
+                .chunks_exact(2)
+                .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                .collect::<Option<Vec<_>>>()?;
+            Some(Value::from_blob(bytes))
+        }
+        _ => None,
+    }
+}
+
+/// A literal of the same kind as `value` that is unlikely to appear anywhere else in a program.
+fn sentinel_literal(idx: usize, value: &Value) -> ast::Literal {
+    let marker = format!("__turso_shape_{idx}__");
+    match value {
+        Value::Integer(_) => ast::Literal::Numeric((SENTINEL_BASE + idx as i64).to_string()),
+        Value::Float(_) => ast::Literal::Numeric(format!("{}.5", SENTINEL_BASE + idx as i64)),
+        Value::Text(_) => ast::Literal::String(format!("'{marker}'")),
+        Value::Blob(_) => ast::Literal::Blob(
+            marker
+                .bytes()
+                .map(|byte| format!("{byte:02X}"))
+                .collect::<String>(),
+        ),
+        Value::Null => unreachable!("NULL literals are never replaced"),
+    }
+}
+
+fn constant_equals(insn: &Insn, expected: &Value) -> bool {
+    match (insn, expected) {
+        (Insn::Integer { value, .. }, Value::Integer(expected)) => value == expected,
+        (Insn::Real { value, .. }, Value::Float(expected)) => value == expected,
+        (Insn::String8 { value, .. }, Value::Text(expected)) => value == expected.as_str(),
+        (Insn::Blob { value, .. }, Value::Blob(expected)) => value == expected,
+        _ => false,
+    }
+}
+
+fn set_constant(insn: &mut Insn, new: &Value) {
+    match (insn, new) {
+        (Insn::Integer { value, .. }, Value::Integer(new)) => *value = *new,
+        (Insn::Real { value, .. }, Value::Float(new)) => *value = *new,
+        (Insn::String8 { value, .. }, Value::Text(new)) => *value = new.as_str().to_string(),
+        (Insn::Blob { value, .. }, Value::Blob(new)) => *value = new.clone(),
+        (insn, new) => unreachable!("cannot patch {new:?} into {insn:?}"),
+    }
+}
@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 use crate::{
+    schema::Index,
     vdbe::{
         builder::ProgramBuilder,
         insn::{IdxInsertFlags, InsertFlags, Insn},
-        BranchOffset,
+        BranchOffset, CursorID,
     },
     Result,
 };
@@ -10,7 +13,7 @@ use crate::{
 use super::{
     emitter::{LimitCtx, Resolver},
     expr::translate_expr,
-    plan::{Distinctness, QueryDestination, SelectPlan},
+    plan::{DistinctCtx, Distinctness, QueryDestination, SelectPlan},
 };
 
 /// Emits the bytecode for:
@@ -128,6 +131,15 @@ pub fn emit_result_row_and_limit(
                 end_offset: BranchOffset::Offset(0),
             });
         }
+        QueryDestination::RecursiveCteQueue { cursor_id, dedupe } => {
+            emit_recursive_cte_queue_insert(
+                program,
+                *cursor_id,
+                dedupe.as_ref(),
+                result_columns_start_reg,
+                plan.result_columns.len(),
+            );
+        }
     }
 
     if plan.limit.is_some() {
@@ -166,3 +178,44 @@ pub fn emit_offset(
     }
     Ok(())
 }
+
+/// Appends the row in `count` registers starting at `start_reg` to the queue of a recursive CTE.
+/// With `dedupe`, the row is skipped if the CTE has already queued an identical row.
+pub fn emit_recursive_cte_queue_insert(
+    program: &mut ProgramBuilder,
+    cursor_id: CursorID,
+    dedupe: Option<&(CursorID, Arc<Index>)>,
+    start_reg: usize,
+    count: usize,
+) {
+    let label_skip = program.allocate_label();
+    if let Some((dedupe_cursor_id, dedupe_index)) = dedupe {
+        DistinctCtx {
+            cursor_id: *dedupe_cursor_id,
+            ephemeral_index_name: dedupe_index.name.clone(),
+            label_on_conflict: label_skip,
+        }
+        .emit_deduplication_insns(program, count, start_reg);
+    }
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg,
+        count,
+        dest_reg: record_reg,
+        index_name: None,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: cursor_id,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new().ephemeral(true),
+        table_name: String::new(),
+    });
+    program.preassign_label_to_next_insn(label_skip);
+}
@@ -9,10 +9,14 @@ use crate::schema::Column;
 use crate::schema::Schema;
 use crate::schema::Table;
 use crate::schema::Type;
+use crate::schema::MAX_COLUMNS;
 use crate::storage::pager::CreateBTreeFlags;
+use crate::translate::collate::CollationSeq;
 use crate::translate::ProgramBuilder;
 use crate::translate::ProgramBuilderOpts;
-use crate::util::PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX;
+use crate::util::{
+    escape_string_literal, normalize_ident, unquote_ident, PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
+};
 use crate::vdbe::builder::CursorType;
 use crate::vdbe::insn::Cookie;
 use crate::vdbe::insn::{CmpInsFlags, InsertFlags, Insn};
@@ -48,8 +52,21 @@ pub fn translate_create_table(
         }
         bail_parse_error!("Table {} already exists", tbl_name);
     }
+    if let ast::CreateTableBody::ColumnsAndConstraints { columns, .. } = &body {
+        if columns.len() > MAX_COLUMNS {
+            bail_parse_error!("too many columns on {}", tbl_name.name.0);
+        }
+    }
+
+    validate_generated_columns(&body)?;
+    let has_autoincrement = validate_autoincrement(&body)?;
+    // The first AUTOINCREMENT table also creates the table its sequence is kept in.
+    let create_sequence_table =
+        has_autoincrement && schema.get_table(SQLITE_SEQUENCE_TABLE).is_none();
 
     let sql = create_table_body_to_str(&tbl_name, &body);
+    // sqlite_schema stores the bare table name; the original quoting is kept in `sql`.
+    let table_name = unquote_ident(&tbl_name.name.0);
 
     let parse_schema_label = program.allocate_label();
     // TODO: ReadCookie
@@ -57,12 +74,22 @@ pub fn translate_create_table(
     // TODO: SetCookie
     // TODO: SetCookie
 
-    // Create the table B-tree
+    // Create the table B-tree. A WITHOUT ROWID table is stored in an index B-tree keyed by its
+    // primary key.
+    let without_rowid = matches!(
+        &body,
+        ast::CreateTableBody::ColumnsAndConstraints { options, .. }
+            if options.contains(ast::TableOptions::WITHOUT_ROWID)
+    );
     let table_root_reg = program.alloc_register();
     program.emit_insn(Insn::CreateBtree {
         db: 0,
         root: table_root_reg,
-        flags: CreateBTreeFlags::new_table(),
+        flags: if without_rowid {
+            CreateBTreeFlags::new_index()
+        } else {
+            CreateBTreeFlags::new_table()
+        },
     });
 
     // Create an automatic index B-tree if needed
@@ -89,7 +116,7 @@ pub fn translate_create_table(
     // https://github.com/sqlite/sqlite/blob/95f6df5b8d55e67d1e34d2bff217305a2f21b1fb/src/build.c#L2856-L2871
     // https://github.com/sqlite/sqlite/blob/95f6df5b8d55e67d1e34d2bff217305a2f21b1fb/src/build.c#L1334C5-L1336C65
 
-    let index_regs = check_automatic_pk_index_required(&body, &mut program, &tbl_name.name.0)?;
+    let index_regs = check_automatic_pk_index_required(&body, &mut program, &table_name)?;
     if let Some(index_regs) = index_regs.as_ref() {
         if !schema.indexes_enabled() {
             bail_parse_error!("Constraints UNIQUE and PRIMARY KEY (unless INTEGER PRIMARY KEY) on table are not supported without indexes");
@@ -108,7 +135,7 @@ pub fn translate_create_table(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: 1usize.into(),
-        name: tbl_name.name.0.clone(),
+        name: table_name.clone(),
     });
 
     // Add the table entry to sqlite_schema
@@ -116,8 +143,8 @@ pub fn translate_create_table(
         &mut program,
         sqlite_schema_cursor_id,
         SchemaEntryType::Table,
-        &tbl_name.name.0,
-        &tbl_name.name.0,
+        &table_name,
+        &table_name,
         table_root_reg,
         Some(sql),
     );
@@ -128,7 +155,7 @@ pub fn translate_create_table(
             let index_name = format!(
                 "{}{}_{}",
                 PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
-                tbl_name.name.0,
+                table_name,
                 idx + 1
             );
             emit_schema_entry(
@@ -136,13 +163,31 @@ pub fn translate_create_table(
                 sqlite_schema_cursor_id,
                 SchemaEntryType::Index,
                 &index_name,
-                &tbl_name.name.0,
+                &table_name,
                 index_reg,
                 None,
             );
         }
     }
 
+    if create_sequence_table {
+        let sequence_root_reg = program.alloc_register();
+        program.emit_insn(Insn::CreateBtree {
+            db: 0,
+            root: sequence_root_reg,
+            flags: CreateBTreeFlags::new_table(),
+        });
+        emit_schema_entry(
+            &mut program,
+            sqlite_schema_cursor_id,
+            SchemaEntryType::Table,
+            SQLITE_SEQUENCE_TABLE,
+            SQLITE_SEQUENCE_TABLE,
+            sequence_root_reg,
+            Some(format!("CREATE TABLE {SQLITE_SEQUENCE_TABLE}(name,seq)")),
+        );
+    }
+
     program.resolve_label(parse_schema_label, program.offset());
     // TODO: SetCookie
     program.emit_insn(Insn::SetCookie {
@@ -152,7 +197,17 @@ pub fn translate_create_table(
         p5: 0,
     });
     // TODO: remove format, it sucks for performance but is convenient
-    let parse_schema_where_clause = format!("tbl_name = '{}' AND type != 'trigger'", tbl_name);
+    let parse_schema_where_clause = if create_sequence_table {
+        format!(
+            "(tbl_name = '{}' OR tbl_name = '{SQLITE_SEQUENCE_TABLE}') AND type != 'trigger'",
+            escape_string_literal(&table_name)
+        )
+    } else {
+        format!(
+            "tbl_name = '{}' AND type != 'trigger'",
+            escape_string_literal(&table_name)
+        )
+    };
     program.emit_insn(Insn::ParseSchema {
         db: sqlite_schema_cursor_id,
         where_clause: Some(parse_schema_where_clause),
@@ -179,6 +234,10 @@ impl SchemaEntryType {
     }
 }
 pub const SQLITE_TABLEID: &str = "sqlite_schema";
+/// The table holding the largest rowid used by each AUTOINCREMENT table.
+pub const SQLITE_SEQUENCE_TABLE: &str = "sqlite_sequence";
+/// The table ANALYZE stores its statistics in.
+pub const SQLITE_STAT1_TABLE: &str = "sqlite_stat1";
 
 pub fn emit_schema_entry(
     program: &mut ProgramBuilder,
@@ -247,6 +306,121 @@ struct PrimaryKeyColumnInfo<'a> {
 /// Check if an automatic PRIMARY KEY index is required for the table.
 /// If so, create a register for the index root page and return it.
 ///
+/// Rejects the generated column definitions SQLite rejects when the table is created.
+fn validate_generated_columns(body: &ast::CreateTableBody) -> Result<()> {
+    let ast::CreateTableBody::ColumnsAndConstraints {
+        columns,
+        constraints,
+        ..
+    } = body
+    else {
+        return Ok(());
+    };
+    let is_generated = |col_def: &ast::ColumnDefinition| {
+        col_def
+            .constraints
+            .iter()
+            .any(|c| matches!(c.constraint, ast::ColumnConstraint::Generated { .. }))
+    };
+    let table_pk_columns = constraints
+        .iter()
+        .flatten()
+        .filter_map(|c| match &c.constraint {
+            ast::TableConstraint::PrimaryKey { columns, .. } => Some(columns),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|col| match &col.expr {
+            ast::Expr::Id(name) => Some(normalize_ident(&name.0)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for (name, col_def) in columns {
+        if !is_generated(col_def) {
+            continue;
+        }
+        for c in &col_def.constraints {
+            match c.constraint {
+                ast::ColumnConstraint::Default(_) => {
+                    bail_parse_error!("cannot use DEFAULT on a generated column")
+                }
+                ast::ColumnConstraint::PrimaryKey { .. } => {
+                    bail_parse_error!("generated columns cannot be part of the PRIMARY KEY")
+                }
+                _ => {}
+            }
+        }
+        if table_pk_columns.contains(&normalize_ident(&name.0)) {
+            bail_parse_error!("generated columns cannot be part of the PRIMARY KEY");
+        }
+    }
+    if columns.values().all(is_generated) {
+        bail_parse_error!("must have at least one non-generated column");
+    }
+    Ok(())
+}
+
+/// Rejects AUTOINCREMENT anywhere but on an INTEGER PRIMARY KEY of a rowid table, like SQLite
+/// does. Returns whether the table uses AUTOINCREMENT.
+fn validate_autoincrement(body: &ast::CreateTableBody) -> Result<bool> {
+    let ast::CreateTableBody::ColumnsAndConstraints {
+        columns,
+        constraints,
+        options,
+    } = body
+    else {
+        return Ok(false);
+    };
+    let is_integer = |col_def: &ast::ColumnDefinition| {
+        col_def
+            .col_type
+            .as_ref()
+            .is_some_and(|ty| ty.name.eq_ignore_ascii_case("INTEGER"))
+    };
+    let mut autoincrement = None;
+    for col_def in columns.values() {
+        for c in &col_def.constraints {
+            if let ast::ColumnConstraint::PrimaryKey {
+                auto_increment: true,
+                ..
+            } = c.constraint
+            {
+                autoincrement = Some(is_integer(col_def));
+            }
+        }
+    }
+    for c in constraints.iter().flatten() {
+        if let ast::TableConstraint::PrimaryKey {
+            columns: pk_columns,
+            auto_increment: true,
+            ..
+        } = &c.constraint
+        {
+            let is_rowid_alias = match pk_columns.as_slice() {
+                [column] => match &column.expr {
+                    ast::Expr::Id(name) => columns
+                        .iter()
+                        .find(|(col_name, _)| {
+                            normalize_ident(&col_name.0) == normalize_ident(&name.0)
+                        })
+                        .is_some_and(|(_, col_def)| is_integer(col_def)),
+                    _ => false,
+                },
+                _ => false,
+            };
+            autoincrement = Some(is_rowid_alias);
+        }
+    }
+    match autoincrement {
+        None => Ok(false),
+        Some(false) => bail_parse_error!("AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY"),
+        Some(true) if options.contains(ast::TableOptions::WITHOUT_ROWID) => {
+            bail_parse_error!("AUTOINCREMENT not allowed on WITHOUT ROWID tables")
+        }
+        Some(true) => Ok(true),
+    }
+}
+
 /// An automatic PRIMARY KEY index is not required if:
 /// - The table has no PRIMARY KEY
 /// - The table has a single-column PRIMARY KEY whose typename is _exactly_ "INTEGER" e.g. not "INT".
@@ -384,9 +558,17 @@ fn check_automatic_pk_index_required(
                 }
             }
 
-            // Check if table has rowid
+            // The primary key of a WITHOUT ROWID table is the table's own b-tree.
             if options.contains(ast::TableOptions::WITHOUT_ROWID) {
-                bail_parse_error!("WITHOUT ROWID tables are not supported yet");
+                if primary_key_definition.is_none() {
+                    bail_parse_error!("PRIMARY KEY missing on table {}", tbl_name);
+                }
+                if !unique_sets.is_empty() {
+                    bail_parse_error!(
+                        "UNIQUE constraints on WITHOUT ROWID tables are not supported yet"
+                    );
+                }
+                return Ok(None);
             }
 
             unique_sets.dedup();
@@ -526,7 +708,7 @@ pub fn translate_create_virtual_table(
         args,
     } = &vtab;
 
-    let table_name = tbl_name.name.0.clone();
+    let table_name = unquote_ident(&tbl_name.name.0);
     let module_name_str = module_name.0.clone();
     let args_vec = args.clone().unwrap_or_default();
     let Some(vtab_module) = syms.vtab_modules.get(&module_name_str) else {
@@ -590,8 +772,8 @@ pub fn translate_create_virtual_table(
         &mut program,
         sqlite_schema_cursor_id,
         SchemaEntryType::Table,
-        &tbl_name.name.0,
-        &tbl_name.name.0,
+        &table_name,
+        &table_name,
         0, // virtual tables dont have a root page
         Some(sql),
     );
@@ -602,7 +784,10 @@ pub fn translate_create_virtual_table(
         value: schema.schema_version as i32 + 1,
         p5: 0,
     });
-    let parse_schema_where_clause = format!("tbl_name = '{}' AND type != 'trigger'", table_name);
+    let parse_schema_where_clause = format!(
+        "tbl_name = '{}' AND type != 'trigger'",
+        escape_string_literal(&table_name)
+    );
     program.emit_insn(Insn::ParseSchema {
         db: sqlite_schema_cursor_id,
         where_clause: Some(parse_schema_where_clause),
@@ -645,7 +830,7 @@ pub fn translate_drop_table(
     let null_reg = program.alloc_register(); //  r1
     program.emit_null(null_reg, None);
     let table_name_and_root_page_register = program.alloc_register(); //  r2, this register is special because it's first used to track table name and then moved root page
-    let table_reg = program.emit_string8_new_reg(tbl_name.name.0.clone()); //  r3
+    let table_reg = program.emit_string8_new_reg(unquote_ident(&tbl_name.name.0)); //  r3
     program.mark_last_insn_constant();
     let table_type = program.emit_string8_new_reg("trigger".to_string()); //  r4
     program.mark_last_insn_constant();
@@ -679,12 +864,13 @@ pub fn translate_drop_table(
         table_name_and_root_page_register,
     );
     let next_label = program.allocate_label();
+    // Table names are case-insensitive, so match sqlite_schema entries the same way.
     program.emit_insn(Insn::Ne {
         lhs: table_name_and_root_page_register,
         rhs: table_reg,
         target_pc: next_label,
         flags: CmpInsFlags::default(),
-        collation: program.curr_collation(),
+        collation: Some(CollationSeq::NoCase),
     });
     program.emit_column(
         sqlite_schema_cursor_id_0,
@@ -704,6 +890,7 @@ pub fn translate_drop_table(
     });
     program.emit_insn(Insn::Delete {
         cursor_id: sqlite_schema_cursor_id_0,
+        table_name: SQLITE_TABLEID.to_string(),
     });
 
     program.resolve_label(next_label, program.offset());
@@ -714,6 +901,48 @@ pub fn translate_drop_table(
     program.preassign_label_to_next_insn(end_metadata_label);
     //  end of loop on schema table
 
+    //  Forget the sequence of an AUTOINCREMENT table, like SQLite does.
+    let sequence_table = schema.get_btree_table(SQLITE_SEQUENCE_TABLE);
+    if let (Some(sequence_table), true) = (
+        sequence_table,
+        table.btree().is_some_and(|table| table.has_autoincrement),
+    ) {
+        let sequence_cursor_id =
+            program.alloc_cursor_id(CursorType::BTreeTable(sequence_table.clone()));
+        program.emit_insn(Insn::OpenWrite {
+            cursor_id: sequence_cursor_id,
+            root_page: sequence_table.root_page.into(),
+            name: SQLITE_SEQUENCE_TABLE.to_string(),
+        });
+        let end_sequence_label = program.allocate_label();
+        let sequence_loop = program.allocate_label();
+        program.emit_insn(Insn::Rewind {
+            cursor_id: sequence_cursor_id,
+            pc_if_empty: end_sequence_label,
+        });
+        program.preassign_label_to_next_insn(sequence_loop);
+        let sequence_name_reg = program.alloc_register();
+        program.emit_column(sequence_cursor_id, 0, sequence_name_reg);
+        let next_sequence_label = program.allocate_label();
+        program.emit_insn(Insn::Ne {
+            lhs: sequence_name_reg,
+            rhs: table_reg,
+            target_pc: next_sequence_label,
+            flags: CmpInsFlags::default(),
+            collation: Some(CollationSeq::NoCase),
+        });
+        program.emit_insn(Insn::Delete {
+            cursor_id: sequence_cursor_id,
+            table_name: SQLITE_SEQUENCE_TABLE.to_string(),
+        });
+        program.resolve_label(next_sequence_label, program.offset());
+        program.emit_insn(Insn::Next {
+            cursor_id: sequence_cursor_id,
+            pc_if_next: sequence_loop,
+        });
+        program.preassign_label_to_next_insn(end_sequence_label);
+    }
+
     //  2. Destroy the indices within a loop
     let indices = schema.get_indices(&tbl_name.name.0);
     for index in indices {
@@ -783,9 +1012,15 @@ pub fn translate_drop_table(
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             }],
             is_strict: false,
+            has_autoincrement: false,
             unique_sets: None,
+            column_lookup: Default::default(),
+            foreign_keys: vec![],
+            checks: vec![],
         });
         //  cursor id 2
         let ephemeral_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(simple_table_rc));
@@ -901,6 +1136,7 @@ pub fn translate_drop_table(
         });
         program.emit_insn(Insn::Delete {
             cursor_id: sqlite_schema_cursor_id_1,
+            table_name: SQLITE_TABLEID.to_string(),
         });
         program.emit_insn(Insn::Insert {
             cursor: sqlite_schema_cursor_id_1,
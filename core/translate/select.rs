@@ -4,14 +4,15 @@ use super::plan::{
     Search, TableReferences,
 };
 use crate::function::{AggFunc, ExtFunc, Func};
-use crate::schema::Table;
+use crate::schema::{Table, MAX_COLUMNS};
 use crate::translate::optimizer::optimize_plan;
 use crate::translate::plan::{Aggregate, GroupBy, Plan, ResultSetColumn, SelectPlan};
 use crate::translate::planner::{
     bind_column_references, break_predicate_at_and_boundaries, parse_from, parse_limit,
     parse_where, resolve_aggregates,
 };
-use crate::util::normalize_ident;
+use crate::translate::window::{resolve_window_definitions, rewrite_window_functions};
+use crate::util::{normalize_ident, unquote_ident};
 use crate::vdbe::builder::{ProgramBuilderOpts, TableRefIdCounter};
 use crate::vdbe::insn::Insn;
 use crate::SymbolTable;
@@ -124,15 +125,6 @@ pub fn prepare_select_plan(
 
             let mut left = Vec::with_capacity(compounds.len());
             for CompoundSelect { select, operator } in compounds {
-                // TODO: add support for EXCEPT
-                if operator != ast::CompoundOperator::UnionAll
-                    && operator != ast::CompoundOperator::Union
-                    && operator != ast::CompoundOperator::Intersect
-                {
-                    crate::bail_parse_error!(
-                        "only UNION ALL, UNION and INTERSECT are supported for compound SELECTs"
-                    );
-                }
                 left.push((last, operator));
                 last = prepare_one_select_plan(
                     schema,
@@ -160,20 +152,21 @@ pub fn prepare_select_plan(
             if offset.map_or(false, |o| o > 0) {
                 crate::bail_parse_error!("OFFSET is not supported for compound SELECTs yet");
             }
-            // FIXME: handle ORDER BY for compound selects
-            if select.order_by.is_some() {
-                crate::bail_parse_error!("ORDER BY is not supported for compound SELECTs yet");
-            }
             // FIXME: handle WITH for compound selects
             if select.with.is_some() {
                 crate::bail_parse_error!("WITH is not supported for compound SELECTs yet");
             }
+            let order_by = select
+                .order_by
+                .take()
+                .map(|order_by| resolve_compound_order_by(order_by, &left, &last))
+                .transpose()?;
             Ok(Plan::CompoundSelect {
                 left,
                 right_most: last,
                 limit,
                 offset,
-                order_by: None,
+                order_by,
             })
         }
     }
@@ -199,7 +192,7 @@ fn prepare_one_select_plan(
                 where_clause,
                 group_by,
                 distinctness,
-                ..
+                window_clause,
             } = *select_inner;
             if !schema.indexes_enabled() && distinctness.is_some() {
                 crate::bail_parse_error!(
@@ -211,6 +204,15 @@ fn prepare_one_select_plan(
                 crate::bail_parse_error!("SELECT without columns is not allowed");
             }
 
+            let mut order_by = order_by;
+            resolve_window_definitions(
+                &mut columns,
+                order_by.as_mut(),
+                where_clause.as_ref(),
+                group_by.as_ref(),
+                window_clause,
+            )?;
+
             let mut where_predicates = vec![];
 
             let mut table_references = TableReferences::new(vec![], outer_query_refs.to_vec());
@@ -241,7 +243,7 @@ fn prepare_one_select_plan(
                         ResultColumn::TableStar(n) => table_references
                             .joined_tables()
                             .iter()
-                            .find(|t| t.identifier == n.0)
+                            .find(|t| t.identifier == normalize_ident(&n.0))
                             .map(|t| t.columns().len())
                             .unwrap_or(5),
                         // Otherwise allocate space for 1 column
@@ -273,6 +275,7 @@ fn prepare_one_select_plan(
                 query_destination,
                 distinctness: Distinctness::from_ast(distinctness.as_ref()),
                 values: vec![],
+                window: None,
             };
 
             let mut aggregate_expressions = Vec::new();
@@ -327,13 +330,17 @@ fn prepare_one_select_plan(
                             Some(&plan.result_columns),
                         )?;
                         match expr {
+                            // Window functions are planned by rewrite_window_functions() below.
                             ast::Expr::FunctionCall {
                                 name,
                                 distinctness,
                                 args,
-                                filter_over: _,
+                                filter_over,
                                 order_by: _,
-                            } => {
+                            } if filter_over
+                                .as_ref()
+                                .map_or(true, |tail| tail.over_clause.is_none()) =>
+                            {
                                 let args_count = if let Some(args) = &args {
                                     args.len()
                                 } else {
@@ -377,8 +384,8 @@ fn prepare_one_select_plan(
                                         aggregate_expressions.push(agg.clone());
                                         plan.result_columns.push(ResultSetColumn {
                                             alias: maybe_alias.as_ref().map(|alias| match alias {
-                                                ast::As::Elided(alias) => alias.0.clone(),
-                                                ast::As::As(alias) => alias.0.clone(),
+                                                ast::As::Elided(alias) => unquote_ident(&alias.0),
+                                                ast::As::As(alias) => unquote_ident(&alias.0),
                                             }),
                                             expr: expr.clone(),
                                             contains_aggregates: true,
@@ -392,8 +399,8 @@ fn prepare_one_select_plan(
                                         )?;
                                         plan.result_columns.push(ResultSetColumn {
                                             alias: maybe_alias.as_ref().map(|alias| match alias {
-                                                ast::As::Elided(alias) => alias.0.clone(),
-                                                ast::As::As(alias) => alias.0.clone(),
+                                                ast::As::Elided(alias) => unquote_ident(&alias.0),
+                                                ast::As::As(alias) => unquote_ident(&alias.0),
                                             }),
                                             expr: expr.clone(),
                                             contains_aggregates,
@@ -412,9 +419,11 @@ fn prepare_one_select_plan(
                                                     alias: maybe_alias.as_ref().map(|alias| {
                                                         match alias {
                                                             ast::As::Elided(alias) => {
-                                                                alias.0.clone()
+                                                                unquote_ident(&alias.0)
+                                                            }
+                                                            ast::As::As(alias) => {
+                                                                unquote_ident(&alias.0)
                                                             }
-                                                            ast::As::As(alias) => alias.0.clone(),
                                                         }
                                                     }),
                                                     expr: expr.clone(),
@@ -432,9 +441,11 @@ fn prepare_one_select_plan(
                                                     alias: maybe_alias.as_ref().map(|alias| {
                                                         match alias {
                                                             ast::As::Elided(alias) => {
-                                                                alias.0.clone()
+                                                                unquote_ident(&alias.0)
+                                                            }
+                                                            ast::As::As(alias) => {
+                                                                unquote_ident(&alias.0)
                                                             }
-                                                            ast::As::As(alias) => alias.0.clone(),
                                                         }
                                                     }),
                                                     expr: expr.clone(),
@@ -448,10 +459,11 @@ fn prepare_one_select_plan(
                                     }
                                 }
                             }
-                            ast::Expr::FunctionCallStar {
-                                name,
-                                filter_over: _,
-                            } => {
+                            ast::Expr::FunctionCallStar { name, filter_over }
+                                if filter_over
+                                    .as_ref()
+                                    .map_or(true, |tail| tail.over_clause.is_none()) =>
+                            {
                                 if let Ok(Func::Agg(f)) = Func::resolve_function(
                                     normalize_ident(name.0.as_str()).as_str(),
                                     0,
@@ -467,8 +479,8 @@ fn prepare_one_select_plan(
                                     aggregate_expressions.push(agg.clone());
                                     plan.result_columns.push(ResultSetColumn {
                                         alias: maybe_alias.as_ref().map(|alias| match alias {
-                                            ast::As::Elided(alias) => alias.0.clone(),
-                                            ast::As::As(alias) => alias.0.clone(),
+                                            ast::As::Elided(alias) => unquote_ident(&alias.0),
+                                            ast::As::As(alias) => unquote_ident(&alias.0),
                                         }),
                                         expr: expr.clone(),
                                         contains_aggregates: true,
@@ -485,8 +497,8 @@ fn prepare_one_select_plan(
                                     resolve_aggregates(schema, expr, &mut aggregate_expressions)?;
                                 plan.result_columns.push(ResultSetColumn {
                                     alias: maybe_alias.as_ref().map(|alias| match alias {
-                                        ast::As::Elided(alias) => alias.0.clone(),
-                                        ast::As::As(alias) => alias.0.clone(),
+                                        ast::As::Elided(alias) => unquote_ident(&alias.0),
+                                        ast::As::As(alias) => unquote_ident(&alias.0),
                                     }),
                                     expr: expr.clone(),
                                     contains_aggregates,
@@ -496,6 +508,9 @@ fn prepare_one_select_plan(
                     }
                 }
             }
+            if plan.result_columns.len() > MAX_COLUMNS {
+                crate::bail_parse_error!("too many columns in result set");
+            }
 
             // Parse the actual WHERE clause and add its conditions to the plan WHERE clause that already contains the join conditions.
             parse_where(
@@ -573,6 +588,8 @@ fn prepare_one_select_plan(
             // Parse the LIMIT/OFFSET clause
             (plan.limit, plan.offset) = limit.map_or(Ok((None, None)), parse_limit)?;
 
+            rewrite_window_functions(schema, &mut plan, table_ref_counter)?;
+
             // Return the unoptimized query plan
             Ok(plan)
         }
@@ -601,6 +618,7 @@ fn prepare_one_select_plan(
                 query_destination,
                 distinctness: Distinctness::NonDistinct,
                 values,
+                window: None,
             };
 
             Ok(plan)
@@ -633,6 +651,67 @@ fn replace_column_number_with_copy_of_column_expr(
     Ok(())
 }
 
+/// Resolves the terms of the ORDER BY clause of a compound SELECT to the result columns they sort
+/// by. As in SQLite, each term must be a column number, or the name or alias of a result column
+/// of one of the SELECTs, optionally with a COLLATE clause. Each term is returned as the column
+/// number, e.g. `ORDER BY b COLLATE NOCASE` becomes `ORDER BY 2 COLLATE NOCASE` for `SELECT a, b`.
+fn resolve_compound_order_by(
+    order_by: Vec<ast::SortedColumn>,
+    left: &[(SelectPlan, ast::CompoundOperator)],
+    right_most: &SelectPlan,
+) -> Result<Vec<(ast::Expr, SortOrder)>> {
+    let num_result_columns = right_most.result_columns.len();
+    let mut key = Vec::with_capacity(order_by.len());
+    for (i, o) in order_by.into_iter().enumerate() {
+        let (expr, collation) = match o.expr {
+            ast::Expr::Collate(expr, collation) => (*expr, Some(collation)),
+            expr => (expr, None),
+        };
+        let column_number = match &expr {
+            ast::Expr::Literal(ast::Literal::Numeric(num)) => match num.parse::<usize>() {
+                Ok(n) if (1..=num_result_columns).contains(&n) => n,
+                _ => crate::bail_parse_error!(
+                    "ORDER BY term {} out of range - should be between 1 and {}",
+                    i + 1,
+                    num_result_columns
+                ),
+            },
+            ast::Expr::Id(ast::Id(name))
+            | ast::Expr::Qualified(_, ast::Name(name))
+            | ast::Expr::DoublyQualified(_, _, ast::Name(name)) => {
+                let name = normalize_ident(name);
+                let position = left
+                    .iter()
+                    .map(|(plan, _)| plan)
+                    .chain(std::iter::once(right_most))
+                    .find_map(|plan| {
+                        plan.result_columns.iter().position(|rc| {
+                            rc.name(&plan.table_references)
+                                .is_some_and(|n| normalize_ident(n) == name)
+                        })
+                    });
+                match position {
+                    Some(position) => position + 1,
+                    None => crate::bail_parse_error!(
+                        "ORDER BY term {} does not match any column in the result set",
+                        i + 1
+                    ),
+                }
+            }
+            _ => crate::bail_parse_error!(
+                "ORDER BY term {} does not match any column in the result set",
+                i + 1
+            ),
+        };
+        let mut expr = ast::Expr::Literal(ast::Literal::Numeric(column_number.to_string()));
+        if let Some(collation) = collation {
+            expr = ast::Expr::Collate(Box::new(expr), collation);
+        }
+        key.push((expr, o.order.unwrap_or(SortOrder::Asc)));
+    }
+    Ok(key)
+}
+
 fn count_plan_required_cursors(plan: &SelectPlan) -> usize {
     let num_table_cursors: usize = plan
         .joined_tables()
@@ -642,6 +721,7 @@ fn count_plan_required_cursors(plan: &SelectPlan) -> usize {
             Operation::Search(search) => match search {
                 Search::RowidEq { .. } => 1,
                 Search::Seek { index, .. } => 1 + index.is_some() as usize,
+                Search::HashJoin { .. } => 2,
             }
         } + if let Table::FromClauseSubquery(from_clause_subquery) = &t.table {
             count_plan_required_cursors(&from_clause_subquery.plan)
@@ -1,13 +1,20 @@
+use std::rc::Rc;
+
 use crate::{
-    schema::Table,
-    vdbe::{builder::ProgramBuilder, insn::Insn},
+    schema::{BTreeTable, FromClauseSubquery, Table},
+    vdbe::{
+        builder::{CursorType, ProgramBuilder},
+        insn::Insn,
+        BranchOffset,
+    },
     Result,
 };
 
 use super::{
+    compound_select::{create_dedupe_index, result_column_collation},
     emitter::{emit_query, Resolver, TranslateCtx},
     main_loop::LoopLabels,
-    plan::{QueryDestination, SelectPlan, TableReferences},
+    plan::{QueryDestination, RecursiveCte, SelectPlan, TableReferences},
 };
 
 /// Emit the subqueries contained in the FROM clause.
@@ -20,8 +27,22 @@ pub fn emit_subqueries(
     for table_reference in tables.joined_tables_mut() {
         if let Table::FromClauseSubquery(from_clause_subquery) = &mut table_reference.table {
             // Emit the subquery and get the start register of the result columns.
-            let result_columns_start =
-                emit_subquery(program, &mut from_clause_subquery.plan, t_ctx)?;
+            let result_columns_start = match from_clause_subquery.recursive_cte {
+                None => emit_subquery(program, &mut from_clause_subquery.plan, t_ctx)?,
+                Some(RecursiveCte::Definition { .. }) => {
+                    emit_recursive_cte(program, from_clause_subquery, t_ctx)?
+                }
+                // The current row of a recursive CTE is bound when the CTE itself is emitted.
+                Some(RecursiveCte::CurrentRow) => {
+                    if from_clause_subquery.result_columns_start_reg.is_none() {
+                        crate::bail_parse_error!(
+                            "recursive reference in a subquery: {}",
+                            from_clause_subquery.name
+                        );
+                    }
+                    continue;
+                }
+            };
             // Set the start register of the subquery's result columns.
             // This is done so that translate_expr() can read the result columns of the subquery,
             // as if it were reading from a regular table.
@@ -64,7 +85,27 @@ pub fn emit_subquery(
         _ => unreachable!("emit_subquery called on non-subquery"),
     }
     let end_coroutine_label = program.allocate_label();
-    let mut metadata = TranslateCtx {
+    let mut metadata = subquery_translate_ctx(program, plan, t_ctx);
+    let subquery_body_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: subquery_body_end_label,
+        start_offset: coroutine_implementation_start_offset,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start_offset);
+    let result_column_start_reg = emit_query(program, plan, &mut metadata)?;
+    program.resolve_label(end_coroutine_label, program.offset());
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(subquery_body_end_label);
+    Ok(result_column_start_reg)
+}
+
+fn subquery_translate_ctx<'a>(
+    program: &mut ProgramBuilder,
+    plan: &SelectPlan,
+    t_ctx: &TranslateCtx<'a>,
+) -> TranslateCtx<'a> {
+    TranslateCtx {
         labels_main_loop: (0..plan.joined_tables().len())
             .map(|_| LoopLabels::new(program))
             .collect(),
@@ -82,17 +123,202 @@ pub fn emit_subquery(
         reg_limit_offset_sum: None,
         resolver: Resolver::new(t_ctx.resolver.schema, t_ctx.resolver.symbol_table),
         non_aggregate_expressions: Vec::new(),
+        meta_window: None,
+    }
+}
+
+/// Emit a recursive CTE as a coroutine and return the start register of its result columns.
+///
+/// The rows of the CTE go through a queue, an ephemeral table whose rowids record the order
+/// in which rows were produced:
+/// - the initial SELECT runs once and appends its rows to the queue.
+/// - the coroutine then takes rows off the queue in order, yields each one to the parent query,
+///   and runs the recursive SELECT for it, which appends any new rows to the queue.
+/// - the coroutine ends once every queued row has been taken off the queue.
+///
+/// For `UNION`, rows that were queued before are not queued again, which also stops
+/// the recursion on cyclic data.
+fn emit_recursive_cte(
+    program: &mut ProgramBuilder,
+    subquery: &mut FromClauseSubquery,
+    t_ctx: &mut TranslateCtx,
+) -> Result<usize> {
+    let FromClauseSubquery {
+        plan: seed,
+        columns,
+        recursive_cte:
+            Some(RecursiveCte::Definition {
+                step,
+                distinct,
+                limit,
+            }),
+        ..
+    } = subquery
+    else {
+        unreachable!("emit_recursive_cte called on non-recursive subquery");
     };
-    let subquery_body_end_label = program.allocate_label();
+    let num_columns = seed.result_columns.len();
+
+    let yield_reg = program.alloc_register();
+    let coroutine_implementation_start = program.allocate_label();
+    let coroutine_body_end_label = program.allocate_label();
     program.emit_insn(Insn::InitCoroutine {
         yield_reg,
-        jump_on_definition: subquery_body_end_label,
-        start_offset: coroutine_implementation_start_offset,
+        jump_on_definition: coroutine_body_end_label,
+        start_offset: coroutine_implementation_start,
     });
-    program.preassign_label_to_next_insn(coroutine_implementation_start_offset);
-    let result_column_start_reg = emit_query(program, plan, &mut metadata)?;
-    program.resolve_label(end_coroutine_label, program.offset());
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+
+    let queue_table = Rc::new(BTreeTable {
+        root_page: 0, // Not relevant for ephemeral table definition
+        name: "recursive_cte_queue".to_string(),
+        has_rowid: true,
+        primary_key_columns: vec![],
+        columns: columns.clone(),
+        is_strict: false,
+        has_autoincrement: false,
+        unique_sets: None,
+        column_lookup: Default::default(),
+        foreign_keys: vec![],
+        checks: vec![],
+    });
+    let queue_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(queue_table));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: queue_cursor_id,
+        is_table: true,
+    });
+    let dedupe = if *distinct {
+        let collations = seed
+            .result_columns
+            .iter()
+            .map(|column| result_column_collation(&column.expr, seed))
+            .collect::<Result<Vec<_>>>()?;
+        Some(create_dedupe_index(
+            program,
+            seed,
+            t_ctx.resolver.schema,
+            &collations,
+        )?)
+    } else {
+        None
+    };
+    let queue = QueryDestination::RecursiveCteQueue {
+        cursor_id: queue_cursor_id,
+        dedupe,
+    };
+
+    // Seed the queue with the rows of the initial SELECT.
+    seed.query_destination = queue.clone();
+    let mut seed_ctx = subquery_translate_ctx(program, seed, t_ctx);
+    emit_query(program, seed, &mut seed_ctx)?;
+
+    let label_done = program.allocate_label();
+    let reg_limit = match *limit {
+        Some(0) => {
+            program.emit_insn(Insn::Goto {
+                target_pc: label_done,
+            });
+            None
+        }
+        Some(limit) if limit > 0 => {
+            let reg = program.alloc_register();
+            program.emit_int(limit as i64, reg);
+            Some(reg)
+        }
+        _ => None,
+    };
+
+    // Rows are queued with increasing rowids, so the queue is read in rowid order
+    // and rows appended by the recursive SELECT are visited after the ones before them.
+    let reg_next_rowid = program.alloc_register();
+    program.emit_int(1, reg_next_rowid);
+    let reg_one = program.alloc_register();
+    program.emit_int(1, reg_one);
+    let result_columns_start = program.alloc_registers(num_columns);
+
+    let label_next_row = program.allocate_label();
+    program.preassign_label_to_next_insn(label_next_row);
+    program.emit_insn(Insn::SeekRowid {
+        cursor_id: queue_cursor_id,
+        src_reg: reg_next_rowid,
+        target_pc: label_done,
+    });
+    for i in 0..num_columns {
+        program.emit_insn(Insn::Column {
+            cursor_id: queue_cursor_id,
+            column: i,
+            dest: result_columns_start + i,
+            default: None,
+        });
+    }
+    program.emit_insn(Insn::Add {
+        lhs: reg_next_rowid,
+        rhs: reg_one,
+        dest: reg_next_rowid,
+    });
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: BranchOffset::Offset(0),
+    });
+    if let Some(reg_limit) = reg_limit {
+        program.emit_insn(Insn::DecrJumpZero {
+            reg: reg_limit,
+            target_pc: label_done,
+        });
+    }
+
+    // Run the recursive SELECT against the row that was just yielded.
+    for table_reference in step.table_references.joined_tables_mut() {
+        if let Table::FromClauseSubquery(current_row) = &mut table_reference.table {
+            if matches!(current_row.recursive_cte, Some(RecursiveCte::CurrentRow)) {
+                emit_recursive_cte_current_row(program, current_row, result_columns_start);
+            }
+        }
+    }
+    step.query_destination = queue;
+    let mut step_ctx = subquery_translate_ctx(program, step, t_ctx);
+    emit_query(program, step, &mut step_ctx)?;
+    program.emit_insn(Insn::Goto {
+        target_pc: label_next_row,
+    });
+
+    program.preassign_label_to_next_insn(label_done);
     program.emit_insn(Insn::EndCoroutine { yield_reg });
-    program.preassign_label_to_next_insn(subquery_body_end_label);
-    Ok(result_column_start_reg)
+    program.preassign_label_to_next_insn(coroutine_body_end_label);
+
+    // The parent query reads the CTE through the coroutine, like any other subquery.
+    seed.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    };
+    Ok(result_columns_start)
+}
+
+/// Emit the coroutine for the reference to a recursive CTE in its recursive SELECT,
+/// which yields the row in `result_columns_start` exactly once.
+fn emit_recursive_cte_current_row(
+    program: &mut ProgramBuilder,
+    current_row: &mut FromClauseSubquery,
+    result_columns_start: usize,
+) {
+    let yield_reg = program.alloc_register();
+    let coroutine_implementation_start = program.allocate_label();
+    let coroutine_body_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: coroutine_body_end_label,
+        start_offset: coroutine_implementation_start,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: BranchOffset::Offset(0),
+    });
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(coroutine_body_end_label);
+    current_row.plan.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    };
+    current_row.result_columns_start_reg = Some(result_columns_start);
 }
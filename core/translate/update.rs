@@ -14,6 +14,7 @@ use crate::{
 use turso_sqlite3_parser::ast::{self, Expr, ResultColumn, SortOrder, Update};
 
 use super::emitter::emit_program;
+use super::index::index_column_dependencies;
 use super::optimizer::optimize_plan;
 use super::plan::{
     ColumnUsedMask, IterationDirection, JoinedTable, Plan, ResultSetColumn, TableReferences,
@@ -53,10 +54,18 @@ addr  opcode         p1    p2    p3    p4             p5  comment
 pub fn translate_update(
     schema: &Schema,
     body: &mut Update,
+    enforce_foreign_keys: bool,
+    enforce_check_constraints: bool,
     syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> crate::Result<ProgramBuilder> {
-    let mut plan = prepare_update_plan(&mut program, schema, body)?;
+    let mut plan = prepare_update_plan(
+        &mut program,
+        schema,
+        body,
+        enforce_foreign_keys,
+        enforce_check_constraints,
+    )?;
     optimize_plan(&mut plan, schema)?;
     // TODO: freestyling these numbers
     let opts = ProgramBuilderOpts {
@@ -76,7 +85,7 @@ pub fn translate_update_with_after(
     mut program: ProgramBuilder,
     after: impl FnOnce(&mut ProgramBuilder),
 ) -> crate::Result<ProgramBuilder> {
-    let mut plan = prepare_update_plan(&mut program, schema, body)?;
+    let mut plan = prepare_update_plan(&mut program, schema, body, false, true)?;
     optimize_plan(&mut plan, schema)?;
     // TODO: freestyling these numbers
     let opts = ProgramBuilderOpts {
@@ -93,6 +102,8 @@ pub fn prepare_update_plan(
     program: &mut ProgramBuilder,
     schema: &Schema,
     body: &mut Update,
+    enforce_foreign_keys: bool,
+    enforce_check_constraints: bool,
 ) -> crate::Result<Plan> {
     if body.with.is_some() {
         bail_parse_error!("WITH clause is not supported");
@@ -112,6 +123,9 @@ pub fn prepare_update_plan(
         Some(table) => table,
         None => bail_parse_error!("Parse error: no such table: {}", table_name),
     };
+    if table.btree().is_some_and(|table| !table.has_rowid) {
+        bail_parse_error!("UPDATE of WITHOUT ROWID table is not supported");
+    }
     let iter_dir = body
         .order_by
         .as_ref()
@@ -131,7 +145,7 @@ pub fn prepare_update_plan(
             Table::BTree(btree_table) => Table::BTree(btree_table.clone()),
             _ => unreachable!(),
         },
-        identifier: table_name.0.clone(),
+        identifier: normalize_ident(&table_name.0),
         internal_id: program.table_reference_counter.next(),
         op: Operation::Scan {
             iter_dir,
@@ -162,6 +176,9 @@ pub fn prepare_update_plan(
                         ident, table_name.0
                     ))
                 })?;
+            if table.columns()[col_index].is_generated() {
+                bail_parse_error!("cannot UPDATE generated column \"{}\"", ident);
+            }
 
             let _ = bind_column_references(&mut set.expr, &mut table_references, None);
             Ok((col_index, set.expr.clone()))
@@ -216,7 +233,7 @@ pub fn prepare_update_plan(
                 Table::BTree(btree_table) => Table::BTree(btree_table.clone()),
                 _ => unreachable!(),
             },
-            identifier: table_name.0.clone(),
+            identifier: normalize_ident(&table_name.0),
             internal_id,
             op: Operation::Scan {
                 iter_dir,
@@ -250,9 +267,15 @@ pub fn prepare_update_plan(
                 default: None,
                 unique: false,
                 collation: None,
+                generated_expr: None,
+                generated_stored: false,
             }],
             is_strict: false,
+            has_autoincrement: false,
             unique_sets: None,
+            column_lookup: Default::default(),
+            foreign_keys: vec![],
+            checks: vec![],
         });
 
         let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
@@ -281,6 +304,7 @@ pub fn prepare_update_plan(
             contains_constant_false_condition: false,
             distinctness: super::plan::Distinctness::NonDistinct,
             values: vec![],
+            window: None,
         };
 
         optimize_select_plan(&mut ephemeral_plan, schema)?;
@@ -317,19 +341,20 @@ pub fn prepare_update_plan(
         .unwrap_or(Ok((None, None)))?;
 
     // Check what indexes will need to be updated by checking set_clauses and see
-    // if a column is contained in an index.
+    // if a column is contained in an index, or decides whether a row is in a partial index.
     let indexes = schema.get_indices(&table_name.0);
-    let indexes_to_update = indexes
-        .iter()
-        .filter(|index| {
-            index.columns.iter().any(|index_column| {
-                set_clauses
-                    .iter()
-                    .any(|(set_index_column, _)| index_column.pos_in_table == *set_index_column)
-            })
-        })
-        .cloned()
-        .collect();
+    let mut indexes_to_update = vec![];
+    if let Some(btree_table) = table.btree() {
+        for index in indexes {
+            let dependencies = index_column_dependencies(&btree_table, index)?;
+            if set_clauses
+                .iter()
+                .any(|(set_index_column, _)| dependencies.contains(set_index_column))
+            {
+                indexes_to_update.push(index.clone());
+            }
+        }
+    }
 
     Ok(Plan::Update(UpdatePlan {
         table_references,
@@ -341,6 +366,8 @@ pub fn prepare_update_plan(
         offset,
         contains_constant_false_condition: false,
         indexes_to_update,
+        enforce_foreign_keys,
+        enforce_check_constraints,
         ephemeral_plan,
     }))
 }
@@ -1,10 +1,13 @@
+use crate::schema::Index;
 use crate::translate::emitter::Resolver;
 use crate::translate::expr::{translate_expr_no_constant_opt, NoConstantOptReason};
 use crate::translate::plan::{QueryDestination, SelectPlan};
+use crate::translate::result_row::emit_recursive_cte_queue_insert;
 use crate::vdbe::builder::ProgramBuilder;
 use crate::vdbe::insn::Insn;
-use crate::vdbe::BranchOffset;
+use crate::vdbe::{BranchOffset, CursorID};
 use crate::Result;
+use std::sync::Arc;
 
 pub fn emit_values(
     program: &mut ProgramBuilder,
@@ -21,6 +24,16 @@ pub fn emit_values(
         QueryDestination::CoroutineYield { yield_reg, .. } => {
             emit_values_in_subquery(program, plan, resolver, yield_reg)?
         }
+        QueryDestination::RecursiveCteQueue {
+            cursor_id,
+            ref dedupe,
+        } => emit_values_into_recursive_cte_queue(
+            program,
+            plan,
+            resolver,
+            cursor_id,
+            dedupe.as_ref(),
+        )?,
         QueryDestination::EphemeralIndex { .. } => unreachable!(),
         QueryDestination::EphemeralTable { .. } => unreachable!(),
     };
@@ -58,6 +71,18 @@ fn emit_values_when_single_row(
                 end_offset: BranchOffset::Offset(0),
             });
         }
+        QueryDestination::RecursiveCteQueue {
+            cursor_id,
+            ref dedupe,
+        } => {
+            emit_recursive_cte_queue_insert(
+                program,
+                cursor_id,
+                dedupe.as_ref(),
+                start_reg,
+                row_len,
+            );
+        }
         QueryDestination::EphemeralIndex { .. } => unreachable!(),
         QueryDestination::EphemeralTable { .. } => unreachable!(),
     }
@@ -145,3 +170,29 @@ fn emit_values_in_subquery(
 
     Ok(start_reg)
 }
+
+fn emit_values_into_recursive_cte_queue(
+    program: &mut ProgramBuilder,
+    plan: &SelectPlan,
+    resolver: &Resolver,
+    cursor_id: CursorID,
+    dedupe: Option<&(CursorID, Arc<Index>)>,
+) -> Result<usize> {
+    let row_len = plan.values[0].len();
+    let start_reg = program.alloc_registers(row_len);
+    for value in &plan.values {
+        for (i, v) in value.iter().enumerate() {
+            translate_expr_no_constant_opt(
+                program,
+                None,
+                v,
+                start_reg + i,
+                resolver,
+                NoConstantOptReason::RegisterReuse,
+            )?;
+        }
+        emit_recursive_cte_queue_insert(program, cursor_id, dedupe, start_reg, row_len);
+    }
+
+    Ok(start_reg)
+}
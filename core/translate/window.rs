@@ -0,0 +1,1769 @@
+// This module contains the planning and bytecode generation for window functions.
+//
+// Like SQLite, a SELECT with window functions is rewritten into a query over a FROM clause subquery:
+// the subquery computes everything that does not depend on a window function (including any
+// GROUP BY and aggregation), and the outer query feeds its rows into a sorter ordered by the
+// PARTITION BY and ORDER BY keys of the window. The sorted rows are then read back one partition
+// at a time into an ephemeral table keyed by their position, so that the functions that look at
+// other rows of the partition (LAG, LEAD, FIRST_VALUE, ...) can seek to them. Once a partition is
+// complete, its rows are read back from the ephemeral table in order and their window functions
+// are computed.
+
+use std::rc::Rc;
+
+use turso_sqlite3_parser::ast::{self, SortOrder};
+
+use crate::{
+    error::SQLITE_ERROR,
+    function::{Func, WindowFunc},
+    schema::{BTreeTable, Column, FromClauseSubquery, PseudoCursorType, Schema, Table, Type},
+    translate::collate::CollationSeq,
+    util::{exprs_are_equivalent, normalize_ident},
+    vdbe::{
+        builder::{CursorType, ProgramBuilder, TableRefIdCounter},
+        insn::{CmpInsFlags, InsertFlags, Insn},
+        BranchOffset, CursorID,
+    },
+    LimboError, Result,
+};
+
+use super::{
+    emitter::TranslateCtx,
+    expr::{translate_expr, walk_expr, walk_expr_mut, WalkControl},
+    order_by::{order_by_sorter_insert, sorter_insert},
+    plan::{
+        Distinctness, JoinOrderMember, JoinedTable, QueryDestination, ResultSetColumn, SelectPlan,
+        TableReferences, Window, WindowFunction,
+    },
+    planner::resolve_aggregates,
+    result_row::emit_select_result,
+};
+
+// Metadata for computing window functions
+#[derive(Debug)]
+pub struct WindowMetadata {
+    // cursor id for the sorter that orders the rows by partition and peer group
+    pub sort_cursor: CursorID,
+    // register where the sorter records are inserted and later retrieved from
+    pub reg_sorter_data: usize,
+    // number of columns in a sorter record
+    pub record_len: usize,
+    // first register of a sorter record: the PARTITION BY keys, the ORDER BY keys and finally
+    // all the columns of the subquery
+    pub reg_record_start: usize,
+    // cursor id for the ephemeral table the sorted rows are buffered in, see emit_window()
+    pub buffer_cursor: CursorID,
+    // collations of the PARTITION BY keys followed by the ORDER BY keys
+    pub collations: Vec<Option<CollationSeq>>,
+}
+
+/// Returns the OVER clause of an expression, if it is a window function call.
+fn over_clause(expr: &ast::Expr) -> Option<&ast::Over> {
+    match expr {
+        ast::Expr::FunctionCall {
+            filter_over: Some(tail),
+            ..
+        }
+        | ast::Expr::FunctionCallStar {
+            filter_over: Some(tail),
+            ..
+        } => tail.over_clause.as_deref(),
+        _ => None,
+    }
+}
+
+/// Returns the first window function call found in an expression, if any.
+fn find_window_function<'a>(expr: &'a ast::Expr) -> Result<Option<&'a ast::Expr>> {
+    let mut found = None;
+    walk_expr(expr, &mut |expr: &'a ast::Expr| -> Result<WalkControl> {
+        if found.is_none() && over_clause(expr).is_some() {
+            found = Some(expr);
+        }
+        if found.is_some() {
+            return Ok(WalkControl::SkipChildren);
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(found)
+}
+
+fn bail_if_contains_window_function(expr: &ast::Expr) -> Result<()> {
+    if let Some(ast::Expr::FunctionCall { name, .. } | ast::Expr::FunctionCallStar { name, .. }) =
+        find_window_function(expr)?
+    {
+        crate::bail_parse_error!("misuse of window function {}()", name.0);
+    }
+    Ok(())
+}
+
+fn find_window_definition<'a>(
+    name: &ast::Name,
+    definitions: &'a [ast::WindowDef],
+) -> Result<&'a ast::Window> {
+    let name_normalized = normalize_ident(&name.0);
+    definitions
+        .iter()
+        .find(|def| normalize_ident(&def.name.0) == name_normalized)
+        .map(|def| &def.window)
+        .ok_or_else(|| LimboError::ParseError(format!("no such window: {}", name.0)))
+}
+
+/// Merges a window with the window it is based on, e.g. OVER (win ORDER BY x).
+fn resolve_window_base(window: ast::Window, definitions: &[ast::WindowDef]) -> Result<ast::Window> {
+    let Some(base_name) = &window.base else {
+        return Ok(window);
+    };
+    let base = find_window_definition(base_name, definitions)?;
+    if window.partition_by.is_some() {
+        crate::bail_parse_error!(
+            "cannot override PARTITION clause of window: {}",
+            base_name.0
+        );
+    }
+    if base.order_by.is_some() && window.order_by.is_some() {
+        crate::bail_parse_error!("cannot override ORDER BY clause of window: {}", base_name.0);
+    }
+    if base.frame_clause.is_some() {
+        crate::bail_parse_error!(
+            "cannot override frame specification of window: {}",
+            base_name.0
+        );
+    }
+    Ok(ast::Window {
+        base: None,
+        partition_by: base.partition_by.clone(),
+        order_by: window.order_by.or_else(|| base.order_by.clone()),
+        frame_clause: window.frame_clause,
+    })
+}
+
+/// Rejects the frame specifications SQLite rejects, and the ones that are not supported yet.
+fn validate_frame(frame: &ast::FrameClause) -> Result<()> {
+    use ast::FrameBound;
+    let end = frame.end.as_ref().unwrap_or(&FrameBound::CurrentRow);
+    // The start of the frame cannot come after its end in the order UNBOUNDED PRECEDING,
+    // n PRECEDING, CURRENT ROW, n FOLLOWING, UNBOUNDED FOLLOWING.
+    let unsupported = match (&frame.start, end) {
+        (FrameBound::UnboundedFollowing, _) | (_, FrameBound::UnboundedPreceding) => true,
+        (FrameBound::CurrentRow, FrameBound::Preceding(_)) => true,
+        (FrameBound::Following(_), FrameBound::Preceding(_) | FrameBound::CurrentRow) => true,
+        _ => false,
+    };
+    if unsupported {
+        crate::bail_parse_error!("unsupported frame specification");
+    }
+    let has_offset = [&frame.start, end]
+        .iter()
+        .any(|bound| matches!(bound, FrameBound::Preceding(_) | FrameBound::Following(_)));
+    match frame.mode {
+        ast::FrameMode::Rows => {}
+        ast::FrameMode::Range if has_offset => {
+            crate::bail_parse_error!("RANGE with offset PRECEDING/FOLLOWING is not supported yet")
+        }
+        ast::FrameMode::Groups if has_offset => {
+            crate::bail_parse_error!("GROUPS with offset PRECEDING/FOLLOWING is not supported yet")
+        }
+        ast::FrameMode::Range | ast::FrameMode::Groups => {}
+    }
+    if !matches!(frame.exclude, None | Some(ast::FrameExclude::NoOthers)) {
+        crate::bail_parse_error!("EXCLUDE clause is not supported yet");
+    }
+    Ok(())
+}
+
+/// Checks a window function call and replaces its OVER clause with the complete window it refers to.
+fn resolve_window_function(expr: &mut ast::Expr, definitions: &[ast::WindowDef]) -> Result<()> {
+    let (name, args, distinctness, tail) = match expr {
+        ast::Expr::FunctionCall {
+            name,
+            args,
+            distinctness,
+            filter_over: Some(tail),
+            ..
+        } if tail.over_clause.is_some() => (
+            &*name,
+            args.as_deref().unwrap_or_default(),
+            matches!(distinctness, Some(ast::Distinctness::Distinct)),
+            tail,
+        ),
+        ast::Expr::FunctionCallStar {
+            name,
+            filter_over: Some(tail),
+        } if tail.over_clause.is_some() => (&*name, &[][..], false, tail),
+        ast::Expr::FunctionCall {
+            name,
+            args: Some(args),
+            ..
+        } => {
+            // The arguments of an aggregate are computed before the windows are.
+            if let Ok(Func::Agg(_)) = Func::resolve_function(&normalize_ident(&name.0), args.len())
+            {
+                for arg in args.iter() {
+                    bail_if_contains_window_function(arg)?;
+                }
+            }
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+
+    let func_name = normalize_ident(&name.0);
+    let Some(func) = WindowFunc::resolve(&func_name) else {
+        match Func::resolve_function(&func_name, args.len())? {
+            Func::Agg(_) => crate::bail_parse_error!(
+                "aggregate window functions are not supported yet: {}()",
+                name.0
+            ),
+            _ => crate::bail_parse_error!("{}() may not be used as a window function", name.0),
+        }
+    };
+    if !func.accepts_num_args(args.len()) {
+        crate::bail_parse_error!("wrong number of arguments to function {}()", name.0);
+    }
+    if distinctness {
+        crate::bail_parse_error!("DISTINCT is not supported for window functions");
+    }
+    if tail.filter_clause.is_some() {
+        crate::bail_parse_error!("FILTER clause may only be used with aggregate window functions");
+    }
+    for arg in args {
+        bail_if_contains_window_function(arg)?;
+    }
+
+    let over = tail
+        .over_clause
+        .as_mut()
+        .expect("window function has an OVER clause");
+    let window = match over.as_ref() {
+        ast::Over::Name(window_name) => find_window_definition(window_name, definitions)?.clone(),
+        ast::Over::Window(window) => resolve_window_base(window.clone(), definitions)?,
+    };
+    if let Some(frame) = &window.frame_clause {
+        validate_frame(frame)?;
+    }
+    for expr in window.partition_by.iter().flatten() {
+        bail_if_contains_window_function(expr)?;
+    }
+    for sorted_column in window.order_by.iter().flatten() {
+        bail_if_contains_window_function(&sorted_column.expr)?;
+    }
+    **over = ast::Over::Window(window);
+    Ok(())
+}
+
+/// Validates the window function calls of a SELECT and resolves the named windows of its WINDOW clause.
+/// After this, the OVER clause of every window function call is a complete [ast::Window] without a base window.
+pub fn resolve_window_definitions(
+    columns: &mut [ast::ResultColumn],
+    order_by: Option<&mut Vec<ast::SortedColumn>>,
+    where_clause: Option<&ast::Expr>,
+    group_by: Option<&ast::GroupBy>,
+    window_clause: Option<Vec<ast::WindowDef>>,
+) -> Result<()> {
+    // A window definition can be based on any window defined before it.
+    let mut definitions: Vec<ast::WindowDef> = vec![];
+    for mut def in window_clause.unwrap_or_default() {
+        def.window = resolve_window_base(def.window, &definitions)?;
+        definitions.push(def);
+    }
+
+    // Window functions are computed after filtering and grouping, so they cannot be used there.
+    let filter_exprs = where_clause.into_iter().chain(
+        group_by
+            .into_iter()
+            .flat_map(|group_by| group_by.exprs.iter().chain(group_by.having.as_deref())),
+    );
+    for expr in filter_exprs {
+        bail_if_contains_window_function(expr)?;
+    }
+
+    let result_exprs = columns.iter_mut().filter_map(|column| match column {
+        ast::ResultColumn::Expr(expr, _) => Some(expr),
+        _ => None,
+    });
+    let order_by_exprs = order_by.into_iter().flat_map(|order_by| {
+        order_by
+            .iter_mut()
+            .map(|sorted_column| &mut sorted_column.expr)
+    });
+    for expr in result_exprs.chain(order_by_exprs) {
+        walk_expr_mut(expr, &mut |expr: &mut ast::Expr| -> Result<()> {
+            resolve_window_function(expr, &definitions)
+        })?;
+    }
+    Ok(())
+}
+
+/// Replaces every largest subexpression of `expr` that does not contain a window function with a
+/// column of the window subquery, adding the subexpression to the subquery result columns.
+fn extract_subquery_columns(
+    expr: &mut ast::Expr,
+    subquery_id: ast::TableInternalId,
+    subquery_columns: &mut Vec<ResultSetColumn>,
+) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut ast::Expr| -> Result<()> {
+        match expr {
+            ast::Expr::Literal(_) => return Ok(()),
+            ast::Expr::Column { table, .. } if *table == subquery_id => return Ok(()),
+            _ => {}
+        }
+        if find_window_function(expr)?.is_some() {
+            return Ok(());
+        }
+        let column = match subquery_columns
+            .iter()
+            .position(|rc| exprs_are_equivalent(&rc.expr, expr))
+        {
+            Some(column) => column,
+            None => {
+                subquery_columns.push(ResultSetColumn {
+                    expr: expr.clone(),
+                    alias: None,
+                    contains_aggregates: false,
+                });
+                subquery_columns.len() - 1
+            }
+        };
+        *expr = ast::Expr::Column {
+            database: None,
+            table: subquery_id,
+            column,
+            is_rowid_alias: false,
+        };
+        Ok(())
+    })
+}
+
+/// Adds a window function call to the window of the query.
+fn add_window_function(window: &mut Option<Window>, expr: &ast::Expr) -> Result<()> {
+    let (name, args) = match expr {
+        ast::Expr::FunctionCall { name, args, .. } => (name, args.clone().unwrap_or_default()),
+        ast::Expr::FunctionCallStar { name, .. } => (name, vec![]),
+        _ => unreachable!("window function must be a function call"),
+    };
+    let Some(func) = WindowFunc::resolve(&normalize_ident(&name.0)) else {
+        unreachable!("window functions are validated before planning");
+    };
+    let Some(ast::Over::Window(over)) = over_clause(expr) else {
+        unreachable!("named windows are resolved before planning");
+    };
+    let partition_by = over.partition_by.clone().unwrap_or_default();
+    let frame = over.frame_clause.clone();
+    let order_by = over
+        .order_by
+        .iter()
+        .flatten()
+        .map(|sorted_column| {
+            (
+                sorted_column.expr.clone(),
+                sorted_column.order.unwrap_or(SortOrder::Asc),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let window = window.get_or_insert_with(|| Window {
+        partition_by: partition_by.clone(),
+        order_by: order_by.clone(),
+        functions: vec![],
+    });
+    let same_partition = window.partition_by.len() == partition_by.len()
+        && window
+            .partition_by
+            .iter()
+            .zip(partition_by.iter())
+            .all(|(a, b)| exprs_are_equivalent(a, b));
+    let same_order = window.order_by.len() == order_by.len()
+        && window
+            .order_by
+            .iter()
+            .zip(order_by.iter())
+            .all(|((a, a_order), (b, b_order))| a_order == b_order && exprs_are_equivalent(a, b));
+    if !same_partition || !same_order {
+        crate::bail_parse_error!(
+            "window functions over different windows in the same SELECT are not supported yet"
+        );
+    }
+    if !window
+        .functions
+        .iter()
+        .any(|f| exprs_are_equivalent(&f.original_expr, expr))
+    {
+        window.functions.push(WindowFunction {
+            func,
+            args,
+            frame,
+            original_expr: expr.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Collation of a result column of the window subquery, so that the outer query compares its
+/// values the same way the original query would have.
+fn subquery_column_collation(
+    expr: &ast::Expr,
+    tables: &TableReferences,
+) -> Result<Option<CollationSeq>> {
+    match expr {
+        ast::Expr::Collate(_, collation_name) => CollationSeq::new(collation_name).map(Some),
+        ast::Expr::Column { table, column, .. } => Ok(tables
+            .find_table_by_internal_id(*table)
+            .and_then(|table| table.get_column_at(*column))
+            .and_then(|column| column.collation)),
+        _ => Ok(None),
+    }
+}
+
+/// If the SELECT uses window functions, rewrites it into a query over a FROM clause subquery that
+/// computes everything the window functions do not depend on. See the top of this module.
+pub fn rewrite_window_functions(
+    schema: &Schema,
+    plan: &mut SelectPlan,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<()> {
+    let mut has_window_functions = false;
+    let order_by_exprs = plan.order_by.iter().flatten().map(|(expr, _)| expr);
+    for expr in plan
+        .result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(order_by_exprs)
+    {
+        has_window_functions |= find_window_function(expr)?.is_some();
+    }
+    if !has_window_functions {
+        return Ok(());
+    }
+
+    let subquery_id = table_ref_counter.next();
+    let mut subquery_columns = vec![];
+    let mut result_columns = Vec::with_capacity(plan.result_columns.len());
+    for rc in plan.result_columns.iter() {
+        let mut expr = rc.expr.clone();
+        extract_subquery_columns(&mut expr, subquery_id, &mut subquery_columns)?;
+        result_columns.push(ResultSetColumn {
+            // The rewritten expression no longer says where the value comes from, so keep the original name.
+            alias: Some(
+                rc.name(&plan.table_references)
+                    .map_or_else(|| rc.expr.to_string(), str::to_string),
+            ),
+            expr,
+            contains_aggregates: false,
+        });
+    }
+    let order_by = plan
+        .order_by
+        .take()
+        .map(|order_by| {
+            order_by
+                .into_iter()
+                .map(|(mut expr, order)| {
+                    extract_subquery_columns(&mut expr, subquery_id, &mut subquery_columns)?;
+                    Ok((expr, order))
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
+
+    let mut window = None;
+    let order_by_exprs = order_by.iter().flatten().map(|(expr, _)| expr);
+    for expr in result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(order_by_exprs)
+    {
+        walk_expr(expr, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+            if over_clause(expr).is_some() {
+                add_window_function(&mut window, expr)?;
+            }
+            Ok(WalkControl::Continue)
+        })?;
+    }
+
+    if subquery_columns.is_empty() {
+        // e.g. SELECT row_number() OVER () FROM t only needs the subquery to produce its rows.
+        subquery_columns.push(ResultSetColumn {
+            expr: ast::Expr::Literal(ast::Literal::Null),
+            alias: None,
+            contains_aggregates: false,
+        });
+    }
+
+    let outer_query_refs = plan.table_references.outer_query_refs().to_vec();
+    let mut subquery = SelectPlan {
+        join_order: std::mem::take(&mut plan.join_order),
+        table_references: std::mem::replace(
+            &mut plan.table_references,
+            TableReferences::new(vec![], outer_query_refs),
+        ),
+        result_columns: subquery_columns,
+        where_clause: std::mem::take(&mut plan.where_clause),
+        group_by: plan.group_by.take(),
+        order_by: None,
+        aggregates: std::mem::take(&mut plan.aggregates),
+        limit: None,
+        offset: None,
+        contains_constant_false_condition: plan.contains_constant_false_condition,
+        query_destination: QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // will be set later in bytecode emission
+            coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+        },
+        distinctness: Distinctness::NonDistinct,
+        values: vec![],
+        window: None,
+    };
+    for rc in subquery.result_columns.iter_mut() {
+        rc.contains_aggregates = resolve_aggregates(schema, &rc.expr, &mut subquery.aggregates)?;
+    }
+    let collations = subquery
+        .result_columns
+        .iter()
+        .map(|rc| subquery_column_collation(&rc.expr, &subquery.table_references))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut table =
+        JoinedTable::new_subquery("window_subquery".to_string(), subquery, None, subquery_id);
+    if let Table::FromClauseSubquery(from_clause_subquery) = &mut table.table {
+        for (column, collation) in from_clause_subquery.columns.iter_mut().zip(collations) {
+            column.collation = collation;
+        }
+    }
+    for idx in 0..table.columns().len() {
+        table.mark_column_used(idx);
+    }
+
+    plan.table_references.add_joined_table(table);
+    plan.join_order = vec![JoinOrderMember {
+        table_id: subquery_id,
+        original_idx: 0,
+        is_outer: false,
+    }];
+    plan.result_columns = result_columns;
+    plan.order_by = order_by;
+    plan.contains_constant_false_condition = false;
+    plan.window = window;
+    Ok(())
+}
+
+/// The FROM clause subquery a window is computed over.
+fn window_subquery(tables: &TableReferences) -> &FromClauseSubquery {
+    match &tables.joined_tables()[0].table {
+        Table::FromClauseSubquery(from_clause_subquery) => from_clause_subquery,
+        _ => unreachable!("window functions are computed over a FROM clause subquery"),
+    }
+}
+
+/// Columns of the rows buffered in the ephemeral table: the RANK() and DENSE_RANK() of the row
+/// within its partition, followed by all the columns of the window subquery.
+const BUFFER_RANK_COLUMN: usize = 0;
+const BUFFER_DENSE_RANK_COLUMN: usize = 1;
+const BUFFER_SUBQUERY_COLUMNS_START: usize = 2;
+
+/// Initialize resources needed for computing window functions
+pub fn init_window(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx,
+    window: &Window,
+    referenced_tables: &TableReferences,
+) -> Result<()> {
+    let sort_cursor = program.alloc_cursor_id(CursorType::Sorter);
+
+    // Like for ORDER BY, a key uses the collation of its COLLATE operator or of the column it reads.
+    let collations = window
+        .partition_by
+        .iter()
+        .chain(window.order_by.iter().map(|(expr, _)| expr))
+        .map(|expr| match expr {
+            ast::Expr::Collate(_, collation_name) => CollationSeq::new(collation_name).map(Some),
+            ast::Expr::Column { table, column, .. } => {
+                let table = referenced_tables.find_table_by_internal_id(*table).unwrap();
+
+                let Some(table_column) = table.get_column_at(*column) else {
+                    crate::bail_parse_error!("column index out of bounds");
+                };
+
+                Ok(table_column.collation)
+            }
+            _ => Ok(Some(CollationSeq::default())),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut order = vec![SortOrder::Asc; window.partition_by.len()];
+    order.extend(window.order_by.iter().map(|(_, order)| *order));
+    let subquery = window_subquery(referenced_tables);
+    let record_len = order.len() + subquery.columns.len();
+    program.emit_insn(Insn::SorterOpen {
+        cursor_id: sort_cursor,
+        columns: order.len(),
+        order,
+        collations: collations.clone(),
+    });
+
+    let rank_column = |name: &str| Column {
+        name: Some(name.to_string()),
+        ty: Type::Integer,
+        ty_str: "INTEGER".to_string(),
+        primary_key: false,
+        is_rowid_alias: false,
+        notnull: true,
+        default: None,
+        unique: false,
+        collation: None,
+        generated_expr: None,
+        generated_stored: false,
+    };
+    let mut buffer_columns = vec![rank_column("rank"), rank_column("dense_rank")];
+    buffer_columns.extend(subquery.columns.iter().cloned());
+    let buffer_table = Rc::new(BTreeTable {
+        root_page: 0, // Not relevant for ephemeral table definition
+        name: "window_buffer".to_string(),
+        has_rowid: true,
+        primary_key_columns: vec![],
+        columns: buffer_columns,
+        is_strict: false,
+        has_autoincrement: false,
+        unique_sets: None,
+        column_lookup: Default::default(),
+        foreign_keys: vec![],
+        checks: vec![],
+    });
+    let buffer_cursor = program.alloc_cursor_id(CursorType::BTreeTable(buffer_table));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: buffer_cursor,
+        is_table: true,
+    });
+
+    t_ctx.meta_window = Some(WindowMetadata {
+        sort_cursor,
+        reg_sorter_data: program.alloc_register(),
+        record_len,
+        reg_record_start: program.alloc_registers(record_len),
+        buffer_cursor,
+        collations,
+    });
+    Ok(())
+}
+
+/// Emits the bytecode for inserting a row of the window subquery into the window sorter.
+pub fn window_sorter_insert(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+) -> Result<()> {
+    let window = plan.window.as_ref().expect("window must exist");
+    let meta = t_ctx
+        .meta_window
+        .as_ref()
+        .expect("window metadata must exist");
+
+    let keys = window
+        .partition_by
+        .iter()
+        .chain(window.order_by.iter().map(|(expr, _)| expr));
+    let mut reg = meta.reg_record_start;
+    for expr in keys {
+        translate_expr(
+            program,
+            Some(&plan.table_references),
+            expr,
+            reg,
+            &t_ctx.resolver,
+        )?;
+        reg += 1;
+    }
+    let subquery = window_subquery(&plan.table_references);
+    program.emit_insn(Insn::Copy {
+        src_reg: subquery
+            .result_columns_start_reg
+            .expect("subquery result columns must be set"),
+        dst_reg: reg,
+        amount: subquery.columns.len() - 1,
+    });
+    sorter_insert(
+        program,
+        meta.reg_record_start,
+        meta.record_len,
+        meta.sort_cursor,
+        meta.reg_sorter_data,
+    );
+    Ok(())
+}
+
+/// Compares the keys of the current row with the ones of the previous row, one key at a time so
+/// that each key uses its own collation, and jumps to `label_changed` if any of them differ.
+fn emit_keys_changed_check(
+    program: &mut ProgramBuilder,
+    reg_keys: usize,
+    reg_prev_keys: usize,
+    collations: &[Option<CollationSeq>],
+    label_changed: BranchOffset,
+) {
+    for (i, collation) in collations.iter().enumerate() {
+        let label_next_key = program.allocate_label();
+        program.emit_insn(Insn::Compare {
+            start_reg_a: reg_keys + i,
+            start_reg_b: reg_prev_keys + i,
+            count: 1,
+            collation: *collation,
+        });
+        program.emit_insn(Insn::Jump {
+            target_pc_lt: label_changed,
+            target_pc_eq: label_next_key,
+            target_pc_gt: label_changed,
+        });
+        program.preassign_label_to_next_insn(label_next_key);
+    }
+}
+
+/// Registers describing the partition whose window functions are being computed, and its current row.
+/// Rows are identified by their rowid in the ephemeral table.
+struct PartitionRegisters {
+    /// Rowid of the first row of the partition.
+    start: usize,
+    /// Rowid of the last row of the partition.
+    end: usize,
+    /// Number of rows in the partition.
+    size: usize,
+    /// Rowid of the current row.
+    current: usize,
+    row_number: usize,
+    rank: usize,
+    dense_rank: usize,
+    /// Rowid of the last peer of the current row, see emit_peer_end().
+    peer_end: usize,
+    one: usize,
+}
+
+/// Emits the bytecode for converting the value in `reg` to an integer, and failing with `message`
+/// unless the value is at least `min`.
+fn emit_integer_argument_check(program: &mut ProgramBuilder, reg: usize, min: i64, message: &str) {
+    let label_error = program.allocate_label();
+    let label_valid = program.allocate_label();
+    let reg_min = program.alloc_register();
+    program.emit_insn(Insn::IsNull {
+        reg,
+        target_pc: label_error,
+    });
+    program.emit_insn(Insn::MustBeInt { reg });
+    program.emit_int(min, reg_min);
+    program.emit_insn(Insn::Ge {
+        lhs: reg,
+        rhs: reg_min,
+        target_pc: label_valid,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.preassign_label_to_next_insn(label_error);
+    program.emit_halt_err(SQLITE_ERROR, message.to_string());
+    program.preassign_label_to_next_insn(label_valid);
+}
+
+/// Emits the bytecode for evaluating a window function argument for the row the buffer cursor
+/// points at, rather than for the current row.
+fn emit_buffered_argument(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    arg: &ast::Expr,
+    buffer_cursor: CursorID,
+    dest: usize,
+) -> Result<()> {
+    let subquery_id = plan.table_references.joined_tables()[0].internal_id;
+    match arg {
+        ast::Expr::Column { table, column, .. } if *table == subquery_id => {
+            program.emit_column(buffer_cursor, BUFFER_SUBQUERY_COLUMNS_START + column, dest);
+        }
+        // Anything else is a literal, see extract_subquery_columns(). It goes through its own
+        // register, as translate_expr() may hoist it and `dest` is overwritten on other rows.
+        _ => {
+            let reg = program.alloc_register();
+            translate_expr(
+                program,
+                Some(&plan.table_references),
+                arg,
+                reg,
+                &t_ctx.resolver,
+            )?;
+            program.emit_insn(Insn::Copy {
+                src_reg: reg,
+                dst_reg: dest,
+                amount: 0,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Emits the bytecode for updating `peer_end` when the current row is past the last peer found so
+/// far, by scanning the following rows until one has a different DENSE_RANK() than the current row.
+/// Each row is scanned once per partition.
+fn emit_peer_end(program: &mut ProgramBuilder, buffer_cursor: CursorID, regs: &PartitionRegisters) {
+    let label_done = program.allocate_label();
+    let label_scan = program.allocate_label();
+    let reg_dense_rank = program.alloc_register();
+    let reg_next = program.alloc_register();
+    let reg_next_dense_rank = program.alloc_register();
+    program.emit_insn(Insn::Le {
+        lhs: regs.current,
+        rhs: regs.peer_end,
+        target_pc: label_done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::SeekRowid {
+        cursor_id: buffer_cursor,
+        src_reg: regs.current,
+        target_pc: label_done,
+    });
+    program.emit_column(buffer_cursor, BUFFER_DENSE_RANK_COLUMN, reg_dense_rank);
+    program.emit_insn(Insn::Copy {
+        src_reg: regs.current,
+        dst_reg: regs.peer_end,
+        amount: 0,
+    });
+    program.preassign_label_to_next_insn(label_scan);
+    program.emit_insn(Insn::Ge {
+        lhs: regs.peer_end,
+        rhs: regs.end,
+        target_pc: label_done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: regs.peer_end,
+        rhs: regs.one,
+        dest: reg_next,
+    });
+    program.emit_insn(Insn::SeekRowid {
+        cursor_id: buffer_cursor,
+        src_reg: reg_next,
+        target_pc: label_done,
+    });
+    program.emit_column(buffer_cursor, BUFFER_DENSE_RANK_COLUMN, reg_next_dense_rank);
+    program.emit_insn(Insn::Ne {
+        lhs: reg_next_dense_rank,
+        rhs: reg_dense_rank,
+        target_pc: label_done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_next,
+        dst_reg: regs.peer_end,
+        amount: 0,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_scan,
+    });
+    program.preassign_label_to_next_insn(label_done);
+}
+
+/// Emits the bytecode for NTILE(n), using the same bucket sizes as SQLite: when the partition does
+/// not divide evenly, the first buckets get one row more than the others.
+/// `reg_buckets` is NULL on the first row of a partition; like SQLite, the argument is only
+/// read on that row and kept for the rest of the partition.
+fn emit_ntile(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    function: &WindowFunction,
+    regs: &PartitionRegisters,
+    reg_buckets: usize,
+    dest: usize,
+) -> Result<()> {
+    // Like SQLite, the argument is converted to an integer, e.g. ntile('2') is ntile(2).
+    let num_buckets = ast::Expr::Cast {
+        expr: Box::new(function.args[0].clone()),
+        type_name: Some(ast::Type {
+            name: "INTEGER".to_string(),
+            size: None,
+        }),
+    };
+    let label_valid_argument = program.allocate_label();
+    program.emit_insn(Insn::NotNull {
+        reg: reg_buckets,
+        target_pc: label_valid_argument,
+    });
+    // The argument goes through its own register, as translate_expr() may hoist it when it is
+    // constant, and reg_buckets is reset on every partition.
+    let reg_argument = program.alloc_register();
+    translate_expr(
+        program,
+        Some(&plan.table_references),
+        &num_buckets,
+        reg_argument,
+        &t_ctx.resolver,
+    )?;
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_argument,
+        dst_reg: reg_buckets,
+        amount: 0,
+    });
+    program.emit_insn(Insn::IfPos {
+        reg: reg_buckets,
+        target_pc: label_valid_argument,
+        decrement_by: 0,
+    });
+    program.emit_halt_err(
+        SQLITE_ERROR,
+        "argument of ntile must be a positive integer".to_string(),
+    );
+    program.preassign_label_to_next_insn(label_valid_argument);
+
+    let reg_size = program.alloc_register();
+    let reg_large_buckets = program.alloc_register();
+    let reg_large_bucket_rows = program.alloc_register();
+    let reg_row_idx = program.alloc_register();
+    let label_less_rows_than_buckets = program.allocate_label();
+    let label_in_small_buckets = program.allocate_label();
+    let label_done = program.allocate_label();
+
+    // size = partition_size / buckets, and the first `large` buckets have size + 1 rows.
+    program.emit_insn(Insn::Divide {
+        lhs: regs.size,
+        rhs: reg_buckets,
+        dest: reg_size,
+    });
+    program.emit_insn(Insn::IfNot {
+        reg: reg_size,
+        target_pc: label_less_rows_than_buckets,
+        jump_if_null: false,
+    });
+    program.emit_insn(Insn::Multiply {
+        lhs: reg_buckets,
+        rhs: reg_size,
+        dest: reg_large_buckets,
+    });
+    program.emit_insn(Insn::Subtract {
+        lhs: regs.size,
+        rhs: reg_large_buckets,
+        dest: reg_large_buckets,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: reg_size,
+        rhs: regs.one,
+        dest: reg_size,
+    });
+    program.emit_insn(Insn::Multiply {
+        lhs: reg_large_buckets,
+        rhs: reg_size,
+        dest: reg_large_bucket_rows,
+    });
+    program.emit_insn(Insn::Subtract {
+        lhs: regs.row_number,
+        rhs: regs.one,
+        dest: reg_row_idx,
+    });
+    program.emit_insn(Insn::Ge {
+        lhs: reg_row_idx,
+        rhs: reg_large_bucket_rows,
+        target_pc: label_in_small_buckets,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    // In one of the large buckets: 1 + row_idx / (size + 1)
+    program.emit_insn(Insn::Divide {
+        lhs: reg_row_idx,
+        rhs: reg_size,
+        dest,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: dest,
+        rhs: regs.one,
+        dest,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+    // In one of the small buckets: 1 + large + (row_idx - large * (size + 1)) / size
+    program.preassign_label_to_next_insn(label_in_small_buckets);
+    program.emit_insn(Insn::Subtract {
+        lhs: reg_row_idx,
+        rhs: reg_large_bucket_rows,
+        dest: reg_row_idx,
+    });
+    program.emit_insn(Insn::Subtract {
+        lhs: reg_size,
+        rhs: regs.one,
+        dest: reg_size,
+    });
+    program.emit_insn(Insn::Divide {
+        lhs: reg_row_idx,
+        rhs: reg_size,
+        dest,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: dest,
+        rhs: reg_large_buckets,
+        dest,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: dest,
+        rhs: regs.one,
+        dest,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+    // Fewer rows than buckets: every row gets a bucket of its own.
+    program.preassign_label_to_next_insn(label_less_rows_than_buckets);
+    program.emit_insn(Insn::Copy {
+        src_reg: regs.row_number,
+        dst_reg: dest,
+        amount: 0,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Emits the bytecode for LAG(expr, offset, default) and LEAD(expr, offset, default): the value of
+/// `expr` for the row `offset` rows before or after the current one, or `default` if the partition
+/// has no such row.
+fn emit_lag_lead(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    function: &WindowFunction,
+    buffer_cursor: CursorID,
+    regs: &PartitionRegisters,
+    dest: usize,
+) -> Result<()> {
+    // Like in SQLite, the offset and the default are evaluated for the current row.
+    match function.args.get(2) {
+        Some(default) => {
+            let reg_default = program.alloc_register();
+            translate_expr(
+                program,
+                Some(&plan.table_references),
+                default,
+                reg_default,
+                &t_ctx.resolver,
+            )?;
+            program.emit_insn(Insn::Copy {
+                src_reg: reg_default,
+                dst_reg: dest,
+                amount: 0,
+            });
+        }
+        None => program.emit_insn(Insn::Null {
+            dest,
+            dest_end: None,
+        }),
+    }
+    let reg_offset = program.alloc_register();
+    match function.args.get(1) {
+        Some(offset) => {
+            translate_expr(
+                program,
+                Some(&plan.table_references),
+                offset,
+                reg_offset,
+                &t_ctx.resolver,
+            )?;
+        }
+        None => program.emit_int(1, reg_offset),
+    }
+    let reg_target = program.alloc_register();
+    if matches!(function.func, WindowFunc::Lag) {
+        program.emit_insn(Insn::Subtract {
+            lhs: regs.current,
+            rhs: reg_offset,
+            dest: reg_target,
+        });
+    } else {
+        program.emit_insn(Insn::Add {
+            lhs: regs.current,
+            rhs: reg_offset,
+            dest: reg_target,
+        });
+    }
+    emit_value_at_row(
+        program,
+        t_ctx,
+        plan,
+        &function.args[0],
+        buffer_cursor,
+        reg_target,
+        (regs.start, regs.end),
+        dest,
+    )
+}
+
+/// Emits the bytecode for FIRST_VALUE(expr), LAST_VALUE(expr) and NTH_VALUE(expr, n): the value of
+/// `expr` for the first, last or n-th row of the frame of the current row, or NULL if the frame
+/// has no such row.
+fn emit_frame_value(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    function: &WindowFunction,
+    buffer_cursor: CursorID,
+    regs: &PartitionRegisters,
+    dest: usize,
+) -> Result<()> {
+    let reg_frame_start = program.alloc_register();
+    let reg_frame_end = program.alloc_register();
+    emit_frame_bounds(
+        program,
+        t_ctx,
+        plan,
+        function.frame.as_ref(),
+        regs,
+        reg_frame_start,
+        reg_frame_end,
+    )?;
+    let reg_target = match function.func {
+        WindowFunc::FirstValue => reg_frame_start,
+        WindowFunc::LastValue => reg_frame_end,
+        _ => {
+            let reg_n = program.alloc_register();
+            translate_expr(
+                program,
+                Some(&plan.table_references),
+                &function.args[1],
+                reg_n,
+                &t_ctx.resolver,
+            )?;
+            emit_integer_argument_check(
+                program,
+                reg_n,
+                1,
+                "second argument to nth_value must be a positive integer",
+            );
+            let reg_target = program.alloc_register();
+            program.emit_insn(Insn::Add {
+                lhs: reg_frame_start,
+                rhs: reg_n,
+                dest: reg_target,
+            });
+            program.emit_insn(Insn::Subtract {
+                lhs: reg_target,
+                rhs: regs.one,
+                dest: reg_target,
+            });
+            reg_target
+        }
+    };
+    program.emit_insn(Insn::Null {
+        dest,
+        dest_end: None,
+    });
+    emit_value_at_row(
+        program,
+        t_ctx,
+        plan,
+        &function.args[0],
+        buffer_cursor,
+        reg_target,
+        (reg_frame_start, reg_frame_end),
+        dest,
+    )
+}
+
+/// Emits the bytecode for evaluating `arg` for the row whose rowid is in `reg_rowid`, leaving
+/// `dest` untouched if that row is not within the rowids in the `bounds` registers.
+#[allow(clippy::too_many_arguments)]
+fn emit_value_at_row(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    arg: &ast::Expr,
+    buffer_cursor: CursorID,
+    reg_rowid: usize,
+    (reg_lower, reg_upper): (usize, usize),
+    dest: usize,
+) -> Result<()> {
+    let label_done = program.allocate_label();
+    program.emit_insn(Insn::Lt {
+        lhs: reg_rowid,
+        rhs: reg_lower,
+        target_pc: label_done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Gt {
+        lhs: reg_rowid,
+        rhs: reg_upper,
+        target_pc: label_done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::SeekRowid {
+        cursor_id: buffer_cursor,
+        src_reg: reg_rowid,
+        target_pc: label_done,
+    });
+    emit_buffered_argument(program, t_ctx, plan, arg, buffer_cursor, dest)?;
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Emits the bytecode for computing the rowids of the first and last rows of the frame of the
+/// current row. The frame is empty if the first comes after the last.
+fn emit_frame_bounds(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    frame: Option<&ast::FrameClause>,
+    regs: &PartitionRegisters,
+    reg_frame_start: usize,
+    reg_frame_end: usize,
+) -> Result<()> {
+    let Some(frame) = frame else {
+        // The default frame is RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW, which ends
+        // with the last peer of the current row, or with the partition if there is no ORDER BY.
+        let window = plan.window.as_ref().expect("window must exist");
+        program.emit_insn(Insn::Copy {
+            src_reg: regs.start,
+            dst_reg: reg_frame_start,
+            amount: 0,
+        });
+        program.emit_insn(Insn::Copy {
+            src_reg: if window.order_by.is_empty() {
+                regs.end
+            } else {
+                regs.peer_end
+            },
+            dst_reg: reg_frame_end,
+            amount: 0,
+        });
+        return Ok(());
+    };
+    let end = frame.end.as_ref().unwrap_or(&ast::FrameBound::CurrentRow);
+    emit_frame_bound(
+        program,
+        t_ctx,
+        plan,
+        frame.mode,
+        &frame.start,
+        true,
+        regs,
+        reg_frame_start,
+    )?;
+    emit_frame_bound(
+        program,
+        t_ctx,
+        plan,
+        frame.mode,
+        end,
+        false,
+        regs,
+        reg_frame_end,
+    )?;
+
+    // Offsets can make the frame extend past the partition.
+    let label_start_in_partition = program.allocate_label();
+    program.emit_insn(Insn::Ge {
+        lhs: reg_frame_start,
+        rhs: regs.start,
+        target_pc: label_start_in_partition,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: regs.start,
+        dst_reg: reg_frame_start,
+        amount: 0,
+    });
+    program.preassign_label_to_next_insn(label_start_in_partition);
+    let label_end_in_partition = program.allocate_label();
+    program.emit_insn(Insn::Le {
+        lhs: reg_frame_end,
+        rhs: regs.end,
+        target_pc: label_end_in_partition,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: regs.end,
+        dst_reg: reg_frame_end,
+        amount: 0,
+    });
+    program.preassign_label_to_next_insn(label_end_in_partition);
+    Ok(())
+}
+
+/// Emits the bytecode for computing the rowid a frame starts or ends at.
+#[allow(clippy::too_many_arguments)]
+fn emit_frame_bound(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    mode: ast::FrameMode,
+    bound: &ast::FrameBound,
+    is_start: bool,
+    regs: &PartitionRegisters,
+    dest: usize,
+) -> Result<()> {
+    let src_reg = match bound {
+        ast::FrameBound::UnboundedPreceding => regs.start,
+        ast::FrameBound::UnboundedFollowing => regs.end,
+        ast::FrameBound::CurrentRow if mode == ast::FrameMode::Rows => regs.current,
+        // In RANGE and GROUPS frames, CURRENT ROW means the first or last peer of the current row.
+        ast::FrameBound::CurrentRow if is_start => {
+            program.emit_insn(Insn::Add {
+                lhs: regs.start,
+                rhs: regs.rank,
+                dest,
+            });
+            program.emit_insn(Insn::Subtract {
+                lhs: dest,
+                rhs: regs.one,
+                dest,
+            });
+            return Ok(());
+        }
+        ast::FrameBound::CurrentRow => regs.peer_end,
+        // Only ROWS frames can have offsets, see validate_frame().
+        ast::FrameBound::Preceding(offset) | ast::FrameBound::Following(offset) => {
+            let reg_offset = program.alloc_register();
+            translate_expr(
+                program,
+                Some(&plan.table_references),
+                offset,
+                reg_offset,
+                &t_ctx.resolver,
+            )?;
+            emit_integer_argument_check(
+                program,
+                reg_offset,
+                0,
+                if is_start {
+                    "frame starting offset must be a non-negative integer"
+                } else {
+                    "frame ending offset must be a non-negative integer"
+                },
+            );
+            if matches!(bound, ast::FrameBound::Preceding(_)) {
+                program.emit_insn(Insn::Subtract {
+                    lhs: regs.current,
+                    rhs: reg_offset,
+                    dest,
+                });
+            } else {
+                program.emit_insn(Insn::Add {
+                    lhs: regs.current,
+                    rhs: reg_offset,
+                    dest,
+                });
+            }
+            return Ok(());
+        }
+    };
+    program.emit_insn(Insn::Copy {
+        src_reg,
+        dst_reg: dest,
+        amount: 0,
+    });
+    Ok(())
+}
+
+/// Emits the bytecode for reading the rows back from the window sorter and buffering them one
+/// partition at a time, computing the window functions of the rows of each partition once it is
+/// complete. This is called once the main loop has inserted all the rows of the window subquery
+/// into the sorter.
+pub fn emit_window<'a>(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx<'a>,
+    plan: &'a SelectPlan,
+) -> Result<()> {
+    let window = plan.window.as_ref().expect("window must exist");
+    let WindowMetadata {
+        sort_cursor,
+        reg_sorter_data,
+        record_len,
+        buffer_cursor,
+        ref collations,
+        ..
+    } = *t_ctx
+        .meta_window
+        .as_ref()
+        .expect("window metadata must exist");
+    let collations = collations.clone();
+    let num_partition_keys = window.partition_by.len();
+    let num_order_keys = window.order_by.len();
+    let num_subquery_columns = window_subquery(&plan.table_references).columns.len();
+
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: record_len,
+    }));
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: record_len,
+    });
+
+    // The keys of the current row are allocated before the ones of the previous row,
+    // because Compare requires its first vector of registers to come first.
+    let reg_keys = program.alloc_registers(num_partition_keys + num_order_keys);
+    let reg_prev_keys = program.alloc_registers(num_partition_keys + num_order_keys);
+    let reg_partition_started = program.alloc_register();
+    let reg_new_peer_group = program.alloc_register();
+    let reg_rows_in_partition = program.alloc_register();
+    let reg_buffer_record_start =
+        program.alloc_registers(BUFFER_SUBQUERY_COLUMNS_START + num_subquery_columns);
+    let reg_rank = reg_buffer_record_start + BUFFER_RANK_COLUMN;
+    let reg_dense_rank = reg_buffer_record_start + BUFFER_DENSE_RANK_COLUMN;
+    let reg_buffer_record = program.alloc_register();
+    let reg_flush_return = program.alloc_register();
+    let regs = PartitionRegisters {
+        start: program.alloc_register(),
+        end: program.alloc_register(),
+        size: program.alloc_register(),
+        current: program.alloc_register(),
+        row_number: program.alloc_register(),
+        rank: program.alloc_register(),
+        dense_rank: program.alloc_register(),
+        peer_end: program.alloc_register(),
+        one: program.alloc_register(),
+    };
+    program.emit_int(0, reg_partition_started);
+    program.emit_int(0, regs.end);
+    program.emit_int(1, regs.one);
+
+    let label_loop_start = program.allocate_label();
+    let label_window_end = program.allocate_label();
+    let label_flush_partition = program.allocate_label();
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sort_cursor,
+        pc_if_empty: label_window_end,
+    });
+    program.preassign_label_to_next_insn(label_loop_start);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sort_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+
+    // Start a new partition on the first row, or when any of the PARTITION BY keys changes,
+    // in which case the previous partition is complete.
+    for i in 0..num_partition_keys {
+        program.emit_column(pseudo_cursor, i, reg_keys + i);
+    }
+    let label_partition_changed = program.allocate_label();
+    let label_new_partition = program.allocate_label();
+    let label_same_partition = program.allocate_label();
+    program.emit_insn(Insn::IfNot {
+        reg: reg_partition_started,
+        target_pc: label_new_partition,
+        jump_if_null: false,
+    });
+    emit_keys_changed_check(
+        program,
+        reg_keys,
+        reg_prev_keys,
+        &collations[..num_partition_keys],
+        label_partition_changed,
+    );
+    program.emit_insn(Insn::Goto {
+        target_pc: label_same_partition,
+    });
+    program.preassign_label_to_next_insn(label_partition_changed);
+    program.emit_insn(Insn::Gosub {
+        target_pc: label_flush_partition,
+        return_reg: reg_flush_return,
+    });
+    program.preassign_label_to_next_insn(label_new_partition);
+    program.emit_int(1, reg_partition_started);
+    if num_partition_keys > 0 {
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_keys,
+            dst_reg: reg_prev_keys,
+            amount: num_partition_keys - 1,
+        });
+    }
+    program.emit_int(0, reg_rows_in_partition);
+    program.emit_int(0, reg_dense_rank);
+    program.emit_int(1, reg_new_peer_group);
+    // The rows of the new partition are buffered after the ones of the previous partitions.
+    program.emit_insn(Insn::Add {
+        lhs: regs.end,
+        rhs: regs.one,
+        dest: regs.start,
+    });
+    program.preassign_label_to_next_insn(label_same_partition);
+
+    // Start a new peer group on the first row of a partition, or when any of the ORDER BY keys changes.
+    for i in 0..num_order_keys {
+        program.emit_column(
+            pseudo_cursor,
+            num_partition_keys + i,
+            reg_keys + num_partition_keys + i,
+        );
+    }
+    let label_new_peer_group = program.allocate_label();
+    let label_same_peer_group = program.allocate_label();
+    program.emit_insn(Insn::If {
+        reg: reg_new_peer_group,
+        target_pc: label_new_peer_group,
+        jump_if_null: false,
+    });
+    emit_keys_changed_check(
+        program,
+        reg_keys + num_partition_keys,
+        reg_prev_keys + num_partition_keys,
+        &collations[num_partition_keys..],
+        label_new_peer_group,
+    );
+    program.emit_insn(Insn::Goto {
+        target_pc: label_same_peer_group,
+    });
+    program.preassign_label_to_next_insn(label_new_peer_group);
+    program.emit_int(0, reg_new_peer_group);
+    if num_order_keys > 0 {
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_keys + num_partition_keys,
+            dst_reg: reg_prev_keys + num_partition_keys,
+            amount: num_order_keys - 1,
+        });
+    }
+    program.emit_insn(Insn::Add {
+        lhs: reg_rows_in_partition,
+        rhs: regs.one,
+        dest: reg_rank,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: reg_dense_rank,
+        rhs: regs.one,
+        dest: reg_dense_rank,
+    });
+    program.preassign_label_to_next_insn(label_same_peer_group);
+    program.emit_insn(Insn::Add {
+        lhs: reg_rows_in_partition,
+        rhs: regs.one,
+        dest: reg_rows_in_partition,
+    });
+
+    // Buffer the row after the previous ones.
+    for i in 0..num_subquery_columns {
+        program.emit_column(
+            pseudo_cursor,
+            num_partition_keys + num_order_keys + i,
+            reg_buffer_record_start + BUFFER_SUBQUERY_COLUMNS_START + i,
+        );
+    }
+    program.emit_insn(Insn::Add {
+        lhs: regs.end,
+        rhs: regs.one,
+        dest: regs.end,
+    });
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: reg_buffer_record_start,
+        count: BUFFER_SUBQUERY_COLUMNS_START + num_subquery_columns,
+        dest_reg: reg_buffer_record,
+        index_name: None,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: buffer_cursor,
+        key_reg: regs.end,
+        record_reg: reg_buffer_record,
+        flag: InsertFlags(0),
+        table_name: "window_buffer".to_string(),
+    });
+
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sort_cursor,
+        pc_if_next: label_loop_start,
+    });
+    // The last partition is complete once the sorter is exhausted.
+    program.emit_insn(Insn::Gosub {
+        target_pc: label_flush_partition,
+        return_reg: reg_flush_return,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_window_end,
+    });
+
+    program.preassign_label_to_next_insn(label_flush_partition);
+    emit_partition(program, t_ctx, plan, buffer_cursor, &regs, label_window_end)?;
+    program.emit_insn(Insn::Return {
+        return_reg: reg_flush_return,
+        can_fallthrough: false,
+    });
+    program.preassign_label_to_next_insn(label_window_end);
+    Ok(())
+}
+
+/// Emits the body of the subroutine that reads the rows of a complete partition back from the
+/// ephemeral table, computes their window functions and passes them on to the ORDER BY sorter or
+/// the result.
+fn emit_partition<'a>(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx<'a>,
+    plan: &'a SelectPlan,
+    buffer_cursor: CursorID,
+    regs: &PartitionRegisters,
+    label_window_end: BranchOffset,
+) -> Result<()> {
+    let window = plan.window.as_ref().expect("window must exist");
+    let subquery = window_subquery(&plan.table_references);
+    let reg_subquery_columns = subquery
+        .result_columns_start_reg
+        .expect("subquery result columns must be set");
+    let num_functions = window.functions.len();
+    let reg_results = program.alloc_registers(num_functions);
+    // State kept by each function across the rows of a partition, reset to NULL on each new one.
+    let reg_states = program.alloc_registers(num_functions);
+    let needs_peer_end = window.functions.iter().any(|f| f.func.uses_frame());
+
+    program.emit_insn(Insn::Subtract {
+        lhs: regs.end,
+        rhs: regs.start,
+        dest: regs.size,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: regs.size,
+        rhs: regs.one,
+        dest: regs.size,
+    });
+    program.emit_insn(Insn::Null {
+        dest: reg_states,
+        dest_end: Some(reg_states + num_functions - 1),
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: regs.start,
+        dst_reg: regs.current,
+        amount: 0,
+    });
+    if needs_peer_end {
+        program.emit_insn(Insn::Subtract {
+            lhs: regs.start,
+            rhs: regs.one,
+            dest: regs.peer_end,
+        });
+    }
+
+    let label_row_start = program.allocate_label();
+    let label_row_next = program.allocate_label();
+    program.preassign_label_to_next_insn(label_row_start);
+    if needs_peer_end {
+        emit_peer_end(program, buffer_cursor, regs);
+    }
+    program.emit_insn(Insn::SeekRowid {
+        cursor_id: buffer_cursor,
+        src_reg: regs.current,
+        target_pc: label_row_next,
+    });
+    program.emit_column(buffer_cursor, BUFFER_RANK_COLUMN, regs.rank);
+    program.emit_column(buffer_cursor, BUFFER_DENSE_RANK_COLUMN, regs.dense_rank);
+    // Restore the subquery columns, so that the rest of the row can be computed as usual.
+    for i in 0..subquery.columns.len() {
+        program.emit_column(
+            buffer_cursor,
+            BUFFER_SUBQUERY_COLUMNS_START + i,
+            reg_subquery_columns + i,
+        );
+    }
+    program.emit_insn(Insn::Subtract {
+        lhs: regs.current,
+        rhs: regs.start,
+        dest: regs.row_number,
+    });
+    program.emit_insn(Insn::Add {
+        lhs: regs.row_number,
+        rhs: regs.one,
+        dest: regs.row_number,
+    });
+
+    // The functions below may move the buffer cursor to other rows of the partition.
+    for (i, function) in window.functions.iter().enumerate() {
+        let reg_result = reg_results + i;
+        match function.func {
+            WindowFunc::RowNumber | WindowFunc::Rank | WindowFunc::DenseRank => {
+                let src_reg = match function.func {
+                    WindowFunc::RowNumber => regs.row_number,
+                    WindowFunc::Rank => regs.rank,
+                    _ => regs.dense_rank,
+                };
+                program.emit_insn(Insn::Copy {
+                    src_reg,
+                    dst_reg: reg_result,
+                    amount: 0,
+                });
+            }
+            WindowFunc::Ntile => emit_ntile(
+                program,
+                t_ctx,
+                plan,
+                function,
+                regs,
+                reg_states + i,
+                reg_result,
+            )?,
+            WindowFunc::Lag | WindowFunc::Lead => emit_lag_lead(
+                program,
+                t_ctx,
+                plan,
+                function,
+                buffer_cursor,
+                regs,
+                reg_result,
+            )?,
+            WindowFunc::FirstValue | WindowFunc::LastValue | WindowFunc::NthValue => {
+                emit_frame_value(
+                    program,
+                    t_ctx,
+                    plan,
+                    function,
+                    buffer_cursor,
+                    regs,
+                    reg_result,
+                )?
+            }
+        }
+    }
+    for (i, function) in window.functions.iter().enumerate() {
+        t_ctx
+            .resolver
+            .expr_to_reg_cache
+            .push((&function.original_expr, reg_results + i));
+    }
+    t_ctx.resolver.enable_expr_to_reg_cache();
+
+    match &plan.order_by {
+        Some(_) => {
+            order_by_sorter_insert(
+                program,
+                &t_ctx.resolver,
+                t_ctx
+                    .meta_sort
+                    .as_ref()
+                    .expect("sort metadata must exist for ORDER BY"),
+                &mut t_ctx.result_column_indexes_in_orderby_sorter,
+                plan,
+            )?;
+        }
+        None => {
+            emit_select_result(
+                program,
+                &t_ctx.resolver,
+                plan,
+                Some(label_window_end),
+                Some(label_row_next),
+                None,
+                t_ctx.reg_offset,
+                t_ctx.reg_result_cols_start.unwrap(),
+                t_ctx.limit_ctx,
+            )?;
+        }
+    }
+    if let Distinctness::Distinct { ctx } = &plan.distinctness {
+        let distinct_ctx = ctx.as_ref().expect("distinct context must exist");
+        program.preassign_label_to_next_insn(distinct_ctx.label_on_conflict);
+    }
+
+    program.preassign_label_to_next_insn(label_row_next);
+    program.emit_insn(Insn::Add {
+        lhs: regs.current,
+        rhs: regs.one,
+        dest: regs.current,
+    });
+    program.emit_insn(Insn::Le {
+        lhs: regs.current,
+        rhs: regs.end,
+        target_pc: label_row_start,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    Ok(())
+}
@@ -11,6 +11,7 @@ use crate::storage::btree::BTreeCursor;
 use crate::storage::sqlite3_ondisk::write_varint;
 use crate::translate::collate::CollationSeq;
 use crate::translate::plan::IterationDirection;
+use crate::vdbe::hash_table::HashTable;
 use crate::vdbe::sorter::Sorter;
 use crate::vdbe::Register;
 use crate::vtab::VirtualTableCursor;
@@ -905,16 +906,18 @@ impl ImmutableRecord {
             // header size here will be 126 == (2^7 - 1)
             header_size += 1;
         } else {
-            todo!("calculate big header size extra bytes");
-            // get header varint len
-            // header_size += n;
-            // if( nVarint<sqlite3VarintLen(nHdr) ) nHdr++;
+            // The header size counts the bytes of its own varint, which can in turn need one
+            // more byte once they are added.
+            let n = write_varint(&mut serial_type_buf, header_size as u64);
+            header_size += n;
+            if n < write_varint(&mut serial_type_buf, header_size as u64) {
+                header_size += 1;
+            }
         }
         // 1. write header size
         let mut buf = Vec::new();
         buf.reserve_exact(header_size + size_values);
         assert_eq!(buf.capacity(), header_size + size_values);
-        assert!(header_size <= 126);
         let n = write_varint(&mut serial_type_buf, header_size as u64);
 
         buf.resize(buf.capacity(), 0);
@@ -1473,12 +1476,13 @@ impl Record {
             // common case
             header_size += 1;
         } else {
-            todo!("calculate big header size extra bytes");
-            // get header varint len
-            // header_size += n;
-            // if( nVarint<sqlite3VarintLen(nHdr) ) nHdr++;
+            let mut varint_buf = [0; 9];
+            let n = write_varint(&mut varint_buf, header_size as u64);
+            header_size += n;
+            if n < write_varint(&mut varint_buf, header_size as u64) {
+                header_size += 1;
+            }
         }
-        assert!(header_size <= 126);
         header_bytes_buf.extend(std::iter::repeat(0).take(9));
         let n = write_varint(header_bytes_buf.as_mut_slice(), header_size as u64);
         header_bytes_buf.truncate(n);
@@ -1491,6 +1495,7 @@ pub enum Cursor {
     Pseudo(PseudoCursor),
     Sorter(Sorter),
     Virtual(VirtualTableCursor),
+    Hash(HashTable),
 }
 
 impl Cursor {
@@ -1533,6 +1538,13 @@ impl Cursor {
             _ => panic!("Cursor is not a virtual cursor"),
         }
     }
+
+    pub fn as_hash_mut(&mut self) -> &mut HashTable {
+        match self {
+            Self::Hash(cursor) => cursor,
+            _ => panic!("Cursor is not a hash table cursor"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -1749,6 +1761,21 @@ mod tests {
         assert_eq!(buf[2], 9); // ConstInt1 serial type
     }
 
+    #[test]
+    fn test_serialize_wide_header() {
+        // 200 serial types don't fit in a header whose size is a single byte varint.
+        let record = Record::new(vec![Value::Integer(0); 200]);
+        let mut buf = Vec::new();
+        record.serialize(&mut buf);
+
+        // [header_size (2 bytes), 200 serial types] + no payload bytes
+        assert_eq!(buf.len(), 202);
+        let (header_size, n) = crate::storage::sqlite3_ondisk::read_varint(&buf).unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(header_size, 202);
+        assert!(buf[2..].iter().all(|serial_type| *serial_type == 8));
+    }
+
     #[test]
     fn test_serialize_single_const_int0() {
         let record = Record::new(vec![Value::Integer(0)]);
@@ -22,19 +22,33 @@ impl RoundToPrecision for f64 {
 }
 
 // https://sqlite.org/lang_keywords.html
-const QUOTE_PAIRS: &[(char, char)] = &[('"', '"'), ('[', ']'), ('`', '`')];
+const QUOTE_PAIRS: &[(char, char)] = &[('"', '"'), ('[', ']'), ('`', '`'), ('\'', '\'')];
+
+/// Strips the quotes from an identifier, preserving its case. A quote
+/// character inside a quoted identifier is escaped by doubling it, except
+/// for [bracket] quoting which has no escape.
+pub fn unquote_ident(identifier: &str) -> String {
+    let quote_pair = QUOTE_PAIRS.iter().find(|&&(start, end)| {
+        identifier.len() >= 2 && identifier.starts_with(start) && identifier.ends_with(end)
+    });
+
+    match quote_pair {
+        Some(&('[', _)) => identifier[1..identifier.len() - 1].to_string(),
+        Some(&(quote, _)) => {
+            let quote = quote.to_string();
+            identifier[1..identifier.len() - 1].replace(&quote.repeat(2), &quote)
+        }
+        None => identifier.to_string(),
+    }
+}
 
 pub fn normalize_ident(identifier: &str) -> String {
-    let quote_pair = QUOTE_PAIRS
-        .iter()
-        .find(|&(start, end)| identifier.starts_with(*start) && identifier.ends_with(*end));
+    unquote_ident(identifier).to_lowercase()
+}
 
-    if let Some(&(_, _)) = quote_pair {
-        &identifier[1..identifier.len() - 1]
-    } else {
-        identifier
-    }
-    .to_lowercase()
+/// Escapes `s` for use inside a single-quoted SQL string literal.
+pub fn escape_string_literal(s: &str) -> String {
+    s.replace('\'', "''")
 }
 
 pub const PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX: &str = "sqlite_autoindex_";
@@ -170,6 +184,43 @@ pub fn parse_schema_rows(
     Ok(())
 }
 
+/// Loads the rows of `sqlite_stat1`, the statistics written by ANALYZE, into `schema`.
+pub fn parse_stat1_rows(
+    rows: Option<Statement>,
+    schema: &mut Schema,
+    io: Arc<dyn IO>,
+    mv_tx_id: Option<u64>,
+) -> Result<()> {
+    let Some(mut rows) = rows else {
+        return Ok(());
+    };
+    rows.set_mv_tx_id(mv_tx_id);
+    let mut stats = Vec::new();
+    loop {
+        match rows.step()? {
+            StepResult::Row => {
+                let row = rows.row().unwrap();
+                let text = |idx: usize| match row.get_value(idx) {
+                    Value::Null => None,
+                    value => Some(value.to_string()),
+                };
+                let (Some(table_name), index_name, Some(stat)) = (text(0), text(1), text(2)) else {
+                    continue;
+                };
+                stats.push((table_name, index_name, stat));
+            }
+            StepResult::IO => {
+                io.run_once()?;
+            }
+            StepResult::Interrupt => break,
+            StepResult::Done => break,
+            StepResult::Busy => break,
+        }
+    }
+    schema.set_stats(stats);
+    Ok(())
+}
+
 fn cmp_numeric_strings(num_str: &str, other: &str) -> bool {
     match (num_str.parse::<f64>(), other.parse::<f64>()) {
         (Ok(num), Ok(other)) => num == other,
@@ -178,15 +229,7 @@ fn cmp_numeric_strings(num_str: &str, other: &str) -> bool {
 }
 
 pub fn check_ident_equivalency(ident1: &str, ident2: &str) -> bool {
-    fn strip_quotes(identifier: &str) -> &str {
-        for &(start, end) in QUOTE_PAIRS {
-            if identifier.starts_with(start) && identifier.ends_with(end) {
-                return &identifier[1..identifier.len() - 1];
-            }
-        }
-        identifier
-    }
-    strip_quotes(ident1).eq_ignore_ascii_case(strip_quotes(ident2))
+    unquote_ident(ident1).eq_ignore_ascii_case(&unquote_ident(ident2))
 }
 
 fn module_name_from_sql(sql: &str) -> Result<&str> {
@@ -574,6 +617,8 @@ pub fn columns_from_create_table_body(body: &ast::CreateTableBody) -> crate::Res
                             )),
                             _ => None,
                         }),
+                    generated_expr: None,
+                    generated_stored: false,
                 };
             Some(column)
         })
@@ -629,6 +674,11 @@ pub struct OpenOptions<'a> {
     pub cache: CacheMode,
     /// immutable=1|0 specifies that the database is stored on read-only media
     pub immutable: bool,
+    /// Journal mode requested with `_journal_mode`. Only `wal` is supported.
+    pub journal_mode: Option<String>,
+    /// Key handed to the VFS when it opens the database files, e.g. the passphrase of the
+    /// `encrypted` VFS.
+    pub key: Option<String>,
 }
 
 pub const MEMORY_PATH: &str = ":memory:";
@@ -762,11 +812,15 @@ impl<'a> OpenOptions<'a> {
             ));
         }
         // If modeof is not applicable or file doesn't exist, use default flags
-        Ok(match self.mode {
+        let mut flags = match self.mode {
             OpenMode::ReadWriteCreate => OpenFlags::Create,
             OpenMode::ReadOnly => OpenFlags::ReadOnly,
             _ => OpenFlags::default(),
-        })
+        };
+        if self.cache == CacheMode::Shared {
+            flags |= OpenFlags::SharedCache;
+        }
+        Ok(flags)
     }
 }
 
@@ -781,6 +835,17 @@ fn parse_query_params(query: &str, opts: &mut OpenOptions) -> Result<()> {
                 "cache" => opts.cache = decoded_value.as_str().into(),
                 "immutable" => opts.immutable = decoded_value == "1",
                 "vfs" => opts.vfs = Some(decoded_value),
+                "key" => opts.key = Some(decoded_value),
+                "_journal_mode" => {
+                    let journal_mode = decoded_value.to_lowercase();
+                    if journal_mode != "wal" {
+                        return Err(LimboError::InvalidArgument(format!(
+                            "Unsupported journal mode: '{}'. Only 'wal' is supported",
+                            decoded_value
+                        )));
+                    }
+                    opts.journal_mode = Some(journal_mode);
+                }
                 _ => {}
             }
         }
@@ -788,6 +853,20 @@ fn parse_query_params(query: &str, opts: &mut OpenOptions) -> Result<()> {
     Ok(())
 }
 
+/// CPU time the calling thread has used so far.
+#[cfg(target_family = "unix")]
+pub(crate) fn thread_cpu_time() -> std::time::Duration {
+    let time = rustix::time::clock_gettime(rustix::time::ClockId::ThreadCPUTime);
+    std::time::Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// Wall-clock time since the first call, for platforms without a per-thread CPU clock.
+#[cfg(not(target_family = "unix"))]
+pub(crate) fn thread_cpu_time() -> std::time::Duration {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed()
+}
+
 /// Decodes percent-encoded characters
 /// this function was adapted from the 'urlencoding' crate. MIT
 pub fn decode_percent(uri: &str) -> String {
@@ -930,6 +1009,17 @@ pub fn checked_cast_text_to_numeric(text: &str) -> std::result::Result<Value, ()
     }
 }
 
+/// Like [checked_cast_text_to_numeric], but fails unless the whole text, ignoring surrounding
+/// whitespace, is a number. This is when SQLite applies numeric affinity to a text value:
+/// '12' becomes 12 while '12abc' stays text.
+pub fn checked_cast_text_to_numeric_exact(text: &str) -> std::result::Result<Value, ()> {
+    let (_, numeric) = parse_numeric_str(text)?;
+    if numeric.len() != text.trim().len() {
+        return Err(());
+    }
+    checked_cast_text_to_numeric(text)
+}
+
 fn parse_numeric_str(text: &str) -> Result<(ValueType, &str), ()> {
     let text = text.trim();
     let bytes = text.as_bytes();
@@ -1044,6 +1134,31 @@ pub fn parse_signed_number(expr: &Expr) -> Result<Value> {
     }
 }
 
+/// Parses a boolean pragma value the way SQLite does: `ON`/`OFF`, `TRUE`/`FALSE`,
+/// `YES`/`NO` (case-insensitive) or an integer, where any non-zero integer is true.
+pub fn parse_pragma_bool(expr: &Expr) -> Result<bool> {
+    let name = match expr {
+        Expr::Name(name) => normalize_ident(&name.0),
+        Expr::Literal(Literal::Keyword(keyword)) => keyword.to_lowercase(),
+        Expr::Literal(Literal::String(s)) => normalize_ident(s),
+        _ => {
+            return match parse_signed_number(expr)? {
+                Value::Integer(i) => Ok(i != 0),
+                Value::Float(f) => Ok(f != 0.0),
+                _ => unreachable!(),
+            }
+        }
+    };
+    match name.as_str() {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => Err(LimboError::InvalidArgument(format!(
+            "invalid boolean pragma value: {}",
+            name
+        ))),
+    }
+}
+
 // for TVF's we need these at planning time so we cannot emit translate_expr
 pub fn vtable_args(args: &[ast::Expr]) -> Vec<turso_ext::Value> {
     let mut vtable_args = Vec::new();
@@ -1084,6 +1199,29 @@ pub mod tests {
         assert_eq!(normalize_ident("`foo`"), "foo");
         assert_eq!(normalize_ident("[foo]"), "foo");
         assert_eq!(normalize_ident("\"foo\""), "foo");
+        assert_eq!(normalize_ident("'foo'"), "foo");
+        assert_eq!(normalize_ident("\"Order\""), "order");
+    }
+
+    #[test]
+    fn test_unquote_ident() {
+        assert_eq!(unquote_ident("Foo"), "Foo");
+        assert_eq!(unquote_ident("\"Group\""), "Group");
+        assert_eq!(unquote_ident("[select]"), "select");
+        assert_eq!(unquote_ident("`from`"), "from");
+        assert_eq!(unquote_ident("\"a\"\"b\""), "a\"b");
+        assert_eq!(unquote_ident("`a``b`"), "a`b");
+        assert_eq!(unquote_ident("[a\"\"b]"), "a\"\"b");
+        assert_eq!(unquote_ident("'it''s'"), "it's");
+        assert_eq!(unquote_ident("\""), "\"");
+    }
+
+    #[test]
+    fn test_check_ident_equivalency() {
+        assert!(check_ident_equivalency("\"order\"", "[ORDER]"));
+        assert!(check_ident_equivalency("`group`", "Group"));
+        assert!(check_ident_equivalency("\"a\"\"b\"", "`a\"b`"));
+        assert!(!check_ident_equivalency("\"order\"", "orders"));
     }
 
     #[test]
@@ -1535,6 +1673,32 @@ pub mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_uri_shared_cache_flags() {
+        let opts = OpenOptions::parse("file:data.db?mode=ro&cache=shared").unwrap();
+        assert_eq!(opts.cache, CacheMode::Shared);
+        assert_eq!(
+            opts.get_flags().unwrap(),
+            OpenFlags::ReadOnly | OpenFlags::SharedCache
+        );
+        let opts = OpenOptions::parse("file:data.db?cache=private").unwrap();
+        assert_eq!(opts.get_flags().unwrap(), OpenFlags::Create);
+    }
+
+    #[test]
+    fn test_uri_key() {
+        let opts = OpenOptions::parse("file:data.db?key=s%26cret&vfs=encrypted").unwrap();
+        assert_eq!(opts.path, "data.db");
+        assert_eq!(opts.key, Some("s&cret".to_string()));
+    }
+
+    #[test]
+    fn test_uri_journal_mode() {
+        let opts = OpenOptions::parse("file:data.db?_journal_mode=WAL").unwrap();
+        assert_eq!(opts.journal_mode, Some("wal".to_string()));
+        assert!(OpenOptions::parse("file:data.db?_journal_mode=delete").is_err());
+    }
+
     // Some examples from https://www.sqlite.org/c3ref/open.html#urifilenameexamples
     #[test]
     fn test_simple_file_current_dir() {
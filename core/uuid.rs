@@ -6,6 +6,7 @@ pub fn register_extension(ext_api: &mut ExtensionApi) {
     unsafe {
         register_scalar_function(ext_api.ctx, c"uuid4_str".as_ptr(), uuid4_str);
         register_scalar_function(ext_api.ctx, c"gen_random_uuid".as_ptr(), uuid4_str);
+        register_scalar_function(ext_api.ctx, c"uuid".as_ptr(), uuid4_str);
         register_scalar_function(ext_api.ctx, c"uuid4".as_ptr(), uuid4_blob);
         register_scalar_function(ext_api.ctx, c"uuid7_str".as_ptr(), uuid7_str);
         register_scalar_function(ext_api.ctx, c"uuid7".as_ptr(), uuid7);
@@ -110,28 +111,35 @@ fn uuid7_ts(args: &[Value]) -> Value {
     }
 }
 
+/// Parses a UUID from a 16-byte blob, or from text in any of the formats accepted by
+/// [uuid::Uuid::parse_str] (hyphenated, simple, braced or URN).
+fn parse_uuid(value: &Value) -> Option<uuid::Uuid> {
+    match value.value_type() {
+        ValueType::Blob => uuid::Uuid::from_slice(value.to_blob()?.as_slice()).ok(),
+        ValueType::Text => uuid::Uuid::parse_str(value.to_text()?).ok(),
+        _ => None,
+    }
+}
+
 #[scalar(name = "uuid_str")]
 fn uuid_str(args: &[Value]) -> Value {
-    let Some(blob) = args[0].to_blob() else {
-        return Value::null();
+    let Some(arg) = args.first() else {
+        return Value::from_text(uuid::Uuid::new_v4().to_string());
     };
-    let parsed = uuid::Uuid::from_slice(blob.as_slice())
-        .ok()
-        .map(|u| u.to_string());
-    match parsed {
-        Some(s) => Value::from_text(s),
+    match parse_uuid(arg) {
+        Some(uuid) => Value::from_text(uuid.to_string()),
         None => Value::null(),
     }
 }
 
 #[scalar(name = "uuid_blob")]
 fn uuid_blob(&self, args: &[Value]) -> Value {
-    let Some(text) = args[0].to_text() else {
-        return Value::null();
+    let Some(arg) = args.first() else {
+        return Value::from_blob(uuid::Uuid::new_v4().as_bytes().to_vec());
     };
-    match uuid::Uuid::parse_str(text) {
-        Ok(uuid) => Value::from_blob(uuid.as_bytes().to_vec()),
-        Err(_) => Value::null(),
+    match parse_uuid(arg) {
+        Some(uuid) => Value::from_blob(uuid.as_bytes().to_vec()),
+        None => Value::null(),
     }
 }
 
@@ -12,6 +12,7 @@ use crate::{
         emitter::TransactionMode,
         plan::{ResultSetColumn, TableReferences},
     },
+    util::exprs_are_equivalent,
     Connection, Value, VirtualTable,
 };
 
@@ -34,7 +35,10 @@ impl TableRefIdCounter {
     }
 }
 
-use super::{BranchOffset, CursorID, Insn, InsnFunction, InsnReference, JumpTarget, Program};
+use super::{
+    optimizer, BranchOffset, CursorID, Insn, InsnFunction, InsnReference, JumpTarget, Program,
+    ResultColumnMetadata,
+};
 
 /// A key that uniquely identifies a cursor.
 /// The key is a pair of table reference id and index.
@@ -79,6 +83,29 @@ impl CursorKey {
     }
 }
 
+/// An expression that occurs more than once in a query, and is therefore compiled once as a
+/// subroutine that every occurrence calls with `Gosub`.
+#[derive(Debug)]
+pub struct ExprSubroutine {
+    pub expr: ast::Expr,
+    pub state: ExprSubroutineState,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExprSubroutineState {
+    /// No occurrence of the expression has been translated yet.
+    NotEmitted,
+    /// The body of the subroutine is being translated.
+    Emitting,
+    /// The body has been emitted; calling it stores the value of the expression in `reg_result`.
+    Emitted {
+        label_start: BranchOffset,
+        reg_return: usize,
+        reg_result: usize,
+        collation: Option<(CollationSeq, bool)>,
+    },
+}
+
 #[allow(dead_code)]
 pub struct ProgramBuilder {
     pub table_reference_counter: TableRefIdCounter,
@@ -108,8 +135,11 @@ pub struct ProgramBuilder {
     collation: Option<(CollationSeq, bool)>,
     /// Current parsing nesting level
     nested_level: usize,
+    /// Repeated expressions of the query being translated that are compiled as subroutines.
+    expr_subroutines: Vec<ExprSubroutine>,
     init_label: BranchOffset,
     start_offset: BranchOffset,
+    query_mode: QueryMode,
 }
 
 #[derive(Debug, Clone)]
@@ -119,6 +149,8 @@ pub enum CursorType {
     Pseudo(PseudoCursorType),
     Sorter,
     VirtualTable(Rc<VirtualTable>),
+    /// The hash table of a hash join. The index describes the layout of its rows.
+    HashTable(Arc<Index>),
 }
 
 impl CursorType {
@@ -131,13 +163,15 @@ impl CursorType {
 pub enum QueryMode {
     Normal,
     Explain,
+    ExplainQueryPlan,
 }
 
-impl From<ast::Cmd> for QueryMode {
-    fn from(stmt: ast::Cmd) -> Self {
+impl From<&ast::Cmd> for QueryMode {
+    fn from(stmt: &ast::Cmd) -> Self {
         match stmt {
-            ast::Cmd::ExplainQueryPlan(_) | ast::Cmd::Explain(_) => QueryMode::Explain,
-            _ => QueryMode::Normal,
+            ast::Cmd::Explain(_) => QueryMode::Explain,
+            ast::Cmd::ExplainQueryPlan(_) => QueryMode::ExplainQueryPlan,
+            ast::Cmd::Stmt(_) => QueryMode::Normal,
         }
     }
 }
@@ -169,9 +203,11 @@ impl ProgramBuilder {
             table_references: TableReferences::new(vec![], vec![]),
             collation: None,
             nested_level: 0,
+            expr_subroutines: Vec::new(),
             // These labels will be filled when `prologue()` is called
             init_label: BranchOffset::Placeholder,
             start_offset: BranchOffset::Placeholder,
+            query_mode,
         }
     }
 
@@ -345,10 +381,6 @@ impl ProgramBuilder {
         });
     }
 
-    // no users yet, but I want to avoid someone else in the future
-    // just adding parameters to emit_halt! If you use this, remove the
-    // clippy warning please.
-    #[allow(dead_code)]
     pub fn emit_halt_err(&mut self, err_code: usize, description: String) {
         self.emit_insn(Insn::Halt {
             err_code,
@@ -498,197 +530,7 @@ impl ProgramBuilder {
             }
         };
         for (insn, _, _) in self.insns.iter_mut() {
-            match insn {
-                Insn::Init { target_pc } => {
-                    resolve(target_pc, "Init");
-                }
-                Insn::Eq {
-                    lhs: _lhs,
-                    rhs: _rhs,
-                    target_pc,
-                    ..
-                } => {
-                    resolve(target_pc, "Eq");
-                }
-                Insn::Ne {
-                    lhs: _lhs,
-                    rhs: _rhs,
-                    target_pc,
-                    ..
-                } => {
-                    resolve(target_pc, "Ne");
-                }
-                Insn::Lt {
-                    lhs: _lhs,
-                    rhs: _rhs,
-                    target_pc,
-                    ..
-                } => {
-                    resolve(target_pc, "Lt");
-                }
-                Insn::Le {
-                    lhs: _lhs,
-                    rhs: _rhs,
-                    target_pc,
-                    ..
-                } => {
-                    resolve(target_pc, "Le");
-                }
-                Insn::Gt {
-                    lhs: _lhs,
-                    rhs: _rhs,
-                    target_pc,
-                    ..
-                } => {
-                    resolve(target_pc, "Gt");
-                }
-                Insn::Ge {
-                    lhs: _lhs,
-                    rhs: _rhs,
-                    target_pc,
-                    ..
-                } => {
-                    resolve(target_pc, "Ge");
-                }
-                Insn::If {
-                    reg: _reg,
-                    target_pc,
-                    jump_if_null: _,
-                } => {
-                    resolve(target_pc, "If");
-                }
-                Insn::IfNot {
-                    reg: _reg,
-                    target_pc,
-                    jump_if_null: _,
-                } => {
-                    resolve(target_pc, "IfNot");
-                }
-                Insn::Rewind { pc_if_empty, .. } => {
-                    resolve(pc_if_empty, "Rewind");
-                }
-                Insn::Last { pc_if_empty, .. } => {
-                    resolve(pc_if_empty, "Last");
-                }
-                Insn::Goto { target_pc } => {
-                    resolve(target_pc, "Goto");
-                }
-                Insn::DecrJumpZero {
-                    reg: _reg,
-                    target_pc,
-                } => {
-                    resolve(target_pc, "DecrJumpZero");
-                }
-                Insn::SorterNext {
-                    cursor_id: _cursor_id,
-                    pc_if_next,
-                } => {
-                    resolve(pc_if_next, "SorterNext");
-                }
-                Insn::SorterSort { pc_if_empty, .. } => {
-                    resolve(pc_if_empty, "SorterSort");
-                }
-                Insn::NotNull {
-                    reg: _reg,
-                    target_pc,
-                } => {
-                    resolve(target_pc, "NotNull");
-                }
-                Insn::IfPos { target_pc, .. } => {
-                    resolve(target_pc, "IfPos");
-                }
-                Insn::Next { pc_if_next, .. } => {
-                    resolve(pc_if_next, "Next");
-                }
-                Insn::Once {
-                    target_pc_when_reentered,
-                    ..
-                } => {
-                    resolve(target_pc_when_reentered, "Once");
-                }
-                Insn::Prev { pc_if_prev, .. } => {
-                    resolve(pc_if_prev, "Prev");
-                }
-                Insn::InitCoroutine {
-                    yield_reg: _,
-                    jump_on_definition,
-                    start_offset,
-                } => {
-                    resolve(jump_on_definition, "InitCoroutine");
-                    resolve(start_offset, "InitCoroutine");
-                }
-                Insn::NotExists {
-                    cursor: _,
-                    rowid_reg: _,
-                    target_pc,
-                } => {
-                    resolve(target_pc, "NotExists");
-                }
-                Insn::Yield {
-                    yield_reg: _,
-                    end_offset,
-                } => {
-                    resolve(end_offset, "Yield");
-                }
-                Insn::SeekRowid { target_pc, .. } => {
-                    resolve(target_pc, "SeekRowid");
-                }
-                Insn::Gosub { target_pc, .. } => {
-                    resolve(target_pc, "Gosub");
-                }
-                Insn::Jump {
-                    target_pc_eq,
-                    target_pc_lt,
-                    target_pc_gt,
-                } => {
-                    resolve(target_pc_eq, "Jump");
-                    resolve(target_pc_lt, "Jump");
-                    resolve(target_pc_gt, "Jump");
-                }
-                Insn::SeekGE { target_pc, .. } => {
-                    resolve(target_pc, "SeekGE");
-                }
-                Insn::SeekGT { target_pc, .. } => {
-                    resolve(target_pc, "SeekGT");
-                }
-                Insn::SeekLE { target_pc, .. } => {
-                    resolve(target_pc, "SeekLE");
-                }
-                Insn::SeekLT { target_pc, .. } => {
-                    resolve(target_pc, "SeekLT");
-                }
-                Insn::IdxGE { target_pc, .. } => {
-                    resolve(target_pc, "IdxGE");
-                }
-                Insn::IdxLE { target_pc, .. } => {
-                    resolve(target_pc, "IdxLE");
-                }
-                Insn::IdxGT { target_pc, .. } => {
-                    resolve(target_pc, "IdxGT");
-                }
-                Insn::IdxLT { target_pc, .. } => {
-                    resolve(target_pc, "IdxLT");
-                }
-                Insn::IsNull { reg: _, target_pc } => {
-                    resolve(target_pc, "IsNull");
-                }
-                Insn::VNext { pc_if_next, .. } => {
-                    resolve(pc_if_next, "VNext");
-                }
-                Insn::VFilter { pc_if_empty, .. } => {
-                    resolve(pc_if_empty, "VFilter");
-                }
-                Insn::NoConflict { target_pc, .. } => {
-                    resolve(target_pc, "NoConflict");
-                }
-                Insn::Found { target_pc, .. } => {
-                    resolve(target_pc, "Found");
-                }
-                Insn::NotFound { target_pc, .. } => {
-                    resolve(target_pc, "NotFound");
-                }
-                _ => {}
-            }
+            insn.for_each_jump_target(|pc, insn_name| resolve(pc, insn_name));
         }
         self.label_to_resolved_offset.clear();
     }
@@ -705,6 +547,41 @@ impl ProgramBuilder {
             .unwrap_or_else(|| panic!("Cursor not found: {:?}", key))
     }
 
+    /// Compile each of `exprs` as a subroutine the first time it is translated, and call that
+    /// subroutine wherever an equivalent expression is translated afterwards.
+    pub fn set_expr_subroutines(&mut self, exprs: Vec<ast::Expr>) {
+        self.expr_subroutines = exprs
+            .into_iter()
+            .map(|expr| ExprSubroutine {
+                expr,
+                state: ExprSubroutineState::NotEmitted,
+            })
+            .collect();
+    }
+
+    /// Stop compiling expressions as subroutines, e.g. once the expressions might be
+    /// translated in a context where they read from different cursors or registers.
+    pub fn clear_expr_subroutines(&mut self) {
+        self.expr_subroutines.clear();
+    }
+
+    /// Returns the index of the subroutine for `expr`, unless there is none or its body is
+    /// the expression currently being translated.
+    pub fn resolve_expr_subroutine(&self, expr: &ast::Expr) -> Option<usize> {
+        self.expr_subroutines.iter().position(|subroutine| {
+            !matches!(subroutine.state, ExprSubroutineState::Emitting)
+                && exprs_are_equivalent(&subroutine.expr, expr)
+        })
+    }
+
+    pub fn expr_subroutine_state(&self, idx: usize) -> ExprSubroutineState {
+        self.expr_subroutines[idx].state
+    }
+
+    pub fn set_expr_subroutine_state(&mut self, idx: usize, state: ExprSubroutineState) {
+        self.expr_subroutines[idx].state = state;
+    }
+
     pub fn set_collation(&mut self, c: Option<(CollationSeq, bool)>) {
         self.collation = c
     }
@@ -761,8 +638,14 @@ impl ProgramBuilder {
             self.preassign_label_to_next_insn(self.init_label);
 
             match txn_mode {
-                TransactionMode::Read => self.emit_insn(Insn::Transaction { write: false }),
-                TransactionMode::Write => self.emit_insn(Insn::Transaction { write: true }),
+                TransactionMode::Read => self.emit_insn(Insn::Transaction {
+                    write: false,
+                    exclusive: false,
+                }),
+                TransactionMode::Write => self.emit_insn(Insn::Transaction {
+                    write: true,
+                    exclusive: false,
+                }),
                 TransactionMode::None => {}
             }
 
@@ -813,6 +696,20 @@ impl ProgramBuilder {
     pub fn emit_column(&mut self, cursor_id: CursorID, column: usize, out: usize) {
         let (_, cursor_type) = self.cursor_ref.get(cursor_id).unwrap();
 
+        // VIRTUAL generated columns are not part of the record, so the columns after them are
+        // shifted. Their value is computed from the other columns by the caller instead.
+        let record_pos = match cursor_type {
+            CursorType::BTreeTable(btree) => btree.column_record_pos(column),
+            _ => Some(column),
+        };
+        let Some(record_pos) = record_pos else {
+            self.emit_insn(Insn::Null {
+                dest: out,
+                dest_end: None,
+            });
+            return;
+        };
+
         use crate::translate::expr::sanitize_string;
 
         let default = 'value: {
@@ -852,7 +749,7 @@ impl ProgramBuilder {
 
         self.emit_insn(Insn::Column {
             cursor_id,
-            column,
+            column: record_pos,
             dest: out,
             default,
         });
@@ -862,21 +759,42 @@ impl ProgramBuilder {
         self.resolve_labels();
 
         self.parameters.list.dedup();
+        let is_readonly = !change_cnt_on && !self.insns.iter().any(|(insn, ..)| insn.is_write());
+        let result_column_metadata = self
+            .result_columns
+            .iter()
+            .map(|column| ResultColumnMetadata {
+                name: column
+                    .name(&self.table_references)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| column.expr.to_string()),
+                declared_type: column
+                    .declared_type(&self.table_references)
+                    .map(str::to_string),
+            })
+            .collect();
+        let mut insns = self
+            .insns
+            .into_iter()
+            .map(|(insn, function, _)| (insn, function))
+            .collect();
+        let mut comments = self.comments;
+        optimizer::remove_dead_stores(&mut insns, &mut comments);
+        let max_registers = optimizer::allocate_registers(&mut insns, self.next_free_register);
         Program {
-            max_registers: self.next_free_register,
-            insns: self
-                .insns
-                .into_iter()
-                .map(|(insn, function, _)| (insn, function))
-                .collect(),
+            max_registers,
+            insns,
             cursor_ref: self.cursor_ref,
-            comments: self.comments,
+            comments,
             connection,
             parameters: self.parameters,
             n_change: Cell::new(0),
             change_cnt_on,
+            is_readonly,
             result_columns: self.result_columns,
+            result_column_metadata,
             table_references: self.table_references,
+            query_mode: self.query_mode,
         }
     }
 }
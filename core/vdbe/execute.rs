@@ -3,16 +3,18 @@ use crate::function::AlterTableFunc;
 use crate::numeric::{NullableInteger, Numeric};
 use crate::storage::btree::{integrity_check, IntegrityCheckError, IntegrityCheckState};
 use crate::storage::database::FileMemoryStorage;
-use crate::storage::page_cache::DumbLruPageCache;
+use crate::storage::page_cache::PageCache;
 use crate::storage::pager::CreateBTreeFlags;
 use crate::storage::wal::DummyWAL;
 use crate::storage::{self, header_accessor};
 use crate::translate::collate::CollationSeq;
-use crate::types::{ImmutableRecord, Text};
-use crate::util::normalize_ident;
+use crate::translate::schema::SQLITE_STAT1_TABLE;
+use crate::types::{ImmutableRecord, Text, TextSubtype};
+use crate::util::{normalize_ident, unquote_ident};
 use crate::{
     error::{
-        LimboError, SQLITE_CONSTRAINT, SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY,
+        LimboError, SQLITE_CONSTRAINT, SQLITE_CONSTRAINT_CHECK, SQLITE_CONSTRAINT_FOREIGNKEY,
+        SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY, SQLITE_ERROR,
     },
     ext::ExtValue,
     function::{AggFunc, ExtFunc, MathFunc, MathFuncArity, ScalarFunc, VectorFunc},
@@ -42,17 +44,19 @@ use crate::{
     },
     util::{
         cast_real_to_integer, cast_text_to_integer, cast_text_to_numeric, cast_text_to_real,
-        checked_cast_text_to_numeric, parse_schema_rows, RoundToPrecision,
+        checked_cast_text_to_numeric, checked_cast_text_to_numeric_exact, parse_schema_rows,
+        parse_stat1_rows, RoundToPrecision,
     },
     vdbe::{
         builder::CursorType,
-        insn::{IdxInsertFlags, Insn},
+        insn::{IdxInsertFlags, InsertFlags, Insn},
     },
     vector::{vector32, vector64, vector_distance_cos, vector_extract},
 };
 
 use crate::{
-    info, BufferPool, MvCursor, OpenFlags, RefValue, Row, StepResult, TransactionState, IO,
+    info, BufferPool, MvCursor, OpenFlags, RefValue, Row, StepResult, TransactionState, UpdateType,
+    IO,
 };
 
 use super::{
@@ -64,9 +68,13 @@ use parking_lot::RwLock;
 use rand::thread_rng;
 use turso_sqlite3_parser::ast;
 use turso_sqlite3_parser::ast::fmt::ToTokens;
+use turso_sqlite3_parser::dialect::quote_identifier;
 use turso_sqlite3_parser::lexer::sql::Parser;
 
+#[cfg(feature = "regexp")]
+use super::likeop::exec_regexp;
 use super::{
+    hash_table::HashTable,
     likeop::{construct_like_escape_arg, exec_glob, exec_like_with_escape},
     sorter::Sorter,
 };
@@ -303,28 +311,35 @@ pub fn op_checkpoint(
 ) -> Result<InsnFunctionStepResult> {
     let Insn::Checkpoint {
         database: _,
-        checkpoint_mode: _,
+        checkpoint_mode,
         dest,
     } = insn
     else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    let result = program.connection.checkpoint();
+    let result = program.connection.checkpoint_with_mode(*checkpoint_mode);
     match result {
         Ok(CheckpointResult {
             num_wal_frames: num_wal_pages,
             num_checkpointed_frames: num_checkpointed_pages,
+            busy,
         }) => {
             // https://sqlite.org/pragma.html#pragma_wal_checkpoint
             // 1st col: 1 (checkpoint SQLITE_BUSY) or 0 (not busy).
-            state.registers[*dest] = Register::Value(Value::Integer(0));
+            state.registers[*dest] = Register::Value(Value::Integer(busy as i64));
             // 2nd col: # modified pages written to wal file
             state.registers[*dest + 1] = Register::Value(Value::Integer(num_wal_pages as i64));
             // 3rd col: # pages moved to db after checkpoint
             state.registers[*dest + 2] =
                 Register::Value(Value::Integer(num_checkpointed_pages as i64));
         }
-        Err(_err) => state.registers[*dest] = Register::Value(Value::Integer(1)),
+        Err(LimboError::Busy) => {
+            // Another connection is writing, so nothing could be checkpointed.
+            state.registers[*dest] = Register::Value(Value::Integer(1));
+            state.registers[*dest + 1] = Register::Value(Value::Integer(-1));
+            state.registers[*dest + 2] = Register::Value(Value::Integer(-1));
+        }
+        Err(err) => return Err(err),
     }
 
     state.pc += 1;
@@ -364,7 +379,10 @@ pub fn op_null_row(
     let Insn::NullRow { cursor_id } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    {
+    if let (_, CursorType::HashTable(_)) = &program.cursor_ref[*cursor_id] {
+        let mut cursor = state.get_cursor(*cursor_id);
+        cursor.as_hash_mut().set_null_flag(true);
+    } else {
         let mut cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "NullRow");
         let cursor = cursor.as_btree_mut();
         cursor.set_null_flag(true);
@@ -726,12 +744,11 @@ pub fn op_comparison(
         return Ok(InsnFunctionStepResult::Step);
     }
 
+    // Like SQLite, the affinity only applies to the comparison: the operands keep their
+    // original storage class, so e.g. typeof() of a compared column is unaffected.
     let mut lhs_temp_reg = state.registers[lhs].clone();
     let mut rhs_temp_reg = state.registers[rhs].clone();
 
-    let mut lhs_converted = false;
-    let mut rhs_converted = false;
-
     // Apply affinity conversions
     match affinity {
         Affinity::Numeric | Affinity::Integer => {
@@ -740,10 +757,10 @@ pub fn op_comparison(
 
             if lhs_is_text || rhs_is_text {
                 if lhs_is_text {
-                    lhs_converted = apply_numeric_affinity(&mut lhs_temp_reg, false);
+                    apply_numeric_affinity(&mut lhs_temp_reg, false);
                 }
                 if rhs_is_text {
-                    rhs_converted = apply_numeric_affinity(&mut rhs_temp_reg, false);
+                    apply_numeric_affinity(&mut rhs_temp_reg, false);
                 }
             }
         }
@@ -754,32 +771,30 @@ pub fn op_comparison(
 
             if lhs_is_text || rhs_is_text {
                 if is_numeric_value(&lhs_temp_reg) {
-                    lhs_converted = stringify_register(&mut lhs_temp_reg);
+                    stringify_register(&mut lhs_temp_reg);
                 }
 
                 if is_numeric_value(&rhs_temp_reg) {
-                    rhs_converted = stringify_register(&mut rhs_temp_reg);
+                    stringify_register(&mut rhs_temp_reg);
                 }
             }
         }
 
         Affinity::Real => {
             if matches!(lhs_temp_reg.get_owned_value(), Value::Text(_)) {
-                lhs_converted = apply_numeric_affinity(&mut lhs_temp_reg, false);
+                apply_numeric_affinity(&mut lhs_temp_reg, false);
             }
 
             if matches!(rhs_temp_reg.get_owned_value(), Value::Text(_)) {
-                rhs_converted = apply_numeric_affinity(&mut rhs_temp_reg, false);
+                apply_numeric_affinity(&mut rhs_temp_reg, false);
             }
 
             if let Value::Integer(i) = lhs_temp_reg.get_owned_value() {
                 lhs_temp_reg = Register::Value(Value::Float(*i as f64));
-                lhs_converted = true;
             }
 
             if let Value::Integer(i) = rhs_temp_reg.get_owned_value() {
                 rhs_temp_reg = Register::Value(Value::Float(*i as f64));
-                rhs_converted = true;
             }
         }
 
@@ -792,14 +807,6 @@ pub fn op_comparison(
         &collation,
     );
 
-    if lhs_converted {
-        state.registers[lhs] = lhs_temp_reg;
-    }
-
-    if rhs_converted {
-        state.registers[rhs] = rhs_temp_reg;
-    }
-
     if should_jump {
         state.pc = target_pc.as_offset_int();
     } else {
@@ -891,14 +898,20 @@ pub fn op_open_read(
     };
     let mut cursors = state.cursors.borrow_mut();
     match cursor_type {
-        CursorType::BTreeTable(_) => {
+        CursorType::BTreeTable(table) if table.has_rowid => {
             let cursor = BTreeCursor::new_table(mv_cursor, pager.clone(), *root_page);
             cursors
                 .get_mut(*cursor_id)
                 .unwrap()
                 .replace(Cursor::new_btree(cursor));
         }
-        CursorType::BTreeIndex(index) => {
+        CursorType::BTreeTable(_) | CursorType::BTreeIndex(_) => {
+            // A WITHOUT ROWID table is stored in an index b-tree.
+            let index = match cursor_type {
+                CursorType::BTreeTable(table) => Arc::new(table.without_rowid_index()),
+                CursorType::BTreeIndex(index) => index.clone(),
+                _ => unreachable!(),
+            };
             let conn = program.connection.clone();
             let schema = conn.schema.borrow();
             let table = schema
@@ -909,11 +922,11 @@ pub fn op_open_read(
                     .columns
                     .iter()
                     .map(|c| {
+                        // An indexed expression is not a column of the table.
                         table
                             .columns
                             .get(c.pos_in_table)
-                            .unwrap()
-                            .collation
+                            .map_or(c.collation, |column| column.collation)
                             .unwrap_or_default()
                     })
                     .collect()
@@ -939,6 +952,9 @@ pub fn op_open_read(
         CursorType::VirtualTable(_) => {
             panic!("OpenRead on virtual table cursor, use Insn:VOpen instead");
         }
+        CursorType::HashTable(_) => {
+            panic!("OpenRead on hash table cursor, use Insn::HashOpen instead");
+        }
     }
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
@@ -1040,7 +1056,7 @@ pub fn op_vfilter(
         } else {
             None
         };
-        cursor.filter(*idx_num as i32, idx_str, *arg_count, args)?
+        return_if_io!(cursor.filter(*idx_num as i32, idx_str, *arg_count, args))
     };
     if !has_rows {
         state.pc = pc_if_empty.as_offset_int();
@@ -1113,7 +1129,8 @@ pub fn op_vupdate(
             )));
         }
     }
-    let result = virtual_table.update(&argv);
+    program.connection.begin_vtab_transaction(virtual_table)?;
+    let result = virtual_table.update(&argv, program.connection.clone());
     match result {
         Ok(Some(new_rowid)) => {
             if *conflict_action == 5 {
@@ -1233,6 +1250,7 @@ pub fn op_rewind(
     let is_empty = {
         let mut cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "Rewind");
         let cursor = cursor.as_btree_mut();
+        cursor.set_null_flag(false);
         return_if_io!(cursor.rewind());
         cursor.is_empty()
     };
@@ -1262,6 +1280,7 @@ pub fn op_last(
     let is_empty = {
         let mut cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "Last");
         let cursor = cursor.as_btree_mut();
+        cursor.set_null_flag(false);
         return_if_io!(cursor.last());
         cursor.is_empty()
     };
@@ -1382,6 +1401,14 @@ pub fn op_column(
             };
             state.registers[*dest] = Register::Value(value);
         }
+        CursorType::HashTable(_) => {
+            let value = {
+                let mut cursor = state.get_cursor(*cursor_id);
+                let cursor = cursor.as_hash_mut();
+                cursor.column(*column).cloned().unwrap_or(Value::Null)
+            };
+            state.registers[*dest] = Register::Value(value);
+        }
         CursorType::VirtualTable(_) => {
             panic!("Insn:Column on virtual table cursor, use Insn:VColumn instead");
         }
@@ -1412,6 +1439,10 @@ pub fn op_type_check(
         .iter_mut()
         .zip(table_reference.columns.iter())
         .try_for_each(|(reg, col)| {
+            // Generated columns are computed after the other columns are checked.
+            if col.is_generated() {
+                return Ok(());
+            }
             // INT PRIMARY KEY is not row_id_alias so we throw error if this col is NULL
             if !col.is_rowid_alias
                 && col.primary_key
@@ -1427,11 +1458,18 @@ pub fn op_type_check(
                 // Handle INTEGER PRIMARY KEY for null as usual (Rowid will be auto-assigned)
                 return Ok(());
             }
-            let col_affinity = col.affinity();
-            let ty_str = col.ty_str.as_str();
+            let ty_str = col.ty_str.to_uppercase();
+            // ANY columns of STRICT tables store values exactly as given.
+            let col_affinity = if ty_str == "ANY" {
+                Affinity::Blob
+            } else {
+                col.affinity()
+            };
             let applied = apply_affinity_char(reg, col_affinity);
             let value_type = reg.get_owned_value().value_type();
-            match (ty_str, value_type) {
+            match (ty_str.as_str(), value_type) {
+                // Any column may hold NULL; NOT NULL is enforced separately.
+                (_, ValueType::Null) => {}
                 ("INTEGER" | "INT", ValueType::Integer) => {}
                 ("REAL", ValueType::Float) => {}
                 ("BLOB", ValueType::Blob) => {}
@@ -1488,6 +1526,7 @@ pub fn op_result_row(
     let row = Row {
         values: &state.registers[*start_reg] as *const Register,
         count: *count,
+        columns: program.result_column_metadata.as_slice() as *const _,
     };
     state.result_row = Some(row);
     state.pc += 1;
@@ -1582,6 +1621,20 @@ pub fn halt(
                 description
             )));
         }
+        SQLITE_CONSTRAINT_CHECK => {
+            return Err(LimboError::Constraint(format!(
+                "CHECK constraint failed: {} (19)",
+                description
+            )));
+        }
+        SQLITE_CONSTRAINT_FOREIGNKEY => {
+            return Err(LimboError::Constraint(
+                "FOREIGN KEY constraint failed (19)".to_string(),
+            ));
+        }
+        SQLITE_ERROR => {
+            return Err(LimboError::InvalidArgument(description.to_string()));
+        }
         _ => {
             return Err(LimboError::Constraint(format!(
                 "undocumented halt error code {}",
@@ -1630,6 +1683,20 @@ pub fn op_halt(
                 description
             )));
         }
+        SQLITE_CONSTRAINT_CHECK => {
+            return Err(LimboError::Constraint(format!(
+                "CHECK constraint failed: {} (19)",
+                description
+            )));
+        }
+        SQLITE_CONSTRAINT_FOREIGNKEY => {
+            return Err(LimboError::Constraint(
+                "FOREIGN KEY constraint failed (19)".to_string(),
+            ));
+        }
+        SQLITE_ERROR => {
+            return Err(LimboError::InvalidArgument(description.to_string()));
+        }
         _ => {
             return Err(LimboError::Constraint(format!(
                 "undocumented halt error code {}",
@@ -1637,7 +1704,8 @@ pub fn op_halt(
             )));
         }
     }
-    let auto_commit = program.connection.auto_commit.get();
+    // Statements issued by virtual table callbacks leave committing to the invoking statement.
+    let auto_commit = program.connection.auto_commit.get() && !program.connection.is_nested();
     tracing::trace!("op_halt(auto_commit={})", auto_commit);
     if auto_commit {
         match program.commit_txn(pager.clone(), state, mv_store, false)? {
@@ -1682,11 +1750,11 @@ pub fn op_transaction(
     pager: &Rc<Pager>,
     mv_store: Option<&Rc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
-    let Insn::Transaction { write } = insn else {
+    let Insn::Transaction { write, exclusive } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
     let conn = program.connection.clone();
-    if *write && conn._db.open_flags.contains(OpenFlags::ReadOnly) {
+    if *write && conn.is_readonly() {
         return Err(LimboError::ReadOnly);
     }
 
@@ -1699,25 +1767,15 @@ pub fn op_transaction(
     } else {
         let current_state = conn.transaction_state.get();
         let (new_transaction_state, updated) = match (current_state, write) {
-            (TransactionState::Write { change_schema }, true) => {
-                (TransactionState::Write { change_schema }, false)
-            }
-            (TransactionState::Write { change_schema }, false) => {
-                (TransactionState::Write { change_schema }, false)
-            }
-            (TransactionState::Read, true) => (
+            (TransactionState::Write { .. }, _) => (current_state, false),
+            (TransactionState::Read | TransactionState::None, true) => (
                 TransactionState::Write {
                     change_schema: false,
+                    exclusive: *exclusive,
                 },
                 true,
             ),
             (TransactionState::Read, false) => (TransactionState::Read, false),
-            (TransactionState::None, true) => (
-                TransactionState::Write {
-                    change_schema: false,
-                },
-                true,
-            ),
             (TransactionState::None, false) => (TransactionState::Read, true),
         };
 
@@ -1728,7 +1786,7 @@ pub fn op_transaction(
         }
 
         if updated && matches!(new_transaction_state, TransactionState::Write { .. }) {
-            if let LimboResult::Busy = return_if_io!(pager.begin_write_tx()) {
+            if let LimboResult::Busy = return_if_io!(pager.begin_write_tx(*exclusive)) {
                 pager.end_read_tx()?;
                 tracing::trace!("begin_write_tx busy");
                 return Ok(InsnFunctionStepResult::Busy);
@@ -1757,6 +1815,11 @@ pub fn op_auto_commit(
         unreachable!("unexpected Insn {:?}", insn)
     };
     let conn = program.connection.clone();
+    if conn.is_nested() {
+        return Err(LimboError::TxError(
+            "cannot change the transaction from within a virtual table callback".to_string(),
+        ));
+    }
     if state.commit_state == CommitState::Committing {
         return match program.commit_txn(pager.clone(), state, mv_store, *rollback)? {
             super::StepResult::Done => Ok(InsnFunctionStepResult::Done),
@@ -1767,7 +1830,7 @@ pub fn op_auto_commit(
         };
     }
     let change_schema =
-        if let TransactionState::Write { change_schema } = conn.transaction_state.get() {
+        if let TransactionState::Write { change_schema, .. } = conn.transaction_state.get() {
             change_schema
         } else {
             false
@@ -1976,6 +2039,64 @@ pub fn op_row_data(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_offset(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::Offset { cursor_id, dest } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    if let Some((index_cursor_id, table_cursor_id)) = state.deferred_seeks[*cursor_id].take() {
+        let deferred_seek = 'd: {
+            let rowid = {
+                let mut index_cursor = state.get_cursor(index_cursor_id);
+                let index_cursor = index_cursor.as_btree_mut();
+                match index_cursor.rowid()? {
+                    CursorResult::IO => {
+                        break 'd Some((index_cursor_id, table_cursor_id));
+                    }
+                    CursorResult::Ok(rowid) => rowid,
+                }
+            };
+            let mut table_cursor = state.get_cursor(table_cursor_id);
+            let table_cursor = table_cursor.as_btree_mut();
+            match table_cursor.seek(
+                SeekKey::TableRowId(rowid.unwrap()),
+                SeekOp::GE { eq_only: true },
+            )? {
+                CursorResult::Ok(_) => None,
+                CursorResult::IO => Some((index_cursor_id, table_cursor_id)),
+            }
+        };
+        if let Some(deferred_seek) = deferred_seek {
+            state.deferred_seeks[*cursor_id] = Some(deferred_seek);
+            return Ok(InsnFunctionStepResult::IO);
+        }
+    }
+    let location = {
+        let mut cursors = state.cursors.borrow_mut();
+        match cursors.get_mut(*cursor_id).unwrap() {
+            Some(Cursor::BTree(btree_cursor)) if !btree_cursor.get_null_flag() => {
+                return_if_io!(btree_cursor.record_location())
+            }
+            _ => None,
+        }
+    };
+    let offset = match location {
+        Some((page_idx, offset_in_page)) => pager.db_file_offset(page_idx, offset_in_page)?,
+        None => None,
+    };
+    state.registers[*dest] = match offset {
+        Some(offset) => Register::Value(Value::Integer(offset as i64)),
+        None => Register::Value(Value::Null),
+    };
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_row_id(
     program: &Program,
     state: &mut ProgramState,
@@ -2050,9 +2171,10 @@ pub fn op_idx_row_id(
         unreachable!("unexpected Insn {:?}", insn)
     };
     let mut cursors = state.cursors.borrow_mut();
-    let cursor = cursors.get_mut(*cursor_id).unwrap().as_mut().unwrap();
-    let cursor = cursor.as_btree_mut();
-    let rowid = return_if_io!(cursor.rowid());
+    let rowid = match cursors.get_mut(*cursor_id).unwrap().as_mut().unwrap() {
+        Cursor::Hash(cursor) => cursor.rowid(),
+        cursor => return_if_io!(cursor.as_btree_mut().rowid()),
+    };
     state.registers[*dest] = match rowid {
         Some(rowid) => Register::Value(Value::Integer(rowid)),
         None => Register::Value(Value::Null),
@@ -2589,8 +2711,9 @@ pub fn op_agg_step(
                     }
                 }
             }
+            // Stays NULL until the first non-NULL value, which is also the result if there is none.
             AggFunc::GroupConcat | AggFunc::StringAgg => {
-                Register::Aggregate(AggContext::GroupConcat(Value::build_text("")))
+                Register::Aggregate(AggContext::GroupConcat(Value::Null))
             }
             #[cfg(feature = "json")]
             AggFunc::JsonGroupArray | AggFunc::JsonbGroupArray => {
@@ -2608,7 +2731,8 @@ pub fn op_agg_step(
                     argc,
                 } => Register::Aggregate(AggContext::External(ExternalAggState {
                     state: unsafe { (init)() },
-                    argc: *argc,
+                    argc: argc
+                        .expect("the number of arguments is known when the aggregate is stepped"),
                     step_fn: *step,
                     finalize_fn: *finalize,
                     finalized_value: None,
@@ -2751,16 +2875,43 @@ pub fn op_agg_step(
             let AggContext::GroupConcat(acc) = agg.borrow_mut() else {
                 unreachable!();
             };
-            if acc.to_string().is_empty() {
-                *acc = col;
-            } else {
-                match delimiter {
-                    Register::Value(owned_value) => {
-                        *acc += owned_value;
+            // Like SQLite, NULL values are skipped and a NULL delimiter is an empty one.
+            // Blobs are concatenated as they are, everything else as its text representation.
+            let append = |buf: &mut Vec<u8>, value: &Value| match value {
+                Value::Null => {}
+                Value::Blob(blob) => buf.extend_from_slice(blob),
+                Value::Text(text) => buf.extend_from_slice(&text.value),
+                other => buf.extend_from_slice(other.to_string().as_bytes()),
+            };
+            if !matches!(col, Value::Null) {
+                // Blob bytes need not be valid UTF-8, so once one is appended the result is
+                // accumulated as a blob. AggFinal turns it back into text if it is valid.
+                let delimiter = delimiter.get_owned_value();
+                let appends_blob = matches!(col, Value::Blob(_))
+                    || (!matches!(acc, Value::Null) && matches!(delimiter, Value::Blob(_)));
+                if appends_blob {
+                    if let Value::Text(text) = acc {
+                        *acc = Value::Blob(std::mem::take(&mut text.value));
+                    }
+                }
+                match acc {
+                    Value::Text(Text { value: buf, .. }) | Value::Blob(buf) => {
+                        append(buf, delimiter);
+                        append(buf, &col);
+                    }
+                    _ => {
+                        let mut buf = vec![];
+                        append(&mut buf, &col);
+                        *acc = if appends_blob {
+                            Value::Blob(buf)
+                        } else {
+                            Value::Text(Text {
+                                value: buf,
+                                subtype: TextSubtype::Text,
+                            })
+                        };
                     }
-                    _ => unreachable!(),
                 }
-                *acc += col;
             }
         }
         #[cfg(feature = "json")]
@@ -2903,7 +3054,16 @@ pub fn op_agg_final(
                 let AggContext::GroupConcat(acc) = agg.borrow_mut() else {
                     unreachable!();
                 };
-                state.registers[*register] = Register::Value(acc.clone());
+                // The result is text like in SQLite, unless blobs that aren't valid UTF-8 were
+                // concatenated: a Text value must hold valid UTF-8, so those stay a blob.
+                let value = match acc {
+                    Value::Blob(bytes) => match std::str::from_utf8(bytes) {
+                        Ok(text) => Value::build_text(text),
+                        Err(_) => Value::Blob(bytes.clone()),
+                    },
+                    other => other.clone(),
+                };
+                state.registers[*register] = Register::Value(value);
             }
             #[cfg(feature = "json")]
             AggFunc::JsonGroupObject => {
@@ -3123,6 +3283,118 @@ pub fn op_sorter_next(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_hash_open(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashOpen { cursor_id } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let mut cursors = state.cursors.borrow_mut();
+    cursors
+        .get_mut(*cursor_id)
+        .unwrap()
+        .replace(Cursor::Hash(HashTable::new()));
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_hash_insert(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashInsert {
+        cursor_id,
+        key_start_reg,
+        num_keys,
+        row_start_reg,
+        num_columns,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let key = state.registers[*key_start_reg..*key_start_reg + *num_keys]
+        .iter()
+        .map(|reg| reg.get_owned_value().clone())
+        .collect::<Vec<_>>();
+    let row = state.registers[*row_start_reg..*row_start_reg + *num_columns]
+        .iter()
+        .map(|reg| reg.get_owned_value().clone())
+        .collect();
+    {
+        let mut cursor = state.get_cursor(*cursor_id);
+        cursor.as_hash_mut().insert(&key, row);
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_hash_probe(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashProbe {
+        cursor_id,
+        key_start_reg,
+        num_keys,
+        target_pc,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    assert!(target_pc.is_offset());
+    let key = state.registers[*key_start_reg..*key_start_reg + *num_keys]
+        .iter()
+        .map(|reg| reg.get_owned_value().clone())
+        .collect::<Vec<_>>();
+    let found = {
+        let mut cursor = state.get_cursor(*cursor_id);
+        cursor.as_hash_mut().probe(&key)
+    };
+    if found {
+        state.pc += 1;
+    } else {
+        state.pc = target_pc.as_offset_int();
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_hash_next(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashNext {
+        cursor_id,
+        pc_if_next,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    assert!(pc_if_next.is_offset());
+    let has_more = {
+        let mut cursor = state.get_cursor(*cursor_id);
+        cursor.as_hash_mut().next()
+    };
+    if has_more {
+        state.pc = pc_if_next.as_offset_int();
+    } else {
+        state.pc += 1;
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_function(
     program: &Program,
     state: &mut ProgramState,
@@ -3742,11 +4014,24 @@ pub fn op_function(
                     replacement.get_owned_value(),
                 ));
             }
+            #[cfg(feature = "offset_sql_func")]
+            ScalarFunc::SqliteOffset => {
+                unreachable!("sqlite_offset() is translated to Insn::Offset")
+            }
             #[cfg(feature = "fs")]
             ScalarFunc::LoadExtension => {
                 let extension = &state.registers[*start_reg];
                 let ext = resolve_ext_path(&extension.get_owned_value().to_string())?;
-                program.connection.load_extension(ext)?;
+                let entry_point = match arg_count {
+                    2 => match state.registers[*start_reg + 1].get_owned_value() {
+                        Value::Null => None,
+                        entry_point => Some(entry_point.to_string()),
+                    },
+                    _ => None,
+                };
+                program
+                    .connection
+                    .load_extension(ext, entry_point.as_deref())?;
             }
             ScalarFunc::StrfTime => {
                 let result = exec_strftime(&state.registers[*start_reg..*start_reg + arg_count]);
@@ -3770,6 +4055,27 @@ pub fn op_function(
                     .exec_likelihood(probability.get_owned_value());
                 state.registers[*dest] = Register::Value(result);
             }
+            #[cfg(feature = "regexp")]
+            ScalarFunc::Regexp => {
+                let pattern = state.registers[*start_reg].get_owned_value();
+                let text = state.registers[*start_reg + 1].get_owned_value();
+                let result = match (pattern, text) {
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (pattern, text) => {
+                        let pattern = pattern.exec_cast("TEXT");
+                        let text = text.exec_cast("TEXT");
+                        let cache = if *constant_mask > 0 {
+                            Some(&mut state.regex_cache.regexp)
+                        } else {
+                            None
+                        };
+                        Value::Integer(
+                            exec_regexp(cache, &pattern.to_string(), &text.to_string())? as i64
+                        )
+                    }
+                };
+                state.registers[*dest] = Register::Value(result);
+            }
         },
         crate::function::Func::Vector(vector_func) => match vector_func {
             VectorFunc::Vector => {
@@ -3910,22 +4216,22 @@ pub fn op_function(
 
                     let rename_to = {
                         match &state.registers[*start_reg + 6].get_owned_value() {
-                            Value::Text(rename_to) => normalize_ident(rename_to.as_str()),
+                            Value::Text(rename_to) => unquote_ident(rename_to.as_str()),
                             _ => panic!("rename_to parameter should be TEXT"),
                         }
                     };
 
-                    let new_name = if let Some(column) =
-                        &name.strip_prefix(&format!("sqlite_autoindex_{rename_from}_"))
+                    let new_name = if let Some(column) = &normalize_ident(&name)
+                        .strip_prefix(&format!("sqlite_autoindex_{rename_from}_"))
                     {
                         format!("sqlite_autoindex_{rename_to}_{column}")
-                    } else if name == rename_from {
+                    } else if normalize_ident(&name) == rename_from {
                         rename_to.clone()
                     } else {
                         name
                     };
 
-                    let new_tbl_name = if tbl_name == rename_from {
+                    let new_tbl_name = if normalize_ident(&tbl_name) == rename_from {
                         rename_to.clone()
                     } else {
                         tbl_name
@@ -3961,7 +4267,9 @@ pub fn op_function(
                                         unique,
                                         if_not_exists,
                                         idx_name,
-                                        tbl_name: ast::Name(rename_to),
+                                        tbl_name: ast::Name(
+                                            quote_identifier(&rename_to).into_owned(),
+                                        ),
                                         columns,
                                         where_clause,
                                     }
@@ -3987,7 +4295,9 @@ pub fn op_function(
                                         if_not_exists,
                                         tbl_name: ast::QualifiedName {
                                             db_name: None,
-                                            name: ast::Name(rename_to),
+                                            name: ast::Name(
+                                                quote_identifier(&rename_to).into_owned(),
+                                            ),
                                             alias: None,
                                         },
                                         body,
@@ -4019,13 +4329,15 @@ pub fn op_function(
 
                     let rename_to = {
                         match &state.registers[*start_reg + 7].get_owned_value() {
-                            Value::Text(rename_to) => normalize_ident(rename_to.as_str()),
+                            Value::Text(rename_to) => {
+                                quote_identifier(&unquote_ident(rename_to.as_str())).into_owned()
+                            }
                             _ => panic!("rename_to parameter should be TEXT"),
                         }
                     };
 
                     let new_sql = 'sql: {
-                        if table != tbl_name {
+                        if table != normalize_ident(&tbl_name) {
                             break 'sql None;
                         }
 
@@ -4248,7 +4560,7 @@ pub fn op_insert(
     mv_store: Option<&Rc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     let Insn::Insert {
-        cursor,
+        cursor: cursor_id,
         key_reg,
         record_reg,
         flag,
@@ -4258,7 +4570,7 @@ pub fn op_insert(
         unreachable!("unexpected Insn {:?}", insn)
     };
     {
-        let mut cursor = state.get_cursor(*cursor);
+        let mut cursor = state.get_cursor(*cursor_id);
         let cursor = cursor.as_btree_mut();
 
         let key = match &state.registers[*key_reg].get_owned_value() {
@@ -4280,7 +4592,10 @@ pub fn op_insert(
 
         return_if_io!(cursor.insert(&BTreeKey::new_table_rowid(key, Some(record.as_ref())), true));
         // Only update last_insert_rowid for regular table inserts, not schema modifications
-        if cursor.root_page() != 1 {
+        if cursor.root_page() != 1
+            && !flag.has(InsertFlags::EPHEMERAL)
+            && !flag.has(InsertFlags::INTERNAL)
+        {
             if let Some(rowid) = return_if_io!(cursor.rowid()) {
                 program.connection.update_last_rowid(rowid);
 
@@ -4288,6 +4603,21 @@ pub fn op_insert(
                 program.n_change.set(prev_changes + 1);
             }
         }
+        if !flag.has(InsertFlags::INTERNAL)
+            && program.connection.has_update_hook()
+            && Rc::ptr_eq(cursor.pager(), pager)
+        {
+            if let (_, CursorType::BTreeTable(table)) = &program.cursor_ref[*cursor_id] {
+                let update_type = if flag.has(InsertFlags::UPDATE) {
+                    UpdateType::Update
+                } else {
+                    UpdateType::Insert
+                };
+                program
+                    .connection
+                    .call_update_hook(update_type, &table.name, key);
+            }
+        }
     }
 
     state.pc += 1;
@@ -4322,14 +4652,41 @@ pub fn op_delete(
     pager: &Rc<Pager>,
     mv_store: Option<&Rc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
-    let Insn::Delete { cursor_id } = insn else {
+    let Insn::Delete {
+        cursor_id,
+        table_name,
+    } = insn
+    else {
         unreachable!("unexpected Insn {:?}", insn)
     };
+    // The rowid is read before the row is gone, and kept in case the delete needs I/O.
+    if state.op_delete_rowid.is_none()
+        && !table_name.is_empty()
+        && program.connection.has_update_hook()
+    {
+        let rowid = {
+            let mut cursor = state.get_cursor(*cursor_id);
+            let cursor = cursor.as_btree_mut();
+            if Rc::ptr_eq(cursor.pager(), pager) {
+                return_if_io!(cursor.rowid())
+            } else {
+                None
+            }
+        };
+        state.op_delete_rowid = rowid;
+    }
     {
         let mut cursor = state.get_cursor(*cursor_id);
         let cursor = cursor.as_btree_mut();
         return_if_io!(cursor.delete());
     }
+    if let Some(rowid) = state.op_delete_rowid.take() {
+        if let (_, CursorType::BTreeTable(table)) = &program.cursor_ref[*cursor_id] {
+            program
+                .connection
+                .call_update_hook(UpdateType::Delete, &table.name, rowid);
+        }
+    }
     let prev_changes = program.n_change.get();
     program.n_change.set(prev_changes + 1);
     state.pc += 1;
@@ -4503,12 +4860,25 @@ pub fn op_new_rowid(
     mv_store: Option<&Rc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     let Insn::NewRowid {
-        cursor, rowid_reg, ..
+        cursor,
+        rowid_reg,
+        prev_largest_reg,
     } = insn
     else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    let rowid = {
+    let rowid = if *prev_largest_reg > 0 {
+        // AUTOINCREMENT: never reuse a rowid, not even one of a deleted row.
+        let prev_largest = match state.registers[*prev_largest_reg].get_owned_value() {
+            Value::Integer(i) => *i,
+            _ => 0,
+        };
+        let mut cursor = state.get_cursor(*cursor);
+        let cursor = cursor.as_btree_mut();
+        return_if_io!(cursor.seek_to_last());
+        let largest = return_if_io!(cursor.rowid()).unwrap_or(0).max(prev_largest);
+        largest.checked_add(1).ok_or(LimboError::DatabaseFull)?
+    } else {
         let mut cursor = state.get_cursor(*cursor);
         let cursor = cursor.as_btree_mut();
         // TODO: make io handle rng
@@ -4569,6 +4939,27 @@ pub fn op_soft_null(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_mem_max(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::MemMax { dest_reg, src_reg } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let as_int = |value: &Value| match value {
+        Value::Integer(i) => *i,
+        _ => 0,
+    };
+    let max = as_int(state.registers[*dest_reg].get_owned_value())
+        .max(as_int(state.registers[*src_reg].get_owned_value()));
+    state.registers[*dest_reg] = Register::Value(Value::Integer(max));
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_no_conflict(
     program: &Program,
     state: &mut ProgramState,
@@ -4727,8 +5118,12 @@ pub fn op_open_write(
     };
     let (_, cursor_type) = program.cursor_ref.get(*cursor_id).unwrap();
     let mut cursors = state.cursors.borrow_mut();
+    // A WITHOUT ROWID table is stored in an index b-tree.
     let maybe_index = match cursor_type {
-        CursorType::BTreeIndex(index) => Some(index),
+        CursorType::BTreeIndex(index) => Some(index.clone()),
+        CursorType::BTreeTable(table) if !table.has_rowid => {
+            Some(Arc::new(table.without_rowid_index()))
+        }
         _ => None,
     };
     let mv_cursor = match state.mv_tx_id {
@@ -4753,11 +5148,11 @@ pub fn op_open_write(
                 .columns
                 .iter()
                 .map(|c| {
+                    // An indexed expression is not a column of the table.
                     table
                         .columns
                         .get(c.pos_in_table)
-                        .unwrap()
-                        .collation
+                        .map_or(c.collation, |column| column.collation)
                         .unwrap_or_default()
                 })
                 .collect()
@@ -4997,6 +5392,34 @@ pub fn op_parse_schema(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_load_analysis(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::LoadAnalysis { db: _ } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let conn = program.connection.clone();
+    // Like ParseSchema, the statistics are read by a nested query on the same connection.
+    let previous_auto_commit = conn.auto_commit.get();
+    conn.auto_commit.set(false);
+    let stmt = conn.prepare(format!("SELECT tbl, idx, stat FROM {SQLITE_STAT1_TABLE}"))?;
+    let mut new_schema = conn.schema.borrow().clone();
+    parse_stat1_rows(
+        Some(stmt),
+        &mut new_schema,
+        conn.pager.io.clone(),
+        state.mv_tx_id,
+    )?;
+    conn.schema.replace(new_schema);
+    conn.auto_commit.set(previous_auto_commit);
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_read_cookie(
     program: &Program,
     state: &mut ProgramState,
@@ -5056,8 +5479,8 @@ pub fn op_set_cookie(
         Cookie::SchemaVersion => {
             // we update transaction state to indicate that the schema has changed
             match program.connection.transaction_state.get() {
-                TransactionState::Write { change_schema } => {
-                    program.connection.transaction_state.set(TransactionState::Write { change_schema: true });
+                TransactionState::Write { exclusive, .. } => {
+                    program.connection.transaction_state.set(TransactionState::Write { change_schema: true, exclusive });
                 },
                 TransactionState::Read => unreachable!("invalid transaction state for SetCookie: TransactionState::Read, should be write"),
                 TransactionState::None => unreachable!("invalid transaction state for SetCookie: TransactionState::None, should be write"),
@@ -5262,7 +5685,7 @@ pub fn op_open_ephemeral(
             let db_file = Arc::new(FileMemoryStorage::new(file));
 
             let buffer_pool = Arc::new(BufferPool::new(None));
-            let page_cache = Arc::new(RwLock::new(DumbLruPageCache::default()));
+            let page_cache = Arc::new(RwLock::new(PageCache::default()));
 
             let pager = Rc::new(Pager::new(
                 db_file,
@@ -5283,7 +5706,7 @@ pub fn op_open_ephemeral(
         }
         OpOpenEphemeralState::StartingTxn { pager } => {
             tracing::trace!("StartingTxn");
-            return_if_io!(pager.begin_write_tx());
+            return_if_io!(pager.begin_write_tx(false));
             state.op_open_ephemeral_state = OpOpenEphemeralState::CreateBtree {
                 pager: pager.clone(),
             };
@@ -5353,6 +5776,9 @@ pub fn op_open_ephemeral(
                 CursorType::VirtualTable(_) => {
                     panic!("OpenEphemeral on virtual table cursor, use Insn::VOpen instead");
                 }
+                CursorType::HashTable(_) => {
+                    panic!("OpenEphemeral on hash table cursor, use Insn::HashOpen instead");
+                }
             }
 
             state.pc += 1;
@@ -6525,7 +6951,7 @@ fn apply_affinity_char(target: &mut Register, affinity: Affinity) -> bool {
                     }
 
                     // Try to parse as number (similar to applyNumericAffinity)
-                    let Ok(num) = checked_cast_text_to_numeric(text) else {
+                    let Ok(num) = checked_cast_text_to_numeric_exact(text) else {
                         return false;
                     };
 
@@ -6535,13 +6961,8 @@ fn apply_affinity_char(target: &mut Register, affinity: Affinity) -> bool {
                             return true;
                         }
                         Value::Float(fl) => {
-                            // For Numeric affinity, try to convert float to int if exact
-                            if affinity == Affinity::Numeric {
-                                return try_float_to_integer_affinity(value, fl);
-                            } else {
-                                *value = Value::Float(fl);
-                                return true;
-                            }
+                            // INTEGER affinity behaves like NUMERIC: exact floats become integers
+                            return try_float_to_integer_affinity(value, fl);
                         }
                         other => {
                             *value = other;
@@ -6563,8 +6984,11 @@ fn apply_affinity_char(target: &mut Register, affinity: Affinity) -> bool {
                     if s.starts_with("0x") {
                         return false;
                     }
-                    if let Ok(num) = checked_cast_text_to_numeric(s) {
-                        *value = num;
+                    if let Ok(num) = checked_cast_text_to_numeric_exact(s) {
+                        *value = match num {
+                            Value::Integer(i) => Value::Float(i as f64),
+                            num => num,
+                        };
                         return true;
                     } else {
                         return false;
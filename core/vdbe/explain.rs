@@ -20,6 +20,7 @@ pub fn insn_to_str(
             CursorType::Pseudo(_) => "pseudo",
             CursorType::VirtualTable(virtual_table) => &virtual_table.name,
             CursorType::Sorter => "sorter",
+            CursorType::HashTable(index) => &index.name,
         }
     };
     let (opcode, p1, p2, p3, p4, p5, comment): (&str, i32, i32, i32, Value, u16, String) =
@@ -542,6 +543,7 @@ pub fn insn_to_str(
                     CursorType::Pseudo(_) => None,
                     CursorType::Sorter => None,
                     CursorType::VirtualTable(v) => v.columns.get(*column).unwrap().name.as_ref(),
+                    CursorType::HashTable(index) => index.columns.get(*column).map(|c| &c.name),
                 };
                 (
                     "Column",
@@ -645,14 +647,14 @@ pub fn insn_to_str(
                 0,
                 "".to_string(),
             ),
-            Insn::Transaction { write } => (
+            Insn::Transaction { write, exclusive } => (
                 "Transaction",
                 0,
-                *write as i32,
+                *write as i32 + *exclusive as i32,
                 0,
                 Value::build_text(""),
                 0,
-                format!("write={}", write),
+                format!("write={}, exclusive={}", write, exclusive),
             ),
             Insn::Goto { target_pc } => (
                 "Goto",
@@ -737,6 +739,19 @@ pub fn insn_to_str(
                     value.len()
                 ),
             ),
+            Insn::Offset { cursor_id, dest } => (
+                "Offset",
+                *cursor_id as i32,
+                0,
+                *dest as i32,
+                Value::build_text(""),
+                0,
+                format!(
+                    "r[{}]=offset of record in {}",
+                    dest,
+                    get_table_or_index_name(*cursor_id)
+                ),
+            ),
             Insn::RowId { cursor_id, dest } => (
                 "RowId",
                 *cursor_id as i32,
@@ -1026,6 +1041,62 @@ pub fn insn_to_str(
                 0,
                 "".to_string(),
             ),
+            Insn::HashOpen { cursor_id } => (
+                "HashOpen",
+                *cursor_id as i32,
+                0,
+                0,
+                Value::build_text(""),
+                0,
+                format!("cursor {}", cursor_id),
+            ),
+            Insn::HashInsert {
+                cursor_id,
+                key_start_reg,
+                num_keys,
+                row_start_reg,
+                num_columns,
+            } => (
+                "HashInsert",
+                *cursor_id as i32,
+                *key_start_reg as i32,
+                *num_keys as i32,
+                Value::build_text(""),
+                0,
+                format!(
+                    "key=r[{}..{}] row=r[{}..{}]",
+                    key_start_reg,
+                    key_start_reg + num_keys - 1,
+                    row_start_reg,
+                    row_start_reg + num_columns - 1
+                ),
+            ),
+            Insn::HashProbe {
+                cursor_id,
+                key_start_reg,
+                num_keys,
+                target_pc,
+            } => (
+                "HashProbe",
+                *cursor_id as i32,
+                target_pc.as_debug_int(),
+                *key_start_reg as i32,
+                Value::build_text(""),
+                0,
+                format!("key=r[{}..{}]", key_start_reg, key_start_reg + num_keys - 1),
+            ),
+            Insn::HashNext {
+                cursor_id,
+                pc_if_next,
+            } => (
+                "HashNext",
+                *cursor_id as i32,
+                pc_if_next.as_debug_int(),
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
             Insn::Function {
                 constant_mask,
                 start_reg,
@@ -1107,12 +1178,15 @@ pub fn insn_to_str(
                 flag.0 as u16,
                 format!("intkey=r[{}] data=r[{}]", key_reg, record_reg),
             ),
-            Insn::Delete { cursor_id } => (
+            Insn::Delete {
+                cursor_id,
+                table_name,
+            } => (
                 "Delete",
                 *cursor_id as i32,
                 0,
                 0,
-                Value::build_text(""),
+                Value::build_text(table_name),
                 0,
                 "".to_string(),
             ),
@@ -1160,6 +1234,15 @@ pub fn insn_to_str(
                 0,
                 "".to_string(),
             ),
+            Insn::MemMax { dest_reg, src_reg } => (
+                "MemMax",
+                *dest_reg as i32,
+                *src_reg as i32,
+                0,
+                Value::build_text(""),
+                0,
+                format!("r[{}]=max(r[{}],r[{}])", dest_reg, dest_reg, src_reg),
+            ),
             Insn::NoConflict {
                 cursor_id,
                 target_pc,
@@ -1327,6 +1410,15 @@ pub fn insn_to_str(
                 0,
                 where_clause.clone().unwrap_or("NULL".to_string()),
             ),
+            Insn::LoadAnalysis { db } => (
+                "LoadAnalysis",
+                *db as i32,
+                0,
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
             Insn::Prev {
                 cursor_id,
                 pc_if_prev,
@@ -1611,7 +1703,7 @@ pub fn insn_to_str(
             ),
         };
     format!(
-        "{:<4}  {:<17}  {:<4}  {:<4}  {:<4}  {:<13}  {:<2}  {}",
+        "{:<4}  {:<13}  {:<4}  {:<4}  {:<4}  {:<13}  {:<2}  {}",
         addr,
         &(indent + opcode),
         p1,
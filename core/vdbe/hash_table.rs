@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use crate::types::Value;
+
+/// A value of a join key, after the comparison affinity has been applied to it.
+///
+/// Floats with an integral value are stored as integers, so that e.g. `1 = 1.0` matches like it
+/// does when the values are compared.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum HashKey {
+    Integer(i64),
+    Float(u64),
+    Text(Vec<u8>),
+    Blob(Vec<u8>),
+}
+
+impl HashKey {
+    /// Returns None for NULL, which is not equal to anything.
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::Null => return None,
+            Value::Integer(i) => Self::Integer(*i),
+            Value::Float(f) => {
+                // -0.0 is integral too, so it becomes the same key as 0.0.
+                if f.trunc() == *f && *f >= i64::MIN as f64 && *f < i64::MAX as f64 {
+                    Self::Integer(*f as i64)
+                } else {
+                    Self::Float(f.to_bits())
+                }
+            }
+            Value::Text(text) => Self::Text(text.value.clone()),
+            Value::Blob(blob) => Self::Blob(blob.clone()),
+        })
+    }
+}
+
+/// The in-memory table built from the inner table of a hash join.
+///
+/// Every row is stored under the values of the join columns. Probing the table with the join
+/// key of an outer row positions the cursor on the first row with that key, and the other rows
+/// with the same key are then visited in insertion order.
+pub struct HashTable {
+    rows: Vec<Vec<Value>>,
+    /// For every row, the next row with the same key.
+    next_with_same_key: Vec<Option<usize>>,
+    /// The first and last row of every key.
+    keys: HashMap<Vec<HashKey>, (usize, usize)>,
+    current: Option<usize>,
+    null_flag: bool,
+}
+
+impl HashTable {
+    pub fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            next_with_same_key: Vec::new(),
+            keys: HashMap::new(),
+            current: None,
+            null_flag: false,
+        }
+    }
+
+    /// Insert a row under the given key. Rows with a NULL in their key can never match a probe,
+    /// so they are not stored.
+    pub fn insert(&mut self, key: &[Value], row: Vec<Value>) {
+        let Some(key) = key
+            .iter()
+            .map(HashKey::from_value)
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        let row_idx = self.rows.len();
+        self.rows.push(row);
+        self.next_with_same_key.push(None);
+        match self.keys.get_mut(&key) {
+            Some((_, last)) => {
+                self.next_with_same_key[*last] = Some(row_idx);
+                *last = row_idx;
+            }
+            None => {
+                self.keys.insert(key, (row_idx, row_idx));
+            }
+        }
+    }
+
+    /// Position the cursor on the first row with the given key.
+    /// Returns false if there is no such row.
+    pub fn probe(&mut self, key: &[Value]) -> bool {
+        self.null_flag = false;
+        self.current = key
+            .iter()
+            .map(HashKey::from_value)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|key| self.keys.get(&key))
+            .map(|(first, _)| *first);
+        self.current.is_some()
+    }
+
+    /// Advance to the next row with the key of the last probe.
+    /// Returns false if there are no more rows.
+    pub fn next(&mut self) -> bool {
+        self.current = self.current.and_then(|row| self.next_with_same_key[row]);
+        self.current.is_some()
+    }
+
+    /// The value of a column of the current row, or None if there is no current row.
+    pub fn column(&self, column: usize) -> Option<&Value> {
+        if self.null_flag {
+            return None;
+        }
+        self.rows[self.current?].get(column)
+    }
+
+    /// The rowid of the current row, which is stored as its last column.
+    pub fn rowid(&self) -> Option<i64> {
+        if self.null_flag {
+            return None;
+        }
+        match self.rows[self.current?].last() {
+            Some(Value::Integer(rowid)) => Some(*rowid),
+            _ => None,
+        }
+    }
+
+    pub fn set_null_flag(&mut self, flag: bool) {
+        self.null_flag = flag;
+    }
+}
+
+impl Default for HashTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
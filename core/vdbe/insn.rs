@@ -100,6 +100,8 @@ pub struct InsertFlags(pub u8);
 
 impl InsertFlags {
     pub const UPDATE: u8 = 0x01; // Flag indicating this is part of an UPDATE statement
+    pub const EPHEMERAL: u8 = 0x02; // Flag indicating the row goes to an ephemeral table, which is not counted as a change
+    pub const INTERNAL: u8 = 0x04; // Bookkeeping row (e.g. sqlite_sequence): not counted as a change and doesn't set last_insert_rowid
 
     pub fn new() -> Self {
         InsertFlags(0)
@@ -117,6 +119,20 @@ impl InsertFlags {
         }
         self
     }
+
+    pub fn ephemeral(mut self, is_ephemeral: bool) -> Self {
+        if is_ephemeral {
+            self.0 |= InsertFlags::EPHEMERAL;
+        } else {
+            self.0 &= !InsertFlags::EPHEMERAL;
+        }
+        self
+    }
+
+    pub fn internal(mut self) -> Self {
+        self.0 |= InsertFlags::INTERNAL;
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -140,7 +156,7 @@ impl<T: Copy + std::fmt::Display> std::fmt::Display for RegisterOrLiteral<T> {
     }
 }
 
-#[derive(Description, Debug)]
+#[derive(Description, Debug, Clone)]
 pub enum Insn {
     /// Initialize the program state and jump to the given PC.
     Init {
@@ -451,9 +467,11 @@ pub enum Insn {
         err_code: usize,     // p1
     },
 
-    /// Start a transaction.
+    /// Start a transaction. With `exclusive`, other connections are kept from reading
+    /// until the transaction ends.
     Transaction {
         write: bool,
+        exclusive: bool,
     },
 
     /// Set database auto-commit mode and potentially rollback.
@@ -516,6 +534,13 @@ pub enum Insn {
         dest: usize,
     },
 
+    /// Store in register dest the byte offset into the database file of the record
+    /// the cursor is pointing at, or NULL if the record does not live in the database file.
+    Offset {
+        cursor_id: CursorID,
+        dest: usize,
+    },
+
     /// Read the rowid of the current row.
     RowId {
         cursor_id: CursorID,
@@ -691,6 +716,37 @@ pub enum Insn {
         pc_if_next: BranchOffset,
     },
 
+    /// Open an empty hash table for the build side of a hash join.
+    HashOpen {
+        cursor_id: CursorID,
+    },
+
+    /// Insert the row in registers row_start_reg..row_start_reg+num_columns into the hash table,
+    /// under the key in registers key_start_reg..key_start_reg+num_keys.
+    HashInsert {
+        cursor_id: CursorID,
+        key_start_reg: usize,
+        num_keys: usize,
+        row_start_reg: usize,
+        num_columns: usize,
+    },
+
+    /// Position the hash table cursor on the first row with the key in registers
+    /// key_start_reg..key_start_reg+num_keys, or jump to target_pc if there is none.
+    HashProbe {
+        cursor_id: CursorID,
+        key_start_reg: usize,
+        num_keys: usize,
+        target_pc: BranchOffset,
+    },
+
+    /// Advance the hash table cursor to the next row with the key of the last probe,
+    /// and jump to pc_if_next if there is one.
+    HashNext {
+        cursor_id: CursorID,
+        pc_if_next: BranchOffset,
+    },
+
     /// Function
     Function {
         constant_mask: i32, // P1
@@ -731,6 +787,9 @@ pub enum Insn {
 
     Delete {
         cursor_id: CursorID,
+        /// Table the row is deleted from, reported to the update hook. Empty when the delete
+        /// is part of an UPDATE that changes the rowid, which is reported as an update.
+        table_name: String,
     },
 
     IdxDelete {
@@ -742,7 +801,7 @@ pub enum Insn {
     NewRowid {
         cursor: CursorID,        // P1
         rowid_reg: usize,        // P2  Destination register to store the new rowid
-        prev_largest_reg: usize, // P3 Previous largest rowid in the table (Not used for now)
+        prev_largest_reg: usize, // P3 If non-zero, register holding the largest rowid ever used (AUTOINCREMENT)
     },
 
     MustBeInt {
@@ -753,6 +812,13 @@ pub enum Insn {
         reg: usize,
     },
 
+    /// Set the integer in register P1 to the maximum of its current value and the integer in
+    /// register P2.
+    MemMax {
+        dest_reg: usize,
+        src_reg: usize,
+    },
+
     /// If P4==0 then register P3 holds a blob constructed by [MakeRecord](https://sqlite.org/opcode.html#MakeRecord). If P4>0 then register P3 is the first of P4 registers that form an unpacked record.\
     ///
     /// Cursor P1 is on an index btree. If the record identified by P3 and P4 contains any NULL value, jump immediately to P2. If all terms of the record are not-NULL then a check is done to determine if any row in the P1 index btree has a matching key prefix. If there are no matches, jump immediately to P2. If there is a match, fall through and leave the P1 cursor pointing to the matching row.\
@@ -845,6 +911,11 @@ pub enum Insn {
         where_clause: Option<String>,
     },
 
+    /// Read the statistics in `sqlite_stat1` into the in-memory schema of database P1.
+    LoadAnalysis {
+        db: usize,
+    },
+
     /// Place the result of lhs >> rhs in dest register.
     ShiftRight {
         lhs: usize,
@@ -980,7 +1051,605 @@ pub enum Insn {
     },
 }
 
+/// How an instruction accesses a range of registers, see [Insn::for_each_register].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterAccess {
+    /// The registers are read.
+    Read,
+    /// The registers are always overwritten, without being read first.
+    Write,
+    /// The registers may be read, and may be overwritten, e.g. a counter that is decremented.
+    Update,
+}
+
 impl Insn {
+    /// Calls `f` on every range of registers the instruction accesses, with the first register
+    /// of the range, the number of registers in it and how they are accessed. Register 0 means
+    /// "no register" in some instructions and is passed to `f` like any other.
+    ///
+    /// Returns false if the instruction may access registers that it doesn't name, in which
+    /// case the registers passed to `f` are incomplete.
+    pub fn for_each_register(
+        &mut self,
+        mut f: impl FnMut(&mut usize, usize, RegisterAccess),
+    ) -> bool {
+        use RegisterAccess::{Read, Update, Write};
+        match self {
+            Insn::Init { .. }
+            | Insn::NullRow { .. }
+            | Insn::Jump { .. }
+            | Insn::OpenRead { .. }
+            | Insn::VOpen { .. }
+            | Insn::VNext { .. }
+            | Insn::VDestroy { .. }
+            | Insn::Rewind { .. }
+            | Insn::Last { .. }
+            | Insn::Next { .. }
+            | Insn::Prev { .. }
+            | Insn::Halt { .. }
+            | Insn::Transaction { .. }
+            | Insn::AutoCommit { .. }
+            | Insn::Goto { .. }
+            | Insn::SeekEnd { .. }
+            | Insn::DeferredSeek { .. }
+            | Insn::SorterOpen { .. }
+            | Insn::SorterSort { .. }
+            | Insn::SorterNext { .. }
+            | Insn::HashOpen { .. }
+            | Insn::HashNext { .. }
+            | Insn::Delete { .. }
+            | Insn::DropTable { .. }
+            | Insn::DropIndex { .. }
+            | Insn::Close { .. }
+            | Insn::ParseSchema { .. }
+            | Insn::LoadAnalysis { .. }
+            | Insn::Noop
+            | Insn::SetCookie { .. }
+            | Insn::OpenEphemeral { .. }
+            | Insn::OpenAutoindex { .. }
+            | Insn::Once { .. } => {}
+            Insn::Null { dest, dest_end } | Insn::BeginSubrtn { dest, dest_end } => {
+                let count = dest_end.map_or(1, |end| end + 1 - *dest);
+                f(dest, count, Write);
+                if let Some(end) = dest_end {
+                    *end = *dest + count - 1;
+                }
+            }
+            Insn::Add { lhs, rhs, dest }
+            | Insn::Subtract { lhs, rhs, dest }
+            | Insn::Multiply { lhs, rhs, dest }
+            | Insn::Divide { lhs, rhs, dest }
+            | Insn::Remainder { lhs, rhs, dest }
+            | Insn::BitAnd { lhs, rhs, dest }
+            | Insn::BitOr { lhs, rhs, dest }
+            | Insn::ShiftRight { lhs, rhs, dest }
+            | Insn::ShiftLeft { lhs, rhs, dest }
+            | Insn::Concat { lhs, rhs, dest }
+            | Insn::And { lhs, rhs, dest }
+            | Insn::Or { lhs, rhs, dest }
+            | Insn::ZeroOrNull {
+                rg1: lhs,
+                rg2: rhs,
+                dest,
+            } => {
+                f(lhs, 1, Read);
+                f(rhs, 1, Read);
+                f(dest, 1, Write);
+            }
+            Insn::BitNot { reg, dest } | Insn::Not { reg, dest } => {
+                f(reg, 1, Read);
+                f(dest, 1, Write);
+            }
+            Insn::Compare {
+                start_reg_a,
+                start_reg_b,
+                count,
+                ..
+            } => {
+                f(start_reg_a, *count, Read);
+                f(start_reg_b, *count, Read);
+            }
+            Insn::Checkpoint { dest, .. } => f(dest, 3, Write),
+            Insn::Move {
+                source_reg,
+                dest_reg,
+                count,
+            } => {
+                // The source registers are left NULL.
+                f(source_reg, *count, Update);
+                f(dest_reg, *count, Write);
+            }
+            Insn::IfPos { reg, .. }
+            | Insn::DecrJumpZero { reg, .. }
+            | Insn::MustBeInt { reg }
+            | Insn::RealAffinity { register: reg }
+            | Insn::AggFinal { register: reg, .. }
+            | Insn::Yield { yield_reg: reg, .. } => f(reg, 1, Update),
+            Insn::NotNull { reg, .. }
+            | Insn::If { reg, .. }
+            | Insn::IfNot { reg, .. }
+            | Insn::IsNull { reg, .. }
+            | Insn::HaltIfNull {
+                target_reg: reg, ..
+            }
+            | Insn::SeekRowid { src_reg: reg, .. }
+            | Insn::SorterInsert {
+                record_reg: reg, ..
+            }
+            | Insn::NotExists { rowid_reg: reg, .. }
+            | Insn::EndCoroutine { yield_reg: reg }
+            | Insn::Return {
+                return_reg: reg, ..
+            } => f(reg, 1, Read),
+            Insn::Eq { lhs, rhs, .. }
+            | Insn::Ne { lhs, rhs, .. }
+            | Insn::Lt { lhs, rhs, .. }
+            | Insn::Le { lhs, rhs, .. }
+            | Insn::Gt { lhs, rhs, .. }
+            | Insn::Ge { lhs, rhs, .. } => {
+                f(lhs, 1, Read);
+                f(rhs, 1, Read);
+            }
+            Insn::VCreate {
+                module_name,
+                table_name,
+                args_reg,
+            } => {
+                f(module_name, 1, Read);
+                f(table_name, 1, Read);
+                if let Some(args_reg) = args_reg {
+                    f(args_reg, 1, Read);
+                }
+            }
+            Insn::VFilter {
+                arg_count,
+                args_reg,
+                idx_str,
+                ..
+            } => {
+                f(args_reg, *arg_count, Read);
+                if let Some(idx_str) = idx_str {
+                    f(idx_str, 1, Read);
+                }
+            }
+            Insn::VUpdate {
+                arg_count,
+                start_reg,
+                ..
+            } => f(start_reg, *arg_count, Read),
+            Insn::VColumn { dest, .. }
+            | Insn::Column { dest, .. }
+            | Insn::Integer { dest, .. }
+            | Insn::Real { dest, .. }
+            | Insn::String8 { dest, .. }
+            | Insn::Blob { dest, .. }
+            | Insn::RowData { dest, .. }
+            | Insn::Offset { dest, .. }
+            | Insn::RowId { dest, .. }
+            | Insn::IdxRowId { dest, .. }
+            | Insn::Int64 { out_reg: dest, .. }
+            | Insn::SoftNull { reg: dest }
+            | Insn::CreateBtree { root: dest, .. }
+            | Insn::Variable { dest, .. }
+            | Insn::PageCount { dest, .. }
+            | Insn::ReadCookie { dest, .. }
+            | Insn::Count {
+                target_reg: dest, ..
+            }
+            | Insn::InitCoroutine {
+                yield_reg: dest, ..
+            }
+            | Insn::Gosub {
+                return_reg: dest, ..
+            } => f(dest, 1, Write),
+            // The pseudo cursor is filled by SorterData, the content register is never read.
+            Insn::OpenPseudo { content_reg, .. } => f(content_reg, 1, Write),
+            // Not written if the sorter has no current row.
+            Insn::SorterData { dest_reg, .. } => f(dest_reg, 1, Update),
+            Insn::Destroy {
+                former_root_reg, ..
+            } => f(former_root_reg, 1, Update),
+            Insn::IntegrityCk {
+                message_register, ..
+            } => f(message_register, 1, Update),
+            Insn::TypeCheck {
+                start_reg, count, ..
+            } => f(start_reg, *count, Update),
+            Insn::Affinity {
+                start_reg, count, ..
+            } => f(start_reg, count.get(), Update),
+            Insn::MakeRecord {
+                start_reg,
+                count,
+                dest_reg,
+                ..
+            } => {
+                f(start_reg, *count, Read);
+                f(dest_reg, 1, Write);
+            }
+            Insn::ResultRow { start_reg, count } => f(start_reg, *count, Read),
+            Insn::SeekGE {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::SeekGT {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::SeekLE {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::SeekLT {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::IdxGE {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::IdxGT {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::IdxLE {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::IdxLT {
+                start_reg,
+                num_regs,
+                ..
+            }
+            | Insn::IdxDelete {
+                start_reg,
+                num_regs,
+                ..
+            } => f(start_reg, *num_regs, Read),
+            Insn::IdxInsert {
+                record_reg,
+                unpacked_start,
+                unpacked_count,
+                ..
+            } => {
+                f(record_reg, 1, Read);
+                if let (Some(start), Some(count)) = (unpacked_start, unpacked_count) {
+                    f(start, *count as usize, Read);
+                }
+            }
+            // With no registers, the record is a blob in `record_reg`.
+            Insn::NoConflict {
+                record_reg,
+                num_regs,
+                ..
+            }
+            | Insn::Found {
+                record_reg,
+                num_regs,
+                ..
+            }
+            | Insn::NotFound {
+                record_reg,
+                num_regs,
+                ..
+            } => f(record_reg, (*num_regs).max(1), Read),
+            Insn::AggStep {
+                acc_reg,
+                col,
+                delimiter,
+                func,
+            } => {
+                let argc = match func {
+                    AggFunc::External(func) => match func.agg_args() {
+                        Ok(Some(argc)) => argc,
+                        _ => return false,
+                    },
+                    _ => 1,
+                };
+                f(acc_reg, 1, Update);
+                f(col, argc, Read);
+                f(delimiter, 1, Read);
+            }
+            Insn::Function {
+                start_reg,
+                dest,
+                func,
+                ..
+            } => {
+                // ALTER TABLE functions write several registers after `dest`.
+                if let crate::function::Func::AlterTable(_) = func.func {
+                    return false;
+                }
+                f(start_reg, func.arg_count, Read);
+                f(dest, 1, Write);
+            }
+            Insn::Insert {
+                key_reg,
+                record_reg,
+                ..
+            } => {
+                f(key_reg, 1, Read);
+                f(record_reg, 1, Read);
+            }
+            Insn::NewRowid {
+                rowid_reg,
+                prev_largest_reg,
+                ..
+            } => {
+                f(prev_largest_reg, 1, Read);
+                f(rowid_reg, 1, Write);
+            }
+            Insn::HashInsert {
+                key_start_reg,
+                num_keys,
+                row_start_reg,
+                num_columns,
+                ..
+            } => {
+                f(key_start_reg, *num_keys, Read);
+                f(row_start_reg, *num_columns, Read);
+            }
+            Insn::HashProbe {
+                key_start_reg,
+                num_keys,
+                ..
+            } => f(key_start_reg, *num_keys, Read),
+            Insn::MemMax { dest_reg, src_reg } => {
+                f(src_reg, 1, Read);
+                f(dest_reg, 1, Update);
+            }
+            Insn::OffsetLimit {
+                limit_reg,
+                combined_reg,
+                offset_reg,
+            } => {
+                f(limit_reg, 1, Read);
+                f(offset_reg, 1, Read);
+                f(combined_reg, 1, Write);
+            }
+            Insn::OpenWrite { root_page, .. } => {
+                if let RegisterOrLiteral::Register(reg) = root_page {
+                    f(reg, 1, Read);
+                }
+            }
+            Insn::Copy {
+                src_reg,
+                dst_reg,
+                amount,
+            } => {
+                f(src_reg, *amount + 1, Read);
+                f(dst_reg, *amount + 1, Write);
+            }
+        }
+        true
+    }
+
+    /// Calls `f` on every jump target of the instruction, along with the instruction's name.
+    pub fn for_each_jump_target(&mut self, mut f: impl FnMut(&mut BranchOffset, &'static str)) {
+        match self {
+            Insn::Init { target_pc } => {
+                f(target_pc, "Init");
+            }
+            Insn::Eq {
+                lhs: _lhs,
+                rhs: _rhs,
+                target_pc,
+                ..
+            } => {
+                f(target_pc, "Eq");
+            }
+            Insn::Ne {
+                lhs: _lhs,
+                rhs: _rhs,
+                target_pc,
+                ..
+            } => {
+                f(target_pc, "Ne");
+            }
+            Insn::Lt {
+                lhs: _lhs,
+                rhs: _rhs,
+                target_pc,
+                ..
+            } => {
+                f(target_pc, "Lt");
+            }
+            Insn::Le {
+                lhs: _lhs,
+                rhs: _rhs,
+                target_pc,
+                ..
+            } => {
+                f(target_pc, "Le");
+            }
+            Insn::Gt {
+                lhs: _lhs,
+                rhs: _rhs,
+                target_pc,
+                ..
+            } => {
+                f(target_pc, "Gt");
+            }
+            Insn::Ge {
+                lhs: _lhs,
+                rhs: _rhs,
+                target_pc,
+                ..
+            } => {
+                f(target_pc, "Ge");
+            }
+            Insn::If {
+                reg: _reg,
+                target_pc,
+                jump_if_null: _,
+            } => {
+                f(target_pc, "If");
+            }
+            Insn::IfNot {
+                reg: _reg,
+                target_pc,
+                jump_if_null: _,
+            } => {
+                f(target_pc, "IfNot");
+            }
+            Insn::Rewind { pc_if_empty, .. } => {
+                f(pc_if_empty, "Rewind");
+            }
+            Insn::Last { pc_if_empty, .. } => {
+                f(pc_if_empty, "Last");
+            }
+            Insn::Goto { target_pc } => {
+                f(target_pc, "Goto");
+            }
+            Insn::DecrJumpZero {
+                reg: _reg,
+                target_pc,
+            } => {
+                f(target_pc, "DecrJumpZero");
+            }
+            Insn::SorterNext {
+                cursor_id: _cursor_id,
+                pc_if_next,
+            } => {
+                f(pc_if_next, "SorterNext");
+            }
+            Insn::SorterSort { pc_if_empty, .. } => {
+                f(pc_if_empty, "SorterSort");
+            }
+            Insn::HashProbe { target_pc, .. } => {
+                f(target_pc, "HashProbe");
+            }
+            Insn::HashNext { pc_if_next, .. } => {
+                f(pc_if_next, "HashNext");
+            }
+            Insn::NotNull {
+                reg: _reg,
+                target_pc,
+            } => {
+                f(target_pc, "NotNull");
+            }
+            Insn::IfPos { target_pc, .. } => {
+                f(target_pc, "IfPos");
+            }
+            Insn::Next { pc_if_next, .. } => {
+                f(pc_if_next, "Next");
+            }
+            Insn::Once {
+                target_pc_when_reentered,
+                ..
+            } => {
+                f(target_pc_when_reentered, "Once");
+            }
+            Insn::Prev { pc_if_prev, .. } => {
+                f(pc_if_prev, "Prev");
+            }
+            Insn::InitCoroutine {
+                yield_reg: _,
+                jump_on_definition,
+                start_offset,
+            } => {
+                f(jump_on_definition, "InitCoroutine");
+                f(start_offset, "InitCoroutine");
+            }
+            Insn::NotExists {
+                cursor: _,
+                rowid_reg: _,
+                target_pc,
+            } => {
+                f(target_pc, "NotExists");
+            }
+            Insn::Yield {
+                yield_reg: _,
+                end_offset,
+            } => {
+                f(end_offset, "Yield");
+            }
+            Insn::SeekRowid { target_pc, .. } => {
+                f(target_pc, "SeekRowid");
+            }
+            Insn::Gosub { target_pc, .. } => {
+                f(target_pc, "Gosub");
+            }
+            Insn::Jump {
+                target_pc_eq,
+                target_pc_lt,
+                target_pc_gt,
+            } => {
+                f(target_pc_eq, "Jump");
+                f(target_pc_lt, "Jump");
+                f(target_pc_gt, "Jump");
+            }
+            Insn::SeekGE { target_pc, .. } => {
+                f(target_pc, "SeekGE");
+            }
+            Insn::SeekGT { target_pc, .. } => {
+                f(target_pc, "SeekGT");
+            }
+            Insn::SeekLE { target_pc, .. } => {
+                f(target_pc, "SeekLE");
+            }
+            Insn::SeekLT { target_pc, .. } => {
+                f(target_pc, "SeekLT");
+            }
+            Insn::IdxGE { target_pc, .. } => {
+                f(target_pc, "IdxGE");
+            }
+            Insn::IdxLE { target_pc, .. } => {
+                f(target_pc, "IdxLE");
+            }
+            Insn::IdxGT { target_pc, .. } => {
+                f(target_pc, "IdxGT");
+            }
+            Insn::IdxLT { target_pc, .. } => {
+                f(target_pc, "IdxLT");
+            }
+            Insn::IsNull { reg: _, target_pc } => {
+                f(target_pc, "IsNull");
+            }
+            Insn::VNext { pc_if_next, .. } => {
+                f(pc_if_next, "VNext");
+            }
+            Insn::VFilter { pc_if_empty, .. } => {
+                f(pc_if_empty, "VFilter");
+            }
+            Insn::NoConflict { target_pc, .. } => {
+                f(target_pc, "NoConflict");
+            }
+            Insn::Found { target_pc, .. } => {
+                f(target_pc, "Found");
+            }
+            Insn::NotFound { target_pc, .. } => {
+                f(target_pc, "NotFound");
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns true if the instruction can modify the database file or its schema.
+    /// Writes to ephemeral tables and sorters do not count.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Insn::Transaction { write: true, .. }
+                | Insn::OpenWrite { .. }
+                | Insn::CreateBtree { .. }
+                | Insn::Destroy { .. }
+                | Insn::DropTable { .. }
+                | Insn::DropIndex { .. }
+                | Insn::SetCookie { .. }
+                | Insn::VCreate { .. }
+                | Insn::VUpdate { .. }
+                | Insn::VDestroy { .. }
+        )
+    }
+
     pub fn to_function(&self) -> InsnFunction {
         match self {
             Insn::Init { .. } => execute::op_init,
@@ -1041,6 +1710,7 @@ impl Insn {
             Insn::String8 { .. } => execute::op_string8,
             Insn::Blob { .. } => execute::op_blob,
             Insn::RowData { .. } => execute::op_row_data,
+            Insn::Offset { .. } => execute::op_offset,
             Insn::RowId { .. } => execute::op_row_id,
             Insn::IdxRowId { .. } => execute::op_idx_row_id,
             Insn::SeekRowid { .. } => execute::op_seek_rowid,
@@ -1062,6 +1732,10 @@ impl Insn {
             Insn::SorterSort { .. } => execute::op_sorter_sort,
             Insn::SorterData { .. } => execute::op_sorter_data,
             Insn::SorterNext { .. } => execute::op_sorter_next,
+            Insn::HashOpen { .. } => execute::op_hash_open,
+            Insn::HashInsert { .. } => execute::op_hash_insert,
+            Insn::HashProbe { .. } => execute::op_hash_probe,
+            Insn::HashNext { .. } => execute::op_hash_next,
             Insn::Function { .. } => execute::op_function,
             Insn::InitCoroutine { .. } => execute::op_init_coroutine,
             Insn::EndCoroutine { .. } => execute::op_end_coroutine,
@@ -1073,6 +1747,7 @@ impl Insn {
             Insn::NewRowid { .. } => execute::op_new_rowid,
             Insn::MustBeInt { .. } => execute::op_must_be_int,
             Insn::SoftNull { .. } => execute::op_soft_null,
+            Insn::MemMax { .. } => execute::op_mem_max,
             Insn::NoConflict { .. } => execute::op_no_conflict,
             Insn::NotExists { .. } => execute::op_not_exists,
             Insn::OffsetLimit { .. } => execute::op_offset_limit,
@@ -1085,6 +1760,7 @@ impl Insn {
             Insn::Close { .. } => execute::op_close,
             Insn::IsNull { .. } => execute::op_is_null,
             Insn::ParseSchema { .. } => execute::op_parse_schema,
+            Insn::LoadAnalysis { .. } => execute::op_load_analysis,
             Insn::ShiftRight { .. } => execute::op_shift_right,
             Insn::ShiftLeft { .. } => execute::op_shift_left,
             Insn::Variable { .. } => execute::op_variable,
@@ -181,6 +181,32 @@ fn construct_glob_regex(pattern: &str) -> Result<Regex, LimboError> {
     }
 }
 
+/// Implements the REGEXP operator: returns true if `text` contains a match for the regular
+/// expression `pattern`. Caches the compiled regex if a cache is provided.
+#[cfg(feature = "regexp")]
+pub fn exec_regexp(
+    regex_cache: Option<&mut HashMap<String, Regex>>,
+    pattern: &str,
+    text: &str,
+) -> Result<bool, LimboError> {
+    let compile = |pattern: &str| {
+        Regex::new(pattern)
+            .map_err(|e| LimboError::InvalidArgument(format!("invalid regular expression: {}", e)))
+    };
+    match regex_cache {
+        Some(cache) => match cache.get(pattern) {
+            Some(re) => Ok(re.is_match(text)),
+            None => {
+                let re = compile(pattern)?;
+                let res = re.is_match(text);
+                cache.insert(pattern.to_string(), re);
+                Ok(res)
+            }
+        },
+        None => Ok(compile(pattern)?.is_match(text)),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -214,4 +240,19 @@ mod test {
         assert!(exec_glob(None, r#"abc[^][*?]efg"#, r#"abcdefg"#));
         assert!(!exec_glob(None, r#"abc[^][*?]efg"#, r#"abc]efg"#));
     }
+
+    #[test]
+    #[cfg(feature = "regexp")]
+    fn test_regexp() {
+        assert!(exec_regexp(None, "b+", "abbc").unwrap());
+        assert!(!exec_regexp(None, "^b", "abc").unwrap());
+        assert!(exec_regexp(None, "^[a-z]+[0-9]$", "abc1").unwrap());
+        assert!(!exec_regexp(None, "^[a-z]+[0-9]$", "abc12").unwrap());
+        assert!(exec_regexp(None, "(", "abc").is_err());
+
+        let mut cache = HashMap::new();
+        assert!(exec_regexp(Some(&mut cache), "c$", "abc").unwrap());
+        assert!(!exec_regexp(Some(&mut cache), "c$", "cab").unwrap());
+        assert_eq!(cache.len(), 1);
+    }
 }
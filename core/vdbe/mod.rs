@@ -20,8 +20,10 @@
 pub mod builder;
 pub mod execute;
 pub mod explain;
+pub mod hash_table;
 pub mod insn;
 pub mod likeop;
+mod optimizer;
 pub mod sorter;
 
 use crate::{
@@ -41,7 +43,7 @@ use crate::{
 #[cfg(feature = "json")]
 use crate::json::JsonCacheCell;
 use crate::{Connection, MvStore, Result, TransactionState};
-use builder::CursorKey;
+use builder::{CursorKey, QueryMode};
 use execute::{
     InsnFunction, InsnFunctionStepResult, OpIdxDeleteState, OpIntegrityCheckState,
     OpOpenEphemeralState,
@@ -168,6 +170,8 @@ macro_rules! return_if_io {
 struct RegexCache {
     like: HashMap<String, Regex>,
     glob: HashMap<String, Regex>,
+    #[cfg(feature = "regexp")]
+    regexp: HashMap<String, Regex>,
 }
 
 impl RegexCache {
@@ -175,6 +179,8 @@ impl RegexCache {
         Self {
             like: HashMap::new(),
             glob: HashMap::new(),
+            #[cfg(feature = "regexp")]
+            regexp: HashMap::new(),
         }
     }
 }
@@ -227,6 +233,14 @@ pub enum Register {
 pub struct Row {
     values: *const Register,
     count: usize,
+    columns: *const [ResultColumnMetadata],
+}
+
+/// Name and declared type of a result column, resolved once when the program is built.
+#[derive(Debug, Clone)]
+pub struct ResultColumnMetadata {
+    pub name: String,
+    pub declared_type: Option<String>,
 }
 
 /// The program state describes the environment in which the program executes.
@@ -245,9 +259,14 @@ pub struct ProgramState {
     interrupted: bool,
     parameters: HashMap<NonZero<usize>, Value>,
     commit_state: CommitState,
+    /// When set, committing does not report the statement's changes to the connection.
+    /// Used by batch execution, which reports the changes of the whole batch once at the end.
+    pub(crate) defer_change_count: bool,
     #[cfg(feature = "json")]
     json_cache: JsonCacheCell,
     op_idx_delete_state: Option<OpIdxDeleteState>,
+    /// Rowid of the row being removed by [Insn::Delete], kept across I/O for the update hook.
+    op_delete_rowid: Option<i64>,
     op_integrity_check_state: OpIntegrityCheckState,
     op_open_ephemeral_state: OpOpenEphemeralState,
 }
@@ -271,9 +290,11 @@ impl ProgramState {
             interrupted: false,
             parameters: HashMap::new(),
             commit_state: CommitState::Ready,
+            defer_change_count: false,
             #[cfg(feature = "json")]
             json_cache: JsonCacheCell::new(),
             op_idx_delete_state: None,
+            op_delete_rowid: None,
             op_integrity_check_state: OpIntegrityCheckState::Start,
             op_open_ephemeral_state: OpOpenEphemeralState::Start,
         }
@@ -299,24 +320,49 @@ impl ProgramState {
         self.parameters.insert(index, value);
     }
 
+    /// Replaces this state with a fresh one sized for a reprepared program, keeping
+    /// the bound parameters.
+    pub(crate) fn reprepare(&mut self, max_registers: usize, max_cursors: usize) {
+        let mut state = ProgramState::new(max_registers, max_cursors);
+        state.parameters = std::mem::take(&mut self.parameters);
+        state.mv_tx_id = self.mv_tx_id;
+        state.defer_change_count = self.defer_change_count;
+        *self = state;
+    }
+
     pub fn get_parameter(&self, index: NonZero<usize>) -> Value {
         self.parameters.get(&index).cloned().unwrap_or(Value::Null)
     }
 
+    /// The value bound to the parameter at `index`, if any.
+    pub(crate) fn bound_parameter(&self, index: NonZero<usize>) -> Option<&Value> {
+        self.parameters.get(&index)
+    }
+
+    /// Returns the state to the one of a program that has not run yet, so that the program
+    /// can be executed again. Bound parameters are cleared; the MVCC transaction and
+    /// [ProgramState::defer_change_count] are kept.
     pub fn reset(&mut self) {
         self.pc = 0;
         self.cursors.borrow_mut().iter_mut().for_each(|c| *c = None);
         self.registers
             .iter_mut()
             .for_each(|r| *r = Register::Value(Value::Null));
+        self.result_row = None;
         self.last_compare = None;
         self.deferred_seeks.iter_mut().for_each(|s| *s = None);
         self.ended_coroutine.0 = [0; 4];
+        self.once = SmallVec::new();
         self.regex_cache.like.clear();
         self.interrupted = false;
         self.parameters.clear();
+        self.commit_state = CommitState::Ready;
         #[cfg(feature = "json")]
-        self.json_cache.clear()
+        self.json_cache.clear();
+        self.op_idx_delete_state = None;
+        self.op_delete_rowid = None;
+        self.op_integrity_check_state = OpIntegrityCheckState::Start;
+        self.op_open_ephemeral_state = OpOpenEphemeralState::Start;
     }
 
     pub fn get_cursor(&self, cursor_id: CursorID) -> std::cell::RefMut<Cursor> {
@@ -348,6 +394,7 @@ macro_rules! must_be_btree_cursor {
             CursorType::BTreeIndex(_) => $state.get_cursor($cursor_id),
             CursorType::Pseudo(_) => panic!("{} on pseudo cursor", $insn_name),
             CursorType::Sorter => panic!("{} on sorter cursor", $insn_name),
+            CursorType::HashTable(_) => panic!("{} on hash table cursor", $insn_name),
             CursorType::VirtualTable(_) => panic!("{} on virtual table cursor", $insn_name),
         };
         cursor
@@ -363,8 +410,12 @@ pub struct Program {
     pub connection: Arc<Connection>,
     pub n_change: Cell<i64>,
     pub change_cnt_on: bool,
+    /// True if the program never writes to the database, see [Insn::is_write].
+    pub is_readonly: bool,
     pub result_columns: Vec<ResultSetColumn>,
+    pub result_column_metadata: Vec<ResultColumnMetadata>,
     pub table_references: TableReferences,
+    pub query_mode: QueryMode,
 }
 
 impl Program {
@@ -382,7 +433,20 @@ impl Program {
             let _ = state.result_row.take();
             let (insn, insn_function) = &self.insns[state.pc as usize];
             trace_insn(self, state.pc as InsnReference, insn);
-            let res = insn_function(self, state, insn, &pager, mv_store.as_ref())?;
+            let res = match insn_function(self, state, insn, &pager, mv_store.as_ref()) {
+                Ok(res) => res,
+                Err(err @ LimboError::QuotaExceeded(_)) => {
+                    // Like SQLITE_FULL in SQLite, running out of pages rolls back the whole
+                    // transaction so that the database file stays within its quota.
+                    self.connection.rollback_session_txn()?;
+                    return Err(err);
+                }
+                Err(err @ LimboError::CommitHookAborted) => {
+                    self.connection.rollback_session_txn()?;
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            };
             match res {
                 InsnFunctionStepResult::Step => {}
                 InsnFunctionStepResult::Done => return Ok(StepResult::Done),
@@ -411,6 +475,7 @@ impl Program {
                     mv_store.commit_tx(*tx_id).unwrap();
                 }
                 mv_transactions.clear();
+                conn.end_vtab_transactions(rollback)?;
             }
             Ok(StepResult::Done)
         } else {
@@ -421,8 +486,10 @@ impl Program {
                 auto_commit,
                 program_state.commit_state
             );
+            let record_changes = self.change_cnt_on && !program_state.defer_change_count;
             if program_state.commit_state == CommitState::Committing {
-                let TransactionState::Write { change_schema } = connection.transaction_state.get()
+                let TransactionState::Write { change_schema, .. } =
+                    connection.transaction_state.get()
                 else {
                     unreachable!("invalid state for write commit step")
                 };
@@ -432,17 +499,19 @@ impl Program {
                     &connection,
                     rollback,
                     change_schema,
+                    record_changes,
                 )
             } else if auto_commit {
                 let current_state = connection.transaction_state.get();
                 tracing::trace!("Auto-commit state: {:?}", current_state);
                 match current_state {
-                    TransactionState::Write { change_schema } => self.step_end_write_txn(
+                    TransactionState::Write { change_schema, .. } => self.step_end_write_txn(
                         &pager,
                         &mut program_state.commit_state,
                         &connection,
                         rollback,
                         change_schema,
+                        record_changes,
                     ),
                     TransactionState::Read => {
                         connection.transaction_state.replace(TransactionState::None);
@@ -452,7 +521,7 @@ impl Program {
                     TransactionState::None => Ok(StepResult::Done),
                 }
             } else {
-                if self.change_cnt_on {
+                if record_changes {
                     self.connection.set_changes(self.n_change.get());
                 }
                 Ok(StepResult::Done)
@@ -468,6 +537,7 @@ impl Program {
         connection: &Connection,
         rollback: bool,
         change_schema: bool,
+        record_changes: bool,
     ) -> Result<StepResult> {
         let cacheflush_status = pager.end_tx(
             rollback,
@@ -477,11 +547,12 @@ impl Program {
         )?;
         match cacheflush_status {
             PagerCacheflushStatus::Done(_) => {
-                if self.change_cnt_on {
+                if record_changes {
                     self.connection.set_changes(self.n_change.get());
                 }
                 connection.transaction_state.replace(TransactionState::None);
                 *commit_state = CommitState::Ready;
+                connection.end_vtab_transactions(rollback)?;
             }
             PagerCacheflushStatus::IO => {
                 tracing::trace!("Cacheflush IO");
@@ -492,11 +563,21 @@ impl Program {
         Ok(StepResult::Done)
     }
 
-    #[rustfmt::skip]
+    /// Returns the bytecode of the program formatted as an EXPLAIN table.
     pub fn explain(&self) -> String {
-        let mut buff = String::with_capacity(1024);
-        buff.push_str("addr  opcode             p1    p2    p3    p4             p5  comment\n");
-        buff.push_str("----  -----------------  ----  ----  ----  -------------  --  -------\n");
+        let mut buff = Vec::with_capacity(1024);
+        self.explain_to(&mut buff)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(buff).expect("explain output is valid UTF-8")
+    }
+
+    /// Writes the bytecode of the program to `w` as a table with the same layout
+    /// as SQLite's EXPLAIN output: `addr`, `opcode`, `p1`, `p2`, `p3`, `p4`, `p5`
+    /// and `comment`, with loop bodies indented.
+    #[rustfmt::skip]
+    pub fn explain_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "addr  opcode         p1    p2    p3    p4             p5  comment")?;
+        writeln!(w, "----  -------------  ----  ----  ----  -------------  --  -------------")?;
         let indent = "  ";
         let indent_counts = get_indent_counts(&self.insns);
         for (addr, (insn, _)) in self.insns.iter().enumerate() {
@@ -506,11 +587,10 @@ impl Program {
                 addr as InsnReference,
                 insn,
                 indent.repeat(indent_count),
-                &mut buff,
-            );
-            buff.push('\n');
+                w,
+            )?;
         }
-        buff
+        Ok(())
     }
 }
 
@@ -574,7 +654,13 @@ fn trace_insn(program: &Program, addr: InsnReference, insn: &Insn) {
     );
 }
 
-fn print_insn(program: &Program, addr: InsnReference, insn: &Insn, indent: String, w: &mut String) {
+fn print_insn<W: std::io::Write>(
+    program: &Program,
+    addr: InsnReference,
+    insn: &Insn,
+    indent: String,
+    w: &mut W,
+) -> std::io::Result<()> {
     let s = explain::insn_to_str(
         program,
         addr,
@@ -588,7 +674,7 @@ fn print_insn(program: &Program, addr: InsnReference, insn: &Insn, indent: Strin
                 .copied()
         }),
     );
-    w.push_str(&s);
+    writeln!(w, "{}", s)
 }
 
 // The indenting rules are(from SQLite):
@@ -699,6 +785,16 @@ impl<'a> FromValueRow<'a> for &'a Value {
 }
 
 impl Row {
+    /// Creates a row pointing to `values`, which must outlive it.
+    #[cfg(feature = "remote")]
+    pub(crate) fn new(values: &[Register], columns: &[ResultColumnMetadata]) -> Self {
+        Self {
+            values: values.as_ptr(),
+            count: values.len(),
+            columns: columns as *const _,
+        }
+    }
+
     pub fn get<'a, T: FromValueRow<'a> + 'a>(&'a self, idx: usize) -> Result<T> {
         let value = unsafe { self.values.add(idx).as_ref().unwrap() };
         let value = match value {
@@ -726,4 +822,9 @@ impl Row {
     pub fn len(&self) -> usize {
         self.count
     }
+
+    pub fn column_name(&self, idx: usize) -> &str {
+        let columns = unsafe { &*self.columns };
+        &columns.get(idx).expect("No column").name
+    }
 }
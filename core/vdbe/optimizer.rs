@@ -0,0 +1,787 @@
+//! Peephole optimizations run on the bytecode of a program once its labels are resolved.
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use super::{insn::RegisterAccess, BranchOffset, Insn, InsnFunction, InsnReference};
+
+/// Registers read and written by an instruction that always continues with the next one.
+struct Effect {
+    reads: [Option<RangeInclusive<usize>>; 2],
+    writes: Option<RangeInclusive<usize>>,
+}
+
+impl Effect {
+    fn new(
+        reads: [Option<RangeInclusive<usize>>; 2],
+        writes: Option<RangeInclusive<usize>>,
+    ) -> Self {
+        Self { reads, writes }
+    }
+
+    fn reads(&self, reg: usize) -> bool {
+        self.reads
+            .iter()
+            .flatten()
+            .any(|range| range.contains(&reg))
+    }
+
+    fn writes(&self, reg: usize) -> bool {
+        self.writes
+            .as_ref()
+            .is_some_and(|range| range.contains(&reg))
+    }
+}
+
+/// Returns the registers `insn` touches, or `None` if it may jump, or may touch registers
+/// this pass doesn't keep track of.
+fn effect(insn: &Insn) -> Option<Effect> {
+    let reg = |reg: usize| Some(reg..=reg);
+    Some(match insn {
+        Insn::Noop => Effect::new([None, None], None),
+        Insn::Integer { dest, .. }
+        | Insn::Real { dest, .. }
+        | Insn::String8 { dest, .. }
+        | Insn::Blob { dest, .. } => Effect::new([None, None], reg(*dest)),
+        Insn::Null { dest, dest_end } | Insn::BeginSubrtn { dest, dest_end } => {
+            Effect::new([None, None], Some(*dest..=dest_end.unwrap_or(*dest)))
+        }
+        Insn::Copy {
+            src_reg,
+            dst_reg,
+            amount,
+        } => Effect::new(
+            [Some(*src_reg..=src_reg + amount), None],
+            Some(*dst_reg..=dst_reg + amount),
+        ),
+        Insn::Add { lhs, rhs, dest }
+        | Insn::Subtract { lhs, rhs, dest }
+        | Insn::Multiply { lhs, rhs, dest }
+        | Insn::Divide { lhs, rhs, dest }
+        | Insn::Remainder { lhs, rhs, dest }
+        | Insn::BitAnd { lhs, rhs, dest }
+        | Insn::BitOr { lhs, rhs, dest }
+        | Insn::ShiftLeft { lhs, rhs, dest }
+        | Insn::ShiftRight { lhs, rhs, dest }
+        | Insn::Concat { lhs, rhs, dest }
+        | Insn::And { lhs, rhs, dest }
+        | Insn::Or { lhs, rhs, dest } => Effect::new([reg(*lhs), reg(*rhs)], reg(*dest)),
+        Insn::Not { reg: src, dest } | Insn::BitNot { reg: src, dest } => {
+            Effect::new([reg(*src), None], reg(*dest))
+        }
+        _ => return None,
+    })
+}
+
+/// The register written by an instruction that does nothing but load a value into it, and
+/// can therefore be dropped if the value is never read.
+fn load_dest(insn: &Insn) -> Option<usize> {
+    match insn {
+        Insn::Integer { dest, .. }
+        | Insn::Real { dest, .. }
+        | Insn::String8 { dest, .. }
+        | Insn::Blob { dest, .. }
+        | Insn::Null {
+            dest,
+            dest_end: None,
+        }
+        | Insn::Copy {
+            dst_reg: dest,
+            amount: 0,
+            ..
+        } => Some(*dest),
+        _ => None,
+    }
+}
+
+/// Finds the loads whose register is overwritten before it is read, e.g. the first of two
+/// `Integer 0 r[1]`. The search stops at the first instruction that may jump or that touches
+/// registers in ways [effect] doesn't describe, so a load is only found dead when every path
+/// from it overwrites the register.
+fn find_dead_stores(insns: &[(Insn, InsnFunction)]) -> Vec<bool> {
+    let mut dead = vec![false; insns.len()];
+    // Going backwards, so that a load read only by a dead Copy is found dead too.
+    for i in (0..insns.len()).rev() {
+        let Some(reg) = load_dest(&insns[i].0) else {
+            continue;
+        };
+        for (j, (insn, _)) in insns.iter().enumerate().skip(i + 1) {
+            if dead[j] {
+                continue;
+            }
+            let Some(effect) = effect(insn) else {
+                break;
+            };
+            if effect.reads(reg) {
+                break;
+            }
+            if effect.writes(reg) {
+                dead[i] = true;
+                break;
+            }
+        }
+    }
+    dead
+}
+
+/// Removes dead register loads from `insns`, updating jump targets and the EXPLAIN comments
+/// to the new instruction offsets.
+pub fn remove_dead_stores(
+    insns: &mut Vec<(Insn, InsnFunction)>,
+    comments: &mut Option<Vec<(InsnReference, &'static str)>>,
+) {
+    let dead = find_dead_stores(insns);
+    if !dead.contains(&true) {
+        return;
+    }
+
+    // new_offset[i] is where instruction i ends up, or the instruction following it if it's
+    // removed, which is where jumps to it must go.
+    let mut new_offset = Vec::with_capacity(insns.len() + 1);
+    let mut removed = 0;
+    for is_dead in dead.iter() {
+        new_offset.push(new_offset.len() - removed);
+        if *is_dead {
+            removed += 1;
+        }
+    }
+    new_offset.push(insns.len() - removed);
+
+    let mut i = 0;
+    insns.retain(|_| {
+        i += 1;
+        !dead[i - 1]
+    });
+    for (insn, _) in insns.iter_mut() {
+        insn.for_each_jump_target(|pc, _| {
+            if let BranchOffset::Offset(offset) = pc {
+                *offset = new_offset[*offset as usize] as InsnReference;
+            }
+        });
+    }
+    if let Some(comments) = comments {
+        comments.retain(|(offset, _)| !dead[*offset as usize]);
+        for (offset, _) in comments.iter_mut() {
+            *offset = new_offset[*offset as usize] as InsnReference;
+        }
+    }
+}
+
+/// Register allocation is skipped for programs whose instruction count times register count
+/// exceeds this, since liveness analysis takes time and memory proportional to it.
+const MAX_ALLOCATION_SIZE: usize = 1 << 24;
+
+/// A set of registers that must stay contiguous, because an instruction accesses them as a
+/// range, e.g. the columns of a `ResultRow`.
+struct Block {
+    /// The first register of the block.
+    start: usize,
+    width: usize,
+}
+
+/// A fixed size bit set, used for sets of blocks and sets of instructions.
+#[derive(Clone, PartialEq, Eq)]
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn difference_with(&mut self, other: &BitSet) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a &= !b;
+        }
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a |= b;
+        }
+    }
+
+    fn is_disjoint(&self, other: &BitSet) -> bool {
+        self.0.iter().zip(other.0.iter()).all(|(a, b)| a & b == 0)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word_idx, word)| {
+            (0..64usize)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| word_idx * 64 + bit)
+        })
+    }
+}
+
+/// Where control may go after an instruction.
+enum Successors {
+    Insns(Vec<usize>),
+    /// Any instruction, for jumps to addresses in registers that we can't track.
+    Any,
+}
+
+/// Computes the successors of every instruction, or `None` if a jump target is unresolved.
+fn successors(
+    insns: &mut [(Insn, InsnFunction)],
+    accesses: &[Vec<(usize, usize, RegisterAccess)>],
+) -> Option<Vec<Successors>> {
+    // Return jumps back after the Gosub that called it, Yield jumps into the coroutine it
+    // resumes or back after the Yield that resumed it, and EndCoroutine jumps to the Yield
+    // that resumed it. The addresses are only known if the registers holding them aren't
+    // written by other instructions.
+    let mut return_targets: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut coroutine_starts: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut yields: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (pc, (insn, _)) in insns.iter().enumerate() {
+        match insn {
+            Insn::Gosub { return_reg, .. } => {
+                return_targets.entry(*return_reg).or_default().push(pc + 1)
+            }
+            Insn::InitCoroutine {
+                yield_reg,
+                start_offset: BranchOffset::Offset(start),
+                ..
+            } => coroutine_starts
+                .entry(*yield_reg)
+                .or_default()
+                .push(*start as usize),
+            Insn::Yield { yield_reg, .. } => yields.entry(*yield_reg).or_default().push(pc),
+            _ => {}
+        }
+    }
+    let mut untracked = Vec::new();
+    for (pc, (insn, _)) in insns.iter().enumerate() {
+        if matches!(
+            insn,
+            Insn::Gosub { .. }
+                | Insn::InitCoroutine { .. }
+                | Insn::Yield { .. }
+                | Insn::Null { .. }
+                | Insn::BeginSubrtn { .. }
+        ) {
+            continue;
+        }
+        for (start, count, access) in accesses[pc].iter() {
+            if *access != RegisterAccess::Read {
+                untracked.extend(*start..*start + *count);
+            }
+        }
+    }
+    let tracked = |reg: &usize| !untracked.contains(reg);
+
+    let len = insns.len();
+    let mut result = Vec::with_capacity(len);
+    for (pc, (insn, _)) in insns.iter_mut().enumerate() {
+        let mut targets = Vec::new();
+        let mut valid = true;
+        insn.for_each_jump_target(|target, _| match target {
+            BranchOffset::Offset(offset) => targets.push(*offset as usize),
+            _ => valid = false,
+        });
+        if !valid || targets.iter().any(|target| *target >= len) {
+            return None;
+        }
+        let next = (pc + 1 < len).then_some(pc + 1);
+        let successors = match &*insn {
+            Insn::Halt { .. } => Successors::Insns(Vec::new()),
+            Insn::Init { .. } | Insn::Goto { .. } | Insn::Jump { .. } | Insn::Gosub { .. } => {
+                Successors::Insns(targets)
+            }
+            Insn::InitCoroutine {
+                jump_on_definition: BranchOffset::Offset(jump),
+                start_offset: BranchOffset::Offset(start),
+                ..
+            } => {
+                // A jump_on_definition of 0 means falling through.
+                let jump = if *jump == 0 {
+                    next
+                } else {
+                    Some(*jump as usize)
+                };
+                Successors::Insns(jump.into_iter().chain([*start as usize]).collect())
+            }
+            Insn::Return {
+                return_reg,
+                can_fallthrough,
+            } => {
+                if !tracked(return_reg) {
+                    Successors::Any
+                } else {
+                    let mut targets = return_targets.get(return_reg).cloned().unwrap_or_default();
+                    if *can_fallthrough {
+                        targets.extend(next);
+                    }
+                    Successors::Insns(targets)
+                }
+            }
+            Insn::Yield { yield_reg, .. } => {
+                if !tracked(yield_reg) {
+                    Successors::Any
+                } else {
+                    targets.extend(coroutine_starts.get(yield_reg).into_iter().flatten());
+                    targets.extend(
+                        yields
+                            .get(yield_reg)
+                            .into_iter()
+                            .flatten()
+                            .map(|pc| pc + 1)
+                            .filter(|pc| *pc < len),
+                    );
+                    Successors::Insns(targets)
+                }
+            }
+            Insn::EndCoroutine { yield_reg } => {
+                if !tracked(yield_reg) {
+                    Successors::Any
+                } else {
+                    Successors::Insns(yields.get(yield_reg).cloned().unwrap_or_default())
+                }
+            }
+            _ => {
+                targets.extend(next);
+                Successors::Insns(targets)
+            }
+        };
+        result.push(successors);
+    }
+    Some(result)
+}
+
+/// Renumbers the registers of the program so that registers that are never live at the
+/// same time share a number, and returns the number of registers the program needs.
+///
+/// A register is live at an instruction if its value may be read later. Registers accessed
+/// as a range are kept together in a [Block], and the blocks are assigned the lowest numbers
+/// that aren't used by blocks with a register live at the same instructions. If the registers accessed by
+/// an instruction aren't fully known, the program is left as is.
+pub fn allocate_registers(insns: &mut [(Insn, InsnFunction)], num_registers: usize) -> usize {
+    // The registers each instruction accesses. Register 0 is reserved, some instructions use
+    // it to mean "no register".
+    let mut accesses = Vec::with_capacity(insns.len());
+    for (insn, _) in insns.iter_mut() {
+        let mut insn_accesses = Vec::new();
+        let mut in_bounds = true;
+        let known = insn.for_each_register(|start, count, access| {
+            if *start == 0 || count == 0 {
+                return;
+            }
+            in_bounds &= *start + count <= num_registers;
+            insn_accesses.push((*start, count, access));
+        });
+        if !known || !in_bounds {
+            return num_registers;
+        }
+        accesses.push(insn_accesses);
+    }
+
+    // Group the registers into blocks. Overlapping ranges end up in the same block.
+    let mut range_end = vec![None; num_registers];
+    for (start, count, _) in accesses.iter().flatten() {
+        let end = start + count - 1;
+        range_end[*start] = Some(range_end[*start].map_or(end, |e: usize| e.max(end)));
+    }
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut block_of = vec![None; num_registers];
+    let mut block_end = 0;
+    for reg in 1..num_registers {
+        if !blocks.is_empty() && reg <= block_end {
+            blocks.last_mut().unwrap().width += 1;
+        } else if range_end[reg].is_some() {
+            blocks.push(Block {
+                start: reg,
+                width: 1,
+            });
+        } else {
+            continue;
+        }
+        block_of[reg] = Some(blocks.len() - 1);
+        block_end = block_end.max(range_end[reg].unwrap_or(0));
+    }
+    if blocks.is_empty() || insns.len().saturating_mul(num_registers) > MAX_ALLOCATION_SIZE {
+        return num_registers;
+    }
+
+    let Some(successors) = successors(insns, &accesses) else {
+        return num_registers;
+    };
+
+    // The registers read, written and overwritten without being read by each instruction.
+    let mut uses = Vec::with_capacity(insns.len());
+    let mut defs = Vec::with_capacity(insns.len());
+    let mut kills = Vec::with_capacity(insns.len());
+    for insn_accesses in accesses.iter() {
+        let mut used = BitSet::new(num_registers);
+        let mut defined = BitSet::new(num_registers);
+        let mut killed = BitSet::new(num_registers);
+        for (start, count, access) in insn_accesses.iter() {
+            for reg in *start..*start + *count {
+                match access {
+                    RegisterAccess::Read => used.insert(reg),
+                    RegisterAccess::Update => {
+                        used.insert(reg);
+                        defined.insert(reg);
+                    }
+                    RegisterAccess::Write => {
+                        defined.insert(reg);
+                        killed.insert(reg);
+                    }
+                }
+            }
+        }
+        uses.push(used);
+        defs.push(defined);
+        kills.push(killed);
+    }
+
+    // live_in[pc] = uses[pc] + (live_out[pc] - kills[pc]), where live_out[pc] is the union of
+    // live_in of the successors, iterated until nothing changes.
+    let mut live_in = vec![BitSet::new(num_registers); insns.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut live_anywhere = BitSet::new(num_registers);
+        for live in live_in.iter() {
+            live_anywhere.union_with(live);
+        }
+        for pc in (0..insns.len()).rev() {
+            let mut live = match &successors[pc] {
+                Successors::Any => live_anywhere.clone(),
+                Successors::Insns(targets) => {
+                    let mut live = BitSet::new(num_registers);
+                    for target in targets {
+                        live.union_with(&live_in[*target]);
+                    }
+                    live
+                }
+            };
+            live.difference_with(&kills[pc]);
+            live.union_with(&uses[pc]);
+            if live != live_in[pc] {
+                live_in[pc] = live;
+                changed = true;
+            }
+        }
+    }
+
+    // The instructions at which a register of each block holds a value that may be read
+    // later, or is written. Two blocks can share registers if these don't overlap.
+    let mut occupied = vec![BitSet::new(insns.len()); blocks.len()];
+    for pc in 0..insns.len() {
+        for reg in live_in[pc].iter().chain(defs[pc].iter()) {
+            if let Some(block) = block_of[reg] {
+                occupied[block].insert(pc);
+            }
+        }
+    }
+
+    // Assign each block the lowest registers that are free at all its instructions.
+    // registers[i] holds the instructions at which new register i + 1 is occupied.
+    let mut registers: Vec<BitSet> = Vec::new();
+    let mut new_start = vec![0; blocks.len()];
+    for (block, Block { width, .. }) in blocks.iter().enumerate() {
+        let start = (0..)
+            .find(|start| {
+                registers
+                    .iter()
+                    .skip(*start)
+                    .take(*width)
+                    .all(|reg| reg.is_disjoint(&occupied[block]))
+            })
+            .unwrap();
+        while registers.len() < start + width {
+            registers.push(BitSet::new(insns.len()));
+        }
+        for reg in registers[start..start + width].iter_mut() {
+            reg.union_with(&occupied[block]);
+        }
+        new_start[block] = start + 1;
+    }
+    let new_num_registers = registers.len() + 1;
+    if new_num_registers >= num_registers {
+        return num_registers;
+    }
+
+    for (insn, _) in insns.iter_mut() {
+        insn.for_each_register(|reg, count, _| {
+            if *reg == 0 || count == 0 {
+                return;
+            }
+            let block = block_of[*reg].unwrap();
+            *reg = new_start[block] + (*reg - blocks[block].start);
+        });
+    }
+    new_num_registers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(insns: Vec<Insn>) -> Vec<(Insn, InsnFunction)> {
+        insns
+            .into_iter()
+            .map(|insn| {
+                let function = insn.to_function();
+                (insn, function)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_load_is_removed() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 0, dest: 1 },
+            Insn::Integer { value: 0, dest: 1 },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 1,
+            },
+        ]);
+        remove_dead_stores(&mut insns, &mut None);
+        assert_eq!(insns.len(), 2);
+    }
+
+    #[test]
+    fn test_load_read_before_overwrite_is_kept() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 1, dest: 1 },
+            Insn::Add {
+                lhs: 1,
+                rhs: 1,
+                dest: 2,
+            },
+            Insn::Integer { value: 2, dest: 1 },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 2,
+            },
+        ]);
+        remove_dead_stores(&mut insns, &mut None);
+        assert_eq!(insns.len(), 4);
+    }
+
+    #[test]
+    fn test_load_before_jump_is_kept() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 1, dest: 1 },
+            Insn::Goto {
+                target_pc: BranchOffset::Offset(3),
+            },
+            Insn::Integer { value: 2, dest: 1 },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 1,
+            },
+        ]);
+        remove_dead_stores(&mut insns, &mut None);
+        assert_eq!(insns.len(), 4);
+    }
+
+    #[test]
+    fn test_load_only_read_by_dead_copy_is_removed() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 1, dest: 1 },
+            Insn::Copy {
+                src_reg: 1,
+                dst_reg: 2,
+                amount: 0,
+            },
+            Insn::Null {
+                dest: 1,
+                dest_end: Some(2),
+            },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 2,
+            },
+        ]);
+        remove_dead_stores(&mut insns, &mut None);
+        assert_eq!(insns.len(), 2);
+    }
+
+    #[test]
+    fn test_jump_targets_are_remapped() {
+        let mut insns = program(vec![
+            Insn::Goto {
+                target_pc: BranchOffset::Offset(2),
+            },
+            Insn::Halt {
+                err_code: 0,
+                description: String::new(),
+            },
+            Insn::String8 {
+                value: "a".to_string(),
+                dest: 1,
+            },
+            Insn::String8 {
+                value: "b".to_string(),
+                dest: 1,
+            },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 1,
+            },
+            Insn::Goto {
+                target_pc: BranchOffset::Offset(4),
+            },
+        ]);
+        let mut comments = Some(vec![(2, "removed"), (4, "kept")]);
+        remove_dead_stores(&mut insns, &mut comments);
+        assert_eq!(insns.len(), 5);
+        assert!(matches!(
+            insns[0].0,
+            Insn::Goto {
+                target_pc: BranchOffset::Offset(2)
+            }
+        ));
+        assert!(matches!(
+            insns[4].0,
+            Insn::Goto {
+                target_pc: BranchOffset::Offset(3)
+            }
+        ));
+        assert_eq!(comments, Some(vec![(3, "kept")]));
+    }
+
+    fn integer_dest(insn: &Insn) -> usize {
+        match insn {
+            Insn::Integer { dest, .. } => *dest,
+            insn => panic!("expected Integer, got {insn:?}"),
+        }
+    }
+
+    #[test]
+    fn test_dead_registers_are_reused() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 1, dest: 1 },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 1,
+            },
+            Insn::Integer { value: 2, dest: 2 },
+            Insn::ResultRow {
+                start_reg: 2,
+                count: 1,
+            },
+            Insn::Halt {
+                err_code: 0,
+                description: String::new(),
+            },
+        ]);
+        assert_eq!(allocate_registers(&mut insns, 3), 2);
+        assert_eq!(integer_dest(&insns[0].0), 1);
+        assert_eq!(integer_dest(&insns[2].0), 1);
+    }
+
+    #[test]
+    fn test_register_live_across_loop_is_not_reused() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 3, dest: 1 },
+            Insn::Integer { value: 7, dest: 2 },
+            Insn::ResultRow {
+                start_reg: 2,
+                count: 1,
+            },
+            Insn::Integer { value: 8, dest: 3 },
+            Insn::ResultRow {
+                start_reg: 3,
+                count: 1,
+            },
+            Insn::DecrJumpZero {
+                reg: 1,
+                target_pc: BranchOffset::Offset(7),
+            },
+            Insn::Goto {
+                target_pc: BranchOffset::Offset(1),
+            },
+            Insn::Halt {
+                err_code: 0,
+                description: String::new(),
+            },
+        ]);
+        assert_eq!(allocate_registers(&mut insns, 4), 3);
+        let counter = integer_dest(&insns[0].0);
+        assert_eq!(integer_dest(&insns[1].0), integer_dest(&insns[3].0));
+        assert_ne!(integer_dest(&insns[1].0), counter);
+        assert!(matches!(insns[5].0, Insn::DecrJumpZero { reg, .. } if reg == counter));
+    }
+
+    #[test]
+    fn test_ranges_stay_contiguous() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 1, dest: 1 },
+            Insn::Integer { value: 2, dest: 2 },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 2,
+            },
+            Insn::Integer { value: 3, dest: 3 },
+            Insn::Integer { value: 4, dest: 4 },
+            Insn::ResultRow {
+                start_reg: 3,
+                count: 2,
+            },
+            Insn::Halt {
+                err_code: 0,
+                description: String::new(),
+            },
+        ]);
+        assert_eq!(allocate_registers(&mut insns, 5), 3);
+        assert_eq!(integer_dest(&insns[3].0), 1);
+        assert_eq!(integer_dest(&insns[4].0), 2);
+        assert!(matches!(
+            insns[5].0,
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_register_live_across_gosub_is_not_reused() {
+        let mut insns = program(vec![
+            Insn::Integer { value: 9, dest: 1 },
+            Insn::ResultRow {
+                start_reg: 1,
+                count: 1,
+            },
+            Insn::Integer { value: 1, dest: 2 },
+            Insn::Gosub {
+                target_pc: BranchOffset::Offset(6),
+                return_reg: 3,
+            },
+            Insn::ResultRow {
+                start_reg: 2,
+                count: 1,
+            },
+            Insn::Halt {
+                err_code: 0,
+                description: String::new(),
+            },
+            Insn::Integer { value: 5, dest: 4 },
+            Insn::ResultRow {
+                start_reg: 4,
+                count: 1,
+            },
+            Insn::Return {
+                return_reg: 3,
+                can_fallthrough: false,
+            },
+        ]);
+        assert_eq!(allocate_registers(&mut insns, 5), 4);
+        // Register 2 is read after the subroutine returns, so the subroutine can't use it.
+        let live_across_call = integer_dest(&insns[2].0);
+        let Insn::Gosub { return_reg, .. } = insns[3].0 else {
+            panic!("expected Gosub");
+        };
+        let in_subroutine = integer_dest(&insns[6].0);
+        assert_ne!(in_subroutine, live_across_call);
+        assert_ne!(in_subroutine, return_reg);
+        assert_ne!(live_across_call, return_reg);
+        assert!(matches!(insns[8].0, Insn::Return { return_reg: reg, .. } if reg == return_reg));
+    }
+}
@@ -1,5 +1,9 @@
+use crate::dbstat::{DbStatVirtualTable, DbStatVirtualTableCursor, DBSTAT_NAME};
+#[cfg(feature = "json")]
+use crate::json::{JsonVirtualTable, JsonVirtualTableCursor};
 use crate::pragma::{PragmaVirtualTable, PragmaVirtualTableCursor};
 use crate::schema::Column;
+use crate::types::CursorResult;
 use crate::util::{columns_from_create_table_body, vtable_args};
 use crate::{Connection, LimboError, SymbolTable, Value};
 use fallible_iterator::FallibleIterator;
@@ -13,6 +17,9 @@ use turso_sqlite3_parser::{ast, lexer::sql::Parser};
 #[derive(Debug, Clone)]
 enum VirtualTableType {
     Pragma(PragmaVirtualTable),
+    #[cfg(feature = "json")]
+    Json(JsonVirtualTable),
+    DbStat(DbStatVirtualTable),
     External(ExtVirtualTable),
 }
 
@@ -42,6 +49,11 @@ impl VirtualTable {
         } else if let Some(pragma_name) = name.strip_prefix("pragma_") {
             PragmaVirtualTable::create(pragma_name)
                 .map(|(vtab, columns)| (VirtualTableType::Pragma(vtab), columns))?
+        } else if let Some((vtab, columns)) = Self::json_function(name) {
+            (vtab, columns)
+        } else if name == DBSTAT_NAME {
+            let (vtab, columns) = DbStatVirtualTable::create();
+            (VirtualTableType::DbStat(vtab), columns)
         } else {
             return Err(LimboError::ParseError(format!(
                 "No such table-valued function: {}",
@@ -59,6 +71,17 @@ impl VirtualTable {
         Ok(Rc::new(vtab))
     }
 
+    #[cfg(feature = "json")]
+    fn json_function(name: &str) -> Option<(VirtualTableType, String)> {
+        JsonVirtualTable::create(name)
+            .map(|(vtab, columns)| (VirtualTableType::Json(vtab), columns))
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn json_function(_name: &str) -> Option<(VirtualTableType, String)> {
+        None
+    }
+
     pub fn table(
         tbl_name: Option<&str>,
         module_name: &str,
@@ -94,26 +117,64 @@ impl VirtualTable {
     pub(crate) fn open(&self, conn: Arc<Connection>) -> crate::Result<VirtualTableCursor> {
         match &self.vtab_type {
             VirtualTableType::Pragma(table) => Ok(VirtualTableCursor::Pragma(table.open(conn)?)),
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(table) => Ok(VirtualTableCursor::Json(table.open()?)),
+            VirtualTableType::DbStat(table) => Ok(VirtualTableCursor::DbStat(table.open(conn)?)),
             VirtualTableType::External(table) => {
                 Ok(VirtualTableCursor::External(table.open(conn)?))
             }
         }
     }
 
-    pub(crate) fn update(&self, args: &[Value]) -> crate::Result<Option<i64>> {
+    pub(crate) fn update(
+        &self,
+        args: &[Value],
+        conn: Arc<Connection>,
+    ) -> crate::Result<Option<i64>> {
         match &self.vtab_type {
-            VirtualTableType::Pragma(_) => Err(LimboError::ReadOnly),
-            VirtualTableType::External(table) => table.update(args),
+            VirtualTableType::Pragma(_) | VirtualTableType::DbStat(_) => Err(LimboError::ReadOnly),
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(_) => Err(LimboError::ReadOnly),
+            VirtualTableType::External(table) => table.update(args, conn),
         }
     }
 
     pub(crate) fn destroy(&self) -> crate::Result<()> {
         match &self.vtab_type {
-            VirtualTableType::Pragma(_) => Ok(()),
+            VirtualTableType::Pragma(_) | VirtualTableType::DbStat(_) => Ok(()),
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(_) => Ok(()),
             VirtualTableType::External(table) => table.destroy(),
         }
     }
 
+    pub(crate) fn begin(&self) -> crate::Result<()> {
+        match &self.vtab_type {
+            VirtualTableType::Pragma(_) | VirtualTableType::DbStat(_) => Ok(()),
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(_) => Ok(()),
+            VirtualTableType::External(table) => table.begin(),
+        }
+    }
+
+    pub(crate) fn commit(&self) -> crate::Result<()> {
+        match &self.vtab_type {
+            VirtualTableType::Pragma(_) | VirtualTableType::DbStat(_) => Ok(()),
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(_) => Ok(()),
+            VirtualTableType::External(table) => table.commit(),
+        }
+    }
+
+    pub(crate) fn rollback(&self) -> crate::Result<()> {
+        match &self.vtab_type {
+            VirtualTableType::Pragma(_) | VirtualTableType::DbStat(_) => Ok(()),
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(_) => Ok(()),
+            VirtualTableType::External(table) => table.rollback(),
+        }
+    }
+
     pub(crate) fn best_index(
         &self,
         constraints: &[ConstraintInfo],
@@ -126,6 +187,9 @@ impl VirtualTable {
                 // estimation is not currently implemented.
                 Default::default()
             }
+            #[cfg(feature = "json")]
+            VirtualTableType::Json(_) => Default::default(),
+            VirtualTableType::DbStat(_) => Default::default(),
             VirtualTableType::External(table) => table.best_index(constraints, order_by),
         }
     }
@@ -133,6 +197,9 @@ impl VirtualTable {
 
 pub enum VirtualTableCursor {
     Pragma(PragmaVirtualTableCursor),
+    #[cfg(feature = "json")]
+    Json(JsonVirtualTableCursor),
+    DbStat(DbStatVirtualTableCursor),
     External(ExtVirtualTableCursor),
 }
 
@@ -140,6 +207,9 @@ impl VirtualTableCursor {
     pub(crate) fn next(&mut self) -> crate::Result<bool> {
         match self {
             VirtualTableCursor::Pragma(cursor) => cursor.next(),
+            #[cfg(feature = "json")]
+            VirtualTableCursor::Json(cursor) => cursor.next(),
+            VirtualTableCursor::DbStat(cursor) => cursor.next(),
             VirtualTableCursor::External(cursor) => cursor.next(),
         }
     }
@@ -147,6 +217,9 @@ impl VirtualTableCursor {
     pub(crate) fn rowid(&self) -> i64 {
         match self {
             VirtualTableCursor::Pragma(cursor) => cursor.rowid(),
+            #[cfg(feature = "json")]
+            VirtualTableCursor::Json(cursor) => cursor.rowid(),
+            VirtualTableCursor::DbStat(cursor) => cursor.rowid(),
             VirtualTableCursor::External(cursor) => cursor.rowid(),
         }
     }
@@ -154,6 +227,9 @@ impl VirtualTableCursor {
     pub(crate) fn column(&self, column: usize) -> crate::Result<Value> {
         match self {
             VirtualTableCursor::Pragma(cursor) => cursor.column(column),
+            #[cfg(feature = "json")]
+            VirtualTableCursor::Json(cursor) => cursor.column(column),
+            VirtualTableCursor::DbStat(cursor) => cursor.column(column),
             VirtualTableCursor::External(cursor) => cursor.column(column),
         }
     }
@@ -164,12 +240,15 @@ impl VirtualTableCursor {
         idx_str: Option<String>,
         arg_count: usize,
         args: Vec<Value>,
-    ) -> crate::Result<bool> {
+    ) -> crate::Result<CursorResult<bool>> {
         match self {
-            VirtualTableCursor::Pragma(cursor) => cursor.filter(args),
-            VirtualTableCursor::External(cursor) => {
-                cursor.filter(idx_num, idx_str, arg_count, args)
-            }
+            VirtualTableCursor::Pragma(cursor) => cursor.filter(args).map(CursorResult::Ok),
+            #[cfg(feature = "json")]
+            VirtualTableCursor::Json(cursor) => cursor.filter(args).map(CursorResult::Ok),
+            VirtualTableCursor::DbStat(cursor) => cursor.filter(args),
+            VirtualTableCursor::External(cursor) => cursor
+                .filter(idx_num, idx_str, arg_count, args)
+                .map(CursorResult::Ok),
         }
     }
 }
@@ -238,9 +317,15 @@ impl ExtVirtualTable {
         Ok((vtab, schema))
     }
 
-    /// Accepts a pointer connection that owns the VTable, that the module
-    /// can optionally use to query the other tables.
-    fn open(&self, conn: Arc<Connection>) -> crate::Result<ExtVirtualTableCursor> {
+    /// Returns the extension handle for `conn`, the connection running the statement that
+    /// invokes the module. The handle is reused as long as the invoking connection is the same.
+    fn ext_connection(&self, conn: Arc<Connection>) -> *mut turso_ext::Conn {
+        if let Some(ext_conn_ptr) = *self.connection_ptr.borrow() {
+            let current = unsafe { &*((*ext_conn_ptr)._ctx as *const Arc<Connection>) };
+            if Arc::ptr_eq(current, &conn) {
+                return ext_conn_ptr;
+            }
+        }
         // we need a Weak<Connection> to upgrade and call from the extension.
         let weak_box: *mut Arc<Connection> = Box::into_raw(Box::new(conn));
         let conn = turso_ext::Conn::new(
@@ -252,17 +337,27 @@ impl ExtVirtualTable {
         let ext_conn_ptr = Box::into_raw(Box::new(conn));
         // store the leaked connection pointer on the table so it can be freed on drop
         *self.connection_ptr.borrow_mut() = Some(ext_conn_ptr);
+        ext_conn_ptr
+    }
+
+    /// Accepts a pointer connection that owns the VTable, that the module
+    /// can optionally use to query the other tables.
+    fn open(&self, conn: Arc<Connection>) -> crate::Result<ExtVirtualTableCursor> {
+        let ext_conn_ptr = self.ext_connection(conn);
         let cursor = unsafe { (self.implementation.open)(self.table_ptr, ext_conn_ptr) };
         ExtVirtualTableCursor::new(cursor, self.implementation.clone())
     }
 
-    fn update(&self, args: &[Value]) -> crate::Result<Option<i64>> {
+    /// SQL the module runs on `conn` during the update joins the invoking statement's transaction.
+    fn update(&self, args: &[Value], conn: Arc<Connection>) -> crate::Result<Option<i64>> {
+        let ext_conn_ptr = self.ext_connection(conn);
         let arg_count = args.len();
         let ext_args = args.iter().map(|arg| arg.to_ffi()).collect::<Vec<_>>();
         let newrowid = 0i64;
         let rc = unsafe {
             (self.implementation.update)(
                 self.table_ptr,
+                ext_conn_ptr,
                 arg_count as i32,
                 ext_args.as_ptr(),
                 &newrowid as *const _ as *mut i64,
@@ -287,6 +382,30 @@ impl ExtVirtualTable {
             _ => Err(LimboError::ExtensionError(rc.to_string())),
         }
     }
+
+    fn begin(&self) -> crate::Result<()> {
+        let rc = unsafe { (self.implementation.begin)(self.table_ptr) };
+        match rc {
+            ResultCode::OK => Ok(()),
+            _ => Err(LimboError::ExtensionError(rc.to_string())),
+        }
+    }
+
+    fn commit(&self) -> crate::Result<()> {
+        let rc = unsafe { (self.implementation.commit)(self.table_ptr) };
+        match rc {
+            ResultCode::OK => Ok(()),
+            _ => Err(LimboError::ExtensionError(rc.to_string())),
+        }
+    }
+
+    fn rollback(&self) -> crate::Result<()> {
+        let rc = unsafe { (self.implementation.rollback)(self.table_ptr) };
+        match rc {
+            ResultCode::OK => Ok(()),
+            _ => Err(LimboError::ExtensionError(rc.to_string())),
+        }
+    }
 }
 
 pub struct ExtVirtualTableCursor {
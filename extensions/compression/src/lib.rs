@@ -0,0 +1,529 @@
+//! VFS that compresses databases with LZ4.
+//!
+//! Files are split into blocks of 4096 bytes, one per page with the default page size, that are
+//! compressed independently. Every block owns a slot of half its size, so a page that compresses
+//! to less than that is read and written with a single 2 KiB I/O. The remainder of pages that
+//! don't compress that well spills into an overflow slot. Slots are laid out in extents of 16
+//! blocks, their primary slots first and their overflow slots after, so overflow slots that are
+//! never written stay holes on file systems that support sparse files.
+//!
+//! ## Example usage:
+//!
+//! ```text
+//! .load target/debug/liblimbo_compression
+//! .open compressed.db compressed
+//! ```
+//!
+//! Each file starts with a header holding the compression algorithm, its parameters and the
+//! size of the uncompressed content. It is kept in front of the file rather than in the reserved
+//! bytes of the database header, so that the engine reads page 1 exactly as it wrote it and so
+//! that the WAL, whose frames aren't pages, is compressed the same way.
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+use turso_ext::{register_extension, ExtResult, ResultCode, VfsDerive, VfsExtension, VfsFile};
+
+register_extension! {
+    vfs: { CompressedFS },
+}
+
+const MAGIC: &[u8; 8] = b"LIMBOCMP";
+/// The magic, the algorithm, its level, reserved bytes, then the uncompressed size.
+const HEADER_SIZE: usize = 32;
+const ALGORITHM_OFFSET: usize = 8;
+const LEVEL_OFFSET: usize = 9;
+const SIZE_OFFSET: usize = 24;
+const BLOCK_SIZE: usize = 4096;
+const SLOT_SIZE: usize = BLOCK_SIZE / 2;
+/// Each slot starts with the length of the stored block, with [RAW_FLAG] set if it wasn't
+/// compressed.
+const RECORD_HEADER_SIZE: usize = 2;
+const RAW_FLAG: u16 = 0x8000;
+const INLINE_CAPACITY: usize = SLOT_SIZE - RECORD_HEADER_SIZE;
+const OVERFLOW_SLOT_SIZE: usize = BLOCK_SIZE - INLINE_CAPACITY;
+const EXTENT_BLOCKS: u64 = 16;
+const EXTENT_SIZE: u64 = EXTENT_BLOCKS * (SLOT_SIZE + OVERFLOW_SLOT_SIZE) as u64;
+
+/// Compression algorithms a file may be written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Algorithm {
+    Lz4 = 1,
+}
+
+impl Algorithm {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Algorithm::Lz4),
+            _ => None,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Lz4 => lz4_flex::block::compress(data),
+        }
+    }
+
+    fn decompress(self, data: &[u8], len: usize) -> ExtResult<Vec<u8>> {
+        match self {
+            Algorithm::Lz4 => lz4_flex::block::decompress(data, len).map_err(|e| {
+                log::error!("failed to decompress block: {e}");
+                ResultCode::Corrupt
+            }),
+        }
+    }
+}
+
+/// The compressed VFS, storing its files with [FileFS].
+#[derive(VfsDerive, Default)]
+pub struct CompressedFS {
+    vfs: CompressedVfs<FileFS>,
+}
+
+impl VfsExtension for CompressedFS {
+    const NAME: &'static str = "compressed";
+    type File = CompressedFile<FileFS>;
+
+    fn open_file(&self, path: &str, flags: i32, direct: bool) -> ExtResult<Self::File> {
+        log::debug!(
+            "opening file with compressed VFS: {} flags: {}",
+            path,
+            flags
+        );
+        self.vfs.open_file(path, flags, direct)
+    }
+
+    fn delete_file(&self, path: &str) -> ExtResult<()> {
+        log::debug!("deleting file with compressed VFS: {}", path);
+        self.vfs.delete_file(path)
+    }
+
+    fn truncate_file(&self, file: &Self::File, size: i64) -> ExtResult<()> {
+        log::debug!("truncating file with compressed VFS: size: {size}");
+        self.vfs.truncate_file(file, size)
+    }
+}
+
+/// Compresses the files of the VFS `V`.
+#[derive(Default)]
+pub struct CompressedVfs<V: VfsExtension> {
+    inner: V,
+}
+
+impl<V: VfsExtension> CompressedVfs<V> {
+    pub fn new(inner: V) -> Self {
+        Self { inner }
+    }
+
+    pub fn open_file(&self, path: &str, flags: i32, direct: bool) -> ExtResult<CompressedFile<V>> {
+        let mut file = self.inner.open_file(path, flags, direct)?;
+        let (algorithm, size) = if file.size() <= 0 {
+            let mut header = [0u8; HEADER_SIZE];
+            header[..MAGIC.len()].copy_from_slice(MAGIC);
+            header[ALGORITHM_OFFSET] = Algorithm::Lz4 as u8;
+            // LZ4 has no levels, the byte is there for algorithms that do.
+            header[LEVEL_OFFSET] = 0;
+            write_all(&mut file, &header, 0)?;
+            (Algorithm::Lz4, 0)
+        } else {
+            let mut header = [0u8; HEADER_SIZE];
+            read_exact(&mut file, &mut header, 0)?;
+            if &header[..MAGIC.len()] != MAGIC {
+                log::error!("{path} is not a compressed database");
+                return Err(ResultCode::Error);
+            }
+            let Some(algorithm) = Algorithm::from_u8(header[ALGORITHM_OFFSET]) else {
+                log::error!(
+                    "{path} uses unsupported compression algorithm {}",
+                    header[ALGORITHM_OFFSET]
+                );
+                return Err(ResultCode::Unimplemented);
+            };
+            let size = u64::from_be_bytes(header[SIZE_OFFSET..].try_into().unwrap());
+            (algorithm, size)
+        };
+        Ok(CompressedFile {
+            state: Mutex::new(FileState {
+                inner: file,
+                algorithm,
+                size,
+            }),
+        })
+    }
+
+    pub fn delete_file(&self, path: &str) -> ExtResult<()> {
+        self.inner.delete_file(path)
+    }
+
+    pub fn truncate_file(&self, file: &CompressedFile<V>, size: i64) -> ExtResult<()> {
+        let mut state = file.state.lock().map_err(|_| ResultCode::Error)?;
+        let size = size as u64;
+        if size > state.size {
+            let zeroes = vec![0u8; (size - state.size) as usize];
+            let offset = state.size;
+            return state.write_at(&zeroes, offset).map(|_| ());
+        }
+        let tail = (size % BLOCK_SIZE as u64) as usize;
+        if tail != 0 {
+            // The last block is cut short, so it has to be compressed again.
+            let block = size / BLOCK_SIZE as u64;
+            let mut data = state.read_block(block)?;
+            data.truncate(tail);
+            state.write_block(block, &data)?;
+        }
+        state.set_size(size)?;
+        // Trailing slots may never have been written, so the file can be shorter than that.
+        let physical = physical_size(size).min(state.inner.size().max(0) as u64);
+        self.inner.truncate_file(&state.inner, physical as i64)
+    }
+}
+
+pub struct CompressedFile<V: VfsExtension> {
+    state: Mutex<FileState<V::File>>,
+}
+
+struct FileState<F: VfsFile> {
+    inner: F,
+    algorithm: Algorithm,
+    /// Size of the uncompressed content, as recorded in the header.
+    size: u64,
+}
+
+/// Returns the number of bytes `block` holds in a file of `size` bytes.
+fn block_len(block: u64, size: u64) -> usize {
+    size.saturating_sub(block * BLOCK_SIZE as u64)
+        .min(BLOCK_SIZE as u64) as usize
+}
+
+fn extent_offset(block: u64) -> u64 {
+    HEADER_SIZE as u64 + block / EXTENT_BLOCKS * EXTENT_SIZE
+}
+
+fn slot_offset(block: u64) -> i64 {
+    (extent_offset(block) + block % EXTENT_BLOCKS * SLOT_SIZE as u64) as i64
+}
+
+fn overflow_offset(block: u64) -> i64 {
+    (extent_offset(block)
+        + EXTENT_BLOCKS * SLOT_SIZE as u64
+        + block % EXTENT_BLOCKS * OVERFLOW_SLOT_SIZE as u64) as i64
+}
+
+/// Returns the size of the extents holding `size` bytes of uncompressed content.
+fn physical_size(size: u64) -> u64 {
+    let blocks = size.div_ceil(BLOCK_SIZE as u64);
+    HEADER_SIZE as u64 + blocks.div_ceil(EXTENT_BLOCKS) * EXTENT_SIZE
+}
+
+fn read_exact<F: VfsFile>(file: &mut F, buf: &mut [u8], offset: i64) -> ExtResult<()> {
+    let n = file.read(buf, buf.len(), offset)?;
+    if n as usize != buf.len() {
+        log::error!("short read at offset {offset}");
+        return Err(ResultCode::Error);
+    }
+    Ok(())
+}
+
+fn write_all<F: VfsFile>(file: &mut F, buf: &[u8], offset: i64) -> ExtResult<()> {
+    let n = file.write(buf, buf.len(), offset)?;
+    if n as usize != buf.len() {
+        log::error!("short write at offset {offset}");
+        return Err(ResultCode::Error);
+    }
+    Ok(())
+}
+
+impl<F: VfsFile> FileState<F> {
+    fn read_block(&mut self, block: u64) -> ExtResult<Vec<u8>> {
+        let len = block_len(block, self.size);
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        // Slots that were never written read as zeroes, or not at all past the end of the file.
+        let mut slot = [0u8; SLOT_SIZE];
+        self.inner.read(&mut slot, SLOT_SIZE, slot_offset(block))?;
+        let header = u16::from_be_bytes([slot[0], slot[1]]);
+        let stored = (header & !RAW_FLAG) as usize;
+        if stored == 0 {
+            return Ok(vec![0; len]);
+        }
+        if stored > BLOCK_SIZE {
+            log::error!("block {block} has invalid length {stored}");
+            return Err(ResultCode::Corrupt);
+        }
+        let mut record = Vec::with_capacity(stored);
+        record.extend_from_slice(&slot[RECORD_HEADER_SIZE..][..stored.min(INLINE_CAPACITY)]);
+        if stored > INLINE_CAPACITY {
+            let mut overflow = vec![0u8; stored - INLINE_CAPACITY];
+            read_exact(&mut self.inner, &mut overflow, overflow_offset(block))?;
+            record.extend_from_slice(&overflow);
+        }
+        let mut data = if header & RAW_FLAG != 0 {
+            record
+        } else {
+            self.algorithm.decompress(&record, len)?
+        };
+        data.resize(len, 0);
+        Ok(data)
+    }
+
+    /// Stores `data` as `block`, compressed unless that wouldn't make it any smaller.
+    fn write_block(&mut self, block: u64, data: &[u8]) -> ExtResult<()> {
+        let compressed = self.algorithm.compress(data);
+        let (record, header) = if compressed.len() < data.len() {
+            (compressed.as_slice(), compressed.len() as u16)
+        } else {
+            (data, data.len() as u16 | RAW_FLAG)
+        };
+        let inline = record.len().min(INLINE_CAPACITY);
+        let mut slot = Vec::with_capacity(RECORD_HEADER_SIZE + inline);
+        slot.extend_from_slice(&header.to_be_bytes());
+        slot.extend_from_slice(&record[..inline]);
+        write_all(&mut self.inner, &slot, slot_offset(block))?;
+        if record.len() > INLINE_CAPACITY {
+            write_all(
+                &mut self.inner,
+                &record[INLINE_CAPACITY..],
+                overflow_offset(block),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn set_size(&mut self, size: u64) -> ExtResult<()> {
+        write_all(&mut self.inner, &size.to_be_bytes(), SIZE_OFFSET as i64)?;
+        self.size = size;
+        Ok(())
+    }
+
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> ExtResult<i32> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = offset + buf.len() as u64;
+        let new_size = self.size.max(end);
+        // Writing past the end also fills the gap, including the end of the last block.
+        let first = offset.min(self.size) / BLOCK_SIZE as u64;
+        let last = (end - 1) / BLOCK_SIZE as u64;
+        for block in first..=last {
+            let start = block * BLOCK_SIZE as u64;
+            let len = block_len(block, new_size);
+            let mut data = if offset <= start && end >= start + len as u64 {
+                vec![0u8; len]
+            } else {
+                let mut data = self.read_block(block)?;
+                data.resize(len, 0);
+                data
+            };
+            let from = offset.max(start);
+            let to = end.min(start + len as u64);
+            if from < to {
+                data[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&buf[(from - offset) as usize..(to - offset) as usize]);
+            }
+            self.write_block(block, &data)?;
+        }
+        if new_size != self.size {
+            self.set_size(new_size)?;
+        }
+        Ok(buf.len() as i32)
+    }
+}
+
+impl<V: VfsExtension> VfsFile for CompressedFile<V> {
+    fn lock(&mut self, exclusive: bool) -> ExtResult<()> {
+        let state = self.state.get_mut().map_err(|_| ResultCode::Error)?;
+        state.inner.lock(exclusive)
+    }
+
+    fn unlock(&self) -> ExtResult<()> {
+        let state = self.state.lock().map_err(|_| ResultCode::Error)?;
+        state.inner.unlock()
+    }
+
+    fn read(&mut self, buf: &mut [u8], count: usize, offset: i64) -> ExtResult<i32> {
+        log::debug!("reading file with compressed VFS: bytes: {count} offset: {offset}");
+        let state = self.state.get_mut().map_err(|_| ResultCode::Error)?;
+        let offset = offset as u64;
+        if offset >= state.size || count == 0 {
+            return Ok(0);
+        }
+        let end = state.size.min(offset + count as u64);
+        for block in offset / BLOCK_SIZE as u64..=(end - 1) / BLOCK_SIZE as u64 {
+            let data = state.read_block(block)?;
+            let start = block * BLOCK_SIZE as u64;
+            let from = offset.max(start);
+            let to = end.min(start + data.len() as u64);
+            buf[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&data[(from - start) as usize..(to - start) as usize]);
+        }
+        Ok((end - offset) as i32)
+    }
+
+    fn write(&mut self, buf: &[u8], count: usize, offset: i64) -> ExtResult<i32> {
+        log::debug!("writing to file with compressed VFS: bytes: {count} offset: {offset}");
+        let state = self.state.get_mut().map_err(|_| ResultCode::Error)?;
+        state.write_at(&buf[..count], offset as u64)
+    }
+
+    fn sync(&self) -> ExtResult<()> {
+        log::debug!("syncing file with compressed VFS");
+        let state = self.state.lock().map_err(|_| ResultCode::Error)?;
+        state.inner.sync()
+    }
+
+    fn size(&self) -> i64 {
+        self.state
+            .lock()
+            .map(|state| state.size as i64)
+            .unwrap_or(-1)
+    }
+}
+
+/// The VFS the compressed files are stored with, which reads and writes them as regular files.
+#[derive(Default)]
+pub struct FileFS;
+
+pub struct PlainFile {
+    file: std::fs::File,
+}
+
+impl VfsExtension for FileFS {
+    const NAME: &'static str = "file";
+    type File = PlainFile;
+
+    fn open_file(&self, path: &str, flags: i32, _direct: bool) -> ExtResult<Self::File> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(flags & 1 != 0)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| {
+                log::error!("failed to open {path}: {e}");
+                ResultCode::Error
+            })?;
+        Ok(PlainFile { file })
+    }
+
+    fn delete_file(&self, path: &str) -> ExtResult<()> {
+        std::fs::remove_file(path).map_err(|_| ResultCode::Error)
+    }
+
+    fn truncate_file(&self, file: &Self::File, size: i64) -> ExtResult<()> {
+        file.file
+            .set_len(size as u64)
+            .map_err(|_| ResultCode::Error)
+    }
+}
+
+impl VfsFile for PlainFile {
+    fn read(&mut self, buf: &mut [u8], count: usize, offset: i64) -> ExtResult<i32> {
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(|_| ResultCode::Error)?;
+        let mut read = 0;
+        while read < count {
+            match self.file.read(&mut buf[read..count]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(_) => return Err(ResultCode::Error),
+            }
+        }
+        Ok(read as i32)
+    }
+
+    fn write(&mut self, buf: &[u8], count: usize, offset: i64) -> ExtResult<i32> {
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(|_| ResultCode::Error)?;
+        self.file
+            .write_all(&buf[..count])
+            .map_err(|_| ResultCode::Error)?;
+        Ok(count as i32)
+    }
+
+    fn sync(&self) -> ExtResult<()> {
+        self.file.sync_all().map_err(|_| ResultCode::Error)
+    }
+
+    fn size(&self) -> i64 {
+        self.file.metadata().map(|m| m.len() as i64).unwrap_or(-1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(path: &str) -> CompressedFile<FileFS> {
+        CompressedVfs::new(FileFS)
+            .open_file(path, 1, false)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let path = path.to_str().unwrap();
+        let data: Vec<u8> = (0..40_000u32).map(|i| (i / 64 % 7) as u8).collect();
+
+        let mut file = open(path);
+        assert_eq!(
+            file.write(&data, data.len(), 100).unwrap(),
+            data.len() as i32
+        );
+        assert_eq!(file.size(), 40_100);
+        drop(file);
+        assert!(std::fs::metadata(path).unwrap().len() < 40_100);
+
+        let mut file = open(path);
+        assert_eq!(file.size(), 40_100);
+        let mut buf = vec![0xaa; 40_200];
+        assert_eq!(file.read(&mut buf, 40_200, 0).unwrap(), 40_100);
+        assert!(buf[..100].iter().all(|&b| b == 0));
+        assert_eq!(&buf[100..40_100], &data[..]);
+
+        CompressedVfs::new(FileFS)
+            .truncate_file(&file, 5000)
+            .unwrap();
+        assert_eq!(file.size(), 5000);
+        let mut buf = vec![0; 5000];
+        assert_eq!(file.read(&mut buf, 5000, 0).unwrap(), 5000);
+        assert_eq!(&buf[100..], &data[..4900]);
+    }
+
+    #[test]
+    fn test_incompressible_blocks_overflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let path = path.to_str().unwrap();
+        // A xorshift sequence doesn't compress, so every block spills into its overflow slot.
+        let mut x = 0x2545f491u32;
+        let data: Vec<u8> = (0..3 * BLOCK_SIZE)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+
+        let mut file = open(path);
+        file.write(&data, data.len(), 0).unwrap();
+        file.write(b"patched", 7, 5000).unwrap();
+        drop(file);
+
+        let mut file = open(path);
+        let mut buf = vec![0; data.len()];
+        assert_eq!(
+            file.read(&mut buf, data.len(), 0).unwrap(),
+            data.len() as i32
+        );
+        assert_eq!(&buf[..5000], &data[..5000]);
+        assert_eq!(&buf[5000..5007], b"patched");
+        assert_eq!(&buf[5007..], &data[5007..]);
+    }
+}
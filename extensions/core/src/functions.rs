@@ -18,6 +18,17 @@ pub type RegisterAggFn = unsafe extern "C" fn(
     finalize: FinalizeFunction,
 ) -> ResultCode;
 
+/// Compares two UTF-8 strings of the given lengths. Returns a negative number, zero or a positive
+/// number if `lhs` sorts before, equal to or after `rhs`.
+pub type CollationFunction =
+    unsafe extern "C" fn(lhs: *const u8, lhs_len: usize, rhs: *const u8, rhs_len: usize) -> i32;
+
+pub type RegisterCollationFn = unsafe extern "C" fn(
+    ctx: *mut c_void,
+    name: *const c_char,
+    func: CollationFunction,
+) -> ResultCode;
+
 pub type InitAggFunction = unsafe extern "C" fn() -> *mut AggCtx;
 pub type StepFunction = unsafe extern "C" fn(ctx: *mut AggCtx, argc: i32, argv: *const Value);
 pub type FinalizeFunction = unsafe extern "C" fn(ctx: *mut AggCtx) -> Value;
@@ -31,6 +42,10 @@ pub trait AggFunc {
     type State: Default;
     type Error: Display;
     const NAME: &'static str;
+    /// Another name the function can be called by.
+    const ALIAS: Option<&'static str> = None;
+    /// The number of arguments the function takes, or -1 if it accepts any number of them,
+    /// in which case `step` is called with all the arguments of each call.
     const ARGS: i32;
 
     fn step(state: &mut Self::State, args: &[Value]);
@@ -13,14 +13,38 @@ unsafe impl Send for VfsInterface {}
 
 pub trait VfsExtension: Default + Send + Sync {
     const NAME: &'static str;
+    /// Whether a WAL can stay in a file of its own between commits. When false, databases
+    /// opened with this VFS use `journal_mode=DELETE`, which checkpoints every commit and then
+    /// truncates the WAL.
+    const SUPPORTS_WAL: bool = true;
     type File: VfsFile;
     fn open_file(&self, path: &str, flags: i32, direct: bool) -> ExtResult<Self::File>;
+    /// Opens `path` with a key given by the application, e.g. the passphrase of an encrypted
+    /// database. The key is only borrowed for the call, a VFS that keeps it around should wipe
+    /// it once it is done with it.
+    fn open_file_with_key(
+        &self,
+        _path: &str,
+        _flags: i32,
+        _direct: bool,
+        _key: &[u8],
+    ) -> ExtResult<Self::File> {
+        Err(ResultCode::Unimplemented)
+    }
     fn run_once(&self) -> ExtResult<()> {
         Ok(())
     }
     fn close(&self, _file: Self::File) -> ExtResult<()> {
         Ok(())
     }
+    /// Removes the file at `path`, e.g. when a database or its WAL is dropped.
+    fn delete_file(&self, _path: &str) -> ExtResult<()> {
+        Err(ResultCode::Unimplemented)
+    }
+    /// Truncates or extends `file` to `size` bytes, e.g. to reset the WAL after a checkpoint.
+    fn truncate_file(&self, _file: &Self::File, _size: i64) -> ExtResult<()> {
+        Err(ResultCode::Unimplemented)
+    }
     fn generate_random_number(&self) -> i64 {
         let mut buf = [0u8; 8];
         getrandom::fill(&mut buf).unwrap();
@@ -49,6 +73,7 @@ pub struct VfsImpl {
     pub name: *const c_char,
     pub vfs: *const c_void,
     pub open: VfsOpen,
+    pub open_with_key: VfsOpenWithKey,
     pub close: VfsClose,
     pub read: VfsRead,
     pub write: VfsWrite,
@@ -57,8 +82,11 @@ pub struct VfsImpl {
     pub unlock: VfsUnlock,
     pub size: VfsSize,
     pub run_once: VfsRunOnce,
+    pub delete: VfsDelete,
+    pub truncate: VfsTruncate,
     pub current_time: VfsGetCurrentTime,
     pub gen_random_number: VfsGenerateRandomNumber,
+    pub supports_wal: bool,
 }
 
 pub type RegisterVfsFn =
@@ -71,6 +99,15 @@ pub type VfsOpen = unsafe extern "C" fn(
     direct: bool,
 ) -> *const c_void;
 
+pub type VfsOpenWithKey = unsafe extern "C" fn(
+    ctx: *const c_void,
+    path: *const c_char,
+    flags: i32,
+    direct: bool,
+    key: *const u8,
+    key_len: usize,
+) -> *const c_void;
+
 pub type VfsClose = unsafe extern "C" fn(file: *const c_void) -> ResultCode;
 
 pub type VfsRead =
@@ -89,6 +126,10 @@ pub type VfsSize = unsafe extern "C" fn(file: *const c_void) -> i64;
 
 pub type VfsRunOnce = unsafe extern "C" fn(file: *const c_void) -> ResultCode;
 
+pub type VfsDelete = unsafe extern "C" fn(ctx: *const c_void, path: *const c_char) -> ResultCode;
+
+pub type VfsTruncate = unsafe extern "C" fn(file: *const c_void, size: i64) -> ResultCode;
+
 pub type VfsGetCurrentTime = unsafe extern "C" fn() -> *const c_char;
 
 pub type VfsGenerateRandomNumber = unsafe extern "C" fn() -> i64;
@@ -108,6 +149,28 @@ impl VfsFileImpl {
         }
         Ok(Self { file, vfs })
     }
+
+    /// Truncates or extends the file to `size` bytes.
+    pub fn truncate(&self, size: i64) -> ExtResult<()> {
+        let vfs = unsafe { &*self.vfs };
+        let result = unsafe { (vfs.truncate)(self.file, size) };
+        if !result.is_ok() {
+            return Err(result);
+        }
+        Ok(())
+    }
+}
+
+impl VfsImpl {
+    /// Removes the file at `path` through the VFS.
+    pub fn delete_file(&self, path: &str) -> ExtResult<()> {
+        let path = std::ffi::CString::new(path).map_err(|_| ResultCode::InvalidArgs)?;
+        let result = unsafe { (self.delete)(self as *const Self as *const c_void, path.as_ptr()) };
+        if !result.is_ok() {
+            return Err(result);
+        }
+        Ok(())
+    }
 }
 
 impl Drop for VfsFileImpl {
@@ -27,6 +27,9 @@ pub struct VTabModuleImpl {
     pub rowid: VtabRowIDFn,
     pub destroy: VtabFnDestroy,
     pub best_idx: BestIdxFn,
+    pub begin: VtabFnBegin,
+    pub commit: VtabFnCommit,
+    pub rollback: VtabFnRollback,
 }
 
 #[repr(C)]
@@ -94,6 +97,7 @@ pub type VtabRowIDFn = unsafe extern "C" fn(cursor: *const c_void) -> i64;
 
 pub type VtabFnUpdate = unsafe extern "C" fn(
     table: *const c_void,
+    conn: *mut Conn,
     argc: i32,
     argv: *const Value,
     p_out_rowid: *mut i64,
@@ -101,6 +105,12 @@ pub type VtabFnUpdate = unsafe extern "C" fn(
 
 pub type VtabFnDestroy = unsafe extern "C" fn(table: *const c_void) -> ResultCode;
 
+pub type VtabFnBegin = unsafe extern "C" fn(table: *const c_void) -> ResultCode;
+
+pub type VtabFnCommit = unsafe extern "C" fn(table: *const c_void) -> ResultCode;
+
+pub type VtabFnRollback = unsafe extern "C" fn(table: *const c_void) -> ResultCode;
+
 pub type BestIdxFn = unsafe extern "C" fn(
     constraints: *const ConstraintInfo,
     constraint_len: i32,
@@ -132,29 +142,69 @@ pub trait VTable {
     /// 'conn' is an Option to allow for testing. Otherwise a valid connection to the core database
     /// that created the virtual table will be available to use in your extension here.
     fn open(&self, _conn: Option<Arc<Connection>>) -> Result<Self::Cursor, Self::Error>;
-    fn update(&mut self, _rowid: i64, _args: &[Value]) -> Result<(), Self::Error> {
+    /// `update`, `insert` and `delete` receive the connection running the statement that
+    /// modifies the table. SQL executed on it joins that statement's transaction, so it sees
+    /// the uncommitted rows and is committed or rolled back together with them.
+    /// Changing the transaction (BEGIN, COMMIT, ROLLBACK) on it is an error.
+    fn update(
+        &mut self,
+        _conn: Option<Arc<Connection>>,
+        _rowid: i64,
+        _args: &[Value],
+    ) -> Result<(), Self::Error> {
         Ok(())
     }
-    fn insert(&mut self, _args: &[Value]) -> Result<i64, Self::Error> {
+    fn insert(
+        &mut self,
+        _conn: Option<Arc<Connection>>,
+        _args: &[Value],
+    ) -> Result<i64, Self::Error> {
         Ok(0)
     }
-    fn delete(&mut self, _rowid: i64) -> Result<(), Self::Error> {
+    fn delete(&mut self, _conn: Option<Arc<Connection>>, _rowid: i64) -> Result<(), Self::Error> {
         Ok(())
     }
     fn destroy(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
-    fn best_index(_constraints: &[ConstraintInfo], _order_by: &[OrderByInfo]) -> IndexInfo {
+    /// Called before the first change made to the table in a transaction, i.e. before the
+    /// first call to `insert`, `update` or `delete` since the last `commit` or `rollback`.
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Called when the transaction that changed the table is committed.
+    fn commit(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Called when the transaction that changed the table is rolled back: the table should
+    /// undo the changes made since `begin`.
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// The equivalent of SQLite's xBestIndex: called by the planner with the WHERE clause
+    /// constraints on the columns of the table, and the ORDER BY terms of the query.
+    ///
+    /// For each constraint, the returned [ConstraintUsage] tells whether its value is passed to
+    /// [VTabCursor::filter] (and at which position), and whether core can skip evaluating it
+    /// because the cursor only returns rows that satisfy it.
+    ///
+    /// By default, the values of all usable constraints are passed to `filter` in order, and
+    /// core still evaluates every constraint on the returned rows.
+    fn best_index(constraints: &[ConstraintInfo], _order_by: &[OrderByInfo]) -> IndexInfo {
+        let mut argv_index = 0;
         IndexInfo {
             idx_num: 0,
             idx_str: None,
             order_by_consumed: false,
             estimated_cost: 1_000_000.0,
             estimated_rows: u32::MAX,
-            constraint_usages: _constraints
+            constraint_usages: constraints
                 .iter()
-                .map(|_| ConstraintUsage {
-                    argv_index: Some(0),
+                .map(|constraint| ConstraintUsage {
+                    argv_index: constraint.usable.then(|| {
+                        argv_index += 1;
+                        argv_index
+                    }),
                     omit: false,
                 })
                 .collect(),
@@ -1,7 +1,10 @@
 use crate::Error;
 use blake3::Hasher;
 use data_encoding::{BASE32, BASE64, HEXLOWER};
-use ring::digest::{self, digest};
+use ring::{
+    digest::{self, digest},
+    hmac,
+};
 use std::{borrow::Cow, error::Error as StdError};
 use turso_ext::{Value, ValueType};
 
@@ -35,6 +38,38 @@ pub fn sha384(data: &Value) -> Result<Vec<u8>, Error> {
     }
 }
 
+pub fn hmac_sha256(key: &Value, data: &Value) -> Result<Vec<u8>, Error> {
+    match (key.value_type(), data.value_type()) {
+        (ValueType::Error | ValueType::Null, _) | (_, ValueType::Error | ValueType::Null) => {
+            Err(Error::InvalidType)
+        }
+        _ => {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, &key.as_bytes());
+            let tag = hmac::sign(&key, &data.as_bytes());
+            Ok(tag.as_ref().to_vec())
+        }
+    }
+}
+
+pub fn base64_encode(data: &Value) -> Result<String, Error> {
+    match data.value_type() {
+        ValueType::Error | ValueType::Null => Err(Error::InvalidType),
+        _ => Ok(BASE64.encode(data.as_bytes().as_ref())),
+    }
+}
+
+pub fn base64_decode(data: &Value) -> Result<Vec<u8>, Error> {
+    match data.value_type() {
+        ValueType::Text => {
+            let input_text = data.to_text().ok_or(Error::InvalidType)?;
+            BASE64
+                .decode(input_text.trim().as_bytes())
+                .map_err(|_| Error::DecodeFailed)
+        }
+        _ => Err(Error::InvalidType),
+    }
+}
+
 pub fn blake3(data: &Value) -> Result<Vec<u8>, Error> {
     match data.value_type() {
         ValueType::Error | ValueType::Null => Err(Error::InvalidType),
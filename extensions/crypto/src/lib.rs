@@ -1,4 +1,7 @@
-use crypto::{blake3, decode, encode, md5, sha1, sha256, sha384, sha512};
+use crypto::{
+    base64_decode, base64_encode, blake3, decode, encode, hmac_sha256, md5, sha1, sha256, sha384,
+    sha512,
+};
 use turso_ext::{register_extension, scalar, ResultCode, Value};
 
 mod crypto;
@@ -11,7 +14,7 @@ enum Error {
     InvalidUtf8,
 }
 
-#[scalar(name = "crypto_sha256", alias = "crypto_sha256")]
+#[scalar(name = "crypto_sha256", alias = "sha256")]
 fn crypto_sha256(args: &[Value]) -> Value {
     if args.len() != 1 {
         return Value::error(ResultCode::Error);
@@ -24,7 +27,7 @@ fn crypto_sha256(args: &[Value]) -> Value {
     Value::from_blob(hash)
 }
 
-#[scalar(name = "crypto_sha512", alias = "crypto_sha512")]
+#[scalar(name = "crypto_sha512", alias = "sha512")]
 fn crypto_sha512(args: &[Value]) -> Value {
     if args.len() != 1 {
         return Value::error(ResultCode::Error);
@@ -37,6 +40,19 @@ fn crypto_sha512(args: &[Value]) -> Value {
     Value::from_blob(hash)
 }
 
+#[scalar(name = "crypto_hmac_sha256", alias = "hmac_sha256")]
+fn crypto_hmac_sha256(args: &[Value]) -> Value {
+    if args.len() != 2 {
+        return Value::error(ResultCode::Error);
+    }
+
+    let Ok(tag) = hmac_sha256(&args[0], &args[1]) else {
+        return Value::error(ResultCode::Error);
+    };
+
+    Value::from_blob(tag)
+}
+
 #[scalar(name = "crypto_sha384", alias = "crypto_sha384")]
 fn crypto_sha384(args: &[Value]) -> Value {
     if args.len() != 1 {
@@ -115,6 +131,44 @@ fn crypto_decode(args: &[Value]) -> Value {
     payload
 }
 
+#[scalar(name = "base64_encode")]
+fn crypto_base64_encode(args: &[Value]) -> Value {
+    if args.len() != 1 {
+        return Value::error(ResultCode::Error);
+    }
+
+    let Ok(payload) = base64_encode(&args[0]) else {
+        return Value::error(ResultCode::Error);
+    };
+
+    Value::from_text(payload)
+}
+
+#[scalar(name = "base64_decode")]
+fn crypto_base64_decode(args: &[Value]) -> Value {
+    if args.len() != 1 {
+        return Value::error(ResultCode::Error);
+    }
+
+    let Ok(payload) = base64_decode(&args[0]) else {
+        return Value::error(ResultCode::Error);
+    };
+
+    Value::from_blob(payload)
+}
+
 register_extension! {
-    scalars: { crypto_sha256, crypto_sha512, crypto_sha384, crypto_blake3, crypto_sha1, crypto_md5, crypto_encode, crypto_decode },
+    scalars: {
+        crypto_sha256,
+        crypto_sha512,
+        crypto_hmac_sha256,
+        crypto_sha384,
+        crypto_blake3,
+        crypto_sha1,
+        crypto_md5,
+        crypto_encode,
+        crypto_decode,
+        crypto_base64_encode,
+        crypto_base64_decode,
+    },
 }
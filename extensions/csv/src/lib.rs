@@ -264,15 +264,24 @@ impl VTable for CsvTable {
         }
     }
 
-    fn update(&mut self, _rowid: i64, _args: &[Value]) -> Result<(), Self::Error> {
+    fn update(
+        &mut self,
+        _conn: Option<Arc<Connection>>,
+        _rowid: i64,
+        _args: &[Value],
+    ) -> Result<(), Self::Error> {
         Err(ResultCode::ReadOnly)
     }
 
-    fn insert(&mut self, _args: &[Value]) -> Result<i64, Self::Error> {
+    fn insert(
+        &mut self,
+        _conn: Option<Arc<Connection>>,
+        _args: &[Value],
+    ) -> Result<i64, Self::Error> {
         Err(ResultCode::ReadOnly)
     }
 
-    fn delete(&mut self, _rowid: i64) -> Result<(), Self::Error> {
+    fn delete(&mut self, _conn: Option<Arc<Connection>>, _rowid: i64) -> Result<(), Self::Error> {
         Err(ResultCode::ReadOnly)
     }
 
@@ -0,0 +1,548 @@
+//! VFS that encrypts databases at rest with AES-256-GCM.
+//!
+//! The files are split into blocks of 4096 bytes that are encrypted independently, so a page
+//! can be read or written without touching the rest of the file. Each block is stored with its
+//! own nonce and authentication tag, and both the nonce and the associated data include the
+//! block number, so blocks can't be swapped or moved around without being detected.
+//!
+//! The key is derived from a passphrase with PBKDF2-HMAC-SHA256, salted with random bytes kept
+//! in a small header at the start of each file. The header lives outside the database pages
+//! rather than in the reserved space of page 1, as the VFS doesn't know about the page layout,
+//! and it lets the WAL, which isn't made of database pages, be encrypted the same way.
+//!
+//! The passphrase is given by the application when it opens a database, with the `key`
+//! parameter of a URI filename, and reaches the VFS through [VfsExtension::open_file_with_key].
+//! The VFS never stores it: only the derived key is kept, in the open file. Opening a file
+//! without a key fails.
+//!
+//! ## Example usage:
+//!
+//! ```text
+//! .load target/debug/liblimbo_encryption
+//! ```
+//!
+//! Then, from the application:
+//!
+//! ```text
+//! let db = Database::open_uri("file:encrypted.db?key=secret")?;
+//! ```
+//!
+//! When the extension is built into the application, register its VFS with
+//! `turso_core::register_static_vfs(limbo_encryption::register_static_EncryptedFS())` instead
+//! of loading it.
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use turso_ext::{register_extension, ExtResult, ResultCode, VfsDerive, VfsExtension, VfsFile};
+
+register_extension! {
+    vfs: { EncryptedFS },
+}
+
+const MAGIC: &[u8; 8] = b"LIMBOENC";
+const SALT_SIZE: usize = 16;
+const TAG_SIZE: usize = 16;
+/// The magic, the salt, then the magic encrypted with the key, which tells a wrong passphrase
+/// apart from a corrupted block.
+const HEADER_SIZE: usize = MAGIC.len() + SALT_SIZE + MAGIC.len() + TAG_SIZE;
+/// Nonce used to encrypt the magic in the header. Block nonces never start with the block
+/// number `u32::MAX`, so they can't collide with it.
+const HEADER_NONCE: [u8; NONCE_LEN] = [0xff; NONCE_LEN];
+const BLOCK_SIZE: usize = 4096;
+const SEALED_BLOCK_SIZE: usize = NONCE_LEN + BLOCK_SIZE + TAG_SIZE;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+fn derive_key(passphrase: &[u8], salt: &[u8]) -> ExtResult<LessSafeKey> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase,
+        &mut key,
+    );
+    let unbound = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| ResultCode::Error);
+    wipe(&mut key);
+    Ok(LessSafeKey::new(unbound?))
+}
+
+/// Overwrites key material with zeroes, in a way the compiler can't optimize away.
+fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// The encrypted VFS, storing its files with [FileFS].
+#[derive(VfsDerive, Default)]
+pub struct EncryptedFS {
+    vfs: EncryptedVfs<FileFS>,
+}
+
+impl VfsExtension for EncryptedFS {
+    const NAME: &'static str = "encrypted";
+    type File = EncryptedFile<FileFS>;
+
+    fn open_file(&self, path: &str, _flags: i32, _direct: bool) -> ExtResult<Self::File> {
+        log::error!("no encryption key given for {path}");
+        Err(ResultCode::InvalidArgs)
+    }
+
+    fn open_file_with_key(
+        &self,
+        path: &str,
+        flags: i32,
+        direct: bool,
+        key: &[u8],
+    ) -> ExtResult<Self::File> {
+        log::debug!("opening file with encrypted VFS: {} flags: {}", path, flags);
+        self.vfs.open_file(path, flags, direct, key)
+    }
+
+    fn delete_file(&self, path: &str) -> ExtResult<()> {
+        log::debug!("deleting file with encrypted VFS: {}", path);
+        self.vfs.delete_file(path)
+    }
+
+    fn truncate_file(&self, file: &Self::File, size: i64) -> ExtResult<()> {
+        log::debug!("truncating file with encrypted VFS: size: {size}");
+        self.vfs.truncate_file(file, size)
+    }
+}
+
+/// Encrypts the files of the VFS `V`, which only ever sees ciphertext.
+#[derive(Default)]
+pub struct EncryptedVfs<V: VfsExtension> {
+    inner: V,
+}
+
+impl<V: VfsExtension> EncryptedVfs<V> {
+    pub fn new(inner: V) -> Self {
+        Self { inner }
+    }
+
+    /// Opens `path` with the key derived from `passphrase`.
+    pub fn open_file(
+        &self,
+        path: &str,
+        flags: i32,
+        direct: bool,
+        passphrase: &[u8],
+    ) -> ExtResult<EncryptedFile<V>> {
+        if passphrase.is_empty() {
+            log::error!("empty encryption key given for {path}");
+            return Err(ResultCode::InvalidArgs);
+        }
+        let mut file = self.inner.open_file(path, flags, direct)?;
+        let key = if file.size() <= 0 {
+            let mut salt = [0u8; SALT_SIZE];
+            SystemRandom::new()
+                .fill(&mut salt)
+                .map_err(|_| ResultCode::Error)?;
+            let key = derive_key(passphrase, &salt)?;
+            let mut check = *MAGIC;
+            let tag = key
+                .seal_in_place_separate_tag(
+                    Nonce::assume_unique_for_key(HEADER_NONCE),
+                    Aad::empty(),
+                    &mut check,
+                )
+                .map_err(|_| ResultCode::Error)?;
+            let mut header = Vec::with_capacity(HEADER_SIZE);
+            header.extend_from_slice(MAGIC);
+            header.extend_from_slice(&salt);
+            header.extend_from_slice(&check);
+            header.extend_from_slice(tag.as_ref());
+            write_all(&mut file, &header, 0)?;
+            key
+        } else {
+            let mut header = [0u8; HEADER_SIZE];
+            read_exact(&mut file, &mut header, 0)?;
+            if &header[..MAGIC.len()] != MAGIC {
+                log::error!("{path} is not an encrypted database");
+                return Err(ResultCode::Error);
+            }
+            let (salt, check) = header[MAGIC.len()..].split_at_mut(SALT_SIZE);
+            let key = derive_key(passphrase, salt)?;
+            if key
+                .open_in_place(
+                    Nonce::assume_unique_for_key(HEADER_NONCE),
+                    Aad::empty(),
+                    check,
+                )
+                .is_err()
+            {
+                log::error!("wrong encryption key for {path}");
+                return Err(ResultCode::Error);
+            }
+            key
+        };
+        Ok(EncryptedFile {
+            inner: Mutex::new(file),
+            key,
+        })
+    }
+
+    pub fn delete_file(&self, path: &str) -> ExtResult<()> {
+        self.inner.delete_file(path)
+    }
+
+    pub fn truncate_file(&self, file: &EncryptedFile<V>, size: i64) -> ExtResult<()> {
+        let mut inner = file.inner.lock().map_err(|_| ResultCode::Error)?;
+        let size = size as u64;
+        let current = logical_size(&*inner);
+        if size > current {
+            // Extending the file writes zeroes, which must be encrypted like any other data.
+            let zeroes = vec![0u8; (size - current) as usize];
+            return file.write_at(&mut inner, &zeroes, current).map(|_| ());
+        }
+        let tail = (size % BLOCK_SIZE as u64) as usize;
+        if tail != 0 {
+            // The last block is cut short, so it has to be encrypted again.
+            let block = size / BLOCK_SIZE as u64;
+            let mut plaintext = file.read_block(&mut inner, block, current)?;
+            plaintext.truncate(tail);
+            file.write_block(&mut inner, block, plaintext)?;
+        }
+        self.inner.truncate_file(&inner, physical_size(size) as i64)
+    }
+}
+
+pub struct EncryptedFile<V: VfsExtension> {
+    inner: Mutex<V::File>,
+    key: LessSafeKey,
+}
+
+/// Returns the size of the plaintext stored in `file`.
+fn logical_size<F: VfsFile>(file: &F) -> u64 {
+    let body = (file.size().max(0) as u64).saturating_sub(HEADER_SIZE as u64);
+    let blocks = body / SEALED_BLOCK_SIZE as u64;
+    let tail = (body % SEALED_BLOCK_SIZE as u64).saturating_sub((NONCE_LEN + TAG_SIZE) as u64);
+    blocks * BLOCK_SIZE as u64 + tail
+}
+
+/// Returns the size of the file storing `logical` bytes of plaintext.
+fn physical_size(logical: u64) -> u64 {
+    let blocks = logical / BLOCK_SIZE as u64;
+    let tail = logical % BLOCK_SIZE as u64;
+    let sealed_tail = if tail == 0 {
+        0
+    } else {
+        tail + (NONCE_LEN + TAG_SIZE) as u64
+    };
+    HEADER_SIZE as u64 + blocks * SEALED_BLOCK_SIZE as u64 + sealed_tail
+}
+
+/// Returns the number of plaintext bytes `block` holds in a file of `size` bytes.
+fn block_len(block: u64, size: u64) -> usize {
+    size.saturating_sub(block * BLOCK_SIZE as u64)
+        .min(BLOCK_SIZE as u64) as usize
+}
+
+fn block_offset(block: u64) -> i64 {
+    (HEADER_SIZE as u64 + block * SEALED_BLOCK_SIZE as u64) as i64
+}
+
+fn read_exact<F: VfsFile>(file: &mut F, buf: &mut [u8], offset: i64) -> ExtResult<()> {
+    let n = file.read(buf, buf.len(), offset)?;
+    if n as usize != buf.len() {
+        log::error!("short read at offset {offset}");
+        return Err(ResultCode::Error);
+    }
+    Ok(())
+}
+
+fn write_all<F: VfsFile>(file: &mut F, buf: &[u8], offset: i64) -> ExtResult<()> {
+    let n = file.write(buf, buf.len(), offset)?;
+    if n as usize != buf.len() {
+        log::error!("short write at offset {offset}");
+        return Err(ResultCode::Error);
+    }
+    Ok(())
+}
+
+impl<V: VfsExtension> EncryptedFile<V> {
+    /// Reads and decrypts `block` of a file holding `size` bytes of plaintext.
+    fn read_block(&self, inner: &mut V::File, block: u64, size: u64) -> ExtResult<Vec<u8>> {
+        let len = block_len(block, size);
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let mut sealed = vec![0u8; NONCE_LEN + len + TAG_SIZE];
+        read_exact(inner, &mut sealed, block_offset(block))?;
+        let (nonce, ciphertext) = sealed.split_at_mut(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| ResultCode::Error)?;
+        self.key
+            .open_in_place(nonce, Aad::from(block.to_be_bytes()), ciphertext)
+            .map_err(|_| {
+                log::error!("block {block} failed authentication");
+                ResultCode::Corrupt
+            })?;
+        sealed.drain(..NONCE_LEN);
+        sealed.truncate(len);
+        Ok(sealed)
+    }
+
+    /// Encrypts `plaintext` with a fresh nonce and stores it as `block`.
+    fn write_block(
+        &self,
+        inner: &mut V::File,
+        block: u64,
+        mut plaintext: Vec<u8>,
+    ) -> ExtResult<()> {
+        let mut nonce = [0u8; NONCE_LEN];
+        let number = u32::try_from(block)
+            .ok()
+            .filter(|&n| n != u32::MAX)
+            .ok_or(ResultCode::Error)?;
+        nonce[..4].copy_from_slice(&number.to_be_bytes());
+        SystemRandom::new()
+            .fill(&mut nonce[4..])
+            .map_err(|_| ResultCode::Error)?;
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(block.to_be_bytes()),
+                &mut plaintext,
+            )
+            .map_err(|_| ResultCode::Error)?;
+        let mut sealed = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_SIZE);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&plaintext);
+        sealed.extend_from_slice(tag.as_ref());
+        write_all(inner, &sealed, block_offset(block))
+    }
+
+    fn write_at(&self, inner: &mut V::File, buf: &[u8], offset: u64) -> ExtResult<i32> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let size = logical_size(inner);
+        let end = offset + buf.len() as u64;
+        let new_size = size.max(end);
+        // Writing past the end also fills the gap, including the end of the last block.
+        let first = offset.min(size) / BLOCK_SIZE as u64;
+        let last = (end - 1) / BLOCK_SIZE as u64;
+        for block in first..=last {
+            let start = block * BLOCK_SIZE as u64;
+            let len = block_len(block, new_size);
+            let mut plaintext = if offset <= start && end >= start + len as u64 {
+                vec![0u8; len]
+            } else {
+                let mut plaintext = self.read_block(inner, block, size)?;
+                plaintext.resize(len, 0);
+                plaintext
+            };
+            let from = offset.max(start);
+            let to = end.min(start + len as u64);
+            if from < to {
+                plaintext[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&buf[(from - offset) as usize..(to - offset) as usize]);
+            }
+            self.write_block(inner, block, plaintext)?;
+        }
+        Ok(buf.len() as i32)
+    }
+}
+
+impl<V: VfsExtension> VfsFile for EncryptedFile<V> {
+    fn lock(&mut self, exclusive: bool) -> ExtResult<()> {
+        self.inner
+            .get_mut()
+            .map_err(|_| ResultCode::Error)?
+            .lock(exclusive)
+    }
+
+    fn unlock(&self) -> ExtResult<()> {
+        self.inner.lock().map_err(|_| ResultCode::Error)?.unlock()
+    }
+
+    fn read(&mut self, buf: &mut [u8], count: usize, offset: i64) -> ExtResult<i32> {
+        log::debug!("reading file with encrypted VFS: bytes: {count} offset: {offset}");
+        let mut inner = self.inner.lock().map_err(|_| ResultCode::Error)?;
+        let size = logical_size(&*inner);
+        let offset = offset as u64;
+        if offset >= size || count == 0 {
+            return Ok(0);
+        }
+        let end = size.min(offset + count as u64);
+        for block in offset / BLOCK_SIZE as u64..=(end - 1) / BLOCK_SIZE as u64 {
+            let plaintext = self.read_block(&mut inner, block, size)?;
+            let start = block * BLOCK_SIZE as u64;
+            let from = offset.max(start);
+            let to = end.min(start + plaintext.len() as u64);
+            buf[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&plaintext[(from - start) as usize..(to - start) as usize]);
+        }
+        Ok((end - offset) as i32)
+    }
+
+    fn write(&mut self, buf: &[u8], count: usize, offset: i64) -> ExtResult<i32> {
+        log::debug!("writing to file with encrypted VFS: bytes: {count} offset: {offset}");
+        let mut inner = self.inner.lock().map_err(|_| ResultCode::Error)?;
+        self.write_at(&mut inner, &buf[..count], offset as u64)
+    }
+
+    fn sync(&self) -> ExtResult<()> {
+        log::debug!("syncing file with encrypted VFS");
+        self.inner.lock().map_err(|_| ResultCode::Error)?.sync()
+    }
+
+    fn size(&self) -> i64 {
+        self.inner
+            .lock()
+            .map(|inner| logical_size(&*inner) as i64)
+            .unwrap_or(-1)
+    }
+}
+
+/// The VFS the encrypted files are stored with, which reads and writes them as regular files.
+#[derive(Default)]
+pub struct FileFS;
+
+pub struct PlainFile {
+    file: std::fs::File,
+}
+
+impl VfsExtension for FileFS {
+    const NAME: &'static str = "file";
+    type File = PlainFile;
+
+    fn open_file(&self, path: &str, flags: i32, _direct: bool) -> ExtResult<Self::File> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(flags & 1 != 0)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| {
+                log::error!("failed to open {path}: {e}");
+                ResultCode::Error
+            })?;
+        Ok(PlainFile { file })
+    }
+
+    fn delete_file(&self, path: &str) -> ExtResult<()> {
+        std::fs::remove_file(path).map_err(|_| ResultCode::Error)
+    }
+
+    fn truncate_file(&self, file: &Self::File, size: i64) -> ExtResult<()> {
+        file.file
+            .set_len(size as u64)
+            .map_err(|_| ResultCode::Error)
+    }
+}
+
+impl VfsFile for PlainFile {
+    fn read(&mut self, buf: &mut [u8], count: usize, offset: i64) -> ExtResult<i32> {
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(|_| ResultCode::Error)?;
+        let mut read = 0;
+        while read < count {
+            match self.file.read(&mut buf[read..count]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(_) => return Err(ResultCode::Error),
+            }
+        }
+        Ok(read as i32)
+    }
+
+    fn write(&mut self, buf: &[u8], count: usize, offset: i64) -> ExtResult<i32> {
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(|_| ResultCode::Error)?;
+        self.file
+            .write_all(&buf[..count])
+            .map_err(|_| ResultCode::Error)?;
+        Ok(count as i32)
+    }
+
+    fn sync(&self) -> ExtResult<()> {
+        self.file.sync_all().map_err(|_| ResultCode::Error)
+    }
+
+    fn size(&self) -> i64 {
+        self.file.metadata().map(|m| m.len() as i64).unwrap_or(-1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(path: &str, passphrase: &str) -> ExtResult<EncryptedFile<FileFS>> {
+        EncryptedVfs::new(FileFS).open_file(path, 1, false, passphrase.as_bytes())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let path = path.to_str().unwrap();
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut file = open(path, "secret").unwrap();
+        assert_eq!(
+            file.write(&data, data.len(), 100).unwrap(),
+            data.len() as i32
+        );
+        assert_eq!(file.size(), 10_100);
+        drop(file);
+
+        let raw = std::fs::read(path).unwrap();
+        assert!(!raw.windows(64).any(|w| w == &data[..64]));
+
+        let mut file = open(path, "secret").unwrap();
+        let mut buf = vec![0xaa; 10_200];
+        assert_eq!(file.read(&mut buf, 10_200, 0).unwrap(), 10_100);
+        assert!(buf[..100].iter().all(|&b| b == 0));
+        assert_eq!(&buf[100..10_100], &data[..]);
+
+        EncryptedVfs::new(FileFS)
+            .truncate_file(&file, 5000)
+            .unwrap();
+        assert_eq!(file.size(), 5000);
+        let mut buf = vec![0; 5000];
+        assert_eq!(file.read(&mut buf, 5000, 0).unwrap(), 5000);
+        assert_eq!(&buf[100..], &data[..4900]);
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let path = path.to_str().unwrap();
+        let mut file = open(path, "secret").unwrap();
+        file.write(b"hello", 5, 0).unwrap();
+        drop(file);
+        assert!(open(path, "wrong").is_err());
+        assert!(open(path, "").is_err());
+    }
+
+    #[test]
+    fn test_tampered_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let path = path.to_str().unwrap();
+        let mut file = open(path, "secret").unwrap();
+        file.write(&[1u8; 8192], 8192, 0).unwrap();
+        drop(file);
+
+        // Swapping the two blocks must be detected, as they are bound to their position.
+        let mut raw = std::fs::read(path).unwrap();
+        let (first, second) = raw[HEADER_SIZE..].split_at_mut(SEALED_BLOCK_SIZE);
+        first.swap_with_slice(second);
+        std::fs::write(path, raw).unwrap();
+        let mut file = open(path, "secret").unwrap();
+        let mut buf = [0u8; 10];
+        assert!(file.read(&mut buf, 10, 0).is_err());
+    }
+}
@@ -0,0 +1,399 @@
+//! VFS that keeps databases in S3-compatible object storage.
+//!
+//! Every file the database opens maps to an object. Reads issue ranged GETs, while writes are
+//! buffered in memory and uploaded with a single PUT when the file is synced or closed, as object
+//! storage can't modify part of an object in place.
+//!
+//! ## Example usage:
+//!
+//! ```text
+//! .load target/debug/liblimbo_s3
+//! .open s3://my-bucket/app.db s3
+//! ```
+//!
+//! Paths are either `s3://<bucket>/<key>`, or a bare key in the bucket named by `AWS_BUCKET`.
+//! Credentials, region and endpoint are read from the usual `AWS_*` environment variables, so
+//! `AWS_ENDPOINT` and `AWS_ALLOW_HTTP` point the VFS at S3-compatible services such as MinIO or
+//! R2.
+//!
+//! Object storage can't hold a WAL between commits, so databases opened with this VFS always use
+//! `journal_mode=DELETE`: every commit uploads the WAL, `<key>-wal`, checkpoints it into the
+//! database, uploads the database, and deletes the WAL. As an object is replaced as a whole,
+//! every commit and checkpoint uploads the entire database, so the VFS is meant for databases
+//! that are small or rarely written to. Objects are limited to 5 GiB, the most a single PUT can
+//! upload to S3. Object storage has no locks either, so only one process may open a database at
+//! a time.
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::runtime::Runtime;
+use turso_ext::{register_extension, ExtResult, ResultCode, VfsDerive, VfsExtension, VfsFile};
+
+register_extension! {
+    vfs: { S3FS },
+}
+
+/// Runs the object store requests, which are async, on behalf of the VFS calls, which aren't.
+fn runtime() -> ExtResult<&'static Runtime> {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| {
+            log::error!("failed to start the S3 VFS runtime: {e}");
+            ResultCode::Error
+        })?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Runs `future` to completion on the VFS runtime. A runtime can't be blocked on from inside
+/// another one, so when the host calls the VFS from async code the future runs on a thread of
+/// its own.
+fn block_on<F>(future: F) -> ExtResult<F::Output>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    let runtime = runtime()?;
+    if tokio::runtime::Handle::try_current().is_err() {
+        return Ok(runtime.block_on(future));
+    }
+    std::thread::scope(|scope| scope.spawn(|| runtime.block_on(future)).join()).map_err(|_| {
+        log::error!("S3 VFS request panicked");
+        ResultCode::Error
+    })
+}
+
+/// Splits `path` into the bucket, if it names one, and the key of the object.
+fn parse_path(path: &str) -> (Option<&str>, &str) {
+    match path.strip_prefix("s3://") {
+        Some(rest) => match rest.split_once('/') {
+            Some((bucket, key)) => (Some(bucket), key),
+            None => (Some(rest), ""),
+        },
+        None => (None, path.trim_start_matches('/')),
+    }
+}
+
+#[derive(VfsDerive, Default)]
+pub struct S3FS;
+
+impl S3FS {
+    fn open_store(&self, path: &str) -> ExtResult<(Arc<dyn ObjectStore>, Path)> {
+        let (bucket, key) = parse_path(path);
+        if key.is_empty() {
+            log::error!("S3 path {path} names no object");
+            return Err(ResultCode::InvalidArgs);
+        }
+        let mut builder = AmazonS3Builder::from_env();
+        if let Some(bucket) = bucket {
+            builder = builder.with_bucket_name(bucket);
+        }
+        let store = builder.build().map_err(|e| {
+            log::error!("failed to configure S3 for {path}: {e}");
+            ResultCode::Error
+        })?;
+        let key = Path::parse(key).map_err(|_| ResultCode::InvalidArgs)?;
+        Ok((Arc::new(store), key))
+    }
+}
+
+/// The most a single PUT can upload to S3.
+const MAX_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+impl VfsExtension for S3FS {
+    const NAME: &'static str = "s3";
+    const SUPPORTS_WAL: bool = false;
+    type File = S3File;
+
+    fn open_file(&self, path: &str, flags: i32, _direct: bool) -> ExtResult<Self::File> {
+        log::debug!("opening file with S3 VFS: {} flags: {}", path, flags);
+        let (store, key) = self.open_store(path)?;
+        S3File::open(store, key, flags & 1 != 0)
+    }
+
+    fn delete_file(&self, path: &str) -> ExtResult<()> {
+        log::debug!("deleting file with S3 VFS: {}", path);
+        let (store, key) = self.open_store(path)?;
+        match block_on(store.delete(&key))? {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => {
+                log::error!("failed to delete {path}: {e}");
+                Err(ResultCode::Error)
+            }
+        }
+    }
+
+    fn truncate_file(&self, file: &Self::File, size: i64) -> ExtResult<()> {
+        log::debug!("truncating file with S3 VFS: size: {size}");
+        let mut state = file.state.lock().map_err(|_| ResultCode::Error)?;
+        if size == 0 {
+            // The WAL after a commit was checkpointed. Delete it right away rather than
+            // downloading it, the database no longer needs it.
+            state.data = Some(Vec::new());
+            state.size = 0;
+            state.dirty = true;
+            return file.flush(&mut state);
+        }
+        file.load(&mut state)?.resize(size as usize, 0);
+        state.size = size as u64;
+        state.dirty = true;
+        Ok(())
+    }
+
+    fn close(&self, file: Self::File) -> ExtResult<()> {
+        log::debug!("closing file with S3 VFS");
+        // Writes the database didn't sync, e.g. with `PRAGMA synchronous = OFF`, would otherwise
+        // be lost.
+        let mut state = file.state.lock().map_err(|_| ResultCode::Error)?;
+        file.flush(&mut state)
+    }
+}
+
+pub struct S3File {
+    store: Arc<dyn ObjectStore>,
+    key: Path,
+    state: Mutex<S3FileState>,
+}
+
+struct S3FileState {
+    /// Size of the file, including writes that weren't uploaded yet.
+    size: u64,
+    /// The whole content of the object, downloaded on the first write. Reads are served from it
+    /// from then on, as it holds writes that weren't uploaded yet.
+    data: Option<Vec<u8>>,
+    /// Whether `data` has writes that weren't uploaded yet.
+    dirty: bool,
+}
+
+impl S3File {
+    fn open(store: Arc<dyn ObjectStore>, key: Path, create: bool) -> ExtResult<Self> {
+        let size = match block_on(store.head(&key))? {
+            Ok(meta) => meta.size,
+            // Like creating a local file, the object only comes into existence once synced.
+            Err(object_store::Error::NotFound { .. }) if create => 0,
+            Err(e) => {
+                log::error!("failed to open {key}: {e}");
+                return Err(ResultCode::Error);
+            }
+        };
+        Ok(S3File {
+            store,
+            key,
+            state: Mutex::new(S3FileState {
+                size,
+                data: None,
+                dirty: false,
+            }),
+        })
+    }
+
+    /// Downloads the object into `state`, unless it already was, so that it can be modified.
+    fn load<'a>(&self, state: &'a mut S3FileState) -> ExtResult<&'a mut Vec<u8>> {
+        if state.data.is_none() {
+            let data = if state.size == 0 {
+                Vec::new()
+            } else {
+                block_on(async { self.store.get(&self.key).await?.bytes().await })?
+                    .map_err(|e| {
+                        log::error!("failed to download {}: {e}", self.key);
+                        ResultCode::Error
+                    })?
+                    .to_vec()
+            };
+            state.data = Some(data);
+        }
+        Ok(state.data.as_mut().unwrap())
+    }
+
+    /// Uploads the writes that weren't uploaded yet. An empty file is deleted instead, so that a
+    /// truncated WAL doesn't linger as an empty object.
+    fn flush(&self, state: &mut S3FileState) -> ExtResult<()> {
+        if !state.dirty {
+            return Ok(());
+        }
+        if state.size == 0 {
+            match block_on(self.store.delete(&self.key))? {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+                Err(e) => {
+                    log::error!("failed to delete {}: {e}", self.key);
+                    return Err(ResultCode::Error);
+                }
+            }
+        } else {
+            let payload = PutPayload::from(state.data.clone().unwrap_or_default());
+            block_on(self.store.put(&self.key, payload))?.map_err(|e| {
+                log::error!("failed to upload {}: {e}", self.key);
+                ResultCode::Error
+            })?;
+        }
+        state.dirty = false;
+        Ok(())
+    }
+}
+
+impl VfsFile for S3File {
+    fn read(&mut self, buf: &mut [u8], count: usize, offset: i64) -> ExtResult<i32> {
+        log::debug!("reading file with S3 VFS: bytes: {count} offset: {offset}");
+        let state = self.state.lock().map_err(|_| ResultCode::Error)?;
+        let offset = offset as u64;
+        if offset >= state.size {
+            return Ok(0);
+        }
+        let end = state.size.min(offset + count as u64);
+        let len = (end - offset) as usize;
+        match &state.data {
+            Some(data) => buf[..len].copy_from_slice(&data[offset as usize..end as usize]),
+            None => {
+                let bytes =
+                    block_on(self.store.get_range(&self.key, offset..end))?.map_err(|e| {
+                        log::error!("failed to read {}: {e}", self.key);
+                        ResultCode::Error
+                    })?;
+                buf[..len].copy_from_slice(&bytes);
+            }
+        }
+        Ok(len as i32)
+    }
+
+    fn write(&mut self, buf: &[u8], count: usize, offset: i64) -> ExtResult<i32> {
+        log::debug!("writing to file with S3 VFS: bytes: {count} offset: {offset}");
+        let mut state = self.state.lock().map_err(|_| ResultCode::Error)?;
+        if offset as u64 + count as u64 > MAX_OBJECT_SIZE {
+            log::error!(
+                "{} would grow past the 5 GiB an S3 object can have",
+                self.key
+            );
+            return Err(ResultCode::Error);
+        }
+        let data = self.load(&mut state)?;
+        let offset = offset as usize;
+        if data.len() < offset + count {
+            data.resize(offset + count, 0);
+        }
+        data[offset..offset + count].copy_from_slice(&buf[..count]);
+        state.size = state.size.max((offset + count) as u64);
+        state.dirty = true;
+        Ok(count as i32)
+    }
+
+    fn sync(&self) -> ExtResult<()> {
+        log::debug!("syncing file with S3 VFS");
+        let mut state = self.state.lock().map_err(|_| ResultCode::Error)?;
+        self.flush(&mut state)
+    }
+
+    fn size(&self) -> i64 {
+        self.state
+            .lock()
+            .map(|state| state.size as i64)
+            .unwrap_or(-1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    fn open(store: &Arc<InMemory>, create: bool) -> ExtResult<S3File> {
+        S3File::open(store.clone(), Path::from("app.db"), create)
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("s3://bucket/dir/app.db"),
+            (Some("bucket"), "dir/app.db")
+        );
+        assert_eq!(parse_path("s3://bucket"), (Some("bucket"), ""));
+        assert_eq!(parse_path("s3://bucket/"), (Some("bucket"), ""));
+        assert_eq!(parse_path("dir/app.db"), (None, "dir/app.db"));
+        assert_eq!(parse_path("/app.db"), (None, "app.db"));
+    }
+
+    #[test]
+    fn test_open_missing_object() {
+        let store = Arc::new(InMemory::new());
+        assert!(open(&store, false).is_err());
+        let file = open(&store, true).unwrap();
+        assert_eq!(file.size(), 0);
+        // Nothing is uploaded until the file is synced.
+        assert!(open(&store, false).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let store = Arc::new(InMemory::new());
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut file = open(&store, true).unwrap();
+        assert_eq!(file.write(&data, data.len(), 100).unwrap(), 10_000);
+        assert_eq!(file.size(), 10_100);
+        let mut buf = vec![0xaa; 200];
+        assert_eq!(file.read(&mut buf, 200, 0).unwrap(), 200);
+        assert!(buf[..100].iter().all(|&b| b == 0));
+        assert_eq!(&buf[100..], &data[..100]);
+        file.sync().unwrap();
+
+        // A new file reads the uploaded object with ranged requests.
+        let mut file = open(&store, false).unwrap();
+        assert_eq!(file.size(), 10_100);
+        let mut buf = vec![0; 500];
+        assert_eq!(file.read(&mut buf, 500, 9_800).unwrap(), 300);
+        assert_eq!(&buf[..300], &data[9_700..]);
+        assert_eq!(file.read(&mut buf, 500, 10_100).unwrap(), 0);
+
+        file.write(b"patched", 7, 5_000).unwrap();
+        S3FS.truncate_file(&file, 6_000).unwrap();
+        assert_eq!(file.size(), 6_000);
+        file.sync().unwrap();
+
+        let mut file = open(&store, false).unwrap();
+        assert_eq!(file.size(), 6_000);
+        let mut buf = vec![0; 6_000];
+        assert_eq!(file.read(&mut buf, 6_000, 0).unwrap(), 6_000);
+        assert_eq!(&buf[5_000..5_007], b"patched");
+        assert_eq!(&buf[5_007..], &data[4_907..5_900]);
+    }
+
+    #[test]
+    fn test_close_and_truncate() {
+        let store = Arc::new(InMemory::new());
+        let mut file = open(&store, true).unwrap();
+        file.write(b"frames", 6, 0).unwrap();
+        // Closing uploads what wasn't synced.
+        S3FS.close(file).unwrap();
+        let file = open(&store, false).unwrap();
+        assert_eq!(file.size(), 6);
+
+        // Truncating to nothing deletes the object.
+        S3FS.truncate_file(&file, 0).unwrap();
+        assert_eq!(file.size(), 0);
+        assert!(open(&store, false).is_err());
+        S3FS.close(file).unwrap();
+        assert!(open(&store, false).is_err());
+    }
+
+    #[test]
+    fn test_inside_runtime() {
+        let store = Arc::new(InMemory::new());
+        let host = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        host.block_on(async {
+            let mut file = open(&store, true).unwrap();
+            file.write(b"hello", 5, 0).unwrap();
+            file.sync().unwrap();
+            let mut file = open(&store, false).unwrap();
+            let mut buf = [0; 5];
+            assert_eq!(file.read(&mut buf, 5, 0).unwrap(), 5);
+            assert_eq!(&buf, b"hello");
+        });
+    }
+}
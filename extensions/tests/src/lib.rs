@@ -5,16 +5,18 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
 use turso_ext::{
-    register_extension, scalar, Connection, ConstraintInfo, ConstraintOp, ConstraintUsage,
-    ExtResult, IndexInfo, OrderByInfo, ResultCode, StepResult, VTabCursor, VTabKind, VTabModule,
-    VTabModuleDerive, VTable, Value,
+    collation, register_extension, scalar, AggFunc, AggregateDerive, Connection, ConstraintInfo,
+    ConstraintOp, ConstraintUsage, ExtResult, IndexInfo, OrderByInfo, ResultCode, StepResult,
+    VTabCursor, VTabKind, VTabModule, VTabModuleDerive, VTable, Value,
 };
 #[cfg(not(target_family = "wasm"))]
 use turso_ext::{VfsDerive, VfsExtension, VfsFile};
 
 register_extension! {
-    vtabs: { KVStoreVTabModule, TableStatsVtabModule },
+    vtabs: { KVStoreVTabModule, TableStatsVtabModule, MirrorVTabModule },
     scalars: { test_scalar },
+    aggregates: { TestGroupConcat, TestConcatArgs },
+    collations: { test_reverse },
     vfs: { TestFS },
 }
 
@@ -38,7 +40,7 @@ impl VTabModule for KVStoreVTabModule {
 
     fn create(_args: &[Value]) -> Result<(String, Self::Table), ResultCode> {
         let schema = "CREATE TABLE x (key TEXT PRIMARY KEY, value TEXT);".to_string();
-        Ok((schema, KVStoreTable {}))
+        Ok((schema, KVStoreTable::default()))
     }
 }
 
@@ -132,7 +134,11 @@ impl VTabCursor for KVStoreCursor {
     }
 }
 
-pub struct KVStoreTable {}
+#[derive(Default)]
+pub struct KVStoreTable {
+    /// A copy of the store taken when a transaction changes the table, restored on rollback.
+    snapshot: Option<BTreeMap<i64, (String, String)>>,
+}
 
 impl VTable for KVStoreTable {
     type Cursor = KVStoreCursor;
@@ -187,7 +193,11 @@ impl VTable for KVStoreTable {
         }
     }
 
-    fn insert(&mut self, values: &[Value]) -> Result<i64, Self::Error> {
+    fn insert(
+        &mut self,
+        _conn: Option<Arc<Connection>>,
+        values: &[Value],
+    ) -> Result<i64, Self::Error> {
         let key = values
             .first()
             .and_then(|v| v.to_text())
@@ -206,18 +216,23 @@ impl VTable for KVStoreTable {
         Ok(rowid)
     }
 
-    fn delete(&mut self, rowid: i64) -> Result<(), Self::Error> {
+    fn delete(&mut self, _conn: Option<Arc<Connection>>, rowid: i64) -> Result<(), Self::Error> {
         let mut store = GLOBAL_STORE.lock().unwrap();
         store.remove(&rowid);
         Ok(())
     }
 
-    fn update(&mut self, rowid: i64, values: &[Value]) -> Result<(), Self::Error> {
+    fn update(
+        &mut self,
+        conn: Option<Arc<Connection>>,
+        rowid: i64,
+        values: &[Value],
+    ) -> Result<(), Self::Error> {
         {
             let mut store = GLOBAL_STORE.lock().unwrap();
             store.remove(&rowid);
         }
-        let _ = self.insert(values)?;
+        let _ = self.insert(conn, values)?;
         Ok(())
     }
 
@@ -225,6 +240,23 @@ impl VTable for KVStoreTable {
         println!("VDestroy called");
         Ok(())
     }
+
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        self.snapshot = Some(GLOBAL_STORE.lock().unwrap().clone());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), Self::Error> {
+        self.snapshot = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        if let Some(snapshot) = self.snapshot.take() {
+            *GLOBAL_STORE.lock().unwrap() = snapshot;
+        }
+        Ok(())
+    }
 }
 
 pub struct TestFile {
@@ -245,6 +277,52 @@ fn test_scalar(_args: turso_ext::Value) -> turso_ext::Value {
     turso_ext::Value::from_integer(42)
 }
 
+#[collation(name = "test_reverse")]
+fn test_reverse(lhs: &str, rhs: &str) -> std::cmp::Ordering {
+    rhs.cmp(lhs)
+}
+
+/// Joins its arguments with commas, and can also be called as `test_string_agg`.
+#[derive(AggregateDerive)]
+pub struct TestGroupConcat;
+
+impl AggFunc for TestGroupConcat {
+    type State = Vec<String>;
+    type Error = String;
+    const NAME: &'static str = "test_group_concat";
+    const ALIAS: Option<&'static str> = Some("test_string_agg");
+    const ARGS: i32 = 1;
+
+    fn step(state: &mut Self::State, args: &[Value]) {
+        if let Some(text) = args.first().and_then(|v| v.to_text()) {
+            state.push(text.to_string());
+        }
+    }
+
+    fn finalize(state: Self::State) -> Result<Value, Self::Error> {
+        Ok(Value::from_text(state.join(",")))
+    }
+}
+
+/// Joins all the arguments of every call with commas, for any number of arguments.
+#[derive(AggregateDerive)]
+pub struct TestConcatArgs;
+
+impl AggFunc for TestConcatArgs {
+    type State = Vec<String>;
+    type Error = String;
+    const NAME: &'static str = "test_concat_args";
+    const ARGS: i32 = -1;
+
+    fn step(state: &mut Self::State, args: &[Value]) {
+        state.extend(args.iter().filter_map(|v| v.to_text()).map(String::from));
+    }
+
+    fn finalize(state: Self::State) -> Result<Value, Self::Error> {
+        Ok(Value::from_text(state.join(",")))
+    }
+}
+
 #[cfg(not(target_family = "wasm"))]
 impl VfsExtension for TestFS {
     const NAME: &'static str = "testvfs";
@@ -260,6 +338,18 @@ impl VfsExtension for TestFS {
             .map_err(|_| ResultCode::Error)?;
         Ok(TestFile { file })
     }
+
+    fn delete_file(&self, path: &str) -> ExtResult<()> {
+        log::debug!("deleting file with testing VFS: {}", path);
+        std::fs::remove_file(path).map_err(|_| ResultCode::Error)
+    }
+
+    fn truncate_file(&self, file: &Self::File, size: i64) -> ExtResult<()> {
+        log::debug!("truncating file with testing VFS: size: {size}");
+        file.file
+            .set_len(size as u64)
+            .map_err(|_| ResultCode::Error)
+    }
 }
 
 #[cfg(not(target_family = "wasm"))]
@@ -435,3 +525,157 @@ impl VTabCursor for StatsCursor {
         self.pos as i64
     }
 }
+
+/// Keeps its rows in the regular `mirror_shadow(key, value)` table of the connection that
+/// writes to it, so every insert/update/delete runs nested SQL in the caller's transaction.
+#[derive(VTabModuleDerive, Default)]
+pub struct MirrorVTabModule;
+
+pub struct MirrorTable {}
+
+fn mirror_columns(values: &[Value]) -> (Value, Value) {
+    let text =
+        |v: Option<&Value>| Value::from_text(v.and_then(|v| v.to_text()).unwrap_or("").to_string());
+    (text(values.first()), text(values.get(1)))
+}
+
+pub struct MirrorCursor {
+    pos: usize,
+    rows: Vec<(i64, String, String)>,
+    conn: Option<Arc<Connection>>,
+}
+
+impl VTabModule for MirrorVTabModule {
+    type Table = MirrorTable;
+    const VTAB_KIND: VTabKind = VTabKind::VirtualTable;
+    const NAME: &'static str = "mirror";
+
+    fn create(_args: &[Value]) -> Result<(String, Self::Table), ResultCode> {
+        let schema = "CREATE TABLE x(key TEXT, value TEXT);".to_string();
+        Ok((schema, MirrorTable {}))
+    }
+}
+
+impl VTable for MirrorTable {
+    type Cursor = MirrorCursor;
+    type Error = String;
+
+    fn open(&self, conn: Option<Arc<Connection>>) -> Result<Self::Cursor, Self::Error> {
+        Ok(MirrorCursor {
+            pos: 0,
+            rows: Vec::new(),
+            conn,
+        })
+    }
+
+    fn insert(
+        &mut self,
+        conn: Option<Arc<Connection>>,
+        values: &[Value],
+    ) -> Result<i64, Self::Error> {
+        let conn = conn.ok_or("no connection present")?;
+        let (key, value) = mirror_columns(values);
+        conn.execute(
+            "INSERT INTO mirror_shadow (key, value) VALUES (?, ?);",
+            &[key, value],
+        )
+        .map_err(|_| "failed to write to mirror_shadow".to_string())?;
+        // the row written above must be visible before the statement commits
+        let mut stmt = conn
+            .prepare("SELECT max(rowid) FROM mirror_shadow;")
+            .map_err(|_| "failed to read from mirror_shadow".to_string())?;
+        let rowid = match stmt.step() {
+            StepResult::Row => stmt.get_row()[0].to_integer(),
+            _ => None,
+        };
+        stmt.close();
+        rowid.ok_or_else(|| "inserted row not visible".to_string())
+    }
+
+    fn update(
+        &mut self,
+        conn: Option<Arc<Connection>>,
+        rowid: i64,
+        values: &[Value],
+    ) -> Result<(), Self::Error> {
+        let conn = conn.ok_or("no connection present")?;
+        let (key, value) = mirror_columns(values);
+        conn.execute(
+            "UPDATE mirror_shadow SET key = ?, value = ? WHERE rowid = ?;",
+            &[key, value, Value::from_integer(rowid)],
+        )
+        .map_err(|_| "failed to update mirror_shadow".to_string())?;
+        Ok(())
+    }
+
+    fn delete(&mut self, conn: Option<Arc<Connection>>, rowid: i64) -> Result<(), Self::Error> {
+        let conn = conn.ok_or("no connection present")?;
+        conn.execute(
+            "DELETE FROM mirror_shadow WHERE rowid = ?;",
+            &[Value::from_integer(rowid)],
+        )
+        .map_err(|_| "failed to delete from mirror_shadow".to_string())?;
+        Ok(())
+    }
+}
+
+impl VTabCursor for MirrorCursor {
+    type Error = String;
+
+    fn filter(&mut self, _args: &[Value], _idx_info: Option<(&str, i32)>) -> ResultCode {
+        self.rows.clear();
+        self.pos = 0;
+        let Some(conn) = &self.conn else {
+            log::error!("no connection present");
+            return ResultCode::Error;
+        };
+        let Ok(mut stmt) = conn.prepare("SELECT rowid, key, value FROM mirror_shadow;") else {
+            return ResultCode::Error;
+        };
+        while let StepResult::Row = stmt.step() {
+            let row = stmt.get_row();
+            self.rows.push((
+                row[0].to_integer().unwrap_or(0),
+                row[1].to_text().unwrap_or("").to_string(),
+                row[2].to_text().unwrap_or("").to_string(),
+            ));
+        }
+        stmt.close();
+        if self.rows.is_empty() {
+            ResultCode::EOF
+        } else {
+            ResultCode::OK
+        }
+    }
+
+    fn column(&self, idx: u32) -> Result<Value, Self::Error> {
+        self.rows
+            .get(self.pos)
+            .ok_or("row out of range".to_string())
+            .and_then(|(_, key, value)| match idx {
+                0 => Ok(Value::from_text(key.clone())),
+                1 => Ok(Value::from_text(value.clone())),
+                _ => Err("bad column".into()),
+            })
+    }
+
+    fn next(&mut self) -> ResultCode {
+        self.pos += 1;
+        if self.pos >= self.rows.len() {
+            ResultCode::EOF
+        } else {
+            ResultCode::OK
+        }
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.rows.len()
+    }
+
+    fn rowid(&self) -> i64 {
+        self.rows
+            .get(self.pos)
+            .map(|(rowid, _, _)| *rowid)
+            .unwrap_or(-1)
+    }
+}
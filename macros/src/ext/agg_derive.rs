@@ -13,6 +13,11 @@ pub fn derive_agg_func(input: TokenStream) -> TokenStream {
     let register_fn_name = format_ident!("register_{}", struct_name);
 
     let expanded = quote! {
+        const _: () = ::std::assert!(
+            <#struct_name as ::turso_ext::AggFunc>::ARGS >= -1,
+            "AggFunc::ARGS must be a number of arguments, or -1 for any number of them"
+        );
+
         impl #struct_name {
             #[no_mangle]
             pub extern "C" fn #init_fn_name() -> *mut ::turso_ext::AggCtx {
@@ -68,7 +73,7 @@ pub fn derive_agg_func(input: TokenStream) -> TokenStream {
                     Err(_) => return ::turso_ext::ResultCode::Error,
                 };
 
-                (api.register_aggregate_function)(
+                let result = (api.register_aggregate_function)(
                     api.ctx,
                     c_name.as_ptr(),
                     #struct_name::ARGS,
@@ -78,6 +83,26 @@ pub fn derive_agg_func(input: TokenStream) -> TokenStream {
                         as ::turso_ext::StepFunction,
                     #struct_name::#finalize_fn_name
                         as ::turso_ext::FinalizeFunction,
+                );
+                if !result.is_ok() {
+                    return result;
+                }
+                let Some(alias) = <#struct_name as ::turso_ext::AggFunc>::ALIAS else {
+                    return result;
+                };
+                let Ok(alias_c_name) = ::std::ffi::CString::new(alias) else {
+                    return ::turso_ext::ResultCode::Error;
+                };
+                (api.register_aggregate_function)(
+                    api.ctx,
+                    alias_c_name.as_ptr(),
+                    #struct_name::ARGS,
+                    #struct_name::#init_fn_name
+                        as ::turso_ext::InitAggFunction,
+                    #struct_name::#step_fn_name
+                        as ::turso_ext::StepFunction,
+                    #struct_name::#finalize_fn_name
+                        as ::turso_ext::FinalizeFunction,
                 )
             }
         }
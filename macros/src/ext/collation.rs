@@ -0,0 +1,76 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, ItemFn};
+
+use super::ScalarInfo;
+
+pub fn collation(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let mut ast = parse_macro_input!(input as ItemFn);
+    let fn_name = ast.sig.ident.clone();
+    let collation_info = parse_macro_input!(attr as ScalarInfo);
+    let name = &collation_info.name;
+    let register_fn_name = format_ident!("register_{}", fn_name);
+    // The comparison function is kept as is inside of the FFI function that core calls.
+    ast.sig.ident = format_ident!("compare");
+    let alias_check = if let Some(alias) = &collation_info.alias {
+        quote! {
+            let Ok(alias_c_name) = ::std::ffi::CString::new(#alias) else {
+                return ::turso_ext::ResultCode::Error;
+            };
+            let result = (api.register_collation)(
+                api.ctx,
+                alias_c_name.as_ptr(),
+                #fn_name,
+            );
+            if !result.is_ok() {
+                return result;
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let expanded = quote! {
+        #[no_mangle]
+        pub unsafe extern "C" fn #register_fn_name(
+            api: *const ::turso_ext::ExtensionApi
+        ) -> ::turso_ext::ResultCode {
+            if api.is_null() {
+                return ::turso_ext::ResultCode::Error;
+            }
+            let api = unsafe { &*api };
+            let Ok(c_name) = ::std::ffi::CString::new(#name) else {
+                return ::turso_ext::ResultCode::Error;
+            };
+            let result = (api.register_collation)(
+                api.ctx,
+                c_name.as_ptr(),
+                #fn_name,
+            );
+            if !result.is_ok() {
+                return result;
+            }
+            #alias_check
+            ::turso_ext::ResultCode::OK
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn #fn_name(
+            lhs: *const u8,
+            lhs_len: usize,
+            rhs: *const u8,
+            rhs_len: usize,
+        ) -> i32 {
+            #ast
+            let (lhs, rhs) = unsafe {
+                (
+                    ::std::str::from_utf8_unchecked(::std::slice::from_raw_parts(lhs, lhs_len)),
+                    ::std::str::from_utf8_unchecked(::std::slice::from_raw_parts(rhs, rhs_len)),
+                )
+            };
+            compare(lhs, rhs) as i32
+        }
+    };
+
+    TokenStream::from(expanded)
+}
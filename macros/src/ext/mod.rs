@@ -5,10 +5,12 @@ use syn::punctuated::Punctuated;
 use syn::token::Eq;
 use syn::{parse_macro_input, Ident, LitStr, Token};
 mod agg_derive;
+mod collation;
 mod scalars;
 mod vfs_derive;
 mod vtab_derive;
 pub use agg_derive::derive_agg_func;
+pub use collation::collation;
 pub use scalars::scalar;
 pub use vfs_derive::derive_vfs_module;
 pub use vtab_derive::derive_vtab_module;
@@ -18,6 +20,7 @@ pub fn register_extension(input: TokenStream) -> TokenStream {
     let RegisterExtensionInput {
         aggregates,
         scalars,
+        collations,
         vtabs,
         vfs,
     } = input_ast;
@@ -35,6 +38,21 @@ pub fn register_extension(input: TokenStream) -> TokenStream {
         }
     });
 
+    let collation_calls = collations.iter().map(|collation_ident| {
+        let register_fn = syn::Ident::new(
+            &format!("register_{}", collation_ident),
+            collation_ident.span(),
+        );
+        quote! {
+            {
+                let result = unsafe { #register_fn(api)};
+                if !result.is_ok() {
+                    return result;
+                }
+            }
+        }
+    });
+
     let aggregate_calls = aggregates.iter().map(|agg_ident| {
         let register_fn = syn::Ident::new(&format!("register_{}", agg_ident), agg_ident.span());
         quote! {
@@ -82,6 +100,7 @@ pub fn register_extension(input: TokenStream) -> TokenStream {
     });
     let static_aggregates = aggregate_calls.clone();
     let static_scalars = scalar_calls.clone();
+    let static_collations = collation_calls.clone();
     let static_vtabs = vtab_calls.clone();
 
     let expanded = quote! {
@@ -94,6 +113,8 @@ pub fn register_extension(input: TokenStream) -> TokenStream {
             pub unsafe extern "C" fn register_extension_static(api: &mut ::turso_ext::ExtensionApi) -> ::turso_ext::ResultCode {
                 #(#static_scalars)*
 
+                #(#static_collations)*
+
                 #(#static_aggregates)*
 
                 #(#static_vtabs)*
@@ -109,6 +130,8 @@ pub fn register_extension(input: TokenStream) -> TokenStream {
             pub unsafe extern "C" fn register_extension(api: &::turso_ext::ExtensionApi) -> ::turso_ext::ResultCode {
                 #(#scalar_calls)*
 
+                #(#collation_calls)*
+
                 #(#aggregate_calls)*
 
                 #(#vtab_calls)*
@@ -125,6 +148,7 @@ pub fn register_extension(input: TokenStream) -> TokenStream {
 pub(crate) struct RegisterExtensionInput {
     pub aggregates: Vec<Ident>,
     pub scalars: Vec<Ident>,
+    pub collations: Vec<Ident>,
     pub vtabs: Vec<Ident>,
     pub vfs: Vec<Ident>,
 }
@@ -133,13 +157,14 @@ impl syn::parse::Parse for RegisterExtensionInput {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let mut aggregates = Vec::new();
         let mut scalars = Vec::new();
+        let mut collations = Vec::new();
         let mut vtabs = Vec::new();
         let mut vfs = Vec::new();
         while !input.is_empty() {
             if input.peek(syn::Ident) && input.peek2(Token![:]) {
                 let section_name: Ident = input.parse()?;
                 input.parse::<Token![:]>()?;
-                let names = ["aggregates", "scalars", "vtabs", "vfs"];
+                let names = ["aggregates", "scalars", "collations", "vtabs", "vfs"];
                 if names.contains(&section_name.to_string().as_str()) {
                     let content;
                     syn::braced!(content in input);
@@ -150,6 +175,7 @@ impl syn::parse::Parse for RegisterExtensionInput {
                     match section_name.to_string().as_str() {
                         "aggregates" => aggregates = parsed_items,
                         "scalars" => scalars = parsed_items,
+                        "collations" => collations = parsed_items,
                         "vtabs" => vtabs = parsed_items,
                         "vfs" => vfs = parsed_items,
                         _ => unreachable!(),
@@ -169,6 +195,7 @@ impl syn::parse::Parse for RegisterExtensionInput {
         Ok(Self {
             aggregates,
             scalars,
+            collations,
             vtabs,
             vfs,
         })
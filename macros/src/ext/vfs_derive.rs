@@ -8,6 +8,7 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
     let register_fn_name = format_ident!("register_{}", struct_name);
     let register_static = format_ident!("register_static_{}", struct_name);
     let open_fn_name = format_ident!("{}_open", struct_name);
+    let open_with_key_fn_name = format_ident!("{}_open_with_key", struct_name);
     let close_fn_name = format_ident!("{}_close", struct_name);
     let read_fn_name = format_ident!("{}_read", struct_name);
     let write_fn_name = format_ident!("{}_write", struct_name);
@@ -16,6 +17,8 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
     let sync_fn_name = format_ident!("{}_sync", struct_name);
     let size_fn_name = format_ident!("{}_size", struct_name);
     let run_once_fn_name = format_ident!("{}_run_once", struct_name);
+    let delete_fn_name = format_ident!("{}_delete", struct_name);
+    let truncate_fn_name = format_ident!("{}_truncate", struct_name);
     let generate_random_number_fn_name = format_ident!("{}_generate_random_number", struct_name);
     let get_current_time_fn_name = format_ident!("{}_get_current_time", struct_name);
 
@@ -29,6 +32,7 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
                 vfs: ctx,
                 name,
                 open: #open_fn_name,
+                open_with_key: #open_with_key_fn_name,
                 close: #close_fn_name,
                 read: #read_fn_name,
                 write: #write_fn_name,
@@ -37,8 +41,11 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
                 sync: #sync_fn_name,
                 size: #size_fn_name,
                 run_once: #run_once_fn_name,
+                delete: #delete_fn_name,
+                truncate: #truncate_fn_name,
                 gen_random_number: #generate_random_number_fn_name,
                 current_time: #get_current_time_fn_name,
+                supports_wal: <#struct_name as ::turso_ext::VfsExtension>::SUPPORTS_WAL,
             };
             ::std::boxed::Box::into_raw(::std::boxed::Box::new(vfs_mod)) as *const ::turso_ext::VfsImpl
         }
@@ -52,6 +59,7 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
                 vfs: ctx,
                 name,
                 open: #open_fn_name,
+                open_with_key: #open_with_key_fn_name,
                 close: #close_fn_name,
                 read: #read_fn_name,
                 write: #write_fn_name,
@@ -60,8 +68,11 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
                 sync: #sync_fn_name,
                 size: #size_fn_name,
                 run_once: #run_once_fn_name,
+                delete: #delete_fn_name,
+                truncate: #truncate_fn_name,
                 gen_random_number: #generate_random_number_fn_name,
                 current_time: #get_current_time_fn_name,
+                supports_wal: <#struct_name as ::turso_ext::VfsExtension>::SUPPORTS_WAL,
             };
             let vfsimpl = ::std::boxed::Box::into_raw(::std::boxed::Box::new(vfs_mod)) as *const ::turso_ext::VfsImpl;
             (api.vfs_interface.register_vfs)(name, vfsimpl)
@@ -89,6 +100,34 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
             ::std::boxed::Box::into_raw(::std::boxed::Box::new(vfs_file)) as *const ::std::ffi::c_void
         }
 
+        #[no_mangle]
+        pub unsafe extern "C" fn #open_with_key_fn_name(
+            ctx: *const ::std::ffi::c_void,
+            path: *const ::std::ffi::c_char,
+            flags: i32,
+            direct: bool,
+            key: *const u8,
+            key_len: usize,
+        ) -> *const ::std::ffi::c_void {
+            let ctx = &*(ctx as *const ::turso_ext::VfsImpl);
+            let Ok(path_str) = ::std::ffi::CStr::from_ptr(path).to_str() else {
+                  return ::std::ptr::null_mut();
+            };
+            if key.is_null() {
+                return ::std::ptr::null();
+            }
+            let key = ::std::slice::from_raw_parts(key, key_len);
+            let vfs = &*(ctx.vfs as *const #struct_name);
+            let Ok(file_handle) = <#struct_name as ::turso_ext::VfsExtension>::open_file_with_key(vfs, path_str, flags, direct, key) else {
+                return ::std::ptr::null();
+            };
+            let boxed = ::std::boxed::Box::into_raw(::std::boxed::Box::new(file_handle)) as *const ::std::ffi::c_void;
+            let Ok(vfs_file) = ::turso_ext::VfsFileImpl::new(boxed, ctx) else {
+                return ::std::ptr::null();
+            };
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(vfs_file)) as *const ::std::ffi::c_void
+        }
+
         #[no_mangle]
         pub unsafe extern "C" fn #close_fn_name(file_ptr: *const ::std::ffi::c_void) -> ::turso_ext::ResultCode {
             if file_ptr.is_null() {
@@ -132,6 +171,40 @@ pub fn derive_vfs_module(input: TokenStream) -> TokenStream {
             ::turso_ext::ResultCode::OK
         }
 
+        #[no_mangle]
+        pub unsafe extern "C" fn #delete_fn_name(
+            ctx: *const ::std::ffi::c_void,
+            path: *const ::std::ffi::c_char,
+        ) -> ::turso_ext::ResultCode {
+            if ctx.is_null() || path.is_null() {
+                return ::turso_ext::ResultCode::Error;
+            }
+            let ctx = &*(ctx as *const ::turso_ext::VfsImpl);
+            let Ok(path_str) = ::std::ffi::CStr::from_ptr(path).to_str() else {
+                return ::turso_ext::ResultCode::InvalidArgs;
+            };
+            let vfs = &*(ctx.vfs as *const #struct_name);
+            if let Err(e) = <#struct_name as ::turso_ext::VfsExtension>::delete_file(vfs, path_str) {
+                return e;
+            }
+            ::turso_ext::ResultCode::OK
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn #truncate_fn_name(file_ptr: *const ::std::ffi::c_void, size: i64) -> ::turso_ext::ResultCode {
+            if file_ptr.is_null() {
+                return ::turso_ext::ResultCode::Error;
+            }
+            let vfs_file: &mut ::turso_ext::VfsFileImpl = &mut *(file_ptr as *mut ::turso_ext::VfsFileImpl);
+            let vfs = &*((*vfs_file.vfs).vfs as *const #struct_name);
+            let file: &<#struct_name as ::turso_ext::VfsExtension>::File =
+                &*(vfs_file.file as *const <#struct_name as ::turso_ext::VfsExtension>::File);
+            if let Err(e) = <#struct_name as ::turso_ext::VfsExtension>::truncate_file(vfs, file, size) {
+                return e;
+            }
+            ::turso_ext::ResultCode::OK
+        }
+
         #[no_mangle]
         pub unsafe extern "C" fn #write_fn_name(file_ptr: *const ::std::ffi::c_void, buf: *const u8, count: usize, offset: i64) -> i32 {
             if file_ptr.is_null() {
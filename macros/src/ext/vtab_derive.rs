@@ -18,6 +18,9 @@ pub fn derive_vtab_module(input: TokenStream) -> TokenStream {
     let rowid_fn_name = format_ident!("rowid_{}", struct_name);
     let destroy_fn_name = format_ident!("destroy_{}", struct_name);
     let best_idx_fn_name = format_ident!("best_idx_{}", struct_name);
+    let begin_fn_name = format_ident!("begin_{}", struct_name);
+    let commit_fn_name = format_ident!("commit_{}", struct_name);
+    let rollback_fn_name = format_ident!("rollback_{}", struct_name);
 
     let expanded = quote! {
         impl #struct_name {
@@ -136,6 +139,7 @@ pub fn derive_vtab_module(input: TokenStream) -> TokenStream {
             #[no_mangle]
             unsafe extern "C" fn #update_fn_name(
                 table: *const ::std::ffi::c_void,
+                conn: *mut ::turso_ext::Conn,
                 argc: i32,
                 argv: *const ::turso_ext::Value,
                 p_out_rowid: *mut i64,
@@ -146,6 +150,7 @@ pub fn derive_vtab_module(input: TokenStream) -> TokenStream {
 
                 let table = &mut *(table as *mut <#struct_name as ::turso_ext::VTabModule>::Table);
                 let args = ::std::slice::from_raw_parts(argv, argc as usize);
+                let conn = if conn.is_null() { None } else { Some(::std::sync::Arc::new(::turso_ext::Connection::new(conn)))};
 
                 let old_rowid = match args.get(0).map(|v| v.value_type()) {
                     Some(::turso_ext::ValueType::Integer) => args.get(0).unwrap().to_integer(),
@@ -159,21 +164,21 @@ pub fn derive_vtab_module(input: TokenStream) -> TokenStream {
                 match (old_rowid, new_rowid) {
                     // DELETE: old_rowid provided, no new_rowid
                     (Some(old), None) => {
-                     if <#struct_name as VTabModule>::Table::delete(table, old).is_err() {
+                     if <#struct_name as VTabModule>::Table::delete(table, conn, old).is_err() {
                             return ::turso_ext::ResultCode::Error;
                       }
                             return ::turso_ext::ResultCode::OK;
                     }
                     // UPDATE: old_rowid provided and new_rowid may exist
                     (Some(old), Some(new)) => {
-                        if <#struct_name as VTabModule>::Table::update(table, old, &columns).is_err() {
+                        if <#struct_name as VTabModule>::Table::update(table, conn, old, &columns).is_err() {
                             return ::turso_ext::ResultCode::Error;
                         }
                         return ::turso_ext::ResultCode::OK;
                     }
                     // INSERT: no old_rowid (old_rowid = None)
                     (None, _) => {
-                        if let Ok(rowid) = <#struct_name as VTabModule>::Table::insert(table, &columns) {
+                        if let Ok(rowid) = <#struct_name as VTabModule>::Table::insert(table, conn, &columns) {
                             if !p_out_rowid.is_null() {
                                 *p_out_rowid = rowid;
                                  return ::turso_ext::ResultCode::RowID;
@@ -212,6 +217,48 @@ pub fn derive_vtab_module(input: TokenStream) -> TokenStream {
                 return ::turso_ext::ResultCode::OK;
             }
 
+            #[no_mangle]
+            unsafe extern "C" fn #begin_fn_name(
+                table: *const ::std::ffi::c_void,
+            ) -> ::turso_ext::ResultCode {
+                if table.is_null() {
+                    return ::turso_ext::ResultCode::Error;
+                }
+                let table = &mut *(table as *mut <#struct_name as ::turso_ext::VTabModule>::Table);
+                if <#struct_name as ::turso_ext::VTabModule>::Table::begin(table).is_err() {
+                    return ::turso_ext::ResultCode::Error;
+                }
+                ::turso_ext::ResultCode::OK
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn #commit_fn_name(
+                table: *const ::std::ffi::c_void,
+            ) -> ::turso_ext::ResultCode {
+                if table.is_null() {
+                    return ::turso_ext::ResultCode::Error;
+                }
+                let table = &mut *(table as *mut <#struct_name as ::turso_ext::VTabModule>::Table);
+                if <#struct_name as ::turso_ext::VTabModule>::Table::commit(table).is_err() {
+                    return ::turso_ext::ResultCode::Error;
+                }
+                ::turso_ext::ResultCode::OK
+            }
+
+            #[no_mangle]
+            unsafe extern "C" fn #rollback_fn_name(
+                table: *const ::std::ffi::c_void,
+            ) -> ::turso_ext::ResultCode {
+                if table.is_null() {
+                    return ::turso_ext::ResultCode::Error;
+                }
+                let table = &mut *(table as *mut <#struct_name as ::turso_ext::VTabModule>::Table);
+                if <#struct_name as ::turso_ext::VTabModule>::Table::rollback(table).is_err() {
+                    return ::turso_ext::ResultCode::Error;
+                }
+                ::turso_ext::ResultCode::OK
+            }
+
             #[no_mangle]
             pub unsafe extern "C" fn #best_idx_fn_name(
                 constraints: *const ::turso_ext::ConstraintInfo,
@@ -247,6 +294,9 @@ pub fn derive_vtab_module(input: TokenStream) -> TokenStream {
                     rowid: Self::#rowid_fn_name,
                     destroy: Self::#destroy_fn_name,
                     best_idx: Self::#best_idx_fn_name,
+                    begin: Self::#begin_fn_name,
+                    commit: Self::#commit_fn_name,
+                    rollback: Self::#rollback_fn_name,
                 };
                 (api.register_vtab_module)(api.ctx, name_c, module, <#struct_name as ::turso_ext::VTabModule>::VTAB_KIND)
             }
@@ -199,6 +199,22 @@ pub fn scalar(attr: TokenStream, input: TokenStream) -> TokenStream {
     ext::scalar(attr, input)
 }
 
+/// Declare a collation sequence for your extension. This requires the name:
+/// #[collation(name = "example")] of the collation, as used in `COLLATE example`.
+/// The function compares two strings and returns their `Ordering`.
+/// ```ignore
+/// use std::cmp::Ordering;
+/// use turso_ext::collation;
+/// #[collation(name = "reverse")]
+/// fn reverse(lhs: &str, rhs: &str) -> Ordering {
+///     rhs.cmp(lhs)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn collation(attr: TokenStream, input: TokenStream) -> TokenStream {
+    ext::collation(attr, input)
+}
+
 /// Define an aggregate function for your extension by deriving
 /// AggregateDerive on a struct that implements the AggFunc trait.
 /// ```ignore
@@ -276,17 +292,17 @@ pub fn derive_agg_func(input: TokenStream) -> TokenStream {
 /// /// **Optional** methods for non-readonly tables:
 ///
 ///  /// Update the row with the provided values, return the new rowid
-///  fn update(&mut self, rowid: i64, args: &[Value]) -> Result<Option<i64>, Self::Error> {
+///  fn update(&mut self, conn: Option<Arc<Connection>>, rowid: i64, args: &[Value]) -> Result<Option<i64>, Self::Error> {
 ///      Ok(None)// return Ok(None) for read-only
 ///  }
 ///
 ///  /// Insert a new row with the provided values, return the new rowid
-///  fn insert(&mut self, args: &[Value]) -> Result<(), Self::Error> {
+///  fn insert(&mut self, conn: Option<Arc<Connection>>, args: &[Value]) -> Result<(), Self::Error> {
 ///      Ok(()) //
 ///  }
 ///
 ///  /// Delete the row with the provided rowid
-///  fn delete(&mut self, rowid: i64) -> Result<(), Self::Error> {
+///  fn delete(&mut self, conn: Option<Arc<Connection>>, rowid: i64) -> Result<(), Self::Error> {
 ///    Ok(())
 ///  }
 ///
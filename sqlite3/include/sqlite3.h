@@ -262,6 +262,8 @@ const char *sqlite3_libversion(void);
 
 int sqlite3_libversion_number(void);
 
+int sqlite3_wal_autocheckpoint(sqlite3 *db, int n);
+
 int sqlite3_wal_checkpoint(sqlite3 *_db, const char *_db_name);
 
 int sqlite3_wal_checkpoint_v2(sqlite3 *db, const char *_db_name, int _mode, int *_log_size, int *_checkpoint_count);
@@ -189,8 +189,14 @@ pub unsafe extern "C" fn sqlite3_progress_handler(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn sqlite3_busy_timeout(_db: *mut sqlite3, _ms: ffi::c_int) -> ffi::c_int {
-    stub!();
+pub unsafe extern "C" fn sqlite3_busy_timeout(db: *mut sqlite3, ms: ffi::c_int) -> ffi::c_int {
+    if db.is_null() {
+        return SQLITE_MISUSE;
+    }
+    let db: &mut sqlite3 = &mut *db;
+    let db = db.inner.lock().unwrap();
+    db.conn.set_busy_timeout(ms.max(0) as u64);
+    SQLITE_OK
 }
 
 #[no_mangle]
@@ -1085,6 +1091,17 @@ fn sqlite3_errstr_impl(rc: i32) -> *const ffi::c_char {
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn sqlite3_wal_autocheckpoint(db: *mut sqlite3, n: ffi::c_int) -> ffi::c_int {
+    if db.is_null() {
+        return SQLITE_MISUSE;
+    }
+    let db: &mut sqlite3 = &mut *db;
+    let db = db.inner.lock().unwrap();
+    db.conn.set_wal_autocheckpoint(n.max(0) as u64);
+    SQLITE_OK
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn sqlite3_wal_checkpoint(
     db: *mut sqlite3,
@@ -1103,20 +1120,38 @@ pub unsafe extern "C" fn sqlite3_wal_checkpoint(
 pub unsafe extern "C" fn sqlite3_wal_checkpoint_v2(
     db: *mut sqlite3,
     _db_name: *const ffi::c_char,
-    _mode: ffi::c_int,
-    _log_size: *mut ffi::c_int,
-    _checkpoint_count: *mut ffi::c_int,
+    mode: ffi::c_int,
+    log_size: *mut ffi::c_int,
+    checkpoint_count: *mut ffi::c_int,
 ) -> ffi::c_int {
     if db.is_null() {
         return SQLITE_MISUSE;
     }
+    let mode = match mode {
+        SQLITE_CHECKPOINT_PASSIVE => turso_core::CheckpointMode::Passive,
+        SQLITE_CHECKPOINT_FULL => turso_core::CheckpointMode::Full,
+        SQLITE_CHECKPOINT_RESTART => turso_core::CheckpointMode::Restart,
+        SQLITE_CHECKPOINT_TRUNCATE => turso_core::CheckpointMode::Truncate,
+        _ => return SQLITE_MISUSE,
+    };
     let db: &mut sqlite3 = &mut *db;
     let db = db.inner.lock().unwrap();
-    // TODO: Checkpointing modes and reporting back log size and checkpoint count to caller.
-    if db.conn.checkpoint().is_err() {
-        return SQLITE_ERROR;
+    let (rc, num_wal_frames, num_checkpointed_frames) = match db.conn.checkpoint_with_mode(mode) {
+        Ok(result) => (
+            if result.busy { SQLITE_BUSY } else { SQLITE_OK },
+            result.num_wal_frames as ffi::c_int,
+            result.num_checkpointed_frames as ffi::c_int,
+        ),
+        Err(turso_core::LimboError::Busy) => (SQLITE_BUSY, -1, -1),
+        Err(_) => return SQLITE_ERROR,
+    };
+    if !log_size.is_null() {
+        *log_size = num_wal_frames;
     }
-    SQLITE_OK
+    if !checkpoint_count.is_null() {
+        *checkpoint_count = num_checkpointed_frames;
+    }
+    rc
 }
 
 /// Get the number of frames in the WAL.
@@ -1,5 +1,6 @@
 #!/usr/bin/env python3
 import os
+import re
 import time
 from pathlib import Path
 
@@ -288,6 +289,82 @@ def test_uri_readonly():
     turso.quit()
 
 
+def test_explain():
+    turso = TestTursoShell()
+    header = (
+        "addr  opcode         p1    p2    p3    p4             p5  comment\n"
+        "----  -------------  ----  ----  ----  -------------  --  -------------\n"
+    )
+    turso.run_test_fn(
+        "EXPLAIN SELECT 1;",
+        lambda res: res.startswith(header) and "ResultRow" in res,
+        "explain-matches-sqlite-layout",
+    )
+    turso.run_test_fn(
+        ".explain SELECT 1;",
+        lambda res: res.startswith(header) and "ResultRow" in res,
+        "explain-dot-command",
+    )
+    turso.run_test_fn(
+        ".explain SELECT * FROM users;",
+        lambda res: any(line[6:14] == "  Column" for line in res.splitlines()),
+        "explain-indents-loop-body",
+    )
+    turso.quit()
+
+
+def test_timer():
+    turso = TestTursoShell()
+    turso.execute_dot(".timer on")
+    turso.run_test_fn(
+        "SELECT 1;",
+        lambda res: re.fullmatch(r"1\nRun Time: real \d+\.\d{3} user \d+\.\d{6} sys \d+\.\d{6}", res) is not None,
+        "timer-run-time-line",
+    )
+    turso.execute_dot(".timer off")
+    turso.run_test("timer-off", "SELECT 1;", "1")
+    turso.quit()
+
+
+def test_read_and_bail():
+    script = Path("testing/read_script.sql")
+    script.write_text(
+        ".echo off\n"
+        "CREATE TABLE t (x INTEGER PRIMARY KEY, y TEXT);\n"
+        "INSERT INTO t VALUES (1, 'a;b');\n"
+        "INSERT INTO t VALUES (1, 'duplicate');\n"
+        "-- rows after the failing statement\n"
+        "INSERT INTO t VALUES (2, 'c');\n"
+    )
+    turso = TestTursoShell()
+    turso.run_test_fn(
+        ".read testing/read_script.sql",
+        lambda res: "UNIQUE constraint failed" in res
+        and "statement 3 failed" in res
+        and "query execution failed" not in res,
+        "read-bail-on",
+    )
+    turso.run_test("read-bail-on-stops", "SELECT y FROM t;", "a;b")
+    turso.quit()
+
+    turso = TestTursoShell()
+    turso.execute_dot(".bail off")
+    turso.run_test_fn(".read testing/read_script.sql", lambda res: "UNIQUE constraint failed" in res, "read-bail-off")
+    turso.run_test("read-bail-off-continues", "SELECT y FROM t;", "a;b\nc")
+    turso.quit()
+    script.unlink()
+
+
+def test_statements_after_error():
+    turso = TestTursoShell()
+    turso.run_test_fn(
+        "SELECT * FROM missing; SELECT 42;",
+        lambda res: "not found" in res and res.rstrip().endswith("42"),
+        "statements-after-error-run",
+    )
+    turso.quit()
+
+
 def main():
     console.info("Running all turso CLI tests...")
     test_basic_queries()
@@ -308,6 +385,10 @@ def main():
     test_update_with_limit()
     test_update_with_limit_and_offset()
     test_uri_readonly()
+    test_explain()
+    test_timer()
+    test_read_and_bail()
+    test_statements_after_error()
     console.info("All tests have passed")
 
 
@@ -55,6 +55,39 @@ def test_uuid():
         validate_string_uuid,
         "scalar alias's are registered properly",
     )
+    limbo.run_test_fn(
+        "SELECT uuid();",
+        lambda res: len(res) == 36 and res[14] == "4",
+        "uuid() returns a version 4 UUID string",
+    )
+    limbo.run_test_fn(
+        "SELECT typeof(uuid_blob()), length(uuid_blob());",
+        lambda res: res == "blob|16",
+        "uuid_blob() without arguments returns a random 16 byte blob",
+    )
+    limbo.run_test_fn(
+        "SELECT uuid_str(x'0123456789abcdef0123456789abcdef');",
+        lambda res: res == "01234567-89ab-cdef-0123-456789abcdef",
+    )
+    limbo.run_test_fn(
+        "SELECT hex(uuid_blob('{01234567-89AB-CDEF-0123-456789ABCDEF}'));",
+        lambda res: res == "0123456789ABCDEF0123456789ABCDEF",
+    )
+    limbo.run_test_fn(
+        "SELECT uuid_str('0123456789ABCDEF0123456789ABCDEF');",
+        lambda res: res == "01234567-89ab-cdef-0123-456789abcdef",
+        "uuid_str accepts text UUIDs",
+    )
+    limbo.run_test_fn(
+        "WITH u(id) AS (SELECT uuid()) SELECT uuid_str(uuid_blob(id)) = id FROM u;",
+        lambda res: res == "1",
+        "uuid strings round-trip through blobs",
+    )
+    limbo.run_test_fn(
+        "SELECT uuid_str('not a uuid') IS NULL, uuid_blob(x'0102') IS NULL;",
+        lambda res: res == "1|1",
+        "invalid UUIDs return NULL",
+    )
     limbo.quit()
 
 
@@ -246,6 +279,26 @@ def test_crypto():
         == "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",  # noqa: E501
         "sha512 should encrypt correctly",
     )
+    limbo.run_test_fn(
+        "SELECT hex(sha256(''));",
+        lambda a: a == "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+        "sha256 alias should hash the empty string correctly",
+    )
+    limbo.run_test_fn(
+        "SELECT hex(sha256(x'616263'));",
+        lambda a: a == "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD",
+        "sha256 alias should hash blobs correctly",
+    )
+    limbo.run_test_fn(
+        "SELECT length(sha512('abc'));",
+        lambda a: a == "64",
+        "sha512 alias should return a 64 byte blob",
+    )
+    limbo.run_test_fn(
+        "SELECT crypto_encode(hmac_sha256('Jefe', 'what do ya want for nothing?'), 'hex');",
+        lambda a: a == "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        "hmac_sha256 should match the RFC 4231 test vector",
+    )
 
     # Encoding and Decoding
     limbo.run_test_fn(
@@ -298,6 +351,21 @@ def test_crypto():
         validate_url_decode,
         "url should decode correctly",
     )
+    limbo.run_test_fn(
+        "SELECT base64_encode(x'68656c6c6f');",
+        validate_base64_encode,
+        "base64_encode should encode blobs correctly",
+    )
+    limbo.run_test_fn(
+        "SELECT hex(base64_decode('aGVsbG8='));",
+        lambda a: a == "68656C6C6F",
+        "base64_decode should decode to a blob",
+    )
+    limbo.run_test_fn(
+        "SELECT typeof(base64_decode('aGVsbG8='));",
+        lambda a: a == "blob",
+        "base64_decode should return a blob",
+    )
     limbo.quit()
 
 
@@ -432,6 +500,25 @@ def _test_kv(exec_name, ext_path):
         lambda res: res == "100|updated2",
         "there is only 1 key remaining after setting all keys to same value",
     )
+    if exec_name is None:
+        # The C version of kv_store used with SQLite doesn't implement xBegin/xRollback.
+        limbo.execute_dot("BEGIN;")
+        limbo.execute_dot("insert into t values ('rolled', 'back');")
+        limbo.execute_dot("delete from t where key = '100';")
+        limbo.execute_dot("ROLLBACK;")
+        limbo.run_test_fn(
+            "select * from t;",
+            lambda res: res == "100|updated2",
+            "changes to the vtable are undone on rollback",
+        )
+        limbo.execute_dot("BEGIN;")
+        limbo.execute_dot("insert into t values ('committed', 'value');")
+        limbo.execute_dot("COMMIT;")
+        limbo.run_test_fn(
+            "select count(*) from t;",
+            lambda res: res == "2",
+            "changes to the vtable are kept on commit",
+        )
     limbo.quit()
 
 
@@ -703,6 +790,75 @@ def test_csv():
     limbo.quit()
 
 
+def test_collation():
+    ext_path = "target/debug/libturso_ext_tests"
+    limbo = TestTursoShell()
+    limbo.execute_dot("CREATE TABLE t(x TEXT);")
+    limbo.execute_dot("INSERT INTO t VALUES ('b'), ('a'), ('c');")
+    limbo.run_test_fn(
+        "SELECT x FROM t ORDER BY x COLLATE test_reverse;",
+        lambda res: "no such collation sequence" in res,
+        "collation is not available before loading the extension",
+    )
+    limbo.execute_dot(f".load {ext_path}")
+    limbo.run_test_fn(
+        "SELECT x FROM t ORDER BY x COLLATE test_reverse;",
+        lambda res: res == "c\nb\na",
+        "ORDER BY uses the collation registered by the extension",
+    )
+    limbo.run_test_fn(
+        "SELECT 'a' < 'b' COLLATE test_reverse;",
+        lambda res: res == "0",
+        "comparisons use the collation registered by the extension",
+    )
+    limbo.execute_dot("CREATE TABLE u(x TEXT COLLATE test_reverse);")
+    limbo.execute_dot("INSERT INTO u VALUES ('b'), ('a'), ('c');")
+    limbo.run_test_fn(
+        "SELECT x FROM u ORDER BY x;",
+        lambda res: res == "c\nb\na",
+        "column collation registered by the extension",
+    )
+    limbo.quit()
+
+
+def test_aggregate_alias():
+    ext_path = "target/debug/libturso_ext_tests"
+    limbo = TestTursoShell()
+    limbo.execute_dot("CREATE TABLE t(x TEXT);")
+    limbo.execute_dot("INSERT INTO t VALUES ('a'), ('b'), ('c');")
+    limbo.execute_dot(f".load {ext_path}")
+    limbo.run_test_fn(
+        "SELECT test_group_concat(x) FROM t;",
+        lambda res: res == "a,b,c",
+        "aggregate is registered under its name",
+    )
+    limbo.run_test_fn(
+        "SELECT test_string_agg(x) FROM t;",
+        lambda res: res == "a,b,c",
+        "aggregate is registered under its alias",
+    )
+    limbo.quit()
+
+
+def test_aggregate_varargs():
+    ext_path = "target/debug/libturso_ext_tests"
+    limbo = TestTursoShell()
+    limbo.execute_dot("CREATE TABLE t(x TEXT, y TEXT, z TEXT);")
+    limbo.execute_dot("INSERT INTO t VALUES ('a', 'b', 'c'), ('d', 'e', 'f');")
+    limbo.execute_dot(f".load {ext_path}")
+    limbo.run_test_fn(
+        "SELECT test_concat_args(x) FROM t;",
+        lambda res: res == "a,d",
+        "aggregate with any number of arguments called with one",
+    )
+    limbo.run_test_fn(
+        "SELECT test_concat_args(x, y, z) FROM t;",
+        lambda res: res == "a,b,c,d,e,f",
+        "aggregate with any number of arguments called with three",
+    )
+    limbo.quit()
+
+
 def cleanup():
     if os.path.exists("testing/vfs.db"):
         os.remove("testing/vfs.db")
@@ -797,6 +953,45 @@ def test_tablestats():
     limbo.quit()
 
 
+def test_mirror():
+    ext_path = "target/debug/libturso_ext_tests"
+    limbo = TestTursoShell()
+    limbo.execute_dot("CREATE TABLE mirror_shadow(key TEXT, value TEXT);")
+    limbo.execute_dot(f".load {ext_path}")
+    limbo.execute_dot("CREATE VIRTUAL TABLE m USING mirror;")
+    limbo.run_test_fn(
+        "INSERT INTO m VALUES ('a', '1'), ('b', '2'); SELECT key, value FROM m ORDER BY key;",
+        lambda res: res == "a|1\nb|2",
+        "vtab insert sees rows it wrote earlier in the same statement",
+    )
+    limbo.run_test_fn(
+        "SELECT key, value FROM mirror_shadow ORDER BY key;",
+        lambda res: res == "a|1\nb|2",
+        "rows written through the vtab land in the shadow table",
+    )
+    limbo.run_test_fn(
+        "UPDATE m SET value = '3' WHERE key = 'b'; SELECT value FROM mirror_shadow WHERE key = 'b';",
+        lambda res: res == "3",
+        "vtab update is applied to the shadow table",
+    )
+    limbo.run_test_fn(
+        "BEGIN; INSERT INTO m VALUES ('c', '4'); SELECT count(*) FROM mirror_shadow;",
+        lambda res: res == "3",
+        "vtab writes are visible inside the open transaction",
+    )
+    limbo.run_test_fn(
+        "ROLLBACK; SELECT count(*) FROM mirror_shadow;",
+        lambda res: res == "2",
+        "vtab writes roll back with the invoking transaction",
+    )
+    limbo.run_test_fn(
+        "DELETE FROM m WHERE key = 'a'; SELECT key FROM mirror_shadow;",
+        lambda res: res == "b",
+        "vtab delete is applied to the shadow table",
+    )
+    limbo.quit()
+
+
 def main():
     try:
         test_regexp()
@@ -812,6 +1007,10 @@ def main():
         test_create_virtual_table()
         test_csv()
         test_tablestats()
+        test_mirror()
+        test_collation()
+        test_aggregate_alias()
+        test_aggregate_varargs()
     except Exception as e:
         console.error(f"Test FAILED: {e}")
         cleanup()
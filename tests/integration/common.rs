@@ -241,11 +241,13 @@ pub(crate) fn limbo_exec_rows_error(
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
     use std::vec;
     use tempfile::TempDir;
 
     use super::{limbo_exec_rows, limbo_exec_rows_error, TempDatabase};
     use rusqlite::types::Value;
+    use turso_core::IO;
 
     #[test]
     fn test_statement_columns() -> anyhow::Result<()> {
@@ -318,6 +320,279 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_limbo_open_readonly_rejects_writes() -> anyhow::Result<()> {
+        let path = TempDir::new().unwrap().keep().join("temp_open_readonly");
+        {
+            let db = TempDatabase::new_with_existent(&path, false);
+            let conn = db.connect_limbo();
+            limbo_exec_rows(&db, &conn, "CREATE TABLE t(a)");
+            limbo_exec_rows(&db, &conn, "INSERT INTO t VALUES (1)");
+            conn.close().unwrap()
+        }
+
+        #[allow(clippy::arc_with_non_send_sync)]
+        let io = std::sync::Arc::new(turso_core::PlatformIO::new().unwrap());
+        let db = turso_core::Database::open_readonly(io, path.to_str().unwrap())?;
+        let conn = db.connect()?;
+        assert!(conn.is_readonly());
+        assert!(conn.prepare("SELECT * FROM t").is_ok());
+        for sql in [
+            "INSERT INTO t VALUES (2)",
+            "UPDATE t SET a = 2",
+            "DELETE FROM t",
+            "CREATE TABLE u(b)",
+            "CREATE INDEX t_a ON t(a)",
+            "DROP TABLE t",
+            "ALTER TABLE t ADD COLUMN b",
+        ] {
+            let err = conn.prepare(sql).err();
+            assert!(
+                matches!(err, Some(turso_core::LimboError::ReadOnly)),
+                "{}: {:?}",
+                sql,
+                err
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_limbo_readonly_connection_rejects_write_locks() -> anyhow::Result<()> {
+        let db = TempDatabase::new_with_rusqlite("CREATE TABLE t(a);", false);
+        let conn = db.connect_limbo();
+        let mut insert = conn.prepare("INSERT INTO t VALUES (1)")?;
+        conn.set_readonly(true);
+
+        for sql in [
+            "BEGIN IMMEDIATE",
+            "BEGIN EXCLUSIVE",
+            "PRAGMA user_version = 1",
+            "PRAGMA wal_checkpoint",
+        ] {
+            let err = conn.prepare(sql).err();
+            assert!(
+                matches!(err, Some(turso_core::LimboError::ReadOnly)),
+                "{}: {:?}",
+                sql,
+                err
+            );
+        }
+        // A statement prepared before the connection became read-only fails to start its write
+        // transaction.
+        let err = insert.step().err();
+        assert!(
+            matches!(err, Some(turso_core::LimboError::ReadOnly)),
+            "{:?}",
+            err
+        );
+
+        assert_eq!(
+            limbo_exec_rows(&db, &conn, "PRAGMA user_version"),
+            vec![vec![Value::Integer(0)]]
+        );
+        limbo_exec_rows(&db, &conn, "PRAGMA cache_size = 100");
+        limbo_exec_rows(&db, &conn, "BEGIN");
+        assert!(limbo_exec_rows(&db, &conn, "SELECT * FROM t").is_empty());
+        limbo_exec_rows(&db, &conn, "COMMIT");
+        Ok(())
+    }
+
+    #[test]
+    fn test_limbo_open_uri() -> anyhow::Result<()> {
+        let path = TempDir::new().unwrap().keep().join("temp_open_uri");
+        let uri = format!("file:{}?cache=shared&_journal_mode=wal", path.display());
+        let db = turso_core::Database::open_uri(&uri)?;
+        let io = db.io();
+        let conn1 = db.connect()?;
+        let conn2 = db.connect()?;
+        for sql in ["CREATE TABLE t(a)", "INSERT INTO t VALUES (1)"] {
+            let mut stmt = conn1.prepare(sql)?;
+            while !matches!(stmt.step()?, turso_core::StepResult::Done) {
+                io.run_once()?;
+            }
+        }
+        let mut stmt = conn2.prepare("SELECT a FROM t")?;
+        let mut rows = Vec::new();
+        loop {
+            match stmt.step()? {
+                turso_core::StepResult::Row => rows.push(stmt.row().unwrap().get::<i64>(0)?),
+                turso_core::StepResult::IO => io.run_once()?,
+                turso_core::StepResult::Done => break,
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+        assert_eq!(rows, vec![1]);
+        drop(stmt);
+        conn1.close()?;
+        conn2.close()?;
+
+        let db = turso_core::Database::open_uri(&format!("file:{}?mode=ro", path.display()))?;
+        let conn = db.connect()?;
+        assert!(conn.is_readonly());
+        assert!(matches!(
+            conn.prepare("INSERT INTO t VALUES (2)").err(),
+            Some(turso_core::LimboError::ReadOnly)
+        ));
+
+        assert!(turso_core::Database::open_uri(&format!(
+            "file:{}?_journal_mode=delete",
+            path.display()
+        ))
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_limbo_open_uri_shared_cache() -> anyhow::Result<()> {
+        let path = TempDir::new()
+            .unwrap()
+            .keep()
+            .join("temp_open_uri_shared_cache");
+        let uri = format!("file:{}?cache=shared", path.display());
+        let db1 = turso_core::Database::open_uri(&uri)?;
+        let db2 = turso_core::Database::open_uri(&uri)?;
+        assert!(Arc::ptr_eq(&db1, &db2));
+        let private = turso_core::Database::open_uri(&format!("file:{}", path.display()))?;
+        assert!(!Arc::ptr_eq(&db1, &private));
+
+        // A named in-memory database can be opened more than once with cache=shared.
+        let uri = "file:shared_cache_memdb?mode=memory&cache=shared";
+        let db1 = turso_core::Database::open_uri(uri)?;
+        let io = db1.io();
+        let conn1 = db1.connect()?;
+        for sql in ["CREATE TABLE t(a)", "INSERT INTO t VALUES (1)"] {
+            let mut stmt = conn1.prepare(sql)?;
+            while !matches!(stmt.step()?, turso_core::StepResult::Done) {
+                io.run_once()?;
+            }
+        }
+        let db2 = turso_core::Database::open_uri(uri)?;
+        let conn2 = db2.connect()?;
+        let mut stmt = conn2.prepare("SELECT a FROM t")?;
+        let mut rows = Vec::new();
+        loop {
+            match stmt.step()? {
+                turso_core::StepResult::Row => rows.push(stmt.row().unwrap().get::<i64>(0)?),
+                turso_core::StepResult::IO => io.run_once()?,
+                turso_core::StepResult::Done => break,
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+        assert_eq!(rows, vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_limbo_open_uri_with_key() -> anyhow::Result<()> {
+        let path = TempDir::new()
+            .unwrap()
+            .keep()
+            .join("temp_open_uri_with_key");
+        let uri = format!("file:{}?key=secret", path.display());
+        // The encrypted VFS is not built into core, it has to be registered first.
+        unsafe {
+            turso_core::register_static_vfs(limbo_encryption::register_static_EncryptedFS())?
+        };
+
+        let db = turso_core::Database::open_uri(&uri)?;
+        let io = db.io();
+        let conn = db.connect()?;
+        for sql in ["CREATE TABLE t(a)", "INSERT INTO t VALUES ('hidden value')"] {
+            let mut stmt = conn.prepare(sql)?;
+            while !matches!(stmt.step()?, turso_core::StepResult::Done) {
+                io.run_once()?;
+            }
+        }
+        conn.close()?;
+        db.close(turso_core::CloseOptions::default())?;
+        // The key was wiped when the database was closed.
+        let other = format!("{}-other", path.display());
+        assert!(io
+            .open_file(&other, turso_core::OpenFlags::Create, false)
+            .is_err());
+        for file in [
+            path.clone(),
+            std::path::PathBuf::from(format!("{}-wal", path.display())),
+        ] {
+            let raw = std::fs::read(file)?;
+            assert!(!raw.windows(12).any(|w| w == b"hidden value"));
+        }
+
+        let db = turso_core::Database::open_uri(&uri)?;
+        let io = db.io();
+        let conn = db.connect()?;
+        let mut stmt = conn.prepare("SELECT a FROM t")?;
+        let mut rows = Vec::new();
+        loop {
+            match stmt.step()? {
+                turso_core::StepResult::Row => {
+                    rows.push(stmt.row().unwrap().get::<String>(0)?);
+                }
+                turso_core::StepResult::IO => io.run_once()?,
+                turso_core::StepResult::Done => break,
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+        assert_eq!(rows, vec!["hidden value".to_string()]);
+        drop(stmt);
+        conn.close()?;
+
+        let wrong = format!("file:{}?key=wrong", path.display());
+        assert!(turso_core::Database::open_uri(&wrong).is_err());
+        let shared = format!("file:{}?key=secret&cache=shared", path.display());
+        assert!(turso_core::Database::open_uri(&shared).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_limbo_database_builder() -> anyhow::Result<()> {
+        let path = TempDir::new().unwrap().keep().join("temp_database_builder");
+        {
+            let io: Arc<dyn IO + Send> = Arc::new(turso_core::PlatformIO::new().unwrap());
+            let db = turso_core::DatabaseBuilder::new(io.clone(), path.to_str().unwrap())
+                .cache_size(100)
+                .page_size(8192)
+                .build()?;
+            let db = TempDatabase {
+                path: path.clone(),
+                io,
+                db,
+            };
+            let conn = db.connect_limbo();
+            limbo_exec_rows(&db, &conn, "CREATE TABLE t(a)");
+            limbo_exec_rows(&db, &conn, "INSERT INTO t VALUES (1)");
+            let ret = limbo_exec_rows(&db, &conn, "PRAGMA page_size");
+            assert_eq!(ret, vec![vec![Value::Integer(8192)]]);
+            let ret = limbo_exec_rows(&db, &conn, "PRAGMA cache_size");
+            assert_eq!(ret, vec![vec![Value::Integer(100)]]);
+            limbo_exec_rows(&db, &conn, "PRAGMA cache_size = 200");
+            conn.reset_session()?;
+            let ret = limbo_exec_rows(&db, &conn, "PRAGMA cache_size");
+            assert_eq!(ret, vec![vec![Value::Integer(100)]]);
+            conn.close()?;
+        }
+
+        // The page size is only used to create the database.
+        let io: Arc<dyn IO + Send> = Arc::new(turso_core::PlatformIO::new().unwrap());
+        let db = turso_core::DatabaseBuilder::new(io.clone(), path.to_str().unwrap())
+            .page_size(1024)
+            .build()?;
+        let db = TempDatabase { path, io, db };
+        let conn = db.connect_limbo();
+        let ret = limbo_exec_rows(&db, &conn, "PRAGMA page_size");
+        assert_eq!(ret, vec![vec![Value::Integer(8192)]]);
+        let ret = limbo_exec_rows(&db, &conn, "SELECT a FROM t");
+        assert_eq!(ret, vec![vec![Value::Integer(1)]]);
+
+        let io: Arc<dyn IO + Send> = Arc::new(turso_core::PlatformIO::new().unwrap());
+        assert!(turso_core::DatabaseBuilder::new(io, "unused.db")
+            .page_size(1000)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_unique_index_ordering() -> anyhow::Result<()> {
         use rand::Rng;
@@ -1 +1,3 @@
+mod test_function_offset;
 mod test_function_rowid;
+mod test_load_extension;
@@ -0,0 +1,48 @@
+use crate::common::{limbo_exec_rows, TempDatabase};
+use rusqlite::types::Value;
+
+#[test]
+fn test_sqlite_offset_points_at_record() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t (y TEXT);", false);
+    {
+        // Closing the last rusqlite connection checkpoints every row into the database file.
+        let conn = rusqlite::Connection::open(&tmp_db.path)?;
+        for i in 0..200 {
+            conn.execute("INSERT INTO t VALUES (?)", [format!("row-{i}")])?;
+        }
+    }
+    let conn = tmp_db.connect_limbo();
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT y, sqlite_offset(y) FROM t");
+    assert_eq!(rows.len(), 200);
+
+    let file = std::fs::read(&tmp_db.path)?;
+    for row in rows {
+        let (Value::Text(text), Value::Integer(offset)) = (&row[0], &row[1]) else {
+            panic!("unexpected row: {:?}", row);
+        };
+        // A record with a single short TEXT column is laid out as:
+        // header size (2), serial type (2 * len + 13), then the text itself.
+        let record = &file[*offset as usize..];
+        assert_eq!(record[0], 2);
+        assert_eq!(record[1] as usize, 2 * text.len() + 13);
+        assert_eq!(&record[2..2 + text.len()], text.as_bytes());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_offset_is_null_for_wal_pages() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t (x INTEGER, y TEXT);", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("INSERT INTO t VALUES (1, 'in the wal')")?;
+
+    let rows = limbo_exec_rows(
+        &tmp_db,
+        &conn,
+        "SELECT sqlite_offset(x), sqlite_offset(1) FROM t",
+    );
+    assert_eq!(rows, vec![vec![Value::Null, Value::Null]]);
+    Ok(())
+}
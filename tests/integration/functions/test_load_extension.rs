@@ -0,0 +1,57 @@
+use crate::common::{limbo_exec_rows, TempDatabase};
+use rusqlite::types::Value;
+use std::path::PathBuf;
+
+/// The test extension of `extensions/tests`. `cargo build` builds it along with the rest of the
+/// workspace, as the extension tests of the Makefile also expect.
+fn test_extension_path() -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/debug/libturso_ext_tests");
+    turso_core::resolve_ext_path(path.to_str().unwrap()).unwrap_or_else(|_| {
+        panic!(
+            "{} was not built, run `cargo build -p turso_ext_tests` first",
+            path.display()
+        )
+    })
+}
+
+#[test]
+fn test_load_extension() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let ext_path = test_extension_path();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+
+    assert!(conn
+        .execute("CREATE VIRTUAL TABLE t USING kv_store")
+        .is_err());
+    conn.load_extension(&ext_path, None)?;
+    limbo_exec_rows(&tmp_db, &conn, "CREATE VIRTUAL TABLE t USING kv_store");
+    limbo_exec_rows(&tmp_db, &conn, "INSERT INTO t VALUES ('hello', 'world')");
+    assert_eq!(
+        limbo_exec_rows(&tmp_db, &conn, "SELECT value FROM t WHERE key = 'hello'"),
+        vec![vec![Value::Text("world".to_string())]]
+    );
+    assert_eq!(
+        limbo_exec_rows(&tmp_db, &conn, "SELECT test_scalar()"),
+        vec![vec![Value::Integer(42)]]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_load_extension_entry_point() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let ext_path = test_extension_path();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+
+    assert!(conn
+        .load_extension(&ext_path, Some("no_such_entry_point"))
+        .is_err());
+    conn.load_extension(&ext_path, Some("register_extension"))?;
+    assert_eq!(
+        limbo_exec_rows(&tmp_db, &conn, "SELECT test_scalar()"),
+        vec![vec![Value::Integer(42)]]
+    );
+    Ok(())
+}
@@ -10,7 +10,7 @@ mod tests {
     use rusqlite::params;
 
     use crate::{
-        common::{limbo_exec_rows, sqlite_exec_rows, TempDatabase},
+        common::{limbo_exec_rows, limbo_exec_rows_error, sqlite_exec_rows, TempDatabase},
         fuzz::grammar_generator::{const_str, rand_int, rand_str, GrammarGenerator},
     };
 
@@ -531,13 +531,26 @@ mod tests {
         let num_tables = rng.random_range(MIN_TABLES..=MAX_TABLES);
 
         const COLS: [&str; 3] = ["c1", "c2", "c3"];
+        // Columns of different tables get different affinities and collations, so that the
+        // same value can be stored as an integer, a real or a text depending on the table.
+        const COLUMN_TYPES: [&str; 6] = [
+            "INTEGER",
+            "REAL",
+            "TEXT",
+            "NUMERIC",
+            "",
+            "TEXT COLLATE NOCASE",
+        ];
+        const VALUES: [&str; 10] = [
+            "-1", "0", "1", "1.0", "2.5", "'1'", "'2.5'", "'a'", "'A'", "NULL",
+        ];
         for i in 0..num_tables {
             let table_name = format!("t{}", i);
             let create_table_sql = format!(
                 "CREATE TABLE {} ({})",
                 table_name,
                 COLS.iter()
-                    .map(|c| format!("{} INTEGER", c))
+                    .map(|c| format!("{} {}", c, COLUMN_TYPES.choose(&mut rng).unwrap()))
                     .collect::<Vec<_>>()
                     .join(", ")
             );
@@ -547,13 +560,12 @@ mod tests {
 
             let num_rows_to_insert = rng.random_range(MIN_ROWS_PER_TABLE..=MAX_ROWS_PER_TABLE);
             for _ in 0..num_rows_to_insert {
-                let c1_val: i64 = rng.random_range(-3..3);
-                let c2_val: i64 = rng.random_range(-3..3);
-                let c3_val: i64 = rng.random_range(-3..3);
-
                 let insert_sql = format!(
                     "INSERT INTO {} VALUES ({}, {}, {})",
-                    table_name, c1_val, c2_val, c3_val
+                    table_name,
+                    VALUES.choose(&mut rng).unwrap(),
+                    VALUES.choose(&mut rng).unwrap(),
+                    VALUES.choose(&mut rng).unwrap()
                 );
                 limbo_exec_rows(&db, &limbo_conn, &insert_sql);
                 sqlite_exec_rows(&sqlite_conn, &insert_sql);
@@ -584,7 +596,8 @@ mod tests {
                 ));
             }
 
-            const COMPOUND_OPERATORS: [&str; 3] = [" UNION ALL ", " UNION ", " INTERSECT "];
+            const COMPOUND_OPERATORS: [&str; 4] =
+                [" UNION ALL ", " UNION ", " INTERSECT ", " EXCEPT "];
 
             let mut query = String::new();
             for (i, select_statement) in select_statements.iter().enumerate() {
@@ -623,6 +636,830 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn group_concat_fuzz() {
+        let _ = env_logger::try_init();
+        let (mut rng, seed) = rng_from_time();
+        log::info!("group_concat_fuzz seed: {}", seed);
+
+        const NUM_ROWS: usize = 50;
+        const NUM_FUZZ_ITERATIONS: usize = 500;
+        const VALUES: [&str; 8] = ["NULL", "''", "'a'", "'bc'", "1", "2.5", "-3", "x'4142'"];
+        const SEPARATORS: [&str; 6] = ["','", "''", "NULL", "' | '", "b", "'<' || a || '>'"];
+
+        let db = TempDatabase::new_empty(true);
+        let limbo_conn = db.connect_limbo();
+        let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+
+        let create_table_sql = "CREATE TABLE t (a, b, g INTEGER)";
+        limbo_exec_rows(&db, &limbo_conn, create_table_sql);
+        sqlite_exec_rows(&sqlite_conn, create_table_sql);
+        for _ in 0..NUM_ROWS {
+            let insert_sql = format!(
+                "INSERT INTO t VALUES ({}, {}, {})",
+                VALUES.choose(&mut rng).unwrap(),
+                VALUES.choose(&mut rng).unwrap(),
+                rng.random_range(0..4)
+            );
+            limbo_exec_rows(&db, &limbo_conn, &insert_sql);
+            sqlite_exec_rows(&sqlite_conn, &insert_sql);
+        }
+
+        for iter_num in 0..NUM_FUZZ_ITERATIONS {
+            let column = ["a", "b"].choose(&mut rng).unwrap();
+            let aggregate = if rng.random_bool(0.3) {
+                format!("group_concat(DISTINCT {})", column)
+            } else if rng.random_bool(0.3) {
+                format!("group_concat({})", column)
+            } else {
+                format!(
+                    "group_concat({}, {})",
+                    column,
+                    SEPARATORS.choose(&mut rng).unwrap()
+                )
+            };
+            let where_clause = if rng.random_bool(0.5) {
+                format!(" WHERE g != {}", rng.random_range(0..4))
+            } else {
+                String::new()
+            };
+            // Rows of a group can be visited in any order, so only the length of the result is compared.
+            let query = if rng.random_bool(0.5) {
+                format!(
+                    "SELECT g, length({}) FROM t{} GROUP BY g ORDER BY g",
+                    aggregate, where_clause
+                )
+            } else {
+                format!("SELECT {} FROM t{}", aggregate, where_clause)
+            };
+
+            log::debug!(
+                "Iteration {}/{}: Query: {}",
+                iter_num + 1,
+                NUM_FUZZ_ITERATIONS,
+                query
+            );
+
+            let limbo_results = limbo_exec_rows(&db, &limbo_conn, &query);
+            let sqlite_results = sqlite_exec_rows(&sqlite_conn, &query);
+            assert_eq!(
+                limbo_results, sqlite_results,
+                "query: {}, seed: {}",
+                query, seed
+            );
+        }
+    }
+
+    #[test]
+    pub fn without_rowid_fuzz() {
+        let _ = env_logger::try_init();
+        let (mut rng, seed) = rng_from_time();
+        log::info!("without_rowid_fuzz seed: {}", seed);
+
+        const NUM_TABLES: usize = 20;
+        const NUM_STATEMENTS: usize = 200;
+        const TYPES: [&str; 6] = ["INTEGER", "TEXT", "REAL", "BLOB", "NUMERIC", ""];
+        const VALUES: [&str; 10] = [
+            "NULL", "1", "2", "-3", "2.5", "'1'", "'a'", "'B'", "x'4142'", "'2.0'",
+        ];
+
+        for table_num in 0..NUM_TABLES {
+            let db = TempDatabase::new_empty(true);
+            let limbo_conn = db.connect_limbo();
+            let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+
+            let num_pk_cols = rng.random_range(1..=2);
+            let columns = (0..3)
+                .map(|i| format!("c{} {}", i, TYPES.choose(&mut rng).unwrap()))
+                .collect::<Vec<_>>();
+            let primary_key = (0..num_pk_cols)
+                .map(|i| {
+                    let order = ["", " ASC", " DESC"].choose(&mut rng).unwrap();
+                    format!("c{}{}", i, order)
+                })
+                .collect::<Vec<_>>();
+            let create_table_sql = format!(
+                "CREATE TABLE t ({}, PRIMARY KEY ({})) WITHOUT ROWID",
+                columns.join(", "),
+                primary_key.join(", ")
+            );
+            log::debug!(
+                "Table {}/{}: {}",
+                table_num + 1,
+                NUM_TABLES,
+                create_table_sql
+            );
+            limbo_exec_rows(&db, &limbo_conn, &create_table_sql);
+            sqlite_exec_rows(&sqlite_conn, &create_table_sql);
+
+            for _ in 0..NUM_STATEMENTS {
+                let sql = if rng.random_bool(0.8) {
+                    format!(
+                        "INSERT INTO t VALUES ({}, {}, {})",
+                        VALUES.choose(&mut rng).unwrap(),
+                        VALUES.choose(&mut rng).unwrap(),
+                        VALUES.choose(&mut rng).unwrap()
+                    )
+                } else {
+                    format!(
+                        "DELETE FROM t WHERE c{} = {}",
+                        rng.random_range(0..3),
+                        VALUES.choose(&mut rng).unwrap()
+                    )
+                };
+                // Duplicate and NULL keys must fail in both databases.
+                let limbo_result = limbo_exec_rows_error(&db, &limbo_conn, &sql);
+                let sqlite_result = sqlite_conn.execute(&sql, params![]);
+                assert_eq!(
+                    limbo_result.is_ok(),
+                    sqlite_result.is_ok(),
+                    "sql: {}, limbo: {:?}, sqlite: {:?}, seed: {}",
+                    sql,
+                    limbo_result,
+                    sqlite_result,
+                    seed
+                );
+
+                // Rows are visited in primary key order.
+                let query = "SELECT * FROM t";
+                let limbo_results = limbo_exec_rows(&db, &limbo_conn, query);
+                let sqlite_results = sqlite_exec_rows(&sqlite_conn, query);
+                assert_eq!(
+                    limbo_results, sqlite_results,
+                    "table: {}, after: {}, seed: {}",
+                    create_table_sql, sql, seed
+                );
+            }
+        }
+    }
+
+    /// Returns a random frame specification that SQLite accepts, or an empty string for the default frame.
+    fn random_frame(rng: &mut ChaCha8Rng) -> String {
+        const BOUNDS: [&str; 5] = [
+            "UNBOUNDED PRECEDING",
+            "{} PRECEDING",
+            "CURRENT ROW",
+            "{} FOLLOWING",
+            "UNBOUNDED FOLLOWING",
+        ];
+        let mode = ["", "ROWS", "RANGE", "GROUPS"].choose(rng).unwrap();
+        if mode.is_empty() {
+            return String::new();
+        }
+        // Only ROWS frames can have offsets, and the start of a frame cannot come after its end.
+        let candidates: &[usize] = if *mode == "ROWS" {
+            &[0, 1, 2, 3, 4]
+        } else {
+            &[0, 2, 4]
+        };
+        let start = *candidates[..candidates.len() - 1].choose(rng).unwrap();
+        let end = *candidates
+            .iter()
+            .filter(|&&end| end >= start.max(1))
+            .collect::<Vec<_>>()
+            .choose(rng)
+            .unwrap();
+        let mut bound =
+            |idx: usize| BOUNDS[idx].replace("{}", &rng.random_range(0..=3).to_string());
+        format!("{} BETWEEN {} AND {}", mode, bound(start), bound(*end))
+    }
+
+    #[test]
+    pub fn full_join_fuzz() {
+        let _ = env_logger::try_init();
+        let (mut rng, seed) = rng_from_time();
+        log::info!("full_join_fuzz seed: {}", seed);
+
+        const NUM_DATABASES: usize = 10;
+        const NUM_QUERIES: usize = 100;
+        const LEFT_SIDES: [&str; 3] = ["t1", "t1 LEFT JOIN t3 ON t1.b = t3.a", "t1, t3"];
+        const JOIN_OPS: [&str; 2] = ["FULL JOIN", "FULL OUTER JOIN"];
+        const ON_TERMS: [&str; 6] = [
+            "t1.a = t2.a",
+            "t1.a = t2.b",
+            "t1.b < t2.b",
+            "t1.a = t2.a AND t2.c > 2",
+            "t1.c = t2.c OR t1.a = t2.b",
+            "t2.a IS NULL",
+        ];
+        const WHERE_TERMS: [&str; 7] = [
+            "",
+            " WHERE t1.a IS NULL",
+            " WHERE t2.a IS NULL",
+            " WHERE t2.b > 2",
+            " WHERE t1.a = 3",
+            " WHERE t1.c = t2.c OR t2.a IS NULL",
+            " WHERE coalesce(t1.b, t2.b) < 4",
+        ];
+
+        for db_num in 0..NUM_DATABASES {
+            let db = TempDatabase::new_empty(true);
+            let limbo_conn = db.connect_limbo();
+            let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+
+            let mut setup = vec![
+                "CREATE TABLE t1 (a INTEGER, b INTEGER, c INTEGER)".to_string(),
+                "CREATE TABLE t2 (a INTEGER, b INTEGER, c INTEGER)".to_string(),
+                "CREATE TABLE t3 (a INTEGER, b INTEGER, c INTEGER)".to_string(),
+            ];
+            if rng.random_bool(0.5) {
+                setup.push("CREATE INDEX t2_a ON t2 (a)".to_string());
+            }
+            for table in ["t1", "t2", "t3"] {
+                for _ in 0..rng.random_range(0..15) {
+                    let value = |rng: &mut ChaCha8Rng| {
+                        if rng.random_bool(0.2) {
+                            "NULL".to_string()
+                        } else {
+                            rng.random_range(0..6).to_string()
+                        }
+                    };
+                    setup.push(format!(
+                        "INSERT INTO {} VALUES ({}, {}, {})",
+                        table,
+                        value(&mut rng),
+                        value(&mut rng),
+                        value(&mut rng)
+                    ));
+                }
+            }
+            for sql in &setup {
+                limbo_exec_rows(&db, &limbo_conn, sql);
+                sqlite_exec_rows(&sqlite_conn, sql);
+            }
+
+            for _ in 0..NUM_QUERIES {
+                let from = format!(
+                    "{} {} t2 ON {}",
+                    LEFT_SIDES.choose(&mut rng).unwrap(),
+                    JOIN_OPS.choose(&mut rng).unwrap(),
+                    ON_TERMS.choose(&mut rng).unwrap()
+                );
+                let where_clause = WHERE_TERMS.choose(&mut rng).unwrap();
+                let query = if rng.random_bool(0.8) {
+                    format!(
+                        "SELECT t1.a, t1.b, t1.c, t2.a, t2.b, t2.c FROM {}{} ORDER BY 1, 2, 3, 4, 5, 6",
+                        from, where_clause
+                    )
+                } else {
+                    format!(
+                        "SELECT count(*), count(t1.a), sum(t2.b) FROM {}{}",
+                        from, where_clause
+                    )
+                };
+                let limbo_results = limbo_exec_rows(&db, &limbo_conn, &query);
+                let sqlite_results = sqlite_exec_rows(&sqlite_conn, &query);
+                assert_eq!(
+                    limbo_results,
+                    sqlite_results,
+                    "database: {}/{}, query: {}, setup: {:?}, seed: {}",
+                    db_num + 1,
+                    NUM_DATABASES,
+                    query,
+                    setup,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn subquery_flattening_fuzz() {
+        let _ = env_logger::try_init();
+        let (mut rng, seed) = rng_from_time();
+        log::info!("subquery_flattening_fuzz seed: {}", seed);
+
+        const NUM_DATABASES: usize = 10;
+        const NUM_QUERIES: usize = 100;
+        const SUBQUERIES: [&str; 6] = [
+            "SELECT * FROM t1",
+            "SELECT a, b + c AS b, c FROM t1 WHERE a > 1",
+            "SELECT t1.a, t2.b, t1.c FROM t1 JOIN t2 ON t1.a = t2.a",
+            "SELECT a, b, c FROM (SELECT * FROM t2 WHERE c IS NOT NULL) WHERE b < 4",
+            "SELECT a, b, c FROM t1 ORDER BY a LIMIT 5",
+            "SELECT a, count(*) AS b, max(c) AS c FROM t1 GROUP BY a",
+        ];
+        const WHERE_TERMS: [&str; 5] = [
+            "",
+            " WHERE s.a = 3",
+            " WHERE s.b > 2 AND s.c < 4",
+            " WHERE s.a IS NULL OR s.b = s.c",
+            " WHERE s.a = t3.b",
+        ];
+
+        for db_num in 0..NUM_DATABASES {
+            let db = TempDatabase::new_empty(true);
+            let limbo_conn = db.connect_limbo();
+            let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+
+            let mut setup = vec![
+                "CREATE TABLE t1 (a INTEGER, b INTEGER, c INTEGER)".to_string(),
+                "CREATE TABLE t2 (a INTEGER, b INTEGER, c INTEGER)".to_string(),
+                "CREATE TABLE t3 (a INTEGER, b INTEGER, c INTEGER)".to_string(),
+            ];
+            if rng.random_bool(0.5) {
+                setup.push("CREATE INDEX t1_a ON t1 (a)".to_string());
+            }
+            for table in ["t1", "t2", "t3"] {
+                for _ in 0..rng.random_range(0..15) {
+                    let value = |rng: &mut ChaCha8Rng| {
+                        if rng.random_bool(0.2) {
+                            "NULL".to_string()
+                        } else {
+                            rng.random_range(0..6).to_string()
+                        }
+                    };
+                    setup.push(format!(
+                        "INSERT INTO {} VALUES ({}, {}, {})",
+                        table,
+                        value(&mut rng),
+                        value(&mut rng),
+                        value(&mut rng)
+                    ));
+                }
+            }
+            for sql in &setup {
+                limbo_exec_rows(&db, &limbo_conn, sql);
+                sqlite_exec_rows(&sqlite_conn, sql);
+            }
+
+            for _ in 0..NUM_QUERIES {
+                let subquery = SUBQUERIES.choose(&mut rng).unwrap();
+                let where_clause = WHERE_TERMS.choose(&mut rng).unwrap();
+                let query = if rng.random_bool(0.8) {
+                    format!(
+                        "SELECT s.a, s.b, s.c, t3.a FROM ({}) s JOIN t3{} ORDER BY 1, 2, 3, 4",
+                        subquery, where_clause
+                    )
+                } else {
+                    format!(
+                        "SELECT count(*), sum(s.b) FROM ({}) s JOIN t3{}",
+                        subquery, where_clause
+                    )
+                };
+                let limbo_results = limbo_exec_rows(&db, &limbo_conn, &query);
+                let sqlite_results = sqlite_exec_rows(&sqlite_conn, &query);
+                assert_eq!(
+                    limbo_results,
+                    sqlite_results,
+                    "database: {}/{}, query: {}, setup: {:?}, seed: {}",
+                    db_num + 1,
+                    NUM_DATABASES,
+                    query,
+                    setup,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn window_function_fuzz() {
+        let _ = env_logger::try_init();
+        let (mut rng, seed) = rng_from_time();
+        log::info!("window_function_fuzz seed: {}", seed);
+
+        const NUM_ROWS: usize = 60;
+        const NUM_FUZZ_ITERATIONS: usize = 1000;
+        const COLS: [&str; 3] = ["a", "b", "c"];
+
+        let db = TempDatabase::new_empty(false);
+        let limbo_conn = db.connect_limbo();
+        let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+
+        let create_table_sql =
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, a INTEGER, b INTEGER, c INTEGER)";
+        limbo_exec_rows(&db, &limbo_conn, create_table_sql);
+        sqlite_exec_rows(&sqlite_conn, create_table_sql);
+        for id in 0..NUM_ROWS {
+            let values = COLS
+                .iter()
+                .map(|_| match rng.random_range(0..5) {
+                    0 => "NULL".to_string(),
+                    _ => rng.random_range(-3..3).to_string(),
+                })
+                .collect::<Vec<_>>();
+            let insert_sql = format!("INSERT INTO t VALUES ({}, {})", id, values.join(", "));
+            limbo_exec_rows(&db, &limbo_conn, &insert_sql);
+            sqlite_exec_rows(&sqlite_conn, &insert_sql);
+        }
+
+        for iter_num in 0..NUM_FUZZ_ITERATIONS {
+            let num_partition_keys = rng.random_range(0..=2);
+            let partition_by = COLS
+                .choose_multiple(&mut rng, num_partition_keys)
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>();
+            let num_order_keys = rng.random_range(0..=2);
+            let order_by = COLS
+                .choose_multiple(&mut rng, num_order_keys)
+                .map(|c| format!("{} {}", c, ["ASC", "DESC"].choose(&mut rng).unwrap()))
+                .collect::<Vec<_>>();
+
+            let mut window = String::new();
+            if !partition_by.is_empty() {
+                window.push_str(&format!("PARTITION BY {}", partition_by.join(", ")));
+            }
+            if !order_by.is_empty() {
+                if !window.is_empty() {
+                    window.push(' ');
+                }
+                window.push_str(&format!("ORDER BY {}", order_by.join(", ")));
+            }
+
+            // Apart from rank() and dense_rank(), the functions depend on the order of the rows
+            // within a peer group, so they are only used when the window ORDER BY makes it deterministic.
+            let functions = if rng.random_bool(0.3) {
+                vec![
+                    ("rank()".to_string(), String::new()),
+                    ("dense_rank()".to_string(), String::new()),
+                ]
+            } else {
+                let sep = if order_by.is_empty() { "ORDER BY" } else { "," };
+                if window.is_empty() {
+                    window.push_str("ORDER BY id");
+                } else {
+                    window.push_str(&format!(" {} id", sep));
+                }
+                let mut functions = vec![
+                    "row_number()".to_string(),
+                    format!("ntile({})", rng.random_range(1..=8)),
+                    "rank()".to_string(),
+                    format!("lag(a, {}, -1)", rng.random_range(0..=3)),
+                    format!("lead(b, {})", rng.random_range(0..=3)),
+                ]
+                .into_iter()
+                .map(|f| (f, String::new()))
+                .collect::<Vec<_>>();
+                for func in [
+                    "first_value(c)".to_string(),
+                    "last_value(a)".to_string(),
+                    format!("nth_value(b, {})", rng.random_range(1..=4)),
+                ] {
+                    functions.push((func, random_frame(&mut rng)));
+                }
+                functions
+            };
+            let select_list = functions
+                .iter()
+                .map(|(f, frame)| format!("{} OVER ({} {})", f, window, frame))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let query = format!("SELECT id, {} FROM t ORDER BY id", select_list);
+
+            log::debug!(
+                "Iteration {}/{}: Query: {}",
+                iter_num + 1,
+                NUM_FUZZ_ITERATIONS,
+                query
+            );
+
+            let limbo_results = limbo_exec_rows(&db, &limbo_conn, &query);
+            let sqlite_results = sqlite_exec_rows(&sqlite_conn, &query);
+            assert_eq!(
+                limbo_results, sqlite_results,
+                "query: {}, seed: {}",
+                query, seed
+            );
+        }
+    }
+
+    /// Table of the schema tracked by [ddl_interleaving_fuzz].
+    #[derive(Debug, Clone)]
+    struct FuzzTable {
+        name: String,
+        columns: Vec<String>,
+    }
+
+    /// Index of the schema tracked by [ddl_interleaving_fuzz].
+    #[derive(Debug, Clone)]
+    struct FuzzIndex {
+        name: String,
+        table: String,
+        columns: Vec<String>,
+    }
+
+    #[derive(Debug)]
+    enum FuzzDdl {
+        CreateTable(FuzzTable),
+        DropTable(String),
+        CreateIndex(FuzzIndex),
+        DropIndex(String),
+        RenameTable(String, String),
+    }
+
+    impl FuzzDdl {
+        fn sql(&self) -> String {
+            match self {
+                FuzzDdl::CreateTable(table) => format!(
+                    "CREATE TABLE {} ({})",
+                    table.name,
+                    table
+                        .columns
+                        .iter()
+                        .map(|c| format!("{} INTEGER", c))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                FuzzDdl::DropTable(name) => format!("DROP TABLE {}", name),
+                FuzzDdl::CreateIndex(index) => format!(
+                    "CREATE INDEX {} ON {} ({})",
+                    index.name,
+                    index.table,
+                    index.columns.join(", ")
+                ),
+                FuzzDdl::DropIndex(name) => format!("DROP INDEX {}", name),
+                FuzzDdl::RenameTable(from, to) => format!("ALTER TABLE {} RENAME TO {}", from, to),
+            }
+        }
+    }
+
+    /// The schema both databases are expected to have, used to generate valid statements.
+    #[derive(Debug, Default)]
+    struct FuzzSchema {
+        tables: Vec<FuzzTable>,
+        indexes: Vec<FuzzIndex>,
+        next_id: usize,
+    }
+
+    impl FuzzSchema {
+        const MAX_TABLES: usize = 4;
+        const MAX_COLUMNS: usize = 4;
+        const MAX_INDEXES: usize = 6;
+
+        fn fresh_name(&mut self, prefix: &str) -> String {
+            self.next_id += 1;
+            format!("{}{}", prefix, self.next_id)
+        }
+
+        fn random_ddl(&mut self, rng: &mut ChaCha8Rng) -> FuzzDdl {
+            if self.tables.is_empty() {
+                return self.create_table(rng);
+            }
+            match rng.random_range(0..5) {
+                0 if self.tables.len() < Self::MAX_TABLES => self.create_table(rng),
+                1 if self.tables.len() > 1 => {
+                    FuzzDdl::DropTable(self.tables.choose(rng).unwrap().name.clone())
+                }
+                2 if self.indexes.len() < Self::MAX_INDEXES => {
+                    let table = self.tables.choose(rng).unwrap().clone();
+                    let num_columns = rng.random_range(1..=table.columns.len());
+                    FuzzDdl::CreateIndex(FuzzIndex {
+                        name: self.fresh_name("i"),
+                        table: table.name,
+                        columns: table
+                            .columns
+                            .choose_multiple(rng, num_columns)
+                            .cloned()
+                            .collect(),
+                    })
+                }
+                3 if !self.indexes.is_empty() => {
+                    FuzzDdl::DropIndex(self.indexes.choose(rng).unwrap().name.clone())
+                }
+                _ => {
+                    let from = self.tables.choose(rng).unwrap().name.clone();
+                    FuzzDdl::RenameTable(from, self.fresh_name("t"))
+                }
+            }
+        }
+
+        fn create_table(&mut self, rng: &mut ChaCha8Rng) -> FuzzDdl {
+            let num_columns = rng.random_range(1..=Self::MAX_COLUMNS);
+            FuzzDdl::CreateTable(FuzzTable {
+                name: self.fresh_name("t"),
+                columns: (0..num_columns).map(|i| format!("c{}", i)).collect(),
+            })
+        }
+
+        fn apply(&mut self, ddl: FuzzDdl) {
+            match ddl {
+                FuzzDdl::CreateTable(table) => self.tables.push(table),
+                FuzzDdl::DropTable(name) => {
+                    self.tables.retain(|t| t.name != name);
+                    self.indexes.retain(|i| i.table != name);
+                }
+                FuzzDdl::CreateIndex(index) => self.indexes.push(index),
+                FuzzDdl::DropIndex(name) => self.indexes.retain(|i| i.name != name),
+                FuzzDdl::RenameTable(from, to) => {
+                    for table in self.tables.iter_mut().filter(|t| t.name == from) {
+                        table.name = to.clone();
+                    }
+                    for index in self.indexes.iter_mut().filter(|i| i.table == from) {
+                        index.table = to.clone();
+                    }
+                }
+            }
+        }
+
+        /// Returns a DML statement or a query on a random table, with the number of
+        /// parameters it expects.
+        fn random_query(&self, rng: &mut ChaCha8Rng) -> (String, usize) {
+            let table = self.tables.choose(rng).unwrap();
+            let column = table.columns.choose(rng).unwrap();
+            let columns = table.columns.join(", ");
+            match rng.random_range(0..5) {
+                0 => (
+                    format!(
+                        "INSERT INTO {} ({}) VALUES ({})",
+                        table.name,
+                        columns,
+                        vec!["?"; table.columns.len()].join(", ")
+                    ),
+                    table.columns.len(),
+                ),
+                1 => (
+                    format!("DELETE FROM {} WHERE {} = ?", table.name, column),
+                    1,
+                ),
+                2 => (
+                    format!(
+                        "UPDATE {} SET {} = ? WHERE {} = ?",
+                        table.name,
+                        table.columns.choose(rng).unwrap(),
+                        column
+                    ),
+                    2,
+                ),
+                3 => (format!("SELECT COUNT(*) FROM {}", table.name), 0),
+                _ => (
+                    format!(
+                        "SELECT {} FROM {} WHERE {} >= ? ORDER BY {}",
+                        columns, table.name, column, columns
+                    ),
+                    1,
+                ),
+            }
+        }
+    }
+
+    /// A limbo statement kept alive across schema changes.
+    struct CachedStatement {
+        sql: String,
+        num_params: usize,
+        stmt: turso_core::Statement,
+    }
+
+    type FuzzRows = Result<Vec<Vec<rusqlite::types::Value>>, String>;
+
+    /// Panics with the seed and the full interaction history unless both databases
+    /// returned the same rows or both failed.
+    fn assert_same_outcome(seed: u64, history: &[String], limbo: &FuzzRows, sqlite: &FuzzRows) {
+        let same = match (limbo, sqlite) {
+            (Ok(limbo), Ok(sqlite)) => limbo == sqlite,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        };
+        assert!(
+            same,
+            "DIFFERENT RESULTS! limbo: {:?}, sqlite: {:?}, seed: {}\nhistory:\n{}",
+            limbo,
+            sqlite,
+            seed,
+            history.join(";\n")
+        );
+    }
+
+    fn limbo_run_stmt(
+        db: &TempDatabase,
+        stmt: &mut turso_core::Statement,
+        params: &[i64],
+    ) -> FuzzRows {
+        stmt.reset();
+        for (i, param) in params.iter().enumerate() {
+            stmt.bind_at(
+                std::num::NonZeroUsize::new(i + 1).unwrap(),
+                turso_core::Value::Integer(*param),
+            );
+        }
+        let mut rows = Vec::new();
+        loop {
+            match stmt.step().map_err(|e| e.to_string())? {
+                turso_core::StepResult::Row => {
+                    let row = stmt.row().unwrap();
+                    rows.push(
+                        row.get_values()
+                            .map(|x| match x {
+                                turso_core::Value::Null => rusqlite::types::Value::Null,
+                                turso_core::Value::Integer(x) => {
+                                    rusqlite::types::Value::Integer(*x)
+                                }
+                                turso_core::Value::Float(x) => rusqlite::types::Value::Real(*x),
+                                turso_core::Value::Text(x) => {
+                                    rusqlite::types::Value::Text(x.as_str().to_string())
+                                }
+                                turso_core::Value::Blob(x) => {
+                                    rusqlite::types::Value::Blob(x.to_vec())
+                                }
+                            })
+                            .collect(),
+                    );
+                }
+                turso_core::StepResult::IO => db.io.run_once().map_err(|e| e.to_string())?,
+                turso_core::StepResult::Done => return Ok(rows),
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+
+    fn sqlite_run_cached(conn: &rusqlite::Connection, sql: &str, params: &[i64]) -> FuzzRows {
+        let mut stmt = conn.prepare_cached(sql).map_err(|e| e.to_string())?;
+        let num_columns = stmt.column_count();
+        let mut rows = stmt
+            .query(rusqlite::params_from_iter(params.iter()))
+            .map_err(|e| e.to_string())?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            results.push(
+                (0..num_columns)
+                    .map(|i| row.get::<_, rusqlite::types::Value>(i).unwrap())
+                    .collect(),
+            );
+        }
+        Ok(results)
+    }
+
+    #[test]
+    /// Interleaves random DDL with DML and queries on limbo and SQLite. Prepared
+    /// statements are kept alive across schema changes, so that they have to be
+    /// reprepared before they run again.
+    pub fn ddl_interleaving_fuzz() {
+        let _ = env_logger::try_init();
+        let (mut rng, seed) = rng_from_time_or_env();
+        log::info!("ddl_interleaving_fuzz seed: {}", seed);
+
+        const NUM_FUZZ_ITERATIONS: usize = 2000;
+        const MAX_CACHED_STATEMENTS: usize = 8;
+
+        let db = TempDatabase::new_empty(true);
+        let limbo_conn = db.connect_limbo();
+        let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+        sqlite_conn.set_prepared_statement_cache_capacity(MAX_CACHED_STATEMENTS * 4);
+
+        let mut schema = FuzzSchema::default();
+        let mut cached: Vec<CachedStatement> = Vec::new();
+        let mut history: Vec<String> = Vec::new();
+
+        for _ in 0..NUM_FUZZ_ITERATIONS {
+            let action = rng.random_range(0..10);
+            if schema.tables.is_empty() || action < 2 {
+                let ddl = schema.random_ddl(&mut rng);
+                let sql = ddl.sql();
+                history.push(sql.clone());
+                let limbo = limbo_conn
+                    .prepare(&sql)
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut stmt| limbo_run_stmt(&db, &mut stmt, &[]));
+                let sqlite = sqlite_run_cached(&sqlite_conn, &sql, &[]);
+                assert_same_outcome(seed, &history, &limbo, &sqlite);
+                assert!(
+                    limbo.is_ok(),
+                    "DDL failed: {:?}, seed: {}\nhistory:\n{}",
+                    limbo,
+                    seed,
+                    history.join(";\n")
+                );
+                schema.apply(ddl);
+            } else if !cached.is_empty() && action < 6 {
+                // Run a statement that was prepared before some of the schema changes.
+                let idx = rng.random_range(0..cached.len());
+                let entry = &mut cached[idx];
+                let params = (0..entry.num_params)
+                    .map(|_| rng.random_range(-5..5))
+                    .collect::<Vec<i64>>();
+                history.push(format!("{} -- cached, params: {:?}", entry.sql, params));
+                let limbo = limbo_run_stmt(&db, &mut entry.stmt, &params);
+                let sqlite = sqlite_run_cached(&sqlite_conn, &entry.sql, &params);
+                assert_same_outcome(seed, &history, &limbo, &sqlite);
+                if limbo.is_err() {
+                    // The statement refers to a table that no longer exists.
+                    cached.swap_remove(idx);
+                }
+            } else {
+                let (sql, num_params) = schema.random_query(&mut rng);
+                let params = (0..num_params)
+                    .map(|_| rng.random_range(-5..5))
+                    .collect::<Vec<i64>>();
+                history.push(format!("{} -- params: {:?}", sql, params));
+                let mut stmt = limbo_conn.prepare(&sql).unwrap();
+                let limbo = limbo_run_stmt(&db, &mut stmt, &params);
+                let sqlite = sqlite_run_cached(&sqlite_conn, &sql, &params);
+                assert_same_outcome(seed, &history, &limbo, &sqlite);
+                let entry = CachedStatement {
+                    sql,
+                    num_params,
+                    stmt,
+                };
+                if cached.len() < MAX_CACHED_STATEMENTS {
+                    cached.push(entry);
+                } else {
+                    let idx = rng.random_range(0..cached.len());
+                    cached[idx] = entry;
+                }
+            }
+        }
+    }
+
     #[test]
     pub fn arithmetic_expression_fuzz() {
         let _ = env_logger::try_init();
@@ -685,6 +1522,75 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn constant_folding_fuzz() {
+        let _ = env_logger::try_init();
+        let g = GrammarGenerator::new();
+        let (expr, expr_builder) = g.create_handle();
+        let (bin_op, bin_op_builder) = g.create_handle();
+        let (unary_op, unary_op_builder) = g.create_handle();
+        let (paren, paren_builder) = g.create_handle();
+
+        paren_builder
+            .concat("")
+            .push_str("(")
+            .push(expr)
+            .push_str(")")
+            .build();
+
+        unary_op_builder
+            .concat(" ")
+            .push(g.create().choice().options_str(["+", "-"]).build())
+            .push(expr)
+            .build();
+
+        bin_op_builder
+            .concat(" ")
+            .push(expr)
+            .push(
+                g.create()
+                    .choice()
+                    .options_str(["+", "-", "*", "/", "%"])
+                    .build(),
+            )
+            .push(expr)
+            .build();
+
+        expr_builder
+            .choice()
+            .option_w(unary_op, 1.0)
+            .option_w(bin_op, 1.0)
+            .option_w(paren, 1.0)
+            .option_symbol_w(rand_int(0..10), 1.0)
+            .option_w(
+                g.create()
+                    .choice()
+                    .options_str(["0.5", "2.25", "1e3", "9223372036854775807"])
+                    .build(),
+                0.5,
+            )
+            .build();
+
+        let sql = g.create().concat(" ").push_str("SELECT").push(expr).build();
+
+        let db = TempDatabase::new_empty(false);
+        let limbo_conn = db.connect_limbo();
+        let sqlite_conn = rusqlite::Connection::open_in_memory().unwrap();
+
+        let (mut rng, seed) = rng_from_time();
+        log::info!("seed: {}", seed);
+        for _ in 0..1024 {
+            let query = g.generate(&mut rng, sql, 50);
+            let limbo = limbo_exec_rows(&db, &limbo_conn, &query);
+            let sqlite = sqlite_exec_rows(&sqlite_conn, &query);
+            assert_eq!(
+                limbo, sqlite,
+                "query: {}, limbo: {:?}, sqlite: {:?} seed: {}",
+                query, limbo, sqlite, seed
+            );
+        }
+    }
+
     #[test]
     pub fn fuzz_ex() {
         let _ = env_logger::try_init();
@@ -1,2 +1,5 @@
+mod test_expr_types;
+mod test_identifiers;
 mod test_read_path;
+mod test_window;
 mod test_write_path;
@@ -0,0 +1,121 @@
+use crate::common::{limbo_exec_rows, sqlite_exec_rows, TempDatabase};
+
+const SCHEMA: &str = "CREATE TABLE t (i INTEGER, r REAL, x TEXT, b BLOB, n NUMERIC, v VARCHAR, u);
+INSERT INTO t VALUES (1, 1.5, '12', x'01', '3.0', 42, '7');";
+
+/// Expressions whose declared type and runtime storage class are compared against SQLite.
+const EXPRESSIONS: &[&str] = &[
+    // Plain and qualified column references keep their declared type.
+    "i",
+    "r",
+    "x",
+    "b",
+    "n",
+    "v",
+    "u",
+    "t.i",
+    "t.rowid",
+    "(i)",
+    // Columns read through a FROM clause subquery keep the declared type of their source.
+    "s.si",
+    "s.sx",
+    "s.sk",
+    // Anything computed has no declared type, whatever its runtime storage class.
+    "+i",
+    "-i",
+    "i + 1",
+    "i * 2",
+    "i / 2",
+    "r + 1",
+    "i + r",
+    "x + 0",
+    "x || 'a'",
+    "i || ''",
+    "i COLLATE NOCASE",
+    "x COLLATE NOCASE",
+    "CAST(i AS TEXT)",
+    "CAST(x AS INTEGER)",
+    "CAST(x AS integer)",
+    "CAST(x AS REAL)",
+    "CAST(r AS INTEGER)",
+    "CAST(n AS TEXT)",
+    "CAST('1.0' AS NUMERIC)",
+    "CAST(i AS BLOB)",
+    "typeof(i)",
+    "length(x)",
+    "abs(i)",
+    "abs(r)",
+    "round(r)",
+    "coalesce(i, 0)",
+    "coalesce(u, 0)",
+    "ifnull(NULL, x)",
+    "nullif(i, 2)",
+    "max(i, r)",
+    "min(x, 'z')",
+    "upper(x)",
+    "substr(x, 1, 1)",
+    "hex(b)",
+    "zeroblob(2)",
+    "i = 1",
+    "x = 12",
+    "i > '0'",
+    "x IS NULL",
+    "i BETWEEN 0 AND 2",
+    "CASE WHEN i THEN x ELSE r END",
+    "CASE i WHEN 1 THEN 'one' END",
+    "1",
+    "1.5",
+    "'a'",
+    "x'00'",
+    "NULL",
+];
+
+#[test]
+fn test_expression_decltype_and_typeof_match_sqlite() {
+    let tmp_db = TempDatabase::new_with_rusqlite(SCHEMA, false);
+    let conn = tmp_db.connect_limbo();
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path).unwrap();
+
+    for expr in EXPRESSIONS {
+        let query = format!(
+            "SELECT {expr}, typeof({expr}) FROM t, (SELECT i AS si, x AS sx, i + 1 AS sk FROM t) AS s"
+        );
+
+        let limbo_decltype = {
+            let stmt = conn.prepare(&query).unwrap();
+            stmt.column_declared_type(0).map(str::to_string)
+        };
+        let sqlite_decltype = {
+            let stmt = sqlite_conn.prepare(&query).unwrap();
+            stmt.columns()[0].decl_type().map(str::to_string)
+        };
+        assert_eq!(limbo_decltype, sqlite_decltype, "decltype of {expr}");
+
+        assert_eq!(
+            limbo_exec_rows(&tmp_db, &conn, &query),
+            sqlite_exec_rows(&sqlite_conn, &query),
+            "value and typeof of {expr}"
+        );
+    }
+}
+
+#[test]
+fn test_comparison_affinity_does_not_change_storage_class() {
+    let tmp_db = TempDatabase::new_with_rusqlite(SCHEMA, false);
+    let conn = tmp_db.connect_limbo();
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path).unwrap();
+
+    for query in [
+        "SELECT x, typeof(x) FROM t WHERE x = 12",
+        "SELECT i, typeof(i) FROM t WHERE i = '1'",
+        "SELECT v, typeof(v), v = 42, typeof(v) FROM t",
+        "SELECT n, typeof(n), n < '4', typeof(n) FROM t",
+        "SELECT r, typeof(r) FROM t WHERE r > '1'",
+    ] {
+        assert_eq!(
+            limbo_exec_rows(&tmp_db, &conn, query),
+            sqlite_exec_rows(&sqlite_conn, query),
+            "{query}"
+        );
+    }
+}
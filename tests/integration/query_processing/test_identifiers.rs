@@ -0,0 +1,198 @@
+use crate::common::{limbo_exec_rows, limbo_exec_rows_error, sqlite_exec_rows, TempDatabase};
+use rusqlite::types::Value;
+
+/// Reserved words that can only be used as names when quoted.
+const KEYWORDS: &[&str] = &[
+    "group",
+    "order",
+    "select",
+    "from",
+    "where",
+    "table",
+    "index",
+    "values",
+    "limit",
+    "union",
+    "default",
+    "check",
+    "primary",
+    "references",
+    "transaction",
+];
+
+/// The three ways SQLite lets you quote an identifier.
+const QUOTING_STYLES: &[fn(&str) -> String] = &[
+    |name| format!("\"{}\"", name.replace('"', "\"\"")),
+    |name| format!("[{}]", name),
+    |name| format!("`{}`", name.replace('`', "``")),
+];
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+#[test]
+fn test_keyword_identifiers_crud() {
+    for quote in QUOTING_STYLES {
+        let tmp_db = TempDatabase::new_empty(true);
+        let conn = tmp_db.connect_limbo();
+        let value_col = quote("it's");
+
+        for kw in KEYWORDS {
+            let t = quote(kw);
+            let idx = quote(&format!("{kw} idx"));
+            limbo_exec_rows_error(
+                &tmp_db,
+                &conn,
+                &format!("CREATE TABLE {t} ({t} INTEGER PRIMARY KEY, {value_col} TEXT)"),
+            )
+            .unwrap();
+            limbo_exec_rows_error(
+                &tmp_db,
+                &conn,
+                &format!("CREATE INDEX {idx} ON {t} ({value_col})"),
+            )
+            .unwrap();
+            limbo_exec_rows_error(
+                &tmp_db,
+                &conn,
+                &format!("INSERT INTO {t} ({t}, {value_col}) VALUES (1, 'a'), (2, 'b'), (3, 'c')"),
+            )
+            .unwrap();
+            limbo_exec_rows_error(
+                &tmp_db,
+                &conn,
+                &format!("UPDATE {t} SET {value_col} = 'z' WHERE {t}.{t} = 2"),
+            )
+            .unwrap();
+            limbo_exec_rows_error(
+                &tmp_db,
+                &conn,
+                &format!("DELETE FROM {t} WHERE {t}.{t} = 3"),
+            )
+            .unwrap();
+
+            let rows = limbo_exec_rows(
+                &tmp_db,
+                &conn,
+                &format!("SELECT {t}.{t}, {value_col} FROM {t} ORDER BY {t}"),
+            );
+            assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), text("a")],
+                    vec![Value::Integer(2), text("z")]
+                ],
+                "table {t}"
+            );
+
+            let stmt = conn
+                .prepare(format!(
+                    "SELECT {t} AS {} FROM {t} AS {} WHERE {}.{t} = 1",
+                    quote("Order"),
+                    quote("Group"),
+                    quote("Group")
+                ))
+                .unwrap();
+            assert_eq!(stmt.get_column_name(0), "Order");
+
+            let rows = limbo_exec_rows(&tmp_db, &conn, &format!("PRAGMA table_info({t})"));
+            let names = rows.iter().map(|row| row[1].clone()).collect::<Vec<_>>();
+            assert_eq!(names, vec![text(kw), text("it's")], "table {t}");
+        }
+
+        // sqlite_schema stores bare names and keeps the quoting in the original SQL.
+        let rows = limbo_exec_rows(
+            &tmp_db,
+            &conn,
+            "SELECT name, tbl_name, sql FROM sqlite_schema WHERE type = 'table' ORDER BY rowid",
+        );
+        assert_eq!(rows.len(), KEYWORDS.len());
+        for (row, kw) in rows.iter().zip(KEYWORDS) {
+            let t = quote(kw);
+            assert_eq!(row[0], text(kw));
+            assert_eq!(row[1], text(kw));
+            let Value::Text(sql) = &row[2] else {
+                panic!("expected sql text, got {:?}", row[2]);
+            };
+            assert!(
+                sql.starts_with(&format!("CREATE TABLE {t} ({t} INTEGER")),
+                "{sql}"
+            );
+            assert!(sql.contains(&value_col), "{sql}");
+        }
+
+        // The schema must round trip through both a reopened connection and SQLite itself.
+        let reopened = TempDatabase::new_with_existent(&tmp_db.path, true);
+        let reopened_conn = reopened.connect_limbo();
+        let sqlite_conn = rusqlite::Connection::open(&tmp_db.path).unwrap();
+        for kw in KEYWORDS {
+            let t = quote(kw);
+            let query = format!("SELECT {t}, {value_col} FROM {t} ORDER BY {t}");
+            let expected = vec![
+                vec![Value::Integer(1), text("a")],
+                vec![Value::Integer(2), text("z")],
+            ];
+            assert_eq!(limbo_exec_rows(&reopened, &reopened_conn, &query), expected);
+            assert_eq!(sqlite_exec_rows(&sqlite_conn, &query), expected);
+        }
+
+        for kw in KEYWORDS {
+            limbo_exec_rows_error(
+                &reopened,
+                &reopened_conn,
+                &format!("DROP TABLE {}", quote(kw)),
+            )
+            .unwrap();
+        }
+        let rows = limbo_exec_rows(&reopened, &reopened_conn, "SELECT name FROM sqlite_schema");
+        assert!(rows.is_empty(), "{:?}", rows);
+    }
+}
+
+#[test]
+fn test_alter_table_keyword_identifiers() {
+    for quote in QUOTING_STYLES {
+        let tmp_db = TempDatabase::new_empty(false);
+        let conn = tmp_db.connect_limbo();
+        let t = quote("order");
+
+        limbo_exec_rows_error(&tmp_db, &conn, &format!("CREATE TABLE {t} ({t} INTEGER)")).unwrap();
+        limbo_exec_rows_error(
+            &tmp_db,
+            &conn,
+            &format!("ALTER TABLE {t} ADD COLUMN {} TEXT", quote("limit")),
+        )
+        .unwrap();
+        limbo_exec_rows_error(
+            &tmp_db,
+            &conn,
+            &format!(
+                "ALTER TABLE {t} RENAME COLUMN {} TO {}",
+                quote("limit"),
+                quote("where")
+            ),
+        )
+        .unwrap();
+        limbo_exec_rows_error(&tmp_db, &conn, &format!("INSERT INTO {t} VALUES (1, 'x')")).unwrap();
+        limbo_exec_rows_error(
+            &tmp_db,
+            &conn,
+            &format!("ALTER TABLE {t} RENAME TO {}", quote("group")),
+        )
+        .unwrap();
+
+        let reopened = TempDatabase::new_with_existent(&tmp_db.path, false);
+        let reopened_conn = reopened.connect_limbo();
+        let query = format!(
+            "SELECT {}, {} FROM {}",
+            quote("order"),
+            quote("where"),
+            quote("group")
+        );
+        let expected = vec![vec![Value::Integer(1), text("x")]];
+        assert_eq!(limbo_exec_rows(&reopened, &reopened_conn, &query), expected);
+        let sqlite_conn = rusqlite::Connection::open(&tmp_db.path).unwrap();
+        assert_eq!(sqlite_exec_rows(&sqlite_conn, &query), expected);
+    }
+}
@@ -1,5 +1,6 @@
-use crate::common::TempDatabase;
-use turso_core::{StepResult, Value};
+use crate::common::{limbo_exec_rows, limbo_exec_rows_error, TempDatabase};
+use std::sync::Arc;
+use turso_core::{CloseOptions, Database, LimboError, QueryShapeCacheStats, StepResult, Value};
 
 #[test]
 fn test_statement_reset_bind() -> anyhow::Result<()> {
@@ -45,6 +46,42 @@ fn test_statement_reset_bind() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_statement_reset_reruns_program() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("create table a (x integer);", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("create table b (y integer)")?;
+    let values = (0..100)
+        .map(|i| format!("({})", i % 10))
+        .collect::<Vec<_>>();
+    conn.execute(format!("insert into a values {}", values.join(", ")))?;
+    conn.execute(format!("insert into b values {}", values.join(", ")))?;
+
+    // The unindexed join builds its index once per execution; a reset must rebuild it.
+    let mut stmt = conn.prepare("select count(*), ? from a join b on a.x = b.y")?;
+    let run = |stmt: &mut turso_core::Statement| -> anyhow::Result<Vec<Value>> {
+        let mut values = Vec::new();
+        loop {
+            match stmt.step()? {
+                StepResult::Row => values.extend(stmt.row().unwrap().get_values().cloned()),
+                StepResult::IO => tmp_db.io.run_once()?,
+                _ => break,
+            }
+        }
+        Ok(values)
+    };
+    stmt.bind_at(1.try_into()?, Value::Integer(7));
+    assert_eq!(
+        run(&mut stmt)?,
+        vec![Value::Integer(1000), Value::Integer(7)]
+    );
+
+    // Parameters are cleared by the reset.
+    stmt.reset();
+    assert_eq!(run(&mut stmt)?, vec![Value::Integer(1000), Value::Null]);
+    Ok(())
+}
+
 #[test]
 fn test_statement_bind() -> anyhow::Result<()> {
     let tmp_db = TempDatabase::new_with_rusqlite("create table test (i integer);", false);
@@ -703,3 +740,512 @@ fn test_bind_parameters_delete_rowid_alias_seek_out_of_order() -> anyhow::Result
     assert_eq!(ins.parameters().count(), 4);
     Ok(())
 }
+
+#[test]
+fn test_statement_column_metadata() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite(
+        "CREATE TABLE test (id INTEGER PRIMARY KEY, name TEXT, data);",
+        false,
+    );
+    let conn = tmp_db.connect_limbo();
+    conn.execute("insert into test values (1, 'a', x'01');")?;
+
+    let mut stmt =
+        conn.prepare("select id, name AS alias, data, rowid, length(name) from test;")?;
+    assert_eq!(stmt.column_count(), 5);
+    let names = (0..4)
+        .map(|i| stmt.column_name(i).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["id", "alias", "data", "id"]);
+    let types = (0..stmt.column_count())
+        .map(|i| stmt.column_declared_type(i))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![Some("INTEGER"), Some("TEXT"), None, Some("INTEGER"), None]
+    );
+
+    let mut rows = 0;
+    loop {
+        match stmt.step()? {
+            StepResult::Row => {
+                let row = stmt.row().unwrap();
+                assert_eq!(row.column_name(0), "id");
+                assert_eq!(row.column_name(1), "alias");
+                rows += 1;
+            }
+            StepResult::IO => tmp_db.io.run_once()?,
+            StepResult::Done | StepResult::Interrupt => break,
+            StepResult::Busy => panic!("database busy"),
+        }
+    }
+    assert_eq!(rows, 1);
+    Ok(())
+}
+
+#[test]
+fn test_open_readonly_bytes() -> anyhow::Result<()> {
+    static FIXTURE: &[u8] = include_bytes!("../../../testing/testing_small.db");
+    let db = Database::open_readonly_bytes(FIXTURE)?;
+    let conn = db.connect()?;
+    let mut stmt = conn.prepare("SELECT id, value FROM demo WHERE value IS NOT NULL")?;
+    let mut rows = Vec::new();
+    loop {
+        match stmt.step()? {
+            StepResult::Row => {
+                let row = stmt.row().unwrap();
+                rows.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+            }
+            StepResult::IO => db.io().run_once()?,
+            StepResult::Done | StepResult::Interrupt => break,
+            StepResult::Busy => panic!("database busy"),
+        }
+    }
+    assert_eq!(
+        rows,
+        vec![
+            (1, "A".to_string()),
+            (3, "B".to_string()),
+            (5, "C".to_string())
+        ]
+    );
+    assert!(matches!(
+        conn.execute("INSERT INTO demo VALUES (6, 'D')"),
+        Err(LimboError::ReadOnly)
+    ));
+
+    assert!(matches!(
+        Database::open_readonly_bytes(vec![0u8; 4096]),
+        Err(LimboError::NotADB)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_open_readonly_bytes_small_pages() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(false);
+    {
+        let conn = rusqlite::Connection::open(&tmp_db.path)?;
+        conn.execute_batch(
+            "PRAGMA page_size = 512;
+             CREATE TABLE t (x TEXT);
+             WITH RECURSIVE c(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM c WHERE i < 200)
+             INSERT INTO t SELECT printf('row %d', i) FROM c;",
+        )?;
+    }
+    let bytes: Arc<[u8]> = std::fs::read(&tmp_db.path)?.into();
+    let db = Database::open_readonly_bytes(bytes)?;
+    let conn = db.connect()?;
+    let mut stmt = conn.prepare("SELECT count(*), max(x) FROM t")?;
+    loop {
+        match stmt.step()? {
+            StepResult::Row => {
+                let row = stmt.row().unwrap();
+                assert_eq!(row.get::<i64>(0)?, 200);
+                assert_eq!(row.get::<String>(1)?, "row 99");
+            }
+            StepResult::IO => db.io().run_once()?,
+            StepResult::Done | StepResult::Interrupt => break,
+            StepResult::Busy => panic!("database busy"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_statement_expanded_sql() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (a, b, c, d);", false);
+    let conn = tmp_db.connect_limbo();
+
+    let mut stmt = conn.prepare("INSERT INTO test VALUES (?, ?, ?, ?)")?;
+    stmt.bind_at(1.try_into()?, Value::Integer(1));
+    stmt.bind_at(2.try_into()?, Value::build_text("it's"));
+    stmt.bind_at(3.try_into()?, Value::Blob(vec![0x01, 0xab]));
+    stmt.bind_at(4.try_into()?, Value::Null);
+    assert_eq!(
+        stmt.expanded_sql()?,
+        "INSERT INTO test VALUES (1, 'it''s', X'01AB', NULL)"
+    );
+
+    let mut stmt = conn.prepare("SELECT * FROM test WHERE a = :a AND b = :a OR c = ?3")?;
+    stmt.bind_at(1.try_into()?, Value::Float(2.5));
+    stmt.bind_at(3.try_into()?, Value::build_text("x"));
+    assert_eq!(
+        stmt.expanded_sql()?,
+        "SELECT * FROM test WHERE a = 2.5 AND b = 2.5 OR c = 'x'"
+    );
+
+    let mut stmt = conn.prepare("SELECT '?', ?")?;
+    assert!(matches!(
+        stmt.expanded_sql(),
+        Err(LimboError::InvalidArgument(_))
+    ));
+    stmt.bind_at(1.try_into()?, Value::Integer(7));
+    assert_eq!(stmt.expanded_sql()?, "SELECT '?', 7");
+    Ok(())
+}
+
+#[test]
+fn test_statement_is_readonly() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER);", false);
+    let conn = tmp_db.connect_limbo();
+
+    for (sql, readonly) in [
+        ("SELECT * FROM test", true),
+        ("SELECT x FROM test ORDER BY x", true),
+        ("PRAGMA table_info(test)", true),
+        ("BEGIN", true),
+        ("INSERT INTO test VALUES (1)", false),
+        ("UPDATE test SET x = 2", false),
+        ("DELETE FROM test", false),
+        ("CREATE TABLE other (y)", false),
+        ("DROP TABLE test", false),
+        ("PRAGMA user_version = 1", false),
+    ] {
+        assert_eq!(conn.prepare(sql)?.is_readonly(), readonly, "{sql}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_wide_table() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+
+    let columns = (0..2000).map(|i| format!("c{i}")).collect::<Vec<_>>();
+    let create = format!("CREATE TABLE wide ({})", columns.join(", "));
+    limbo_exec_rows_error(&tmp_db, &conn, &create)?;
+    let values = (0..2000).map(|i| i.to_string()).collect::<Vec<_>>();
+    let insert = format!("INSERT INTO wide VALUES ({})", values.join(", "));
+    limbo_exec_rows_error(&tmp_db, &conn, &insert)?;
+
+    let start = std::time::Instant::now();
+    let stmt = conn.prepare("SELECT * FROM wide")?;
+    let elapsed = start.elapsed();
+    assert_eq!(stmt.num_columns(), 2000);
+    assert!(
+        elapsed < std::time::Duration::from_secs(5),
+        "preparing SELECT * took {elapsed:?}"
+    );
+    drop(stmt);
+
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT * FROM wide");
+    assert_eq!(rows.len(), 1);
+    let expected = (0..2000)
+        .map(rusqlite::types::Value::Integer)
+        .collect::<Vec<_>>();
+    assert_eq!(rows[0], expected);
+
+    let rows = limbo_exec_rows(
+        &tmp_db,
+        &conn,
+        "SELECT c1999, C1000, c0 FROM wide WHERE c1500 = 1500",
+    );
+    assert_eq!(
+        rows,
+        vec![vec![
+            rusqlite::types::Value::Integer(1999),
+            rusqlite::types::Value::Integer(1000),
+            rusqlite::types::Value::Integer(0),
+        ]]
+    );
+
+    let too_wide = format!("CREATE TABLE wider ({}, extra)", columns.join(", "));
+    let Err(err) = conn.prepare(&too_wide) else {
+        panic!("creating a table with 2001 columns should fail");
+    };
+    assert!(
+        err.to_string().contains("too many columns on wider"),
+        "{err}"
+    );
+    let Err(err) = conn.prepare("SELECT *, 1 FROM wide") else {
+        panic!("selecting 2001 columns should fail");
+    };
+    assert!(
+        err.to_string().contains("too many columns in result set"),
+        "{err}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_database_close_with_leaked_statement() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t (x INTEGER);", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("INSERT INTO t VALUES (1)")?;
+    let mut stmt = conn.prepare("SELECT x FROM t")?;
+
+    assert!(matches!(
+        tmp_db.db.close(CloseOptions::default()),
+        Err(LimboError::DatabaseInUse {
+            connections: 1,
+            statements: 1
+        })
+    ));
+    conn.close()?;
+    assert!(matches!(
+        tmp_db.db.close(CloseOptions::default()),
+        Err(LimboError::DatabaseInUse {
+            connections: 0,
+            statements: 1
+        })
+    ));
+    assert!(!tmp_db.db.is_closed());
+
+    tmp_db.db.close(CloseOptions {
+        force: true,
+        ..Default::default()
+    })?;
+    assert!(tmp_db.db.is_closed());
+    assert!(matches!(stmt.step(), Err(LimboError::DatabaseClosed)));
+    assert!(matches!(
+        conn.prepare("SELECT 1"),
+        Err(LimboError::DatabaseClosed)
+    ));
+    assert!(matches!(
+        tmp_db.db.connect(),
+        Err(LimboError::DatabaseClosed)
+    ));
+    // Closing again is a no-op.
+    tmp_db.db.close(CloseOptions::default())?;
+    drop(stmt);
+
+    // The insert survives closing and reopening the database.
+    let db = tmp_db.limbo_database(false);
+    let conn = db.connect()?;
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM t");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Integer(1)]]);
+    conn.close()?;
+    db.close(CloseOptions::default())?;
+    Ok(())
+}
+
+#[test]
+fn test_database_force_close_rolls_back_open_transaction() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t (x INTEGER);", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("BEGIN")?;
+    conn.execute("INSERT INTO t VALUES (1)")?;
+
+    tmp_db.db.close(CloseOptions {
+        force: true,
+        ..Default::default()
+    })?;
+    // The connection rolls back its transaction the next time it is used.
+    assert!(matches!(
+        conn.execute("COMMIT"),
+        Err(LimboError::DatabaseClosed)
+    ));
+    drop(conn);
+
+    let db = tmp_db.limbo_database(false);
+    let conn = db.connect()?;
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM t");
+    assert!(rows.is_empty());
+    conn.close()?;
+    db.close(CloseOptions::default())?;
+    Ok(())
+}
+
+#[test]
+fn test_query_shape_cache() -> anyhow::Result<()> {
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE t (id INTEGER PRIMARY KEY, x TEXT);", false);
+    let conn = tmp_db.connect_limbo();
+    let stats = |hits, misses| QueryShapeCacheStats { hits, misses };
+
+    limbo_exec_rows(&tmp_db, &conn, "INSERT INTO t VALUES (1, 'a')");
+    assert_eq!(conn.query_shape_cache_stats(), stats(0, 1));
+    limbo_exec_rows(&tmp_db, &conn, "INSERT INTO t VALUES (2, 'b')");
+    assert_eq!(conn.query_shape_cache_stats(), stats(1, 1));
+
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM t WHERE id = 1");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Text("a".into())]]);
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM t WHERE id = 2");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Text("b".into())]]);
+    assert_eq!(conn.query_shape_cache_stats(), stats(2, 2));
+
+    // A literal of another type is another shape.
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT id FROM t WHERE x = 'b'");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Integer(2)]]);
+    assert_eq!(conn.query_shape_cache_stats(), stats(2, 3));
+
+    // LIKE patterns are part of the shape.
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT id FROM t WHERE x LIKE 'a%'");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Integer(1)]]);
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT id FROM t WHERE x LIKE 'b%'");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Integer(2)]]);
+    assert_eq!(conn.query_shape_cache_stats(), stats(2, 3));
+
+    // Literals outside the WHERE clause are part of the shape.
+    let stmt = conn.prepare("SELECT id = 1 FROM t")?;
+    assert_eq!(stmt.get_column_name(0), "id = 1");
+    let stmt = conn.prepare("SELECT id = 1 FROM t WHERE id = 2")?;
+    assert_eq!(stmt.get_column_name(0), "id = 1");
+    let stmt = conn.prepare("SELECT id = 1 FROM t WHERE id = 1")?;
+    assert_eq!(stmt.get_column_name(0), "id = 1");
+    drop(stmt);
+    assert_eq!(conn.query_shape_cache_stats(), stats(3, 4));
+
+    // Schema changes invalidate cached shapes.
+    limbo_exec_rows(&tmp_db, &conn, "CREATE INDEX t_x ON t (x)");
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM t WHERE id = 1");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Text("a".into())]]);
+    assert_eq!(conn.query_shape_cache_stats(), stats(3, 5));
+
+    conn.set_query_shape_cache(false);
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM t WHERE id = 2");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Text("b".into())]]);
+    assert_eq!(conn.query_shape_cache_stats(), stats(3, 5));
+    Ok(())
+}
+
+#[test]
+fn test_query_shape_cache_foreign_keys() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite(
+        "CREATE TABLE p (id INTEGER PRIMARY KEY); CREATE TABLE c (x, p_id REFERENCES p(id));",
+        false,
+    );
+    let conn = tmp_db.connect_limbo();
+
+    limbo_exec_rows_error(&tmp_db, &conn, "INSERT INTO c VALUES (1, 10)")?;
+    conn.execute("PRAGMA foreign_keys = ON")?;
+    let err = limbo_exec_rows_error(&tmp_db, &conn, "INSERT INTO c VALUES (2, 20)").unwrap_err();
+    assert!(matches!(err, LimboError::Constraint(_)), "got: {}", err);
+    conn.execute("PRAGMA foreign_keys = OFF")?;
+    limbo_exec_rows_error(&tmp_db, &conn, "INSERT INTO c VALUES (3, 30)")?;
+
+    let rows = limbo_exec_rows(&tmp_db, &conn, "SELECT x FROM c ORDER BY x");
+    assert_eq!(
+        rows,
+        vec![
+            vec![rusqlite::types::Value::Integer(1)],
+            vec![rusqlite::types::Value::Integer(3)]
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pragma_index_list_and_index_info() -> anyhow::Result<()> {
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE t (x TEXT PRIMARY KEY, y UNIQUE, z);", true);
+    let conn = tmp_db.connect_limbo();
+    limbo_exec_rows(&tmp_db, &conn, "CREATE INDEX t_zy ON t (z, y)");
+
+    let index_row = |seq: i64, name: &str, unique: i64, origin: &str| {
+        vec![
+            rusqlite::types::Value::Integer(seq),
+            rusqlite::types::Value::Text(name.into()),
+            rusqlite::types::Value::Integer(unique),
+            rusqlite::types::Value::Text(origin.into()),
+            rusqlite::types::Value::Integer(0),
+        ]
+    };
+    let rows = limbo_exec_rows(&tmp_db, &conn, "PRAGMA index_list(t)");
+    assert_eq!(
+        rows,
+        vec![
+            index_row(0, "t_zy", 0, "c"),
+            index_row(1, "sqlite_autoindex_t_2", 1, "u"),
+            index_row(2, "sqlite_autoindex_t_1", 1, "pk"),
+        ]
+    );
+
+    let column_row = |seqno: i64, cid: i64, name: &str| {
+        vec![
+            rusqlite::types::Value::Integer(seqno),
+            rusqlite::types::Value::Integer(cid),
+            rusqlite::types::Value::Text(name.into()),
+        ]
+    };
+    let rows = limbo_exec_rows(&tmp_db, &conn, "PRAGMA index_info(t_zy)");
+    assert_eq!(rows, vec![column_row(0, 2, "z"), column_row(1, 1, "y")]);
+    let rows = limbo_exec_rows(
+        &tmp_db,
+        &conn,
+        "SELECT name FROM pragma_index_info('sqlite_autoindex_t_1')",
+    );
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Text("x".into())]]);
+
+    assert!(limbo_exec_rows(&tmp_db, &conn, "PRAGMA index_list(missing)").is_empty());
+    assert!(limbo_exec_rows(&tmp_db, &conn, "PRAGMA index_info(missing)").is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_read_connections_across_threads() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER);", false);
+    let conn = tmp_db.connect_limbo();
+    for i in 1..=100 {
+        conn.execute(format!("INSERT INTO test VALUES ({i})"))?;
+    }
+
+    let shared = std::sync::Arc::new(tmp_db.db.connect_read()?);
+    let handles = (0..4)
+        .map(|i| {
+            let db = tmp_db.db.clone();
+            let shared = shared.clone();
+            std::thread::spawn(move || -> turso_core::Result<()> {
+                let own = db.connect_read()?;
+                for read_conn in [&own, shared.as_ref()] {
+                    let rows = read_conn.query("SELECT sum(x), count(*) FROM test")?;
+                    assert_eq!(rows, vec![vec![Value::Integer(5050), Value::Integer(100)]]);
+                    let rows = read_conn.query_with_params(
+                        "SELECT x FROM test WHERE x = ?",
+                        &[Value::Integer(i)],
+                    )?;
+                    assert_eq!(rows, vec![vec![Value::Integer(i)]]);
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+
+    assert!(shared.query("INSERT INTO test VALUES (0)").is_err());
+    assert_eq!(
+        shared.query("SELECT count(*) FROM test")?,
+        vec![vec![Value::Integer(100)]]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_database_close_with_read_connection() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER);", false);
+    let read_conn = std::sync::Arc::new(tmp_db.db.connect_read()?);
+
+    assert!(matches!(
+        tmp_db.db.close(CloseOptions::default()),
+        Err(LimboError::DatabaseInUse {
+            connections: 1,
+            statements: 0
+        })
+    ));
+
+    let reader = {
+        let read_conn = read_conn.clone();
+        std::thread::spawn(move || {
+            for _ in 0..100 {
+                match read_conn.query("SELECT count(*) FROM test") {
+                    Ok(rows) => assert_eq!(rows, vec![vec![Value::Integer(0)]]),
+                    Err(LimboError::DatabaseClosed) => return,
+                    Err(e) => panic!("unexpected error: {e}"),
+                }
+            }
+        })
+    };
+    tmp_db.db.close(CloseOptions {
+        force: true,
+        ..Default::default()
+    })?;
+    reader.join().unwrap();
+    assert!(matches!(
+        read_conn.query("SELECT 1"),
+        Err(LimboError::DatabaseClosed)
+    ));
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use crate::common::{limbo_exec_rows, limbo_exec_rows_error, sqlite_exec_rows, TempDatabase};
+
+const ROWS: &str = "INSERT INTO t VALUES
+    (1, 'a', 10, 'x'), (2, 'a', 10, 'X'), (3, 'a', 20, 'y'), (4, 'a', NULL, 'y'),
+    (5, 'b', 5, 'z'), (6, 'b', 5, 'Z'), (7, 'b', 5, NULL), (8, NULL, 1, 'x'),
+    (9, NULL, NULL, 'x'), (10, 'c', 30, 'w'), (11, 'A', 10, 'x')";
+
+fn setup() -> (TempDatabase, rusqlite::Connection) {
+    let tmp_db = TempDatabase::new_with_rusqlite(
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, grp TEXT, score INTEGER, tag TEXT COLLATE NOCASE)",
+        true,
+    );
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path).unwrap();
+    sqlite_conn.execute(ROWS, ()).unwrap();
+    (tmp_db, sqlite_conn)
+}
+
+#[test]
+fn test_rank_functions_match_sqlite() {
+    let (tmp_db, sqlite_conn) = setup();
+    let conn = tmp_db.connect_limbo();
+
+    for query in [
+        // Partitions, ties and NULL keys.
+        "SELECT id, row_number() OVER (PARTITION BY grp ORDER BY score, id) FROM t ORDER BY id",
+        "SELECT id, rank() OVER (PARTITION BY grp ORDER BY score) FROM t ORDER BY id",
+        "SELECT id, dense_rank() OVER (PARTITION BY grp ORDER BY score DESC) FROM t ORDER BY id",
+        "SELECT id, rank() OVER (ORDER BY grp, score), dense_rank() OVER (ORDER BY grp, score) FROM t ORDER BY id",
+        "SELECT id, rank() OVER (PARTITION BY grp) FROM t ORDER BY id",
+        "SELECT id, row_number() OVER () FROM t ORDER BY id",
+        // Keys use the collation of the column they read or of their COLLATE operator.
+        "SELECT id, dense_rank() OVER (ORDER BY tag) FROM t ORDER BY id",
+        "SELECT id, rank() OVER (PARTITION BY grp COLLATE NOCASE ORDER BY score) FROM t ORDER BY id",
+        // NTILE with more rows than buckets, uneven buckets and more buckets than rows.
+        "SELECT id, ntile(3) OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, ntile(4) OVER (PARTITION BY grp ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, ntile(20) OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, ntile('2') OVER (ORDER BY id), ntile(2.9) OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, ntile(score) OVER (PARTITION BY grp ORDER BY id) FROM t WHERE score > 0 ORDER BY id",
+        // Window functions inside expressions, next to other columns, and in ORDER BY.
+        "SELECT grp, score + rank() OVER (ORDER BY score) * 100 AS r FROM t ORDER BY id",
+        "SELECT id FROM t ORDER BY row_number() OVER (ORDER BY score DESC, id DESC)",
+        "SELECT id, rank() OVER (ORDER BY score) AS r FROM t ORDER BY r DESC, id LIMIT 4 OFFSET 2",
+        "SELECT DISTINCT grp, dense_rank() OVER (ORDER BY grp) FROM t ORDER BY 2",
+        // Windows over grouped and filtered rows.
+        "SELECT grp, sum(score), rank() OVER (ORDER BY sum(score) DESC) FROM t GROUP BY grp ORDER BY grp",
+        "SELECT count(*), row_number() OVER () FROM t",
+        "SELECT id, row_number() OVER (ORDER BY id) FROM t WHERE grp = 'a' ORDER BY id",
+        "SELECT * FROM (SELECT id, rank() OVER (ORDER BY score) AS r FROM t) WHERE r < 4 ORDER BY id",
+        // Named windows.
+        "SELECT id, rank() OVER w, row_number() OVER w FROM t WINDOW w AS (PARTITION BY grp ORDER BY score, id) ORDER BY id",
+        "SELECT id, rank() OVER (w ORDER BY score) FROM t WINDOW w AS (PARTITION BY grp) ORDER BY id",
+    ] {
+        assert_eq!(
+            limbo_exec_rows(&tmp_db, &conn, query),
+            sqlite_exec_rows(&sqlite_conn, query),
+            "{query}"
+        );
+    }
+}
+
+#[test]
+fn test_offset_and_frame_functions_match_sqlite() {
+    let (tmp_db, sqlite_conn) = setup();
+    let conn = tmp_db.connect_limbo();
+
+    for query in [
+        // LAG and LEAD stay within the partition and fall back to the default.
+        "SELECT id, lag(score) OVER (ORDER BY id), lead(score) OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, lag(id, 2) OVER (PARTITION BY grp ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, lead(id, 2, -1) OVER (PARTITION BY grp ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, lag(tag, 1, grp) OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, lag(id, -1) OVER (ORDER BY id), lead(id, 0) OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, lag(id, NULL, 'none') OVER (ORDER BY id) FROM t ORDER BY id",
+        "SELECT id, lead(id, score % 3) OVER (ORDER BY id) FROM t ORDER BY id",
+        // The default frame ends with the last peer of the current row. Peers can come in any order,
+        // so only their ORDER BY key is read when the frame starts or ends at a peer.
+        "SELECT id, first_value(score) OVER (PARTITION BY grp ORDER BY score), nth_value(score, 3) OVER (PARTITION BY grp ORDER BY score) FROM t ORDER BY id",
+        "SELECT id, nth_value(grp, 3) OVER (PARTITION BY grp) FROM t ORDER BY id",
+        "SELECT id, nth_value(score, 2) OVER (ORDER BY score), nth_value(score, 20) OVER (ORDER BY score) FROM t ORDER BY id",
+        // ROWS frames, including frames that are empty or extend past the partition.
+        "SELECT id, first_value(id) OVER (ORDER BY id ROWS 2 PRECEDING) FROM t ORDER BY id",
+        "SELECT id, last_value(id) OVER (PARTITION BY grp ORDER BY id ROWS BETWEEN CURRENT ROW AND 2 FOLLOWING) FROM t ORDER BY id",
+        "SELECT id, first_value(id) OVER (ORDER BY id ROWS BETWEEN 1 FOLLOWING AND UNBOUNDED FOLLOWING) FROM t ORDER BY id",
+        "SELECT id, last_value(id) OVER (ORDER BY id ROWS BETWEEN 3 PRECEDING AND 2 PRECEDING) FROM t ORDER BY id",
+        "SELECT id, nth_value(id, 3) OVER (ORDER BY id ROWS BETWEEN 2 PRECEDING AND 2 FOLLOWING) FROM t ORDER BY id",
+        "SELECT id, first_value(id) OVER (ORDER BY id ROWS BETWEEN 2 FOLLOWING AND 1 FOLLOWING) FROM t ORDER BY id",
+        // RANGE and GROUPS frames bounded by peers.
+        "SELECT id, nth_value(score, 2) OVER (ORDER BY score RANGE BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING) FROM t ORDER BY id",
+        "SELECT id, nth_value(score, 4) OVER (ORDER BY score GROUPS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) FROM t ORDER BY id",
+        "SELECT id, last_value(id) OVER (ORDER BY score RANGE BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) FROM t ORDER BY id",
+        // Frames are per function, next to functions that ignore them.
+        "SELECT id, first_value(id) OVER w, last_value(id) OVER (w ROWS 1 PRECEDING), rank() OVER w FROM t WINDOW w AS (ORDER BY score, id) ORDER BY id",
+        "SELECT id, lag(score) OVER (ORDER BY id) - score AS delta FROM t ORDER BY delta, id LIMIT 5",
+    ] {
+        assert_eq!(
+            limbo_exec_rows(&tmp_db, &conn, query),
+            sqlite_exec_rows(&sqlite_conn, query),
+            "{query}"
+        );
+    }
+}
+
+#[test]
+fn test_window_function_column_names() {
+    let (tmp_db, _sqlite_conn) = setup();
+    let conn = tmp_db.connect_limbo();
+
+    let stmt = conn
+        .prepare("SELECT id, grp AS g, rank() OVER (ORDER BY score) AS r FROM t")
+        .unwrap();
+    assert_eq!(stmt.get_column_name(0), "id");
+    assert_eq!(stmt.get_column_name(1), "g");
+    assert_eq!(stmt.get_column_name(2), "r");
+    assert_eq!(stmt.column_declared_type(0), Some("INTEGER"));
+}
+
+#[test]
+fn test_window_function_errors() {
+    let (tmp_db, _sqlite_conn) = setup();
+    let conn = tmp_db.connect_limbo();
+
+    for (query, message) in [
+        (
+            "SELECT id FROM t WHERE row_number() OVER () > 1",
+            "misuse of window function row_number()",
+        ),
+        (
+            "SELECT grp FROM t GROUP BY grp HAVING rank() OVER () > 1",
+            "misuse of window function rank()",
+        ),
+        (
+            "SELECT rank() OVER (ORDER BY row_number() OVER ()) FROM t",
+            "misuse of window function row_number()",
+        ),
+        ("SELECT rank() OVER w FROM t", "no such window: w"),
+        (
+            "SELECT rank() OVER (w PARTITION BY id) FROM t WINDOW w AS (PARTITION BY grp)",
+            "cannot override PARTITION clause of window: w",
+        ),
+        (
+            "SELECT ntile() OVER () FROM t",
+            "wrong number of arguments to function ntile()",
+        ),
+        (
+            "SELECT abs(id) OVER () FROM t",
+            "abs() may not be used as a window function",
+        ),
+        (
+            "SELECT lag() OVER () FROM t",
+            "wrong number of arguments to function lag()",
+        ),
+        (
+            "SELECT first_value(id) OVER (ROWS BETWEEN CURRENT ROW AND 1 PRECEDING) FROM t",
+            "unsupported frame specification",
+        ),
+        (
+            "SELECT first_value(id) OVER (ORDER BY id RANGE 1 PRECEDING) FROM t",
+            "RANGE with offset PRECEDING/FOLLOWING is not supported yet",
+        ),
+    ] {
+        let err = conn
+            .prepare(query)
+            .err()
+            .unwrap_or_else(|| panic!("{query}"));
+        assert!(err.to_string().contains(message), "{query}: {err}");
+    }
+
+    for (query, message) in [
+        (
+            "SELECT ntile(0) OVER (ORDER BY id) FROM t",
+            "argument of ntile must be a positive integer",
+        ),
+        (
+            "SELECT ntile(NULL) OVER (ORDER BY id) FROM t",
+            "argument of ntile must be a positive integer",
+        ),
+        (
+            "SELECT ntile('x') OVER (ORDER BY id) FROM t",
+            "argument of ntile must be a positive integer",
+        ),
+        (
+            "SELECT nth_value(id, 0) OVER (ORDER BY id) FROM t",
+            "second argument to nth_value must be a positive integer",
+        ),
+        (
+            "SELECT first_value(id) OVER (ORDER BY id ROWS -1 PRECEDING) FROM t",
+            "frame starting offset must be a non-negative integer",
+        ),
+        (
+            "SELECT first_value(id) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND NULL FOLLOWING) FROM t",
+            "frame ending offset must be a non-negative integer",
+        ),
+    ] {
+        let err = limbo_exec_rows_error(&tmp_db, &conn, query).unwrap_err();
+        assert!(err.to_string().contains(message), "{query}: {err}");
+    }
+}
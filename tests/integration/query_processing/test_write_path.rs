@@ -1,9 +1,13 @@
 use crate::common::{self, maybe_setup_tracing};
 use crate::common::{compare_string, do_flush, TempDatabase};
 use log::debug;
+use std::cell::RefCell;
 use std::io::{Read, Seek, Write};
-use std::sync::Arc;
-use turso_core::{Connection, Database, Row, Statement, StepResult, Value};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use turso_core::{
+    BackupStatus, Connection, Database, LimboError, Row, Statement, StepResult, UpdateType, Value,
+};
 
 const WAL_HEADER_SIZE: usize = 32;
 const WAL_FRAME_HEADER_SIZE: usize = 24;
@@ -272,6 +276,132 @@ fn test_statement_reset() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_execute_batch() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_with_rusqlite("create table test (x integer, y text);", false);
+    let conn = tmp_db.connect_limbo();
+
+    let n = 3 * turso_core::BATCH_YIELD_INTERVAL + 1;
+    let mut rows = (0..n).map(|i| vec![Value::Integer(i as i64), Value::build_text("row")]);
+    let mut stmt = conn.prepare("insert into test values (?, ?)")?;
+    let mut yields = 0;
+    loop {
+        match stmt.execute_batch(&mut rows)? {
+            StepResult::IO => {
+                yields += 1;
+                tmp_db.io.run_once()?;
+            }
+            StepResult::Done => break,
+            _ => unreachable!(),
+        }
+    }
+    assert!(yields >= 3);
+    assert_eq!(stmt.batch_rows_done(), n);
+    assert_eq!(conn.changes(), n as i64);
+
+    run_query_on_row(&tmp_db, &conn, "select count(*), sum(x) from test", |row| {
+        assert_eq!(row.get::<i64>(0).unwrap(), n as i64);
+        assert_eq!(row.get::<i64>(1).unwrap(), (n * (n - 1) / 2) as i64);
+    })?;
+    Ok(())
+}
+
+#[test]
+fn test_execute_batch_reports_failing_row() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("create table test (x integer primary key);", false);
+    let conn = tmp_db.connect_limbo();
+
+    let mut rows = [1, 2, 3, 2, 4].into_iter().map(|x| vec![Value::Integer(x)]);
+    let mut stmt = conn.prepare("insert into test values (?)")?;
+    let err = loop {
+        match stmt.execute_batch(&mut rows) {
+            Ok(StepResult::IO) => tmp_db.io.run_once()?,
+            Ok(StepResult::Done) => panic!("expected the batch to fail"),
+            Ok(_) => unreachable!(),
+            Err(err) => break err,
+        }
+    };
+    assert!(matches!(err, LimboError::Constraint(_)), "got: {}", err);
+    assert_eq!(stmt.batch_rows_done(), 3);
+    assert_eq!(conn.changes(), 3);
+
+    // In autocommit mode the rows before the failing one stay committed.
+    run_query_on_row(&tmp_db, &conn, "select count(*) from test", |row| {
+        assert_eq!(row.get::<i64>(0).unwrap(), 3);
+    })?;
+    Ok(())
+}
+
+#[test]
+fn test_prepare_all() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_with_rusqlite("create table test (x integer, y text);", false);
+    let conn = tmp_db.connect_limbo();
+
+    let mut stmts = conn.prepare_all(
+        "insert into test values (1, 'a;b');
+         -- a comment; with a semicolon
+         insert into test values (2, 'c');
+         select count(*) from test;",
+    )?;
+    assert_eq!(stmts.len(), 3);
+    let mut count = None;
+    for stmt in stmts.iter_mut() {
+        loop {
+            match stmt.step()? {
+                StepResult::IO => tmp_db.io.run_once()?,
+                StepResult::Row => count = Some(stmt.row().unwrap().get::<i64>(0)?),
+                StepResult::Done => break,
+                _ => unreachable!(),
+            }
+        }
+    }
+    assert_eq!(count, Some(2));
+
+    assert!(conn.prepare_all("")?.is_empty());
+    assert!(conn
+        .prepare_all("select 1; select * from missing;")
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_reset_session() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_with_rusqlite("create table test (x integer);", false);
+    let conn = tmp_db.connect_limbo();
+
+    run_query(&tmp_db, &conn, "pragma cache_size = 100")?;
+    run_query(&tmp_db, &conn, "pragma synchronous = off")?;
+    run_query(&tmp_db, &conn, "insert into test values (1)")?;
+    // Leak an open transaction with an uncommitted write.
+    run_query(&tmp_db, &conn, "begin")?;
+    run_query(&tmp_db, &conn, "insert into test values (2)")?;
+    assert!(!conn.get_auto_commit());
+
+    conn.reset_session()?;
+
+    assert!(conn.get_auto_commit());
+    assert_eq!(conn.last_insert_rowid(), 0);
+    assert_eq!(conn.total_changes(), 0);
+    run_query_on_row(&tmp_db, &conn, "select count(*) from test", |row| {
+        assert_eq!(row.get::<i64>(0).unwrap(), 1);
+    })?;
+    run_query_on_row(&tmp_db, &conn, "pragma cache_size", |row| {
+        assert_eq!(row.get::<i64>(0).unwrap(), -2000);
+    })?;
+    run_query_on_row(&tmp_db, &conn, "pragma synchronous", |row| {
+        assert_eq!(row.get::<i64>(0).unwrap(), 2);
+    })?;
+    // A new transaction can be started after the reset.
+    run_query(&tmp_db, &conn, "begin")?;
+    run_query(&tmp_db, &conn, "commit")?;
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn test_wal_checkpoint() -> anyhow::Result<()> {
@@ -691,46 +821,571 @@ fn test_wal_bad_frame() -> anyhow::Result<()> {
         db_path
     };
     {
-        let result = std::panic::catch_unwind(|| {
-            let io: Arc<dyn turso_core::IO> = Arc::new(turso_core::PlatformIO::new().unwrap());
-            let db = Database::open_file_with_flags(
-                io.clone(),
-                db_path.to_str().unwrap(),
-                turso_core::OpenFlags::default(),
-                false,
-                false,
-            )
-            .unwrap();
-            let tmp_db = TempDatabase {
-                path: db_path,
-                io,
-                db,
-            };
-            let conn = tmp_db.connect_limbo();
-            run_query_on_row(&tmp_db, &conn, "SELECT count(1) from t2", |row| {
-                let x = row.get::<i64>(0).unwrap();
-                assert_eq!(x, 0);
-            })
+        // The bad frame was the commit frame, so the whole transaction is discarded.
+        let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+        let report = tmp_db.db.recovery_report();
+        assert_eq!(report.frames_scanned, 3);
+        assert_eq!(report.frames_applied, 0);
+        assert_eq!(report.frames_discarded, 1);
+        assert_eq!(report.truncated_tail_offset, Some(WAL_HEADER_SIZE as u64));
+        assert!(report.dirty_shutdown());
+
+        let conn = tmp_db.connect_limbo();
+        run_query(&tmp_db, &conn, "SELECT count(1) from t1")?;
+        assert!(conn.query("SELECT count(1) from t2").is_err());
+    }
+
+    Ok(())
+}
+
+/// Commits `n` single row transactions without closing the connection, as if the process
+/// crashed right after, and returns the path of the database.
+fn write_without_shutdown(n: usize) -> anyhow::Result<std::path::PathBuf> {
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    run_query(&tmp_db, &conn, "CREATE TABLE t (x)")?;
+    for i in 0..n {
+        run_query(&tmp_db, &conn, &format!("INSERT INTO t VALUES ({i})"))?;
+    }
+    Ok(tmp_db.path.clone())
+}
+
+/// Returns the 1-based indexes of the commit frames in a WAL.
+fn wal_commit_frames(wal: &[u8]) -> Vec<u64> {
+    let frame_size = WAL_FRAME_HEADER_SIZE + 4096;
+    (0..(wal.len() - WAL_HEADER_SIZE) / frame_size)
+        .filter(|i| {
+            let offset = WAL_HEADER_SIZE + i * frame_size;
+            wal[offset + 4..offset + 8] != [0, 0, 0, 0]
+        })
+        .map(|i| i as u64 + 1)
+        .collect()
+}
+
+#[test]
+fn test_wal_recovery_report() -> anyhow::Result<()> {
+    let frame_size = (WAL_FRAME_HEADER_SIZE + 4096) as u64;
+
+    // A WAL that was fully written only needs replaying.
+    let db_path = write_without_shutdown(10)?;
+    let wal_path = db_path.with_extension("db-wal");
+    let wal = std::fs::read(&wal_path)?;
+    let total_frames = (wal.len() - WAL_HEADER_SIZE) as u64 / frame_size;
+    let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+    let report = tmp_db.db.recovery_report();
+    assert!(report.dirty_shutdown());
+    assert_eq!(report.frames_scanned, total_frames);
+    assert_eq!(report.frames_applied, total_frames);
+    assert_eq!(report.frames_discarded, 0);
+    assert_eq!(report.truncated_tail_offset, None);
+
+    // Tear the last frame in half, as if the crash happened while it was being written.
+    let db_path = write_without_shutdown(10)?;
+    let wal_path = db_path.with_extension("db-wal");
+    let wal = std::fs::read(&wal_path)?;
+    let cut = wal.len() - 100;
+    std::fs::write(&wal_path, &wal[..cut])?;
+    let commits = wal_commit_frames(&wal[..cut]);
+    let last_commit = *commits.last().unwrap();
+    let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+    let report = tmp_db.db.recovery_report();
+    assert!(report.dirty_shutdown());
+    assert_eq!(
+        report.frames_scanned,
+        (cut - WAL_HEADER_SIZE) as u64 / frame_size
+    );
+    assert_eq!(report.frames_applied, last_commit);
+    assert_eq!(report.frames_discarded, 0);
+    assert_eq!(
+        report.truncated_tail_offset,
+        Some(WAL_HEADER_SIZE as u64 + last_commit * frame_size)
+    );
+    // The torn frame was the commit of the last insert.
+    let conn = tmp_db.connect_limbo();
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "SELECT count(*) FROM t", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![9]);
+
+    // Corrupt a frame in the middle: it and every frame after it are discarded.
+    let db_path = write_without_shutdown(10)?;
+    let wal_path = db_path.with_extension("db-wal");
+    let mut wal = std::fs::read(&wal_path)?;
+    let total_frames = (wal.len() - WAL_HEADER_SIZE) as u64 / frame_size;
+    let commits = wal_commit_frames(&wal);
+    let bad_frame = commits[commits.len() / 2];
+    let offset = WAL_HEADER_SIZE + (bad_frame - 1) as usize * frame_size as usize;
+    wal[offset + WAL_FRAME_HEADER_SIZE + 10] ^= 0xff;
+    std::fs::write(&wal_path, &wal)?;
+    let last_commit = *commits.iter().filter(|f| **f < bad_frame).last().unwrap();
+    let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+    let report = tmp_db.db.recovery_report();
+    assert_eq!(report.frames_scanned, total_frames);
+    assert_eq!(report.frames_applied, last_commit);
+    assert_eq!(report.frames_discarded, total_frames - bad_frame + 1);
+    assert_eq!(
+        report.truncated_tail_offset,
+        Some(WAL_HEADER_SIZE as u64 + last_commit * frame_size)
+    );
+
+    // The same numbers are available through the pragma.
+    let conn = tmp_db.connect_limbo();
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "PRAGMA recovery_report", |row| {
+        rows.push(
+            (0..5)
+                .map(|i| row.get::<i64>(i).unwrap())
+                .collect::<Vec<_>>(),
+        )
+    })?;
+    assert_eq!(
+        rows,
+        vec![vec![
+            1,
+            total_frames as i64,
+            last_commit as i64,
+            (total_frames - bad_frame + 1) as i64,
+            (WAL_HEADER_SIZE as u64 + last_commit * frame_size) as i64,
+        ]]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_page_checksum_detects_bit_flip() -> anyhow::Result<()> {
+    maybe_setup_tracing();
+    let db_path = {
+        let tmp_db = TempDatabase::new_empty(false);
+        let conn = tmp_db.connect_limbo();
+        // Enabling verification before the first write makes the new database carry checksums.
+        conn.execute("PRAGMA checksum_verification = ON")?;
+        conn.execute("CREATE TABLE t(x)")?;
+        conn.execute("INSERT INTO t VALUES ('hello')")?;
+        run_query_on_row(&tmp_db, &conn, "SELECT count(1) FROM t", |row| {
+            assert_eq!(row.get::<i64>(0).unwrap(), 1);
+        })?;
+        // Closing checkpoints every frame into the database file.
+        conn.close()?;
+        tmp_db.path.clone()
+    };
+    std::fs::remove_file(db_path.with_extension("db-wal"))?;
+
+    // Flip a bit in the middle of page 2, the root page of `t`.
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&db_path)?;
+    let offset = 4096 + 2048;
+    let mut byte = [0u8; 1];
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    file.read_exact(&mut byte)?;
+    byte[0] ^= 0x01;
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    file.write_all(&byte)?;
+    file.flush()?;
+
+    let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+    let corrupted_pages = Arc::new(Mutex::new(Vec::new()));
+    {
+        let corrupted_pages = corrupted_pages.clone();
+        tmp_db.db.set_corruption_callback(move |mismatch| {
+            corrupted_pages.lock().unwrap().push(mismatch.page_id);
         });
+    }
+    let conn = tmp_db.connect_limbo();
+    conn.execute("PRAGMA checksum_verification = ON")?;
+    let err = run_query(&tmp_db, &conn, "SELECT * FROM t").unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<LimboError>(),
+            Some(LimboError::Corrupt(_))
+        ),
+        "expected corruption error, got: {}",
+        err
+    );
+    assert_eq!(*corrupted_pages.lock().unwrap(), vec![2]);
+    Ok(())
+}
 
-        match result {
-            Err(panic_info) => {
-                let panic_msg = panic_info
-                    .downcast_ref::<String>()
-                    .map(|s| s.as_str())
-                    .or_else(|| panic_info.downcast_ref::<&str>().copied())
-                    .unwrap_or("Unknown panic message");
-
-                assert!(
-                    panic_msg.contains("WAL frame checksum mismatch."),
-                    "Expected panic message not found. Got: {}",
-                    panic_msg
-                );
+#[test]
+fn test_page_checksum_verification_without_checksums() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x)", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("PRAGMA checksum_verification = ON")?;
+    conn.execute("INSERT INTO t VALUES (1)")?;
+    run_query_on_row(&tmp_db, &conn, "SELECT count(1) FROM t", |row| {
+        assert_eq!(row.get::<i64>(0).unwrap(), 1);
+    })?;
+    Ok(())
+}
+
+#[test]
+fn test_serialize_deserialize() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(false);
+    assert!(tmp_db.db.serialize()?.is_empty());
+
+    let conn = tmp_db.connect_limbo();
+    run_query(&tmp_db, &conn, "CREATE TABLE t (x INTEGER, y TEXT)")?;
+    for i in 0..1000 {
+        run_query(
+            &tmp_db,
+            &conn,
+            &format!("INSERT INTO t VALUES ({i}, '{}')", "y".repeat(100)),
+        )?;
+    }
+
+    // The committed rows live in the WAL, which the snapshot must include.
+    let bytes = tmp_db.db.serialize()?;
+    assert_eq!(bytes.len() % 4096, 0);
+    assert!(bytes.starts_with(b"SQLite format 3\0"));
+
+    // SQLite can read the snapshot as a plain database file.
+    let snapshot = tmp_db.path.with_extension("snapshot.db");
+    std::fs::write(&snapshot, &bytes)?;
+    let sqlite_conn = rusqlite::Connection::open(&snapshot)?;
+    let (count, sum): (i64, i64) =
+        sqlite_conn.query_row("SELECT count(*), sum(x) FROM t", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_eq!((count, sum), (1000, 499500));
+
+    // The deserialized copy has the same contents and evolves independently.
+    let copy = Database::deserialize(&bytes)?;
+    let copy_conn = copy.connect()?;
+    run_query(&tmp_db, &copy_conn, "DELETE FROM t WHERE x >= 10")?;
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &copy_conn, "SELECT count(*) FROM t", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![10]);
+
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "SELECT count(*) FROM t", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![1000]);
+
+    assert!(matches!(
+        Database::deserialize(b"not a database"),
+        Err(LimboError::NotADB)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_freelist_pages_are_reused() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+
+    let fill_table = |table: &str| -> anyhow::Result<()> {
+        run_query(&tmp_db, &conn, &format!("CREATE TABLE {table} (x, y)"))?;
+        for i in 0..500 {
+            run_query(
+                &tmp_db,
+                &conn,
+                &format!("INSERT INTO {table} VALUES ({i}, '{}')", "y".repeat(200)),
+            )?;
+        }
+        Ok(())
+    };
+    let page_count = || -> anyhow::Result<i64> {
+        let mut count = 0;
+        run_query_on_row(&tmp_db, &conn, "PRAGMA page_count", |row| {
+            count = row.get::<i64>(0).unwrap();
+        })?;
+        Ok(count)
+    };
+
+    fill_table("t1")?;
+    let pages_before = page_count()?;
+    let stats_before = conn.page_allocation_stats();
+    assert_eq!(stats_before.freelist_hits, 0);
+
+    // The dropped table's pages go to the freelist and are handed out to the new one.
+    run_query(&tmp_db, &conn, "DROP TABLE t1")?;
+    fill_table("t2")?;
+    let stats = conn.page_allocation_stats();
+    assert!(stats.freelist_hits > 0);
+    assert!(stats.locality_histogram.iter().sum::<u64>() <= stats.freelist_hits);
+    assert!(
+        stats.file_extensions - stats_before.file_extensions < stats.freelist_hits,
+        "{stats:?}"
+    );
+    assert!(page_count()? < pages_before * 2);
+
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "SELECT count(*), sum(x) FROM t2", |row| {
+        rows.push((row.get::<i64>(0).unwrap(), row.get::<i64>(1).unwrap()))
+    })?;
+    assert_eq!(rows, vec![(500, 124750)]);
+
+    check_integrity_is_ok(tmp_db, conn)?;
+    Ok(())
+}
+
+#[test]
+fn test_backup_to() -> anyhow::Result<()> {
+    let source = TempDatabase::new_empty(false);
+    let conn = source.connect_limbo();
+    run_query(&source, &conn, "CREATE TABLE t (x INTEGER, y TEXT)")?;
+    for i in 0..1000 {
+        run_query(
+            &source,
+            &conn,
+            &format!("INSERT INTO t VALUES ({i}, '{}')", "y".repeat(100)),
+        )?;
+    }
+
+    let dest = TempDatabase::new_empty(false);
+    let mut backup = source.db.backup_to(&dest.db)?;
+    let mut previous = usize::MAX;
+    loop {
+        match backup.step(5)? {
+            BackupStatus::OK(remaining) => {
+                assert!(remaining < previous);
+                previous = remaining;
             }
-            Ok(_) => panic!("Expected query to panic, but it succeeded"),
+            BackupStatus::Done => break,
+            BackupStatus::IO => backup.run_once()?,
+        }
+    }
+    assert_eq!(backup.step(5)?, BackupStatus::Done);
+    assert!(backup.page_count() > 5);
+
+    // The source keeps working and the backup does not see later changes.
+    run_query(&source, &conn, "DELETE FROM t WHERE x >= 10")?;
+
+    let dest_conn = dest.connect_limbo();
+    let mut rows = Vec::new();
+    run_query_on_row(&dest, &dest_conn, "SELECT count(*), sum(x) FROM t", |row| {
+        rows.push((row.get::<i64>(0).unwrap(), row.get::<i64>(1).unwrap()))
+    })?;
+    assert_eq!(rows, vec![(1000, 499500)]);
+
+    do_flush(&dest_conn, &dest)?;
+    let sqlite_conn = rusqlite::Connection::open(&dest.path)?;
+    let count: i64 = sqlite_conn.query_row("SELECT count(*) FROM t", [], |row| row.get(0))?;
+    assert_eq!(count, 1000);
+    Ok(())
+}
+
+#[test]
+fn test_page_quota() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x INTEGER, y BLOB)")?;
+    conn.execute("CREATE TABLE scratch (y BLOB)")?;
+    for _ in 0..20 {
+        conn.execute("INSERT INTO scratch VALUES (zeroblob(3000))")?;
+    }
+    conn.execute("INSERT INTO t VALUES (0, zeroblob(3000))")?;
+
+    let usage = conn.page_usage()?;
+    assert_eq!(usage.freelist_pages, 0);
+    assert_eq!(usage.quota, None);
+    let page_count = usage.page_count;
+    let quota = page_count + 3;
+    tmp_db.db.set_page_quota(Some(quota));
+    assert_eq!(conn.page_usage()?.quota, Some(quota));
+    assert_eq!(tmp_db.db.page_usage()?.page_count, page_count);
+
+    // Hitting the quota in the middle of a transaction rolls the whole transaction back.
+    conn.execute("BEGIN")?;
+    let mut inserted = 0;
+    let err = loop {
+        match conn.execute(format!(
+            "INSERT INTO t VALUES ({}, zeroblob(3000))",
+            inserted + 1
+        )) {
+            Ok(()) => inserted += 1,
+            Err(err) => break err,
+        }
+        assert!(inserted < 100, "quota was never hit");
+    };
+    assert!(matches!(err, LimboError::QuotaExceeded(q) if q == quota));
+    assert!(inserted > 0);
+    assert!(conn.get_auto_commit());
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "SELECT count(*) FROM t", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![1]);
+    assert_eq!(conn.page_usage()?.page_count, page_count);
+
+    // Pages freed by dropping a table are reused without growing the file.
+    conn.execute("DROP TABLE scratch")?;
+    let usage = conn.page_usage()?;
+    assert!(usage.freelist_pages > 0);
+    for i in 1..=10 {
+        conn.execute(format!("INSERT INTO t VALUES ({i}, zeroblob(3000))"))?;
+    }
+    assert_eq!(conn.page_usage()?.page_count, page_count);
+    rows.clear();
+    run_query_on_row(&tmp_db, &conn, "SELECT count(*) FROM t", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![11]);
+    rows.clear();
+    run_query_on_row(&tmp_db, &conn, "PRAGMA page_count", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![page_count as i64]);
+
+    tmp_db.db.set_page_quota(None);
+    do_flush(&conn, &tmp_db)?;
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path)?;
+    let integrity: String =
+        sqlite_conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    assert_eq!(integrity, "ok");
+    Ok(())
+}
+
+#[test]
+fn test_update_hook() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x INTEGER PRIMARY KEY, y TEXT)")?;
+
+    let changes = Rc::new(RefCell::new(Vec::new()));
+    let hook_changes = changes.clone();
+    conn.set_update_hook(Box::new(move |update_type, db, table, rowid| {
+        hook_changes
+            .borrow_mut()
+            .push((update_type, db.to_string(), table.to_string(), rowid));
+    }));
+
+    conn.execute("INSERT INTO t VALUES (1, 'a'), (2, 'b')")?;
+    conn.execute("UPDATE t SET y = 'c' WHERE x = 2")?;
+    conn.execute("UPDATE t SET x = 3 WHERE x = 1")?;
+    conn.execute("DELETE FROM t WHERE x = 2")?;
+    // Schema changes touch sqlite_schema only, which is not reported.
+    conn.execute("CREATE TABLE u (z)")?;
+    let change = |update_type, rowid| (update_type, "main".to_string(), "t".to_string(), rowid);
+    assert_eq!(
+        *changes.borrow(),
+        vec![
+            change(UpdateType::Insert, 1),
+            change(UpdateType::Insert, 2),
+            change(UpdateType::Update, 2),
+            change(UpdateType::Update, 3),
+            change(UpdateType::Delete, 2),
+        ]
+    );
+
+    conn.clear_update_hook();
+    conn.execute("INSERT INTO t VALUES (4, 'd')")?;
+    assert_eq!(changes.borrow().len(), 5);
+    Ok(())
+}
+
+#[test]
+fn test_commit_and_rollback_hooks() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x)")?;
+
+    let commits = Rc::new(RefCell::new(0));
+    let rollbacks = Rc::new(RefCell::new(0));
+    let allow_commit = Rc::new(RefCell::new(true));
+    let (hook_commits, hook_allow_commit) = (commits.clone(), allow_commit.clone());
+    conn.set_commit_hook(Box::new(move || {
+        *hook_commits.borrow_mut() += 1;
+        *hook_allow_commit.borrow()
+    }));
+    let hook_rollbacks = rollbacks.clone();
+    conn.set_rollback_hook(Box::new(move || *hook_rollbacks.borrow_mut() += 1));
+
+    conn.execute("INSERT INTO t VALUES (1)")?;
+    conn.execute("BEGIN")?;
+    conn.execute("INSERT INTO t VALUES (2)")?;
+    conn.execute("ROLLBACK")?;
+    assert_eq!((*commits.borrow(), *rollbacks.borrow()), (1, 1));
+
+    // A commit vetoed by the hook is rolled back.
+    *allow_commit.borrow_mut() = false;
+    assert!(matches!(
+        conn.execute("INSERT INTO t VALUES (3)"),
+        Err(LimboError::CommitHookAborted)
+    ));
+    assert_eq!((*commits.borrow(), *rollbacks.borrow()), (2, 2));
+    *allow_commit.borrow_mut() = true;
+
+    let mut rows = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "SELECT x FROM t", |row| {
+        rows.push(row.get::<i64>(0).unwrap())
+    })?;
+    assert_eq!(rows, vec![1]);
+    Ok(())
+}
+
+#[test]
+fn test_trace_and_profile_hooks() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+
+    let traced = Rc::new(RefCell::new(Vec::new()));
+    let profiled = Rc::new(RefCell::new(Vec::new()));
+    let hook_traced = traced.clone();
+    conn.set_trace_hook(Box::new(move |sql, elapsed| {
+        hook_traced.borrow_mut().push((sql.to_string(), elapsed));
+    }));
+    let hook_profiled = profiled.clone();
+    conn.set_profile_hook(Box::new(move |sql, nanos| {
+        hook_profiled.borrow_mut().push((sql.to_string(), nanos));
+    }));
+
+    conn.execute("CREATE TABLE t (x)")?;
+    run_query(&tmp_db, &conn, "INSERT INTO t VALUES (1), (2)")?;
+    // A statement abandoned before it completes is not reported.
+    let mut stmt = conn.prepare("SELECT x FROM t")?;
+    loop {
+        match stmt.step()? {
+            StepResult::IO => tmp_db.io.run_once()?,
+            StepResult::Row => break,
+            res => panic!("unexpected step result {:?}", res),
         }
     }
+    stmt.cancel()?;
+    run_query(&tmp_db, &conn, "SELECT x FROM t")?;
+
+    let expected = [
+        "CREATE TABLE t (x)",
+        "INSERT INTO t VALUES (1), (2)",
+        "SELECT x FROM t",
+    ];
+    let traced_sql: Vec<_> = traced.borrow().iter().map(|(sql, _)| sql.clone()).collect();
+    let profiled_sql: Vec<_> = profiled
+        .borrow()
+        .iter()
+        .map(|(sql, _)| sql.clone())
+        .collect();
+    assert_eq!(traced_sql, expected);
+    assert_eq!(profiled_sql, expected);
+    // CPU time is only counted while the statement steps, so it can't exceed the wall-clock
+    // time from its first step until it completed, give or take the resolution of the clocks.
+    for ((_, elapsed), (_, nanos)) in traced.borrow().iter().zip(profiled.borrow().iter()) {
+        assert!(*nanos as u128 <= elapsed.as_nanos() + 1_000_000);
+    }
 
+    // A statement that keeps the CPU busy reports the CPU time it used.
+    run_query(
+        &tmp_db,
+        &conn,
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 100000) SELECT sum(x) FROM c",
+    )?;
+    assert!(profiled.borrow_mut().pop().unwrap().1 > 0);
+    traced.borrow_mut().pop();
+
+    conn.clear_trace_hook();
+    conn.clear_profile_hook();
+    conn.execute("INSERT INTO t VALUES (3)")?;
+    assert_eq!(traced.borrow().len(), 3);
+    assert_eq!(profiled.borrow().len(), 3);
     Ok(())
 }
 
@@ -2,8 +2,9 @@ use crate::common::{do_flush, maybe_setup_tracing, TempDatabase};
 use std::cell::RefCell;
 use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use turso_core::{Connection, LimboError, Result, StepResult};
+use turso_core::{Connection, LimboError, Result, StepResult, TransactionKind};
 
 #[allow(clippy::arc_with_non_send_sync)]
 #[test]
@@ -32,6 +33,320 @@ fn test_wal_checkpoint_result() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_wal_checkpoint_rate_limit() -> anyhow::Result<()> {
+    maybe_setup_tracing();
+    // Checkpoint the same ~1MB of WAL frames with and without a 1 MB/s limit.
+    let mut elapsed = Vec::new();
+    for limit in [0, 1] {
+        let tmp_db = TempDatabase::new_empty(false);
+        let conn = tmp_db.connect_limbo();
+        conn.execute("CREATE TABLE t (x BLOB)")?;
+        for _ in 0..32 {
+            conn.execute("INSERT INTO t VALUES (randomblob(32 * 1024))")?;
+        }
+        do_flush(&conn, &tmp_db)?;
+
+        conn.execute(format!("PRAGMA checkpoint_rate_limit = {limit}"))?;
+        let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA checkpoint_rate_limit")?;
+        assert_eq!(res, vec![limit]);
+
+        let start = std::time::Instant::now();
+        let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_checkpoint")?;
+        elapsed.push(start.elapsed());
+        assert_eq!(res[0], 0);
+        assert!(res[2] > 0);
+        assert!(!conn.is_checkpoint_throttled());
+    }
+    assert!(
+        elapsed[1] >= std::time::Duration::from_millis(500),
+        "throttled checkpoint took {:?}",
+        elapsed[1]
+    );
+    assert!(
+        elapsed[0] * 2 < elapsed[1],
+        "unthrottled {:?} vs throttled {:?}",
+        elapsed[0],
+        elapsed[1]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_wal_checkpoint_rate_limit_foreground_reads() -> anyhow::Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x BLOB)")?;
+    for _ in 0..32 {
+        conn.execute("INSERT INTO t VALUES (randomblob(32 * 1024))")?;
+    }
+    do_flush(&conn, &tmp_db)?;
+
+    // Checkpoint ~1MB of frames at 1 MB/s on another thread while this one keeps reading.
+    let db = tmp_db.db.clone();
+    let done = Arc::new(AtomicBool::new(false));
+    let checkpoint = std::thread::spawn({
+        let done = done.clone();
+        move || {
+            let result = (|| -> Result<std::time::Duration> {
+                let conn = db.connect()?;
+                conn.execute("PRAGMA checkpoint_rate_limit = 1")?;
+                let start = std::time::Instant::now();
+                let mut stmt = conn.prepare("PRAGMA wal_checkpoint")?;
+                loop {
+                    match stmt.step()? {
+                        StepResult::IO => stmt.run_once()?,
+                        StepResult::Done => break,
+                        _ => {}
+                    }
+                }
+                Ok(start.elapsed())
+            })();
+            done.store(true, Ordering::SeqCst);
+            result
+        }
+    });
+
+    let mut latencies = Vec::new();
+    while !done.load(Ordering::SeqCst) {
+        let rowid = latencies.len() % 32 + 1;
+        let start = std::time::Instant::now();
+        let res = execute_and_get_ints(
+            &tmp_db,
+            &conn,
+            &format!("SELECT length(x) FROM t WHERE rowid = {rowid}"),
+        )?;
+        latencies.push(start.elapsed());
+        assert_eq!(res, vec![32 * 1024]);
+    }
+    let checkpoint_elapsed = checkpoint.join().unwrap()?;
+    assert!(
+        checkpoint_elapsed >= std::time::Duration::from_millis(500),
+        "throttled checkpoint took {checkpoint_elapsed:?}"
+    );
+    // Reads are served between the throttled batches instead of waiting for the whole
+    // checkpoint.
+    let slowest = latencies.iter().max().copied().unwrap_or_default();
+    assert!(
+        latencies.len() >= 10,
+        "only {} reads finished during the checkpoint",
+        latencies.len()
+    );
+    assert!(
+        slowest * 4 < checkpoint_elapsed,
+        "slowest read took {slowest:?} during a {checkpoint_elapsed:?} checkpoint"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_wal_checkpoint_modes() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new("test_wal.db", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x INTEGER)")?;
+    conn.execute("INSERT INTO t VALUES (1), (2)")?;
+    let wal_path = format!("{}-wal", tmp_db.path.to_str().unwrap());
+
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_checkpoint(FULL)")?;
+    assert_eq!(res[0], 0);
+    assert_eq!(res[1], res[2]);
+
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_checkpoint(TRUNCATE)")?;
+    assert_eq!(res, vec![0, 0, 0]);
+    assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+
+    // The WAL starts over from its first frame.
+    conn.execute("INSERT INTO t VALUES (3)")?;
+    assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_checkpoint(RESTART)")?;
+    assert_eq!(res[0], 0);
+    conn.execute("INSERT INTO t VALUES (4)")?;
+
+    // A writer keeps every mode but PASSIVE from running.
+    let conn2 = tmp_db.connect_limbo();
+    conn2.execute_batch("BEGIN; INSERT INTO t VALUES (5);")?;
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_checkpoint(RESTART)")?;
+    assert_eq!(res, vec![1, -1, -1]);
+    conn2.execute("COMMIT")?;
+
+    let res = execute_and_get_ints(&tmp_db, &conn, "SELECT x FROM t ORDER BY x")?;
+    assert_eq!(res, vec![1, 2, 3, 4, 5]);
+
+    // Frames written after the restart are recovered from the WAL.
+    let tmp_db = TempDatabase::new_with_existent(&tmp_db.path, false);
+    let conn = tmp_db.connect_limbo();
+    let res = execute_and_get_ints(&tmp_db, &conn, "SELECT x FROM t ORDER BY x")?;
+    assert_eq!(res, vec![1, 2, 3, 4, 5]);
+    Ok(())
+}
+
+#[test]
+fn test_wal_checkpoint_many_pages() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new("test_wal.db", false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x INTEGER PRIMARY KEY, y BLOB)")?;
+    // Enough pages for the checkpoint to write them in several batches.
+    for i in 0..300 {
+        conn.execute(&format!("INSERT INTO t VALUES ({i}, randomblob(3000))"))?;
+    }
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_checkpoint(TRUNCATE)")?;
+    assert_eq!(res, vec![0, 0, 0]);
+
+    // Everything must now be read from the database file alone.
+    let tmp_db = TempDatabase::new_with_existent(&tmp_db.path, false);
+    let conn = tmp_db.connect_limbo();
+    let res = execute_and_get_ints(&tmp_db, &conn, "SELECT count(*), sum(x) FROM t")?;
+    assert_eq!(res, vec![300, 300 * 299 / 2]);
+    let res = execute_and_get_strings(&tmp_db, &conn, "PRAGMA integrity_check")?;
+    assert_eq!(res, vec!["ok"]);
+    Ok(())
+}
+
+#[test]
+fn test_wal_autocheckpoint() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new("test_wal.db", false);
+    let conn = tmp_db.connect_limbo();
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_autocheckpoint")?;
+    assert_eq!(res, vec![1000]);
+
+    let res = execute_and_get_ints(&tmp_db, &conn, "PRAGMA wal_autocheckpoint = 0")?;
+    assert_eq!(res, vec![0]);
+    conn.execute("CREATE TABLE t (x BLOB)")?;
+    let db_size = std::fs::metadata(&tmp_db.path).unwrap().len();
+    for _ in 0..10 {
+        conn.execute("INSERT INTO t VALUES (zeroblob(8192))")?;
+    }
+    // Nothing was checkpointed into the database file.
+    assert_eq!(std::fs::metadata(&tmp_db.path).unwrap().len(), db_size);
+
+    // The threshold is shared by every connection.
+    let conn2 = tmp_db.connect_limbo();
+    conn2.set_wal_autocheckpoint(1);
+    assert_eq!(conn.wal_autocheckpoint(), 1);
+    conn.execute("INSERT INTO t VALUES (zeroblob(8192))")?;
+    assert!(std::fs::metadata(&tmp_db.path).unwrap().len() > db_size);
+    Ok(())
+}
+
+#[test]
+fn test_wal_journal_mode_delete() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    let wal_size =
+        || std::fs::metadata(format!("{}-wal", tmp_db.path.display())).map_or(0, |m| m.len());
+    conn.execute("CREATE TABLE t (x INTEGER)")?;
+    conn.execute("INSERT INTO t VALUES (1)")?;
+    assert!(wal_size() > 0);
+
+    // Switching checkpoints and truncates the WAL right away.
+    let res = execute_and_get_strings(&tmp_db, &conn, "PRAGMA journal_mode = delete")?;
+    assert_eq!(res, vec!["delete"]);
+    assert_eq!(wal_size(), 0);
+
+    // Every commit then leaves everything in the database file, for every connection.
+    let conn2 = tmp_db.connect_limbo();
+    let res = execute_and_get_strings(&tmp_db, &conn2, "PRAGMA journal_mode")?;
+    assert_eq!(res, vec!["delete"]);
+    conn2.execute("INSERT INTO t VALUES (2)")?;
+    assert_eq!(wal_size(), 0);
+    let res = execute_and_get_ints(&tmp_db, &conn, "SELECT count(*) FROM t")?;
+    assert_eq!(res, vec![2]);
+
+    // Modes Limbo doesn't have leave the mode alone.
+    let res = execute_and_get_strings(&tmp_db, &conn, "PRAGMA journal_mode = memory")?;
+    assert_eq!(res, vec!["delete"]);
+
+    let res = execute_and_get_strings(&tmp_db, &conn, "PRAGMA journal_mode = wal")?;
+    assert_eq!(res, vec!["wal"]);
+    conn.execute("INSERT INTO t VALUES (3)")?;
+    assert!(wal_size() > 0);
+    Ok(())
+}
+
+#[test]
+fn test_wal_busy_timeout() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new("test_wal.db", false);
+    let conn1 = tmp_db.connect_limbo();
+    let conn2 = tmp_db.connect_limbo();
+    conn1.execute("CREATE TABLE t (x INTEGER)")?;
+
+    // conn1 holds the write lock until it commits.
+    conn1.execute_batch("BEGIN; INSERT INTO t VALUES (1);")?;
+
+    assert!(matches!(
+        conn2.execute_batch("INSERT INTO t VALUES (2)"),
+        Err(LimboError::Busy)
+    ));
+
+    conn2.set_busy_timeout(200);
+    assert_eq!(conn2.busy_timeout(), 200);
+    let start = std::time::Instant::now();
+    assert!(matches!(
+        conn2.execute_batch("INSERT INTO t VALUES (2)"),
+        Err(LimboError::Busy)
+    ));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+
+    conn1.execute("COMMIT")?;
+    conn2.execute_batch("INSERT INTO t VALUES (2)")?;
+    let res = execute_and_get_ints(&tmp_db, &conn1, "SELECT x FROM t ORDER BY x")?;
+    assert_eq!(res, vec![1, 2]);
+    Ok(())
+}
+
+#[test]
+fn test_wal_begin_immediate_and_exclusive() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new("test_wal.db", false);
+    let conn1 = tmp_db.connect_limbo();
+    let conn2 = tmp_db.connect_limbo();
+    conn1.execute("CREATE TABLE t (x INTEGER)")?;
+    conn1.execute("INSERT INTO t VALUES (1)")?;
+
+    // IMMEDIATE takes the write lock at BEGIN but lets others read.
+    conn1.execute_batch("BEGIN IMMEDIATE")?;
+    assert_eq!(conn1.transaction_kind(), Some(TransactionKind::Write));
+    let res = execute_and_get_ints(&tmp_db, &conn2, "SELECT x FROM t")?;
+    assert_eq!(res, vec![1]);
+    assert!(matches!(
+        conn2.execute_batch("INSERT INTO t VALUES (2)"),
+        Err(LimboError::Busy)
+    ));
+    conn1.execute("COMMIT")?;
+
+    // EXCLUSIVE fails right away, without waiting for the busy timeout, while another
+    // connection is reading.
+    conn2.execute_batch("BEGIN; SELECT x FROM t;")?;
+    conn1.set_busy_timeout(5000);
+    let start = std::time::Instant::now();
+    assert!(matches!(
+        conn1.execute_batch("BEGIN EXCLUSIVE"),
+        Err(LimboError::Busy)
+    ));
+    assert!(start.elapsed() < std::time::Duration::from_millis(5000));
+    assert!(conn1.get_auto_commit());
+    conn2.execute("COMMIT")?;
+
+    // Once it's held, other connections can't even read.
+    conn1.execute_batch("BEGIN EXCLUSIVE; INSERT INTO t VALUES (2);")?;
+    assert_eq!(conn1.transaction_kind(), Some(TransactionKind::Exclusive));
+    assert!(matches!(
+        conn2.execute_batch("SELECT x FROM t"),
+        Err(LimboError::Busy)
+    ));
+    conn1.execute("COMMIT")?;
+    assert_eq!(conn1.transaction_kind(), None);
+    let res = execute_and_get_ints(&tmp_db, &conn2, "SELECT x FROM t ORDER BY x")?;
+    assert_eq!(res, vec![1, 2]);
+    Ok(())
+}
+
 #[test]
 #[ignore = "ignored for now because it's flaky"]
 fn test_wal_1_writer_1_reader() -> Result<()> {
@@ -109,6 +424,41 @@ fn test_wal_1_writer_1_reader() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_wal_shared_page_cache_isolation() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new("test_wal.db", false);
+    let writer = tmp_db.connect_limbo();
+    let reader = tmp_db.connect_limbo();
+    writer.execute("CREATE TABLE t (x INTEGER);")?;
+    writer.execute("INSERT INTO t VALUES (1);")?;
+
+    // Populate the shared cache through the reader.
+    let res = execute_and_get_ints(&tmp_db, &reader, "SELECT x FROM t;")?;
+    assert_eq!(res, vec![1]);
+
+    // Uncommitted changes must not leak into the shared cache.
+    writer.execute("BEGIN;")?;
+    writer.execute("UPDATE t SET x = 2;")?;
+    let res = execute_and_get_ints(&tmp_db, &writer, "SELECT x FROM t;")?;
+    assert_eq!(res, vec![2]);
+    let res = execute_and_get_ints(&tmp_db, &reader, "SELECT x FROM t;")?;
+    assert_eq!(res, vec![1]);
+
+    // A reader holding a snapshot keeps seeing it after the writer commits.
+    reader.execute("BEGIN;")?;
+    let res = execute_and_get_ints(&tmp_db, &reader, "SELECT x FROM t;")?;
+    assert_eq!(res, vec![1]);
+    writer.execute("COMMIT;")?;
+    let res = execute_and_get_ints(&tmp_db, &reader, "SELECT x FROM t;")?;
+    assert_eq!(res, vec![1]);
+    reader.execute("COMMIT;")?;
+
+    let res = execute_and_get_ints(&tmp_db, &reader, "SELECT x FROM t;")?;
+    assert_eq!(res, vec![2]);
+    Ok(())
+}
+
 /// Execute a statement and get strings result
 pub(crate) fn execute_and_get_strings(
     tmp_db: &TempDatabase,
@@ -1,5 +1,6 @@
 //! SQLite dialect
 
+use std::borrow::Cow;
 use std::fmt::Formatter;
 use std::str;
 use uncased::UncasedStr;
@@ -50,6 +51,15 @@ pub fn keyword_token(word: &[u8]) -> Option<TokenType> {
     KEYWORDS.get(UncasedStr::new(s)).cloned()
 }
 
+/// Quote `name` with double quotes if it cannot be used as a bare identifier,
+/// i.e. if it is a keyword or contains characters outside of an identifier.
+pub fn quote_identifier(name: &str) -> Cow<'_, str> {
+    if is_identifier(name) && keyword_token(name.as_bytes()).is_none() {
+        return Cow::Borrowed(name);
+    }
+    Cow::Owned(format!("\"{}\"", name.replace('"', "\"\"")))
+}
+
 pub(crate) fn is_identifier(name: &str) -> bool {
     if name.is_empty() {
         return false;
@@ -1726,10 +1726,26 @@ pub type PragmaValue = Expr; // TODO
 #[strum(serialize_all = "snake_case")]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PragmaName {
+    /// Maximum number of rows ANALYZE visits per index
+    AnalysisLimit,
     /// set the autovacuum mode
     AutoVacuum,
+    /// Eviction policy of the page cache
+    CachePolicy,
     /// `cache_size` pragma
     CacheSize,
+    /// Limit checkpoint throughput, in MB/s
+    CheckpointRateLimit,
+    /// Verify page checksums on read
+    ChecksumVerification,
+    /// Enforce foreign key constraints
+    ForeignKeys,
+    /// Skip CHECK constraints when writing rows
+    IgnoreCheckConstraints,
+    /// returns information about the columns of an index
+    IndexInfo,
+    /// returns the indexes of a table
+    IndexList,
     /// Run integrity check on the database file
     IntegrityCheck,
     /// `journal_mode` pragma
@@ -1740,12 +1756,18 @@ pub enum PragmaName {
     PageCount,
     /// Return the page size of the database in bytes.
     PageSize,
+    /// Returns what WAL recovery found when the database was opened.
+    RecoveryReport,
     /// Returns schema version of the database file.
     SchemaVersion,
+    /// `synchronous` pragma
+    Synchronous,
     /// returns information about the columns of a table
     TableInfo,
     /// Returns the user version of the database file.
     UserVersion,
+    /// Number of WAL frames after which a commit checkpoints
+    WalAutocheckpoint,
     /// trigger a checkpoint to run on database(s) if WAL is enabled
     WalCheckpoint,
 }
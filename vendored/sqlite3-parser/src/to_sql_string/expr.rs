@@ -1,6 +1,7 @@
 use std::fmt::Display;
 
 use crate::ast::{self, fmt::ToTokens, Expr};
+use crate::dialect::quote_identifier;
 
 use super::ToSqlString;
 
@@ -142,13 +143,14 @@ impl ToSqlString for Expr {
                 column,
                 is_rowid_alias: _,
             } => {
-                ret.push_str(context.get_table_name(*table));
+                ret.push_str(&quote_identifier(context.get_table_name(*table)));
                 ret.push('.');
-                ret.push_str(context.get_column_name(*table, *column));
-            }
-            Expr::RowId { database: _, table } => {
-                ret.push_str(&format!("{}.rowid", context.get_table_name(*table)))
+                ret.push_str(&quote_identifier(context.get_column_name(*table, *column)));
             }
+            Expr::RowId { database: _, table } => ret.push_str(&format!(
+                "{}.rowid",
+                quote_identifier(context.get_table_name(*table))
+            )),
             Expr::InList { lhs, not, rhs } => {
                 ret.push_str(&format!(
                     "{} {}IN ({})",